@@ -40,6 +40,7 @@ feature(threads ON)
 feature(gc ON)
 feature(gc-drc ON)
 feature(gc-null ON)
+feature(gc-copying ON)
 feature(async ON)
 feature(cranelift ON)
 feature(winch ON)
@@ -0,0 +1,291 @@
+//! A content-addressed store of compiled code which is shared between
+//! processes, see [`CodeStore`].
+
+use crate::{compiler_dir, hash_key};
+use log::{debug, trace, warn};
+use std::fs;
+use std::hash::Hash;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::time::{Duration, SystemTime};
+use wasmtime_environ::error::{Context, Result};
+
+/// A directory of compiled artifacts which any number of processes can map
+/// read-only and share.
+///
+/// Unlike [`Cache`](crate::Cache), which stores compressed artifacts that each
+/// process decompresses into memory of its own, artifacts in a code store are
+/// kept uncompressed so that they can be mapped directly from the file. All
+/// processes using the same artifact then share the same physical pages for its
+/// code and metadata through the OS's page cache.
+///
+/// Artifacts are addressed by a hash of everything that went into compiling
+/// them, see [`CodeStore::key`], and are immutable once published. Publishing
+/// writes the artifact to a temporary file which is then atomically renamed into
+/// place, so processes looking up an artifact either find all of it or nothing,
+/// and processes racing to publish the same artifact are harmless.
+///
+/// Each lookup of an artifact updates its modification time, which
+/// [`CodeStore::gc`] uses to remove artifacts that haven't been used recently.
+#[derive(Debug, Clone)]
+pub struct CodeStore {
+    directory: PathBuf,
+    root: PathBuf,
+}
+
+/// Statistics returned by [`CodeStore::gc`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct CodeStoreGcStats {
+    /// The number of files that were removed.
+    pub removed_files: usize,
+    /// The total size, in bytes, of the files that were removed.
+    pub removed_bytes: u64,
+    /// The number of files left in the store.
+    pub retained_files: usize,
+    /// The total size, in bytes, of the files left in the store.
+    pub retained_bytes: u64,
+}
+
+impl CodeStore {
+    /// Opens the code store in `directory`, creating the directory if it
+    /// doesn't exist yet.
+    ///
+    /// Artifacts are kept in a subdirectory specific to this build of Wasmtime,
+    /// so the same directory can be shared by processes running different
+    /// versions of Wasmtime.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory can't be created.
+    pub fn new(directory: impl AsRef<Path>) -> Result<Self> {
+        let directory = directory.as_ref();
+        fs::create_dir_all(directory).with_context(|| {
+            format!(
+                "failed to create code store directory: {}",
+                directory.display()
+            )
+        })?;
+        let directory = fs::canonicalize(directory).with_context(|| {
+            format!(
+                "failed to canonicalize code store directory: {}",
+                directory.display()
+            )
+        })?;
+        let root = directory.join(compiler_dir("wasmtime"));
+        Ok(Self { directory, root })
+    }
+
+    /// Returns the directory this store was opened in.
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Returns the key of the artifact that compiling `state` produces.
+    ///
+    /// `state` must hash everything that affects the contents of the artifact.
+    pub fn key<T: Hash + ?Sized>(&self, state: &T) -> String {
+        hash_key(state)
+    }
+
+    /// Returns the path of the artifact published under `key`, if any, and
+    /// marks it as used.
+    ///
+    /// The artifact may be removed by [`CodeStore::gc`] in another process
+    /// between this lookup and the caller opening it, in which case the caller
+    /// should treat it as missing. Artifacts which are already open or mapped
+    /// remain usable after they're removed.
+    pub fn lookup(&self, key: &str) -> Option<PathBuf> {
+        let path = self.root.join(key);
+        trace!("lookup() for path: {}", path.display());
+        if !path.is_file() {
+            return None;
+        }
+        // Failing to mark the artifact as used is deliberately ignored: the
+        // store may be read-only for this process, for example when it's
+        // shared from a read-only mount, and the artifact is usable all the
+        // same. It's just more likely to be removed by `gc` elsewhere.
+        if let Err(err) = touch(&path) {
+            trace!(
+                "Failed to mark artifact as used, path: {}, message: {}",
+                path.display(),
+                err
+            );
+        }
+        Some(path)
+    }
+
+    /// Atomically publishes `contents` as the artifact for `key`, returning
+    /// its path.
+    ///
+    /// If an artifact was already published under `key`, for example by
+    /// another process racing with this one, then it's left as-is and its path
+    /// is returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the artifact can't be written to disk.
+    pub fn publish(&self, key: &str, contents: &[u8]) -> Result<PathBuf> {
+        static NEXT_TEMP: AtomicUsize = AtomicUsize::new(0);
+
+        let path = self.root.join(key);
+        trace!("publish() for path: {}", path.display());
+        fs::create_dir_all(&self.root).with_context(|| {
+            format!(
+                "failed to create code store directory: {}",
+                self.root.display()
+            )
+        })?;
+
+        // Write the whole artifact, and make sure it's on disk, before it
+        // becomes visible under its final name.
+        let temp = path.with_extension(format!(
+            "wip-{}-{}",
+            std::process::id(),
+            NEXT_TEMP.fetch_add(1, Relaxed)
+        ));
+        let written = fs::OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&temp)
+            .and_then(|mut file| {
+                file.write_all(contents)?;
+                file.sync_all()
+            });
+        if let Err(err) = written {
+            let _ = fs::remove_file(&temp);
+            return Err(err)
+                .with_context(|| format!("failed to write artifact: {}", temp.display()));
+        }
+
+        if path.is_file() {
+            let _ = fs::remove_file(&temp);
+            return Ok(path);
+        }
+        if let Err(err) = fs::rename(&temp, &path) {
+            let _ = fs::remove_file(&temp);
+            // Renaming can fail on some platforms if another process published
+            // the same artifact in the meantime and already has it open.
+            if path.is_file() {
+                return Ok(path);
+            }
+            return Err(err)
+                .with_context(|| format!("failed to publish artifact: {}", path.display()));
+        }
+
+        // Persist the rename itself as well. This isn't supported on all
+        // platforms and not doing so only risks losing the artifact on a crash.
+        #[cfg(unix)]
+        if let Err(err) = fs::File::open(&self.root).and_then(|dir| dir.sync_all()) {
+            debug!(
+                "Failed to sync code store directory, path: {}, message: {}",
+                self.root.display(),
+                err
+            );
+        }
+
+        Ok(path)
+    }
+
+    /// Removes artifacts which haven't been looked up or published for longer
+    /// than `unused_for`.
+    ///
+    /// Artifacts published by other versions of Wasmtime are removed on the
+    /// same basis, as are temporary files left behind by processes which
+    /// crashed while publishing. Processes which still have a removed artifact
+    /// open or mapped can continue to use it, but the next lookup of it will
+    /// miss and the artifact will have to be compiled and published again.
+    /// Temporary files of processes currently publishing are only safe from
+    /// removal if `unused_for` is longer than publishing takes.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store's directory can't be read. Failing to
+    /// remove individual files isn't an error, and they're counted as retained.
+    pub fn gc(&self, unused_for: Duration) -> Result<CodeStoreGcStats> {
+        let mut stats = CodeStoreGcStats::default();
+        let now = SystemTime::now();
+        let entries = fs::read_dir(&self.directory).with_context(|| {
+            format!(
+                "failed to read code store directory: {}",
+                self.directory.display()
+            )
+        })?;
+        for entry in entries {
+            let dir = match entry {
+                Ok(entry) if entry.file_type().is_ok_and(|ty| ty.is_dir()) => entry.path(),
+                _ => continue,
+            };
+            let Ok(files) = fs::read_dir(&dir) else {
+                continue;
+            };
+            for file in files {
+                let Ok(file) = file else { continue };
+                let Ok(metadata) = file.metadata() else {
+                    continue;
+                };
+                if !metadata.is_file() {
+                    continue;
+                }
+                // Files from the future, e.g. because of clock drift between
+                // machines sharing the directory, count as just used.
+                let unused = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|mtime| now.duration_since(mtime).ok())
+                    .unwrap_or_default();
+                if unused > unused_for {
+                    match fs::remove_file(file.path()) {
+                        Ok(()) => {
+                            stats.removed_files += 1;
+                            stats.removed_bytes += metadata.len();
+                            continue;
+                        }
+                        Err(err) => warn!(
+                            "Failed to remove unused artifact, path: {}, message: {}",
+                            file.path().display(),
+                            err
+                        ),
+                    }
+                }
+                stats.retained_files += 1;
+                stats.retained_bytes += metadata.len();
+            }
+            // This only succeeds for directories left empty, and the current
+            // version's directory is recreated when publishing.
+            let _ = fs::remove_dir(&dir);
+        }
+        Ok(stats)
+    }
+}
+
+/// Sets the modification time of the file at `path` to now, without opening
+/// it for writing.
+#[cfg(unix)]
+fn touch(path: &Path) -> std::io::Result<()> {
+    use rustix::fs::{AtFlags, CWD, Timespec, Timestamps, UTIME_NOW, UTIME_OMIT, utimensat};
+
+    let times = Timestamps {
+        last_access: Timespec {
+            tv_sec: 0,
+            tv_nsec: UTIME_OMIT,
+        },
+        last_modification: Timespec {
+            tv_sec: 0,
+            tv_nsec: UTIME_NOW,
+        },
+    };
+    utimensat(CWD, path, &times, AtFlags::empty())?;
+    Ok(())
+}
+
+/// Sets the modification time of the file at `path` to now, without opening
+/// it for writing.
+#[cfg(not(unix))]
+fn touch(path: &Path) -> std::io::Result<()> {
+    fs::File::open(path)?.set_modified(SystemTime::now())
+}
+
+#[cfg(test)]
+mod tests;
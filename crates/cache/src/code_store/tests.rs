@@ -0,0 +1,55 @@
+use super::*;
+use filetime::{FileTime, set_file_mtime};
+
+#[test]
+fn test_publish_and_lookup() {
+    let dir = tempfile::tempdir().expect("Can't create temporary directory");
+    let store = CodeStore::new(dir.path().join("code")).unwrap();
+    let key = store.key(&("module", 1));
+    assert_eq!(key, store.key(&("module", 1)));
+    assert_ne!(key, store.key(&("module", 2)));
+
+    assert!(store.lookup(&key).is_none());
+    let path = store.publish(&key, b"first").unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"first");
+    assert_eq!(store.lookup(&key), Some(path.clone()));
+
+    // Publishing an existing artifact keeps the original, and doesn't leave
+    // any temporary files behind.
+    assert_eq!(store.publish(&key, b"second").unwrap(), path);
+    assert_eq!(fs::read(&path).unwrap(), b"first");
+    assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+}
+
+#[test]
+fn test_gc() {
+    let dir = tempfile::tempdir().expect("Can't create temporary directory");
+    let store = CodeStore::new(dir.path()).unwrap();
+    let old = store.publish("old", b"old artifact").unwrap();
+    let new = store.publish("new", b"new").unwrap();
+    let old_version = dir.path().join("wasmtime-old-version");
+    fs::create_dir(&old_version).unwrap();
+    fs::write(old_version.join("artifact"), b"other").unwrap();
+    fs::write(old.with_extension("wip-1-0"), b"crashed").unwrap();
+
+    let long_ago = FileTime::from_unix_time(0, 0);
+    set_file_mtime(&old, long_ago).unwrap();
+    set_file_mtime(old_version.join("artifact"), long_ago).unwrap();
+    set_file_mtime(old.with_extension("wip-1-0"), long_ago).unwrap();
+
+    let stats = store.gc(Duration::from_secs(60 * 60)).unwrap();
+    assert_eq!(stats.removed_files, 3);
+    assert_eq!(stats.removed_bytes, 12 + 5 + 7);
+    assert_eq!(stats.retained_files, 1);
+    assert_eq!(stats.retained_bytes, 3);
+    assert!(!old.exists());
+    assert!(new.exists());
+    assert!(!old_version.exists());
+
+    // Looking an artifact up keeps it alive.
+    set_file_mtime(&new, long_ago).unwrap();
+    assert!(store.lookup("new").is_some());
+    let stats = store.gc(Duration::from_secs(60 * 60)).unwrap();
+    assert_eq!(stats.removed_files, 0);
+    assert!(new.exists());
+}
@@ -6,7 +6,6 @@
 //! > be aware that your usage of this crate is not supported.
 
 use base64::Engine;
-use log::{debug, trace, warn};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::hash::Hash;
@@ -19,26 +18,36 @@ use std::time::Duration;
 use std::{fs, io};
 use wasmtime_environ::error::Result;
 
+mod code_store;
 #[macro_use] // for tests
 mod config;
+mod store;
 mod worker;
 
+pub use code_store::{CodeStore, CodeStoreGcStats};
 pub use config::{CacheConfig, create_new_config};
-use worker::Worker;
+pub use store::{DirectoryCacheStore, ModuleCacheStore};
 
 /// Global configuration for how the cache is managed
 #[derive(Debug, Clone)]
 pub struct Cache {
-    config: CacheConfig,
-    worker: Worker,
+    store: Arc<dyn ModuleCacheStore>,
+    /// The same store as `store` when it's the default directory store, for
+    /// the getters of its configuration.
+    directory_store: Option<Arc<DirectoryCacheStore>>,
     state: Arc<CacheState>,
 }
 
 macro_rules! generate_config_setting_getter {
     ($setting:ident: $setting_type:ty) => {
         #[doc = concat!("Returns ", "`", stringify!($setting), "`.")]
+        ///
+        /// # Panics
+        ///
+        /// Panics if this cache wasn't created from a [`CacheConfig`], see
+        /// [`Cache::directory_store`].
         pub fn $setting(&self) -> $setting_type {
-            self.config.$setting()
+            self.expect_directory_store().$setting()
         }
     };
 }
@@ -46,20 +55,33 @@ macro_rules! generate_config_setting_getter {
 impl Cache {
     /// Builds a [`Cache`] from the configuration and spawns the cache worker.
     ///
+    /// The cache is kept in the directory configured in `config`, see
+    /// [`DirectoryCacheStore`]. Use [`Cache::with_store`] to keep it
+    /// elsewhere.
+    ///
     /// If you want to load the cache configuration from a file, use [`CacheConfig::from_file`].
     /// You can call [`CacheConfig::new`] for the default configuration.
     ///
     /// # Errors
     /// Returns an error if the configuration is invalid.
-    pub fn new(mut config: CacheConfig) -> Result<Self> {
-        config.validate()?;
+    pub fn new(config: CacheConfig) -> Result<Self> {
+        let store = Arc::new(DirectoryCacheStore::new(config)?);
         Ok(Self {
-            worker: Worker::start_new(&config),
-            config,
+            store: store.clone(),
+            directory_store: Some(store),
             state: Default::default(),
         })
     }
 
+    /// Builds a [`Cache`] which keeps its artifacts in a custom `store`.
+    pub fn with_store(store: Arc<dyn ModuleCacheStore>) -> Self {
+        Self {
+            store,
+            directory_store: None,
+            state: Default::default(),
+        }
+    }
+
     /// Loads cache configuration specified at `path`.
     ///
     /// This method will read the file specified by `path` on the filesystem and
@@ -82,6 +104,23 @@ impl Cache {
         Self::new(config)
     }
 
+    /// Returns the store this cache keeps its artifacts in.
+    pub fn store(&self) -> &Arc<dyn ModuleCacheStore> {
+        &self.store
+    }
+
+    /// Returns the directory store this cache keeps its artifacts in, if it
+    /// was created from a [`CacheConfig`] rather than with
+    /// [`Cache::with_store`].
+    pub fn directory_store(&self) -> Option<&DirectoryCacheStore> {
+        self.directory_store.as_deref()
+    }
+
+    fn expect_directory_store(&self) -> &DirectoryCacheStore {
+        self.directory_store()
+            .expect("cache with a custom store has no directory configuration")
+    }
+
     generate_config_setting_getter!(worker_event_queue_size: u64);
     generate_config_setting_getter!(baseline_compression_level: i32);
     generate_config_setting_getter!(optimized_compression_level: i32);
@@ -95,16 +134,18 @@ impl Cache {
     generate_config_setting_getter!(files_total_size_limit_percent_if_deleting: u8);
 
     /// Returns path to the cache directory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this cache wasn't created from a [`CacheConfig`], see
+    /// [`Cache::directory_store`].
     pub fn directory(&self) -> &PathBuf {
-        &self
-            .config
-            .directory()
-            .expect("directory should be validated in Config::new")
+        self.expect_directory_store().directory()
     }
 
     #[cfg(test)]
-    fn worker(&self) -> &Worker {
-        &self.worker
+    fn worker(&self) -> &worker::Worker {
+        self.expect_directory_store().worker()
     }
 
     /// Returns the number of cache hits seen so far
@@ -116,16 +157,6 @@ impl Cache {
     pub fn cache_misses(&self) -> usize {
         self.state.misses.load(SeqCst)
     }
-
-    pub(crate) fn on_cache_get_async(&self, path: impl AsRef<Path>) {
-        self.state.hits.fetch_add(1, SeqCst);
-        self.worker.on_cache_get_async(path)
-    }
-
-    pub(crate) fn on_cache_update_async(&self, path: impl AsRef<Path>) {
-        self.state.misses.fetch_add(1, SeqCst);
-        self.worker.on_cache_update_async(path)
-    }
 }
 
 #[derive(Default, Debug)]
@@ -138,7 +169,7 @@ struct CacheState {
 pub struct ModuleCacheEntry<'cache>(Option<ModuleCacheEntryInner<'cache>>);
 
 struct ModuleCacheEntryInner<'cache> {
-    root_path: PathBuf,
+    compiler_dir: String,
     cache: &'cache Cache,
 }
 
@@ -195,24 +226,18 @@ impl<'cache> ModuleCacheEntry<'cache> {
             None => return compute(state),
         };
 
-        let mut hasher = Sha256Hasher(Sha256::new());
-        state.hash(&mut hasher);
-        let hash: [u8; 32] = hasher.0.finalize().into();
-        // standard encoding uses '/' which can't be used for filename
-        let hash = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&hash);
+        let key = format!("{}/{}", inner.compiler_dir, hash_key(state));
 
-        if let Some(cached_val) = inner.get_data(&hash) {
+        if let Some(cached_val) = inner.cache.store.get(&key) {
             if let Some(val) = deserialize(state, cached_val) {
-                let mod_cache_path = inner.root_path.join(&hash);
-                inner.cache.on_cache_get_async(&mod_cache_path); // call on success
+                inner.cache.state.hits.fetch_add(1, SeqCst); // count on success
                 return Ok(val);
             }
         }
         let val_to_cache = compute(state)?;
         if let Some(bytes) = serialize(state, &val_to_cache) {
-            if inner.update_data(&hash, &bytes).is_some() {
-                let mod_cache_path = inner.root_path.join(&hash);
-                inner.cache.on_cache_update_async(&mod_cache_path); // call on success
+            if inner.cache.store.insert(&key, &bytes) {
+                inner.cache.state.misses.fetch_add(1, SeqCst); // count on success
             }
         }
         Ok(val_to_cache)
@@ -221,101 +246,61 @@ impl<'cache> ModuleCacheEntry<'cache> {
 
 impl<'cache> ModuleCacheEntryInner<'cache> {
     fn new(compiler_name: &str, cache: &'cache Cache) -> Self {
-        // If debug assertions are enabled then assume that we're some sort of
-        // local build. We don't want local builds to stomp over caches between
-        // builds, so just use a separate cache directory based on the mtime of
-        // our executable, which should roughly correlate with "you changed the
-        // source code so you get a different directory".
-        //
-        // Otherwise if this is a release build we use the `GIT_REV` env var
-        // which is either the git rev if installed from git or the crate
-        // version if installed from crates.io.
-        let compiler_dir = if cfg!(debug_assertions) {
-            fn self_mtime() -> Option<String> {
-                let path = std::env::current_exe().ok()?;
-                let metadata = path.metadata().ok()?;
-                let mtime = metadata.modified().ok()?;
-                Some(match mtime.duration_since(std::time::UNIX_EPOCH) {
-                    Ok(dur) => format!("{}", dur.as_millis()),
-                    Err(err) => format!("m{}", err.duration().as_millis()),
-                })
-            }
-            let self_mtime = self_mtime().unwrap_or("no-mtime".to_string());
-            format!(
-                "{comp_name}-{comp_ver}-{comp_mtime}",
-                comp_name = compiler_name,
-                comp_ver = env!("GIT_REV"),
-                comp_mtime = self_mtime,
-            )
-        } else {
-            format!(
-                "{comp_name}-{comp_ver}",
-                comp_name = compiler_name,
-                comp_ver = env!("GIT_REV"),
-            )
-        };
-        let root_path = cache.directory().join("modules").join(compiler_dir);
-
-        Self { root_path, cache }
-    }
-
-    fn get_data(&self, hash: &str) -> Option<Vec<u8>> {
-        let mod_cache_path = self.root_path.join(hash);
-        trace!("get_data() for path: {}", mod_cache_path.display());
-        let compressed_cache_bytes = fs::read(&mod_cache_path).ok()?;
-        let cache_bytes = zstd::decode_all(&compressed_cache_bytes[..])
-            .map_err(|err| warn!("Failed to decompress cached code: {err}"))
-            .ok()?;
-        Some(cache_bytes)
-    }
-
-    fn update_data(&self, hash: &str, serialized_data: &[u8]) -> Option<()> {
-        let mod_cache_path = self.root_path.join(hash);
-        trace!("update_data() for path: {}", mod_cache_path.display());
-        let compressed_data = zstd::encode_all(
-            &serialized_data[..],
-            self.cache.baseline_compression_level(),
-        )
-        .map_err(|err| warn!("Failed to compress cached code: {err}"))
-        .ok()?;
-
-        // Optimize syscalls: first, try writing to disk. It should succeed in most cases.
-        // Otherwise, try creating the cache directory and retry writing to the file.
-        if fs_write_atomic(&mod_cache_path, "mod", &compressed_data).is_ok() {
-            return Some(());
+        let compiler_dir = compiler_dir(compiler_name);
+        Self {
+            compiler_dir,
+            cache,
         }
+    }
+}
 
-        debug!(
-            "Attempting to create the cache directory, because \
-             failed to write cached code to disk, path: {}",
-            mod_cache_path.display(),
-        );
-
-        let cache_dir = mod_cache_path.parent().unwrap();
-        fs::create_dir_all(cache_dir)
-            .map_err(|err| {
-                warn!(
-                    "Failed to create cache directory, path: {}, message: {}",
-                    cache_dir.display(),
-                    err
-                )
+/// Returns the name of the directory that artifacts produced by this build of
+/// `compiler_name` are kept in.
+fn compiler_dir(compiler_name: &str) -> String {
+    // If debug assertions are enabled then assume that we're some sort of
+    // local build. We don't want local builds to stomp over caches between
+    // builds, so just use a separate cache directory based on the mtime of
+    // our executable, which should roughly correlate with "you changed the
+    // source code so you get a different directory".
+    //
+    // Otherwise if this is a release build we use the `GIT_REV` env var
+    // which is either the git rev if installed from git or the crate
+    // version if installed from crates.io.
+    if cfg!(debug_assertions) {
+        fn self_mtime() -> Option<String> {
+            let path = std::env::current_exe().ok()?;
+            let metadata = path.metadata().ok()?;
+            let mtime = metadata.modified().ok()?;
+            Some(match mtime.duration_since(std::time::UNIX_EPOCH) {
+                Ok(dur) => format!("{}", dur.as_millis()),
+                Err(err) => format!("m{}", err.duration().as_millis()),
             })
-            .ok()?;
-
-        match fs_write_atomic(&mod_cache_path, "mod", &compressed_data) {
-            Ok(_) => Some(()),
-            Err(err) => {
-                warn!(
-                    "Failed to write file with rename, target path: {}, err: {}",
-                    mod_cache_path.display(),
-                    err
-                );
-                None
-            }
         }
+        let self_mtime = self_mtime().unwrap_or("no-mtime".to_string());
+        format!(
+            "{comp_name}-{comp_ver}-{comp_mtime}",
+            comp_name = compiler_name,
+            comp_ver = env!("GIT_REV"),
+            comp_mtime = self_mtime,
+        )
+    } else {
+        format!(
+            "{comp_name}-{comp_ver}",
+            comp_name = compiler_name,
+            comp_ver = env!("GIT_REV"),
+        )
     }
 }
 
+/// Hashes `state` into a key that can be used as a file name.
+fn hash_key<T: Hash + ?Sized>(state: &T) -> String {
+    let mut hasher = Sha256Hasher(Sha256::new());
+    state.hash(&mut hasher);
+    let hash: [u8; 32] = hasher.0.finalize().into();
+    // standard encoding uses '/' which can't be used for filename
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(&hash)
+}
+
 impl Hasher for Sha256Hasher {
     fn finish(&self) -> u64 {
         panic!("Sha256Hasher doesn't support finish!");
@@ -0,0 +1,178 @@
+//! Storage backends for the module cache, see [`ModuleCacheStore`].
+
+use crate::worker::Worker;
+use crate::{CacheConfig, fs_write_atomic};
+use log::{debug, trace, warn};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wasmtime_environ::error::Result;
+
+/// Storage for the artifacts of a [`Cache`](crate::Cache).
+///
+/// Implementations of this trait can be used with [`Cache::with_store`] to
+/// keep cached compilation artifacts somewhere other than a local directory,
+/// for example in memory, in a key-value database or in a remote blob store.
+/// [`DirectoryCacheStore`] is the implementation used by [`Cache::new`].
+///
+/// Keys consist of the name and version of the compiler which produced an
+/// artifact, followed by a `/` and a hash of the inputs to compilation. Apart
+/// from the `/` they only contain ASCII alphanumerics, `-`, `_` and `.`.
+/// Values are uncompressed and are typically some hundreds of kilobytes to
+/// megabytes in size, so stores may want to compress them.
+///
+/// Stores are free to evict entries at any time. They are only a cache, and a
+/// missing or failed entry just means that a module has to be compiled again.
+///
+/// [`Cache::with_store`]: crate::Cache::with_store
+/// [`Cache::new`]: crate::Cache::new
+pub trait ModuleCacheStore: Send + Sync + fmt::Debug {
+    /// Returns the value most recently inserted for `key`, if it's still
+    /// stored.
+    fn get(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// Stores `value` for `key`, replacing any previous value.
+    ///
+    /// Returns `false` if the value couldn't be stored.
+    fn insert(&self, key: &str, value: &[u8]) -> bool;
+}
+
+/// A [`ModuleCacheStore`] that keeps compressed artifacts in a local directory.
+///
+/// This is the store used by [`Cache::new`](crate::Cache::new). A background
+/// worker thread keeps track of how often each artifact is used, recompresses
+/// frequently used ones more aggressively, and evicts the least recently used
+/// ones once the limits in the [`CacheConfig`] are exceeded. See the
+/// [documentation online][docs] for details.
+///
+/// [docs]: https://bytecodealliance.github.io/wasmtime/cli-cache.html
+#[derive(Debug, Clone)]
+pub struct DirectoryCacheStore {
+    config: CacheConfig,
+    worker: Worker,
+}
+
+macro_rules! generate_config_setting_getter {
+    ($setting:ident: $setting_type:ty) => {
+        #[doc = concat!("Returns ", "`", stringify!($setting), "`.")]
+        pub fn $setting(&self) -> $setting_type {
+            self.config.$setting()
+        }
+    };
+}
+
+impl DirectoryCacheStore {
+    /// Builds a [`DirectoryCacheStore`] from the configuration and spawns the
+    /// cache worker.
+    ///
+    /// # Errors
+    /// Returns an error if the configuration is invalid.
+    pub fn new(mut config: CacheConfig) -> Result<Self> {
+        config.validate()?;
+        Ok(Self {
+            worker: Worker::start_new(&config),
+            config,
+        })
+    }
+
+    generate_config_setting_getter!(worker_event_queue_size: u64);
+    generate_config_setting_getter!(baseline_compression_level: i32);
+    generate_config_setting_getter!(optimized_compression_level: i32);
+    generate_config_setting_getter!(optimized_compression_usage_counter_threshold: u64);
+    generate_config_setting_getter!(cleanup_interval: std::time::Duration);
+    generate_config_setting_getter!(optimizing_compression_task_timeout: std::time::Duration);
+    generate_config_setting_getter!(allowed_clock_drift_for_files_from_future: std::time::Duration);
+    generate_config_setting_getter!(file_count_soft_limit: u64);
+    generate_config_setting_getter!(files_total_size_soft_limit: u64);
+    generate_config_setting_getter!(file_count_limit_percent_if_deleting: u8);
+    generate_config_setting_getter!(files_total_size_limit_percent_if_deleting: u8);
+
+    /// Returns path to the cache directory.
+    pub fn directory(&self) -> &PathBuf {
+        &self
+            .config
+            .directory()
+            .expect("directory should be validated in Config::new")
+    }
+
+    #[cfg(test)]
+    pub(crate) fn worker(&self) -> &Worker {
+        &self.worker
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory().join("modules").join(key)
+    }
+}
+
+impl ModuleCacheStore for DirectoryCacheStore {
+    fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let mod_cache_path = self.path(key);
+        trace!("get_data() for path: {}", mod_cache_path.display());
+        let compressed_cache_bytes = fs::read(&mod_cache_path).ok()?;
+        let cache_bytes = zstd::decode_all(&compressed_cache_bytes[..])
+            .map_err(|err| warn!("Failed to decompress cached code: {err}"))
+            .ok()?;
+        self.worker.on_cache_get_async(&mod_cache_path);
+        Some(cache_bytes)
+    }
+
+    fn insert(&self, key: &str, serialized_data: &[u8]) -> bool {
+        let mod_cache_path = self.path(key);
+        trace!("update_data() for path: {}", mod_cache_path.display());
+        let written = write_compressed(
+            &mod_cache_path,
+            serialized_data,
+            self.baseline_compression_level(),
+        );
+        if written.is_some() {
+            self.worker.on_cache_update_async(&mod_cache_path);
+        }
+        written.is_some()
+    }
+}
+
+fn write_compressed(
+    mod_cache_path: &Path,
+    serialized_data: &[u8],
+    compression_level: i32,
+) -> Option<()> {
+    let compressed_data = zstd::encode_all(&serialized_data[..], compression_level)
+        .map_err(|err| warn!("Failed to compress cached code: {err}"))
+        .ok()?;
+
+    // Optimize syscalls: first, try writing to disk. It should succeed in most cases.
+    // Otherwise, try creating the cache directory and retry writing to the file.
+    if fs_write_atomic(mod_cache_path, "mod", &compressed_data).is_ok() {
+        return Some(());
+    }
+
+    debug!(
+        "Attempting to create the cache directory, because \
+         failed to write cached code to disk, path: {}",
+        mod_cache_path.display(),
+    );
+
+    let cache_dir = mod_cache_path.parent().unwrap();
+    fs::create_dir_all(cache_dir)
+        .map_err(|err| {
+            warn!(
+                "Failed to create cache directory, path: {}, message: {}",
+                cache_dir.display(),
+                err
+            )
+        })
+        .ok()?;
+
+    match fs_write_atomic(mod_cache_path, "mod", &compressed_data) {
+        Ok(_) => Some(()),
+        Err(err) => {
+            warn!(
+                "Failed to write file with rename, target path: {}, err: {}",
+                mod_cache_path.display(),
+                err
+            );
+            None
+        }
+    }
+}
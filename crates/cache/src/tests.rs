@@ -88,3 +88,46 @@ fn test_write_read_cache() {
     entry1.get_data::<_, i32, i32>(4, |_| panic!()).unwrap();
     entry2.get_data::<_, i32, i32>(1, |_| panic!()).unwrap();
 }
+
+#[test]
+fn test_custom_store() {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Default)]
+    struct MemoryStore(Mutex<HashMap<String, Vec<u8>>>);
+
+    impl ModuleCacheStore for MemoryStore {
+        fn get(&self, key: &str) -> Option<Vec<u8>> {
+            self.0.lock().unwrap().get(key).cloned()
+        }
+
+        fn insert(&self, key: &str, value: &[u8]) -> bool {
+            self.0
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), value.to_vec());
+            true
+        }
+    }
+
+    let store = Arc::new(MemoryStore::default());
+    let cache = Cache::with_store(store.clone());
+    let entry = ModuleCacheEntry::new("test", Some(&cache));
+
+    entry.get_data::<_, i32, i32>(1, |_| Ok(100)).unwrap();
+    assert_eq!(entry.get_data::<_, i32, i32>(1, |_| panic!()), Ok(100));
+    entry.get_data::<_, i32, i32>(2, |_| Ok(200)).unwrap();
+    assert_eq!(cache.cache_hits(), 1);
+    assert_eq!(cache.cache_misses(), 2);
+
+    let entries = store.0.lock().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert!(entries.keys().all(|key| key.starts_with("test-")));
+
+    // Entries evicted by the store are simply recomputed.
+    drop(entries);
+    store.0.lock().unwrap().clear();
+    assert_eq!(entry.get_data::<_, i32, i32>(1, |_| Ok(101)), Ok(101));
+    assert_eq!(cache.cache_misses(), 3);
+}
@@ -210,20 +210,23 @@ wasmtime_option_group! {
     #[derive(PartialEq, Clone, Deserialize)]
     #[serde(rename_all = "kebab-case", deny_unknown_fields)]
     pub struct CodegenOptions {
-        /// Either `cranelift` or `winch`.
+        /// Either `cranelift`, `winch` or `tiered`.
         ///
-        /// Currently only `cranelift` and `winch` are supported, but not all
-        /// builds of Wasmtime have both built in.
+        /// `tiered` compiles with `winch` first and recompiles frequently
+        /// called functions with `cranelift` in the background. Not all builds
+        /// of Wasmtime have both compilers built in.
         #[serde(default)]
         #[serde(deserialize_with = "crate::opt::cli_parse_wrapper")]
         pub compiler: Option<wasmtime::Strategy>,
-        /// Which garbage collector to use: `drc` or `null`.
+        /// Which garbage collector to use: `drc`, `null`, or `copying`.
         ///
         /// `drc` is the deferred reference-counting collector.
         ///
         /// `null` is the null garbage collector, which does not collect any
         /// garbage.
         ///
+        /// `copying` is the semi-space copying collector.
+        ///
         /// Note that not all builds of Wasmtime will have support for garbage
         /// collection included.
         #[serde(default)]
@@ -235,6 +238,12 @@ wasmtime_option_group! {
         pub cache: Option<bool>,
         /// Configuration for compiled module caching.
         pub cache_config: Option<String>,
+        /// Directory of a code store to share compiled modules through with
+        /// other processes.
+        pub code_store: Option<String>,
+        /// Remove artifacts from the code store that haven't been used for
+        /// this long (3600, 86400s, etc) on startup.
+        pub code_store_gc: Option<Duration>,
         /// Whether or not to enable parallel compilation of modules.
         pub parallel_compilation: Option<bool>,
         /// Whether to enable proof-carrying code (PCC)-based validation.
@@ -777,6 +786,20 @@ impl CommonOptions {
         if self.codegen.cache == Some(true) {
             anyhow::bail!("support for caching disabled at compile time");
         }
+        #[cfg(feature = "cache")]
+        if let Some(dir) = &self.codegen.code_store {
+            let store = wasmtime::CodeStore::new(dir)?;
+            if let Some(unused_for) = self.codegen.code_store_gc {
+                store.gc(unused_for)?;
+            }
+            config.code_store(Some(store));
+        } else if self.codegen.code_store_gc.is_some() {
+            anyhow::bail!("`-C code-store-gc` requires `-C code-store`");
+        }
+        #[cfg(not(feature = "cache"))]
+        if self.codegen.code_store.is_some() {
+            anyhow::bail!("support for caching disabled at compile time");
+        }
 
         match_feature! {
             ["parallel-compilation" : self.codegen.parallel_compilation]
@@ -1175,6 +1198,7 @@ mod tests {
         for (strategy_value, expected) in [
             ("\"cranelift\"", Some(wasmtime::Strategy::Cranelift)),
             ("\"winch\"", Some(wasmtime::Strategy::Winch)),
+            ("\"tiered\"", Some(wasmtime::Strategy::Tiered)),
             ("\"hello\"", None), // should fail
             ("5", None),         // should fail
             ("true", None),      // should fail
@@ -1201,6 +1225,7 @@ mod tests {
                 Some(wasmtime::Collector::DeferredReferenceCounting),
             ),
             ("\"null\"", Some(wasmtime::Collector::Null)),
+            ("\"copying\"", Some(wasmtime::Collector::Copying)),
             ("\"hello\"", None), // should fail
             ("5", None),         // should fail
             ("true", None),      // should fail
@@ -69,6 +69,9 @@ pub struct CompiledFunction {
     pub debug_slot_descriptor: Option<FrameStateSlotBuilder>,
     /// Debug breakpoint patches: Wasm PC, offset range in buffer.
     pub breakpoint_patch_points: Vec<(u32, Range<u32>)>,
+    /// The Wasm PC of each instruction counter, if instruction counting
+    /// is enabled.
+    pub instruction_counter_pcs: Vec<u32>,
 }
 
 impl CompiledFunction {
@@ -87,6 +90,7 @@ impl CompiledFunction {
             metadata: Default::default(),
             debug_slot_descriptor: None,
             breakpoint_patch_points: vec![],
+            instruction_counter_pcs: vec![],
         };
         this.finalize_breakpoints();
 
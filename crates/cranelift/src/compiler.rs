@@ -32,13 +32,15 @@ use std::path;
 use std::sync::{Arc, Mutex};
 use wasmparser::{FuncValidatorAllocations, FunctionBody};
 use wasmtime_environ::error::{Context as _, Result};
-use wasmtime_environ::obj::{ELF_WASMTIME_EXCEPTIONS, ELF_WASMTIME_FRAMES};
+use wasmtime_environ::obj::{
+    ELF_WASMTIME_EXCEPTIONS, ELF_WASMTIME_FRAMES, ELF_WASMTIME_INSTRUCTION_COUNTS,
+};
 use wasmtime_environ::{
     prelude::*, Abi, AddressMapSection, BuiltinFunctionIndex, CacheStore, CompileError,
-    CompiledFunctionBody, DefinedFuncIndex, FlagValue, FrameInstPos, FrameStackShape,
-    FrameStateSlotBuilder, FrameTableBuilder, FuncKey, FunctionBodyData, FunctionLoc, HostCall,
-    InliningCompiler, ModuleTranslation, ModuleTypesBuilder, PtrSize, StackMapSection,
-    StaticModuleIndex, TrapEncodingBuilder, TrapSentinel, TripleExt, Tunables, WasmFuncType, WasmValType,
+    CompiledFunctionBody, DefinedFuncIndex, FlagValue, FrameInstPos, FrameMemoryAccess,
+    FrameStackShape, FrameStateSlotBuilder, FrameTableBuilder, FuncKey, FunctionBodyData, FunctionLoc, HostCall,
+    InliningCompiler, InstructionCountsBuilder, ModuleTranslation, ModuleTypesBuilder, PtrSize, StackMapSection,
+    StaticModuleIndex, TrapEncodingBuilder, TrapSentinel, TripleExt, Tunables, VMOffsets, WasmFuncType, WasmValType,
 };
 use wasmtime_unwinder::ExceptionTableBuilder;
 
@@ -58,6 +60,7 @@ struct CompilerContext {
     incremental_cache_ctx: Option<IncrementalCacheContext>,
     validator_allocations: FuncValidatorAllocations,
     debug_slot_descriptor: Option<FrameStateSlotBuilder>,
+    instruction_counter_pcs: Vec<u32>,
     abi: Option<Abi>,
 }
 
@@ -69,6 +72,7 @@ impl Default for CompilerContext {
             incremental_cache_ctx: None,
             validator_allocations: Default::default(),
             debug_slot_descriptor: None,
+            instruction_counter_pcs: Vec::new(),
             abi: None,
         }
     }
@@ -348,6 +352,7 @@ impl wasmtime_environ::Compiler for Compiler {
 
         let needs_gc_heap = func_env.needs_gc_heap();
 
+        compiler.cx.instruction_counter_pcs = mem::take(&mut func_env.instruction_counter_pcs);
         if let Some((_, slot_builder)) = func_env.state_slot {
             compiler.cx.debug_slot_descriptor = Some(slot_builder);
         }
@@ -374,9 +379,22 @@ impl wasmtime_environ::Compiler for Compiler {
         let sig = translation.module.functions[func_index]
             .signature
             .unwrap_module_type_index();
+
+        // With tiered compilation the callee's code may be replaced at
+        // runtime, so call whatever its `VMFuncRef` currently points to. This
+        // way only the `wasm_call` pointer of the `VMFuncRef` ever changes.
+        let indirect_callee = if self.tunables.tiered_compilation {
+            let offsets = VMOffsets::new(self.isa.pointer_bytes(), &translation.module);
+            let func_ref = translation.module.functions[func_index].func_ref;
+            Some(offsets.vmctx_func_ref(func_ref) + u32::from(offsets.ptr.vm_func_ref_wasm_call()))
+        } else {
+            None
+        };
+
         self.array_to_wasm_trampoline(
             key,
             FuncKey::DefinedWasmFunction(module_index, def_func_index),
+            indirect_callee,
             types[sig].unwrap_func(),
             symbol,
             self.isa.pointer_bytes().vmctx_store_context().into(),
@@ -481,6 +499,7 @@ impl wasmtime_environ::Compiler for Compiler {
         let mut stack_maps = StackMapSection::default();
         let mut exception_tables = ExceptionTableBuilder::default();
         let mut frame_tables = FrameTableBuilder::default();
+        let mut instruction_counts = InstructionCountsBuilder::default();
 
         let funcs = funcs
             .iter()
@@ -493,6 +512,7 @@ impl wasmtime_environ::Compiler for Compiler {
             .collect::<Vec<_>>();
 
         let mut frame_descriptors = HashMap::new();
+        let mut memory_accesses: Vec<(u32, FrameMemoryAccess)> = Vec::new();
         if self.tunables.debug_guest {
             for (_, key, func) in &funcs {
                 frame_descriptors.insert(
@@ -502,14 +522,26 @@ impl wasmtime_environ::Compiler for Compiler {
                         .map(|builder| builder.serialize())
                         .unwrap_or_else(|| vec![]),
                 );
+                if let Some(builder) = &func.debug_slot_descriptor {
+                    memory_accesses.extend_from_slice(builder.memory_accesses());
+                }
             }
         }
 
+        // As with breakpoints below, a Wasm PC may have been translated
+        // more than once due to inlining; its access is the same each
+        // time.
+        memory_accesses.sort_by_key(|(wasm_pc, _)| *wasm_pc);
+        memory_accesses.dedup_by_key(|(wasm_pc, _)| *wasm_pc);
+        for (wasm_pc, access) in &memory_accesses {
+            frame_tables.add_memory_access(*wasm_pc, access);
+        }
+
         let mut breakpoint_table = Vec::new();
         let mut nop_units = None;
 
         let mut ret = Vec::with_capacity(funcs.len());
-        for (i, (sym, _key, func)) in funcs.iter().enumerate() {
+        for (i, (sym, key, func)) in funcs.iter().enumerate() {
             let (sym_id, range) = builder.append_func(&sym, func, |idx| resolve_reloc(i, idx));
             log::trace!("symbol id {sym_id:?} = {sym:?}");
 
@@ -549,6 +581,9 @@ impl wasmtime_environ::Compiler for Compiler {
                 )?;
                 nop_units.get_or_insert_with(|| func.buffer.nop_units.clone());
             }
+            if self.tunables.count_instructions && !func.instruction_counter_pcs.is_empty() {
+                instruction_counts.add_function(*key, &func.instruction_counter_pcs);
+            }
             builder.append_padding(self.linkopts.padding_between_functions);
 
             let info = FunctionLoc {
@@ -616,6 +651,17 @@ impl wasmtime_environ::Compiler for Compiler {
             });
         }
 
+        if self.tunables.count_instructions {
+            let section = obj.add_section(
+                obj.segment_name(StandardSegment::Data).to_vec(),
+                ELF_WASMTIME_INSTRUCTION_COUNTS.as_bytes().to_vec(),
+                SectionKind::ReadOnlyData,
+            );
+            instruction_counts.serialize(|bytes| {
+                obj.append_section_data(section, bytes, 4);
+            });
+        }
+
         Ok(ret)
     }
 
@@ -1293,10 +1339,17 @@ impl Compiler {
         builder.ins().trapz(is_expected_vmctx, TRAP_INTERNAL_ASSERT);
     }
 
+    /// Compiles a trampoline which calls the wasm function `callee_key` with
+    /// arguments and results passed in an array.
+    ///
+    /// If `indirect_callee` is provided then it's the offset within the callee
+    /// vmctx of the code pointer to call, and `callee_key` isn't called
+    /// directly.
     fn array_to_wasm_trampoline(
         &self,
         trampoline_key: FuncKey,
         callee_key: FuncKey,
+        indirect_callee: Option<u32>,
         callee_sig: &WasmFuncType,
         symbol: &str,
         vm_store_context_offset: u32,
@@ -1363,21 +1416,6 @@ impl Compiler {
 
         // Then call the Wasm function with those arguments.
         let signature = builder.func.import_signature(wasm_call_sig.clone());
-        let callee = {
-            let (namespace, index) = callee_key.into_raw_parts();
-            let name = ir::ExternalName::User(
-                builder
-                    .func
-                    .declare_imported_user_function(ir::UserExternalName { namespace, index }),
-            );
-            builder.func.dfg.ext_funcs.push(ir::ExtFuncData {
-                name,
-                signature,
-                colocated: true,
-                patchable: false,
-            })
-        };
-
         let dfg = &mut builder.func.dfg;
         let exception_table = dfg.exception_tables.push(ir::ExceptionTableData::new(
             signature,
@@ -1393,7 +1431,32 @@ impl Compiler {
                 &mut dfg.value_lists,
             ))],
         ));
-        builder.ins().try_call(callee, &args, exception_table);
+        match indirect_callee {
+            Some(offset) => {
+                let callee = builder.ins().load(
+                    pointer_type,
+                    MemFlags::trusted(),
+                    vmctx,
+                    i32::try_from(offset).unwrap(),
+                );
+                builder.ins().try_call_indirect(callee, &args, exception_table);
+            }
+            None => {
+                let (namespace, index) = callee_key.into_raw_parts();
+                let name = ir::ExternalName::User(
+                    builder
+                        .func
+                        .declare_imported_user_function(ir::UserExternalName { namespace, index }),
+                );
+                let callee = builder.func.dfg.ext_funcs.push(ir::ExtFuncData {
+                    name,
+                    signature,
+                    colocated: true,
+                    patchable: false,
+                });
+                builder.ins().try_call(callee, &args, exception_table);
+            }
+        }
 
         builder.seal_block(try_call_block);
         builder.seal_block(normal_return);
@@ -1526,6 +1589,7 @@ impl FunctionCompiler<'_> {
         if let Some(builder) = self.cx.debug_slot_descriptor.take() {
             compiled_function.debug_slot_descriptor = Some(builder);
         }
+        compiled_function.instruction_counter_pcs = mem::take(&mut self.cx.instruction_counter_pcs);
 
         if body_and_tunables
             .map(|(_, t)| t.debug_native)
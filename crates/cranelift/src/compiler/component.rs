@@ -1423,6 +1423,7 @@ impl ComponentCompiler for Compiler {
                 return Ok(self.array_to_wasm_trampoline(
                     key,
                     FuncKey::ComponentTrampoline(Abi::Wasm, trampoline_index),
+                    None,
                     sig,
                     symbol,
                     offsets.vm_store_context(),
@@ -1506,6 +1507,7 @@ impl ComponentCompiler for Compiler {
                 return Ok(self.array_to_wasm_trampoline(
                     FuncKey::UnsafeIntrinsic(abi, intrinsic),
                     FuncKey::UnsafeIntrinsic(Abi::Wasm, intrinsic),
+                    None,
                     &wasm_func_ty,
                     symbol,
                     offsets.vm_store_context(),
@@ -4,7 +4,7 @@ pub(crate) mod stack_switching;
 use crate::compiler::Compiler;
 use crate::translate::{
     FuncTranslationStacks, GlobalVariable, Heap, HeapData, StructFieldsVec, TableData, TableSize,
-    TargetEnvironment,
+    TargetEnvironment, bitcast_wasm_returns,
 };
 use crate::{BuiltinFunctionSignatures, TRAP_INTERNAL_ASSERT};
 use cranelift_codegen::cursor::FuncCursor;
@@ -24,11 +24,11 @@ use std::mem;
 use wasmparser::{FuncValidator, Operator, WasmFeatures, WasmModuleResources};
 use wasmtime_environ::{
     BuiltinFunctionIndex, DataIndex, DefinedFuncIndex, ElemIndex, EngineOrModuleTypeIndex,
-    FrameStateSlotBuilder, FrameValType, FuncIndex, FuncKey, GlobalIndex, IndexType, Memory,
-    MemoryIndex, Module, ModuleInternedTypeIndex, ModuleTranslation, ModuleTypesBuilder, PtrSize,
-    Table, TableIndex, TagIndex, TripleExt, Tunables, TypeConvert, TypeIndex, VMOffsets,
-    WasmCompositeInnerType, WasmFuncType, WasmHeapTopType, WasmHeapType, WasmRefType, WasmResult,
-    WasmValType,
+    FrameControl, FrameMemoryAccess, FrameMemoryAccessKind, FrameStateSlotBuilder, FrameValType,
+    FuncIndex, FuncKey, GlobalIndex, IndexType, Memory, MemoryIndex, Module,
+    ModuleInternedTypeIndex, ModuleTranslation, ModuleTypesBuilder, PtrSize, Table, TableIndex,
+    TagIndex, TripleExt, Tunables, TypeConvert, TypeIndex, VMOffsets, WasmCompositeInnerType,
+    WasmFuncType, WasmHeapTopType, WasmHeapType, WasmRefType, WasmResult, WasmValType,
 };
 use wasmtime_environ::{FUNCREF_INIT_BIT, FUNCREF_MASK};
 use wasmtime_math::f64_cvt_to_int_bounds;
@@ -194,6 +194,23 @@ pub struct FuncEnvironment<'module_environment> {
 
     fuel_consumed: i64,
 
+    /// A cached pointer to this function's instruction counters, when
+    /// instruction counting is enabled. Initialized in the function
+    /// prologue.
+    instruction_counters_var: cranelift_frontend::Variable,
+
+    /// The number of instructions translated since the current
+    /// instruction counter's block began, not yet added to the counter.
+    instructions_pending: u32,
+
+    /// The Wasm PC of the first instruction of the current instruction
+    /// counter's block.
+    instruction_block_pc: u32,
+
+    /// The Wasm PC at which each of this function's instruction counters
+    /// begins, indexed by counter.
+    pub(crate) instruction_counter_pcs: Vec<u32>,
+
     /// A `GlobalValue` in CLIF which represents the stack limit.
     ///
     /// Typically this resides in the `stack_limit` value of `ir::Function` but
@@ -269,6 +286,11 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
             // functions should consume at least some fuel.
             fuel_consumed: 1,
 
+            instruction_counters_var: Variable::reserved_value(),
+            instructions_pending: 0,
+            instruction_block_pc: 0,
+            instruction_counter_pcs: Vec::new(),
+
             translation,
 
             stack_limit_at_function_entry: None,
@@ -904,6 +926,94 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         builder.switch_to_block(continuation_block);
     }
 
+    /// Loads the pointer to this function's instruction counters out of
+    /// the `VMContext`'s instruction-counters table.
+    fn instruction_counters_function_entry(&mut self, builder: &mut FunctionBuilder<'_>) {
+        debug_assert!(self.instruction_counters_var.is_reserved_value());
+        self.instruction_counters_var = builder.declare_var(self.pointer_type());
+
+        let (_, def_func_index) = self.key.unwrap_defined_wasm_function();
+        let vmctx = self.vmctx(builder.func);
+        let pointer_type = self.pointer_type();
+        let flags = ir::MemFlags::trusted().with_readonly();
+        let base = builder.ins().global_value(pointer_type, vmctx);
+        let table = builder.ins().load(
+            pointer_type,
+            flags,
+            base,
+            i32::from(self.offsets.ptr.vmctx_instruction_counters()),
+        );
+        let offset = def_func_index.as_u32() * u32::from(self.offsets.ptr.size());
+        let offset = i32::try_from(offset).unwrap();
+        let counters = builder.ins().load(pointer_type, flags, table, offset);
+        builder.def_var(self.instruction_counters_var, counters);
+    }
+
+    fn count_instructions_before_op(
+        &mut self,
+        op: &Operator<'_>,
+        builder: &mut FunctionBuilder<'_>,
+    ) {
+        if !self.is_reachable() {
+            // As with fuel, becoming unreachable means the current
+            // block's counter was already updated.
+            debug_assert_eq!(self.instructions_pending, 0);
+            return;
+        }
+
+        if self.instructions_pending == 0 {
+            self.instruction_block_pc = builder.srcloc().bits();
+        }
+        self.instructions_pending += 1;
+
+        match op {
+            // These mirror the block boundaries in `fuel_before_op`: any
+            // instruction which may move control elsewhere ends the
+            // current block, and the instructions executed so far in it
+            // are added to its counter before control leaves.
+            Operator::Unreachable
+            | Operator::Return
+            | Operator::Call { .. }
+            | Operator::CallIndirect { .. }
+            | Operator::CallRef { .. }
+            | Operator::ReturnCall { .. }
+            | Operator::ReturnCallRef { .. }
+            | Operator::ReturnCallIndirect { .. }
+            | Operator::Throw { .. }
+            | Operator::ThrowRef
+            | Operator::Loop { .. }
+            | Operator::If { .. }
+            | Operator::Else
+            | Operator::End
+            | Operator::Br { .. }
+            | Operator::BrIf { .. }
+            | Operator::BrTable { .. }
+            | Operator::BrOnNull { .. }
+            | Operator::BrOnNonNull { .. }
+            | Operator::BrOnCast { .. }
+            | Operator::BrOnCastFail { .. } => self.count_instructions_flush(builder),
+
+            _ => {}
+        }
+    }
+
+    /// Adds the instructions executed so far in the current block to a
+    /// new counter for that block.
+    fn count_instructions_flush(&mut self, builder: &mut FunctionBuilder<'_>) {
+        let index = u32::try_from(self.instruction_counter_pcs.len()).unwrap();
+        self.instruction_counter_pcs.push(self.instruction_block_pc);
+
+        let counters = builder.use_var(self.instruction_counters_var);
+        let offset = i32::try_from(index * 8).unwrap();
+        let flags = ir::MemFlags::trusted();
+        let count = builder.ins().load(I64, flags, counters, offset);
+        let count = builder
+            .ins()
+            .iadd_imm(count, i64::from(self.instructions_pending));
+        builder.ins().store(flags, count, counters, offset);
+        self.instructions_pending = 0;
+    }
+
     /// Get the Memory for the given index.
     fn memory(&self, index: MemoryIndex) -> Memory {
         self.module.memories[index]
@@ -1354,7 +1464,11 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
     /// Initialize the state slot with an empty layout.
     pub(crate) fn create_state_slot(&mut self, builder: &mut FunctionBuilder) {
         if self.tunables.debug_guest {
-            let frame_builder = FrameStateSlotBuilder::new(self.key, self.pointer_type().bytes());
+            let mut frame_builder =
+                FrameStateSlotBuilder::new(self.key, self.pointer_type().bytes());
+            for ty in self.wasm_func_ty.returns() {
+                frame_builder.add_result(FrameValType::from(*ty));
+            }
 
             // Initially zero-size and with no descriptor; we will fill in
             // this info once we're done with the function body.
@@ -1478,6 +1592,114 @@ impl<'module_environment> FuncEnvironment<'module_environment> {
         }
     }
 
+    /// Act on any request the debugger left in the state slot's
+    /// control word while this frame was paused at the breakpoint
+    /// just emitted: either reload locals and operand-stack values
+    /// from the slot, or return early with results from the slot.
+    ///
+    /// Reference-typed values are never reloaded; the runtime refuses
+    /// to modify them, and likewise refuses early returns from
+    /// functions with reference-typed results.
+    fn handle_state_slot_control(&mut self, builder: &mut FunctionBuilder) {
+        let Some((slot, b)) = self.state_slot.take() else {
+            return;
+        };
+        let control_offset = b.control_offset().offset();
+        let control = builder.ins().stack_load(I32, slot, control_offset);
+
+        let modified_block = builder.create_block();
+        let continuation_block = builder.create_block();
+        builder.set_cold_block(modified_block);
+
+        // Operand-stack values that may have been modified flow into
+        // the continuation as block parameters.
+        let reloadable = (0..self.stacks.stack.len())
+            .filter(|&i| {
+                let ty = b.stack_last_type(self.stacks.stack_shape[i]);
+                frame_val_clif_type(ty).is_some()
+            })
+            .collect::<SmallVec<[usize; 8]>>();
+        let args = reloadable
+            .iter()
+            .map(|&i| {
+                let value = self.stacks.stack[i];
+                let ty = builder.func.dfg.value_type(value);
+                builder.append_block_param(continuation_block, ty);
+                BlockArg::Value(value)
+            })
+            .collect::<SmallVec<[BlockArg; 8]>>();
+        builder
+            .ins()
+            .brif(control, modified_block, &[], continuation_block, &args);
+
+        builder.switch_to_block(modified_block);
+        builder.seal_block(modified_block);
+        let none = builder
+            .ins()
+            .iconst(I32, i64::from(FrameControl::None as u32));
+        builder.ins().stack_store(none, slot, control_offset);
+
+        let result_tys = b
+            .results()
+            .iter()
+            .map(|&(ty, offset)| Some((frame_val_clif_type(ty)?, offset)))
+            .collect::<Option<SmallVec<[_; 4]>>>();
+        if let Some(result_tys) = result_tys {
+            let return_block = builder.create_block();
+            let reload_block = builder.create_block();
+            builder.set_cold_block(return_block);
+            builder.set_cold_block(reload_block);
+            let is_return = builder.ins().icmp_imm(
+                IntCC::Equal,
+                control,
+                i64::from(FrameControl::Return as u32),
+            );
+            builder
+                .ins()
+                .brif(is_return, return_block, &[], reload_block, &[]);
+
+            builder.switch_to_block(return_block);
+            builder.seal_block(return_block);
+            let mut results = result_tys
+                .iter()
+                .map(|&(ty, offset)| builder.ins().stack_load(ty, slot, offset.offset()))
+                .collect::<Vec<_>>();
+            self.handle_before_return(&results, builder);
+            bitcast_wasm_returns(&mut results, builder);
+            builder.ins().return_(&results);
+
+            builder.switch_to_block(reload_block);
+            builder.seal_block(reload_block);
+        }
+
+        for (i, &(ty, offset)) in b.locals().iter().enumerate() {
+            if let Some(ty) = frame_val_clif_type(ty) {
+                let value = builder.ins().stack_load(ty, slot, offset.offset());
+                builder.def_var(Variable::from_u32(u32::try_from(i).unwrap()), value);
+            }
+        }
+        let reloaded = reloadable
+            .iter()
+            .map(|&i| {
+                let ty = builder.func.dfg.value_type(self.stacks.stack[i]);
+                let offset = b.stack_last_offset(self.stacks.stack_shape[i]);
+                BlockArg::Value(builder.ins().stack_load(ty, slot, offset.offset()))
+            })
+            .collect::<SmallVec<[BlockArg; 8]>>();
+        builder.ins().jump(continuation_block, &reloaded);
+
+        builder.switch_to_block(continuation_block);
+        builder.seal_block(continuation_block);
+        for (&i, &value) in reloadable
+            .iter()
+            .zip(builder.block_params(continuation_block))
+        {
+            self.stacks.stack[i] = value;
+        }
+
+        self.state_slot = Some((slot, b));
+    }
+
     fn update_state_slot_vmctx(&mut self, builder: &mut FunctionBuilder) {
         if let &Some((slot, _)) = &self.state_slot {
             let vmctx = self.vmctx_val(&mut builder.cursor());
@@ -2085,6 +2307,25 @@ impl<'a, 'func, 'module_env> Call<'a, 'func, 'module_env> {
             // Then append the regular call arguments.
             real_call_args.extend_from_slice(wasm_call_args);
 
+            // With tiered compilation the callee's code may be replaced at
+            // runtime, so call whatever its `VMFuncRef` currently points to.
+            if self.env.tunables.tiered_compilation {
+                let func_ref = self.env.module.functions[callee_index].func_ref;
+                let pointer_type = self.env.pointer_type();
+                let body_offset = i32::try_from(
+                    self.env.offsets.vmctx_func_ref(func_ref)
+                        + u32::from(self.env.offsets.ptr.vm_func_ref_wasm_call()),
+                )
+                .unwrap();
+                let func_addr = self.builder.ins().load(
+                    pointer_type,
+                    ir::MemFlags::trusted(),
+                    caller_vmctx,
+                    body_offset,
+                );
+                return Ok(self.indirect_call_inst(sig_ref, func_addr, &real_call_args));
+            }
+
             // Finally, make the direct call!
             let callee = self
                 .env
@@ -3899,12 +4140,22 @@ impl FuncEnvironment<'_> {
         if self.tunables.consume_fuel {
             self.fuel_before_op(op, builder);
         }
+        if self.tunables.count_instructions {
+            self.count_instructions_before_op(op, builder);
+        }
         if self.is_reachable() && self.state_slot.is_some() {
             let builtin = self.builtin_functions.patchable_breakpoint(builder.func);
             let vmctx = self.vmctx_val(&mut builder.cursor());
             let inst = builder.ins().call(builtin, &[vmctx]);
             let tags = self.debug_tags(builder.srcloc());
             builder.func.debug_tags.set(inst, tags);
+            self.handle_state_slot_control(builder);
+
+            if let Some(access) = frame_memory_access(op)
+                && let Some((_, b)) = &mut self.state_slot
+            {
+                b.add_memory_access(builder.srcloc().bits(), access);
+            }
         }
 
         Ok(())
@@ -3952,6 +4203,10 @@ impl FuncEnvironment<'_> {
             self.epoch_function_entry(builder);
         }
 
+        if self.tunables.count_instructions {
+            self.instruction_counters_function_entry(builder);
+        }
+
         #[cfg(feature = "wmemcheck")]
         if self.compiler.wmemcheck {
             let func_name = self.current_func_name(builder);
@@ -3971,6 +4226,7 @@ impl FuncEnvironment<'_> {
         if self.tunables.consume_fuel && self.is_reachable() {
             self.fuel_function_exit(builder);
         }
+        debug_assert_eq!(self.instructions_pending, 0);
         self.finish_debug_metadata(builder);
         Ok(())
     }
@@ -4689,3 +4945,90 @@ fn index_type_to_ir_type(index_type: IndexType) -> ir::Type {
         IndexType::I64 => I64,
     }
 }
+
+/// Describe the linear-memory access performed by `op`, if any, for
+/// the frame table's watchpoint metadata.
+///
+/// Only plain (and atomic) loads and stores are described; bulk-memory
+/// operations, lane accesses and atomic read-modify-writes are not.
+fn frame_memory_access(op: &Operator) -> Option<FrameMemoryAccess> {
+    use FrameMemoryAccessKind::{Load, Store};
+    let (memarg, size, kind) = match op {
+        Operator::I32Load8S { memarg }
+        | Operator::I32Load8U { memarg }
+        | Operator::I64Load8S { memarg }
+        | Operator::I64Load8U { memarg }
+        | Operator::V128Load8Splat { memarg }
+        | Operator::I32AtomicLoad8U { memarg }
+        | Operator::I64AtomicLoad8U { memarg } => (memarg, 1, Load),
+        Operator::I32Load16S { memarg }
+        | Operator::I32Load16U { memarg }
+        | Operator::I64Load16S { memarg }
+        | Operator::I64Load16U { memarg }
+        | Operator::V128Load16Splat { memarg }
+        | Operator::I32AtomicLoad16U { memarg }
+        | Operator::I64AtomicLoad16U { memarg } => (memarg, 2, Load),
+        Operator::I32Load { memarg }
+        | Operator::F32Load { memarg }
+        | Operator::I64Load32S { memarg }
+        | Operator::I64Load32U { memarg }
+        | Operator::V128Load32Splat { memarg }
+        | Operator::V128Load32Zero { memarg }
+        | Operator::I32AtomicLoad { memarg }
+        | Operator::I64AtomicLoad32U { memarg } => (memarg, 4, Load),
+        Operator::I64Load { memarg }
+        | Operator::F64Load { memarg }
+        | Operator::V128Load8x8S { memarg }
+        | Operator::V128Load8x8U { memarg }
+        | Operator::V128Load16x4S { memarg }
+        | Operator::V128Load16x4U { memarg }
+        | Operator::V128Load32x2S { memarg }
+        | Operator::V128Load32x2U { memarg }
+        | Operator::V128Load64Splat { memarg }
+        | Operator::V128Load64Zero { memarg }
+        | Operator::I64AtomicLoad { memarg } => (memarg, 8, Load),
+        Operator::V128Load { memarg } => (memarg, 16, Load),
+        Operator::I32Store8 { memarg }
+        | Operator::I64Store8 { memarg }
+        | Operator::I32AtomicStore8 { memarg }
+        | Operator::I64AtomicStore8 { memarg } => (memarg, 1, Store),
+        Operator::I32Store16 { memarg }
+        | Operator::I64Store16 { memarg }
+        | Operator::I32AtomicStore16 { memarg }
+        | Operator::I64AtomicStore16 { memarg } => (memarg, 2, Store),
+        Operator::I32Store { memarg }
+        | Operator::F32Store { memarg }
+        | Operator::I64Store32 { memarg }
+        | Operator::I32AtomicStore { memarg }
+        | Operator::I64AtomicStore32 { memarg } => (memarg, 4, Store),
+        Operator::I64Store { memarg }
+        | Operator::F64Store { memarg }
+        | Operator::I64AtomicStore { memarg } => (memarg, 8, Store),
+        Operator::V128Store { memarg } => (memarg, 16, Store),
+        _ => return None,
+    };
+    Some(FrameMemoryAccess {
+        memory: MemoryIndex::from_u32(memarg.memory),
+        offset: memarg.offset,
+        size,
+        kind,
+    })
+}
+
+/// The CLIF type in which a local or operand-stack value of the given
+/// frame type is reloaded from the state slot, or `None` for reference
+/// types, which are never reloaded.
+fn frame_val_clif_type(ty: FrameValType) -> Option<ir::Type> {
+    match ty {
+        FrameValType::I32 => Some(I32),
+        FrameValType::I64 => Some(I64),
+        FrameValType::F32 => Some(F32),
+        FrameValType::F64 => Some(F64),
+        FrameValType::V128 => Some(I8X16),
+        FrameValType::AnyRef
+        | FrameValType::FuncRef
+        | FrameValType::ExternRef
+        | FrameValType::ExnRef
+        | FrameValType::ContRef => None,
+    }
+}
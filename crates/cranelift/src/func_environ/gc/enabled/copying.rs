@@ -0,0 +1,240 @@
+//! Compiler for the semi-space copying collector.
+//!
+//! The copying collector does not need read or write barriers: it finds every
+//! live object by tracing from the roots at collection time, and it relocates
+//! on-stack GC references by rewriting the stack slots described by stack
+//! maps. Therefore, the only requirements on compiled code are that every GC
+//! reference that is live across a safepoint is included in a stack map (so
+//! that it can be updated when its referent moves) and that allocation goes
+//! through the `gc_alloc_raw` libcall, which may trigger a collection.
+
+use super::*;
+use crate::func_environ::FuncEnvironment;
+use cranelift_codegen::ir::{self, InstBuilder};
+use cranelift_frontend::FunctionBuilder;
+use wasmtime_environ::copying::{EXCEPTION_TAG_DEFINED_OFFSET, EXCEPTION_TAG_INSTANCE_OFFSET};
+use wasmtime_environ::{
+    GcTypeLayouts, TypeIndex, VMGcKind, WasmRefType, WasmResult, copying::CopyingTypeLayouts,
+};
+
+#[derive(Default)]
+pub struct CopyingCompiler {
+    layouts: CopyingTypeLayouts,
+}
+
+impl GcCompiler for CopyingCompiler {
+    fn layouts(&self) -> &dyn GcTypeLayouts {
+        &self.layouts
+    }
+
+    fn alloc_array(
+        &mut self,
+        func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder<'_>,
+        array_type_index: TypeIndex,
+        init: super::ArrayInit<'_>,
+    ) -> WasmResult<ir::Value> {
+        let interned_type_index =
+            func_env.module.types[array_type_index].unwrap_module_type_index();
+        let ptr_ty = func_env.pointer_type();
+
+        let len_offset = gc_compiler(func_env)?.layouts().array_length_field_offset();
+        let array_layout = func_env.array_layout(interned_type_index).clone();
+        let base_size = array_layout.base_size;
+        let align = array_layout.align;
+        let len_to_elems_delta = base_size.checked_sub(len_offset).unwrap();
+
+        // First, compute the array's total size from its base size, element
+        // size, and length.
+        let len = init.len(&mut builder.cursor());
+        let size = emit_array_size(func_env, builder, &array_layout, len);
+
+        // Second, allocate the array via the `gc_alloc_raw` libcall.
+        let array_ref = emit_gc_raw_alloc(
+            func_env,
+            builder,
+            VMGcKind::ArrayRef,
+            interned_type_index,
+            size,
+            align,
+        );
+
+        // Write the array's length into the appropriate slot.
+        //
+        // Note: we don't need to bounds-check the GC ref access here, since we
+        // trust the results of the allocation libcall.
+        let base = func_env.get_gc_heap_base(builder);
+        let extended_array_ref =
+            uextend_i32_to_pointer_type(builder, func_env.pointer_type(), array_ref);
+        let object_addr = builder.ins().iadd(base, extended_array_ref);
+        let len_addr = builder.ins().iadd_imm(object_addr, i64::from(len_offset));
+        let len = init.len(&mut builder.cursor());
+        builder
+            .ins()
+            .store(ir::MemFlags::trusted(), len, len_addr, 0);
+
+        // Finally, initialize the elements.
+        let len_to_elems_delta = builder.ins().iconst(ptr_ty, i64::from(len_to_elems_delta));
+        let elems_addr = builder.ins().iadd(len_addr, len_to_elems_delta);
+        init.initialize(
+            func_env,
+            builder,
+            interned_type_index,
+            base_size,
+            size,
+            elems_addr,
+            |func_env, builder, elem_ty, elem_addr, val| {
+                write_field_at_addr(func_env, builder, elem_ty, elem_addr, val)
+            },
+        )?;
+        Ok(array_ref)
+    }
+
+    fn alloc_struct(
+        &mut self,
+        func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder<'_>,
+        struct_type_index: TypeIndex,
+        field_vals: &[ir::Value],
+    ) -> WasmResult<ir::Value> {
+        let interned_type_index =
+            func_env.module.types[struct_type_index].unwrap_module_type_index();
+        let struct_layout = func_env.struct_or_exn_layout(interned_type_index);
+
+        // Copy some stuff out of the struct layout to avoid borrowing issues.
+        let struct_size = struct_layout.size;
+        let struct_align = struct_layout.align;
+        assert_eq!(field_vals.len(), struct_layout.fields.len());
+
+        let struct_size_val = builder.ins().iconst(ir::types::I32, i64::from(struct_size));
+
+        let struct_ref = emit_gc_raw_alloc(
+            func_env,
+            builder,
+            VMGcKind::StructRef,
+            interned_type_index,
+            struct_size_val,
+            struct_align,
+        );
+
+        // Initialize each of the newly-allocated struct's fields.
+        //
+        // Note: we don't need to bounds-check the GC ref access here, since we
+        // trust the results of the allocation libcall.
+        let base = func_env.get_gc_heap_base(builder);
+        let extended_struct_ref =
+            uextend_i32_to_pointer_type(builder, func_env.pointer_type(), struct_ref);
+        let raw_ptr_to_struct = builder.ins().iadd(base, extended_struct_ref);
+        initialize_struct_fields(
+            func_env,
+            builder,
+            interned_type_index,
+            raw_ptr_to_struct,
+            field_vals,
+            |func_env, builder, ty, field_addr, val| {
+                write_field_at_addr(func_env, builder, ty, field_addr, val)
+            },
+        )?;
+
+        Ok(struct_ref)
+    }
+
+    fn alloc_exn(
+        &mut self,
+        func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder<'_>,
+        tag_index: TagIndex,
+        field_vals: &[ir::Value],
+        instance_id: ir::Value,
+        tag: ir::Value,
+    ) -> WasmResult<ir::Value> {
+        let interned_type_index = func_env.module.tags[tag_index]
+            .exception
+            .unwrap_module_type_index();
+        let exn_layout = func_env.struct_or_exn_layout(interned_type_index);
+
+        // Copy some stuff out of the exception layout to avoid borrowing issues.
+        let exn_size = exn_layout.size;
+        let exn_align = exn_layout.align;
+        assert_eq!(field_vals.len(), exn_layout.fields.len());
+
+        let exn_size_val = builder.ins().iconst(ir::types::I32, i64::from(exn_size));
+
+        let exn_ref = emit_gc_raw_alloc(
+            func_env,
+            builder,
+            VMGcKind::ExnRef,
+            interned_type_index,
+            exn_size_val,
+            exn_align,
+        );
+
+        // Initialize each of the newly-allocated exception object's fields.
+        //
+        // Note: we don't need to bounds-check the GC ref access here, since we
+        // trust the results of the allocation libcall.
+        let base = func_env.get_gc_heap_base(builder);
+        let extended_exn_ref =
+            uextend_i32_to_pointer_type(builder, func_env.pointer_type(), exn_ref);
+        let raw_ptr_to_exn = builder.ins().iadd(base, extended_exn_ref);
+        initialize_struct_fields(
+            func_env,
+            builder,
+            interned_type_index,
+            raw_ptr_to_exn,
+            field_vals,
+            |func_env, builder, ty, field_addr, val| {
+                write_field_at_addr(func_env, builder, ty, field_addr, val)
+            },
+        )?;
+
+        // Finally, initialize the tag fields.
+        let instance_id_addr = builder
+            .ins()
+            .iadd_imm(raw_ptr_to_exn, i64::from(EXCEPTION_TAG_INSTANCE_OFFSET));
+        write_field_at_addr(
+            func_env,
+            builder,
+            WasmStorageType::Val(WasmValType::I32),
+            instance_id_addr,
+            instance_id,
+        )?;
+        let tag_addr = builder
+            .ins()
+            .iadd_imm(raw_ptr_to_exn, i64::from(EXCEPTION_TAG_DEFINED_OFFSET));
+        write_field_at_addr(
+            func_env,
+            builder,
+            WasmStorageType::Val(WasmValType::I32),
+            tag_addr,
+            tag,
+        )?;
+
+        Ok(exn_ref)
+    }
+
+    fn translate_read_gc_reference(
+        &mut self,
+        _func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder,
+        ty: WasmRefType,
+        src: ir::Value,
+        flags: ir::MemFlags,
+    ) -> WasmResult<ir::Value> {
+        // No read barrier, but the loaded reference must be included in stack
+        // maps so that the collector can update it if its referent is moved.
+        unbarriered_load_gc_ref(builder, ty.heap_type, src, flags)
+    }
+
+    fn translate_write_gc_reference(
+        &mut self,
+        _func_env: &mut FuncEnvironment<'_>,
+        builder: &mut FunctionBuilder,
+        ty: WasmRefType,
+        dst: ir::Value,
+        new_val: ir::Value,
+        flags: ir::MemFlags,
+    ) -> WasmResult<()> {
+        unbarriered_store_gc_ref(builder, ty.heap_type, dst, new_val, flags)
+    }
+}
@@ -24,3 +24,5 @@ pub use self::heap::{Heap, HeapData};
 pub use self::stack::FuncTranslationStacks;
 pub use self::table::{TableData, TableSize};
 pub use self::translation_utils::*;
+
+pub(crate) use self::code_translator::bitcast_wasm_returns;
@@ -0,0 +1,933 @@
+//! A GDB remote serial protocol server on top of a [`Debugger`].
+//!
+//! This speaks the subset of the protocol, plus LLDB's WebAssembly
+//! extensions (`qWasmCallStack`, `qWasmLocal`, etc.), that LLDB needs
+//! to debug a Wasm guest: it can connect with `process connect
+//! connect://HOST:PORT` (or `gdb-remote HOST:PORT`), set breakpoints,
+//! continue, single-step, and inspect the call stack, locals,
+//! operand stack, globals and linear memory.
+//!
+//! Breakpoints by source line are resolved by LLDB itself: we serve
+//! the bytes of each module (including any DWARF custom sections) in
+//! the client's address space, and LLDB maps lines to Wasm PCs
+//! (module file offsets) and sends us ordinary address breakpoints.
+//!
+//! Addresses use LLDB's Wasm address encoding: the top two bits are
+//! the address space (`0` for linear memory, `1` for module bytes,
+//! a.k.a. "object" space), the next 30 bits are a module ID, and the
+//! low 32 bits are an offset within that space. Module IDs are
+//! indices into the server's module list.
+//!
+//! We only serve one thread (the store), with thread ID 1. Only the
+//! first memory of an instance is accessible. Asynchronous
+//! interrupts (`^C`) are not supported: the guest only stops at debug
+//! events.
+
+use crate::{DebugRunResult, Debugger};
+use std::fmt::Write as _;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufStream};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use wasmtime::{Instance, Module, Result, Val};
+
+/// The target triple we report to clients.
+const TRIPLE: &str = "wasm32-unknown-unknown-wasm";
+
+/// The reply for requests that are malformed or can't be satisfied.
+const ERROR: &str = "E01";
+
+/// The thread ID under which the store's execution is presented.
+const THREAD_ID: u32 = 1;
+
+/// Signal numbers used in stop replies.
+const SIGTRAP: u8 = 5;
+const SIGABRT: u8 = 6;
+const SIGSEGV: u8 = 11;
+
+/// A server for the GDB remote serial protocol, allowing LLDB to
+/// attach to the execution wrapped by a [`Debugger`].
+pub struct GdbServer<T: Send + 'static> {
+    debugger: Debugger<T>,
+    /// Modules known to the client; the index is the module ID.
+    modules: Vec<ServedModule>,
+    /// Stack frames at the current stop, innermost first.
+    frames: Vec<Frame>,
+    /// The reason execution is currently stopped.
+    stop: Stop,
+    /// Exit status to report once the inner body finishes.
+    exit_status: u8,
+}
+
+struct ServedModule {
+    name: String,
+    module: Module,
+    /// The original Wasm bytes, which LLDB reads to find DWARF.
+    /// Empty if unknown.
+    bytes: Arc<[u8]>,
+}
+
+struct Frame {
+    instance: Instance,
+    module: Option<Module>,
+    pc: Option<u32>,
+    locals: Vec<Vec<u8>>,
+    stack: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, Copy)]
+struct Stop {
+    signal: u8,
+    reason: Option<&'static str>,
+}
+
+enum Address {
+    Memory { module: u32, offset: u32 },
+    Object { module: u32, offset: u32 },
+}
+
+impl Address {
+    const MEMORY: u64 = 0;
+    const OBJECT: u64 = 1;
+
+    fn decode(addr: u64) -> Option<Address> {
+        let module = u32::try_from((addr >> 32) & 0x3fff_ffff).unwrap();
+        let offset = addr as u32;
+        match addr >> 62 {
+            Self::MEMORY => Some(Address::Memory { module, offset }),
+            Self::OBJECT => Some(Address::Object { module, offset }),
+            _ => None,
+        }
+    }
+
+    fn encode(&self) -> u64 {
+        let (space, module, offset) = match *self {
+            Address::Memory { module, offset } => (Self::MEMORY, module, offset),
+            Address::Object { module, offset } => (Self::OBJECT, module, offset),
+        };
+        (space << 62) | (u64::from(module) << 32) | u64::from(offset)
+    }
+}
+
+/// What to do after handling a packet.
+enum Action {
+    Reply(String),
+    /// A reply carrying binary data, which is escaped when sent.
+    ReplyBinary(Vec<u8>),
+    ReplyAndExit(String),
+}
+
+impl<T: Send + 'static> GdbServer<T> {
+    /// Create a new server for the given debugger, which must not
+    /// yet have been run.
+    pub fn new(debugger: Debugger<T>) -> GdbServer<T> {
+        GdbServer {
+            debugger,
+            modules: vec![],
+            frames: vec![],
+            stop: Stop {
+                signal: SIGTRAP,
+                reason: None,
+            },
+            exit_status: 0,
+        }
+    }
+
+    /// Make a module and its original Wasm bytes available to the
+    /// client.
+    ///
+    /// The client reads the bytes to find debug info, so source-level
+    /// debugging is only possible for modules added here. Modules
+    /// that are encountered on the stack without having been added
+    /// are still reported, but without contents.
+    pub fn add_module(&mut self, name: impl Into<String>, module: &Module, bytes: Arc<[u8]>) {
+        match self
+            .modules
+            .iter_mut()
+            .find(|m| Module::same(&m.module, module))
+        {
+            Some(m) => m.bytes = bytes,
+            None => self.modules.push(ServedModule {
+                name: name.into(),
+                module: module.clone(),
+                bytes,
+            }),
+        }
+    }
+
+    /// Wait for one client to connect on the given address, then
+    /// serve it as with [`GdbServer::serve`].
+    pub async fn listen(self, addr: impl ToSocketAddrs) -> Result<Debugger<T>> {
+        let listener = TcpListener::bind(addr).await?;
+        let (stream, peer) = listener.accept().await?;
+        log::info!("gdb: client connected from {peer}");
+        stream.set_nodelay(true)?;
+        self.serve(stream).await
+    }
+
+    /// Serve the protocol over `stream` until the client detaches or
+    /// kills the session, the connection is closed, or the inner body
+    /// finishes.
+    ///
+    /// Returns the debugger, which may not yet be complete; callers
+    /// will typically `finish()` it.
+    pub async fn serve<S>(mut self, stream: S) -> Result<Debugger<T>>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let mut conn = Connection {
+            stream: BufStream::new(stream),
+            no_ack: false,
+        };
+        while let Some(packet) = conn.read_packet().await? {
+            log::trace!("gdb: received {packet:?}");
+            match self.handle(&packet).await? {
+                Action::Reply(reply) => {
+                    conn.write_packet(reply.as_bytes()).await?;
+                    if packet == "QStartNoAckMode" {
+                        conn.no_ack = true;
+                    }
+                }
+                Action::ReplyBinary(reply) => {
+                    conn.write_packet(&binary_escape(&reply)).await?;
+                }
+                Action::ReplyAndExit(reply) => {
+                    conn.write_packet(reply.as_bytes()).await?;
+                    break;
+                }
+            }
+        }
+        Ok(self.debugger)
+    }
+
+    async fn handle(&mut self, packet: &str) -> Result<Action> {
+        let reply = |s: &str| Ok(Action::Reply(s.to_string()));
+
+        if packet.starts_with("qSupported") {
+            return reply("PacketSize=4000;QStartNoAckMode+;qXfer:libraries:read+");
+        }
+        if let Some(annex) = packet.strip_prefix("qXfer:libraries:read::") {
+            return Ok(match self.read_libraries(annex) {
+                Some(reply) => Action::ReplyBinary(reply),
+                None => Action::Reply(ERROR.to_string()),
+            });
+        }
+        if packet.starts_with("qWasmCallStack") {
+            let mut pcs = Vec::with_capacity(self.frames.len() * 8);
+            for i in 0..self.frames.len() {
+                pcs.extend_from_slice(&self.frame_pc(i).to_le_bytes());
+            }
+            return Ok(Action::Reply(hex(&pcs)));
+        }
+        if let Some(args) = packet.strip_prefix("qWasmLocal:") {
+            let value = parse_frame_and_index(args)
+                .and_then(|(frame, i)| self.frames.get(frame)?.locals.get(i).cloned());
+            return Ok(Action::Reply(value.map_or(ERROR.to_string(), |v| hex(&v))));
+        }
+        if let Some(args) = packet.strip_prefix("qWasmStackValue:") {
+            let value = parse_frame_and_index(args)
+                .and_then(|(frame, i)| self.frames.get(frame)?.stack.get(i).cloned());
+            return Ok(Action::Reply(value.map_or(ERROR.to_string(), |v| hex(&v))));
+        }
+        if let Some(args) = packet.strip_prefix("qWasmGlobal:") {
+            let value = match parse_frame_and_index(args) {
+                Some((frame, i)) => self.read_global(frame, i).await?,
+                None => None,
+            };
+            return Ok(Action::Reply(value.map_or(ERROR.to_string(), |v| hex(&v))));
+        }
+        if let Some(args) = packet.strip_prefix("qWasmMem:") {
+            let instance = parse_frame_addr_and_len(args)
+                .and_then(|(frame, addr, len)| Some((self.frames.get(frame)?.instance, addr, len)));
+            let value = match instance {
+                Some((instance, addr, len)) => self.read_memory(instance, addr, len).await?,
+                None => None,
+            };
+            return Ok(Action::Reply(value.map_or(ERROR.to_string(), |v| hex(&v))));
+        }
+        if let Some(args) = packet.strip_prefix('m') {
+            let value = match parse_addr_and_len(args) {
+                Some((addr, len)) => self.read_address(addr, len).await?,
+                None => None,
+            };
+            return Ok(Action::Reply(value.map_or(ERROR.to_string(), |v| hex(&v))));
+        }
+        if let Some(args) = packet.strip_prefix("Z0,") {
+            return Ok(Action::Reply(self.edit_breakpoint(args, true).await?));
+        }
+        if let Some(args) = packet.strip_prefix("z0,") {
+            return Ok(Action::Reply(self.edit_breakpoint(args, false).await?));
+        }
+        if packet.starts_with('H') {
+            return reply("OK");
+        }
+
+        match packet {
+            "QStartNoAckMode" => reply("OK"),
+            "qHostInfo" => reply(&format!(
+                "triple:{};ptrsize:4;endian:little;",
+                hex(TRIPLE.as_bytes())
+            )),
+            "qProcessInfo" => reply(&format!(
+                "pid:1;triple:{};ptrsize:4;endian:little;",
+                hex(TRIPLE.as_bytes())
+            )),
+            "qAttached" => reply("1"),
+            "qC" => reply(&format!("QC{THREAD_ID:x}")),
+            "qfThreadInfo" => reply(&format!("m{THREAD_ID:x}")),
+            "qsThreadInfo" => reply("l"),
+            "qRegisterInfo0" => reply(
+                "name:pc;alt-name:pc;bitsize:64;offset:0;encoding:uint;format:hex;\
+                 set:General Purpose Registers;gcc:16;dwarf:16;generic:pc;",
+            ),
+            "p0" | "g" => reply(&hex(&self.frame_pc(0).to_le_bytes())),
+            "?" => Ok(Action::Reply(self.stop_reply())),
+            "c" => self.resume(false).await,
+            "s" => self.resume(true).await,
+            "D" => Ok(Action::ReplyAndExit("OK".to_string())),
+            "k" => Ok(Action::ReplyAndExit("X09".to_string())),
+            // An empty reply tells the client that a packet isn't
+            // supported.
+            _ => reply(""),
+        }
+    }
+
+    /// Continue or single-step, returning the next stop reply.
+    async fn resume(&mut self, step: bool) -> Result<Action> {
+        if step {
+            self.debugger
+                .with_store(|store| store.edit_breakpoints().unwrap().single_step(true))
+                .await??;
+        }
+        let stop = loop {
+            match self.debugger.run().await? {
+                DebugRunResult::Finished => {
+                    let reply = format!("W{:02x}", self.exit_status);
+                    return Ok(Action::ReplyAndExit(reply));
+                }
+                DebugRunResult::Breakpoint => {
+                    break Stop {
+                        signal: SIGTRAP,
+                        reason: Some(if step { "trace" } else { "breakpoint" }),
+                    };
+                }
+                DebugRunResult::Watchpoint(event) => {
+                    log::debug!("gdb: stopped at watchpoint: {event:?}");
+                    break Stop {
+                        signal: SIGTRAP,
+                        reason: Some("watchpoint"),
+                    };
+                }
+                DebugRunResult::Trap(trap) => {
+                    log::debug!("gdb: stopped at trap: {trap}");
+                    self.exit_status = 1;
+                    break Stop {
+                        signal: SIGSEGV,
+                        reason: Some("exception"),
+                    };
+                }
+                DebugRunResult::UncaughtExceptionThrown(_) => {
+                    self.exit_status = 1;
+                    break Stop {
+                        signal: SIGABRT,
+                        reason: Some("exception"),
+                    };
+                }
+                DebugRunResult::HostcallError => {
+                    break Stop {
+                        signal: SIGABRT,
+                        reason: Some("exception"),
+                    };
+                }
+                // Neither of these is interesting to the client.
+                DebugRunResult::EpochYield | DebugRunResult::CaughtExceptionThrown(_) => {}
+            }
+        };
+        if step {
+            self.debugger
+                .with_store(|store| store.edit_breakpoints().unwrap().single_step(false))
+                .await??;
+        }
+        self.stop = stop;
+        self.refresh_frames().await?;
+        Ok(Action::Reply(self.stop_reply()))
+    }
+
+    fn stop_reply(&mut self) -> String {
+        let mut reply = format!(
+            "T{:02x}thread:{THREAD_ID:x};00:{};",
+            self.stop.signal,
+            hex(&self.frame_pc(0).to_le_bytes())
+        );
+        if let Some(reason) = self.stop.reason {
+            write!(reply, "reason:{reason};").unwrap();
+        }
+        reply
+    }
+
+    /// Capture the stack at the current stop.
+    ///
+    /// Locals and operand-stack values can't change while stopped, so
+    /// we read them all up front; memory and globals are read on
+    /// request.
+    async fn refresh_frames(&mut self) -> Result<()> {
+        self.frames = self
+            .debugger
+            .with_store(|store| {
+                let mut frames = vec![];
+                let Some(mut cursor) = store.debug_frames() else {
+                    return frames;
+                };
+                while !cursor.done() {
+                    frames.push(Frame {
+                        instance: cursor.instance(),
+                        module: cursor.module().cloned(),
+                        pc: cursor.wasm_function_index_and_pc().map(|(_, pc)| pc),
+                        locals: (0..cursor.num_locals())
+                            .map(|i| val_bytes(&cursor.local(i)))
+                            .collect(),
+                        stack: (0..cursor.num_stacks())
+                            .map(|i| val_bytes(&cursor.stack(i)))
+                            .collect(),
+                    });
+                    cursor.move_to_parent();
+                }
+                frames
+            })
+            .await?;
+        Ok(())
+    }
+
+    /// Get the ID of `module`, assigning one if the client hasn't
+    /// seen it yet.
+    fn module_id(&mut self, module: &Module) -> u32 {
+        let id = match self
+            .modules
+            .iter()
+            .position(|m| Module::same(&m.module, module))
+        {
+            Some(id) => id,
+            None => {
+                let id = self.modules.len();
+                self.modules.push(ServedModule {
+                    name: module
+                        .name()
+                        .map_or_else(|| format!("module{id}.wasm"), |n| n.to_string()),
+                    module: module.clone(),
+                    bytes: Arc::from([]),
+                });
+                id
+            }
+        };
+        u32::try_from(id).unwrap()
+    }
+
+    /// The PC of the given frame, as an object-space address.
+    fn frame_pc(&mut self, frame: usize) -> u64 {
+        let Some(Frame {
+            module: Some(module),
+            pc: Some(pc),
+            ..
+        }) = self.frames.get(frame)
+        else {
+            return 0;
+        };
+        let (module, offset) = (module.clone(), *pc);
+        let module = self.module_id(&module);
+        Address::Object { module, offset }.encode()
+    }
+
+    fn read_libraries(&mut self, annex: &str) -> Option<Vec<u8>> {
+        let (offset, len) = parse_addr_and_len(annex)?;
+        let mut xml = String::from("<library-list>");
+        for (id, m) in self.modules.iter().enumerate() {
+            let base = Address::Object {
+                module: u32::try_from(id).unwrap(),
+                offset: 0,
+            }
+            .encode();
+            write!(
+                xml,
+                "<library name=\"{}\"><section address=\"{base:#x}\"/></library>",
+                xml_escape(&m.name)
+            )
+            .unwrap();
+        }
+        xml.push_str("</library-list>");
+
+        // The offset and length are in bytes and chosen by the client, so
+        // chunks may split UTF-8 characters in module names.
+        let xml = xml.as_bytes();
+        let start = usize::try_from(offset).ok()?.min(xml.len());
+        let end = start.saturating_add(usize::try_from(len).ok()?);
+        let mut reply = Vec::new();
+        match xml.get(start..end) {
+            Some(chunk) => {
+                reply.push(b'm');
+                reply.extend_from_slice(chunk);
+            }
+            None => {
+                reply.push(b'l');
+                reply.extend_from_slice(&xml[start..]);
+            }
+        }
+        Some(reply)
+    }
+
+    async fn read_address(&mut self, addr: u64, len: u64) -> Result<Option<Vec<u8>>> {
+        let Ok(len) = u32::try_from(len) else {
+            return Ok(None);
+        };
+        match Address::decode(addr) {
+            Some(Address::Object { module, offset }) => {
+                let Some(m) = self.modules.get(usize::try_from(module).unwrap()) else {
+                    return Ok(None);
+                };
+                let start = usize::try_from(offset).unwrap();
+                let end = start.saturating_add(usize::try_from(len).unwrap());
+                Ok(m.bytes
+                    .get(start..end.min(m.bytes.len()))
+                    .map(|b| b.to_vec()))
+            }
+            Some(Address::Memory { module, offset }) => {
+                // Linear memory belongs to an instance rather than a
+                // module, so use the innermost instance of the module
+                // on the stack.
+                let mut instance = None;
+                for i in 0..self.frames.len() {
+                    let Some(m) = self.frames[i].module.clone() else {
+                        continue;
+                    };
+                    if self.module_id(&m) == module {
+                        instance = Some(self.frames[i].instance);
+                        break;
+                    }
+                }
+                match instance {
+                    Some(instance) => self.read_memory(instance, offset, len).await,
+                    None => Ok(None),
+                }
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn read_memory(
+        &mut self,
+        instance: Instance,
+        offset: u32,
+        len: u32,
+    ) -> Result<Option<Vec<u8>>> {
+        self.debugger
+            .with_store(move |mut store| {
+                let memory = instance.debug_memory(&mut store, 0)?;
+                let data = memory.data(&store);
+                let start = usize::try_from(offset).unwrap();
+                let end = start.saturating_add(usize::try_from(len).unwrap());
+                // Partial reads at the end of memory are allowed.
+                data.get(start..end.min(data.len())).map(|d| d.to_vec())
+            })
+            .await
+    }
+
+    async fn read_global(&mut self, frame: usize, index: usize) -> Result<Option<Vec<u8>>> {
+        let (Some(frame), Ok(index)) = (self.frames.get(frame), u32::try_from(index)) else {
+            return Ok(None);
+        };
+        let instance = frame.instance;
+        self.debugger
+            .with_store(move |mut store| {
+                let global = instance.debug_global(&mut store, index)?;
+                Some(val_bytes(&global.get(&mut store)))
+            })
+            .await
+    }
+
+    async fn edit_breakpoint(&mut self, args: &str, add: bool) -> Result<String> {
+        // Arguments are `ADDR,KIND`; the kind is meaningless for Wasm.
+        let addr = args.split(',').next().unwrap_or("");
+        let Ok(addr) = u64::from_str_radix(addr, 16) else {
+            return Ok(ERROR.to_string());
+        };
+        let Some(Address::Object { module, offset }) = Address::decode(addr) else {
+            return Ok(ERROR.to_string());
+        };
+        let Some(m) = self.modules.get(usize::try_from(module).unwrap()) else {
+            return Ok(ERROR.to_string());
+        };
+        let module = m.module.clone();
+        let result = self
+            .debugger
+            .with_store(move |store| {
+                let mut edit = store.edit_breakpoints().unwrap();
+                if add {
+                    edit.add_breakpoint(&module, offset)
+                } else {
+                    edit.remove_breakpoint(&module, offset)
+                }
+            })
+            .await?;
+        Ok(match result {
+            Ok(()) => "OK".to_string(),
+            Err(e) => {
+                log::debug!("gdb: failed to edit breakpoint at {addr:#x}: {e:?}");
+                ERROR.to_string()
+            }
+        })
+    }
+}
+
+/// Packet framing over a byte stream.
+struct Connection<S> {
+    stream: BufStream<S>,
+    /// Whether `QStartNoAckMode` has been negotiated.
+    no_ack: bool,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<S> {
+    /// Read the next packet, acknowledging it if necessary. Returns
+    /// `None` once the client closes the connection.
+    async fn read_packet(&mut self) -> Result<Option<String>> {
+        loop {
+            // Skip everything up to the start of a packet: acks,
+            // naks (we never need to retransmit, as our transport is
+            // reliable) and interrupts (which we don't support).
+            loop {
+                match self.read_byte().await? {
+                    Some(b'$') => break,
+                    Some(_) => {}
+                    None => return Ok(None),
+                }
+            }
+
+            let mut data = vec![];
+            loop {
+                match self.read_byte().await? {
+                    Some(b'#') => break,
+                    Some(b) => data.push(b),
+                    None => return Ok(None),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.stream.read_exact(&mut checksum).await?;
+
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                == Some(checksum_of(&data));
+            if !self.no_ack {
+                self.stream
+                    .write_all(if valid { b"+" } else { b"-" })
+                    .await?;
+                self.stream.flush().await?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+            log::debug!("gdb: dropping packet with bad checksum");
+        }
+    }
+
+    async fn read_byte(&mut self) -> Result<Option<u8>> {
+        match self.stream.read_u8().await {
+            Ok(b) => Ok(Some(b)),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn write_packet(&mut self, data: &[u8]) -> Result<()> {
+        log::trace!("gdb: replying {:?}", data.escape_ascii().to_string());
+        self.stream.write_all(b"$").await?;
+        self.stream.write_all(data).await?;
+        let checksum = format!("#{:02x}", checksum_of(data));
+        self.stream.write_all(checksum.as_bytes()).await?;
+        self.stream.flush().await?;
+        Ok(())
+    }
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+/// Escape binary data for a reply: `$`, `#` and `}` would otherwise be
+/// taken for packet framing or escapes, and `*` for run-length encoding.
+fn binary_escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &b in data {
+        if matches!(b, b'$' | b'#' | b'}' | b'*') {
+            escaped.extend_from_slice(&[b'}', b ^ 0x20]);
+        } else {
+            escaped.push(b);
+        }
+    }
+    escaped
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for b in bytes {
+        write!(s, "{b:02x}").unwrap();
+    }
+    s
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Parse the `FRAME;INDEX` arguments (both decimal) of LLDB's Wasm
+/// packets.
+fn parse_frame_and_index(args: &str) -> Option<(usize, usize)> {
+    let (frame, index) = args.split_once(';')?;
+    Some((frame.parse().ok()?, index.parse().ok()?))
+}
+
+/// Parse the `FRAME;ADDR;LEN` arguments (decimal, hex and hex) of
+/// `qWasmMem`.
+fn parse_frame_addr_and_len(args: &str) -> Option<(usize, u32, u32)> {
+    let mut parts = args.split(';');
+    let frame = parts.next()?.parse().ok()?;
+    let addr = u32::from_str_radix(parts.next()?, 16).ok()?;
+    let len = u32::from_str_radix(parts.next()?, 16).ok()?;
+    Some((frame, addr, len))
+}
+
+/// Parse `ADDR,LEN` arguments (both hex).
+fn parse_addr_and_len(args: &str) -> Option<(u64, u64)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((
+        u64::from_str_radix(addr, 16).ok()?,
+        u64::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+/// The little-endian bytes of a value, as LLDB expects them.
+fn val_bytes(val: &Val) -> Vec<u8> {
+    match val {
+        Val::I32(x) => x.to_le_bytes().to_vec(),
+        Val::I64(x) => x.to_le_bytes().to_vec(),
+        Val::F32(x) => x.to_le_bytes().to_vec(),
+        Val::F64(x) => x.to_le_bytes().to_vec(),
+        Val::V128(x) => x.as_u128().to_le_bytes().to_vec(),
+        // References have no address that would mean anything to
+        // the client, so they're shown as null pointers.
+        Val::FuncRef(_) | Val::ExternRef(_) | Val::AnyRef(_) | Val::ExnRef(_) | Val::ContRef(_) => {
+            0u32.to_le_bytes().to_vec()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tokio::io::DuplexStream;
+    use wasmtime::*;
+
+    /// A minimal client that sends one packet and waits for the reply.
+    struct Client(DuplexStream);
+
+    impl Client {
+        async fn request(&mut self, packet: &str) -> Result<String> {
+            Ok(String::from_utf8(self.request_bytes(packet).await?)?)
+        }
+
+        async fn request_bytes(&mut self, packet: &str) -> Result<Vec<u8>> {
+            let packet = format!("${packet}#{:02x}", checksum_of(packet.as_bytes()));
+            self.0.write_all(packet.as_bytes()).await?;
+            while self.0.read_u8().await? != b'$' {}
+            let mut data = vec![];
+            loop {
+                match self.0.read_u8().await? {
+                    b'#' => break,
+                    b => data.push(b),
+                }
+            }
+            let mut checksum = [0; 2];
+            self.0.read_exact(&mut checksum).await?;
+            assert_eq!(
+                u8::from_str_radix(std::str::from_utf8(&checksum)?, 16)?,
+                checksum_of(&data)
+            );
+            Ok(data)
+        }
+    }
+
+    fn engine() -> Result<Engine> {
+        let mut config = Config::new();
+        config.guest_debug(true);
+        config.async_support(true);
+        Engine::new(&config)
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn breakpoint_and_locals() -> Result<()> {
+        let _ = env_logger::try_init();
+
+        let engine = engine()?;
+        let wasm = wat::parse_str(
+            r#"
+                (module
+                  (func (export "main") (param i32 i32) (result i32)
+                    local.get 0
+                    local.get 1
+                    i32.add))
+            "#,
+        )?;
+        let module = Module::new(&engine, &wasm)?;
+
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new_async(&mut store, &module, &[]).await?;
+        let main = instance.get_func(&mut store, "main").unwrap();
+
+        let debugger = Debugger::new(store, move |mut store| async move {
+            let mut results = [Val::I32(0)];
+            main.call_async(&mut store, &[Val::I32(1), Val::I32(2)], &mut results[..])
+                .await?;
+            assert_eq!(results[0].unwrap_i32(), 3);
+            Ok(store)
+        });
+        let mut server = GdbServer::new(debugger);
+        server.add_module("main.wasm", &module, wasm.into());
+
+        let (client, server_io) = tokio::io::duplex(4096);
+        let server = tokio::spawn(server.serve(server_io));
+        let mut client = Client(client);
+
+        assert_eq!(client.request("QStartNoAckMode").await?, "OK");
+        assert_eq!(client.request("qfThreadInfo").await?, "m1");
+        assert!(
+            client
+                .request("qXfer:libraries:read::0,1000")
+                .await?
+                .contains("<library name=\"main.wasm\"><section address=\"0x4000000000000000\"/>")
+        );
+        assert_eq!(client.request("m4000000000000000,4").await?, "0061736d");
+
+        // Break at `i32.add`, at offset 40.
+        assert_eq!(client.request("Z0,4000000000000028,1").await?, "OK");
+        let stop = client.request("c").await?;
+        assert!(stop.starts_with("T05thread:1;"), "{stop}");
+        assert_eq!(
+            client.request("qWasmCallStack:1").await?,
+            "2800000000000040"
+        );
+        assert_eq!(client.request("qWasmLocal:0;0").await?, "01000000");
+        assert_eq!(client.request("qWasmLocal:0;1").await?, "02000000");
+        assert_eq!(client.request("qWasmStackValue:0;1").await?, "02000000");
+        assert_eq!(client.request("qWasmLocal:0;2").await?, ERROR);
+
+        assert_eq!(client.request("z0,4000000000000028,1").await?, "OK");
+        assert_eq!(client.request("c").await?, "W00");
+
+        let mut debugger = server.await??;
+        assert!(debugger.is_complete());
+        assert!(debugger.take_store().await?.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn step_and_read_state() -> Result<()> {
+        let _ = env_logger::try_init();
+
+        let engine = engine()?;
+        let wasm = wat::parse_str(
+            r#"
+                (module
+                  (memory 1)
+                  (data (i32.const 16) "hello")
+                  (global (mut i32) (i32.const 42))
+                  (func (export "main")
+                    global.get 0
+                    drop))
+            "#,
+        )?;
+        let module = Module::new(&engine, &wasm)?;
+
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new_async(&mut store, &module, &[]).await?;
+        let main = instance.get_func(&mut store, "main").unwrap();
+
+        let debugger = Debugger::new(store, move |mut store| async move {
+            main.call_async(&mut store, &[], &mut []).await?;
+            Ok(store)
+        });
+        let mut server = GdbServer::new(debugger);
+        server.add_module("main.wasm", &module, wasm.into());
+
+        let (client, server_io) = tokio::io::duplex(4096);
+        let server = tokio::spawn(server.serve(server_io));
+        let mut client = Client(client);
+
+        let stop = client.request("s").await?;
+        assert!(stop.starts_with("T05thread:1;"), "{stop}");
+        assert!(stop.ends_with("reason:trace;"), "{stop}");
+        assert_eq!(client.request("qWasmGlobal:0;0").await?, "2a000000");
+        assert_eq!(client.request("qWasmMem:0;10;5").await?, hex(b"hello"));
+        assert_eq!(client.request("m10,5").await?, hex(b"hello"));
+        assert_eq!(client.request("qWasmGlobal:0;1").await?, ERROR);
+
+        // Stepping doesn't stick.
+        assert_eq!(client.request("c").await?, "W00");
+        server.await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[cfg_attr(miri, ignore)]
+    async fn read_libraries_in_chunks() -> Result<()> {
+        let _ = env_logger::try_init();
+
+        let engine = engine()?;
+        let module = Module::new(&engine, "(module)")?;
+        let store = Store::new(&engine, ());
+        let debugger = Debugger::new(store, |store| async move { Ok(store) });
+        let mut server = GdbServer::new(debugger);
+        server.add_module("ünïcode$#}*.wasm", &module, Vec::new().into());
+
+        let (client, server_io) = tokio::io::duplex(4096);
+        let server = tokio::spawn(server.serve(server_io));
+        let mut client = Client(client);
+
+        // Read one byte at a time, so that chunks split the module name's
+        // multi-byte characters.
+        let mut xml = vec![];
+        loop {
+            let reply = client
+                .request_bytes(&format!("qXfer:libraries:read::{:x},1", xml.len()))
+                .await?;
+            let mut bytes = reply[1..].iter();
+            while let Some(&b) = bytes.next() {
+                assert!(!matches!(b, b'$' | b'#' | b'*'));
+                xml.push(if b == b'}' {
+                    bytes.next().unwrap() ^ 0x20
+                } else {
+                    b
+                });
+            }
+            if reply[0] == b'l' {
+                break;
+            }
+            assert_eq!(reply[0], b'm');
+        }
+        let xml = String::from_utf8(xml)?;
+        assert!(xml.contains("<library name=\"ünïcode$#}*.wasm\">"), "{xml}");
+
+        assert_eq!(client.request("D").await?, "OK");
+        server.await??;
+        Ok(())
+    }
+}
@@ -6,6 +6,9 @@
 //! provider of a stream of events, on which actions can be taken
 //! between each event.
 //!
+//! A [`GdbServer`] exposes a `Debugger` to external tools, such as
+//! LLDB, over the GDB remote serial protocol.
+//!
 //! In the future, this crate will also provide a WIT-level API and
 //! world in which to run debugger components.
 
@@ -16,9 +19,12 @@ use tokio::{
 };
 use wasmtime::{
     AsContextMut, DebugEvent, DebugHandler, ExnRef, OwnedRooted, Result, Store, StoreContextMut,
-    Trap,
+    Trap, WatchpointEvent,
 };
 
+mod gdb;
+pub use gdb::GdbServer;
+
 /// A `Debugger` wraps up state associated with debugging the code
 /// running in a single `Store`.
 ///
@@ -160,6 +166,7 @@ impl<T: Send + 'static> DebugHandler for Handler<T> {
             }
             DebugEvent::Trap(trap) => DebugRunResult::Trap(trap),
             DebugEvent::Breakpoint => DebugRunResult::Breakpoint,
+            DebugEvent::Watchpoint(event) => DebugRunResult::Watchpoint(event),
             DebugEvent::EpochYield => DebugRunResult::EpochYield,
         };
         self.0
@@ -210,15 +217,23 @@ impl<T: Send + 'static> Debugger<T> {
         let (out_tx, out_rx) = mpsc::channel(1);
 
         let inner = tokio::spawn(async move {
-            // Receive one "continue" command on the inbound channel
-            // before continuing.
-            match in_rx.recv().await {
-                Some(cmd) => {
-                    assert!(matches!(cmd, Command::Continue));
-                }
-                None => {
-                    // Premature exit due to closed channel. Just drop `inner`.
-                    anyhow::bail!("Debugger channel dropped");
+            // Serve queries until we receive one "continue" command
+            // on the inbound channel, so that e.g. breakpoints can be
+            // set before the inner body starts.
+            loop {
+                match in_rx.recv().await {
+                    Some(Command::Continue) => break,
+                    Some(Command::Query(closure)) => {
+                        let result = closure(store.as_context_mut());
+                        out_tx
+                            .send(Response::QueryResponse(result))
+                            .await
+                            .expect("outbound channel closed prematurely");
+                    }
+                    None => {
+                        // Premature exit due to closed channel. Just drop `inner`.
+                        anyhow::bail!("Debugger channel dropped");
+                    }
                 }
             }
 
@@ -454,6 +469,8 @@ pub enum DebugRunResult {
     Trap(Trap),
     /// A breakpoint was reached.
     Breakpoint,
+    /// A watched range of linear memory is about to be accessed.
+    Watchpoint(WatchpointEvent),
 }
 
 #[cfg(test)]
@@ -42,6 +42,9 @@ macro_rules! foreach_builtin_function {
             // Invoked when we reach a new epoch.
             #[cfg(target_has_atomic = "64")]
             new_epoch(vmctx: vmctx) -> u64;
+            // Invoked when a function compiled for tiered compilation has been
+            // called `Tunables::tier_up_threshold` times.
+            tier_up(vmctx: vmctx, func: u32);
             // Invoked before malloc returns.
             #[cfg(feature = "wmemcheck")]
             check_malloc(vmctx: vmctx, addr: u32, len: u32) -> bool;
@@ -82,7 +85,7 @@ macro_rules! foreach_builtin_function {
 
             // Allocate a new, uninitialized GC object and return a reference to
             // it.
-            #[cfg(feature = "gc-drc")]
+            #[cfg(any(feature = "gc-drc", feature = "gc-copying"))]
             gc_alloc_raw(
                 vmctx: vmctx,
                 kind: u32,
@@ -9,8 +9,8 @@
 //! those values off of the state in the stack frame.
 
 use crate::{
-    FrameInstPos, FrameStackShape, FrameStateSlotOffset, FrameTableDescriptorIndex, FrameValType,
-    FuncKey, WasmHeapTopType, WasmValType, prelude::*,
+    FrameInstPos, FrameMemoryAccess, FrameStackShape, FrameStateSlot, FrameStateSlotOffset,
+    FrameTableDescriptorIndex, FrameValType, FuncKey, WasmHeapTopType, WasmValType, prelude::*,
 };
 use object::{LittleEndian, U32Bytes};
 use std::collections::{HashMap, hash_map::Entry};
@@ -23,6 +23,9 @@ pub struct FrameStateSlotBuilder {
     /// Pointer size for target.
     pointer_size: u32,
 
+    /// Result types and offsets.
+    results: Vec<(FrameValType, FrameStateSlotOffset)>,
+
     /// Local types and offsets.
     locals: Vec<(FrameValType, FrameStateSlotOffset)>,
 
@@ -33,14 +36,21 @@ pub struct FrameStateSlotBuilder {
     stacks_dedup:
         HashMap<(Option<FrameStackShape>, FrameValType, FrameStateSlotOffset), FrameStackShape>,
 
-    /// Size of vmctx (one pointer).
-    vmctx_size: u32,
+    /// Size of vmctx (one pointer) and the control word.
+    header_size: u32,
+
+    /// Size of all results.
+    results_size: u32,
 
     /// Size of all locals.
     locals_size: u32,
 
     /// Maximum size of whole state slot.
     slot_size: u32,
+    /// Linear-memory accesses in this function, by Wasm PC. These
+    /// are not part of the descriptor; they are collected here to be
+    /// added to the frame table.
+    memory_accesses: Vec<(u32, FrameMemoryAccess)>,
 }
 
 impl From<WasmValType> for FrameValType {
@@ -68,24 +78,42 @@ impl FrameStateSlotBuilder {
         FrameStateSlotBuilder {
             func_key,
             pointer_size,
+            results: vec![],
             locals: vec![],
             stacks: vec![],
             stacks_dedup: HashMap::new(),
-            vmctx_size: pointer_size,
+            header_size: pointer_size + FrameStateSlot::CONTROL_SIZE,
+            results_size: 0,
             locals_size: 0,
-            slot_size: pointer_size,
+            slot_size: pointer_size + FrameStateSlot::CONTROL_SIZE,
+            memory_accesses: vec![],
         }
     }
 
+    /// Add storage for a function result to the state-slot.
+    ///
+    /// Results must be added in order, and must be added before any
+    /// locals or stack shapes are defined. The offset in the state
+    /// slot is returned.
+    pub fn add_result(&mut self, ty: FrameValType) -> FrameStateSlotOffset {
+        debug_assert!(self.locals.is_empty() && self.stacks.is_empty());
+        // N.B.: the vmctx pointer and control word are always first,
+        // so we add their size here.
+        let offset = FrameStateSlotOffset(self.header_size + self.results_size);
+        let size = ty.storage_size(self.pointer_size);
+        self.results_size += size;
+        self.slot_size += size;
+        self.results.push((ty, offset));
+        offset
+    }
+
     /// Add a local to the state-slot.
     ///
     /// Locals must be added in local index order, and must be added
     /// before any stack shapes are defined. The offset in the state
     /// slot is returned.
     pub fn add_local(&mut self, ty: FrameValType) -> FrameStateSlotOffset {
-        // N.B.: the vmctx pointer is always at offset 0, so we add
-        // its size here.
-        let offset = FrameStateSlotOffset(self.vmctx_size + self.locals_size);
+        let offset = FrameStateSlotOffset(self.header_size + self.results_size + self.locals_size);
         let size = ty.storage_size(self.pointer_size);
         self.locals_size += size;
         self.slot_size += size;
@@ -99,6 +127,21 @@ impl FrameStateSlotBuilder {
         self.locals[index].1
     }
 
+    /// Get the types and offsets of all locals, in local index order.
+    pub fn locals(&self) -> &[(FrameValType, FrameStateSlotOffset)] {
+        &self.locals
+    }
+
+    /// Get the types and offsets of all results, in order.
+    pub fn results(&self) -> &[(FrameValType, FrameStateSlotOffset)] {
+        &self.results
+    }
+
+    /// Get the offset of the control word in the state-slot.
+    pub fn control_offset(&self) -> FrameStateSlotOffset {
+        FrameStateSlot::control_offset(self.pointer_size)
+    }
+
     /// Push a stack entry. Returns the stack-shape descriptor and the
     /// offset at which to write the pushed value.
     pub fn push_stack(
@@ -111,10 +154,12 @@ impl FrameStateSlotBuilder {
                 let (_, ty, offset) = self.stacks[parent.index()];
                 offset.add(ty.storage_size(self.pointer_size))
             })
-            // N.B.: the stack starts at vmctx_size + locals_size,
-            // because the layout puts vmctx first, then locals, then
-            // stack.
-            .unwrap_or(FrameStateSlotOffset(self.vmctx_size + self.locals_size));
+            // N.B.: the stack starts after the header, results and
+            // locals, because the layout puts vmctx and the control
+            // word first, then results, then locals, then stack.
+            .unwrap_or(FrameStateSlotOffset(
+                self.header_size + self.results_size + self.locals_size,
+            ));
 
         self.slot_size = core::cmp::max(
             self.slot_size,
@@ -138,6 +183,22 @@ impl FrameStateSlotBuilder {
         self.stacks[shape.index()].2
     }
 
+    /// Get the type of the top slot in a given stack shape.
+    pub fn stack_last_type(&self, shape: FrameStackShape) -> FrameValType {
+        self.stacks[shape.index()].1
+    }
+
+    /// Record that the instruction at `wasm_pc` accesses linear
+    /// memory.
+    pub fn add_memory_access(&mut self, wasm_pc: u32, access: FrameMemoryAccess) {
+        self.memory_accesses.push((wasm_pc, access));
+    }
+
+    /// Get the linear-memory accesses recorded in this function.
+    pub fn memory_accesses(&self) -> &[(u32, FrameMemoryAccess)] {
+        &self.memory_accesses
+    }
+
     /// Serialize the frame-slot descriptor so it can be included as
     /// metadata.
     pub fn serialize(&self) -> Vec<u8> {
@@ -145,16 +206,21 @@ impl FrameStateSlotBuilder {
         // - func_key: (u32, u32)
         // - num_locals: u32
         // - num_stack_shapes: u32
+        // - num_results: u32
         // - local_offsets: num_locals times:
         //   - offset: u32 (offset from start of state slot)
         // - stack_shape_parents: num_stack_shapes times:
         //   - parent_shape: u32 (or u32::MAX for none)
         // - stack_shape_offsets: num_stack_shapes times:
         //   - offset: u32 (offset from start of state slot for top-of-stack value)
+        // - result_offsets: num_results times:
+        //   - offset: u32 (offset from start of state slot)
         // - local_types: num_locals times:
         //   - type: u8
         // - stack_shape_types: num_stack_shapes times:
         //   - type: u8 (type of top-of-stack value)
+        // - result_types: num_results times:
+        //   - type: u8
 
         let mut buffer = vec![];
         let (func_key_namespace, func_key_index) = self.func_key.into_parts();
@@ -163,6 +229,9 @@ impl FrameStateSlotBuilder {
 
         buffer.extend_from_slice(&u32::to_le_bytes(u32::try_from(self.locals.len()).unwrap()));
         buffer.extend_from_slice(&u32::to_le_bytes(u32::try_from(self.stacks.len()).unwrap()));
+        buffer.extend_from_slice(&u32::to_le_bytes(
+            u32::try_from(self.results.len()).unwrap(),
+        ));
 
         for (_, offset) in &self.locals {
             buffer.extend_from_slice(&u32::to_le_bytes(offset.0));
@@ -174,12 +243,18 @@ impl FrameStateSlotBuilder {
         for (_, _, offset) in &self.stacks {
             buffer.extend_from_slice(&u32::to_le_bytes(offset.0));
         }
+        for (_, offset) in &self.results {
+            buffer.extend_from_slice(&u32::to_le_bytes(offset.0));
+        }
         for (ty, _) in &self.locals {
             buffer.push(*ty as u8);
         }
         for (_, ty, _) in &self.stacks {
             buffer.push(*ty as u8);
         }
+        for (ty, _) in &self.results {
+            buffer.push(*ty as u8);
+        }
 
         buffer
     }
@@ -197,6 +272,7 @@ impl FrameStateSlotBuilder {
 /// - `num_slot_descriptors`: u32
 /// - `num_progpoints`: u32
 /// - `num_breakpoints`: u32
+/// - `num_memory_accesses`: u32
 /// - `frame_descriptor_pool_length`: u32
 /// - `progpoint_descriptor_pool_length`: u32
 /// - `breakpoint_patch_pool_length`: u32
@@ -218,6 +294,12 @@ impl FrameStateSlotBuilder {
 ///    - end of breakpoint patch data in pool: u32
 ///      (find the start by end of previous; patches are in the
 ///      pool in order and this saves storing redundant start/end values)
+/// - `num_memory_accesses` times:
+///    - Wasm PC: u32 (sorted order; unique)
+/// - `num_memory_accesses` times:
+///    - memory index: u32
+///    - static offset: u32 (low half), u32 (high half)
+///    - size in bytes: u32, with bit 8 set for stores
 /// - frame descriptors (format described above; `frame_descriptor_pool_length` bytes)
 /// - progpoint descriptors (`progpoint_descriptor_pool_length` bytes)
 ///   - each descriptor: sequence of frames
@@ -245,6 +327,9 @@ pub struct FrameTableBuilder {
     breakpoint_patch_data_ends: Vec<U32Bytes<LittleEndian>>,
 
     breakpoint_patch_data: Vec<u8>,
+
+    memory_access_pcs: Vec<U32Bytes<LittleEndian>>,
+    memory_access_data: Vec<U32Bytes<LittleEndian>>,
 }
 
 impl FrameTableBuilder {
@@ -325,6 +410,26 @@ impl FrameTableBuilder {
             .push(U32Bytes::new(LittleEndian, end));
     }
 
+    /// Add one linear-memory access.
+    ///
+    /// Accesses must be added in increasing Wasm PC order, with at
+    /// most one access per PC.
+    pub fn add_memory_access(&mut self, wasm_pc: u32, access: &FrameMemoryAccess) {
+        debug_assert!(
+            self.memory_access_pcs
+                .last()
+                .is_none_or(|last| last.get(LittleEndian) < wasm_pc)
+        );
+        self.memory_access_pcs
+            .push(U32Bytes::new(LittleEndian, wasm_pc));
+        self.memory_access_data.extend(
+            access
+                .encode()
+                .into_iter()
+                .map(|word| U32Bytes::new(LittleEndian, word)),
+        );
+    }
+
     /// Serialize the framd-table data section, taking a closure to
     /// consume slices.
     pub fn serialize<F: FnMut(&[u8])>(&mut self, mut f: F) {
@@ -340,6 +445,8 @@ impl FrameTableBuilder {
         f(&num_prog_points.to_le_bytes());
         let num_breakpoints = u32::try_from(self.breakpoint_pcs.len()).unwrap();
         f(&num_breakpoints.to_le_bytes());
+        let num_memory_accesses = u32::try_from(self.memory_access_pcs.len()).unwrap();
+        f(&num_memory_accesses.to_le_bytes());
 
         let frame_descriptor_pool_length = u32::try_from(self.frame_descriptor_data.len()).unwrap();
         f(&frame_descriptor_pool_length.to_le_bytes());
@@ -356,6 +463,8 @@ impl FrameTableBuilder {
         f(object::bytes_of_slice(&self.breakpoint_pcs));
         f(object::bytes_of_slice(&self.breakpoint_patch_offsets));
         f(object::bytes_of_slice(&self.breakpoint_patch_data_ends));
+        f(object::bytes_of_slice(&self.memory_access_pcs));
+        f(object::bytes_of_slice(&self.memory_access_data));
         f(&self.frame_descriptor_data);
         f(object::bytes_of_slice(&self.progpoint_descriptor_data));
         f(&self.breakpoint_patch_data);
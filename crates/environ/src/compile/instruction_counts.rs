@@ -0,0 +1,37 @@
+//! Builder for the `ELF_WASMTIME_INSTRUCTION_COUNTS` section in
+//! compiled executables.
+//!
+//! This section is present only if instruction counting is enabled. It
+//! describes, for each function, which Wasm PC each of the function's
+//! instruction counters corresponds to, so that the runtime can
+//! allocate counters and report their values.
+
+use crate::{FuncKey, prelude::*};
+
+/// Builder for the instruction-counter section.
+#[derive(Default)]
+pub struct InstructionCountsBuilder {
+    data: Vec<u8>,
+}
+
+impl InstructionCountsBuilder {
+    /// Add a function and the Wasm PCs of its counters, in counter-index
+    /// order.
+    pub fn add_function(&mut self, key: FuncKey, counter_pcs: &[u32]) {
+        // Format (all little-endian):
+        // - func_key: (u32, u32)
+        // - num_counters: u32
+        // - counter_pcs: num_counters times:
+        //   - pc: u32
+        let (namespace, index) = key.into_raw_parts();
+        let num_counters = u32::try_from(counter_pcs.len()).unwrap();
+        for word in [namespace, index, num_counters].iter().chain(counter_pcs) {
+            self.data.extend_from_slice(&word.to_le_bytes());
+        }
+    }
+
+    /// Serialize the section, passing its bytes to `f`.
+    pub fn serialize<F: FnMut(&[u8])>(&self, mut f: F) {
+        f(&self.data);
+    }
+}
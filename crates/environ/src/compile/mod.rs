@@ -18,6 +18,7 @@ use std::sync::Arc;
 
 mod address_map;
 mod frame_table;
+mod instruction_counts;
 mod module_artifacts;
 mod module_environ;
 mod module_types;
@@ -26,6 +27,7 @@ mod trap_encoding;
 
 pub use self::address_map::*;
 pub use self::frame_table::*;
+pub use self::instruction_counts::*;
 pub use self::module_artifacts::*;
 pub use self::module_environ::*;
 pub use self::module_types::*;
@@ -243,6 +245,16 @@ pub trait Compiler: Send + Sync {
     /// `InliningCompiler::finish_compiling`.
     fn inlining_compiler(&self) -> Option<&dyn InliningCompiler>;
 
+    /// Returns the compiler that hot functions are recompiled with when tiered
+    /// compilation is enabled, if this compiler supports it.
+    ///
+    /// Functions compiled by the returned compiler must be callable in place
+    /// of functions compiled by this one. The returned compiler never performs
+    /// inlining.
+    fn tier_up_compiler(&self) -> Option<&dyn Compiler> {
+        None
+    }
+
     /// Compiles the function `index` within `translation`.
     ///
     /// The body of the function is available in `data` and configuration
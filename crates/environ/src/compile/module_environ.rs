@@ -387,7 +387,12 @@ impl<'a, 'data> ModuleEnvironment<'a, 'data> {
                     let sigindex = entry?;
                     let ty = TypeIndex::from_u32(sigindex);
                     let interned_index = self.result.module.types[ty];
-                    self.result.module.push_function(interned_index);
+                    let func_index = self.result.module.push_function(interned_index);
+                    // With tiered compilation all calls are made through a
+                    // `VMFuncRef`, so every function needs one.
+                    if self.tunables.tiered_compilation {
+                        self.flag_func_escaped(func_index);
+                    }
                 }
             }
 
@@ -4,7 +4,7 @@
 //! section in a compiled artifact as produced by
 //! [`crate::compile::frame_table::FrameTableBuilder`].
 
-use crate::FuncKey;
+use crate::{FuncKey, MemoryIndex};
 use alloc::vec::Vec;
 use object::{Bytes, LittleEndian, U32Bytes};
 
@@ -58,6 +58,9 @@ pub struct FrameTable<'a> {
     breakpoint_patch_data_ends: &'a [U32Bytes<LittleEndian>],
     breakpoint_patch_data: &'a [u8],
 
+    memory_access_pcs: &'a [U32Bytes<LittleEndian>],
+    memory_access_data: &'a [U32Bytes<LittleEndian>],
+
     original_text: &'a [u8],
 }
 
@@ -79,6 +82,10 @@ impl<'a> FrameTable<'a> {
             .read::<U32Bytes<LittleEndian>>()
             .map_err(|_| anyhow::anyhow!("Unable to read breakpoint count prefix"))?;
         let num_breakpoints = usize::try_from(num_breakpoints.get(LittleEndian))?;
+        let num_memory_accesses = data
+            .read::<U32Bytes<LittleEndian>>()
+            .map_err(|_| anyhow::anyhow!("Unable to read memory access count prefix"))?;
+        let num_memory_accesses = usize::try_from(num_memory_accesses.get(LittleEndian))?;
 
         let frame_descriptor_pool_length = data
             .read::<U32Bytes<LittleEndian>>()
@@ -118,6 +125,14 @@ impl<'a> FrameTable<'a> {
         let (breakpoint_patch_data_ends, data) =
             object::slice_from_bytes::<U32Bytes<LittleEndian>>(data, num_breakpoints)
                 .map_err(|_| anyhow::anyhow!("Unable to read breakpoint patch data ends slice"))?;
+        let (memory_access_pcs, data) =
+            object::slice_from_bytes::<U32Bytes<LittleEndian>>(data, num_memory_accesses)
+                .map_err(|_| anyhow::anyhow!("Unable to read memory access PC slice"))?;
+        let (memory_access_data, data) = object::slice_from_bytes::<U32Bytes<LittleEndian>>(
+            data,
+            MEMORY_ACCESS_WORDS * num_memory_accesses,
+        )
+        .map_err(|_| anyhow::anyhow!("Unable to read memory access data slice"))?;
 
         let (frame_descriptor_data, data) = data
             .split_at_checked(frame_descriptor_pool_length)
@@ -144,6 +159,8 @@ impl<'a> FrameTable<'a> {
             breakpoint_patch_offsets,
             breakpoint_patch_data_ends,
             breakpoint_patch_data,
+            memory_access_pcs,
+            memory_access_data,
             original_text,
         })
     }
@@ -309,6 +326,92 @@ impl<'a> FrameTable<'a> {
             (wasm_pc, data)
         })
     }
+
+    fn memory_access(&self, i: usize) -> FrameMemoryAccess {
+        let data = &self.memory_access_data[MEMORY_ACCESS_WORDS * i..][..MEMORY_ACCESS_WORDS];
+        let offset_lo = u64::from(data[1].get(LittleEndian));
+        let offset_hi = u64::from(data[2].get(LittleEndian));
+        let size_and_kind = data[3].get(LittleEndian);
+        FrameMemoryAccess {
+            memory: MemoryIndex::from_u32(data[0].get(LittleEndian)),
+            offset: offset_lo | (offset_hi << 32),
+            size: size_and_kind as u8,
+            kind: if size_and_kind & 0x100 != 0 {
+                FrameMemoryAccessKind::Store
+            } else {
+                FrameMemoryAccessKind::Load
+            },
+        }
+    }
+
+    /// Find the linear-memory access, if any, performed by the
+    /// instruction at the given Wasm PC.
+    pub fn lookup_memory_access(&self, pc: u32) -> Option<FrameMemoryAccess> {
+        let i = self
+            .memory_access_pcs
+            .binary_search_by_key(&pc, |p| p.get(LittleEndian))
+            .ok()?;
+        Some(self.memory_access(i))
+    }
+
+    /// Return an iterator over all linear-memory accesses, sorted by
+    /// Wasm PC.
+    pub fn memory_accesses(&self) -> impl Iterator<Item = (u32, FrameMemoryAccess)> + '_ {
+        self.memory_access_pcs
+            .iter()
+            .enumerate()
+            .map(|(i, pc)| (pc.get(LittleEndian), self.memory_access(i)))
+    }
+}
+
+/// The number of `u32` words in the encoding of one
+/// `FrameMemoryAccess`: memory index, offset (low and high halves),
+/// and size-and-kind.
+pub(crate) const MEMORY_ACCESS_WORDS: usize = 4;
+
+/// A linear-memory access performed by one Wasm instruction.
+///
+/// These are recorded in the frame table so that debuggers can
+/// implement watchpoints: when execution is paused at an instruction
+/// that accesses memory, its operands are on the operand stack (the
+/// address is on top for loads; the stored value is on top, with the
+/// address below it, for stores) and the effective address is the
+/// address operand plus `offset`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FrameMemoryAccess {
+    /// The accessed memory, in the module's memory index space.
+    pub memory: MemoryIndex,
+    /// The static offset added to the address operand.
+    pub offset: u64,
+    /// The number of bytes accessed.
+    pub size: u8,
+    /// Whether this access reads or writes memory.
+    pub kind: FrameMemoryAccessKind,
+}
+
+impl FrameMemoryAccess {
+    #[cfg(feature = "compile")]
+    pub(crate) fn encode(&self) -> [u32; MEMORY_ACCESS_WORDS] {
+        let kind = match self.kind {
+            FrameMemoryAccessKind::Load => 0,
+            FrameMemoryAccessKind::Store => 0x100,
+        };
+        [
+            self.memory.as_u32(),
+            self.offset as u32,
+            (self.offset >> 32) as u32,
+            u32::from(self.size) | kind,
+        ]
+    }
+}
+
+/// The kind of a [`FrameMemoryAccess`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FrameMemoryAccessKind {
+    /// The instruction reads memory.
+    Load,
+    /// The instruction writes memory.
+    Store,
 }
 
 /// Data describing how to patch code to enable or disable one
@@ -406,6 +509,23 @@ impl FrameStateSlotOffset {
     }
 }
 
+/// A request from the debugger to a paused frame, written to the
+/// control word of its state slot.
+///
+/// Generated code checks the control word after every breakpoint
+/// and clears it before acting on it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum FrameControl {
+    /// Continue as normal.
+    None = 0,
+    /// Locals or operand-stack values in the slot were modified and
+    /// must be reloaded before continuing.
+    Reload = 1,
+    /// Return from the frame with the results stored in the slot.
+    Return = 2,
+}
+
 /// A type stored in a frame.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[allow(missing_docs, reason = "self-describing variants")]
@@ -483,11 +603,23 @@ pub struct FrameStateSlot<'a> {
     local_offsets: &'a [U32Bytes<LittleEndian>],
     stack_shape_parents: &'a [U32Bytes<LittleEndian>],
     stack_shape_offsets: &'a [U32Bytes<LittleEndian>],
+    result_offsets: &'a [U32Bytes<LittleEndian>],
     local_types: &'a [u8],
     stack_shape_types: &'a [u8],
+    result_types: &'a [u8],
 }
 
 impl<'a> FrameStateSlot<'a> {
+    /// Size of the control word, a `u32` holding a [`FrameControl`]
+    /// value.
+    pub const CONTROL_SIZE: u32 = 4;
+
+    /// Get the offset of the control word in every state slot: it
+    /// follows the vmctx pointer at offset 0.
+    pub fn control_offset(pointer_size: u32) -> FrameStateSlotOffset {
+        FrameStateSlotOffset(pointer_size)
+    }
+
     /// Parse a slot descriptor.
     ///
     /// This parses the descriptor bytes as provided by
@@ -514,6 +646,11 @@ impl<'a> FrameStateSlot<'a> {
             .map_err(|_| anyhow::anyhow!("Unable to read num_stack_shapes"))?
             .get(LittleEndian);
         let num_stack_shapes = usize::try_from(num_stack_shapes)?;
+        let num_results = data
+            .read::<U32Bytes<LittleEndian>>()
+            .map_err(|_| anyhow::anyhow!("Unable to read num_results"))?
+            .get(LittleEndian);
+        let num_results = usize::try_from(num_results)?;
 
         let (local_offsets, data) =
             object::slice_from_bytes::<U32Bytes<LittleEndian>>(data.0, num_locals)
@@ -524,20 +661,28 @@ impl<'a> FrameStateSlot<'a> {
         let (stack_shape_offsets, data) =
             object::slice_from_bytes::<U32Bytes<LittleEndian>>(data, num_stack_shapes)
                 .map_err(|_| anyhow::anyhow!("Unable to read stack_shape_offsets slice"))?;
+        let (result_offsets, data) =
+            object::slice_from_bytes::<U32Bytes<LittleEndian>>(data, num_results)
+                .map_err(|_| anyhow::anyhow!("Unable to read result_offsets slice"))?;
         let (local_types, data) = data
             .split_at_checked(num_locals)
             .ok_or_else(|| anyhow::anyhow!("Unable to read local_types slice"))?;
-        let (stack_shape_types, _) = data
+        let (stack_shape_types, data) = data
             .split_at_checked(num_stack_shapes)
             .ok_or_else(|| anyhow::anyhow!("Unable to read stack_shape_types slice"))?;
+        let (result_types, _) = data
+            .split_at_checked(num_results)
+            .ok_or_else(|| anyhow::anyhow!("Unable to read result_types slice"))?;
 
         Ok(FrameStateSlot {
             func_key,
             local_offsets,
             stack_shape_parents,
             stack_shape_offsets,
+            result_offsets,
             local_types,
             stack_shape_types,
+            result_types,
         })
     }
 
@@ -564,6 +709,20 @@ impl<'a> FrameStateSlot<'a> {
         self.local_offsets.len()
     }
 
+    /// Get the offsets and types of the storage for the function's
+    /// results, used to return early from the frame.
+    pub fn results(&self) -> impl Iterator<Item = (FrameStateSlotOffset, FrameValType)> + '_ {
+        self.result_offsets
+            .iter()
+            .zip(self.result_types)
+            .map(|(offset, ty)| {
+                (
+                    FrameStateSlotOffset(offset.get(LittleEndian)),
+                    FrameValType::try_from(*ty).expect("Invalid type"),
+                )
+            })
+    }
+
     /// Get the offsets and types for operand stack values, from top
     /// of stack (most recently pushed) down.
     pub fn stack(
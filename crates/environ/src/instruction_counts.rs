@@ -0,0 +1,60 @@
+//! Instruction-counter table parser.
+//!
+//! This module contains utilities to interpret the
+//! `.wasmtime.instrcounts` section in a compiled artifact as produced
+//! by [`crate::compile::InstructionCountsBuilder`].
+
+use crate::FuncKey;
+use object::{Bytes, LittleEndian, U32Bytes};
+
+/// A parser for an instruction-counter section.
+///
+/// For every function compiled with instruction counting enabled, the
+/// section lists the Wasm PC at which each of the function's counters
+/// begins. Compiled code adds to the function's `i`th counter the number
+/// of Wasm instructions executed in the straight-line run of code which
+/// starts at the `i`th PC.
+pub struct InstructionCountTable<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> InstructionCountTable<'a> {
+    /// Parse an instruction-counter section.
+    pub fn parse(data: &'a [u8]) -> anyhow::Result<InstructionCountTable<'a>> {
+        let mut rest = Bytes(data);
+        while !rest.is_empty() {
+            Self::read_function(&mut rest)?;
+        }
+        Ok(InstructionCountTable { data })
+    }
+
+    fn read_function(
+        data: &mut Bytes<'a>,
+    ) -> anyhow::Result<(FuncKey, &'a [U32Bytes<LittleEndian>])> {
+        let mut read_u32 = |what: &str| {
+            data.read::<U32Bytes<LittleEndian>>()
+                .map(|v| v.get(LittleEndian))
+                .map_err(|_| anyhow::anyhow!("Unable to read {what}"))
+        };
+        let namespace = read_u32("func key namespace")?;
+        let index = read_u32("func key index")?;
+        let num_counters = usize::try_from(read_u32("counter count")?)?;
+        let pcs = data
+            .read_slice::<U32Bytes<LittleEndian>>(num_counters)
+            .map_err(|_| anyhow::anyhow!("Unable to read counter PCs"))?;
+        Ok((FuncKey::from_raw_parts(namespace, index), pcs))
+    }
+
+    /// Iterate over all functions in this table, along with the Wasm PC
+    /// of each of their counters.
+    pub fn functions(&self) -> impl Iterator<Item = (FuncKey, &'a [U32Bytes<LittleEndian>])> + 'a {
+        let mut rest = Bytes(self.data);
+        core::iter::from_fn(move || {
+            if rest.is_empty() {
+                return None;
+            }
+            // The data was validated in `parse`.
+            Some(Self::read_function(&mut rest).unwrap())
+        })
+    }
+}
@@ -26,6 +26,7 @@ mod demangling;
 mod ext;
 mod gc;
 mod hostcall;
+mod instruction_counts;
 mod key;
 mod module;
 mod module_artifacts;
@@ -48,6 +49,7 @@ pub use crate::demangling::*;
 pub use crate::frame_table::*;
 pub use crate::gc::*;
 pub use crate::hostcall::*;
+pub use crate::instruction_counts::*;
 pub use crate::key::*;
 pub use crate::module::*;
 pub use crate::module_artifacts::*;
@@ -120,6 +120,17 @@ pub const ELF_WASMTIME_EXCEPTIONS: &str = ".wasmtime.exceptions";
 /// offsets are relative to the start of the text segment.
 pub const ELF_WASMTIME_FRAMES: &str = ".wasmtime.frames";
 
+/// A custom binary-encoded section of the wasmtime compilation
+/// artifacts which describes instruction counters.
+///
+/// This section is present only when instruction counting is enabled
+/// and is used at runtime to allocate each function's counters and to
+/// map counters back to Wasm PCs.
+///
+/// This section's format is defined by the
+/// [`wasmtime_environ::InstructionCountsBuilder`] data structure.
+pub const ELF_WASMTIME_INSTRUCTION_COUNTS: &str = ".wasmtime.instrcounts";
+
 /// A custom section which consists of just 1 byte which is either 0 or 1 as to
 /// whether BTI is enabled.
 pub const ELF_WASM_BTI: &str = ".wasmtime.bti";
@@ -128,6 +139,13 @@ pub const ELF_WASM_BTI: &str = ".wasmtime.bti";
 /// double-check that an artifact can be loaded into the current host.
 pub const ELF_WASM_ENGINE: &str = ".wasmtime.engine";
 
+/// A section containing the public key which signed an artifact, followed by
+/// the signature itself.
+///
+/// The signature covers the whole artifact with the bytes of the signature
+/// itself set to zero.
+pub const ELF_WASMTIME_SIGNATURE: &str = ".wasmtime.signature";
+
 /// This is the name of the section in the final ELF image which contains
 /// concatenated data segments from the original wasm module.
 ///
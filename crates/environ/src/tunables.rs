@@ -87,6 +87,10 @@ define_tunables! {
         /// will be consumed every time a wasm instruction is executed.
         pub consume_fuel: bool,
 
+        /// Whether or not generated code counts the wasm instructions it
+        /// executes, per function and per basic block.
+        pub count_instructions: bool,
+
         /// Whether or not we use epoch-based interruption.
         pub epoch_interruption: bool,
 
@@ -119,6 +123,21 @@ define_tunables! {
         /// Whether or not Wasm functions target the winch abi.
         pub winch_callable: bool,
 
+        /// Whether or not functions are compiled for tiered compilation, where
+        /// functions are first compiled with Winch and hot functions are later
+        /// recompiled with Cranelift.
+        ///
+        /// Winch-compiled functions count how often they're called, and calls
+        /// to functions defined in the same module are made through their
+        /// `VMFuncRef` so that they can be re-routed to recompiled code.
+        pub tiered_compilation: bool,
+
+        /// The number of calls after which a Winch-compiled function asks to
+        /// be tiered up, when `tiered_compilation` is enabled. Functions don't
+        /// ask at all when this is zero, since they're all tiered up as soon
+        /// as their module is created.
+        pub tier_up_threshold: u32,
+
         /// Whether or not the host will be using native signals (e.g. SIGILL,
         /// SIGSEGV, etc) to implement traps.
         pub signals_based_traps: bool,
@@ -200,6 +219,7 @@ impl Tunables {
             debug_native: false,
             parse_wasm_debuginfo: true,
             consume_fuel: false,
+            count_instructions: false,
             epoch_interruption: false,
             memory_may_move: true,
             guard_before_linear_memory: true,
@@ -208,6 +228,8 @@ impl Tunables {
             debug_adapter_modules: false,
             relaxed_simd_deterministic: false,
             winch_callable: false,
+            tiered_compilation: false,
+            tier_up_threshold: 1000,
             signals_based_traps: false,
             memory_init_cow: true,
             inlining: false,
@@ -281,6 +303,8 @@ pub enum Collector {
     DeferredReferenceCounting,
     /// The null collector.
     Null,
+    /// The semi-space copying collector.
+    Copying,
 }
 
 impl fmt::Display for Collector {
@@ -288,6 +312,7 @@ impl fmt::Display for Collector {
         match self {
             Collector::DeferredReferenceCounting => write!(f, "deferred reference-counting"),
             Collector::Null => write!(f, "null"),
+            Collector::Copying => write!(f, "copying"),
         }
     }
 }
@@ -461,12 +461,31 @@ pub trait PtrSize {
         self.vmctx_gc_heap_data() + self.size()
     }
 
+    /// The offset of the instruction-counters table pointer.
+    ///
+    /// When instruction counting is enabled this points to an array, indexed
+    /// by `DefinedFuncIndex`, of pointers to each function's `u64` counters.
+    #[inline]
+    fn vmctx_instruction_counters(&self) -> u8 {
+        self.vmctx_type_ids_array() + self.size()
+    }
+
+    /// The offset of the call-counts array pointer.
+    ///
+    /// When tiered compilation is enabled this points to an array, indexed by
+    /// `DefinedFuncIndex`, of `u32` counts of calls to each function. The
+    /// array is shared by all instances of a module.
+    #[inline]
+    fn vmctx_call_counts(&self) -> u8 {
+        self.vmctx_instruction_counters() + self.size()
+    }
+
     /// The end of statically known offsets in `VMContext`.
     ///
     /// Data after this is dynamically sized.
     #[inline]
     fn vmctx_dynamic_data_start(&self) -> u8 {
-        self.vmctx_type_ids_array() + self.size()
+        self.vmctx_call_counts() + self.size()
     }
 }
 
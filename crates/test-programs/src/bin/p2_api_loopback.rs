@@ -0,0 +1,121 @@
+//! One side of an exchange between two stores on a shared network.
+//!
+//! Usage: `p2_api_loopback <tcp|udp> <server|client> <host> <port>`, where
+//! the server binds to `host` and answers a single message from the client
+//! by echoing it back.
+
+use test_programs::wasi::clocks::monotonic_clock;
+use test_programs::wasi::sockets::network::{ErrorCode, IpSocketAddress, Network};
+use test_programs::wasi::sockets::tcp::TcpSocket;
+use test_programs::wasi::sockets::udp::{OutgoingDatagram, UdpSocket};
+
+const MESSAGE: &[u8] = b"Hello from the other store!";
+
+/// How long to wait between attempts to reach a server which may not have
+/// bound its socket yet.
+const RETRY_NS: u64 = 10_000_000;
+
+const ATTEMPTS: u32 = 500;
+
+fn tcp_server(net: &Network, addr: IpSocketAddress) {
+    let listener = TcpSocket::new(addr.family()).unwrap();
+    listener.blocking_bind(net, addr).unwrap();
+    listener.blocking_listen().unwrap();
+
+    let sub = listener.subscribe();
+    let (_accepted, input, output) = loop {
+        match listener.accept() {
+            Err(ErrorCode::WouldBlock) => sub.block(),
+            result => break result.unwrap(),
+        }
+    };
+    let data = input.blocking_read(MESSAGE.len() as u64).unwrap();
+    assert_eq!(data, MESSAGE);
+    output.blocking_write_util(&data).unwrap();
+}
+
+fn tcp_client(net: &Network, addr: IpSocketAddress) {
+    let mut attempts = 0;
+    let (input, output) = loop {
+        let client = TcpSocket::new(addr.family()).unwrap();
+        match client.blocking_connect(net, addr) {
+            Ok(streams) => break streams,
+            Err(ErrorCode::ConnectionRefused) if attempts < ATTEMPTS => {
+                attempts += 1;
+                monotonic_clock::subscribe_duration(RETRY_NS).block();
+            }
+            Err(e) => panic!("failed to connect to {addr:?}: {e:?}"),
+        }
+    };
+    output.blocking_write_util(MESSAGE).unwrap();
+    let data = input.blocking_read(MESSAGE.len() as u64).unwrap();
+    assert_eq!(data, MESSAGE);
+}
+
+fn udp_server(net: &Network, addr: IpSocketAddress) {
+    let server = UdpSocket::new(addr.family()).unwrap();
+    server.blocking_bind(net, addr).unwrap();
+    let (incoming, outgoing) = server.stream(None).unwrap();
+
+    let sub = incoming.subscribe();
+    let datagram = loop {
+        match incoming.receive(1).unwrap().pop() {
+            Some(datagram) => break datagram,
+            None => sub.block(),
+        }
+    };
+    assert_eq!(datagram.data, MESSAGE);
+    outgoing
+        .blocking_send(&[OutgoingDatagram {
+            data: datagram.data,
+            remote_address: Some(datagram.remote_address),
+        }])
+        .unwrap();
+}
+
+fn udp_client(net: &Network, addr: IpSocketAddress) {
+    let client = UdpSocket::new(addr.family()).unwrap();
+    client.blocking_bind_unspecified(net).unwrap();
+    let (incoming, outgoing) = client.stream(Some(addr)).unwrap();
+
+    // Datagrams sent before the server is bound are dropped, so keep sending
+    // until it answers.
+    let sub = incoming.subscribe();
+    for _ in 0..ATTEMPTS {
+        outgoing
+            .blocking_send(&[OutgoingDatagram {
+                data: MESSAGE.to_vec(),
+                remote_address: None,
+            }])
+            .unwrap();
+        let timeout = monotonic_clock::subscribe_duration(RETRY_NS);
+        if sub.block_until(&timeout).is_err() {
+            continue;
+        }
+        let datagrams = incoming.receive(1).unwrap();
+        assert_eq!(datagrams.len(), 1);
+        assert_eq!(datagrams[0].data, MESSAGE);
+        assert_eq!(datagrams[0].remote_address, addr);
+        return;
+    }
+    panic!("no answer from {addr:?}");
+}
+
+fn main() {
+    let args = std::env::args().collect::<Vec<_>>();
+    let [_, protocol, role, host, port] = &args[..] else {
+        panic!("usage: p2_api_loopback <tcp|udp> <server|client> <host> <port>");
+    };
+
+    let net = Network::default();
+    let ip = net.blocking_resolve_addresses(host).unwrap()[0];
+    let addr = IpSocketAddress::new(ip, port.parse().unwrap());
+
+    match (protocol.as_str(), role.as_str()) {
+        ("tcp", "server") => tcp_server(&net, addr),
+        ("tcp", "client") => tcp_client(&net, addr),
+        ("udp", "server") => udp_server(&net, addr),
+        ("udp", "client") => udp_client(&net, addr),
+        _ => panic!("unknown protocol `{protocol}` or role `{role}`"),
+    }
+}
@@ -3,6 +3,8 @@ use cap_std::{AmbientAuthority, ambient_authority};
 use cap_time_ext::{MonotonicClockExt as _, SystemClockExt as _};
 use std::error::Error;
 use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use wasmtime::component::{HasData, ResourceTable};
 
 /// A helper struct which implements [`HasData`] for the `wasi:clocks` APIs.
@@ -52,6 +54,9 @@ impl HasData for WasiClocks {
 pub struct WasiClocksCtx {
     pub(crate) wall_clock: Box<dyn HostWallClock + Send>,
     pub(crate) monotonic_clock: Box<dyn HostMonotonicClock + Send>,
+    /// The clock which sleeping advances instead of waiting, if any, see
+    /// [`WasiCtxBuilder::deterministic`](crate::WasiCtxBuilder::deterministic).
+    pub(crate) virtual_clock: Option<VirtualClock>,
 }
 
 impl Default for WasiClocksCtx {
@@ -59,6 +64,7 @@ impl Default for WasiClocksCtx {
         Self {
             wall_clock: wall_clock(),
             monotonic_clock: monotonic_clock(),
+            virtual_clock: None,
         }
     }
 }
@@ -155,6 +161,111 @@ impl HostMonotonicClock for MonotonicClock {
     }
 }
 
+/// A clock which only advances when it's read or when it's slept on, rather
+/// than with real time.
+///
+/// Every reading advances the clock by its resolution, so that consecutive
+/// readings differ. The same clock can be used as both a wall clock and a
+/// monotonic clock, in which case both advance together. Clones of a
+/// `VirtualClock` share the same time.
+///
+/// This is what [`WasiCtxBuilder::deterministic`] uses, which also makes
+/// guests' sleeps advance the clock instead of waiting. Guests given this
+/// clock otherwise wait for real time when sleeping.
+///
+/// [`WasiCtxBuilder::deterministic`]: crate::WasiCtxBuilder::deterministic
+#[derive(Clone)]
+pub struct VirtualClock {
+    inner: Arc<VirtualClockInner>,
+}
+
+struct VirtualClockInner {
+    /// The wall clock time when this clock started.
+    start: Duration,
+    /// How much each reading advances the clock by, in nanoseconds.
+    resolution: u64,
+    /// The time since this clock started, in nanoseconds.
+    elapsed: AtomicU64,
+}
+
+impl VirtualClock {
+    /// Creates a clock which starts at `start` since the Unix epoch when used
+    /// as a wall clock, and at zero when used as a monotonic clock, and which
+    /// advances by `resolution` on each reading.
+    pub fn new(start: Duration, resolution: Duration) -> Self {
+        Self {
+            inner: Arc::new(VirtualClockInner {
+                start,
+                resolution: resolution.as_nanos().try_into().unwrap_or(u64::MAX),
+                elapsed: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Returns the time since this clock started, without advancing it.
+    pub fn elapsed(&self) -> Duration {
+        Duration::from_nanos(self.inner.elapsed.load(Ordering::SeqCst))
+    }
+
+    /// Advances this clock by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        let nanos = duration.as_nanos().try_into().unwrap_or(u64::MAX);
+        let _ = self
+            .inner
+            .elapsed
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |elapsed| {
+                Some(elapsed.saturating_add(nanos))
+            });
+    }
+
+    /// Advances this clock to the monotonic time `when`, in nanoseconds, if
+    /// it's not already past it.
+    pub(crate) fn advance_to(&self, when: u64) {
+        self.inner.elapsed.fetch_max(when, Ordering::SeqCst);
+    }
+
+    /// Returns the monotonic time `duration` from now, without advancing the
+    /// clock.
+    pub(crate) fn deadline(&self, duration: Duration) -> u64 {
+        let nanos: u64 = duration.as_nanos().try_into().unwrap_or(u64::MAX);
+        nanos.saturating_add(self.inner.elapsed.load(Ordering::SeqCst))
+    }
+
+    /// Advances this clock by its resolution and returns the new monotonic
+    /// time.
+    fn tick(&self) -> u64 {
+        let resolution = self.inner.resolution;
+        let previous = self
+            .inner
+            .elapsed
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |elapsed| {
+                Some(elapsed.saturating_add(resolution))
+            })
+            .unwrap();
+        previous.saturating_add(resolution)
+    }
+}
+
+impl HostWallClock for VirtualClock {
+    fn resolution(&self) -> Duration {
+        Duration::from_nanos(self.inner.resolution)
+    }
+
+    fn now(&self) -> Duration {
+        self.inner.start + Duration::from_nanos(self.tick())
+    }
+}
+
+impl HostMonotonicClock for VirtualClock {
+    fn resolution(&self) -> u64 {
+        self.inner.resolution
+    }
+
+    fn now(&self) -> u64 {
+        self.tick()
+    }
+}
+
 pub fn monotonic_clock() -> Box<dyn HostMonotonicClock + Send> {
     Box::new(MonotonicClock::default())
 }
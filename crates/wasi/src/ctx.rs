@@ -1,8 +1,10 @@
 use crate::cli::{StdinStream, StdoutStream, WasiCliCtx};
 use crate::clocks::{HostMonotonicClock, HostWallClock, WasiClocksCtx};
-use crate::filesystem::{Dir, WasiFilesystemCtx};
+use crate::filesystem::{Dir, OverlayDir, WasiDir, WasiFilesystemCtx};
 use crate::random::WasiRandomCtx;
-use crate::sockets::{SocketAddrCheck, SocketAddrUse, WasiSocketsCtx};
+use crate::sockets::{
+    LoopbackNetwork, SocketAddrCheck, SocketAddrUse, WasiNetwork, WasiSocketsCtx,
+};
 use crate::{DirPerms, FilePerms, OpenMode};
 use anyhow::Result;
 use cap_rand::RngCore;
@@ -12,6 +14,7 @@ use std::mem;
 use std::net::SocketAddr;
 use std::path::Path;
 use std::pin::Pin;
+use std::sync::Arc;
 use tokio::io::{stderr, stdin, stdout};
 
 /// Builder-style structure used to create a [`WasiCtx`].
@@ -41,6 +44,7 @@ pub struct WasiCtxBuilder {
     filesystem: WasiFilesystemCtx,
     random: WasiRandomCtx,
     sockets: WasiSocketsCtx,
+    deterministic: Option<u64>,
     built: bool,
 }
 
@@ -297,6 +301,43 @@ impl WasiCtxBuilder {
         file_perms: FilePerms,
     ) -> Result<&mut Self> {
         let dir = cap_std::fs::Dir::open_ambient_dir(host_path.as_ref(), ambient_authority())?;
+        Ok(self.preopened_virtual_dir(dir, guest_path, dir_perms, file_perms))
+    }
+
+    /// Provides a directory which isn't a directory of the host's filesystem
+    /// to the guest, such as a [`MemoryDir`](crate::filesystem::MemoryDir).
+    ///
+    /// This is like [`WasiCtxBuilder::preopened_dir`] except that the contents
+    /// of the directory are served by `dir`, which can be any implementation
+    /// of [`WasiDir`]. WASIp1, WASIp2 and WASIp3 guests see no difference
+    /// between it and a host directory. `dir_perms` and `file_perms` are
+    /// enforced the same way as for host directories, so for example a
+    /// directory embedded in the host can be provided read-only with
+    /// [`DirPerms::READ`] and [`FilePerms::READ`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wasmtime_wasi::WasiCtxBuilder;
+    /// use wasmtime_wasi::filesystem::MemoryDir;
+    /// use wasmtime_wasi::{DirPerms, FilePerms};
+    ///
+    /// # fn main() -> std::io::Result<()> {
+    /// let assets = MemoryDir::new();
+    /// assets.write_file("index.html", "<h1>Hello</h1>")?;
+    ///
+    /// let mut wasi = WasiCtxBuilder::new();
+    /// wasi.preopened_virtual_dir(assets, "/assets", DirPerms::READ, FilePerms::READ);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preopened_virtual_dir(
+        &mut self,
+        dir: impl WasiDir,
+        guest_path: impl AsRef<str>,
+        dir_perms: DirPerms,
+        file_perms: FilePerms,
+    ) -> &mut Self {
         let mut open_mode = OpenMode::empty();
         if dir_perms.contains(DirPerms::READ) {
             open_mode |= OpenMode::READ;
@@ -305,8 +346,8 @@ impl WasiCtxBuilder {
             open_mode |= OpenMode::WRITE;
         }
         self.filesystem.preopens.push((
-            Dir::new(
-                dir,
+            Dir::from_backend(
+                Arc::new(dir),
                 dir_perms,
                 file_perms,
                 open_mode,
@@ -314,7 +355,94 @@ impl WasiCtxBuilder {
             ),
             guest_path.as_ref().to_owned(),
         ));
-        Ok(self)
+        self
+    }
+
+    /// Provides a copy-on-write view of a directory of the host to the guest.
+    ///
+    /// The guest sees the contents of `host_path` and can modify them as
+    /// allowed by `dir_perms` and `file_perms`, but all changes it makes are
+    /// kept in memory and `host_path` is never written to. The returned
+    /// [`OverlayDir`] can be used to list or export those changes after the
+    /// guest has run, see [`OverlayDir::changes`] and
+    /// [`OverlayDir::export`].
+    ///
+    /// To keep the changes somewhere other than in memory, create an
+    /// [`OverlayDir`] with [`OverlayDir::new`] and provide it with
+    /// [`WasiCtxBuilder::preopened_virtual_dir`] instead.
+    ///
+    /// # Errors
+    ///
+    /// This method will return an error if `host_path` cannot be opened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use wasmtime_wasi::WasiCtxBuilder;
+    /// use wasmtime_wasi::{DirPerms, FilePerms};
+    ///
+    /// # fn main() {}
+    /// # fn foo() -> wasmtime::Result<()> {
+    /// let mut wasi = WasiCtxBuilder::new();
+    /// let overlay =
+    ///     wasi.preopened_overlay_dir("./project", "/project", DirPerms::all(), FilePerms::all())?;
+    ///
+    /// // ... run the guest, then look at what it changed ...
+    /// for change in overlay.changes()? {
+    ///     println!("{change:?}");
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn preopened_overlay_dir(
+        &mut self,
+        host_path: impl AsRef<Path>,
+        guest_path: impl AsRef<str>,
+        dir_perms: DirPerms,
+        file_perms: FilePerms,
+    ) -> Result<OverlayDir> {
+        let dir = cap_std::fs::Dir::open_ambient_dir(host_path.as_ref(), ambient_authority())?;
+        let overlay = OverlayDir::in_memory(dir);
+        self.preopened_virtual_dir(overlay.clone(), guest_path, dir_perms, file_perms);
+        Ok(overlay)
+    }
+
+    /// Limits the number of bytes the guest can write to files in preopened
+    /// directories.
+    ///
+    /// Writes which would exceed the limit are shortened to fit it, and fail
+    /// with a quota error once nothing is left, which is `EDQUOT` for WASIp1
+    /// and `error-code.quota` for WASIp2 and WASIp3. Extending a file by
+    /// setting its size also counts as writing to it.
+    ///
+    /// The limit applies to everything the guest writes through this context,
+    /// and the number of bytes written so far can be read with
+    /// [`WasiFilesystemCtx::usage`]. By default there's no limit.
+    pub fn max_bytes_written(&mut self, limit: u64) -> &mut Self {
+        self.filesystem.limits.bytes_written = Some(limit);
+        self
+    }
+
+    /// Limits the number of files, directories and symbolic links the guest
+    /// can create in preopened directories, less the number of those it
+    /// removes again. Removing anything else doesn't allow the guest to
+    /// create more.
+    ///
+    /// Creating more fails with a quota error, like
+    /// [`WasiCtxBuilder::max_bytes_written`]. By default there's no limit.
+    pub fn max_files(&mut self, limit: u64) -> &mut Self {
+        self.filesystem.limits.files = Some(limit);
+        self
+    }
+
+    /// Limits the number of files and directories the guest can have open at
+    /// once, not counting preopened directories.
+    ///
+    /// Opening more fails with a quota error, like
+    /// [`WasiCtxBuilder::max_bytes_written`]. By default there's no limit.
+    pub fn max_open_descriptors(&mut self, limit: u64) -> &mut Self {
+        self.filesystem.limits.open_descriptors = Some(limit);
+        self
     }
 
     /// Set the generator for the `wasi:random/random` number generator to the
@@ -366,6 +494,36 @@ impl WasiCtxBuilder {
         self
     }
 
+    /// Makes the guest's execution deterministic, so that running it again
+    /// with the same `seed` and the same inputs produces the same results.
+    ///
+    /// This replaces all sources of nondeterminism that WASI otherwise gives
+    /// guests when [`build`](Self::build) is called:
+    ///
+    /// * The wall clock and the monotonic clock are replaced with a
+    ///   [`VirtualClock`](crate::clocks::VirtualClock) which starts at
+    ///   2000-01-01T00:00:00Z and only advances when the guest reads it or
+    ///   sleeps. Sleeping advances the clock instead of waiting.
+    /// * All random number generators, and the insecure random seed, are
+    ///   derived from `seed`.
+    /// * Entries of preopened directories are listed in order of their names,
+    ///   and all files and directories report the clocks' start time as their
+    ///   timestamps. They're numbered in the order the guest first sees them
+    ///   rather than by their inode numbers on the host.
+    /// * All networking is disabled, overriding methods such as
+    ///   [`WasiCtxBuilder::inherit_network`].
+    ///
+    /// Inputs such as stdin, environment variables and the contents of
+    /// preopened directories are left as configured. Execution of the guest
+    /// itself can be made deterministic too with options such as
+    /// [`Config::relaxed_simd_deterministic`] and NaN canonicalization.
+    ///
+    /// [`Config::relaxed_simd_deterministic`]: wasmtime::Config::relaxed_simd_deterministic
+    pub fn deterministic(&mut self, seed: u64) -> &mut Self {
+        self.deterministic = Some(seed);
+        self
+    }
+
     /// Allow all network addresses accessible to the host.
     ///
     /// This method will inherit all network addresses meaning that any address
@@ -392,6 +550,31 @@ impl WasiCtxBuilder {
         self
     }
 
+    /// Attaches the guest's sockets to `network` instead of the host's
+    /// network, and answers its name lookups from `network`.
+    ///
+    /// Addresses are still subject to [`WasiCtxBuilder::socket_addr_check`],
+    /// so this is typically combined with
+    /// [`WasiCtxBuilder::inherit_network`]. See [`WasiNetwork`] for what
+    /// implementations need to provide.
+    pub fn network(&mut self, network: impl WasiNetwork) -> &mut Self {
+        self.sockets.network = Arc::new(network);
+        self
+    }
+
+    /// Attaches the guest's sockets to an in-process [`LoopbackNetwork`]
+    /// instead of the host's network.
+    ///
+    /// Sockets then only reach other sockets on the same network, such as
+    /// those of other stores given a clone of `network`, and name lookups are
+    /// answered from the hosts registered with
+    /// [`LoopbackNetwork::add_host`]. Addresses are still subject to
+    /// [`WasiCtxBuilder::socket_addr_check`], so this is typically combined
+    /// with [`WasiCtxBuilder::inherit_network`].
+    pub fn loopback_network(&mut self, network: LoopbackNetwork) -> &mut Self {
+        self.network(network)
+    }
+
     /// Allow usage of `wasi:sockets/ip-name-lookup`
     ///
     /// By default this is disabled.
@@ -433,14 +616,26 @@ impl WasiCtxBuilder {
 
         let Self {
             cli,
-            clocks,
-            filesystem,
-            random,
-            sockets,
+            mut clocks,
+            mut filesystem,
+            mut random,
+            mut sockets,
+            deterministic,
             built: _,
         } = mem::replace(self, Self::new());
         self.built = true;
 
+        if let Some(seed) = deterministic {
+            crate::deterministic::apply(
+                seed,
+                &mut clocks,
+                &mut random,
+                &mut filesystem,
+                &mut sockets,
+            );
+        }
+        filesystem.start_accounting();
+
         WasiCtx {
             cli,
             clocks,
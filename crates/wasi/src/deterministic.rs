@@ -0,0 +1,61 @@
+//! Removal of all sources of nondeterminism from a context, see
+//! [`WasiCtxBuilder::deterministic`].
+//!
+//! [`WasiCtxBuilder::deterministic`]: crate::WasiCtxBuilder::deterministic
+
+use crate::clocks::{VirtualClock, WasiClocksCtx};
+use crate::filesystem::{Stable, StableDir, WasiFilesystemCtx};
+use crate::random::WasiRandomCtx;
+use crate::sockets::{AllowedNetworkUses, SocketAddrCheck, WasiSocketsCtx};
+use cap_rand::rngs::StdRng;
+use cap_rand::{Rng as _, SeedableRng as _};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+/// The time the clocks start at, and the timestamp of all files, which is
+/// 2000-01-01T00:00:00Z. Some tools reject timestamps before 1980, so this
+/// isn't the Unix epoch.
+const START: Duration = Duration::from_secs(946_684_800);
+
+/// How much each reading of a clock advances it by.
+const RESOLUTION: Duration = Duration::from_micros(1);
+
+/// Replaces the clocks and random number generators in `clocks` and `random`
+/// with deterministic ones derived from `seed`, makes the preopened
+/// directories in `filesystem` stable, and disables all networking in
+/// `sockets`.
+pub(crate) fn apply(
+    seed: u64,
+    clocks: &mut WasiClocksCtx,
+    random: &mut WasiRandomCtx,
+    filesystem: &mut WasiFilesystemCtx,
+    sockets: &mut WasiSocketsCtx,
+) {
+    let clock = VirtualClock::new(START, RESOLUTION);
+    *clocks = WasiClocksCtx {
+        wall_clock: Box::new(clock.clone()),
+        monotonic_clock: Box::new(clock.clone()),
+        virtual_clock: Some(clock),
+    };
+
+    // `StdRng` is used for the insecure generator too since, unlike
+    // `SmallRng`, it's the same on all platforms.
+    let mut seeds = StdRng::seed_from_u64(seed);
+    *random = WasiRandomCtx {
+        random: Box::new(StdRng::from_seed(seeds.r#gen())),
+        insecure_random: Box::new(StdRng::from_seed(seeds.r#gen())),
+        insecure_random_seed: seeds.r#gen(),
+    };
+
+    let stable = Arc::new(Stable::new(SystemTime::UNIX_EPOCH + START));
+    for (dir, _) in filesystem.preopens.iter_mut() {
+        dir.dir = Arc::new(StableDir::new(Arc::clone(&dir.dir), Arc::clone(&stable)));
+    }
+
+    sockets.socket_addr_check = SocketAddrCheck::default();
+    sockets.allowed_network_uses = AllowedNetworkUses {
+        ip_name_lookup: false,
+        udp: false,
+        tcp: false,
+    };
+}
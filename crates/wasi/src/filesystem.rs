@@ -1,13 +1,29 @@
 use crate::clocks::Datetime;
 use crate::runtime::{AbortOnDropJoinHandle, spawn_blocking};
 use anyhow::Context as _;
-use cap_fs_ext::{FileTypeExt as _, MetadataExt as _};
 use fs_set_times::SystemTimeSpec;
 use std::collections::hash_map;
 use std::sync::Arc;
 use tracing::debug;
 use wasmtime::component::{HasData, Resource, ResourceTable};
 
+#[cfg(any(feature = "tar", feature = "zip"))]
+mod archive;
+mod backend;
+mod memory;
+mod overlay;
+mod quota;
+mod stable;
+
+#[cfg(any(feature = "tar", feature = "zip"))]
+pub use self::archive::ArchiveDir;
+pub use self::backend::{DirEntry, FileType, Metadata, OpenOptions, Opened, WasiDir, WasiFile};
+pub use self::memory::MemoryDir;
+pub use self::overlay::{OverlayChange, OverlayDir};
+pub(crate) use self::quota::FilesystemLimits;
+pub use self::quota::FilesystemUsage;
+pub(crate) use self::stable::{Stable, StableDir};
+
 /// A helper struct which implements [`HasData`] for the `wasi:filesystem` APIs.
 ///
 /// This can be useful when directly calling `add_to_linker` functions directly,
@@ -56,6 +72,29 @@ impl HasData for WasiFilesystem {
 pub struct WasiFilesystemCtx {
     pub(crate) allow_blocking_current_thread: bool,
     pub(crate) preopens: Vec<(Dir, String)>,
+    pub(crate) limits: FilesystemLimits,
+    accounting: Arc<quota::Accounting>,
+}
+
+impl WasiFilesystemCtx {
+    /// Returns statistics about what the guest has done through the preopened
+    /// directories of this context so far, such as how many bytes it has read
+    /// and written.
+    pub fn usage(&self) -> FilesystemUsage {
+        self.accounting.usage()
+    }
+
+    /// Starts accounting for the use of all preopened directories, enforcing
+    /// `self.limits`, which is done once when a context is built.
+    pub(crate) fn start_accounting(&mut self) {
+        self.accounting = Arc::new(quota::Accounting::new(self.limits));
+        for (dir, _) in self.preopens.iter_mut() {
+            dir.dir = Arc::new(quota::AccountedDir::new(
+                Arc::clone(&dir.dir),
+                Arc::clone(&self.accounting),
+            ));
+        }
+    }
 }
 
 pub struct WasiFilesystemCtxView<'a> {
@@ -230,6 +269,10 @@ pub(crate) enum ErrorCode {
     Pipe,
     /// Invalid seek, similar to `ESPIPE` in POSIX.
     InvalidSeek,
+    /// Cross-device link, similar to `EXDEV` in POSIX.
+    CrossDevice,
+    /// Storage quota exceeded, similar to `EDQUOT` in POSIX.
+    Quota,
 }
 
 fn datetime_from(t: std::time::SystemTime) -> Datetime {
@@ -256,20 +299,15 @@ pub(crate) enum DescriptorType {
     RegularFile,
 }
 
-impl From<cap_std::fs::FileType> for DescriptorType {
-    fn from(ft: cap_std::fs::FileType) -> Self {
-        if ft.is_dir() {
-            DescriptorType::Directory
-        } else if ft.is_symlink() {
-            DescriptorType::SymbolicLink
-        } else if ft.is_block_device() {
-            DescriptorType::BlockDevice
-        } else if ft.is_char_device() {
-            DescriptorType::CharacterDevice
-        } else if ft.is_file() {
-            DescriptorType::RegularFile
-        } else {
-            DescriptorType::Unknown
+impl From<FileType> for DescriptorType {
+    fn from(ft: FileType) -> Self {
+        match ft {
+            FileType::Unknown => DescriptorType::Unknown,
+            FileType::BlockDevice => DescriptorType::BlockDevice,
+            FileType::CharacterDevice => DescriptorType::CharacterDevice,
+            FileType::Directory => DescriptorType::Directory,
+            FileType::SymbolicLink => DescriptorType::SymbolicLink,
+            FileType::RegularFile => DescriptorType::RegularFile,
         }
     }
 }
@@ -302,15 +340,15 @@ pub(crate) struct DescriptorStat {
     pub status_change_timestamp: Option<Datetime>,
 }
 
-impl From<cap_std::fs::Metadata> for DescriptorStat {
-    fn from(meta: cap_std::fs::Metadata) -> Self {
+impl From<Metadata> for DescriptorStat {
+    fn from(meta: Metadata) -> Self {
         Self {
-            type_: meta.file_type().into(),
-            link_count: meta.nlink(),
-            size: meta.len(),
-            data_access_timestamp: meta.accessed().map(|t| datetime_from(t.into_std())).ok(),
-            data_modification_timestamp: meta.modified().map(|t| datetime_from(t.into_std())).ok(),
-            status_change_timestamp: meta.created().map(|t| datetime_from(t.into_std())).ok(),
+            type_: meta.file_type.into(),
+            link_count: meta.link_count,
+            size: meta.size,
+            data_access_timestamp: meta.accessed.map(datetime_from),
+            data_modification_timestamp: meta.modified.map(datetime_from),
+            status_change_timestamp: meta.status_changed.map(datetime_from),
         }
     }
 }
@@ -324,17 +362,16 @@ pub(crate) struct MetadataHashValue {
     pub upper: u64,
 }
 
-impl From<&cap_std::fs::Metadata> for MetadataHashValue {
-    fn from(meta: &cap_std::fs::Metadata) -> Self {
-        use cap_fs_ext::MetadataExt;
+impl From<&Metadata> for MetadataHashValue {
+    fn from(meta: &Metadata) -> Self {
         // Without incurring any deps, std provides us with a 64 bit hash
         // function:
         use std::hash::Hasher;
         // Note that this means that the metadata hash (which becomes a preview1 ino) may
         // change when a different rustc release is used to build this host implementation:
         let mut hasher = hash_map::DefaultHasher::new();
-        hasher.write_u64(meta.dev());
-        hasher.write_u64(meta.ino());
+        hasher.write_u64(meta.device);
+        hasher.write_u64(meta.inode);
         let lower = hasher.finish();
         // MetadataHashValue has a pair of 64-bit members for representing a
         // single 128-bit number. However, we only have 64 bits of entropy. To
@@ -382,6 +419,8 @@ fn from_raw_os_error(err: Option<i32>) -> Option<ErrorCode> {
         RustixErrno::ALREADY => ErrorCode::Already,
         RustixErrno::INPROGRESS => ErrorCode::InProgress,
         RustixErrno::INTR => ErrorCode::Interrupted,
+        RustixErrno::XDEV => ErrorCode::CrossDevice,
+        RustixErrno::DQUOT => ErrorCode::Quota,
 
         // On some platforms, these have the same value as other errno values.
         #[allow(unreachable_patterns, reason = "see comment")]
@@ -417,6 +456,7 @@ fn from_raw_os_error(raw_os_error: Option<i32>) -> Option<ErrorCode> {
         Some(Foundation::ERROR_ALREADY_EXISTS) => ErrorCode::Exist,
         Some(Foundation::ERROR_STOPPED_ON_SYMLINK) => ErrorCode::Loop,
         Some(Foundation::ERROR_DIRECTORY_NOT_SUPPORTED) => ErrorCode::IsDirectory,
+        Some(Foundation::ERROR_DISK_QUOTA_EXCEEDED) => ErrorCode::Quota,
         _ => return None,
     })
 }
@@ -432,6 +472,19 @@ impl<'a> From<&'a std::io::Error> for ErrorCode {
                     std::io::ErrorKind::PermissionDenied => ErrorCode::NotPermitted,
                     std::io::ErrorKind::AlreadyExists => ErrorCode::Exist,
                     std::io::ErrorKind::InvalidInput => ErrorCode::Invalid,
+                    std::io::ErrorKind::NotADirectory => ErrorCode::NotDirectory,
+                    std::io::ErrorKind::IsADirectory => ErrorCode::IsDirectory,
+                    std::io::ErrorKind::DirectoryNotEmpty => ErrorCode::NotEmpty,
+                    std::io::ErrorKind::ReadOnlyFilesystem => ErrorCode::NotPermitted,
+                    std::io::ErrorKind::CrossesDevices => ErrorCode::CrossDevice,
+                    std::io::ErrorKind::QuotaExceeded => ErrorCode::Quota,
+                    std::io::ErrorKind::StorageFull => ErrorCode::InsufficientSpace,
+                    std::io::ErrorKind::FileTooLarge => ErrorCode::FileTooLarge,
+                    std::io::ErrorKind::TooManyLinks => ErrorCode::TooManyLinks,
+                    std::io::ErrorKind::NotSeekable => ErrorCode::InvalidSeek,
+                    std::io::ErrorKind::Unsupported => ErrorCode::Unsupported,
+                    std::io::ErrorKind::OutOfMemory => ErrorCode::InsufficientMemory,
+                    std::io::ErrorKind::Interrupted => ErrorCode::Interrupted,
                     _ => ErrorCode::Io,
                 }
             }
@@ -466,7 +519,7 @@ impl Descriptor {
         }
     }
 
-    async fn get_metadata(&self) -> std::io::Result<cap_std::fs::Metadata> {
+    async fn get_metadata(&self) -> std::io::Result<Metadata> {
         match self {
             Self::File(f) => {
                 // No permissions check on metadata: if opened, allowed to stat it
@@ -498,12 +551,8 @@ impl Descriptor {
                 }
             }
             Self::Dir(d) => {
-                d.run_blocking(|d| {
-                    let d = d.open(std::path::Component::CurDir)?;
-                    d.sync_data()?;
-                    Ok(())
-                })
-                .await
+                d.run_blocking(|d| d.sync_data()).await?;
+                Ok(())
             }
         }
     }
@@ -524,10 +573,15 @@ impl Descriptor {
             }
             out
         }
+        // Only host files and directories have flags of their own.
         match self {
             Self::File(f) => {
-                let flags = f.run_blocking(|f| f.get_fd_flags()).await?;
-                let mut flags = get_from_fdflags(flags);
+                let flags = f
+                    .run_blocking(|f| f.as_host_file().map(|f| f.get_fd_flags()).transpose())
+                    .await?;
+                let mut flags = flags
+                    .map(get_from_fdflags)
+                    .unwrap_or(DescriptorFlags::empty());
                 if f.open_mode.contains(OpenMode::READ) {
                     flags |= DescriptorFlags::READ;
                 }
@@ -537,8 +591,12 @@ impl Descriptor {
                 Ok(flags)
             }
             Self::Dir(d) => {
-                let flags = d.run_blocking(|d| d.get_fd_flags()).await?;
-                let mut flags = get_from_fdflags(flags);
+                let flags = d
+                    .run_blocking(|d| d.as_host_dir().map(|d| d.get_fd_flags()).transpose())
+                    .await?;
+                let mut flags = flags
+                    .map(get_from_fdflags)
+                    .unwrap_or(DescriptorFlags::empty());
                 if d.open_mode.contains(OpenMode::READ) {
                     flags |= DescriptorFlags::READ;
                 }
@@ -554,7 +612,7 @@ impl Descriptor {
         match self {
             Self::File(f) => {
                 let meta = f.run_blocking(|f| f.metadata()).await?;
-                Ok(meta.file_type().into())
+                Ok(meta.file_type.into())
             }
             Self::Dir(_) => Ok(DescriptorType::Directory),
         }
@@ -565,20 +623,21 @@ impl Descriptor {
         atim: Option<SystemTimeSpec>,
         mtim: Option<SystemTimeSpec>,
     ) -> Result<(), ErrorCode> {
-        use fs_set_times::SetTimes as _;
+        let atim = atim.map(cap_fs_ext::SystemTimeSpec::from_std);
+        let mtim = mtim.map(cap_fs_ext::SystemTimeSpec::from_std);
         match self {
             Self::File(f) => {
                 if !f.perms.contains(FilePerms::WRITE) {
                     return Err(ErrorCode::NotPermitted);
                 }
-                f.run_blocking(|f| f.set_times(atim, mtim)).await?;
+                f.run_blocking(move |f| f.set_times(atim, mtim)).await?;
                 Ok(())
             }
             Self::Dir(d) => {
                 if !d.perms.contains(DirPerms::MUTATE) {
                     return Err(ErrorCode::NotPermitted);
                 }
-                d.run_blocking(|d| d.set_times(atim, mtim)).await?;
+                d.run_blocking(move |d| d.set_times(atim, mtim)).await?;
                 Ok(())
             }
         }
@@ -603,12 +662,8 @@ impl Descriptor {
                 }
             }
             Self::Dir(d) => {
-                d.run_blocking(|d| {
-                    let d = d.open(std::path::Component::CurDir)?;
-                    d.sync_all()?;
-                    Ok(())
-                })
-                .await
+                d.run_blocking(|d| d.sync_all()).await?;
+                Ok(())
             }
         }
     }
@@ -629,10 +684,9 @@ impl Descriptor {
     }
 
     pub(crate) async fn is_same_object(&self, other: &Self) -> wasmtime::Result<bool> {
-        use cap_fs_ext::MetadataExt;
         let meta_a = self.get_metadata().await?;
         let meta_b = other.get_metadata().await?;
-        if meta_a.dev() == meta_b.dev() && meta_a.ino() == meta_b.ino() {
+        if meta_a.device == meta_b.device && meta_a.inode == meta_b.inode {
             // MetadataHashValue does not derive eq, so use a pair of
             // comparisons to check equality:
             debug_assert_eq!(
@@ -658,14 +712,15 @@ impl Descriptor {
 
 #[derive(Clone)]
 pub struct File {
-    /// The operating system File this struct is mediating access to.
+    /// The file this struct is mediating access to, which is an operating
+    /// system file unless it was opened through a virtual directory.
     ///
     /// Wrapped in an Arc because the same underlying file is used for
     /// implementing the stream types. A copy is also needed for
     /// [`spawn_blocking`].
     ///
     /// [`spawn_blocking`]: Self::spawn_blocking
-    pub file: Arc<cap_std::fs::File>,
+    pub file: Arc<dyn WasiFile>,
     /// Permissions to enforce on access to the file. These permissions are
     /// specified by a user of the `crate::WasiCtxBuilder`, and are
     /// enforced prior to any enforced by the underlying operating system.
@@ -685,9 +740,24 @@ impl File {
         perms: FilePerms,
         open_mode: OpenMode,
         allow_blocking_current_thread: bool,
+    ) -> Self {
+        Self::from_backend(
+            Arc::new(file),
+            perms,
+            open_mode,
+            allow_blocking_current_thread,
+        )
+    }
+
+    /// Creates a file which is backed by any implementation of [`WasiFile`].
+    pub fn from_backend(
+        file: Arc<dyn WasiFile>,
+        perms: FilePerms,
+        open_mode: OpenMode,
+        allow_blocking_current_thread: bool,
     ) -> Self {
         Self {
-            file: Arc::new(file),
+            file,
             perms,
             open_mode,
             allow_blocking_current_thread,
@@ -710,7 +780,7 @@ impl File {
     /// - [Implement opt-in for enabling WASI to block the current thread](https://github.com/bytecodealliance/wasmtime/pull/8190)
     pub(crate) async fn run_blocking<F, R>(&self, body: F) -> R
     where
-        F: FnOnce(&cap_std::fs::File) -> R + Send + 'static,
+        F: FnOnce(&dyn WasiFile) -> R + Send + 'static,
         R: Send + 'static,
     {
         match self.as_blocking_file() {
@@ -721,27 +791,27 @@ impl File {
 
     pub(crate) fn spawn_blocking<F, R>(&self, body: F) -> AbortOnDropJoinHandle<R>
     where
-        F: FnOnce(&cap_std::fs::File) -> R + Send + 'static,
+        F: FnOnce(&dyn WasiFile) -> R + Send + 'static,
         R: Send + 'static,
     {
         let f = self.file.clone();
-        spawn_blocking(move || body(&f))
+        spawn_blocking(move || body(&*f))
     }
 
     /// Returns `Some` when the current thread is allowed to block in filesystem
     /// operations, and otherwise returns `None` to indicate that
     /// `spawn_blocking` must be used.
-    pub(crate) fn as_blocking_file(&self) -> Option<&cap_std::fs::File> {
+    pub(crate) fn as_blocking_file(&self) -> Option<&dyn WasiFile> {
         if self.allow_blocking_current_thread {
-            Some(&self.file)
+            Some(&*self.file)
         } else {
             None
         }
     }
 
-    /// Returns reference to the underlying [`cap_std::fs::File`]
+    /// Returns reference to the underlying [`WasiFile`]
     #[cfg(feature = "p3")]
-    pub(crate) fn as_file(&self) -> &Arc<cap_std::fs::File> {
+    pub(crate) fn as_file(&self) -> &Arc<dyn WasiFile> {
         &self.file
     }
 
@@ -752,8 +822,13 @@ impl File {
         advice: system_interface::fs::Advice,
     ) -> Result<(), ErrorCode> {
         use system_interface::fs::FileIoExt as _;
-        self.run_blocking(move |f| f.advise(offset, len, advice))
-            .await?;
+        // Advice is only meaningful to the OS, so it's ignored for virtual
+        // files.
+        self.run_blocking(move |f| match f.as_host_file() {
+            Some(f) => f.advise(offset, len, advice),
+            None => Ok(()),
+        })
+        .await?;
         Ok(())
     }
 
@@ -768,13 +843,14 @@ impl File {
 
 #[derive(Clone)]
 pub struct Dir {
-    /// The operating system file descriptor this struct is mediating access
-    /// to.
+    /// The directory this struct is mediating access to, which is an
+    /// operating system directory unless it was preopened with
+    /// [`crate::WasiCtxBuilder::preopened_virtual_dir`].
     ///
     /// Wrapped in an Arc because a copy is needed for [`spawn_blocking`].
     ///
     /// [`spawn_blocking`]: Self::spawn_blocking
-    pub dir: Arc<cap_std::fs::Dir>,
+    pub dir: Arc<dyn WasiDir>,
     /// Permissions to enforce on access to this directory. These permissions
     /// are specified by a user of the `crate::WasiCtxBuilder`, and
     /// are enforced prior to any enforced by the underlying operating system.
@@ -800,9 +876,27 @@ impl Dir {
         file_perms: FilePerms,
         open_mode: OpenMode,
         allow_blocking_current_thread: bool,
+    ) -> Self {
+        Self::from_backend(
+            Arc::new(dir),
+            perms,
+            file_perms,
+            open_mode,
+            allow_blocking_current_thread,
+        )
+    }
+
+    /// Creates a directory which is backed by any implementation of
+    /// [`WasiDir`].
+    pub fn from_backend(
+        dir: Arc<dyn WasiDir>,
+        perms: DirPerms,
+        file_perms: FilePerms,
+        open_mode: OpenMode,
+        allow_blocking_current_thread: bool,
     ) -> Self {
         Dir {
-            dir: Arc::new(dir),
+            dir,
             perms,
             file_perms,
             open_mode,
@@ -826,20 +920,20 @@ impl Dir {
     /// - [Implement opt-in for enabling WASI to block the current thread](https://github.com/bytecodealliance/wasmtime/pull/8190)
     pub(crate) async fn run_blocking<F, R>(&self, body: F) -> R
     where
-        F: FnOnce(&cap_std::fs::Dir) -> R + Send + 'static,
+        F: FnOnce(&dyn WasiDir) -> R + Send + 'static,
         R: Send + 'static,
     {
         if self.allow_blocking_current_thread {
-            body(&self.dir)
+            body(&*self.dir)
         } else {
             let d = self.dir.clone();
-            spawn_blocking(move || body(&d)).await
+            spawn_blocking(move || body(&*d)).await
         }
     }
 
-    /// Returns reference to the underlying [`cap_std::fs::Dir`]
+    /// Returns reference to the underlying [`WasiDir`]
     #[cfg(feature = "p3")]
-    pub(crate) fn as_dir(&self) -> &Arc<cap_std::fs::Dir> {
+    pub(crate) fn as_dir(&self) -> &Arc<dyn WasiDir> {
         &self.dir
     }
 
@@ -847,7 +941,7 @@ impl Dir {
         if !self.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted);
         }
-        self.run_blocking(move |d| d.create_dir(&path)).await?;
+        self.run_blocking(move |d| d.create_dir_at(&path)).await?;
         Ok(())
     }
 
@@ -860,12 +954,10 @@ impl Dir {
             return Err(ErrorCode::NotPermitted);
         }
 
-        let meta = if path_flags.contains(PathFlags::SYMLINK_FOLLOW) {
-            self.run_blocking(move |d| d.metadata(&path)).await?
-        } else {
-            self.run_blocking(move |d| d.symlink_metadata(&path))
-                .await?
-        };
+        let follow = path_flags.contains(PathFlags::SYMLINK_FOLLOW);
+        let meta = self
+            .run_blocking(move |d| d.metadata_at(&path, follow))
+            .await?;
         Ok(meta.into())
     }
 
@@ -876,30 +968,19 @@ impl Dir {
         atim: Option<SystemTimeSpec>,
         mtim: Option<SystemTimeSpec>,
     ) -> Result<(), ErrorCode> {
-        use cap_fs_ext::DirExt as _;
-
         if !self.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted);
         }
-        if path_flags.contains(PathFlags::SYMLINK_FOLLOW) {
-            self.run_blocking(move |d| {
-                d.set_times(
-                    &path,
-                    atim.map(cap_fs_ext::SystemTimeSpec::from_std),
-                    mtim.map(cap_fs_ext::SystemTimeSpec::from_std),
-                )
-            })
-            .await?;
-        } else {
-            self.run_blocking(move |d| {
-                d.set_symlink_times(
-                    &path,
-                    atim.map(cap_fs_ext::SystemTimeSpec::from_std),
-                    mtim.map(cap_fs_ext::SystemTimeSpec::from_std),
-                )
-            })
-            .await?;
-        }
+        let follow = path_flags.contains(PathFlags::SYMLINK_FOLLOW);
+        self.run_blocking(move |d| {
+            d.set_times_at(
+                &path,
+                follow,
+                atim.map(cap_fs_ext::SystemTimeSpec::from_std),
+                mtim.map(cap_fs_ext::SystemTimeSpec::from_std),
+            )
+        })
+        .await?;
         Ok(())
     }
 
@@ -920,7 +1001,7 @@ impl Dir {
             return Err(ErrorCode::Invalid);
         }
         let new_dir_handle = Arc::clone(&new_dir.dir);
-        self.run_blocking(move |d| d.hard_link(&old_path, &new_dir_handle, &new_path))
+        self.run_blocking(move |d| d.hard_link_at(&old_path, &*new_dir_handle, &new_path))
             .await?;
         Ok(())
     }
@@ -933,9 +1014,6 @@ impl Dir {
         flags: DescriptorFlags,
         allow_blocking_current_thread: bool,
     ) -> Result<Descriptor, ErrorCode> {
-        use cap_fs_ext::{FollowSymlinks, OpenOptionsFollowExt, OpenOptionsMaybeDirExt};
-        use system_interface::fs::{FdFlags, GetSetFdFlags};
-
         if !self.perms.contains(DirPerms::READ) {
             return Err(ErrorCode::NotPermitted);
         }
@@ -953,42 +1031,38 @@ impl Dir {
         let mut create = false;
         // Track open mode, for permission check and recording in created descriptor:
         let mut open_mode = OpenMode::empty();
-        // Construct the OpenOptions to give the OS:
-        let mut opts = cap_std::fs::OpenOptions::new();
-        opts.maybe_dir(true);
+        // Construct the OpenOptions to give the backend:
+        let mut opts = OpenOptions::default();
 
         if oflags.contains(OpenFlags::CREATE) {
             if oflags.contains(OpenFlags::EXCLUSIVE) {
-                opts.create_new(true);
+                opts.create_new = true;
             } else {
-                opts.create(true);
+                opts.create = true;
             }
             create = true;
-            opts.write(true);
+            opts.write = true;
             open_mode |= OpenMode::WRITE;
         }
 
         if oflags.contains(OpenFlags::TRUNCATE) {
-            opts.truncate(true).write(true);
+            opts.truncate = true;
+            opts.write = true;
         }
         if flags.contains(DescriptorFlags::READ) {
-            opts.read(true);
+            opts.read = true;
             open_mode |= OpenMode::READ;
         }
         if flags.contains(DescriptorFlags::WRITE) {
-            opts.write(true);
+            opts.write = true;
             open_mode |= OpenMode::WRITE;
         } else {
             // If not opened write, open read. This way the OS lets us open
             // the file, but we can use perms to reject use of the file later.
-            opts.read(true);
+            opts.read = true;
             open_mode |= OpenMode::READ;
         }
-        if path_flags.contains(PathFlags::SYMLINK_FOLLOW) {
-            opts.follow(FollowSymlinks::Yes);
-        } else {
-            opts.follow(FollowSymlinks::No);
-        }
+        let follow = path_flags.contains(PathFlags::SYMLINK_FOLLOW);
 
         // These flags are not yet supported in cap-std:
         if flags.contains(DescriptorFlags::FILE_INTEGRITY_SYNC)
@@ -1016,32 +1090,8 @@ impl Dir {
             return Err(ErrorCode::NotPermitted);
         }
 
-        // Represents each possible outcome from the spawn_blocking operation.
-        // This makes sure we don't have to give spawn_blocking any way to
-        // manipulate the table.
-        enum OpenResult {
-            Dir(cap_std::fs::Dir),
-            File(cap_std::fs::File),
-            NotDir,
-        }
-
         let opened = self
-            .run_blocking::<_, std::io::Result<OpenResult>>(move |d| {
-                let mut opened = d.open_with(&path, &opts)?;
-                if opened.metadata()?.is_dir() {
-                    Ok(OpenResult::Dir(cap_std::fs::Dir::from_std_file(
-                        opened.into_std(),
-                    )))
-                } else if oflags.contains(OpenFlags::DIRECTORY) {
-                    Ok(OpenResult::NotDir)
-                } else {
-                    // FIXME cap-std needs a nonblocking open option so that files reads and writes
-                    // are nonblocking. Instead we set it after opening here:
-                    let set_fd_flags = opened.new_set_fd_flags(FdFlags::NONBLOCK)?;
-                    opened.set_fd_flags(set_fd_flags)?;
-                    Ok(OpenResult::File(opened))
-                }
-            })
+            .run_blocking(move |d| d.open_at(&path, follow, &opts))
             .await?;
 
         match opened {
@@ -1049,11 +1099,9 @@ impl Dir {
             // POSIX returns EISDIR if you open a directory with the
             // WRITE flag: https://pubs.opengroup.org/onlinepubs/9699919799/functions/open.html#:~:text=EISDIR
             #[cfg(windows)]
-            OpenResult::Dir(_) if flags.contains(DescriptorFlags::WRITE) => {
-                Err(ErrorCode::IsDirectory)
-            }
+            Opened::Dir(_) if flags.contains(DescriptorFlags::WRITE) => Err(ErrorCode::IsDirectory),
 
-            OpenResult::Dir(dir) => Ok(Descriptor::Dir(Dir::new(
+            Opened::Dir(dir) => Ok(Descriptor::Dir(Dir::from_backend(
                 dir,
                 self.perms,
                 self.file_perms,
@@ -1061,14 +1109,16 @@ impl Dir {
                 allow_blocking_current_thread,
             ))),
 
-            OpenResult::File(file) => Ok(Descriptor::File(File::new(
+            Opened::File(_) if oflags.contains(OpenFlags::DIRECTORY) => {
+                Err(ErrorCode::NotDirectory)
+            }
+
+            Opened::File(file) => Ok(Descriptor::File(File::from_backend(
                 file,
                 self.file_perms,
                 open_mode,
                 allow_blocking_current_thread,
             ))),
-
-            OpenResult::NotDir => Err(ErrorCode::NotDirectory),
         }
     }
 
@@ -1076,7 +1126,7 @@ impl Dir {
         if !self.perms.contains(DirPerms::READ) {
             return Err(ErrorCode::NotPermitted);
         }
-        let link = self.run_blocking(move |d| d.read_link(&path)).await?;
+        let link = self.run_blocking(move |d| d.read_link_at(&path)).await?;
         link.into_os_string()
             .into_string()
             .or(Err(ErrorCode::IllegalByteSequence))
@@ -1086,7 +1136,7 @@ impl Dir {
         if !self.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted);
         }
-        self.run_blocking(move |d| d.remove_dir(&path)).await?;
+        self.run_blocking(move |d| d.remove_dir_at(&path)).await?;
         Ok(())
     }
 
@@ -1103,7 +1153,7 @@ impl Dir {
             return Err(ErrorCode::NotPermitted);
         }
         let new_dir_handle = Arc::clone(&new_dir.dir);
-        self.run_blocking(move |d| d.rename(&old_path, &new_dir_handle, &new_path))
+        self.run_blocking(move |d| d.rename_at(&old_path, &*new_dir_handle, &new_path))
             .await?;
         Ok(())
     }
@@ -1113,26 +1163,19 @@ impl Dir {
         src_path: String,
         dest_path: String,
     ) -> Result<(), ErrorCode> {
-        // On windows, Dir.symlink is provided by DirExt
-        #[cfg(windows)]
-        use cap_fs_ext::DirExt;
-
         if !self.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted);
         }
-        self.run_blocking(move |d| d.symlink(&src_path, &dest_path))
+        self.run_blocking(move |d| d.symlink_at(&src_path, &dest_path))
             .await?;
         Ok(())
     }
 
     pub(crate) async fn unlink_file_at(&self, path: String) -> Result<(), ErrorCode> {
-        use cap_fs_ext::DirExt;
-
         if !self.perms.contains(DirPerms::MUTATE) {
             return Err(ErrorCode::NotPermitted);
         }
-        self.run_blocking(move |d| d.remove_file_or_symlink(&path))
-            .await?;
+        self.run_blocking(move |d| d.remove_file_at(&path)).await?;
         Ok(())
     }
 
@@ -1142,14 +1185,9 @@ impl Dir {
         path: String,
     ) -> Result<MetadataHashValue, ErrorCode> {
         // No permissions check on metadata: if dir opened, allowed to stat it
+        let follow = path_flags.contains(PathFlags::SYMLINK_FOLLOW);
         let meta = self
-            .run_blocking(move |d| {
-                if path_flags.contains(PathFlags::SYMLINK_FOLLOW) {
-                    d.metadata(path)
-                } else {
-                    d.symlink_metadata(path)
-                }
-            })
+            .run_blocking(move |d| d.metadata_at(&path, follow))
             .await?;
         Ok(MetadataHashValue::from(&meta))
     }
@@ -0,0 +1,327 @@
+//! Read-only directories loaded from archives, see [`ArchiveDir`].
+
+use super::backend::{DirEntry, Metadata, OpenOptions, Opened, WasiDir, WasiFile};
+use super::memory::MemoryDir;
+use crate::SystemTimeSpec;
+use std::any::Any;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+
+/// A read-only directory holding the contents of an archive.
+///
+/// The archive is read in full when the directory is created, so the reader
+/// can be anything, for example an archive embedded in the host binary with
+/// [`include_bytes!`] wrapped in an [`io::Cursor`]. Regular files,
+/// directories, symbolic links and, for tar archives, hard links are loaded
+/// along with any parent directories which the archive doesn't list itself.
+/// Other kinds of entries, as well as permissions and timestamps, are
+/// ignored.
+///
+/// Guests can read everything in the directory, but anything which would
+/// modify it fails with [`io::ErrorKind::ReadOnlyFilesystem`] regardless of
+/// the permissions it was preopened with.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(feature = "tar")]
+/// # fn main() -> std::io::Result<()> {
+/// use wasmtime_wasi::filesystem::ArchiveDir;
+/// use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder};
+///
+/// let archive = std::fs::File::open("assets.tar")?;
+/// let dir = ArchiveDir::from_tar(archive)?;
+///
+/// let mut builder = WasiCtxBuilder::new();
+/// builder.preopened_virtual_dir(dir, "/assets", DirPerms::READ, FilePerms::READ);
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "tar"))]
+/// # fn main() {}
+/// ```
+#[derive(Clone)]
+pub struct ArchiveDir {
+    dir: Arc<dyn WasiDir>,
+}
+
+/// A file opened within an [`ArchiveDir`].
+struct ArchiveFile {
+    file: Arc<dyn WasiFile>,
+}
+
+fn read_only() -> io::Error {
+    io::ErrorKind::ReadOnlyFilesystem.into()
+}
+
+/// Returns `path`, taken from an archive, relative to the root of the archive
+/// and with `/` as its separator, or `None` if it refers to the root itself.
+fn entry_path(path: &Path) -> io::Result<Option<String>> {
+    let mut names = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => names.push(name.to_str().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("archive entry `{}` isn't valid UTF-8", path.display()),
+                )
+            })?),
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "archive entry `{}` refers to something outside of the archive",
+                        path.display()
+                    ),
+                ));
+            }
+        }
+    }
+    Ok((!names.is_empty()).then(|| names.join("/")))
+}
+
+/// Returns the parent of `path`, which uses `/` as its separator.
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+/// Adds a file or symbolic link at `path` to `root`, creating its parents if
+/// they don't exist yet.
+fn add_entry(
+    root: &MemoryDir,
+    path: &str,
+    add: impl FnOnce(&MemoryDir) -> io::Result<()>,
+) -> io::Result<()> {
+    root.create_dir_all(parent(path))?;
+    add(root)
+}
+
+impl ArchiveDir {
+    /// Loads the tar archive read from `reader`.
+    ///
+    /// Entries which appear more than once replace the earlier ones, as when
+    /// extracting the archive.
+    #[cfg(feature = "tar")]
+    pub fn from_tar(reader: impl io::Read) -> io::Result<ArchiveDir> {
+        let root = MemoryDir::new();
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let Some(path) = entry_path(&entry.path()?)? else {
+                continue;
+            };
+            let kind = entry.header().entry_type();
+            if kind.is_dir() {
+                root.create_dir_all(&path)?;
+            } else if kind.is_file() {
+                let mut contents = Vec::new();
+                io::Read::read_to_end(&mut entry, &mut contents)?;
+                add_entry(&root, &path, |root| root.write_file(&path, contents))?;
+            } else if kind.is_symlink() || kind.is_hard_link() {
+                let target = entry.link_name()?.ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("link `{path}` has no target"),
+                    )
+                })?;
+                add_entry(&root, &path, |root| {
+                    // Replace an earlier entry, since neither kind of link
+                    // can be created over an existing one.
+                    match root.remove_file_at(&path) {
+                        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                        _ => {}
+                    }
+                    if kind.is_symlink() {
+                        // Symbolic links are resolved within the directory,
+                        // so their targets are kept as they are.
+                        let target = target.to_str().ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("the target of `{path}` isn't valid UTF-8"),
+                            )
+                        })?;
+                        root.symlink_at(target, &path)
+                    } else {
+                        // Hard links refer to an earlier entry by its path
+                        // within the archive.
+                        let target = entry_path(&target)?
+                            .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidData))?;
+                        root.hard_link_at(&target, root, &path)
+                    }
+                })?;
+            }
+        }
+        Ok(ArchiveDir::new(root))
+    }
+
+    /// Loads the zip archive read from `reader`.
+    ///
+    /// Symbolic links are recognized by the Unix permissions stored for them,
+    /// as written by the `zip` command-line tool with `--symlinks`.
+    #[cfg(feature = "zip")]
+    pub fn from_zip(reader: impl io::Read + io::Seek) -> io::Result<ArchiveDir> {
+        const S_IFMT: u32 = 0o170000;
+        const S_IFLNK: u32 = 0o120000;
+
+        let root = MemoryDir::new();
+        let mut archive = zip::ZipArchive::new(reader)?;
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i)?;
+            let name = file.enclosed_name().map(Path::to_path_buf).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "archive entry `{}` refers to something outside of the archive",
+                        file.name()
+                    ),
+                )
+            })?;
+            let Some(path) = entry_path(&name)? else {
+                continue;
+            };
+            if file.is_dir() {
+                root.create_dir_all(&path)?;
+                continue;
+            }
+            let mut contents = Vec::new();
+            io::Read::read_to_end(&mut file, &mut contents)?;
+            if file
+                .unix_mode()
+                .is_some_and(|mode| mode & S_IFMT == S_IFLNK)
+            {
+                let target = String::from_utf8(contents).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("the target of `{path}` isn't valid UTF-8"),
+                    )
+                })?;
+                add_entry(&root, &path, |root| root.symlink_at(&target, &path))?;
+            } else {
+                add_entry(&root, &path, |root| root.write_file(&path, contents))?;
+            }
+        }
+        Ok(ArchiveDir::new(root))
+    }
+
+    fn new(dir: impl WasiDir) -> ArchiveDir {
+        ArchiveDir { dir: Arc::new(dir) }
+    }
+}
+
+impl WasiDir for ArchiveDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn dir_metadata(&self) -> io::Result<Metadata> {
+        self.dir.dir_metadata()
+    }
+
+    fn metadata_at(&self, path: &str, follow: bool) -> io::Result<Metadata> {
+        self.dir.metadata_at(path, follow)
+    }
+
+    fn open_at(&self, path: &str, follow: bool, options: &OpenOptions) -> io::Result<Opened> {
+        if options.write || options.create || options.create_new || options.truncate {
+            // Still report objects which don't exist, or which are
+            // directories, the same way as any other directory.
+            self.dir.metadata_at(path, follow)?;
+            return Err(read_only());
+        }
+        Ok(match self.dir.open_at(path, follow, options)? {
+            Opened::Dir(dir) => Opened::Dir(Arc::new(ArchiveDir { dir })),
+            Opened::File(file) => Opened::File(Arc::new(ArchiveFile { file })),
+        })
+    }
+
+    fn entries(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<DirEntry>> + Send>> {
+        self.dir.entries()
+    }
+
+    fn create_dir_at(&self, _path: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn remove_dir_at(&self, _path: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn remove_file_at(&self, _path: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn rename_at(
+        &self,
+        _old_path: &str,
+        _new_dir: &dyn WasiDir,
+        _new_path: &str,
+    ) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn hard_link_at(
+        &self,
+        _old_path: &str,
+        _new_dir: &dyn WasiDir,
+        _new_path: &str,
+    ) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn symlink_at(&self, _target: &str, _path: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<PathBuf> {
+        self.dir.read_link_at(path)
+    }
+
+    fn set_times_at(
+        &self,
+        _path: &str,
+        _follow: bool,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn set_times(
+        &self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        Err(read_only())
+    }
+}
+
+impl WasiFile for ArchiveFile {
+    fn metadata(&self) -> io::Result<Metadata> {
+        self.file.metadata()
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.file.read_at(buf, offset)
+    }
+
+    fn write_at(&self, _buf: &[u8], _offset: u64) -> io::Result<usize> {
+        Err(read_only())
+    }
+
+    fn append(&self, _buf: &[u8]) -> io::Result<usize> {
+        Err(read_only())
+    }
+
+    fn set_len(&self, _size: u64) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn set_times(
+        &self,
+        _atime: Option<SystemTimeSpec>,
+        _mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        Err(read_only())
+    }
+}
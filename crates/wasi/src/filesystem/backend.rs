@@ -0,0 +1,475 @@
+//! Backends which serve the contents of preopened directories, see
+//! [`WasiDir`] and [`WasiFile`].
+
+use crate::SystemTimeSpec;
+use cap_fs_ext::{FileTypeExt as _, MetadataExt as _};
+use std::any::Any;
+use std::ffi::OsString;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// A directory which can be preopened for, or opened by, a guest.
+///
+/// This is implemented for [`cap_std::fs::Dir`], which is what
+/// [`WasiCtxBuilder::preopened_dir`](crate::WasiCtxBuilder::preopened_dir)
+/// uses, and for [`MemoryDir`](super::MemoryDir). Other implementations, for
+/// example of read-only archives embedded in the host or of overlays of other
+/// directories, can be preopened with
+/// [`WasiCtxBuilder::preopened_virtual_dir`](crate::WasiCtxBuilder::preopened_virtual_dir)
+/// and are served the same way to WASIp1, WASIp2 and WASIp3 guests.
+///
+/// Paths given to the methods of this trait are relative to the directory and
+/// use `/` as their separator. Implementations must not let them refer to
+/// anything outside of the directory, whether through `..` components,
+/// absolute paths or symbolic links, and should fail with
+/// [`io::ErrorKind::PermissionDenied`] if they try to.
+///
+/// The permissions given when preopening a directory are checked before any
+/// method of this trait is called, so implementations don't need to enforce
+/// them. Errors are reported to guests based on their raw OS error code if
+/// they have one, and otherwise on their [`io::ErrorKind`].
+///
+/// All methods may block, and are called on a thread where that's fine unless
+/// [`WasiCtxBuilder::allow_blocking_current_thread`](crate::WasiCtxBuilder::allow_blocking_current_thread)
+/// is enabled.
+pub trait WasiDir: Send + Sync + 'static {
+    /// Returns `self` as [`Any`], so that methods which take another directory
+    /// can check whether it's of the same type.
+    fn as_any(&self) -> &dyn Any;
+
+    /// Returns the metadata of this directory itself.
+    fn dir_metadata(&self) -> io::Result<Metadata>;
+
+    /// Returns the metadata of the object at `path`, following a symbolic
+    /// link in its last component only if `follow` is set.
+    fn metadata_at(&self, path: &str, follow: bool) -> io::Result<Metadata>;
+
+    /// Opens the file or directory at `path`, following a symbolic link in its
+    /// last component only if `follow` is set.
+    ///
+    /// Opening a symbolic link without following it should fail, as should
+    /// opening a directory for writing.
+    fn open_at(&self, path: &str, follow: bool, options: &OpenOptions) -> io::Result<Opened>;
+
+    /// Returns the entries of this directory, excluding `.` and `..`.
+    fn entries(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<DirEntry>> + Send>>;
+
+    /// Creates a new, empty directory at `path`.
+    fn create_dir_at(&self, path: &str) -> io::Result<()>;
+
+    /// Removes the empty directory at `path`.
+    fn remove_dir_at(&self, path: &str) -> io::Result<()>;
+
+    /// Removes the file or symbolic link at `path`.
+    fn remove_file_at(&self, path: &str) -> io::Result<()>;
+
+    /// Renames the object at `old_path` in this directory to `new_path` in
+    /// `new_dir`, replacing whatever is already there if possible.
+    ///
+    /// Implementations which can't move objects to `new_dir`, for example
+    /// because it's of a different type, should fail with
+    /// [`io::ErrorKind::CrossesDevices`].
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()>;
+
+    /// Creates a hard link at `new_path` in `new_dir` to the file at
+    /// `old_path` in this directory, without following symbolic links.
+    ///
+    /// Like [`WasiDir::rename_at`], this should fail with
+    /// [`io::ErrorKind::CrossesDevices`] if `new_dir` isn't supported.
+    fn hard_link_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str)
+    -> io::Result<()>;
+
+    /// Creates a symbolic link at `path` which points to `target`.
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()>;
+
+    /// Returns the target of the symbolic link at `path`.
+    fn read_link_at(&self, path: &str) -> io::Result<PathBuf>;
+
+    /// Sets the timestamps of the object at `path`, following a symbolic link
+    /// in its last component only if `follow` is set. Timestamps which are
+    /// `None` are left unchanged.
+    fn set_times_at(
+        &self,
+        path: &str,
+        follow: bool,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()>;
+
+    /// Sets the timestamps of this directory itself.
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()>;
+
+    /// Synchronizes this directory, and its metadata, to storage.
+    fn sync_all(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Synchronizes the data of this directory to storage.
+    fn sync_data(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the host directory this is backed by, if any, which is used for
+    /// operations that only exist for host directories, such as reading their
+    /// descriptor flags.
+    fn as_host_dir(&self) -> Option<&cap_std::fs::Dir> {
+        None
+    }
+}
+
+/// A file which has been opened through a [`WasiDir`].
+///
+/// Guests can only read from, or write to, files at explicit offsets, so
+/// implementations don't have a current position.
+pub trait WasiFile: Send + Sync + 'static {
+    /// Returns the metadata of this file.
+    fn metadata(&self) -> io::Result<Metadata>;
+
+    /// Reads from this file at `offset` into `buf`, returning the number of
+    /// bytes read, which is zero at the end of the file.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
+
+    /// Writes `buf` to this file at `offset`, extending it if necessary, and
+    /// returns the number of bytes written.
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize>;
+
+    /// Writes `buf` to the end of this file and returns the number of bytes
+    /// written.
+    fn append(&self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Truncates or extends this file to `size` bytes.
+    fn set_len(&self, size: u64) -> io::Result<()>;
+
+    /// Sets the timestamps of this file. Timestamps which are `None` are left
+    /// unchanged.
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()>;
+
+    /// Synchronizes this file, and its metadata, to storage.
+    fn sync_all(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Synchronizes the data of this file to storage.
+    fn sync_data(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the host file this is backed by, if any, which is used for
+    /// operations that only exist for host files, such as advising the OS of
+    /// access patterns.
+    fn as_host_file(&self) -> Option<&cap_std::fs::File> {
+        None
+    }
+}
+
+/// How [`WasiDir::open_at`] opens a file.
+///
+/// Directories are opened if they're found at the path, in which case only
+/// `read` may be set.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct OpenOptions {
+    /// Open the file for reading.
+    pub read: bool,
+    /// Open the file for writing.
+    pub write: bool,
+    /// Create the file if it doesn't exist.
+    pub create: bool,
+    /// Create the file, failing if it already exists.
+    pub create_new: bool,
+    /// Truncate the file to zero bytes.
+    pub truncate: bool,
+}
+
+/// An object opened by [`WasiDir::open_at`].
+pub enum Opened {
+    /// A directory was opened.
+    Dir(Arc<dyn WasiDir>),
+    /// A file was opened.
+    File(Arc<dyn WasiFile>),
+}
+
+/// The type of a filesystem object.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileType {
+    /// The type is unknown or different from any of the other types.
+    Unknown,
+    /// A block device.
+    BlockDevice,
+    /// A character device.
+    CharacterDevice,
+    /// A directory.
+    Directory,
+    /// A symbolic link.
+    SymbolicLink,
+    /// A regular file.
+    RegularFile,
+}
+
+/// The metadata of a filesystem object.
+#[derive(Clone, Debug)]
+pub struct Metadata {
+    /// The type of the object.
+    pub file_type: FileType,
+    /// The number of hard links to the object.
+    pub link_count: u64,
+    /// For regular files, the size in bytes. For symbolic links, the length in
+    /// bytes of their target.
+    pub size: u64,
+    /// When the object's data was last accessed, if that's known.
+    pub accessed: Option<SystemTime>,
+    /// When the object's data was last modified, if that's known.
+    pub modified: Option<SystemTime>,
+    /// When the object's status was last changed, if that's known.
+    pub status_changed: Option<SystemTime>,
+    /// The device the object resides on.
+    ///
+    /// Together with `inode` this identifies the object, for example to tell
+    /// whether two descriptors refer to the same one.
+    pub device: u64,
+    /// The number of the object within its device.
+    pub inode: u64,
+}
+
+/// An entry returned by [`WasiDir::entries`].
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    /// The name of the entry.
+    pub name: OsString,
+    /// The type of the object the entry refers to, without following symbolic
+    /// links.
+    pub file_type: FileType,
+}
+
+impl From<cap_std::fs::FileType> for FileType {
+    fn from(ft: cap_std::fs::FileType) -> Self {
+        if ft.is_dir() {
+            FileType::Directory
+        } else if ft.is_symlink() {
+            FileType::SymbolicLink
+        } else if ft.is_block_device() {
+            FileType::BlockDevice
+        } else if ft.is_char_device() {
+            FileType::CharacterDevice
+        } else if ft.is_file() {
+            FileType::RegularFile
+        } else {
+            FileType::Unknown
+        }
+    }
+}
+
+impl From<cap_std::fs::Metadata> for Metadata {
+    fn from(meta: cap_std::fs::Metadata) -> Self {
+        Self {
+            file_type: meta.file_type().into(),
+            link_count: meta.nlink(),
+            size: meta.len(),
+            accessed: meta.accessed().map(|t| t.into_std()).ok(),
+            modified: meta.modified().map(|t| t.into_std()).ok(),
+            status_changed: meta.created().map(|t| t.into_std()).ok(),
+            device: meta.dev(),
+            inode: meta.ino(),
+        }
+    }
+}
+
+/// Converts a timestamp to the type used by `fs_set_times`.
+fn to_std(spec: SystemTimeSpec) -> fs_set_times::SystemTimeSpec {
+    match spec {
+        SystemTimeSpec::SymbolicNow => fs_set_times::SystemTimeSpec::SymbolicNow,
+        SystemTimeSpec::Absolute(t) => fs_set_times::SystemTimeSpec::Absolute(t.into_std()),
+    }
+}
+
+fn cross_device() -> io::Error {
+    io::Error::from(io::ErrorKind::CrossesDevices)
+}
+
+impl WasiDir for cap_std::fs::Dir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn dir_metadata(&self) -> io::Result<Metadata> {
+        Ok(cap_std::fs::Dir::dir_metadata(self)?.into())
+    }
+
+    fn metadata_at(&self, path: &str, follow: bool) -> io::Result<Metadata> {
+        let meta = if follow {
+            self.metadata(path)?
+        } else {
+            self.symlink_metadata(path)?
+        };
+        Ok(meta.into())
+    }
+
+    fn open_at(&self, path: &str, follow: bool, options: &OpenOptions) -> io::Result<Opened> {
+        use cap_fs_ext::{FollowSymlinks, OpenOptionsFollowExt, OpenOptionsMaybeDirExt};
+        use system_interface::fs::{FdFlags, GetSetFdFlags};
+
+        let mut opts = cap_std::fs::OpenOptions::new();
+        opts.maybe_dir(true);
+        opts.read(options.read);
+        opts.write(options.write);
+        opts.create(options.create);
+        opts.create_new(options.create_new);
+        opts.truncate(options.truncate);
+        if follow {
+            opts.follow(FollowSymlinks::Yes);
+        } else {
+            opts.follow(FollowSymlinks::No);
+        }
+
+        let mut opened = self.open_with(path, &opts)?;
+        if opened.metadata()?.is_dir() {
+            Ok(Opened::Dir(Arc::new(cap_std::fs::Dir::from_std_file(
+                opened.into_std(),
+            ))))
+        } else {
+            // FIXME cap-std needs a nonblocking open option so that files reads and writes
+            // are nonblocking. Instead we set it after opening here:
+            let set_fd_flags = opened.new_set_fd_flags(FdFlags::NONBLOCK)?;
+            opened.set_fd_flags(set_fd_flags)?;
+            Ok(Opened::File(Arc::new(opened)))
+        }
+    }
+
+    fn entries(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<DirEntry>> + Send>> {
+        let entries = cap_std::fs::Dir::entries(self)?.map(|entry| {
+            let entry = entry?;
+            let meta = entry.metadata()?;
+            Ok(DirEntry {
+                name: entry.file_name(),
+                file_type: meta.file_type().into(),
+            })
+        });
+        Ok(Box::new(entries))
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        self.create_dir(path)
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        self.remove_dir(path)
+    }
+
+    fn remove_file_at(&self, path: &str) -> io::Result<()> {
+        use cap_fs_ext::DirExt as _;
+        self.remove_file_or_symlink(path)
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        let new_dir = new_dir.as_host_dir().ok_or_else(cross_device)?;
+        self.rename(old_path, new_dir, new_path)
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        let new_dir = new_dir.as_host_dir().ok_or_else(cross_device)?;
+        self.hard_link(old_path, new_dir, new_path)
+    }
+
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()> {
+        // On windows, Dir.symlink is provided by DirExt
+        #[cfg(windows)]
+        use cap_fs_ext::DirExt;
+
+        self.symlink(target, path)
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<PathBuf> {
+        self.read_link(path)
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        follow: bool,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        use cap_fs_ext::DirExt as _;
+        if follow {
+            cap_fs_ext::DirExt::set_times(self, path, atime, mtime)
+        } else {
+            self.set_symlink_times(path, atime, mtime)
+        }
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        fs_set_times::SetTimes::set_times(self, atime.map(to_std), mtime.map(to_std))
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        self.open(std::path::Component::CurDir)?.sync_all()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.open(std::path::Component::CurDir)?.sync_data()
+    }
+
+    fn as_host_dir(&self) -> Option<&cap_std::fs::Dir> {
+        Some(self)
+    }
+}
+
+impl WasiFile for cap_std::fs::File {
+    fn metadata(&self) -> io::Result<Metadata> {
+        Ok(cap_std::fs::File::metadata(self)?.into())
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        system_interface::fs::FileIoExt::read_at(self, buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        system_interface::fs::FileIoExt::write_at(self, buf, offset)
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        system_interface::fs::FileIoExt::append(self, buf)
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        cap_std::fs::File::set_len(self, size)
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        fs_set_times::SetTimes::set_times(self, atime.map(to_std), mtime.map(to_std))
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        cap_std::fs::File::sync_all(self)
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        cap_std::fs::File::sync_data(self)
+    }
+
+    fn as_host_file(&self) -> Option<&cap_std::fs::File> {
+        Some(self)
+    }
+}
@@ -0,0 +1,765 @@
+//! An in-memory filesystem, see [`MemoryDir`].
+
+use super::backend::{DirEntry, FileType, Metadata, OpenOptions, Opened, WasiDir, WasiFile};
+use crate::SystemTimeSpec;
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::SystemTime;
+
+/// The maximum number of symbolic links followed while resolving a path,
+/// matching Linux's limit.
+const MAX_SYMLINK_EXPANSIONS: usize = 40;
+
+/// A directory of a filesystem which lives entirely in memory.
+///
+/// A new `MemoryDir` is the root of an empty filesystem, which can be filled
+/// in by the embedder, for example from the entries of an archive embedded in
+/// the host binary, and then preopened for guests with
+/// [`WasiCtxBuilder::preopened_virtual_dir`](crate::WasiCtxBuilder::preopened_virtual_dir).
+/// Preopening it with [`DirPerms::READ`](crate::DirPerms::READ) and
+/// [`FilePerms::READ`](crate::FilePerms::READ) only serves it read-only.
+///
+/// Cloning a `MemoryDir` returns another handle to the same directory, so the
+/// embedder can keep one to inspect what guests have done. Guests can't
+/// access anything outside of the directory they were given, and absolute
+/// symbolic links are treated as escaping it.
+///
+/// Guests can grow files as much as they like by default, up to the memory
+/// available to the host. [`MemoryDir::with_size_limit`] caps the total size
+/// of all files instead.
+///
+/// # Examples
+///
+/// ```
+/// use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder};
+/// use wasmtime_wasi::filesystem::MemoryDir;
+///
+/// # fn main() -> std::io::Result<()> {
+/// let root = MemoryDir::new();
+/// root.create_dir_all("etc")?;
+/// root.write_file("etc/motd", "hello from memory\n")?;
+///
+/// let mut builder = WasiCtxBuilder::new();
+/// builder.preopened_virtual_dir(root.clone(), "/", DirPerms::all(), FilePerms::all());
+/// let ctx = builder.build();
+///
+/// // ... run a guest with `ctx` ...
+///
+/// assert_eq!(root.read_file("etc/motd")?, b"hello from memory\n");
+/// # Ok(())
+/// # }
+/// ```
+pub struct MemoryDir {
+    fs: Arc<Mutex<Tree>>,
+    ino: usize,
+}
+
+/// A file of a filesystem which lives in memory, opened through a
+/// [`MemoryDir`].
+struct MemoryFile {
+    fs: Arc<Mutex<Tree>>,
+    ino: usize,
+}
+
+/// All inodes of an in-memory filesystem.
+struct Tree {
+    device: u64,
+    /// The total size in bytes of all files.
+    size: u64,
+    /// The maximum value of `size`.
+    size_limit: u64,
+    /// Inodes indexed by their number, which are `None` once they have been
+    /// freed. Numbers aren't reused.
+    inodes: Vec<Option<Inode>>,
+}
+
+struct Inode {
+    kind: Kind,
+    /// The number of directory entries which refer to this inode.
+    links: u64,
+    /// The number of `MemoryDir`s and `MemoryFile`s which refer to this inode.
+    handles: u64,
+    accessed: SystemTime,
+    modified: SystemTime,
+    changed: SystemTime,
+}
+
+enum Kind {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, usize>),
+    Symlink(String),
+}
+
+fn escape_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "a path led outside of the directory",
+    )
+}
+
+fn loop_error() -> io::Error {
+    #[cfg(unix)]
+    let code = rustix::io::Errno::LOOP.raw_os_error();
+    #[cfg(windows)]
+    let code = windows_sys::Win32::Foundation::ERROR_STOPPED_ON_SYMLINK as i32;
+    io::Error::from_raw_os_error(code)
+}
+
+fn system_time(spec: SystemTimeSpec) -> SystemTime {
+    match spec {
+        SystemTimeSpec::SymbolicNow => SystemTime::now(),
+        SystemTimeSpec::Absolute(t) => t.into_std(),
+    }
+}
+
+impl Tree {
+    fn inode(&self, ino: usize) -> &Inode {
+        self.inodes[ino].as_ref().expect("inode is in use")
+    }
+
+    fn inode_mut(&mut self, ino: usize) -> &mut Inode {
+        self.inodes[ino].as_mut().expect("inode is in use")
+    }
+
+    fn alloc(&mut self, kind: Kind) -> usize {
+        let now = SystemTime::now();
+        self.inodes.push(Some(Inode {
+            kind,
+            links: 0,
+            handles: 0,
+            accessed: now,
+            modified: now,
+            changed: now,
+        }));
+        self.inodes.len() - 1
+    }
+
+    /// Frees `ino` if nothing refers to it anymore.
+    fn release(&mut self, ino: usize) {
+        let inode = self.inode(ino);
+        if inode.links == 0 && inode.handles == 0 {
+            if let Kind::File(data) = &inode.kind {
+                self.size -= data.len() as u64;
+            }
+            self.inodes[ino] = None;
+        }
+    }
+
+    fn data(&self, ino: usize) -> &[u8] {
+        match &self.inode(ino).kind {
+            Kind::File(data) => data,
+            _ => unreachable!("only regular files are opened as files"),
+        }
+    }
+
+    fn data_mut(&mut self, ino: usize) -> &mut Vec<u8> {
+        match &mut self.inode_mut(ino).kind {
+            Kind::File(data) => data,
+            _ => unreachable!("only regular files are opened as files"),
+        }
+    }
+
+    /// Truncates or extends the file `ino` to `len` bytes.
+    ///
+    /// Extending the file fails, rather than aborting the process, if it would
+    /// take the filesystem over its size limit or if the memory for it can't
+    /// be allocated.
+    fn resize(&mut self, ino: usize, len: u64) -> io::Result<()> {
+        let old_len = self.data(ino).len() as u64;
+        if len <= old_len {
+            self.data_mut(ino).truncate(len as usize);
+            self.size -= old_len - len;
+            return Ok(());
+        }
+        let len = usize::try_from(len)
+            .ok()
+            .filter(|len| isize::try_from(*len).is_ok())
+            .ok_or(io::ErrorKind::FileTooLarge)?;
+        let additional = len - old_len as usize;
+        let size = self.size + additional as u64;
+        if size > self.size_limit {
+            return Err(io::ErrorKind::StorageFull.into());
+        }
+        let data = self.data_mut(ino);
+        data.try_reserve(additional)
+            .map_err(|_| io::ErrorKind::OutOfMemory)?;
+        data.resize(len, 0);
+        self.size = size;
+        Ok(())
+    }
+
+    fn entries(&self, ino: usize) -> io::Result<&BTreeMap<String, usize>> {
+        match &self.inode(ino).kind {
+            Kind::Dir(entries) => Ok(entries),
+            _ => Err(io::ErrorKind::NotADirectory.into()),
+        }
+    }
+
+    fn entries_mut(&mut self, ino: usize) -> io::Result<&mut BTreeMap<String, usize>> {
+        match &mut self.inode_mut(ino).kind {
+            Kind::Dir(entries) => Ok(entries),
+            _ => Err(io::ErrorKind::NotADirectory.into()),
+        }
+    }
+
+    fn touch(&mut self, ino: usize) {
+        let now = SystemTime::now();
+        let inode = self.inode_mut(ino);
+        inode.modified = now;
+        inode.changed = now;
+    }
+
+    fn link(&mut self, dir: usize, name: &str, ino: usize) -> io::Result<()> {
+        self.entries_mut(dir)?.insert(name.to_string(), ino);
+        self.inode_mut(ino).links += 1;
+        self.inode_mut(ino).changed = SystemTime::now();
+        self.touch(dir);
+        Ok(())
+    }
+
+    fn unlink(&mut self, dir: usize, name: &str) -> io::Result<()> {
+        let ino = self
+            .entries_mut(dir)?
+            .remove(name)
+            .ok_or(io::ErrorKind::NotFound)?;
+        self.inode_mut(ino).links -= 1;
+        self.inode_mut(ino).changed = SystemTime::now();
+        self.release(ino);
+        self.touch(dir);
+        Ok(())
+    }
+
+    /// Resolves `path` relative to the directory `dir`, following a symbolic
+    /// link in the last component only if `follow` is set.
+    fn resolve(&self, dir: usize, path: &str, follow: bool) -> io::Result<usize> {
+        if path.is_empty() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        if path.starts_with('/') {
+            return Err(escape_error());
+        }
+        // The directories leading to the current one, starting with `dir`,
+        // which `..` pops from.
+        let mut stack = vec![dir];
+        // The components left to resolve, in reverse order.
+        let mut pending = path
+            .split('/')
+            .rev()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let mut expansions = 0;
+        while let Some(component) = pending.pop() {
+            let cur = *stack.last().unwrap();
+            let entries = self.entries(cur)?;
+            match component.as_str() {
+                // Empty components come from repeated or trailing slashes,
+                // and require the current object to be a directory, which
+                // it is.
+                "" | "." => {}
+                ".." => {
+                    stack.pop();
+                    if stack.is_empty() {
+                        return Err(escape_error());
+                    }
+                }
+                name => {
+                    let child = *entries.get(name).ok_or(io::ErrorKind::NotFound)?;
+                    if let Kind::Symlink(target) = &self.inode(child).kind {
+                        if follow || !pending.is_empty() {
+                            expansions += 1;
+                            if expansions > MAX_SYMLINK_EXPANSIONS {
+                                return Err(loop_error());
+                            }
+                            if target.starts_with('/') {
+                                return Err(escape_error());
+                            }
+                            pending.extend(target.split('/').rev().map(str::to_string));
+                            continue;
+                        }
+                    }
+                    stack.push(child);
+                }
+            }
+        }
+        Ok(*stack.last().unwrap())
+    }
+
+    /// Resolves all but the last component of `path` relative to the
+    /// directory `dir`, returning the directory it refers to and the last
+    /// component.
+    fn resolve_parent<'a>(&self, dir: usize, path: &'a str) -> io::Result<(usize, &'a str)> {
+        let trimmed = path.trim_end_matches('/');
+        let (parent, name) = match trimmed.rsplit_once('/') {
+            Some(("", _)) => return Err(escape_error()),
+            Some((parent, name)) => (self.resolve(dir, parent, true)?, name),
+            None if trimmed.is_empty() && !path.is_empty() => return Err(escape_error()),
+            None if trimmed.is_empty() => return Err(io::ErrorKind::NotFound.into()),
+            None => (dir, trimmed),
+        };
+        self.entries(parent)?;
+        if name == "." || name == ".." {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        // Nothing can be created in directories which have been removed.
+        if self.inode(parent).links == 0 {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        Ok((parent, name))
+    }
+
+    /// Resolves the last component of `path` relative to `dir` without
+    /// following it, returning its parent directory, its name and its inode.
+    fn resolve_entry<'a>(&self, dir: usize, path: &'a str) -> io::Result<(usize, &'a str, usize)> {
+        let (parent, name) = self.resolve_parent(dir, path)?;
+        let ino = *self
+            .entries(parent)?
+            .get(name)
+            .ok_or(io::ErrorKind::NotFound)?;
+        Ok((parent, name, ino))
+    }
+
+    fn metadata(&self, ino: usize) -> Metadata {
+        let inode = self.inode(ino);
+        let (file_type, size) = match &inode.kind {
+            Kind::File(data) => (FileType::RegularFile, data.len()),
+            Kind::Dir(_) => (FileType::Directory, 0),
+            Kind::Symlink(target) => (FileType::SymbolicLink, target.len()),
+        };
+        Metadata {
+            file_type,
+            link_count: inode.links,
+            size: size as u64,
+            accessed: Some(inode.accessed),
+            modified: Some(inode.modified),
+            status_changed: Some(inode.changed),
+            device: self.device,
+            inode: ino as u64,
+        }
+    }
+
+    fn set_times(
+        &mut self,
+        ino: usize,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) {
+        let inode = self.inode_mut(ino);
+        if let Some(atime) = atime {
+            inode.accessed = system_time(atime);
+        }
+        if let Some(mtime) = mtime {
+            inode.modified = system_time(mtime);
+        }
+        inode.changed = SystemTime::now();
+    }
+
+    /// Returns whether `ino` is `dir` or any directory within it.
+    fn is_within(&self, ino: usize, dir: usize) -> bool {
+        ino == dir
+            || self
+                .entries(dir)
+                .map(|entries| entries.values().any(|child| self.is_within(ino, *child)))
+                .unwrap_or(false)
+    }
+}
+
+impl MemoryDir {
+    /// Creates the empty root directory of a new in-memory filesystem.
+    pub fn new() -> MemoryDir {
+        MemoryDir::with_size_limit(u64::MAX)
+    }
+
+    /// Creates the empty root directory of a new in-memory filesystem whose
+    /// files can hold at most `limit` bytes in total.
+    ///
+    /// Writes which would exceed the limit fail with
+    /// [`io::ErrorKind::StorageFull`], which guests see as
+    /// `insufficient-space`.
+    pub fn with_size_limit(limit: u64) -> MemoryDir {
+        // Devices are numbered from the top of the range, which is unlikely
+        // to be used by devices of the host.
+        static NEXT_DEVICE: AtomicU64 = AtomicU64::new(0);
+        let mut tree = Tree {
+            device: u64::MAX - NEXT_DEVICE.fetch_add(1, Ordering::Relaxed),
+            size: 0,
+            size_limit: limit,
+            inodes: Vec::new(),
+        };
+        let root = tree.alloc(Kind::Dir(BTreeMap::new()));
+        tree.inode_mut(root).links = 1;
+        tree.inode_mut(root).handles = 1;
+        MemoryDir {
+            fs: Arc::new(Mutex::new(tree)),
+            ino: root,
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Tree> {
+        lock(&self.fs)
+    }
+
+    /// Creates the directory at `path`, relative to this directory, along with
+    /// any of its parents which don't exist yet.
+    pub fn create_dir_all(&self, path: &str) -> io::Result<()> {
+        let mut fs = self.lock();
+        let mut dir = self.ino;
+        for name in path.split('/').filter(|c| !c.is_empty()) {
+            dir = match fs.resolve(dir, name, true) {
+                Ok(ino) => {
+                    fs.entries(ino)?;
+                    ino
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                    let (parent, name) = fs.resolve_parent(dir, name)?;
+                    let ino = fs.alloc(Kind::Dir(BTreeMap::new()));
+                    fs.link(parent, name, ino)?;
+                    ino
+                }
+                Err(e) => return Err(e),
+            };
+        }
+        Ok(())
+    }
+
+    /// Writes `contents` to the file at `path`, relative to this directory,
+    /// creating it if it doesn't exist and replacing its contents if it does.
+    ///
+    /// The file's parent directory must already exist.
+    pub fn write_file(&self, path: &str, contents: impl AsRef<[u8]>) -> io::Result<()> {
+        let mut fs = self.lock();
+        let ino = match fs.resolve(self.ino, path, true) {
+            Ok(ino) => ino,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let (parent, name) = fs.resolve_parent(self.ino, path)?;
+                let ino = fs.alloc(Kind::File(Vec::new()));
+                fs.link(parent, name, ino)?;
+                ino
+            }
+            Err(e) => return Err(e),
+        };
+        let contents = contents.as_ref();
+        match fs.inode(ino).kind {
+            Kind::File(_) => {
+                fs.resize(ino, 0)?;
+                fs.resize(ino, contents.len() as u64)?;
+                fs.data_mut(ino).copy_from_slice(contents);
+            }
+            _ => return Err(io::ErrorKind::IsADirectory.into()),
+        }
+        fs.touch(ino);
+        Ok(())
+    }
+
+    /// Returns the contents of the file at `path`, relative to this
+    /// directory.
+    pub fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        let fs = self.lock();
+        let ino = fs.resolve(self.ino, path, true)?;
+        match &fs.inode(ino).kind {
+            Kind::File(data) => Ok(data.clone()),
+            _ => Err(io::ErrorKind::IsADirectory.into()),
+        }
+    }
+
+    /// Returns another handle to the same filesystem, for the inode `ino`.
+    fn handle(&self, fs: &mut Tree, ino: usize) -> MemoryDir {
+        fs.inode_mut(ino).handles += 1;
+        MemoryDir {
+            fs: Arc::clone(&self.fs),
+            ino,
+        }
+    }
+
+    /// Returns the inode of `other` if it's a directory of the same
+    /// filesystem, which is required to move objects into it.
+    fn same_fs(&self, other: &dyn WasiDir) -> io::Result<usize> {
+        match other.as_any().downcast_ref::<MemoryDir>() {
+            Some(other) if Arc::ptr_eq(&self.fs, &other.fs) => Ok(other.ino),
+            _ => Err(io::ErrorKind::CrossesDevices.into()),
+        }
+    }
+}
+
+fn lock(fs: &Mutex<Tree>) -> MutexGuard<'_, Tree> {
+    // Operations on the tree never leave it in an inconsistent state when
+    // they panic, so it's fine to keep using it after that.
+    fs.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Default for MemoryDir {
+    fn default() -> MemoryDir {
+        MemoryDir::new()
+    }
+}
+
+impl Clone for MemoryDir {
+    fn clone(&self) -> MemoryDir {
+        let mut fs = self.lock();
+        self.handle(&mut fs, self.ino)
+    }
+}
+
+impl Drop for MemoryDir {
+    fn drop(&mut self) {
+        let mut fs = self.lock();
+        fs.inode_mut(self.ino).handles -= 1;
+        fs.release(self.ino);
+    }
+}
+
+impl Drop for MemoryFile {
+    fn drop(&mut self) {
+        let mut fs = lock(&self.fs);
+        fs.inode_mut(self.ino).handles -= 1;
+        fs.release(self.ino);
+    }
+}
+
+impl WasiDir for MemoryDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn dir_metadata(&self) -> io::Result<Metadata> {
+        Ok(self.lock().metadata(self.ino))
+    }
+
+    fn metadata_at(&self, path: &str, follow: bool) -> io::Result<Metadata> {
+        let fs = self.lock();
+        let ino = fs.resolve(self.ino, path, follow)?;
+        Ok(fs.metadata(ino))
+    }
+
+    fn open_at(&self, path: &str, follow: bool, options: &OpenOptions) -> io::Result<Opened> {
+        let mut fs = self.lock();
+        let ino = match fs.resolve(self.ino, path, follow) {
+            Ok(_) if options.create_new => return Err(io::ErrorKind::AlreadyExists.into()),
+            Ok(ino) => ino,
+            Err(e)
+                if e.kind() == io::ErrorKind::NotFound
+                    && (options.create || options.create_new) =>
+            {
+                let (parent, name) = fs.resolve_parent(self.ino, path)?;
+                // This is a dangling symbolic link, which isn't followed to
+                // create its target.
+                if fs.entries(parent)?.contains_key(name) {
+                    return Err(io::ErrorKind::AlreadyExists.into());
+                }
+                let ino = fs.alloc(Kind::File(Vec::new()));
+                fs.link(parent, name, ino)?;
+                ino
+            }
+            Err(e) => return Err(e),
+        };
+        match fs.inode(ino).kind {
+            Kind::Dir(_) if options.write || options.truncate => {
+                Err(io::ErrorKind::IsADirectory.into())
+            }
+            Kind::Dir(_) => Ok(Opened::Dir(Arc::new(self.handle(&mut fs, ino)))),
+            Kind::File(_) => {
+                if options.truncate {
+                    fs.resize(ino, 0)?;
+                    fs.touch(ino);
+                }
+                fs.inode_mut(ino).handles += 1;
+                Ok(Opened::File(Arc::new(MemoryFile {
+                    fs: Arc::clone(&self.fs),
+                    ino,
+                })))
+            }
+            Kind::Symlink(_) => Err(loop_error()),
+        }
+    }
+
+    fn entries(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<DirEntry>> + Send>> {
+        let fs = self.lock();
+        let entries = fs
+            .entries(self.ino)?
+            .iter()
+            .map(|(name, ino)| {
+                Ok(DirEntry {
+                    name: name.into(),
+                    file_type: fs.metadata(*ino).file_type,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        let mut fs = self.lock();
+        let (parent, name) = fs.resolve_parent(self.ino, path)?;
+        if fs.entries(parent)?.contains_key(name) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        let ino = fs.alloc(Kind::Dir(BTreeMap::new()));
+        fs.link(parent, name, ino)
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        let mut fs = self.lock();
+        let (parent, name, ino) = fs.resolve_entry(self.ino, path)?;
+        if !fs.entries(ino)?.is_empty() {
+            return Err(io::ErrorKind::DirectoryNotEmpty.into());
+        }
+        fs.unlink(parent, name)
+    }
+
+    fn remove_file_at(&self, path: &str) -> io::Result<()> {
+        let mut fs = self.lock();
+        let (parent, name, ino) = fs.resolve_entry(self.ino, path)?;
+        if let Kind::Dir(_) = fs.inode(ino).kind {
+            return Err(io::ErrorKind::IsADirectory.into());
+        }
+        fs.unlink(parent, name)
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        let new_dir = self.same_fs(new_dir)?;
+        let mut fs = self.lock();
+        let (old_parent, old_name, ino) = fs.resolve_entry(self.ino, old_path)?;
+        let (new_parent, new_name) = fs.resolve_parent(new_dir, new_path)?;
+        let is_dir = matches!(fs.inode(ino).kind, Kind::Dir(_));
+        if is_dir && fs.is_within(new_parent, ino) {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        if let Some(&existing) = fs.entries(new_parent)?.get(new_name) {
+            if existing == ino {
+                return Ok(());
+            }
+            match (is_dir, &fs.inode(existing).kind) {
+                (true, Kind::Dir(entries)) if !entries.is_empty() => {
+                    return Err(io::ErrorKind::DirectoryNotEmpty.into());
+                }
+                (true, Kind::Dir(_)) | (false, Kind::File(_) | Kind::Symlink(_)) => {}
+                (true, _) => return Err(io::ErrorKind::NotADirectory.into()),
+                (false, Kind::Dir(_)) => return Err(io::ErrorKind::IsADirectory.into()),
+            }
+            fs.unlink(new_parent, new_name)?;
+        }
+        fs.link(new_parent, new_name, ino)?;
+        fs.unlink(old_parent, old_name)
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        let new_dir = self.same_fs(new_dir)?;
+        let mut fs = self.lock();
+        let (_, _, ino) = fs.resolve_entry(self.ino, old_path)?;
+        if let Kind::Dir(_) = fs.inode(ino).kind {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        let (new_parent, new_name) = fs.resolve_parent(new_dir, new_path)?;
+        if fs.entries(new_parent)?.contains_key(new_name) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        fs.link(new_parent, new_name, ino)
+    }
+
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()> {
+        let mut fs = self.lock();
+        let (parent, name) = fs.resolve_parent(self.ino, path)?;
+        if fs.entries(parent)?.contains_key(name) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        let ino = fs.alloc(Kind::Symlink(target.to_string()));
+        fs.link(parent, name, ino)
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<PathBuf> {
+        let fs = self.lock();
+        let ino = fs.resolve(self.ino, path, false)?;
+        match &fs.inode(ino).kind {
+            Kind::Symlink(target) => Ok(target.into()),
+            _ => Err(io::ErrorKind::InvalidInput.into()),
+        }
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        follow: bool,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        let mut fs = self.lock();
+        let ino = fs.resolve(self.ino, path, follow)?;
+        fs.set_times(ino, atime, mtime);
+        Ok(())
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        self.lock().set_times(self.ino, atime, mtime);
+        Ok(())
+    }
+}
+
+impl MemoryFile {
+    fn write(&self, buf: &[u8], offset: Option<u64>) -> io::Result<usize> {
+        let mut fs = lock(&self.fs);
+        let start = offset.unwrap_or(fs.data(self.ino).len() as u64);
+        let end = start
+            .checked_add(buf.len() as u64)
+            .ok_or(io::ErrorKind::FileTooLarge)?;
+        if end > fs.data(self.ino).len() as u64 {
+            fs.resize(self.ino, end)?;
+        }
+        // Both fit in a `usize` now that the file is at least `end` bytes.
+        fs.data_mut(self.ino)[start as usize..end as usize].copy_from_slice(buf);
+        fs.touch(self.ino);
+        Ok(buf.len())
+    }
+}
+
+impl WasiFile for MemoryFile {
+    fn metadata(&self) -> io::Result<Metadata> {
+        Ok(lock(&self.fs).metadata(self.ino))
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let fs = lock(&self.fs);
+        let data = fs.data(self.ino);
+        let start = usize::try_from(offset)
+            .unwrap_or(usize::MAX)
+            .min(data.len());
+        let n = buf.len().min(data.len() - start);
+        buf[..n].copy_from_slice(&data[start..][..n]);
+        Ok(n)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.write(buf, Some(offset))
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        self.write(buf, None)
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        let mut fs = lock(&self.fs);
+        fs.resize(self.ino, size)?;
+        fs.touch(self.ino);
+        Ok(())
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        lock(&self.fs).set_times(self.ino, atime, mtime);
+        Ok(())
+    }
+}
@@ -0,0 +1,815 @@
+//! Copy-on-write overlays of directories, see [`OverlayDir`].
+
+use super::backend::{DirEntry, FileType, Metadata, OpenOptions, Opened, WasiDir};
+use super::memory::MemoryDir;
+use crate::SystemTimeSpec;
+use std::any::Any;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// The maximum number of symbolic links followed while resolving a path,
+/// matching Linux's limit.
+const MAX_SYMLINK_EXPANSIONS: usize = 40;
+
+/// A directory which presents a read-only lower directory with a writable
+/// upper directory on top of it.
+///
+/// Guests see the contents of both directories merged, with the upper one
+/// taking precedence. Anything the guest creates or modifies is written to the
+/// upper directory, and files of the lower directory are copied up to it in
+/// full before they're modified. Removing or renaming something which exists
+/// in the lower directory only hides it. The lower directory is never
+/// written to, so a host directory can be given to a guest which believes it
+/// has full access to it without the guest ever touching it.
+///
+/// Once a guest is done, [`OverlayDir::changes`] lists what it has done
+/// compared to the lower directory, and [`OverlayDir::export`] applies those
+/// changes to another directory.
+///
+/// Cloning an `OverlayDir` returns another handle to the same directory.
+/// Directories opened within an overlay refer to their path within it, so if
+/// a guest renames a directory while it has it open, its handle then refers to
+/// whatever is at the old path. Files opened from the lower directory for
+/// reading only don't see changes made by later copy-ups.
+///
+/// # Examples
+///
+/// ```no_run
+/// use wasmtime_wasi::filesystem::OverlayChange;
+/// use wasmtime_wasi::{DirPerms, FilePerms, WasiCtxBuilder};
+///
+/// # fn main() -> wasmtime::Result<()> {
+/// let mut builder = WasiCtxBuilder::new();
+/// let overlay = builder.preopened_overlay_dir("./src", "/src", DirPerms::all(), FilePerms::all())?;
+/// let ctx = builder.build();
+///
+/// // ... run a guest with `ctx` ...
+///
+/// for change in overlay.changes()? {
+///     match change {
+///         OverlayChange::Removed(path) => println!("removed {path}"),
+///         OverlayChange::Added(path, _) => println!("added {path}"),
+///         OverlayChange::Modified(path, _) => println!("modified {path}"),
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct OverlayDir {
+    overlay: Arc<Overlay>,
+    /// The path of this directory within the overlay, empty for its root.
+    path: String,
+}
+
+/// A change made to an [`OverlayDir`], as returned by
+/// [`OverlayDir::changes`].
+///
+/// Paths are relative to the root of the overlay and use `/` as their
+/// separator.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OverlayChange {
+    /// The object at this path of the lower directory was removed, including
+    /// everything within it if it's a directory.
+    ///
+    /// If a new object was created at the same path afterwards, it's listed
+    /// as added after this change.
+    Removed(String),
+    /// An object of the given type was created at this path, which doesn't
+    /// exist in the lower directory.
+    Added(String, FileType),
+    /// The file or symbolic link at this path of the lower directory was
+    /// modified, and is now of the given type.
+    Modified(String, FileType),
+}
+
+struct Overlay {
+    lower: Arc<dyn WasiDir>,
+    upper: Arc<dyn WasiDir>,
+    /// Paths below which nothing of the lower directory is visible, since
+    /// they've been removed or renamed. Operations hold this lock throughout
+    /// so that copy-ups and whiteouts appear atomic to guests.
+    whiteouts: Mutex<BTreeSet<String>>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Layer {
+    Upper,
+    Lower,
+}
+
+fn escape_error() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        "a path led outside of the directory",
+    )
+}
+
+fn loop_error() -> io::Error {
+    #[cfg(unix)]
+    let code = rustix::io::Errno::LOOP.raw_os_error();
+    #[cfg(windows)]
+    let code = windows_sys::Win32::Foundation::ERROR_STOPPED_ON_SYMLINK as i32;
+    io::Error::from_raw_os_error(code)
+}
+
+fn is_not_found(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+    )
+}
+
+/// Returns the path to give to the layers for `path`, which is empty for the
+/// root of the overlay.
+fn layer_path(path: &str) -> &str {
+    if path.is_empty() { "." } else { path }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{dir}/{name}")
+    }
+}
+
+fn parent(path: &str) -> &str {
+    path.rsplit_once('/')
+        .map(|(parent, _)| parent)
+        .unwrap_or("")
+}
+
+/// Returns `path` and all of its ancestors other than the root, starting with
+/// the outermost one.
+fn ancestors(path: &str) -> impl Iterator<Item = &str> {
+    path.match_indices('/')
+        .map(|(i, _)| &path[..i])
+        .chain((!path.is_empty()).then_some(path))
+}
+
+/// The options to open directories of the layers with, for reading their
+/// entries.
+const READ: OpenOptions = OpenOptions {
+    read: true,
+    write: false,
+    create: false,
+    create_new: false,
+    truncate: false,
+};
+
+/// Copies the file at `path` in `from` to the same path in `to`, along with
+/// its timestamps.
+fn copy_file(from: &dyn WasiDir, to: &dyn WasiDir, path: &str, meta: &Metadata) -> io::Result<()> {
+    let write = OpenOptions {
+        write: true,
+        create: true,
+        truncate: true,
+        ..OpenOptions::default()
+    };
+    let Opened::File(src) = from.open_at(path, false, &READ)? else {
+        return Err(io::ErrorKind::IsADirectory.into());
+    };
+    let Opened::File(dst) = to.open_at(path, false, &write)? else {
+        return Err(io::ErrorKind::IsADirectory.into());
+    };
+    let mut buf = vec![0; 64 * 1024];
+    let mut offset = 0;
+    loop {
+        let n = src.read_at(&mut buf, offset)?;
+        if n == 0 {
+            break;
+        }
+        let mut written = 0;
+        while written < n {
+            match dst.write_at(&buf[written..n], offset + written as u64)? {
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                m => written += m,
+            }
+        }
+        offset += n as u64;
+    }
+    let timestamp = |t| SystemTimeSpec::Absolute(cap_std::time::SystemTime::from_std(t));
+    dst.set_times(meta.accessed.map(timestamp), meta.modified.map(timestamp))
+}
+
+/// Removes whatever is at `path` in `dir`, including everything within it if
+/// it's a directory.
+fn remove_all(dir: &dyn WasiDir, path: &str) -> io::Result<()> {
+    let meta = match dir.metadata_at(path, false) {
+        Ok(meta) => meta,
+        Err(e) if is_not_found(&e) => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    if meta.file_type != FileType::Directory {
+        return dir.remove_file_at(path);
+    }
+    let Opened::Dir(sub) = dir.open_at(path, false, &READ)? else {
+        return Err(io::ErrorKind::NotADirectory.into());
+    };
+    for entry in sub.entries()? {
+        let name = entry?.name;
+        let name = name.to_str().ok_or(io::ErrorKind::InvalidData)?;
+        remove_all(dir, &join(path, name))?;
+    }
+    dir.remove_dir_at(path)
+}
+
+impl Overlay {
+    fn lock(&self) -> MutexGuard<'_, BTreeSet<String>> {
+        self.whiteouts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn layer(&self, layer: Layer) -> &dyn WasiDir {
+        match layer {
+            Layer::Upper => &*self.upper,
+            Layer::Lower => &*self.lower,
+        }
+    }
+
+    /// Returns whether the lower directory has something visible at `path`.
+    fn in_lower(&self, whiteouts: &BTreeSet<String>, path: &str) -> io::Result<bool> {
+        if ancestors(path).any(|p| whiteouts.contains(p)) {
+            return Ok(false);
+        }
+        match self.lower.metadata_at(layer_path(path), false) {
+            Ok(_) => Ok(true),
+            Err(e) if is_not_found(&e) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the layer the object at `path` is found in, and its metadata,
+    /// without following symbolic links.
+    fn stat(
+        &self,
+        whiteouts: &BTreeSet<String>,
+        path: &str,
+    ) -> io::Result<Option<(Layer, Metadata)>> {
+        match self.upper.metadata_at(layer_path(path), false) {
+            Ok(meta) => return Ok(Some((Layer::Upper, meta))),
+            Err(e) if is_not_found(&e) => {}
+            Err(e) => return Err(e),
+        }
+        if ancestors(path).any(|p| whiteouts.contains(p)) {
+            return Ok(None);
+        }
+        match self.lower.metadata_at(layer_path(path), false) {
+            Ok(meta) => Ok(Some((Layer::Lower, meta))),
+            Err(e) if is_not_found(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Resolves `path` relative to the directory at `base` to a path within
+    /// the overlay without any symbolic links, or `..` or `.` components,
+    /// following a symbolic link in the last component only if `follow` is
+    /// set.
+    ///
+    /// The object at the returned path may not exist, but its parent
+    /// directory does.
+    fn resolve(
+        &self,
+        whiteouts: &BTreeSet<String>,
+        base: &str,
+        path: &str,
+        follow: bool,
+    ) -> io::Result<String> {
+        if path.is_empty() {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+        if path.starts_with('/') {
+            return Err(escape_error());
+        }
+        let mut stack = base
+            .split('/')
+            .filter(|c| !c.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        // `..` can't lead above `base`.
+        let floor = stack.len();
+        // The components left to resolve, in reverse order.
+        let mut pending = path
+            .split('/')
+            .rev()
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        let mut expansions = 0;
+        while let Some(component) = pending.pop() {
+            match component.as_str() {
+                "" | "." => {}
+                ".." => {
+                    if stack.len() == floor {
+                        return Err(escape_error());
+                    }
+                    stack.pop();
+                }
+                name => {
+                    let child = join(&stack.join("/"), name);
+                    // Only empty components, from trailing slashes, and `.`
+                    // may follow the last component.
+                    let last = pending.iter().all(|c| c.is_empty() || c == ".");
+                    match self.stat(whiteouts, &child)? {
+                        None if last => {}
+                        None => return Err(io::ErrorKind::NotFound.into()),
+                        Some((layer, meta)) => match meta.file_type {
+                            FileType::Directory => {}
+                            FileType::SymbolicLink if follow || !pending.is_empty() => {
+                                expansions += 1;
+                                if expansions > MAX_SYMLINK_EXPANSIONS {
+                                    return Err(loop_error());
+                                }
+                                let target = self.layer(layer).read_link_at(&child)?;
+                                let target = target
+                                    .into_os_string()
+                                    .into_string()
+                                    .map_err(|_| io::ErrorKind::InvalidData)?;
+                                if target.starts_with('/') {
+                                    return Err(escape_error());
+                                }
+                                pending.extend(target.split('/').rev().map(str::to_string));
+                                continue;
+                            }
+                            _ if !pending.is_empty() => {
+                                return Err(io::ErrorKind::NotADirectory.into());
+                            }
+                            _ => {}
+                        },
+                    }
+                    stack.push(name.to_string());
+                }
+            }
+        }
+        Ok(stack.join("/"))
+    }
+
+    /// Returns the merged entries of the directory at `path`.
+    fn entries(
+        &self,
+        whiteouts: &BTreeSet<String>,
+        path: &str,
+    ) -> io::Result<BTreeMap<String, FileType>> {
+        let mut entries = BTreeMap::new();
+        let Some((layer, meta)) = self.stat(whiteouts, path)? else {
+            return Err(io::ErrorKind::NotFound.into());
+        };
+        if meta.file_type != FileType::Directory {
+            return Err(io::ErrorKind::NotADirectory.into());
+        }
+        let mut layers = vec![layer];
+        if layer == Layer::Upper && self.in_lower(whiteouts, path)? {
+            layers.push(Layer::Lower);
+        }
+        for layer in layers {
+            let Opened::Dir(dir) = self.layer(layer).open_at(layer_path(path), false, &READ)?
+            else {
+                return Err(io::ErrorKind::NotADirectory.into());
+            };
+            for entry in dir.entries()? {
+                let DirEntry { name, file_type } = entry?;
+                let name = name.into_string().map_err(|_| io::ErrorKind::InvalidData)?;
+                if layer == Layer::Lower && whiteouts.contains(&join(path, &name)) {
+                    continue;
+                }
+                entries.entry(name).or_insert(file_type);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Creates all directories leading to, and including, `path` in the upper
+    /// directory which don't exist there yet.
+    fn copy_up_dirs(&self, path: &str) -> io::Result<()> {
+        for dir in ancestors(path) {
+            match self.upper.metadata_at(dir, false) {
+                Ok(_) => {}
+                Err(e) if is_not_found(&e) => self.upper.create_dir_at(dir)?,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the object at `path` to the upper directory if it's only in the
+    /// lower one. Only the directory itself is copied for directories.
+    fn copy_up(&self, whiteouts: &BTreeSet<String>, path: &str) -> io::Result<()> {
+        let Some((layer, meta)) = self.stat(whiteouts, path)? else {
+            return Err(io::ErrorKind::NotFound.into());
+        };
+        if layer == Layer::Upper {
+            return Ok(());
+        }
+        self.copy_up_dirs(parent(path))?;
+        match meta.file_type {
+            FileType::Directory => self.upper.create_dir_at(path),
+            FileType::SymbolicLink => {
+                let target = self.lower.read_link_at(path)?;
+                let target = target.to_str().ok_or(io::ErrorKind::InvalidData)?;
+                self.upper.symlink_at(target, path)
+            }
+            _ => copy_file(&*self.lower, &*self.upper, path, &meta),
+        }
+    }
+
+    /// Copies the object at `path` to the upper directory, along with
+    /// everything within it if it's a directory.
+    fn copy_up_all(&self, whiteouts: &BTreeSet<String>, path: &str) -> io::Result<()> {
+        self.copy_up(whiteouts, path)?;
+        if self.upper.metadata_at(path, false)?.file_type == FileType::Directory {
+            for name in self.entries(whiteouts, path)?.into_keys() {
+                self.copy_up_all(whiteouts, &join(path, &name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the changes within the directory at `path` of the upper
+    /// directory to `changes`.
+    fn changes(
+        &self,
+        whiteouts: &BTreeSet<String>,
+        path: &str,
+        changes: &mut Vec<OverlayChange>,
+    ) -> io::Result<()> {
+        let Opened::Dir(dir) = self.upper.open_at(layer_path(path), false, &READ)? else {
+            return Err(io::ErrorKind::NotADirectory.into());
+        };
+        let mut entries = dir
+            .entries()?
+            .map(|entry| {
+                let DirEntry { name, file_type } = entry?;
+                let name = name.into_string().map_err(|_| io::ErrorKind::InvalidData)?;
+                Ok((join(path, &name), file_type))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for (path, file_type) in entries {
+            let lower = match self.in_lower(whiteouts, &path)? {
+                true => Some(self.lower.metadata_at(&path, false)?.file_type),
+                false => None,
+            };
+            match (file_type, lower) {
+                // Directories which were only copied up to hold changes
+                // within them aren't changes themselves.
+                (FileType::Directory, Some(FileType::Directory)) => {}
+                (_, Some(_)) => changes.push(OverlayChange::Modified(path.clone(), file_type)),
+                (_, None) => changes.push(OverlayChange::Added(path.clone(), file_type)),
+            }
+            if file_type == FileType::Directory {
+                self.changes(whiteouts, &path, changes)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OverlayDir {
+    /// Creates an overlay which presents `lower` with `upper` on top of it.
+    ///
+    /// `lower` is only ever read from. `upper` should start out empty, and is
+    /// where all changes made through the overlay are written to. It can be a
+    /// [`MemoryDir`], or for example a scratch directory of the host if
+    /// changes are expected to be large.
+    pub fn new(lower: impl WasiDir, upper: impl WasiDir) -> OverlayDir {
+        OverlayDir {
+            overlay: Arc::new(Overlay {
+                lower: Arc::new(lower),
+                upper: Arc::new(upper),
+                whiteouts: Mutex::new(BTreeSet::new()),
+            }),
+            path: String::new(),
+        }
+    }
+
+    /// Creates an overlay which presents `lower` with a new, empty
+    /// [`MemoryDir`] on top of it.
+    pub fn in_memory(lower: impl WasiDir) -> OverlayDir {
+        OverlayDir::new(lower, MemoryDir::new())
+    }
+
+    /// Returns the directory all changes made through this overlay are
+    /// written to.
+    pub fn upper(&self) -> &Arc<dyn WasiDir> {
+        &self.overlay.upper
+    }
+
+    /// Returns the changes made through this overlay compared to its lower
+    /// directory.
+    ///
+    /// Removals are listed first, followed by additions and modifications in
+    /// an order where directories come before their contents. The contents
+    /// of added and modified files can be read from the same paths of
+    /// [`OverlayDir::upper`].
+    pub fn changes(&self) -> io::Result<Vec<OverlayChange>> {
+        let overlay = &self.overlay;
+        let whiteouts = overlay.lock();
+        let mut changes = Vec::new();
+        for path in whiteouts.iter() {
+            // Objects within removed directories aren't listed separately.
+            if ancestors(parent(path)).any(|p| whiteouts.contains(p)) {
+                continue;
+            }
+            changes.push(OverlayChange::Removed(path.clone()));
+        }
+        overlay.changes(&whiteouts, "", &mut changes)?;
+        Ok(changes)
+    }
+
+    /// Applies the changes made through this overlay, as returned by
+    /// [`OverlayDir::changes`], to `target`.
+    ///
+    /// This can be used to commit changes made by a guest to a copy of the
+    /// lower directory, or to the lower directory itself.
+    pub fn export(&self, target: &dyn WasiDir) -> io::Result<()> {
+        let upper = &*self.overlay.upper;
+        for change in self.changes()? {
+            match change {
+                OverlayChange::Removed(path) => remove_all(target, &path)?,
+                OverlayChange::Added(path, file_type)
+                | OverlayChange::Modified(path, file_type) => match file_type {
+                    FileType::Directory => match target.metadata_at(&path, false) {
+                        Ok(meta) if meta.file_type == FileType::Directory => {}
+                        _ => {
+                            remove_all(target, &path)?;
+                            target.create_dir_at(&path)?;
+                        }
+                    },
+                    FileType::SymbolicLink => {
+                        remove_all(target, &path)?;
+                        let link = upper.read_link_at(&path)?;
+                        let link = link.to_str().ok_or(io::ErrorKind::InvalidData)?;
+                        target.symlink_at(link, &path)?;
+                    }
+                    _ => {
+                        if let Ok(meta) = target.metadata_at(&path, false) {
+                            if meta.file_type != FileType::RegularFile {
+                                remove_all(target, &path)?;
+                            }
+                        }
+                        let meta = upper.metadata_at(&path, false)?;
+                        copy_file(upper, target, &path, &meta)?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the path of `other` within this overlay if it's a directory of
+    /// the same overlay, which is required to move objects into it.
+    fn same_overlay<'a>(&self, other: &'a dyn WasiDir) -> io::Result<&'a str> {
+        match other.as_any().downcast_ref::<OverlayDir>() {
+            Some(other) if Arc::ptr_eq(&self.overlay, &other.overlay) => Ok(&other.path),
+            _ => Err(io::ErrorKind::CrossesDevices.into()),
+        }
+    }
+
+    /// Resolves `path` and returns the object it refers to, failing if it
+    /// doesn't exist.
+    fn existing(
+        &self,
+        whiteouts: &BTreeSet<String>,
+        path: &str,
+        follow: bool,
+    ) -> io::Result<(String, Layer, Metadata)> {
+        let path = self.overlay.resolve(whiteouts, &self.path, path, follow)?;
+        match self.overlay.stat(whiteouts, &path)? {
+            Some((layer, meta)) => Ok((path, layer, meta)),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    /// Resolves `path` to an object which is about to be created, failing if
+    /// it already exists.
+    fn new_path(&self, whiteouts: &BTreeSet<String>, path: &str) -> io::Result<String> {
+        let path = self.overlay.resolve(whiteouts, &self.path, path, false)?;
+        match self.overlay.stat(whiteouts, &path)? {
+            Some(_) => Err(io::ErrorKind::AlreadyExists.into()),
+            None => Ok(path),
+        }
+    }
+}
+
+impl WasiDir for OverlayDir {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn dir_metadata(&self) -> io::Result<Metadata> {
+        let whiteouts = self.overlay.lock();
+        match self.overlay.stat(&whiteouts, &self.path)? {
+            Some((_, meta)) => Ok(meta),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn metadata_at(&self, path: &str, follow: bool) -> io::Result<Metadata> {
+        let whiteouts = self.overlay.lock();
+        let (_, _, meta) = self.existing(&whiteouts, path, follow)?;
+        Ok(meta)
+    }
+
+    fn open_at(&self, path: &str, follow: bool, options: &OpenOptions) -> io::Result<Opened> {
+        let overlay = &self.overlay;
+        let whiteouts = overlay.lock();
+        let path = overlay.resolve(&whiteouts, &self.path, path, follow)?;
+        let (layer, meta) = match overlay.stat(&whiteouts, &path)? {
+            Some(_) if options.create_new => return Err(io::ErrorKind::AlreadyExists.into()),
+            Some(found) => found,
+            None if options.create || options.create_new => {
+                overlay.copy_up_dirs(parent(&path))?;
+                return overlay.upper.open_at(&path, false, options);
+            }
+            None => return Err(io::ErrorKind::NotFound.into()),
+        };
+        match meta.file_type {
+            // Directories are always opened as part of the overlay so that
+            // their contents are merged as well.
+            FileType::Directory if options.write || options.truncate => {
+                Err(io::ErrorKind::IsADirectory.into())
+            }
+            FileType::Directory => Ok(Opened::Dir(Arc::new(OverlayDir {
+                overlay: Arc::clone(overlay),
+                path,
+            }))),
+            _ if layer == Layer::Lower && (options.write || options.truncate) => {
+                overlay.copy_up(&whiteouts, &path)?;
+                overlay.upper.open_at(&path, false, options)
+            }
+            _ => overlay
+                .layer(layer)
+                .open_at(layer_path(&path), false, options),
+        }
+    }
+
+    fn entries(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<DirEntry>> + Send>> {
+        let whiteouts = self.overlay.lock();
+        let entries = self
+            .overlay
+            .entries(&whiteouts, &self.path)?
+            .into_iter()
+            .map(|(name, file_type)| {
+                Ok(DirEntry {
+                    name: name.into(),
+                    file_type,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        let overlay = &self.overlay;
+        let whiteouts = overlay.lock();
+        let path = self.new_path(&whiteouts, path)?;
+        overlay.copy_up_dirs(parent(&path))?;
+        overlay.upper.create_dir_at(&path)
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        let overlay = &self.overlay;
+        let mut whiteouts = overlay.lock();
+        let (path, layer, meta) = self.existing(&whiteouts, path, false)?;
+        if meta.file_type != FileType::Directory {
+            return Err(io::ErrorKind::NotADirectory.into());
+        }
+        if path.is_empty() || path == self.path {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        if !overlay.entries(&whiteouts, &path)?.is_empty() {
+            return Err(io::ErrorKind::DirectoryNotEmpty.into());
+        }
+        if layer == Layer::Upper {
+            overlay.upper.remove_dir_at(&path)?;
+        }
+        if overlay.in_lower(&whiteouts, &path)? {
+            whiteouts.insert(path);
+        }
+        Ok(())
+    }
+
+    fn remove_file_at(&self, path: &str) -> io::Result<()> {
+        let overlay = &self.overlay;
+        let mut whiteouts = overlay.lock();
+        let (path, layer, meta) = self.existing(&whiteouts, path, false)?;
+        if meta.file_type == FileType::Directory {
+            return Err(io::ErrorKind::IsADirectory.into());
+        }
+        if layer == Layer::Upper {
+            overlay.upper.remove_file_at(&path)?;
+        }
+        if overlay.in_lower(&whiteouts, &path)? {
+            whiteouts.insert(path);
+        }
+        Ok(())
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        let new_base = self.same_overlay(new_dir)?;
+        let overlay = &self.overlay;
+        let mut whiteouts = overlay.lock();
+        let (old_path, _, meta) = self.existing(&whiteouts, old_path, false)?;
+        let new_path = overlay.resolve(&whiteouts, new_base, new_path, false)?;
+        if old_path.is_empty() || new_path.is_empty() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        if old_path == new_path {
+            return Ok(());
+        }
+        let is_dir = meta.file_type == FileType::Directory;
+        if is_dir && new_path.starts_with(&format!("{old_path}/")) {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        if let Some((_, existing)) = overlay.stat(&whiteouts, &new_path)? {
+            match (is_dir, existing.file_type == FileType::Directory) {
+                (true, true) => {
+                    if !overlay.entries(&whiteouts, &new_path)?.is_empty() {
+                        return Err(io::ErrorKind::DirectoryNotEmpty.into());
+                    }
+                    // The upper directory may not have it yet for the rename
+                    // below to replace it.
+                    overlay.copy_up(&whiteouts, &new_path)?;
+                }
+                (true, false) => return Err(io::ErrorKind::NotADirectory.into()),
+                (false, true) => return Err(io::ErrorKind::IsADirectory.into()),
+                (false, false) => {}
+            }
+        }
+        // Everything being moved has to be in the upper directory, since
+        // it's only hidden in the lower one.
+        overlay.copy_up_all(&whiteouts, &old_path)?;
+        overlay.copy_up_dirs(parent(&new_path))?;
+        overlay
+            .upper
+            .rename_at(&old_path, &*overlay.upper, &new_path)?;
+        if overlay.in_lower(&whiteouts, &old_path)? {
+            whiteouts.insert(old_path);
+        }
+        Ok(())
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        let new_base = self.same_overlay(new_dir)?;
+        let overlay = &self.overlay;
+        let whiteouts = overlay.lock();
+        let (old_path, _, meta) = self.existing(&whiteouts, old_path, false)?;
+        if meta.file_type == FileType::Directory {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        let new_path = overlay.resolve(&whiteouts, new_base, new_path, false)?;
+        if new_path.is_empty() || overlay.stat(&whiteouts, &new_path)?.is_some() {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        overlay.copy_up(&whiteouts, &old_path)?;
+        overlay.copy_up_dirs(parent(&new_path))?;
+        overlay
+            .upper
+            .hard_link_at(&old_path, &*overlay.upper, &new_path)
+    }
+
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()> {
+        let overlay = &self.overlay;
+        let whiteouts = overlay.lock();
+        let path = self.new_path(&whiteouts, path)?;
+        overlay.copy_up_dirs(parent(&path))?;
+        overlay.upper.symlink_at(target, &path)
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<PathBuf> {
+        let whiteouts = self.overlay.lock();
+        let (path, layer, _) = self.existing(&whiteouts, path, false)?;
+        self.overlay.layer(layer).read_link_at(layer_path(&path))
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        follow: bool,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        let overlay = &self.overlay;
+        let whiteouts = overlay.lock();
+        let (path, _, _) = self.existing(&whiteouts, path, follow)?;
+        if path.is_empty() {
+            return overlay.upper.set_times(atime, mtime);
+        }
+        overlay.copy_up(&whiteouts, &path)?;
+        overlay.upper.set_times_at(&path, false, atime, mtime)
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        self.set_times_at(".", false, atime, mtime)
+    }
+}
@@ -0,0 +1,502 @@
+//! Quotas on, and accounting of, what guests do through preopened
+//! directories.
+//!
+//! Every preopened directory of a [`WasiCtx`](crate::WasiCtx) is wrapped in
+//! an [`AccountedDir`] when the context is built, and everything opened
+//! through it is wrapped in turn. All of those share the context's
+//! [`Accounting`], which counts what goes through them and rejects operations
+//! which would exceed the limits configured on the
+//! [`WasiCtxBuilder`](crate::WasiCtxBuilder). Since WASIp1, WASIp2 and WASIp3
+//! all access files through these wrappers, the same limits apply to all of
+//! them.
+
+use super::backend::{DirEntry, Metadata, OpenOptions, Opened, WasiDir, WasiFile};
+use crate::SystemTimeSpec;
+use std::any::Any;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Limits on what guests can do through the preopened directories of a
+/// [`WasiCtx`](crate::WasiCtx), all of which are unlimited by default.
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct FilesystemLimits {
+    pub(crate) bytes_written: Option<u64>,
+    pub(crate) files: Option<u64>,
+    pub(crate) open_descriptors: Option<u64>,
+}
+
+/// Statistics about what a guest has done through the preopened directories
+/// of a [`WasiCtx`](crate::WasiCtx), as returned by
+/// [`WasiFilesystemCtx::usage`](super::WasiFilesystemCtx::usage).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct FilesystemUsage {
+    /// The number of bytes read from files.
+    pub bytes_read: u64,
+    /// The number of bytes written to files, including the size of any zeros
+    /// files were extended with by setting their size.
+    pub bytes_written: u64,
+    /// The number of files, directories and symbolic links created, less the
+    /// number of those which were removed again. Removing objects which the
+    /// guest didn't create doesn't count.
+    pub files: u64,
+    /// The number of files and directories which are currently open, not
+    /// counting preopened directories.
+    pub open_descriptors: u64,
+}
+
+/// The counters shared by all accounted directories and files of a context.
+#[derive(Default)]
+pub(crate) struct Accounting {
+    limits: FilesystemLimits,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+    files: AtomicU64,
+    open_descriptors: AtomicU64,
+    /// The objects counted in `files`, by device and inode, along with how
+    /// many links to each were created.
+    created: Mutex<HashMap<(u64, u64), u64>>,
+}
+
+/// Identifies the object `meta` describes.
+fn object(meta: &Metadata) -> (u64, u64) {
+    (meta.device, meta.inode)
+}
+
+fn quota_error(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::QuotaExceeded,
+        format!("the guest's quota of {what} has been exceeded"),
+    )
+}
+
+/// Adds up to `amount` to `counter` without exceeding `limit`, returning how
+/// much was added.
+fn add_up_to(counter: &AtomicU64, amount: u64, limit: Option<u64>) -> u64 {
+    let limit = limit.unwrap_or(u64::MAX);
+    let mut added = 0;
+    let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+        added = amount.min(limit.saturating_sub(current));
+        Some(current + added)
+    });
+    added
+}
+
+fn subtract(counter: &AtomicU64, amount: u64) {
+    let _ = counter.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+        Some(current.saturating_sub(amount))
+    });
+}
+
+impl Accounting {
+    pub(crate) fn new(limits: FilesystemLimits) -> Accounting {
+        Accounting {
+            limits,
+            ..Accounting::default()
+        }
+    }
+
+    pub(crate) fn usage(&self) -> FilesystemUsage {
+        FilesystemUsage {
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            files: self.files.load(Ordering::Relaxed),
+            open_descriptors: self.open_descriptors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Reserves up to `len` bytes of the quota of bytes written, returning how
+    /// many were reserved, which is only zero if `len` is.
+    fn reserve_write(&self, len: usize) -> io::Result<usize> {
+        let len = u64::try_from(len).unwrap_or(u64::MAX);
+        match add_up_to(&self.bytes_written, len, self.limits.bytes_written) {
+            0 if len > 0 => Err(quota_error("bytes written")),
+            n => Ok(usize::try_from(n).unwrap()),
+        }
+    }
+
+    /// Returns `len` bytes reserved with `reserve_write` which weren't written
+    /// after all.
+    fn unreserve_write(&self, len: usize) {
+        subtract(&self.bytes_written, len as u64);
+    }
+
+    fn create_file(&self) -> io::Result<()> {
+        match add_up_to(&self.files, 1, self.limits.files) {
+            0 => Err(quota_error("files")),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns a file reserved with `create_file` which wasn't created after
+    /// all.
+    fn unreserve_file(&self) {
+        subtract(&self.files, 1);
+    }
+
+    /// Records that a file reserved with `create_file` was created as
+    /// `object`.
+    ///
+    /// If it can't be identified, because its metadata couldn't be read, it
+    /// stays counted even once it's removed.
+    fn record_created(&self, object: io::Result<(u64, u64)>) {
+        if let Ok(object) = object {
+            *self.lock_created().entry(object).or_default() += 1;
+        }
+    }
+
+    /// Records that a link to `object` was removed, which only makes up for a
+    /// file if the link was created through this context.
+    fn record_removed(&self, object: (u64, u64)) {
+        let mut created = self.lock_created();
+        if let Some(links) = created.get_mut(&object) {
+            *links -= 1;
+            if *links == 0 {
+                created.remove(&object);
+            }
+            subtract(&self.files, 1);
+        }
+    }
+
+    fn lock_created(&self) -> MutexGuard<'_, HashMap<(u64, u64), u64>> {
+        self.created.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn open_descriptor(&self) -> io::Result<()> {
+        match add_up_to(&self.open_descriptors, 1, self.limits.open_descriptors) {
+            0 => Err(quota_error("open descriptors")),
+            _ => Ok(()),
+        }
+    }
+
+    fn close_descriptor(&self) {
+        subtract(&self.open_descriptors, 1);
+    }
+}
+
+/// A directory whose use is accounted for.
+pub(crate) struct AccountedDir {
+    dir: Arc<dyn WasiDir>,
+    accounting: Arc<Accounting>,
+    /// Whether this directory counts as an open descriptor, which preopened
+    /// directories don't.
+    descriptor: bool,
+}
+
+impl AccountedDir {
+    /// Wraps a preopened directory.
+    pub(crate) fn new(dir: Arc<dyn WasiDir>, accounting: Arc<Accounting>) -> AccountedDir {
+        AccountedDir {
+            dir,
+            accounting,
+            descriptor: false,
+        }
+    }
+
+    /// Runs `f`, which creates a file, directory or symbolic link, if the
+    /// quota of files allows for it, and then records the object `created`
+    /// returns the metadata of as created.
+    fn create(
+        &self,
+        f: impl FnOnce() -> io::Result<()>,
+        created: impl FnOnce() -> io::Result<Metadata>,
+    ) -> io::Result<()> {
+        self.accounting.create_file()?;
+        f().inspect_err(|_| self.accounting.unreserve_file())?;
+        self.accounting
+            .record_created(created().map(|meta| object(&meta)));
+        Ok(())
+    }
+
+    /// Runs `f`, which removes the object at `path`.
+    fn remove(&self, path: &str, f: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+        let removed = self.dir.metadata_at(path, false);
+        f()?;
+        if let Ok(meta) = removed {
+            self.accounting.record_removed(object(&meta));
+        }
+        Ok(())
+    }
+
+    /// Opens `path`, counting the file as created if opening it creates it.
+    ///
+    /// Whether it does is decided from the results of opening it, rather
+    /// than by checking whether it exists beforehand, since something could
+    /// be created or removed at `path` in the meantime.
+    fn open(&self, path: &str, follow: bool, options: &OpenOptions) -> io::Result<Opened> {
+        if options.create_new {
+            return self.open_new(path, follow, options);
+        }
+        if !options.create {
+            return self.dir.open_at(path, follow, options);
+        }
+        let existing = OpenOptions {
+            create: false,
+            ..*options
+        };
+        match self.dir.open_at(path, follow, &existing) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            result => return result,
+        }
+        let new = OpenOptions {
+            create: false,
+            create_new: true,
+            ..*options
+        };
+        match self.open_new(path, follow, &new) {
+            // Either something was created at `path` in the meantime, in
+            // which case the file may be counted even though it wasn't
+            // created here, or `path` is a dangling symbolic link which is
+            // followed to create its target.
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                self.open_new(path, follow, options)
+            }
+            result => result,
+        }
+    }
+
+    /// Opens `path` with `options`, which may create it, as a new file.
+    fn open_new(&self, path: &str, follow: bool, options: &OpenOptions) -> io::Result<Opened> {
+        self.accounting.create_file()?;
+        let opened = self
+            .dir
+            .open_at(path, follow, options)
+            .inspect_err(|_| self.accounting.unreserve_file())?;
+        let meta = match &opened {
+            Opened::Dir(dir) => dir.dir_metadata(),
+            Opened::File(file) => file.metadata(),
+        };
+        self.accounting
+            .record_created(meta.map(|meta| object(&meta)));
+        Ok(opened)
+    }
+}
+
+impl Drop for AccountedDir {
+    fn drop(&mut self) {
+        if self.descriptor {
+            self.accounting.close_descriptor();
+        }
+    }
+}
+
+impl WasiDir for AccountedDir {
+    fn as_any(&self) -> &dyn Any {
+        // Let the wrapped directory recognize other directories of its own
+        // type, e.g. when renaming objects between them.
+        self.dir.as_any()
+    }
+
+    fn dir_metadata(&self) -> io::Result<Metadata> {
+        self.dir.dir_metadata()
+    }
+
+    fn metadata_at(&self, path: &str, follow: bool) -> io::Result<Metadata> {
+        self.dir.metadata_at(path, follow)
+    }
+
+    fn open_at(&self, path: &str, follow: bool, options: &OpenOptions) -> io::Result<Opened> {
+        let accounting = &self.accounting;
+        accounting.open_descriptor()?;
+        match self.open(path, follow, options) {
+            Ok(Opened::Dir(dir)) => Ok(Opened::Dir(Arc::new(AccountedDir {
+                dir,
+                accounting: Arc::clone(accounting),
+                descriptor: true,
+            }))),
+            Ok(Opened::File(file)) => Ok(Opened::File(Arc::new(AccountedFile {
+                file,
+                accounting: Arc::clone(accounting),
+            }))),
+            Err(e) => {
+                accounting.close_descriptor();
+                Err(e)
+            }
+        }
+    }
+
+    fn entries(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<DirEntry>> + Send>> {
+        self.dir.entries()
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        self.create(
+            || self.dir.create_dir_at(path),
+            || self.dir.metadata_at(path, false),
+        )
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        self.remove(path, || self.dir.remove_dir_at(path))
+    }
+
+    fn remove_file_at(&self, path: &str) -> io::Result<()> {
+        self.remove(path, || self.dir.remove_file_at(path))
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        let renamed = self.dir.metadata_at(old_path, false);
+        let replaced = new_dir.metadata_at(new_path, false);
+        self.dir.rename_at(old_path, new_dir, new_path)?;
+        // Renaming an object over another removes the other one, unless both
+        // are links to the same file, in which case nothing happens.
+        if let Ok(replaced) = replaced {
+            let replaced = object(&replaced);
+            if renamed.map(|meta| object(&meta)).ok() != Some(replaced) {
+                self.accounting.record_removed(replaced);
+            }
+        }
+        Ok(())
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        self.create(
+            || self.dir.hard_link_at(old_path, new_dir, new_path),
+            || new_dir.metadata_at(new_path, false),
+        )
+    }
+
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()> {
+        self.create(
+            || self.dir.symlink_at(target, path),
+            || self.dir.metadata_at(path, false),
+        )
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<PathBuf> {
+        self.dir.read_link_at(path)
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        follow: bool,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        self.dir.set_times_at(path, follow, atime, mtime)
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        self.dir.set_times(atime, mtime)
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        self.dir.sync_all()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.dir.sync_data()
+    }
+
+    fn as_host_dir(&self) -> Option<&cap_std::fs::Dir> {
+        self.dir.as_host_dir()
+    }
+}
+
+/// A file opened through an [`AccountedDir`], which always counts as an open
+/// descriptor.
+struct AccountedFile {
+    file: Arc<dyn WasiFile>,
+    accounting: Arc<Accounting>,
+}
+
+impl AccountedFile {
+    /// Runs `write` with as much of `buf` as the quota of bytes written allows
+    /// for.
+    fn write(
+        &self,
+        buf: &[u8],
+        write: impl FnOnce(&[u8]) -> io::Result<usize>,
+    ) -> io::Result<usize> {
+        let reserved = self.accounting.reserve_write(buf.len())?;
+        match write(&buf[..reserved]) {
+            Ok(n) => {
+                self.accounting.unreserve_write(reserved - n);
+                Ok(n)
+            }
+            Err(e) => {
+                self.accounting.unreserve_write(reserved);
+                Err(e)
+            }
+        }
+    }
+}
+
+impl Drop for AccountedFile {
+    fn drop(&mut self) {
+        self.accounting.close_descriptor();
+    }
+}
+
+impl WasiFile for AccountedFile {
+    fn metadata(&self) -> io::Result<Metadata> {
+        self.file.metadata()
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let n = self.file.read_at(buf, offset)?;
+        self.accounting
+            .bytes_read
+            .fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.write(buf, |buf| self.file.write_at(buf, offset))
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        self.write(buf, |buf| self.file.append(buf))
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        // Extending a file counts as writing zeros to it, since it may take up
+        // as much space.
+        let growth = size.saturating_sub(self.file.metadata()?.size);
+        if growth == 0 {
+            return self.file.set_len(size);
+        }
+        let limit = self.accounting.limits.bytes_written;
+        let added = add_up_to(&self.accounting.bytes_written, growth, limit);
+        if added < growth {
+            subtract(&self.accounting.bytes_written, added);
+            return Err(quota_error("bytes written"));
+        }
+        self.file
+            .set_len(size)
+            .inspect_err(|_| subtract(&self.accounting.bytes_written, growth))
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        self.file.set_times(atime, mtime)
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn as_host_file(&self) -> Option<&cap_std::fs::File> {
+        self.file.as_host_file()
+    }
+}
@@ -0,0 +1,200 @@
+//! Directories whose observable state doesn't depend on when, or on which
+//! host, a guest runs, see
+//! [`WasiCtxBuilder::deterministic`](crate::WasiCtxBuilder::deterministic).
+
+use super::backend::{DirEntry, Metadata, OpenOptions, Opened, WasiDir, WasiFile};
+use crate::SystemTimeSpec;
+use std::any::Any;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// A directory whose entries are listed in order of their names and whose
+/// metadata, along with that of everything within it, doesn't reveal
+/// anything about the host, see [`Stable`].
+pub(crate) struct StableDir {
+    dir: Arc<dyn WasiDir>,
+    stable: Arc<Stable>,
+}
+
+/// What's shared by all stable directories of a context.
+///
+/// All timestamps are `time`, and objects are numbered in the order the guest
+/// first sees them, all on the same device, so that neither depends on the
+/// host's filesystem.
+pub(crate) struct Stable {
+    time: SystemTime,
+    /// The inode numbers given to objects, by their device and inode.
+    inodes: Mutex<HashMap<(u64, u64), u64>>,
+}
+
+impl Stable {
+    pub(crate) fn new(time: SystemTime) -> Stable {
+        Stable {
+            time,
+            inodes: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn fix(&self, mut meta: Metadata) -> Metadata {
+        meta.accessed = Some(self.time);
+        meta.modified = Some(self.time);
+        meta.status_changed = Some(self.time);
+        let mut inodes = self.inodes.lock().unwrap_or_else(|e| e.into_inner());
+        let next = inodes.len() as u64 + 1;
+        meta.inode = *inodes.entry((meta.device, meta.inode)).or_insert(next);
+        meta.device = 0;
+        meta
+    }
+}
+
+impl StableDir {
+    pub(crate) fn new(dir: Arc<dyn WasiDir>, stable: Arc<Stable>) -> StableDir {
+        StableDir { dir, stable }
+    }
+}
+
+impl WasiDir for StableDir {
+    fn as_any(&self) -> &dyn Any {
+        self.dir.as_any()
+    }
+
+    fn dir_metadata(&self) -> io::Result<Metadata> {
+        Ok(self.stable.fix(self.dir.dir_metadata()?))
+    }
+
+    fn metadata_at(&self, path: &str, follow: bool) -> io::Result<Metadata> {
+        Ok(self.stable.fix(self.dir.metadata_at(path, follow)?))
+    }
+
+    fn open_at(&self, path: &str, follow: bool, options: &OpenOptions) -> io::Result<Opened> {
+        Ok(match self.dir.open_at(path, follow, options)? {
+            Opened::Dir(dir) => {
+                Opened::Dir(Arc::new(StableDir::new(dir, Arc::clone(&self.stable))))
+            }
+            Opened::File(file) => Opened::File(Arc::new(StableFile {
+                file,
+                stable: Arc::clone(&self.stable),
+            })),
+        })
+    }
+
+    fn entries(&self) -> io::Result<Box<dyn Iterator<Item = io::Result<DirEntry>> + Send>> {
+        let mut entries = self.dir.entries()?.collect::<io::Result<Vec<_>>>()?;
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Box::new(entries.into_iter().map(Ok)))
+    }
+
+    fn create_dir_at(&self, path: &str) -> io::Result<()> {
+        self.dir.create_dir_at(path)
+    }
+
+    fn remove_dir_at(&self, path: &str) -> io::Result<()> {
+        self.dir.remove_dir_at(path)
+    }
+
+    fn remove_file_at(&self, path: &str) -> io::Result<()> {
+        self.dir.remove_file_at(path)
+    }
+
+    fn rename_at(&self, old_path: &str, new_dir: &dyn WasiDir, new_path: &str) -> io::Result<()> {
+        self.dir.rename_at(old_path, new_dir, new_path)
+    }
+
+    fn hard_link_at(
+        &self,
+        old_path: &str,
+        new_dir: &dyn WasiDir,
+        new_path: &str,
+    ) -> io::Result<()> {
+        self.dir.hard_link_at(old_path, new_dir, new_path)
+    }
+
+    fn symlink_at(&self, target: &str, path: &str) -> io::Result<()> {
+        self.dir.symlink_at(target, path)
+    }
+
+    fn read_link_at(&self, path: &str) -> io::Result<PathBuf> {
+        self.dir.read_link_at(path)
+    }
+
+    fn set_times_at(
+        &self,
+        path: &str,
+        follow: bool,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        self.dir.set_times_at(path, follow, atime, mtime)
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        self.dir.set_times(atime, mtime)
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        self.dir.sync_all()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.dir.sync_data()
+    }
+
+    fn as_host_dir(&self) -> Option<&cap_std::fs::Dir> {
+        self.dir.as_host_dir()
+    }
+}
+
+/// A file opened through a [`StableDir`].
+struct StableFile {
+    file: Arc<dyn WasiFile>,
+    stable: Arc<Stable>,
+}
+
+impl WasiFile for StableFile {
+    fn metadata(&self) -> io::Result<Metadata> {
+        Ok(self.stable.fix(self.file.metadata()?))
+    }
+
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        self.file.read_at(buf, offset)
+    }
+
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        self.file.write_at(buf, offset)
+    }
+
+    fn append(&self, buf: &[u8]) -> io::Result<usize> {
+        self.file.append(buf)
+    }
+
+    fn set_len(&self, size: u64) -> io::Result<()> {
+        self.file.set_len(size)
+    }
+
+    fn set_times(
+        &self,
+        atime: Option<SystemTimeSpec>,
+        mtime: Option<SystemTimeSpec>,
+    ) -> io::Result<()> {
+        self.file.set_times(atime, mtime)
+    }
+
+    fn sync_all(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+
+    fn sync_data(&self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn as_host_file(&self) -> Option<&cap_std::fs::File> {
+        self.file.as_host_file()
+    }
+}
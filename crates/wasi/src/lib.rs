@@ -15,6 +15,7 @@
 pub mod cli;
 pub mod clocks;
 mod ctx;
+mod deterministic;
 mod error;
 pub mod filesystem;
 #[cfg(feature = "p1")]
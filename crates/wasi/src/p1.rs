@@ -65,7 +65,7 @@
 
 use crate::cli::WasiCliView as _;
 use crate::clocks::WasiClocksView as _;
-use crate::filesystem::WasiFilesystemView as _;
+use crate::filesystem::{WasiFile, WasiFilesystemView as _};
 use crate::p2::bindings::{
     cli::{
         stderr::Host as _, stdin::Host as _, stdout::Host as _, terminal_input, terminal_output,
@@ -82,7 +82,6 @@ use std::mem::{self, size_of, size_of_val};
 use std::slice;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU64, Ordering};
-use system_interface::fs::FileIoExt;
 use wasmtime::component::Resource;
 use wasmtime_wasi_io::{
     bindings::wasi::io::streams,
@@ -581,7 +580,7 @@ impl WasiP1Ctx {
                 let f = self.table.get(&fd)?.file()?;
                 let buf = first_non_empty_ciovec(memory, ciovs)?;
 
-                let do_write = move |f: &cap_std::fs::File, buf: &[u8]| match (append, write) {
+                let do_write = move |f: &dyn WasiFile, buf: &[u8]| match (append, write) {
                     // Note that this is implementing Linux semantics of
                     // `pwrite` where the offset is ignored if the file was
                     // opened in append mode.
@@ -1003,6 +1002,8 @@ impl From<crate::filesystem::ErrorCode> for types::Errno {
             crate::filesystem::ErrorCode::NotPermitted => types::Errno::Perm,
             crate::filesystem::ErrorCode::Pipe => types::Errno::Pipe,
             crate::filesystem::ErrorCode::InvalidSeek => types::Errno::Spipe,
+            crate::filesystem::ErrorCode::CrossDevice => types::Errno::Xdev,
+            crate::filesystem::ErrorCode::Quota => types::Errno::Dquot,
         }
     }
 }
@@ -2311,6 +2312,7 @@ impl wasi_snapshot_preview1::WasiSnapshotPreview1 for WasiP1Ctx {
                     .flags
                     .contains(types::Subclockflags::SUBSCRIPTION_CLOCK_ABSTIME)
                     && self.wasi.filesystem.allow_blocking_current_thread
+                    && self.wasi.clocks.virtual_clock.is_none()
                 {
                     std::thread::sleep(std::time::Duration::from_nanos(clocksub.timeout));
                     memory.write(
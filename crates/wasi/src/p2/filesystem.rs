@@ -1,5 +1,5 @@
 use crate::TrappableError;
-use crate::filesystem::File;
+use crate::filesystem::{File, WasiFile};
 use crate::p2::bindings::filesystem::types;
 use crate::p2::{InputStream, OutputStream, Pollable, StreamError, StreamResult};
 use crate::runtime::AbortOnDropJoinHandle;
@@ -40,6 +40,8 @@ impl From<crate::filesystem::ErrorCode> for types::ErrorCode {
             crate::filesystem::ErrorCode::NotPermitted => Self::NotPermitted,
             crate::filesystem::ErrorCode::Pipe => Self::Pipe,
             crate::filesystem::ErrorCode::InvalidSeek => Self::InvalidSeek,
+            crate::filesystem::ErrorCode::CrossDevice => Self::CrossDevice,
+            crate::filesystem::ErrorCode::Quota => Self::Quota,
         }
     }
 }
@@ -83,9 +85,7 @@ impl FileInputStream {
         }
     }
 
-    fn blocking_read(file: &cap_std::fs::File, offset: u64, size: usize) -> ReadState {
-        use system_interface::fs::FileIoExt;
-
+    fn blocking_read(file: &dyn WasiFile, offset: u64, size: usize) -> ReadState {
         let mut buf = BytesMut::zeroed(size);
         loop {
             match file.read_at(&mut buf, offset) {
@@ -239,12 +239,10 @@ impl FileOutputStream {
     }
 
     fn blocking_write(
-        file: &cap_std::fs::File,
+        file: &dyn WasiFile,
         mut buf: Bytes,
         mode: FileOutputMode,
     ) -> io::Result<usize> {
-        use system_interface::fs::FileIoExt;
-
         match mode {
             FileOutputMode::Position(mut p) => {
                 let mut total = 0;
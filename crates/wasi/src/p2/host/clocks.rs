@@ -1,4 +1,4 @@
-use crate::clocks::WasiClocksCtxView;
+use crate::clocks::{VirtualClock, WasiClocksCtxView};
 use crate::p2::DynPollable;
 use crate::p2::bindings::{
     clocks::monotonic_clock::{self, Duration as WasiDuration, Instant},
@@ -97,6 +97,10 @@ impl monotonic_clock::Host for WasiClocksCtxView<'_> {
     }
 
     fn subscribe_instant(&mut self, when: Instant) -> anyhow::Result<Resource<DynPollable>> {
+        if let Some(clock) = &self.ctx.virtual_clock {
+            let deadline = self.table.push(Deadline::Virtual(clock.clone(), when))?;
+            return subscribe(self.table, deadline);
+        }
         let clock_now = self.ctx.monotonic_clock.now();
         let duration = if when > clock_now {
             Duration::from_nanos(when - clock_now)
@@ -110,7 +114,13 @@ impl monotonic_clock::Host for WasiClocksCtxView<'_> {
         &mut self,
         duration: WasiDuration,
     ) -> anyhow::Result<Resource<DynPollable>> {
-        subscribe_to_duration(self.table, Duration::from_nanos(duration))
+        let duration = Duration::from_nanos(duration);
+        if let Some(clock) = &self.ctx.virtual_clock {
+            let when = clock.deadline(duration);
+            let deadline = self.table.push(Deadline::Virtual(clock.clone(), when))?;
+            return subscribe(self.table, deadline);
+        }
+        subscribe_to_duration(self.table, duration)
     }
 }
 
@@ -118,6 +128,8 @@ enum Deadline {
     Past,
     Instant(tokio::time::Instant),
     Never,
+    /// A time of a virtual clock, which is advanced to it instead of waiting.
+    Virtual(VirtualClock, Instant),
 }
 
 #[async_trait::async_trait]
@@ -127,6 +139,7 @@ impl Pollable for Deadline {
             Deadline::Past => {}
             Deadline::Instant(instant) => tokio::time::sleep_until(*instant).await,
             Deadline::Never => std::future::pending().await,
+            Deadline::Virtual(clock, when) => clock.advance_to(*when),
         }
     }
 }
@@ -105,9 +105,6 @@ impl HostDescriptor for WasiFilesystemCtxView<'_> {
         len: types::Filesize,
         offset: types::Filesize,
     ) -> FsResult<(Vec<u8>, bool)> {
-        use std::io::IoSliceMut;
-        use system_interface::fs::FileIoExt;
-
         let f = self.table.get(&fd)?.file()?;
         if !f.perms.contains(FilePerms::READ) {
             return Err(ErrorCode::NotPermitted.into());
@@ -116,7 +113,7 @@ impl HostDescriptor for WasiFilesystemCtxView<'_> {
         let (mut buffer, r) = f
             .run_blocking(move |f| {
                 let mut buffer = vec![0; len.try_into().unwrap_or(usize::MAX)];
-                let r = f.read_vectored_at(&mut [IoSliceMut::new(&mut buffer)], offset);
+                let r = f.read_at(&mut buffer, offset);
                 (buffer, r)
             })
             .await;
@@ -137,17 +134,12 @@ impl HostDescriptor for WasiFilesystemCtxView<'_> {
         buf: Vec<u8>,
         offset: types::Filesize,
     ) -> FsResult<types::Filesize> {
-        use std::io::IoSlice;
-        use system_interface::fs::FileIoExt;
-
         let f = self.table.get(&fd)?.file()?;
         if !f.perms.contains(FilePerms::WRITE) {
             return Err(ErrorCode::NotPermitted.into());
         }
 
-        let bytes_written = f
-            .run_blocking(move |f| f.write_vectored_at(&[IoSlice::new(&buf)], offset))
-            .await?;
+        let bytes_written = f.run_blocking(move |f| f.write_at(&buf, offset)).await?;
 
         Ok(types::Filesize::try_from(bytes_written).expect("usize fits in Filesize"))
     }
@@ -173,17 +165,17 @@ impl HostDescriptor for WasiFilesystemCtxView<'_> {
 
         let entries = d
             .run_blocking(|d| {
-                // Both `entries` and `metadata` perform syscalls, which is why they are done
-                // within this `block` call, rather than delay calculating the metadata
-                // for entries when they're demanded later in the iterator chain.
+                // Reading entries may perform syscalls, which is why they are
+                // all read within this `block` call, rather than delay reading
+                // them until they're demanded later in the iterator chain.
                 Ok::<_, std::io::Error>(
                     d.entries()?
                         .map(|entry| {
                             let entry = entry?;
-                            let meta = entry.metadata()?;
-                            let type_ = descriptortype_from(meta.file_type());
+                            let type_ =
+                                crate::filesystem::DescriptorType::from(entry.file_type).into();
                             let name = entry
-                                .file_name()
+                                .name
                                 .into_string()
                                 .map_err(|_| ReaddirError::IllegalSequence)?;
                             Ok(types::DirectoryEntry { type_, name })
@@ -647,6 +639,8 @@ fn from_raw_os_error(err: Option<i32>) -> Option<ErrorCode> {
         RustixErrno::ALREADY => ErrorCode::Already,
         RustixErrno::INPROGRESS => ErrorCode::InProgress,
         RustixErrno::INTR => ErrorCode::Interrupted,
+        RustixErrno::XDEV => ErrorCode::CrossDevice,
+        RustixErrno::DQUOT => ErrorCode::Quota,
 
         #[allow(
             unreachable_patterns,
@@ -683,6 +677,7 @@ fn from_raw_os_error(raw_os_error: Option<i32>) -> Option<ErrorCode> {
         Some(Foundation::ERROR_ALREADY_EXISTS) => ErrorCode::Exist,
         Some(Foundation::ERROR_STOPPED_ON_SYMLINK) => ErrorCode::Loop,
         Some(Foundation::ERROR_DIRECTORY_NOT_SUPPORTED) => ErrorCode::IsDirectory,
+        Some(Foundation::ERROR_DISK_QUOTA_EXCEEDED) => ErrorCode::Quota,
         _ => return None,
     })
 }
@@ -704,6 +699,19 @@ impl<'a> From<&'a std::io::Error> for ErrorCode {
                     std::io::ErrorKind::PermissionDenied => ErrorCode::NotPermitted,
                     std::io::ErrorKind::AlreadyExists => ErrorCode::Exist,
                     std::io::ErrorKind::InvalidInput => ErrorCode::Invalid,
+                    std::io::ErrorKind::NotADirectory => ErrorCode::NotDirectory,
+                    std::io::ErrorKind::IsADirectory => ErrorCode::IsDirectory,
+                    std::io::ErrorKind::DirectoryNotEmpty => ErrorCode::NotEmpty,
+                    std::io::ErrorKind::ReadOnlyFilesystem => ErrorCode::NotPermitted,
+                    std::io::ErrorKind::CrossesDevices => ErrorCode::CrossDevice,
+                    std::io::ErrorKind::QuotaExceeded => ErrorCode::Quota,
+                    std::io::ErrorKind::StorageFull => ErrorCode::InsufficientSpace,
+                    std::io::ErrorKind::FileTooLarge => ErrorCode::FileTooLarge,
+                    std::io::ErrorKind::TooManyLinks => ErrorCode::TooManyLinks,
+                    std::io::ErrorKind::NotSeekable => ErrorCode::InvalidSeek,
+                    std::io::ErrorKind::Unsupported => ErrorCode::Unsupported,
+                    std::io::ErrorKind::OutOfMemory => ErrorCode::InsufficientMemory,
+                    std::io::ErrorKind::Interrupted => ErrorCode::Interrupted,
                     _ => ErrorCode::Io,
                 }
             }
@@ -724,24 +732,6 @@ impl From<std::num::TryFromIntError> for ErrorCode {
     }
 }
 
-fn descriptortype_from(ft: cap_std::fs::FileType) -> types::DescriptorType {
-    use cap_fs_ext::FileTypeExt;
-    use types::DescriptorType;
-    if ft.is_dir() {
-        DescriptorType::Directory
-    } else if ft.is_symlink() {
-        DescriptorType::SymbolicLink
-    } else if ft.is_block_device() {
-        DescriptorType::BlockDevice
-    } else if ft.is_char_device() {
-        DescriptorType::CharacterDevice
-    } else if ft.is_file() {
-        DescriptorType::RegularFile
-    } else {
-        DescriptorType::Unknown
-    }
-}
-
 fn systemtime_from(t: wall_clock::Datetime) -> Result<std::time::SystemTime, ErrorCode> {
     std::time::SystemTime::UNIX_EPOCH
         .checked_add(core::time::Duration::new(t.seconds, t.nanoseconds))
@@ -8,6 +8,7 @@ impl instance_network::Host for WasiSocketsCtxView<'_> {
         let network = Network {
             socket_addr_check: self.ctx.socket_addr_check.clone(),
             allow_ip_name_lookup: self.ctx.allowed_network_uses.ip_name_lookup,
+            network: self.ctx.network.clone(),
         };
         let network = self.table.push(network)?;
         Ok(network)
@@ -42,6 +42,14 @@ impl From<io::Error> for ErrorCode {
 
 impl From<&io::Error> for ErrorCode {
     fn from(value: &io::Error) -> Self {
+        // Errors of network backends may already carry an error code:
+        if let Some(code) = value
+            .get_ref()
+            .and_then(|e| e.downcast_ref::<crate::sockets::util::ErrorCode>())
+        {
+            return (*code).into();
+        }
+
         // Attempt the more detailed native error code first:
         if let Some(errno) = Errno::from_io_error(value) {
             return errno.into();
@@ -9,7 +9,6 @@ use crate::sockets::{
 use anyhow::anyhow;
 use async_trait::async_trait;
 use std::net::SocketAddr;
-use tokio::io::Interest;
 use wasmtime::component::Resource;
 use wasmtime_wasi_io::poll::DynPollable;
 
@@ -260,9 +259,8 @@ impl udp::HostIncomingDatagramStream for WasiSocketsCtxView<'_> {
 #[async_trait]
 impl Pollable for IncomingDatagramStream {
     async fn ready(&mut self) {
-        // FIXME: Add `Interest::ERROR` when we update to tokio 1.32.
         self.inner
-            .ready(Interest::READABLE)
+            .readable()
             .await
             .expect("failed to await UDP socket readiness");
     }
@@ -397,9 +395,8 @@ impl Pollable for OutgoingDatagramStream {
         match self.send_state {
             SendState::Idle | SendState::Permitted(_) => {}
             SendState::Waiting => {
-                // FIXME: Add `Interest::ERROR` when we update to tokio 1.32.
                 self.inner
-                    .ready(Interest::WRITABLE)
+                    .writable()
                     .await
                     .expect("failed to await UDP socket readiness");
                 self.send_state = SendState::Idle;
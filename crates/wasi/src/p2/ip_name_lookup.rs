@@ -1,11 +1,10 @@
 use crate::p2::SocketError;
 use crate::p2::bindings::sockets::ip_name_lookup::{Host, HostResolveAddressStream};
 use crate::p2::bindings::sockets::network::{ErrorCode, IpAddress, Network};
-use crate::runtime::{AbortOnDropJoinHandle, spawn_blocking};
+use crate::runtime::{AbortOnDropJoinHandle, spawn};
 use crate::sockets::WasiSocketsCtxView;
 use anyhow::Result;
 use std::mem;
-use std::net::ToSocketAddrs;
 use std::pin::Pin;
 use std::vec;
 use wasmtime::component::Resource;
@@ -32,8 +31,28 @@ impl Host for WasiSocketsCtxView<'_> {
             return Err(ErrorCode::PermanentResolverFailure.into());
         }
 
-        let task = spawn_blocking(move || blocking_resolve(&host));
-        let resource = self.table.push(ResolveAddressStream::Waiting(task))?;
+        let addresses = match host {
+            url::Host::Ipv4(v4addr) => vec![IpAddress::Ipv4(from_ipv4_addr(v4addr))],
+            url::Host::Ipv6(v6addr) => vec![IpAddress::Ipv6(from_ipv6_addr(v6addr))],
+            url::Host::Domain(domain) => {
+                let addresses = network.network.resolve_addresses(&domain);
+                let task = spawn(async move {
+                    // If/when we use `getaddrinfo` directly, map the error properly.
+                    let addresses = addresses.await.map_err(|_| ErrorCode::NameUnresolvable)?;
+                    Ok::<_, SocketError>(
+                        addresses
+                            .into_iter()
+                            .map(|addr| addr.to_canonical().into())
+                            .collect(),
+                    )
+                });
+                let resource = self.table.push(ResolveAddressStream::Waiting(task))?;
+                return Ok(resource);
+            }
+        };
+        let resource = self
+            .table
+            .push(ResolveAddressStream::Done(Ok(addresses.into_iter())))?;
         Ok(resource)
     }
 }
@@ -84,22 +103,3 @@ impl Pollable for ResolveAddressStream {
         }
     }
 }
-
-fn blocking_resolve(host: &url::Host) -> Result<Vec<IpAddress>, SocketError> {
-    match host {
-        url::Host::Ipv4(v4addr) => Ok(vec![IpAddress::Ipv4(from_ipv4_addr(*v4addr))]),
-        url::Host::Ipv6(v6addr) => Ok(vec![IpAddress::Ipv6(from_ipv6_addr(*v6addr))]),
-        url::Host::Domain(domain) => {
-            // For now use the standard library to perform actual resolution through
-            // the usage of the `ToSocketAddrs` trait. This is only
-            // resolving names, not ports, so force the port to be 0.
-            let addresses = (domain.as_str(), 0)
-                .to_socket_addrs()
-                .map_err(|_| ErrorCode::NameUnresolvable)? // If/when we use `getaddrinfo` directly, map the error properly.
-                .map(|addr| addr.ip().to_canonical().into())
-                .collect();
-
-            Ok(addresses)
-        }
-    }
-}
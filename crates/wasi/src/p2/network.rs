@@ -1,7 +1,8 @@
 use crate::TrappableError;
 use crate::p2::bindings::sockets::network::ErrorCode;
-use crate::sockets::{SocketAddrCheck, SocketAddrUse};
+use crate::sockets::{SocketAddrCheck, SocketAddrUse, WasiNetwork};
 use std::net::SocketAddr;
+use std::sync::Arc;
 
 pub type SocketResult<T> = Result<T, SocketError>;
 
@@ -57,6 +58,7 @@ impl From<crate::sockets::util::ErrorCode> for ErrorCode {
 pub struct Network {
     pub(crate) socket_addr_check: SocketAddrCheck,
     pub(crate) allow_ip_name_lookup: bool,
+    pub(crate) network: Arc<dyn WasiNetwork>,
 }
 
 impl Network {
@@ -4,8 +4,8 @@ use crate::p2::{
 };
 use crate::runtime::AbortOnDropJoinHandle;
 use crate::sockets::TcpSocket;
+use crate::sockets::backend::TcpStream;
 use anyhow::Result;
-use io_lifetimes::AsSocketlike;
 use rustix::io::Errno;
 use std::io;
 use std::mem;
@@ -30,7 +30,7 @@ impl TcpSocket {
 }
 
 pub(crate) struct P2TcpStreamingState {
-    pub(crate) stream: Arc<tokio::net::TcpStream>,
+    pub(crate) stream: Arc<TcpStream>,
     reader: Arc<Mutex<TcpReader>>,
     writer: Arc<Mutex<TcpWriter>>,
 }
@@ -50,12 +50,12 @@ impl P2TcpStreamingState {
 }
 
 struct TcpReader {
-    stream: Arc<tokio::net::TcpStream>,
+    stream: Arc<TcpStream>,
     closed: bool,
 }
 
 impl TcpReader {
-    fn new(stream: Arc<tokio::net::TcpStream>) -> Self {
+    fn new(stream: Arc<TcpStream>) -> Self {
         Self {
             stream,
             closed: false,
@@ -93,7 +93,7 @@ impl TcpReader {
     }
 
     fn shutdown(&mut self) {
-        native_shutdown(&self.stream, Shutdown::Read);
+        self.stream.shutdown(Shutdown::Read);
         self.closed = true;
     }
 
@@ -125,7 +125,7 @@ impl Pollable for TcpReadStream {
 const SOCKET_READY_SIZE: usize = 1024 * 1024 * 1024;
 
 struct TcpWriter {
-    stream: Arc<tokio::net::TcpStream>,
+    stream: Arc<TcpStream>,
     state: WriteState,
 }
 
@@ -138,14 +138,14 @@ enum WriteState {
 }
 
 impl TcpWriter {
-    fn new(stream: Arc<tokio::net::TcpStream>) -> Self {
+    fn new(stream: Arc<TcpStream>) -> Self {
         Self {
             stream,
             state: WriteState::Ready,
         }
     }
 
-    fn try_write_portable(stream: &tokio::net::TcpStream, buf: &[u8]) -> io::Result<usize> {
+    fn try_write_portable(stream: &TcpStream, buf: &[u8]) -> io::Result<usize> {
         stream.try_write(buf).map_err(|error| {
             match Errno::from_io_error(&error) {
                 // Windows returns `WSAESHUTDOWN` when writing to a shut down socket.
@@ -264,7 +264,7 @@ impl TcpWriter {
         self.state = match mem::replace(&mut self.state, WriteState::Closed) {
             // No write in progress, immediately shut down:
             WriteState::Ready => {
-                native_shutdown(&self.stream, Shutdown::Write);
+                self.stream.shutdown(Shutdown::Write);
                 WriteState::Closed
             }
 
@@ -273,7 +273,7 @@ impl TcpWriter {
                 let stream = self.stream.clone();
                 WriteState::Closing(crate::runtime::spawn(async move {
                     let result = write.await;
-                    native_shutdown(&stream, Shutdown::Write);
+                    stream.shutdown(Shutdown::Write);
                     result
                 }))
             }
@@ -340,12 +340,6 @@ impl Pollable for TcpWriteStream {
     }
 }
 
-fn native_shutdown(stream: &tokio::net::TcpStream, how: Shutdown) {
-    _ = stream
-        .as_socketlike_view::<std::net::TcpStream>()
-        .shutdown(how);
-}
-
 fn try_lock_for_stream<T>(mutex: &Mutex<T>) -> Result<tokio::sync::MutexGuard<'_, T>, StreamError> {
     mutex
         .try_lock()
@@ -1,16 +1,17 @@
+use crate::sockets::backend::UdpSocket;
 use crate::sockets::{SocketAddrCheck, SocketAddressFamily};
 use std::net::SocketAddr;
 use std::sync::Arc;
 
 pub struct IncomingDatagramStream {
-    pub(crate) inner: Arc<tokio::net::UdpSocket>,
+    pub(crate) inner: Arc<UdpSocket>,
 
     /// If this has a value, the stream is "connected".
     pub(crate) remote_address: Option<SocketAddr>,
 }
 
 pub struct OutgoingDatagramStream {
-    pub(crate) inner: Arc<tokio::net::UdpSocket>,
+    pub(crate) inner: Arc<UdpSocket>,
 
     /// If this has a value, the stream is "connected".
     pub(crate) remote_address: Option<SocketAddr>,
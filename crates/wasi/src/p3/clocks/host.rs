@@ -27,18 +27,29 @@ impl monotonic_clock::HostWithStore for WasiClocks {
         store: &Accessor<U, Self>,
         when: monotonic_clock::Mark,
     ) -> wasmtime::Result<()> {
-        let clock_now = store.with(|mut view| view.get().ctx.monotonic_clock.now());
-        if when > clock_now {
+        let (clock_now, virtual_clock) = store.with(|mut view| {
+            let ctx = view.get().ctx;
+            match &ctx.virtual_clock {
+                Some(clock) => (0, Some(clock.clone())),
+                None => (ctx.monotonic_clock.now(), None),
+            }
+        });
+        if let Some(clock) = virtual_clock {
+            clock.advance_to(when);
+        } else if when > clock_now {
             sleep(Duration::from_nanos(when - clock_now)).await;
         };
         Ok(())
     }
 
     async fn wait_for<U>(
-        _store: &Accessor<U, Self>,
+        store: &Accessor<U, Self>,
         duration: types::Duration,
     ) -> wasmtime::Result<()> {
-        if duration > 0 {
+        let virtual_clock = store.with(|mut view| view.get().ctx.virtual_clock.clone());
+        if let Some(clock) = virtual_clock {
+            clock.advance(Duration::from_nanos(duration));
+        } else if duration > 0 {
             sleep(Duration::from_nanos(duration)).await;
         }
         Ok(())
@@ -1,4 +1,6 @@
-use crate::filesystem::{Descriptor, Dir, File, WasiFilesystem, WasiFilesystemCtxView};
+use crate::filesystem::{
+    Descriptor, Dir, File, WasiDir, WasiFile, WasiFilesystem, WasiFilesystemCtxView,
+};
 use crate::p3::bindings::clocks::system_clock;
 use crate::p3::bindings::filesystem::types::{
     self, Advice, DescriptorFlags, DescriptorStat, DescriptorType, DirectoryEntry, ErrorCode,
@@ -14,7 +16,6 @@ use core::task::{Context, Poll, ready};
 use core::{iter, mem};
 use std::io::{self, Cursor};
 use std::sync::Arc;
-use system_interface::fs::FileIoExt as _;
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::{JoinHandle, spawn_blocking};
 use wasmtime::StoreContextMut;
@@ -234,16 +235,15 @@ impl<D> StreamProducer<D> for ReadStreamProducer {
 }
 
 fn map_dir_entry(
-    entry: std::io::Result<cap_std::fs::DirEntry>,
+    entry: std::io::Result<crate::filesystem::DirEntry>,
 ) -> Result<Option<DirectoryEntry>, ErrorCode> {
     match entry {
         Ok(entry) => {
-            let meta = entry.metadata()?;
-            let Ok(name) = entry.file_name().into_string() else {
+            let Ok(name) = entry.name.into_string() else {
                 return Err(ErrorCode::IllegalByteSequence);
             };
             Ok(Some(DirectoryEntry {
-                type_: meta.file_type().into(),
+                type_: entry.file_type.into(),
                 name,
             }))
         }
@@ -273,10 +273,7 @@ struct ReadDirStream {
 }
 
 impl ReadDirStream {
-    fn new(
-        dir: Arc<cap_std::fs::Dir>,
-        result: oneshot::Sender<Result<(), ErrorCode>>,
-    ) -> ReadDirStream {
+    fn new(dir: Arc<dyn WasiDir>, result: oneshot::Sender<Result<(), ErrorCode>>) -> ReadDirStream {
         let (tx, rx) = mpsc::channel(1);
         ReadDirStream {
             task: spawn_blocking(move || {
@@ -418,7 +415,7 @@ impl WriteStreamConsumer {
 }
 
 impl WriteLocation {
-    fn write(&self, file: &cap_std::fs::File, bytes: &[u8]) -> io::Result<usize> {
+    fn write(&self, file: &dyn WasiFile, bytes: &[u8]) -> io::Result<usize> {
         match *self {
             WriteLocation::End => file.append(bytes),
             WriteLocation::Offset(at) => file.write_at(bytes, at),
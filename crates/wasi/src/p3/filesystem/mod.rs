@@ -115,6 +115,8 @@ impl From<crate::filesystem::ErrorCode> for types::ErrorCode {
             crate::filesystem::ErrorCode::NotPermitted => Self::NotPermitted,
             crate::filesystem::ErrorCode::Pipe => Self::Pipe,
             crate::filesystem::ErrorCode::InvalidSeek => Self::InvalidSeek,
+            crate::filesystem::ErrorCode::CrossDevice => Self::CrossDevice,
+            crate::filesystem::ErrorCode::Quota => Self::Quota,
         }
     }
 }
@@ -266,21 +268,8 @@ impl From<crate::filesystem::DescriptorType> for types::DescriptorType {
     }
 }
 
-impl From<cap_std::fs::FileType> for types::DescriptorType {
-    fn from(ft: cap_std::fs::FileType) -> Self {
-        use cap_fs_ext::FileTypeExt as _;
-        if ft.is_dir() {
-            Self::Directory
-        } else if ft.is_symlink() {
-            Self::SymbolicLink
-        } else if ft.is_block_device() {
-            Self::BlockDevice
-        } else if ft.is_char_device() {
-            Self::CharacterDevice
-        } else if ft.is_file() {
-            Self::RegularFile
-        } else {
-            Self::Unknown
-        }
+impl From<crate::filesystem::FileType> for types::DescriptorType {
+    fn from(ft: crate::filesystem::FileType) -> Self {
+        crate::filesystem::DescriptorType::from(ft).into()
     }
 }
@@ -1,4 +1,3 @@
-use tokio::net::lookup_host;
 use wasmtime::component::Accessor;
 
 use crate::p3::bindings::sockets::ip_name_lookup::{ErrorCode, Host, HostWithStore};
@@ -22,10 +21,13 @@ impl HostWithStore for WasiSockets {
             url::Host::Ipv4(addr) => Ok(Ok(vec![types::IpAddress::Ipv4(from_ipv4_addr(addr))])),
             url::Host::Ipv6(addr) => Ok(Ok(vec![types::IpAddress::Ipv6(from_ipv6_addr(addr))])),
             url::Host::Domain(domain) => {
-                // This is only resolving names, not ports, so force the port to be 0.
-                if let Ok(addrs) = lookup_host((domain.as_str(), 0)).await {
+                let addrs = store
+                    .with(|mut view| view.get().ctx.network.resolve_addresses(&domain))
+                    .await;
+                if let Ok(addrs) = addrs {
                     Ok(Ok(addrs
-                        .map(|addr| addr.ip().to_canonical().into())
+                        .into_iter()
+                        .map(|addr| addr.to_canonical().into())
                         .collect()))
                 } else {
                     // If/when we use `getaddrinfo` directly, map the error properly.
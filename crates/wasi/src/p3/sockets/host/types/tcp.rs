@@ -5,17 +5,16 @@ use crate::p3::bindings::sockets::types::{
     TcpSocket,
 };
 use crate::p3::sockets::{SocketError, SocketResult, WasiSockets};
+use crate::sockets::backend::{TcpListener, TcpStream};
 use crate::sockets::{NonInheritedOptions, SocketAddrUse, SocketAddressFamily, WasiSocketsCtxView};
 use anyhow::Context as _;
 use bytes::BytesMut;
 use core::iter;
 use core::pin::Pin;
 use core::task::{Context, Poll};
-use io_lifetimes::AsSocketlike as _;
 use std::io::Cursor;
 use std::net::{Shutdown, SocketAddr};
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::oneshot;
 use wasmtime::component::{
     Access, Accessor, Destination, FutureReader, Resource, ResourceTable, Source, StreamConsumer,
@@ -75,7 +74,7 @@ where
             return Poll::Ready(Ok(StreamResult::Completed));
         }
         let res = match self.listener.poll_accept(cx) {
-            Poll::Ready(res) => res.map(|(stream, _)| stream),
+            Poll::Ready(res) => res,
             Poll::Pending if finish => return Poll::Ready(Ok(StreamResult::Cancelled)),
             Poll::Pending => return Poll::Pending,
         };
@@ -104,10 +103,7 @@ impl Drop for ReceiveStreamProducer {
 impl ReceiveStreamProducer {
     fn close(&mut self, res: Result<(), ErrorCode>) {
         if let Some(tx) = self.result.take() {
-            _ = self
-                .stream
-                .as_socketlike_view::<std::net::TcpStream>()
-                .shutdown(Shutdown::Read);
+            self.stream.shutdown(Shutdown::Read);
             _ = tx.send(res);
         }
     }
@@ -178,10 +174,7 @@ impl Drop for SendStreamConsumer {
 impl SendStreamConsumer {
     fn close(&mut self, res: Result<(), ErrorCode>) {
         if let Some(tx) = self.result.take() {
-            _ = self
-                .stream
-                .as_socketlike_view::<std::net::TcpStream>()
-                .shutdown(Shutdown::Write);
+            self.stream.shutdown(Shutdown::Write);
             _ = tx.send(res);
         }
     }
@@ -0,0 +1,626 @@
+//! Networks which guests' sockets are attached to, see [`WasiNetwork`].
+//!
+//! The traits here mirror the subset of the Tokio networking API used by the
+//! implementations of WASI sockets, and are implemented for the Tokio types
+//! of the host's network stack and for [`LoopbackNetwork`]. The crate-private
+//! wrappers at the bottom of this module are what the rest of the crate uses.
+//! Socket options are only supported on host sockets, which are exposed
+//! through `as_std_view` and `as_host`.
+//!
+//! [`LoopbackNetwork`]: crate::sockets::LoopbackNetwork
+
+use crate::runtime::with_ambient_tokio_runtime;
+use crate::sockets::SocketAddressFamily;
+use crate::sockets::util::{ErrorCode, tcp_bind, udp_bind, udp_disconnect, udp_socket};
+use cap_net_ext::AddressFamily;
+use io_lifetimes::AsSocketlike as _;
+use io_lifetimes::raw::{FromRawSocketlike as _, IntoRawSocketlike as _};
+use io_lifetimes::views::SocketlikeView;
+use rustix::io::Errno;
+use rustix::net::sockopt;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Shutdown, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::Interest;
+use tracing::debug;
+
+/// A network which guests' sockets can be attached to.
+///
+/// This is implemented for [`HostNetwork`], which guests use by default, and
+/// for [`LoopbackNetwork`](super::LoopbackNetwork). Other implementations,
+/// for example of a network simulating latency and packet loss, can be
+/// attached with [`WasiCtxBuilder::network`](crate::WasiCtxBuilder::network)
+/// and are served the same way to WASIp2 and WASIp3 guests.
+///
+/// Addresses are checked against
+/// [`WasiCtxBuilder::socket_addr_check`](crate::WasiCtxBuilder::socket_addr_check),
+/// and the state of sockets is tracked, before any method of the traits of
+/// this module is called, so implementations don't need to do either. Errors
+/// are reported to guests based on their raw OS error code if they have one,
+/// and otherwise on their [`io::ErrorKind`].
+///
+/// Sockets are non-blocking: methods starting with `try_` should fail with
+/// [`io::ErrorKind::WouldBlock`] rather than wait, and the corresponding
+/// `poll_*_ready` methods should wake the task once it's worth trying again.
+pub trait WasiNetwork: Send + Sync + 'static {
+    /// Creates a TCP socket of the given address family.
+    fn tcp_socket(&self, family: SocketAddressFamily) -> io::Result<Box<dyn WasiTcpSocket>>;
+
+    /// Creates a UDP socket of the given address family.
+    fn udp_socket(&self, family: SocketAddressFamily) -> io::Result<Box<dyn WasiUdpSocket>>;
+
+    /// Resolves the domain name `name` to the addresses of its hosts.
+    fn resolve_addresses(
+        &self,
+        name: &str,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Vec<IpAddr>>> + Send>>;
+}
+
+/// A TCP socket of a [`WasiNetwork`] which is neither connected nor listening
+/// yet.
+pub trait WasiTcpSocket: Send + Sync + 'static {
+    /// Binds the socket to `addr`, picking a free port if its port is 0.
+    fn bind(&mut self, addr: SocketAddr) -> io::Result<()>;
+
+    /// Returns the address the socket is bound to.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Starts listening for connections, binding the socket to a free port
+    /// first if it isn't bound yet.
+    fn listen(self: Box<Self>, backlog: u32) -> io::Result<Box<dyn WasiTcpListener>>;
+
+    /// Connects the socket to `addr`, binding it to a free port first if it
+    /// isn't bound yet.
+    fn connect(
+        self: Box<Self>,
+        addr: SocketAddr,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn WasiTcpStream>>> + Send>>;
+
+    /// Returns the host socket this is, if any, which is used for socket
+    /// options.
+    fn as_host_socket(&self) -> Option<&tokio::net::TcpSocket> {
+        None
+    }
+}
+
+/// A listening TCP socket of a [`WasiNetwork`].
+pub trait WasiTcpListener: Send + Sync + 'static {
+    /// Accepts a connection, or registers the task to be woken once there's
+    /// one to accept.
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Box<dyn WasiTcpStream>>>;
+
+    /// Returns the address the socket is listening on.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Changes the number of connections which can wait to be accepted.
+    fn set_backlog(&self, backlog: u32) -> io::Result<()>;
+
+    /// Returns the host socket this is, if any, which is used for socket
+    /// options.
+    fn as_host_listener(&self) -> Option<&tokio::net::TcpListener> {
+        None
+    }
+}
+
+/// A connected TCP socket of a [`WasiNetwork`].
+pub trait WasiTcpStream: Send + Sync + 'static {
+    /// Reads into `buf`, returning 0 once the peer has shut down its writing
+    /// side.
+    fn try_read(&self, buf: &mut [u8]) -> io::Result<usize>;
+
+    /// Returns whether the socket is ready to be read from, registering the
+    /// task to be woken once it is otherwise.
+    fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    /// Writes as much of `buf` as fits into the socket's buffer.
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Returns whether the socket is ready to be written to, registering the
+    /// task to be woken once it is otherwise.
+    fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    /// Returns the local address of the connection.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Returns the address of the peer of the connection.
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Shuts down the reading and/or writing side of the connection, ignoring
+    /// errors.
+    fn shutdown(&self, how: Shutdown);
+
+    /// Returns the host socket this is, if any, which is used for socket
+    /// options.
+    fn as_host_stream(&self) -> Option<&tokio::net::TcpStream> {
+        None
+    }
+}
+
+/// A UDP socket of a [`WasiNetwork`].
+pub trait WasiUdpSocket: Send + Sync + 'static {
+    /// Binds the socket to `addr`, picking a free port if its port is 0.
+    fn bind(&self, addr: SocketAddr) -> io::Result<()>;
+
+    /// Only exchanges datagrams with `addr` from now on, binding the socket
+    /// to a free port first if it isn't bound yet.
+    fn connect(&self, addr: SocketAddr) -> io::Result<()>;
+
+    /// Undoes [`WasiUdpSocket::connect`].
+    fn disconnect(&self) -> io::Result<()>;
+
+    /// Sends a datagram to the address the socket is connected to.
+    fn try_send(&self, buf: &[u8]) -> io::Result<usize>;
+
+    /// Sends a datagram to `addr`, binding the socket to a free port first if
+    /// it isn't bound yet.
+    fn try_send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize>;
+
+    /// Receives a datagram, discarding whatever doesn't fit into `buf`, along
+    /// with the address it came from.
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)>;
+
+    /// Returns whether there's a datagram to receive, registering the task to
+    /// be woken once there is otherwise.
+    fn poll_recv_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    /// Returns whether a datagram can be sent, registering the task to be
+    /// woken once it can otherwise.
+    fn poll_send_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>>;
+
+    /// Returns the address the socket is bound to.
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Returns the address the socket is connected to.
+    fn peer_addr(&self) -> io::Result<SocketAddr>;
+
+    /// Returns the host socket this is, if any, which is used for socket
+    /// options.
+    fn as_host_socket(&self) -> Option<&tokio::net::UdpSocket> {
+        None
+    }
+}
+
+/// The network stack of the host, which guests' sockets are attached to
+/// unless their [`WasiCtx`](crate::WasiCtx) is given another network.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct HostNetwork;
+
+impl WasiNetwork for HostNetwork {
+    fn tcp_socket(&self, family: SocketAddressFamily) -> io::Result<Box<dyn WasiTcpSocket>> {
+        with_ambient_tokio_runtime(|| {
+            let socket = match family {
+                SocketAddressFamily::Ipv4 => tokio::net::TcpSocket::new_v4()?,
+                SocketAddressFamily::Ipv6 => {
+                    let socket = tokio::net::TcpSocket::new_v6()?;
+                    sockopt::set_ipv6_v6only(&socket, true)?;
+                    socket
+                }
+            };
+            Ok(Box::new(socket) as Box<dyn WasiTcpSocket>)
+        })
+    }
+
+    fn udp_socket(&self, family: SocketAddressFamily) -> io::Result<Box<dyn WasiUdpSocket>> {
+        // Delegate socket creation to cap_net_ext. They handle a couple of things for us:
+        // - On Windows: call WSAStartup if not done before.
+        // - Set the NONBLOCK and CLOEXEC flags. Either immediately during socket creation,
+        //   or afterwards using ioctl or fcntl. Exact method depends on the platform.
+        let fd = match family {
+            SocketAddressFamily::Ipv4 => udp_socket(AddressFamily::Ipv4)?,
+            SocketAddressFamily::Ipv6 => {
+                let fd = udp_socket(AddressFamily::Ipv6)?;
+                sockopt::set_ipv6_v6only(&fd, true)?;
+                fd
+            }
+        };
+        let socket = with_ambient_tokio_runtime(|| {
+            tokio::net::UdpSocket::try_from(unsafe {
+                std::net::UdpSocket::from_raw_socketlike(fd.into_raw_socketlike())
+            })
+        })?;
+        Ok(Box::new(socket))
+    }
+
+    fn resolve_addresses(
+        &self,
+        name: &str,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Vec<IpAddr>>> + Send>> {
+        let name = name.to_string();
+        Box::pin(async move {
+            // This is only resolving names, not ports, so force the port to be 0.
+            let addrs = tokio::net::lookup_host((name.as_str(), 0)).await?;
+            Ok(addrs.map(|addr| addr.ip()).collect())
+        })
+    }
+}
+
+impl WasiTcpSocket for tokio::net::TcpSocket {
+    fn bind(&mut self, addr: SocketAddr) -> io::Result<()> {
+        Ok(tcp_bind(self, addr)?)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        tokio::net::TcpSocket::local_addr(self)
+    }
+
+    fn listen(self: Box<Self>, backlog: u32) -> io::Result<Box<dyn WasiTcpListener>> {
+        Ok(Box::new(tokio::net::TcpSocket::listen(*self, backlog)?))
+    }
+
+    fn connect(
+        self: Box<Self>,
+        addr: SocketAddr,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn WasiTcpStream>>> + Send>> {
+        Box::pin(async move {
+            let stream = tokio::net::TcpSocket::connect(*self, addr).await?;
+            Ok(Box::new(stream) as Box<dyn WasiTcpStream>)
+        })
+    }
+
+    fn as_host_socket(&self) -> Option<&tokio::net::TcpSocket> {
+        Some(self)
+    }
+}
+
+impl WasiTcpListener for tokio::net::TcpListener {
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Box<dyn WasiTcpStream>>> {
+        tokio::net::TcpListener::poll_accept(self, cx)
+            .map_ok(|(stream, _)| Box::new(stream) as Box<dyn WasiTcpStream>)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        tokio::net::TcpListener::local_addr(self)
+    }
+
+    fn set_backlog(&self, backlog: u32) -> io::Result<()> {
+        // Not all platforms support this, so try calling `listen` again.
+        if rustix::net::listen(self, backlog.try_into().unwrap_or(i32::MAX)).is_err() {
+            return Err(ErrorCode::NotSupported.into());
+        }
+        Ok(())
+    }
+
+    fn as_host_listener(&self) -> Option<&tokio::net::TcpListener> {
+        Some(self)
+    }
+}
+
+impl WasiTcpStream for tokio::net::TcpStream {
+    fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        tokio::net::TcpStream::try_read(self, buf)
+    }
+
+    fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        tokio::net::TcpStream::poll_read_ready(self, cx)
+    }
+
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        tokio::net::TcpStream::try_write(self, buf)
+    }
+
+    fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        tokio::net::TcpStream::poll_write_ready(self, cx)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        tokio::net::TcpStream::local_addr(self)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        tokio::net::TcpStream::peer_addr(self)
+    }
+
+    fn shutdown(&self, how: Shutdown) {
+        _ = self
+            .as_socketlike_view::<std::net::TcpStream>()
+            .shutdown(how);
+    }
+
+    fn as_host_stream(&self) -> Option<&tokio::net::TcpStream> {
+        Some(self)
+    }
+}
+
+impl WasiUdpSocket for tokio::net::UdpSocket {
+    fn bind(&self, addr: SocketAddr) -> io::Result<()> {
+        Ok(udp_bind(self, addr)?)
+    }
+
+    fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        rustix::net::connect(self, &addr).map_err(|error| {
+            let code = match error {
+                Errno::AFNOSUPPORT => ErrorCode::InvalidArgument, // See `udp_bind` implementation.
+                Errno::INPROGRESS => {
+                    debug!("UDP connect returned EINPROGRESS, which should never happen");
+                    ErrorCode::Unknown
+                }
+                err => err.into(),
+            };
+            code.into()
+        })
+    }
+
+    fn disconnect(&self) -> io::Result<()> {
+        Ok(udp_disconnect(self)?)
+    }
+
+    fn try_send(&self, buf: &[u8]) -> io::Result<usize> {
+        tokio::net::UdpSocket::try_send(self, buf)
+    }
+
+    fn try_send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        tokio::net::UdpSocket::try_send_to(self, buf, addr)
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        tokio::net::UdpSocket::try_recv_from(self, buf)
+    }
+
+    fn poll_recv_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        tokio::net::UdpSocket::poll_recv_ready(self, cx)
+    }
+
+    fn poll_send_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        tokio::net::UdpSocket::poll_send_ready(self, cx)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.as_socketlike_view::<std::net::UdpSocket>()
+            .local_addr()
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.as_socketlike_view::<std::net::UdpSocket>().peer_addr()
+    }
+
+    fn as_host_socket(&self) -> Option<&tokio::net::UdpSocket> {
+        Some(self)
+    }
+}
+
+/// A TCP socket which is neither connected nor listening yet.
+pub(crate) struct TcpSocket(Box<dyn WasiTcpSocket>);
+
+impl TcpSocket {
+    pub(crate) fn new(socket: Box<dyn WasiTcpSocket>) -> TcpSocket {
+        TcpSocket(socket)
+    }
+
+    pub(crate) fn bind(&mut self, addr: SocketAddr) -> Result<(), ErrorCode> {
+        Ok(self.0.bind(addr)?)
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    pub(crate) fn listen(self, backlog: u32) -> io::Result<TcpListener> {
+        self.0.listen(backlog).map(TcpListener)
+    }
+
+    pub(crate) fn connect(
+        self,
+        addr: SocketAddr,
+    ) -> impl Future<Output = io::Result<TcpStream>> + Send + 'static {
+        async move { self.0.connect(addr).await.map(TcpStream) }
+    }
+
+    pub(crate) fn as_std_view(&self) -> Result<SocketlikeView<'_, std::net::TcpStream>, ErrorCode> {
+        match self.0.as_host_socket() {
+            Some(socket) => Ok(socket.as_socketlike_view()),
+            None => Err(ErrorCode::NotSupported),
+        }
+    }
+}
+
+/// A listening TCP socket.
+pub(crate) struct TcpListener(Box<dyn WasiTcpListener>);
+
+impl TcpListener {
+    pub(crate) fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<TcpStream>> {
+        self.0.poll_accept(cx).map_ok(TcpStream)
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    /// Changes the size of the listen queue, if supported.
+    pub(crate) fn set_backlog(&self, value: u32) -> Result<(), ErrorCode> {
+        Ok(self.0.set_backlog(value)?)
+    }
+
+    pub(crate) fn as_std_view(&self) -> Result<SocketlikeView<'_, std::net::TcpStream>, ErrorCode> {
+        match self.0.as_host_listener() {
+            Some(listener) => Ok(listener.as_socketlike_view()),
+            None => Err(ErrorCode::NotSupported),
+        }
+    }
+}
+
+/// A connected TCP socket.
+pub(crate) struct TcpStream(Box<dyn WasiTcpStream>);
+
+impl TcpStream {
+    pub(crate) fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.try_read(buf)
+    }
+
+    /// Reads into the spare capacity of `buf`.
+    pub(crate) fn try_read_buf(&self, buf: &mut bytes::BytesMut) -> io::Result<usize> {
+        if let Some(stream) = self.0.as_host_stream() {
+            return stream.try_read_buf(buf);
+        }
+        let len = buf.len();
+        buf.resize(buf.capacity(), 0);
+        let result = self.0.try_read(&mut buf[len..]);
+        buf.truncate(len + *result.as_ref().unwrap_or(&0));
+        result
+    }
+
+    pub(crate) fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.0.poll_read_ready(cx)
+    }
+
+    pub(crate) async fn readable(&self) -> io::Result<()> {
+        match self.0.as_host_stream() {
+            Some(stream) => stream.readable().await,
+            None => futures::future::poll_fn(|cx| self.0.poll_read_ready(cx)).await,
+        }
+    }
+
+    pub(crate) fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.0.try_write(buf)
+    }
+
+    pub(crate) fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.0.poll_write_ready(cx)
+    }
+
+    pub(crate) async fn writable(&self) -> io::Result<()> {
+        match self.0.as_host_stream() {
+            Some(stream) => stream.writable().await,
+            None => futures::future::poll_fn(|cx| self.0.poll_write_ready(cx)).await,
+        }
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.0.peer_addr()
+    }
+
+    /// Shuts down the read and/or write half of this connection, ignoring
+    /// errors.
+    pub(crate) fn shutdown(&self, how: Shutdown) {
+        self.0.shutdown(how)
+    }
+
+    /// Returns the host socket, for applying socket options.
+    pub(crate) fn as_host(&self) -> Option<&tokio::net::TcpStream> {
+        self.0.as_host_stream()
+    }
+
+    pub(crate) fn as_std_view(&self) -> Result<SocketlikeView<'_, std::net::TcpStream>, ErrorCode> {
+        match self.0.as_host_stream() {
+            Some(stream) => Ok(stream.as_socketlike_view()),
+            None => Err(ErrorCode::NotSupported),
+        }
+    }
+}
+
+/// A UDP socket.
+pub(crate) struct UdpSocket(Box<dyn WasiUdpSocket>);
+
+impl UdpSocket {
+    pub(crate) fn new(socket: Box<dyn WasiUdpSocket>) -> UdpSocket {
+        UdpSocket(socket)
+    }
+
+    pub(crate) fn bind(&self, addr: SocketAddr) -> Result<(), ErrorCode> {
+        Ok(self.0.bind(addr)?)
+    }
+
+    pub(crate) fn connect(&self, addr: SocketAddr) -> Result<(), ErrorCode> {
+        Ok(self.0.connect(addr)?)
+    }
+
+    pub(crate) fn disconnect(&self) -> Result<(), ErrorCode> {
+        Ok(self.0.disconnect()?)
+    }
+
+    pub(crate) fn try_send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.0.try_send(buf)
+    }
+
+    pub(crate) fn try_send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        self.0.try_send_to(buf, addr)
+    }
+
+    pub(crate) fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.0.try_recv_from(buf)
+    }
+
+    #[cfg(feature = "p3")]
+    pub(crate) async fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(socket) = self.0.as_host_socket() {
+            return socket.send(buf).await;
+        }
+        loop {
+            self.writable().await?;
+            match self.0.try_send(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                result => break result,
+            }
+        }
+    }
+
+    #[cfg(feature = "p3")]
+    pub(crate) async fn send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        if let Some(socket) = self.0.as_host_socket() {
+            return socket.send_to(buf, addr).await;
+        }
+        loop {
+            self.writable().await?;
+            match self.0.try_send_to(buf, addr) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                result => break result,
+            }
+        }
+    }
+
+    #[cfg(feature = "p3")]
+    pub(crate) async fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        if let Some(socket) = self.0.as_host_socket() {
+            return socket.recv(buf).await;
+        }
+        self.recv_from(buf).await.map(|(n, _)| n)
+    }
+
+    #[cfg(feature = "p3")]
+    pub(crate) async fn recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        if let Some(socket) = self.0.as_host_socket() {
+            return socket.recv_from(buf).await;
+        }
+        loop {
+            self.readable().await?;
+            match self.0.try_recv_from(buf) {
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                result => break result,
+            }
+        }
+    }
+
+    pub(crate) async fn readable(&self) -> io::Result<()> {
+        match self.0.as_host_socket() {
+            // FIXME: Add `Interest::ERROR` when we update to tokio 1.32.
+            Some(socket) => socket.ready(Interest::READABLE).await.map(drop),
+            None => futures::future::poll_fn(|cx| self.0.poll_recv_ready(cx)).await,
+        }
+    }
+
+    pub(crate) async fn writable(&self) -> io::Result<()> {
+        match self.0.as_host_socket() {
+            // FIXME: Add `Interest::ERROR` when we update to tokio 1.32.
+            Some(socket) => socket.ready(Interest::WRITABLE).await.map(drop),
+            None => futures::future::poll_fn(|cx| self.0.poll_send_ready(cx)).await,
+        }
+    }
+
+    pub(crate) fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.local_addr()
+    }
+
+    pub(crate) fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.0.peer_addr()
+    }
+
+    /// Returns the host socket, for getting and setting socket options.
+    pub(crate) fn as_host(&self) -> Result<&tokio::net::UdpSocket, ErrorCode> {
+        self.0.as_host_socket().ok_or(ErrorCode::NotSupported)
+    }
+}
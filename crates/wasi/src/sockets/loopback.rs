@@ -0,0 +1,687 @@
+//! An in-process network, see [`LoopbackNetwork`].
+
+use crate::sockets::SocketAddressFamily;
+use crate::sockets::backend::{
+    WasiNetwork, WasiTcpListener, WasiTcpSocket, WasiTcpStream, WasiUdpSocket,
+};
+use crate::sockets::util::ErrorCode;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::task::{Context, Poll, Waker};
+
+/// The first port handed out when binding to port 0, which is the start of
+/// the dynamic port range of RFC 6335.
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// The number of bytes which can be written to a TCP connection before the
+/// peer has to read some of them.
+const TCP_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The number of datagrams queued on a UDP socket before further ones are
+/// dropped.
+const UDP_QUEUE_LENGTH: usize = 64;
+
+/// A network which lives entirely within the host process.
+///
+/// Sockets of guests whose [`WasiCtx`](crate::WasiCtx) was built with
+/// [`WasiCtxBuilder::loopback_network`](crate::WasiCtxBuilder::loopback_network)
+/// are attached to this network instead of the host's network stack. They
+/// can bind to any unicast address and can only reach each other, so several
+/// stores, for example one per service of an application, can talk to each
+/// other over TCP and UDP without touching any real interface.
+///
+/// Cloning a `LoopbackNetwork` returns another handle to the same network.
+/// Addresses used by guests are still subject to
+/// [`WasiCtxBuilder::socket_addr_check`](crate::WasiCtxBuilder::socket_addr_check),
+/// and name lookups only resolve `localhost` and names added with
+/// [`LoopbackNetwork::add_host`]. Socket options such as buffer sizes and
+/// keep-alive aren't supported on this network and fail with `not-supported`.
+///
+/// # Examples
+///
+/// ```
+/// use wasmtime_wasi::WasiCtxBuilder;
+/// use wasmtime_wasi::sockets::LoopbackNetwork;
+///
+/// let network = LoopbackNetwork::new();
+/// network.add_host("backend.internal", "10.0.0.2".parse().unwrap());
+///
+/// // The backend listens on `10.0.0.2:8080` ...
+/// let backend = WasiCtxBuilder::new()
+///     .loopback_network(network.clone())
+///     .inherit_network()
+///     .build();
+///
+/// // ... which the frontend connects to as `backend.internal:8080`.
+/// let frontend = WasiCtxBuilder::new()
+///     .loopback_network(network)
+///     .inherit_network()
+///     .allow_ip_name_lookup(true)
+///     .build();
+/// ```
+#[derive(Clone, Default)]
+pub struct LoopbackNetwork {
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Addresses bound by TCP sockets, along with the connections waiting to
+    /// be accepted if the socket is listening.
+    tcp: HashMap<SocketAddr, Weak<Backlog>>,
+    /// Addresses bound by UDP sockets, along with the datagrams received by
+    /// them.
+    udp: HashMap<SocketAddr, Weak<Datagrams>>,
+    /// Addresses of names added with [`LoopbackNetwork::add_host`].
+    hosts: HashMap<String, Vec<IpAddr>>,
+    /// The offset from [`FIRST_EPHEMERAL_PORT`] of the next port to try when
+    /// binding to port 0.
+    next_port: u16,
+}
+
+#[derive(Clone, Copy)]
+enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl LoopbackNetwork {
+    /// Creates a new network without any sockets.
+    pub fn new() -> LoopbackNetwork {
+        LoopbackNetwork::default()
+    }
+
+    /// Makes lookups of `name` by guests on this network resolve to `addr`,
+    /// in addition to any addresses previously added for it.
+    pub fn add_host(&self, name: &str, addr: IpAddr) {
+        self.lock()
+            .hosts
+            .entry(name.to_ascii_lowercase())
+            .or_default()
+            .push(addr);
+    }
+
+    /// Returns the addresses of `name`, or `None` if it isn't known on this
+    /// network.
+    fn resolve(&self, name: &str) -> Option<Vec<IpAddr>> {
+        let name = name.to_ascii_lowercase();
+        if let Some(addrs) = self.lock().hosts.get(&name) {
+            return Some(addrs.clone());
+        }
+        if name == "localhost" {
+            return Some(vec![Ipv4Addr::LOCALHOST.into(), Ipv6Addr::LOCALHOST.into()]);
+        }
+        None
+    }
+
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Reserves `addr` for a socket of `protocol`, picking a free port if its
+    /// port is 0.
+    fn bind(&self, protocol: Protocol, mut addr: SocketAddr) -> io::Result<Binding> {
+        let mut state = self.lock();
+        if addr.port() == 0 {
+            let count = u16::MAX - FIRST_EPHEMERAL_PORT + 1;
+            let found = (0..count).any(|_| {
+                addr.set_port(FIRST_EPHEMERAL_PORT + state.next_port);
+                state.next_port = (state.next_port + 1) % count;
+                !state.in_use(protocol, addr)
+            });
+            if !found {
+                return Err(io::ErrorKind::AddrInUse.into());
+            }
+        } else if state.in_use(protocol, addr) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        match protocol {
+            Protocol::Tcp => {
+                state.tcp.insert(addr, Weak::new());
+            }
+            Protocol::Udp => {
+                state.udp.insert(addr, Weak::new());
+            }
+        }
+        Ok(Binding {
+            network: self.clone(),
+            protocol,
+            addr,
+        })
+    }
+}
+
+impl WasiNetwork for LoopbackNetwork {
+    fn tcp_socket(&self, family: SocketAddressFamily) -> io::Result<Box<dyn WasiTcpSocket>> {
+        Ok(Box::new(TcpSocket {
+            network: self.clone(),
+            family,
+            binding: None,
+        }))
+    }
+
+    fn udp_socket(&self, family: SocketAddressFamily) -> io::Result<Box<dyn WasiUdpSocket>> {
+        Ok(Box::new(UdpSocket {
+            network: self.clone(),
+            family,
+            datagrams: Arc::default(),
+            binding: Mutex::new(None),
+        }))
+    }
+
+    fn resolve_addresses(
+        &self,
+        name: &str,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Vec<IpAddr>>> + Send>> {
+        let addrs = self
+            .resolve(name)
+            .ok_or_else(|| io::ErrorKind::NotFound.into());
+        Box::pin(std::future::ready(addrs))
+    }
+}
+
+impl State {
+    /// Returns whether binding to `addr` would conflict with an existing
+    /// socket of `protocol`.
+    fn in_use(&self, protocol: Protocol, addr: SocketAddr) -> bool {
+        let conflicts = |bound: &SocketAddr| {
+            bound.port() == addr.port()
+                && bound.is_ipv4() == addr.is_ipv4()
+                && (bound.ip() == addr.ip()
+                    || bound.ip().is_unspecified()
+                    || addr.ip().is_unspecified())
+        };
+        match protocol {
+            Protocol::Tcp => self.tcp.keys().any(conflicts),
+            Protocol::Udp => self.udp.keys().any(conflicts),
+        }
+    }
+}
+
+/// Looks up the socket which receives what is sent to `addr`, which is bound
+/// either to `addr` itself or to the unspecified address of its family.
+fn lookup<T>(sockets: &HashMap<SocketAddr, Weak<T>>, addr: SocketAddr) -> Option<Arc<T>> {
+    sockets
+        .get(&addr)
+        .or_else(|| sockets.get(&SocketAddr::new(unspecified(addr.ip()), addr.port())))
+        .and_then(Weak::upgrade)
+}
+
+fn unspecified(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    }
+}
+
+/// The address which packets sent from a socket bound to `bound` come from.
+fn source(bound: SocketAddr) -> SocketAddr {
+    match bound.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => {
+            SocketAddr::new(Ipv4Addr::LOCALHOST.into(), bound.port())
+        }
+        IpAddr::V6(ip) if ip.is_unspecified() => {
+            SocketAddr::new(Ipv6Addr::LOCALHOST.into(), bound.port())
+        }
+        _ => bound,
+    }
+}
+
+fn family_unspecified(family: SocketAddressFamily) -> SocketAddr {
+    match family {
+        SocketAddressFamily::Ipv4 => (Ipv4Addr::UNSPECIFIED, 0).into(),
+        SocketAddressFamily::Ipv6 => (Ipv6Addr::UNSPECIFIED, 0).into(),
+    }
+}
+
+/// An address reserved by a socket, which is released when dropped.
+struct Binding {
+    network: LoopbackNetwork,
+    protocol: Protocol,
+    addr: SocketAddr,
+}
+
+impl Drop for Binding {
+    fn drop(&mut self) {
+        let mut state = self.network.lock();
+        match self.protocol {
+            Protocol::Tcp => {
+                state.tcp.remove(&self.addr);
+            }
+            Protocol::Udp => {
+                state.udp.remove(&self.addr);
+            }
+        }
+    }
+}
+
+/// Tasks waiting for a change of some state.
+#[derive(Default)]
+struct Wakers(Vec<Waker>);
+
+impl Wakers {
+    fn register(&mut self, waker: &Waker) {
+        if !self.0.iter().any(|w| w.will_wake(waker)) {
+            self.0.push(waker.clone());
+        }
+    }
+
+    fn wake(&mut self) {
+        for waker in self.0.drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// A TCP socket which is neither connected nor listening yet.
+struct TcpSocket {
+    network: LoopbackNetwork,
+    family: SocketAddressFamily,
+    binding: Option<Binding>,
+}
+
+impl TcpSocket {
+    /// Returns the address reserved by the socket, binding it to a free port
+    /// first if it isn't bound yet.
+    fn take_binding(self) -> io::Result<Binding> {
+        match self.binding {
+            Some(binding) => Ok(binding),
+            None => self
+                .network
+                .bind(Protocol::Tcp, family_unspecified(self.family)),
+        }
+    }
+
+    fn connect_to(self, remote: SocketAddr) -> io::Result<TcpStream> {
+        let network = self.network.clone();
+        let binding = self.take_binding()?;
+        let local = source(binding.addr);
+        let backlog = lookup(&network.lock().tcp, remote);
+        let Some(backlog) = backlog else {
+            return Err(io::ErrorKind::ConnectionRefused.into());
+        };
+
+        let incoming = Arc::new(Pipe::default());
+        let outgoing = Arc::new(Pipe::default());
+        let server = TcpStream {
+            local: remote,
+            peer: local,
+            incoming: outgoing.clone(),
+            outgoing: incoming.clone(),
+            _binding: None,
+        };
+        let mut state = backlog.state.lock().unwrap();
+        if state.pending.len() >= state.size {
+            return Err(io::ErrorKind::ConnectionRefused.into());
+        }
+        state.pending.push_back(server);
+        state.acceptors.wake();
+        Ok(TcpStream {
+            local,
+            peer: remote,
+            incoming,
+            outgoing,
+            _binding: Some(binding),
+        })
+    }
+}
+
+impl WasiTcpSocket for TcpSocket {
+    fn bind(&mut self, addr: SocketAddr) -> io::Result<()> {
+        if self.binding.is_some() {
+            return Err(ErrorCode::InvalidState.into());
+        }
+        self.binding = Some(self.network.bind(Protocol::Tcp, addr)?);
+        Ok(())
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        match &self.binding {
+            Some(binding) => Ok(binding.addr),
+            None => Err(io::ErrorKind::InvalidInput.into()),
+        }
+    }
+
+    fn listen(self: Box<Self>, backlog: u32) -> io::Result<Box<dyn WasiTcpListener>> {
+        let network = self.network.clone();
+        let binding = self.take_binding()?;
+        let backlog = Arc::new(Backlog {
+            state: Mutex::new(BacklogState {
+                pending: VecDeque::new(),
+                size: backlog.try_into().unwrap_or(usize::MAX),
+                acceptors: Wakers::default(),
+            }),
+        });
+        network
+            .lock()
+            .tcp
+            .insert(binding.addr, Arc::downgrade(&backlog));
+        Ok(Box::new(TcpListener { backlog, binding }))
+    }
+
+    fn connect(
+        self: Box<Self>,
+        addr: SocketAddr,
+    ) -> Pin<Box<dyn Future<Output = io::Result<Box<dyn WasiTcpStream>>> + Send>> {
+        let stream = self.connect_to(addr);
+        Box::pin(std::future::ready(
+            stream.map(|stream| Box::new(stream) as Box<dyn WasiTcpStream>),
+        ))
+    }
+}
+
+/// Connections to a listening TCP socket which haven't been accepted yet.
+struct Backlog {
+    state: Mutex<BacklogState>,
+}
+
+struct BacklogState {
+    pending: VecDeque<TcpStream>,
+    size: usize,
+    acceptors: Wakers,
+}
+
+/// A listening TCP socket.
+struct TcpListener {
+    backlog: Arc<Backlog>,
+    binding: Binding,
+}
+
+impl WasiTcpListener for TcpListener {
+    fn poll_accept(&self, cx: &mut Context<'_>) -> Poll<io::Result<Box<dyn WasiTcpStream>>> {
+        let mut state = self.backlog.state.lock().unwrap();
+        match state.pending.pop_front() {
+            Some(stream) => Poll::Ready(Ok(Box::new(stream))),
+            None => {
+                state.acceptors.register(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.binding.addr)
+    }
+
+    fn set_backlog(&self, size: u32) -> io::Result<()> {
+        self.backlog.state.lock().unwrap().size = size.try_into().unwrap_or(usize::MAX);
+        Ok(())
+    }
+}
+
+/// One direction of a TCP connection.
+#[derive(Default)]
+struct Pipe {
+    state: Mutex<PipeState>,
+}
+
+#[derive(Default)]
+struct PipeState {
+    buf: VecDeque<u8>,
+    /// Whether nothing more will be written, after which reads return the
+    /// rest of `buf` followed by the end of the stream.
+    write_closed: bool,
+    /// Whether nothing more will be read, after which writes fail.
+    read_closed: bool,
+    readers: Wakers,
+    writers: Wakers,
+}
+
+impl Pipe {
+    fn lock(&self) -> MutexGuard<'_, PipeState> {
+        self.state.lock().unwrap()
+    }
+
+    fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        if state.buf.is_empty() && !buf.is_empty() {
+            if state.write_closed || state.read_closed {
+                return Ok(0);
+            }
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let n = buf.len().min(state.buf.len());
+        for (dst, src) in buf.iter_mut().zip(state.buf.drain(..n)) {
+            *dst = src;
+        }
+        state.writers.wake();
+        Ok(n)
+    }
+
+    fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.lock();
+        if !state.buf.is_empty() || state.write_closed || state.read_closed {
+            return Poll::Ready(Ok(()));
+        }
+        state.readers.register(cx.waker());
+        Poll::Pending
+    }
+
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        if state.write_closed || state.read_closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let n = buf.len().min(TCP_BUFFER_SIZE - state.buf.len());
+        if n == 0 && !buf.is_empty() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        state.buf.extend(&buf[..n]);
+        state.readers.wake();
+        Ok(n)
+    }
+
+    fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.lock();
+        if state.buf.len() < TCP_BUFFER_SIZE || state.write_closed || state.read_closed {
+            return Poll::Ready(Ok(()));
+        }
+        state.writers.register(cx.waker());
+        Poll::Pending
+    }
+
+    fn close_read(&self) {
+        let mut state = self.lock();
+        state.read_closed = true;
+        state.buf.clear();
+        state.readers.wake();
+        state.writers.wake();
+    }
+
+    fn close_write(&self) {
+        let mut state = self.lock();
+        state.write_closed = true;
+        state.readers.wake();
+        state.writers.wake();
+    }
+}
+
+/// A connected TCP socket.
+struct TcpStream {
+    local: SocketAddr,
+    peer: SocketAddr,
+    incoming: Arc<Pipe>,
+    outgoing: Arc<Pipe>,
+    /// The address reserved by the connecting side, accepted connections
+    /// share the address of their listener instead.
+    _binding: Option<Binding>,
+}
+
+impl WasiTcpStream for TcpStream {
+    fn try_read(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.incoming.try_read(buf)
+    }
+
+    fn poll_read_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.incoming.poll_read_ready(cx)
+    }
+
+    fn try_write(&self, buf: &[u8]) -> io::Result<usize> {
+        self.outgoing.try_write(buf)
+    }
+
+    fn poll_write_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.outgoing.poll_write_ready(cx)
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.local)
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.peer)
+    }
+
+    fn shutdown(&self, how: Shutdown) {
+        if let Shutdown::Read | Shutdown::Both = how {
+            self.incoming.close_read();
+        }
+        if let Shutdown::Write | Shutdown::Both = how {
+            self.outgoing.close_write();
+        }
+    }
+}
+
+impl Drop for TcpStream {
+    fn drop(&mut self) {
+        WasiTcpStream::shutdown(self, Shutdown::Both);
+    }
+}
+
+/// Datagrams received by a UDP socket.
+#[derive(Default)]
+struct Datagrams {
+    state: Mutex<DatagramsState>,
+}
+
+#[derive(Default)]
+struct DatagramsState {
+    queue: VecDeque<(Vec<u8>, SocketAddr)>,
+    /// The address the socket is connected to, if any, which is the only one
+    /// datagrams are accepted from.
+    peer: Option<SocketAddr>,
+    receivers: Wakers,
+}
+
+/// A UDP socket.
+struct UdpSocket {
+    network: LoopbackNetwork,
+    family: SocketAddressFamily,
+    datagrams: Arc<Datagrams>,
+    binding: Mutex<Option<Binding>>,
+}
+
+impl UdpSocket {
+    fn bind_locked(&self, addr: SocketAddr) -> io::Result<Binding> {
+        let binding = self.network.bind(Protocol::Udp, addr)?;
+        self.network
+            .lock()
+            .udp
+            .insert(binding.addr, Arc::downgrade(&self.datagrams));
+        Ok(binding)
+    }
+
+    /// Returns the address datagrams sent from this socket come from, binding
+    /// it to a free port first if it isn't bound yet.
+    fn source(&self) -> io::Result<SocketAddr> {
+        let mut binding = self.binding.lock().unwrap();
+        if binding.is_none() {
+            *binding = Some(self.bind_locked(family_unspecified(self.family))?);
+        }
+        Ok(source(binding.as_ref().unwrap().addr))
+    }
+}
+
+impl WasiUdpSocket for UdpSocket {
+    fn bind(&self, addr: SocketAddr) -> io::Result<()> {
+        let mut binding = self.binding.lock().unwrap();
+        if binding.is_some() {
+            return Err(ErrorCode::InvalidState.into());
+        }
+        *binding = Some(self.bind_locked(addr)?);
+        Ok(())
+    }
+
+    fn connect(&self, addr: SocketAddr) -> io::Result<()> {
+        self.source()?;
+        self.datagrams.state.lock().unwrap().peer = Some(addr);
+        Ok(())
+    }
+
+    fn disconnect(&self) -> io::Result<()> {
+        self.datagrams.state.lock().unwrap().peer = None;
+        Ok(())
+    }
+
+    fn try_send(&self, buf: &[u8]) -> io::Result<usize> {
+        let peer = self.peer_addr()?;
+        self.try_send_to(buf, peer)
+    }
+
+    fn try_send_to(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        let source = self.source()?;
+        // Like on a real network, datagrams which nobody receives are dropped.
+        let datagrams = lookup(&self.network.lock().udp, addr);
+        let Some(datagrams) = datagrams else {
+            return Ok(buf.len());
+        };
+        let mut state = datagrams.state.lock().unwrap();
+        if state.peer.is_none_or(|peer| peer == source) && state.queue.len() < UDP_QUEUE_LENGTH {
+            state.queue.push_back((buf.to_vec(), source));
+            state.receivers.wake();
+        }
+        Ok(buf.len())
+    }
+
+    fn try_recv_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let mut state = self.datagrams.state.lock().unwrap();
+        let Some((datagram, addr)) = state.queue.pop_front() else {
+            return Err(io::ErrorKind::WouldBlock.into());
+        };
+        // As with real sockets, the rest of a datagram which doesn't fit into
+        // `buf` is discarded.
+        let n = datagram.len().min(buf.len());
+        buf[..n].copy_from_slice(&datagram[..n]);
+        Ok((n, addr))
+    }
+
+    fn poll_recv_ready(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut state = self.datagrams.state.lock().unwrap();
+        if !state.queue.is_empty() {
+            return Poll::Ready(Ok(()));
+        }
+        state.receivers.register(cx.waker());
+        Poll::Pending
+    }
+
+    fn poll_send_ready(&self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        // Sending never blocks, datagrams are dropped instead.
+        Poll::Ready(Ok(()))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        let addr = match &*self.binding.lock().unwrap() {
+            Some(binding) => binding.addr,
+            None => return Err(io::ErrorKind::InvalidInput.into()),
+        };
+        // Connecting picks the local address just like it does on the host.
+        if self.peer_addr().is_ok() {
+            Ok(source(addr))
+        } else {
+            Ok(addr)
+        }
+    }
+
+    fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.datagrams
+            .state
+            .lock()
+            .unwrap()
+            .peer
+            .ok_or_else(|| io::ErrorKind::NotConnected.into())
+    }
+}
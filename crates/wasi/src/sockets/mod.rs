@@ -5,10 +5,16 @@ use std::pin::Pin;
 use std::sync::Arc;
 use wasmtime::component::{HasData, ResourceTable};
 
+pub(crate) mod backend;
+mod loopback;
 mod tcp;
 mod udp;
 pub(crate) mod util;
 
+pub use backend::{
+    HostNetwork, WasiNetwork, WasiTcpListener, WasiTcpSocket, WasiTcpStream, WasiUdpSocket,
+};
+pub use loopback::LoopbackNetwork;
 #[cfg(feature = "p3")]
 pub(crate) use tcp::NonInheritedOptions;
 pub use tcp::TcpSocket;
@@ -66,10 +72,22 @@ pub(crate) const DEFAULT_TCP_BACKLOG: u32 = 128;
 /// In practice, datagrams are typically less than 1500 bytes.
 pub(crate) const MAX_UDP_DATAGRAM_SIZE: usize = u16::MAX as usize;
 
-#[derive(Clone, Default)]
+#[derive(Clone)]
 pub struct WasiSocketsCtx {
     pub(crate) socket_addr_check: SocketAddrCheck,
     pub(crate) allowed_network_uses: AllowedNetworkUses,
+    /// The network sockets are attached to.
+    pub(crate) network: Arc<dyn WasiNetwork>,
+}
+
+impl Default for WasiSocketsCtx {
+    fn default() -> Self {
+        Self {
+            socket_addr_check: SocketAddrCheck::default(),
+            allowed_network_uses: AllowedNetworkUses::default(),
+            network: Arc::new(HostNetwork),
+        }
+    }
 }
 
 pub struct WasiSocketsCtxView<'a> {
@@ -193,8 +211,11 @@ pub enum SocketAddrUse {
     UdpOutgoingDatagram,
 }
 
-#[derive(Copy, Clone, Eq, PartialEq)]
-pub(crate) enum SocketAddressFamily {
+/// The address family of a socket.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SocketAddressFamily {
+    /// IPv4 sockets.
     Ipv4,
+    /// IPv6 sockets, which can't be used with IPv4 addresses.
     Ipv6,
 }
@@ -4,10 +4,9 @@ use crate::sockets::util::{
     ErrorCode, get_unicast_hop_limit, is_valid_address_family, is_valid_remote_address,
     is_valid_unicast_address, receive_buffer_size, send_buffer_size, set_keep_alive_count,
     set_keep_alive_idle_time, set_keep_alive_interval, set_receive_buffer_size,
-    set_send_buffer_size, set_unicast_hop_limit, tcp_bind,
+    set_send_buffer_size, set_unicast_hop_limit,
 };
-use crate::sockets::{DEFAULT_TCP_BACKLOG, SocketAddressFamily, WasiSocketsCtx};
-use io_lifetimes::AsSocketlike as _;
+use crate::sockets::{DEFAULT_TCP_BACKLOG, SocketAddressFamily, WasiSocketsCtx, backend};
 use io_lifetimes::views::SocketlikeView;
 use rustix::io::Errno;
 use rustix::net::sockopt;
@@ -30,37 +29,37 @@ enum TcpState {
     ///
     /// From here a socket can transition to `BindStarted`, `ListenStarted`, or
     /// `Connecting`.
-    Default(tokio::net::TcpSocket),
+    Default(backend::TcpSocket),
 
     /// A state indicating that a bind has been started and must be finished
     /// subsequently with `finish_bind`.
     ///
     /// From here a socket can transition to `Bound`.
-    BindStarted(tokio::net::TcpSocket),
+    BindStarted(backend::TcpSocket),
 
     /// Binding finished. The socket has an address but is not yet listening for
     /// connections.
     ///
     /// From here a socket can transition to `ListenStarted`, or `Connecting`.
-    Bound(tokio::net::TcpSocket),
+    Bound(backend::TcpSocket),
 
     /// Listening on a socket has started and must be completed with
     /// `finish_listen`.
     ///
     /// From here a socket can transition to `Listening`.
-    ListenStarted(tokio::net::TcpSocket),
+    ListenStarted(backend::TcpSocket),
 
     /// The socket is now listening and waiting for an incoming connection.
     ///
     /// Sockets will not leave this state.
     Listening {
-        /// The raw tokio-basd TCP listener managing the underlying socket.
-        listener: Arc<tokio::net::TcpListener>,
+        /// The raw TCP listener managing the underlying socket.
+        listener: Arc<backend::TcpListener>,
 
         /// The last-accepted connection, set during the `ready` method and read
         /// during the `accept` method. Note that this is only used for WASIp2
         /// at this time.
-        pending_accept: Option<io::Result<tokio::net::TcpStream>>,
+        pending_accept: Option<io::Result<backend::TcpStream>>,
     },
 
     /// An outgoing connection is started.
@@ -71,7 +70,7 @@ enum TcpState {
     /// so this is `None`.
     ///
     /// From here a socket can transition to `ConnectReady` or `Connected`.
-    Connecting(Option<Pin<Box<dyn Future<Output = io::Result<tokio::net::TcpStream>> + Send>>>),
+    Connecting(Option<Pin<Box<dyn Future<Output = io::Result<backend::TcpStream>> + Send>>>),
 
     /// A connection via `Connecting` has completed.
     ///
@@ -80,7 +79,7 @@ enum TcpState {
     /// finishes as part of the `ready` method.
     ///
     /// From here a socket can transition to `Connected`.
-    ConnectReady(io::Result<tokio::net::TcpStream>),
+    ConnectReady(io::Result<backend::TcpStream>),
 
     /// A connection has been established.
     ///
@@ -88,13 +87,13 @@ enum TcpState {
     /// sockets from a TCP listener.
     ///
     /// From here a socket can transition to `Receiving` or `P2Streaming`.
-    Connected(Arc<tokio::net::TcpStream>),
+    Connected(Arc<backend::TcpStream>),
 
     /// A connection has been established and `receive` has been called.
     ///
     /// A socket will not transition out of this state.
     #[cfg(feature = "p3")]
-    Receiving(Arc<tokio::net::TcpStream>),
+    Receiving(Arc<backend::TcpStream>),
 
     /// This is a WASIp2-bound socket which stores some extra state for
     /// read/write streams to handle TCP shutdown.
@@ -134,7 +133,7 @@ impl Debug for TcpState {
     }
 }
 
-/// A host TCP socket, plus associated bookkeeping.
+/// A TCP socket, plus associated bookkeeping.
 pub struct TcpSocket {
     /// The current state in the bind/listen/accept/connect progression.
     tcp_state: TcpState,
@@ -155,18 +154,11 @@ impl TcpSocket {
     ) -> Result<Self, ErrorCode> {
         ctx.allowed_network_uses.check_allowed_tcp()?;
 
-        with_ambient_tokio_runtime(|| {
-            let socket = match family {
-                SocketAddressFamily::Ipv4 => tokio::net::TcpSocket::new_v4()?,
-                SocketAddressFamily::Ipv6 => {
-                    let socket = tokio::net::TcpSocket::new_v6()?;
-                    sockopt::set_ipv6_v6only(&socket, true)?;
-                    socket
-                }
-            };
-
-            Ok(Self::from_state(TcpState::Default(socket), family))
-        })
+        let socket = ctx.network.tcp_socket(family)?;
+        Ok(Self::from_state(
+            TcpState::Default(backend::TcpSocket::new(socket)),
+            family,
+        ))
     }
 
     #[cfg(feature = "p3")]
@@ -180,7 +172,7 @@ impl TcpSocket {
     /// This will handle the `result` internally and `result` should be the raw
     /// result from a TCP listen operation.
     pub(crate) fn new_accept(
-        result: io::Result<tokio::net::TcpStream>,
+        result: io::Result<backend::TcpStream>,
         options: &NonInheritedOptions,
         family: SocketAddressFamily,
     ) -> io::Result<Self> {
@@ -217,7 +209,9 @@ impl TcpSocket {
 
             _ => err,
         })?;
-        options.apply(family, &client);
+        if let Some(stream) = client.as_host() {
+            options.apply(family, stream);
+        }
         Ok(Self::from_state(
             TcpState::Connected(Arc::new(client)),
             family,
@@ -239,12 +233,12 @@ impl TcpSocket {
             TcpState::Default(socket)
             | TcpState::BindStarted(socket)
             | TcpState::Bound(socket)
-            | TcpState::ListenStarted(socket) => Ok(socket.as_socketlike_view()),
-            TcpState::Connected(stream) => Ok(stream.as_socketlike_view()),
+            | TcpState::ListenStarted(socket) => socket.as_std_view(),
+            TcpState::Connected(stream) => stream.as_std_view(),
             #[cfg(feature = "p3")]
-            TcpState::Receiving(stream) => Ok(stream.as_socketlike_view()),
-            TcpState::Listening { listener, .. } => Ok(listener.as_socketlike_view()),
-            TcpState::P2Streaming(state) => Ok(state.stream.as_socketlike_view()),
+            TcpState::Receiving(stream) => stream.as_std_view(),
+            TcpState::Listening { listener, .. } => listener.as_std_view(),
+            TcpState::P2Streaming(state) => state.stream.as_std_view(),
             TcpState::Connecting(..) | TcpState::ConnectReady(_) | TcpState::Closed => {
                 Err(ErrorCode::InvalidState)
             }
@@ -259,8 +253,8 @@ impl TcpSocket {
             return Err(ErrorCode::InvalidArgument);
         }
         match mem::replace(&mut self.tcp_state, TcpState::Closed) {
-            TcpState::Default(sock) => {
-                if let Err(err) = tcp_bind(&sock, addr) {
+            TcpState::Default(mut sock) => {
+                if let Err(err) = sock.bind(addr) {
                     self.tcp_state = TcpState::Default(sock);
                     Err(err)
                 } else {
@@ -292,7 +286,7 @@ impl TcpSocket {
     pub(crate) fn start_connect(
         &mut self,
         addr: &SocketAddr,
-    ) -> Result<tokio::net::TcpSocket, ErrorCode> {
+    ) -> Result<backend::TcpSocket, ErrorCode> {
         match self.tcp_state {
             TcpState::Default(..) | TcpState::Bound(..) => {}
             TcpState::Connecting(..) => {
@@ -308,20 +302,20 @@ impl TcpSocket {
             return Err(ErrorCode::InvalidArgument);
         };
 
-        let (TcpState::Default(tokio_socket) | TcpState::Bound(tokio_socket)) =
+        let (TcpState::Default(socket) | TcpState::Bound(socket)) =
             mem::replace(&mut self.tcp_state, TcpState::Connecting(None))
         else {
             unreachable!();
         };
 
-        Ok(tokio_socket)
+        Ok(socket)
     }
 
     /// For WASIp2 this is used to record the actual connection future as part
     /// of `start_connect` within this socket state.
     pub(crate) fn set_pending_connect(
         &mut self,
-        future: impl Future<Output = io::Result<tokio::net::TcpStream>> + Send + 'static,
+        future: impl Future<Output = io::Result<backend::TcpStream>> + Send + 'static,
     ) -> Result<(), ErrorCode> {
         match &mut self.tcp_state {
             TcpState::Connecting(slot @ None) => {
@@ -342,7 +336,7 @@ impl TcpSocket {
     /// * `Err(e)` - a connect operation is not in progress.
     pub(crate) fn take_pending_connect(
         &mut self,
-    ) -> Result<Option<io::Result<tokio::net::TcpStream>>, ErrorCode> {
+    ) -> Result<Option<io::Result<backend::TcpStream>>, ErrorCode> {
         match mem::replace(&mut self.tcp_state, TcpState::Connecting(None)) {
             TcpState::ConnectReady(result) => Ok(Some(result)),
             TcpState::Connecting(Some(mut future)) => {
@@ -364,7 +358,7 @@ impl TcpSocket {
 
     pub(crate) fn finish_connect(
         &mut self,
-        result: io::Result<tokio::net::TcpStream>,
+        result: io::Result<backend::TcpStream>,
     ) -> Result<(), ErrorCode> {
         if !matches!(self.tcp_state, TcpState::Connecting(None)) {
             return Err(ErrorCode::InvalidState);
@@ -383,8 +377,8 @@ impl TcpSocket {
 
     pub(crate) fn start_listen(&mut self) -> Result<(), ErrorCode> {
         match mem::replace(&mut self.tcp_state, TcpState::Closed) {
-            TcpState::Bound(tokio_socket) => {
-                self.tcp_state = TcpState::ListenStarted(tokio_socket);
+            TcpState::Bound(socket) => {
+                self.tcp_state = TcpState::ListenStarted(socket);
                 Ok(())
             }
             previous_state => {
@@ -395,15 +389,15 @@ impl TcpSocket {
     }
 
     pub(crate) fn finish_listen(&mut self) -> Result<(), ErrorCode> {
-        let tokio_socket = match mem::replace(&mut self.tcp_state, TcpState::Closed) {
-            TcpState::ListenStarted(tokio_socket) => tokio_socket,
+        let socket = match mem::replace(&mut self.tcp_state, TcpState::Closed) {
+            TcpState::ListenStarted(socket) => socket,
             previous_state => {
                 self.tcp_state = previous_state;
                 return Err(ErrorCode::NotInProgress);
             }
         };
 
-        match with_ambient_tokio_runtime(|| tokio_socket.listen(self.listen_backlog_size)) {
+        match with_ambient_tokio_runtime(|| socket.listen(self.listen_backlog_size)) {
             Ok(listener) => {
                 self.tcp_state = TcpState::Listening {
                     listener: Arc::new(listener),
@@ -446,9 +440,7 @@ impl TcpSocket {
             Some(result) => result,
             None => {
                 let mut cx = std::task::Context::from_waker(Waker::noop());
-                match with_ambient_tokio_runtime(|| listener.poll_accept(&mut cx))
-                    .map_ok(|(stream, _)| stream)
-                {
+                match with_ambient_tokio_runtime(|| listener.poll_accept(&mut cx)) {
                     Poll::Ready(result) => result,
                     Poll::Pending => return Ok(None),
                 }
@@ -459,7 +451,7 @@ impl TcpSocket {
     }
 
     #[cfg(feature = "p3")]
-    pub(crate) fn start_receive(&mut self) -> Option<&Arc<tokio::net::TcpStream>> {
+    pub(crate) fn start_receive(&mut self) -> Option<&Arc<backend::TcpStream>> {
         match mem::replace(&mut self.tcp_state, TcpState::Closed) {
             TcpState::Connected(stream) => {
                 self.tcp_state = TcpState::Receiving(stream);
@@ -519,11 +511,10 @@ impl TcpSocket {
                 Ok(())
             }
             TcpState::Listening { listener, .. } => {
-                // Try to update the backlog by calling `listen` again.
-                // Not all platforms support this. We'll only update our own value if the OS supports changing the backlog size after the fact.
-                if rustix::net::listen(&listener, value.try_into().unwrap_or(i32::MAX)).is_err() {
-                    return Err(ErrorCode::NotSupported);
-                }
+                // Try to update the backlog of the listener. We'll only update
+                // our own value if changing the backlog size after the fact is
+                // supported.
+                listener.set_backlog(value)?;
                 self.listen_backlog_size = value;
                 Ok(())
             }
@@ -635,7 +626,7 @@ impl TcpSocket {
     }
 
     #[cfg(feature = "p3")]
-    pub(crate) fn tcp_listener_arc(&self) -> Result<&Arc<tokio::net::TcpListener>, ErrorCode> {
+    pub(crate) fn tcp_listener_arc(&self) -> Result<&Arc<backend::TcpListener>, ErrorCode> {
         match &self.tcp_state {
             TcpState::Listening { listener, .. } => Ok(listener),
             #[cfg(feature = "p3")]
@@ -644,7 +635,7 @@ impl TcpSocket {
         }
     }
 
-    pub(crate) fn tcp_stream_arc(&self) -> Result<&Arc<tokio::net::TcpStream>, ErrorCode> {
+    pub(crate) fn tcp_stream_arc(&self) -> Result<&Arc<backend::TcpStream>, ErrorCode> {
         match &self.tcp_state {
             TcpState::Connected(socket) => Ok(socket),
             #[cfg(feature = "p3")]
@@ -708,10 +699,7 @@ impl TcpSocket {
                 listener,
                 pending_accept: slot @ None,
             } => {
-                let result = futures::future::poll_fn(|cx| {
-                    listener.poll_accept(cx).map_ok(|(stream, _)| stream)
-                })
-                .await;
+                let result = futures::future::poll_fn(|cx| listener.poll_accept(cx)).await;
                 *slot = Some(result);
             }
         }
@@ -1,18 +1,12 @@
-use crate::runtime::with_ambient_tokio_runtime;
 use crate::sockets::util::{
     ErrorCode, get_unicast_hop_limit, is_valid_address_family, is_valid_remote_address,
     receive_buffer_size, send_buffer_size, set_receive_buffer_size, set_send_buffer_size,
-    set_unicast_hop_limit, udp_bind, udp_disconnect, udp_socket,
+    set_unicast_hop_limit,
 };
-use crate::sockets::{SocketAddrCheck, SocketAddressFamily, WasiSocketsCtx};
+use crate::sockets::{SocketAddrCheck, SocketAddressFamily, WasiSocketsCtx, backend};
 use cap_net_ext::AddressFamily;
-use io_lifetimes::AsSocketlike as _;
-use io_lifetimes::raw::{FromRawSocketlike as _, IntoRawSocketlike as _};
-use rustix::io::Errno;
-use rustix::net::connect;
 use std::net::SocketAddr;
 use std::sync::Arc;
-use tracing::debug;
 
 /// The state of a UDP socket.
 ///
@@ -38,12 +32,12 @@ enum UdpState {
     Connected(SocketAddr),
 }
 
-/// A host UDP socket, plus associated bookkeeping.
+/// A UDP socket, plus associated bookkeeping.
 ///
 /// The inner state is wrapped in an Arc because the same underlying socket is
 /// used for implementing the stream types.
 pub struct UdpSocket {
-    socket: Arc<tokio::net::UdpSocket>,
+    socket: Arc<backend::UdpSocket>,
 
     /// The current state in the bind/connect progression.
     udp_state: UdpState,
@@ -61,33 +55,21 @@ impl UdpSocket {
     pub(crate) fn new(cx: &WasiSocketsCtx, family: AddressFamily) -> Result<Self, ErrorCode> {
         cx.allowed_network_uses.check_allowed_udp()?;
 
-        // Delegate socket creation to cap_net_ext. They handle a couple of things for us:
-        // - On Windows: call WSAStartup if not done before.
-        // - Set the NONBLOCK and CLOEXEC flags. Either immediately during socket creation,
-        //   or afterwards using ioctl or fcntl. Exact method depends on the platform.
-
-        let fd = udp_socket(family)?;
-
-        let socket_address_family = match family {
+        let family = match family {
             AddressFamily::Ipv4 => SocketAddressFamily::Ipv4,
-            AddressFamily::Ipv6 => {
-                rustix::net::sockopt::set_ipv6_v6only(&fd, true)?;
-                SocketAddressFamily::Ipv6
-            }
+            AddressFamily::Ipv6 => SocketAddressFamily::Ipv6,
         };
+        let socket = cx.network.udp_socket(family)?;
+        Ok(Self::from_socket(backend::UdpSocket::new(socket), family))
+    }
 
-        let socket = with_ambient_tokio_runtime(|| {
-            tokio::net::UdpSocket::try_from(unsafe {
-                std::net::UdpSocket::from_raw_socketlike(fd.into_raw_socketlike())
-            })
-        })?;
-
-        Ok(Self {
+    fn from_socket(socket: backend::UdpSocket, family: SocketAddressFamily) -> Self {
+        Self {
             socket: Arc::new(socket),
             udp_state: UdpState::Default,
-            family: socket_address_family,
+            family,
             socket_addr_check: None,
-        })
+        }
     }
 
     pub(crate) fn bind(&mut self, addr: SocketAddr) -> Result<(), ErrorCode> {
@@ -97,7 +79,7 @@ impl UdpSocket {
         if !is_valid_address_family(addr.ip(), self.family) {
             return Err(ErrorCode::InvalidArgument);
         }
-        udp_bind(&self.socket, addr)?;
+        self.socket.bind(addr)?;
         self.udp_state = UdpState::BindStarted;
         Ok(())
     }
@@ -124,7 +106,7 @@ impl UdpSocket {
         if !self.is_connected() {
             return Err(ErrorCode::InvalidState);
         }
-        udp_disconnect(&self.socket)?;
+        self.socket.disconnect()?;
         self.udp_state = UdpState::Bound;
         Ok(())
     }
@@ -147,18 +129,11 @@ impl UdpSocket {
 
         // Step #1: Disconnect
         if let UdpState::Connected(..) = self.udp_state {
-            udp_disconnect(&self.socket)?;
+            self.socket.disconnect()?;
             self.udp_state = UdpState::Bound;
         }
         // Step #2: (Re)connect
-        connect(&self.socket, &addr).map_err(|error| match error {
-            Errno::AFNOSUPPORT => ErrorCode::InvalidArgument, // See `udp_bind` implementation.
-            Errno::INPROGRESS => {
-                debug!("UDP connect returned EINPROGRESS, which should never happen");
-                ErrorCode::Unknown
-            }
-            err => err.into(),
-        })?;
+        self.socket.connect(addr)?;
         self.udp_state = UdpState::Connected(addr);
         Ok(())
     }
@@ -183,8 +158,8 @@ impl UdpSocket {
         addr: SocketAddr,
     ) -> impl Future<Output = Result<(), ErrorCode>> + use<> {
         enum Mode {
-            Send(Arc<tokio::net::UdpSocket>),
-            SendTo(Arc<tokio::net::UdpSocket>, SocketAddr),
+            Send(Arc<backend::UdpSocket>),
+            SendTo(Arc<backend::UdpSocket>, SocketAddr),
         }
         let socket = match &self.udp_state {
             UdpState::BindStarted => Err(ErrorCode::InvalidState),
@@ -207,8 +182,8 @@ impl UdpSocket {
         &self,
     ) -> impl Future<Output = Result<(Vec<u8>, SocketAddr), ErrorCode>> + use<> {
         enum Mode {
-            Recv(Arc<tokio::net::UdpSocket>, SocketAddr),
-            RecvFrom(Arc<tokio::net::UdpSocket>),
+            Recv(Arc<backend::UdpSocket>, SocketAddr),
+            RecvFrom(Arc<backend::UdpSocket>),
         }
         let socket = match self.udp_state {
             UdpState::Default | UdpState::BindStarted => Err(ErrorCode::InvalidState),
@@ -237,10 +212,7 @@ impl UdpSocket {
         if matches!(self.udp_state, UdpState::Default | UdpState::BindStarted) {
             return Err(ErrorCode::InvalidState);
         }
-        let addr = self
-            .socket
-            .as_socketlike_view::<std::net::UdpSocket>()
-            .local_addr()?;
+        let addr = self.socket.local_addr()?;
         Ok(addr)
     }
 
@@ -248,10 +220,7 @@ impl UdpSocket {
         if !matches!(self.udp_state, UdpState::Connected(..)) {
             return Err(ErrorCode::InvalidState);
         }
-        let addr = self
-            .socket
-            .as_socketlike_view::<std::net::UdpSocket>()
-            .peer_addr()?;
+        let addr = self.socket.peer_addr()?;
         Ok(addr)
     }
 
@@ -260,36 +229,36 @@ impl UdpSocket {
     }
 
     pub(crate) fn unicast_hop_limit(&self) -> Result<u8, ErrorCode> {
-        let n = get_unicast_hop_limit(&self.socket, self.family)?;
+        let n = get_unicast_hop_limit(self.socket.as_host()?, self.family)?;
         Ok(n)
     }
 
     pub(crate) fn set_unicast_hop_limit(&self, value: u8) -> Result<(), ErrorCode> {
-        set_unicast_hop_limit(&self.socket, self.family, value)?;
+        set_unicast_hop_limit(self.socket.as_host()?, self.family, value)?;
         Ok(())
     }
 
     pub(crate) fn receive_buffer_size(&self) -> Result<u64, ErrorCode> {
-        let n = receive_buffer_size(&self.socket)?;
+        let n = receive_buffer_size(self.socket.as_host()?)?;
         Ok(n)
     }
 
     pub(crate) fn set_receive_buffer_size(&self, value: u64) -> Result<(), ErrorCode> {
-        set_receive_buffer_size(&self.socket, value)?;
+        set_receive_buffer_size(self.socket.as_host()?, value)?;
         Ok(())
     }
 
     pub(crate) fn send_buffer_size(&self) -> Result<u64, ErrorCode> {
-        let n = send_buffer_size(&self.socket)?;
+        let n = send_buffer_size(self.socket.as_host()?)?;
         Ok(n)
     }
 
     pub(crate) fn set_send_buffer_size(&self, value: u64) -> Result<(), ErrorCode> {
-        set_send_buffer_size(&self.socket, value)?;
+        set_send_buffer_size(self.socket.as_host()?, value)?;
         Ok(())
     }
 
-    pub(crate) fn socket(&self) -> &Arc<tokio::net::UdpSocket> {
+    pub(crate) fn socket(&self) -> &Arc<backend::UdpSocket> {
         &self.socket
     }
 
@@ -303,7 +272,7 @@ impl UdpSocket {
 }
 
 #[cfg(feature = "p3")]
-async fn send(socket: &tokio::net::UdpSocket, buf: &[u8]) -> Result<(), ErrorCode> {
+async fn send(socket: &backend::UdpSocket, buf: &[u8]) -> Result<(), ErrorCode> {
     let n = socket.send(buf).await?;
     // From Rust stdlib docs:
     // > Note that the operating system may refuse buffers larger than 65507.
@@ -319,7 +288,7 @@ async fn send(socket: &tokio::net::UdpSocket, buf: &[u8]) -> Result<(), ErrorCod
 
 #[cfg(feature = "p3")]
 async fn send_to(
-    socket: &tokio::net::UdpSocket,
+    socket: &backend::UdpSocket,
     buf: &[u8],
     addr: SocketAddr,
 ) -> Result<(), ErrorCode> {
@@ -11,7 +11,7 @@ use tracing::debug;
 
 use crate::sockets::SocketAddressFamily;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub enum ErrorCode {
     Unknown,
     AccessDenied,
@@ -119,6 +119,11 @@ impl From<std::io::Error> for ErrorCode {
 
 impl From<&std::io::Error> for ErrorCode {
     fn from(value: &std::io::Error) -> Self {
+        // Errors of network backends may already carry an error code:
+        if let Some(code) = value.get_ref().and_then(|e| e.downcast_ref::<ErrorCode>()) {
+            return *code;
+        }
+
         // Attempt the more detailed native error code first:
         if let Some(errno) = Errno::from_io_error(value) {
             return errno.into();
@@ -144,6 +149,12 @@ impl From<&std::io::Error> for ErrorCode {
     }
 }
 
+impl From<ErrorCode> for std::io::Error {
+    fn from(value: ErrorCode) -> Self {
+        std::io::Error::other(value)
+    }
+}
+
 impl From<Errno> for ErrorCode {
     fn from(value: Errno) -> Self {
         (&value).into()
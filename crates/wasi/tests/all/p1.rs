@@ -3,9 +3,21 @@ use anyhow::Result;
 use std::path::Path;
 use test_programs_artifacts::*;
 use wasmtime::{Linker, Module};
+use wasmtime_wasi::WasiCtxBuilder;
+use wasmtime_wasi::filesystem::MemoryDir;
 use wasmtime_wasi::p1::{WasiP1Ctx, add_to_linker_async};
 
 async fn run(path: &str, inherit_stdio: bool) -> Result<()> {
+    run_with(path, inherit_stdio, None).await
+}
+
+/// Runs `path` with a [`MemoryDir`] preopened in place of a temporary directory
+/// of the host.
+async fn run_in_memory(path: &str) -> Result<()> {
+    run_with(path, false, Some(MemoryDir::new())).await
+}
+
+async fn run_with(path: &str, inherit_stdio: bool, dir: Option<MemoryDir>) -> Result<()> {
     let path = Path::new(path);
     let name = path.file_stem().unwrap().to_str().unwrap();
     let engine = test_programs_artifacts::engine(|config| {
@@ -15,12 +27,19 @@ async fn run(path: &str, inherit_stdio: bool) -> Result<()> {
     add_to_linker_async(&mut linker, |t| &mut t.wasi)?;
 
     let module = Module::from_file(&engine, path)?;
-    let (mut store, _td) = Ctx::new(&engine, name, |builder| {
+    let configure = |builder: &mut WasiCtxBuilder| {
         if inherit_stdio {
             builder.inherit_stdio();
         }
         builder.build_p1()
-    })?;
+    };
+    let (mut store, _td) = match dir {
+        Some(dir) => (Ctx::new_in_memory(&engine, name, dir, configure)?, None),
+        None => {
+            let (store, td) = Ctx::new(&engine, name, configure)?;
+            (store, Some(td))
+        }
+    };
     let instance = linker.instantiate_async(&mut store, &module).await?;
     let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
     start.call_async(&mut store, ()).await?;
@@ -249,3 +268,34 @@ async fn p1_path_open_lots() {
     reason = "tested in the wasi-cli crate, satisfying foreach_api! macro"
 )]
 fn p1_cli_much_stdout() {}
+
+// The filesystem tests which don't depend on the host's filesystem, run again
+// against a `MemoryDir`.
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p1_fd_readdir_memory_dir() {
+    run_in_memory(P1_FD_READDIR).await.unwrap()
+}
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p1_file_pread_pwrite_memory_dir() {
+    run_in_memory(P1_FILE_PREAD_PWRITE).await.unwrap()
+}
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p1_file_read_write_memory_dir() {
+    run_in_memory(P1_FILE_READ_WRITE).await.unwrap()
+}
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p1_file_seek_tell_memory_dir() {
+    run_in_memory(P1_FILE_SEEK_TELL).await.unwrap()
+}
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p1_file_truncation_memory_dir() {
+    run_in_memory(P1_FILE_TRUNCATION).await.unwrap()
+}
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p1_path_rename_memory_dir() {
+    run_in_memory(P1_PATH_RENAME).await.unwrap()
+}
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p1_readlink_memory_dir() {
+    run_in_memory(P1_READLINK).await.unwrap()
+}
@@ -4,8 +4,13 @@ use std::sync::Mutex;
 use std::time::Duration;
 use wasmtime::Store;
 use wasmtime::component::{Component, Linker, ResourceTable};
+use wasmtime_wasi::clocks::VirtualClock;
+use wasmtime_wasi::filesystem::{
+    FileType, MemoryDir, OpenOptions, Opened, OverlayChange, OverlayDir, WasiDir,
+};
 use wasmtime_wasi::p2::add_to_linker_async;
 use wasmtime_wasi::p2::bindings::{Command, clocks::wall_clock, filesystem::types as filesystem};
+use wasmtime_wasi::sockets::LoopbackNetwork;
 use wasmtime_wasi::{
     DirPerms, FilePerms, HostMonotonicClock, HostWallClock, WasiCtx, WasiCtxBuilder, WasiCtxView,
     WasiView,
@@ -111,6 +116,357 @@ async fn p2_api_read_only() -> Result<()> {
         .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))
 }
 
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_api_read_only_memory_dir() -> Result<()> {
+    let dir = MemoryDir::new();
+    dir.write_file("bar.txt", "And stood awhile in thought")?;
+    dir.create_dir_all("sub")?;
+
+    let table = ResourceTable::new();
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(dir, "/", DirPerms::READ, FilePerms::READ)
+        .build();
+
+    let (mut store, command) =
+        instantiate(P2_API_READ_ONLY_COMPONENT, CommandCtx { table, wasi }).await?;
+
+    command
+        .wasi_cli_run()
+        .call_run(&mut store)
+        .await?
+        .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))
+}
+
+#[cfg(feature = "tar")]
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_api_read_only_archive_dir() -> Result<()> {
+    let mut archive = tar::Builder::new(Vec::new());
+    let contents = "And stood awhile in thought";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(contents.len() as u64);
+    archive.append_data(&mut header, "bar.txt", contents.as_bytes())?;
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_size(0);
+    archive.append_data(&mut header, "sub", std::io::empty())?;
+    let archive = archive.into_inner()?;
+
+    // The directory is read-only even if it's preopened for writing.
+    let dir = wasmtime_wasi::filesystem::ArchiveDir::from_tar(&archive[..])?;
+    let err = dir.create_dir_at("new").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::ReadOnlyFilesystem);
+
+    let table = ResourceTable::new();
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(dir, "/", DirPerms::READ, FilePerms::READ)
+        .build();
+
+    let (mut store, command) =
+        instantiate(P2_API_READ_ONLY_COMPONENT, CommandCtx { table, wasi }).await?;
+
+    command
+        .wasi_cli_run()
+        .call_run(&mut store)
+        .await?
+        .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_file_read_write_memory_dir() -> Result<()> {
+    let dir = MemoryDir::new();
+
+    let table = ResourceTable::new();
+    let wasi = WasiCtxBuilder::new()
+        .preopened_virtual_dir(dir.clone(), "/", DirPerms::all(), FilePerms::all())
+        .build();
+
+    let (mut store, command) =
+        instantiate(P2_FILE_READ_WRITE_COMPONENT, CommandCtx { table, wasi }).await?;
+
+    command
+        .wasi_cli_run()
+        .call_run(&mut store)
+        .await?
+        .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))?;
+
+    // The guest removes the file it wrote once it's done.
+    let err = dir.read_file("test.txt").unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_file_read_write_overlay_dir() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("existing.txt"), "unchanged")?;
+
+    let table = ResourceTable::new();
+    let mut builder = WasiCtxBuilder::new();
+    let overlay =
+        builder.preopened_overlay_dir(dir.path(), "/", DirPerms::all(), FilePerms::all())?;
+    let wasi = builder.build();
+
+    let (mut store, command) =
+        instantiate(P2_FILE_READ_WRITE_COMPONENT, CommandCtx { table, wasi }).await?;
+
+    command
+        .wasi_cli_run()
+        .call_run(&mut store)
+        .await?
+        .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))?;
+
+    // The guest removes the file it wrote once it's done, and the host
+    // directory is never written to in the meantime.
+    assert_eq!(overlay.changes()?, []);
+    let names = std::fs::read_dir(dir.path())?
+        .map(|entry| Ok(entry?.file_name()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(names, ["existing.txt"]);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("existing.txt"))?,
+        "unchanged"
+    );
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_file_read_write_usage() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    let table = ResourceTable::new();
+    let wasi = WasiCtxBuilder::new()
+        .preopened_dir(dir.path(), "/", DirPerms::all(), FilePerms::all())?
+        .max_bytes_written(1024)
+        .max_files(1)
+        .build();
+
+    let (mut store, command) =
+        instantiate(P2_FILE_READ_WRITE_COMPONENT, CommandCtx { table, wasi }).await?;
+
+    command
+        .wasi_cli_run()
+        .call_run(&mut store)
+        .await?
+        .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))?;
+
+    // The guest writes "Hello, World!" and reads the whole file back twice,
+    // then removes it.
+    let usage = store.data_mut().wasi.filesystem().usage();
+    assert_eq!(usage.bytes_written, 13);
+    assert!(usage.bytes_read >= 36, "{usage:?}");
+    assert_eq!(usage.files, 0);
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_file_read_write_existing_file_usage() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    std::fs::write(dir.path().join("test.txt"), "")?;
+
+    let table = ResourceTable::new();
+    let wasi = WasiCtxBuilder::new()
+        .preopened_dir(dir.path(), "/", DirPerms::all(), FilePerms::all())?
+        .max_files(0)
+        .build();
+
+    let (mut store, command) =
+        instantiate(P2_FILE_READ_WRITE_COMPONENT, CommandCtx { table, wasi }).await?;
+
+    // Opening the file doesn't create it, and removing it doesn't count since
+    // the guest didn't create it.
+    command
+        .wasi_cli_run()
+        .call_run(&mut store)
+        .await?
+        .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))?;
+    assert_eq!(store.data_mut().wasi.filesystem().usage().files, 0);
+    assert!(!dir.path().join("test.txt").exists());
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_file_read_write_over_quota() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+
+    let table = ResourceTable::new();
+    let wasi = WasiCtxBuilder::new()
+        .preopened_dir(dir.path(), "/", DirPerms::all(), FilePerms::all())?
+        .max_bytes_written(10)
+        .build();
+
+    let (mut store, command) =
+        instantiate(P2_FILE_READ_WRITE_COMPONENT, CommandCtx { table, wasi }).await?;
+
+    // The guest panics when its second write fails.
+    let result = command.wasi_cli_run().call_run(&mut store).await;
+    assert!(!matches!(result, Ok(Ok(()))));
+    assert_eq!(store.data_mut().wasi.filesystem().usage().bytes_written, 10);
+    assert_eq!(std::fs::read(dir.path().join("test.txt"))?.len(), 15);
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_loopback_network() -> Result<()> {
+    async fn run(path: &str, network: &LoopbackNetwork) -> Result<()> {
+        let table = ResourceTable::new();
+        let wasi = WasiCtxBuilder::new()
+            .loopback_network(network.clone())
+            .inherit_network()
+            .build();
+        let (mut store, command) = instantiate(path, CommandCtx { table, wasi }).await?;
+        command
+            .wasi_cli_run()
+            .call_run(&mut store)
+            .await?
+            .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))
+    }
+
+    // Both stores share one network, each picking its own ports from it.
+    let network = LoopbackNetwork::new();
+    tokio::try_join!(
+        run(P2_TCP_SAMPLE_APPLICATION_COMPONENT, &network),
+        run(P2_UDP_SAMPLE_APPLICATION_COMPONENT, &network),
+    )?;
+    Ok(())
+}
+
+/// Runs a server and a client of `protocol` in two stores on `network`, with
+/// the server bound to `10.0.0.2:8080` and the client connecting to
+/// `host:8080`.
+async fn run_loopback_exchange(network: LoopbackNetwork, protocol: &str, host: &str) -> Result<()> {
+    async fn run(network: &LoopbackNetwork, args: &[&str]) -> Result<()> {
+        let table = ResourceTable::new();
+        let wasi = WasiCtxBuilder::new()
+            .args(args)
+            .loopback_network(network.clone())
+            .inherit_network()
+            .allow_ip_name_lookup(true)
+            .build();
+        let (mut store, command) =
+            instantiate(P2_API_LOOPBACK_COMPONENT, CommandCtx { table, wasi }).await?;
+        command
+            .wasi_cli_run()
+            .call_run(&mut store)
+            .await?
+            .map_err(|()| anyhow::anyhow!("command returned with failing exit status"))
+    }
+
+    let server = ["p2_api_loopback", protocol, "server", "10.0.0.2", "8080"];
+    let client = ["p2_api_loopback", protocol, "client", host, "8080"];
+    tokio::try_join!(run(&network, &server), run(&network, &client))?;
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_api_loopback() -> Result<()> {
+    run_loopback_exchange(LoopbackNetwork::new(), "tcp", "10.0.0.2").await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_api_loopback_udp() -> Result<()> {
+    run_loopback_exchange(LoopbackNetwork::new(), "udp", "10.0.0.2").await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_api_loopback_by_name() -> Result<()> {
+    let network = LoopbackNetwork::new();
+    network.add_host("backend.internal", "10.0.0.2".parse()?);
+    run_loopback_exchange(network, "tcp", "backend.internal").await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p2_api_loopback_udp_by_name() -> Result<()> {
+    let network = LoopbackNetwork::new();
+    network.add_host("backend.internal", "10.0.0.2".parse()?);
+    run_loopback_exchange(network, "udp", "backend.internal").await
+}
+
+#[test]
+fn overlay_dir_changes() -> Result<()> {
+    let lower = MemoryDir::new();
+    lower.write_file("a.txt", "a")?;
+    lower.write_file("dir/b.txt", "b")?;
+    lower.write_file("dir/c.txt", "c")?;
+    let overlay = OverlayDir::in_memory(lower.clone());
+
+    let options = OpenOptions {
+        write: true,
+        truncate: true,
+        ..OpenOptions::default()
+    };
+    let Opened::File(file) = overlay.open_at("a.txt", false, &options)? else {
+        panic!("expected a file");
+    };
+    file.write_at(b"modified", 0)?;
+    overlay.remove_file_at("dir/b.txt")?;
+    overlay.rename_at("dir/c.txt", &overlay, "moved.txt")?;
+    let options = OpenOptions {
+        write: true,
+        create: true,
+        ..OpenOptions::default()
+    };
+    overlay.open_at("new.txt", false, &options)?;
+
+    let names = overlay
+        .entries()?
+        .map(|entry| Ok(entry?.name))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(names, ["a.txt", "dir", "moved.txt", "new.txt"]);
+    let Opened::Dir(dir) = overlay.open_at("dir", false, &OpenOptions::default())? else {
+        panic!("expected a directory");
+    };
+    assert_eq!(dir.entries()?.count(), 0);
+
+    assert_eq!(
+        overlay.changes()?,
+        [
+            OverlayChange::Removed("dir/b.txt".to_string()),
+            OverlayChange::Removed("dir/c.txt".to_string()),
+            OverlayChange::Modified("a.txt".to_string(), FileType::RegularFile),
+            OverlayChange::Added("moved.txt".to_string(), FileType::RegularFile),
+            OverlayChange::Added("new.txt".to_string(), FileType::RegularFile),
+        ]
+    );
+
+    // The lower directory is untouched, and exporting the changes to a copy
+    // of it yields what the overlay presents.
+    assert_eq!(lower.read_file("a.txt")?, b"a");
+    assert_eq!(lower.read_file("dir/b.txt")?, b"b");
+    let target = MemoryDir::new();
+    target.write_file("a.txt", "a")?;
+    target.write_file("dir/b.txt", "b")?;
+    target.write_file("dir/c.txt", "c")?;
+    overlay.export(&target)?;
+    assert_eq!(target.read_file("a.txt")?, b"modified");
+    assert_eq!(target.read_file("moved.txt")?, b"c");
+    assert_eq!(target.read_file("new.txt")?, b"");
+    assert!(target.read_file("dir/b.txt").is_err());
+    assert!(target.read_file("dir/c.txt").is_err());
+    Ok(())
+}
+
+#[test]
+fn virtual_clock() {
+    let clock = VirtualClock::new(Duration::from_secs(100), Duration::from_nanos(10));
+    // Each reading advances the clock by its resolution.
+    assert_eq!(
+        HostWallClock::now(&clock),
+        Duration::from_nanos(100_000_000_010)
+    );
+    assert_eq!(HostMonotonicClock::now(&clock), 20);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(clock.elapsed(), Duration::from_nanos(1_000_000_020));
+    assert_eq!(
+        HostWallClock::now(&clock),
+        Duration::from_nanos(101_000_000_030)
+    );
+
+    // Clones share the same time.
+    let other = clock.clone();
+    other.advance(Duration::from_nanos(5));
+    assert_eq!(clock.elapsed(), Duration::from_nanos(1_000_000_035));
+}
+
 #[expect(
     dead_code,
     reason = "tested in the wasi-http crate, satisfying foreach_api! macro"
@@ -4,15 +4,31 @@ use std::path::Path;
 use test_programs_artifacts::*;
 use wasmtime::Result;
 use wasmtime::component::{Component, Linker};
+use wasmtime_wasi::WasiCtxBuilder;
+use wasmtime_wasi::filesystem::MemoryDir;
 use wasmtime_wasi::p3::bindings::Command;
 
 async fn run(path: &str) -> Result<()> {
-    run_allow_blocking_current_thread(path, false).await
+    run_with(path, false, None).await
 }
 
 async fn run_allow_blocking_current_thread(
     path: &str,
     allow_blocking_current_thread: bool,
+) -> Result<()> {
+    run_with(path, allow_blocking_current_thread, None).await
+}
+
+/// Runs `path` with a [`MemoryDir`] preopened in place of a temporary directory
+/// of the host.
+async fn run_in_memory(path: &str) -> Result<()> {
+    run_with(path, false, Some(MemoryDir::new())).await
+}
+
+async fn run_with(
+    path: &str,
+    allow_blocking_current_thread: bool,
+    dir: Option<MemoryDir>,
 ) -> Result<()> {
     let path = Path::new(path);
     let name = path.file_stem().unwrap().to_str().unwrap();
@@ -26,12 +42,19 @@ async fn run_allow_blocking_current_thread(
         .context("failed to link `wasi:cli@0.2.x`")?;
     wasmtime_wasi::p3::add_to_linker(&mut linker).context("failed to link `wasi:cli@0.3.x`")?;
 
-    let (mut store, _td) = Ctx::new(&engine, name, |builder| MyWasiCtx {
+    let configure = |builder: &mut WasiCtxBuilder| MyWasiCtx {
         wasi: builder
             .allow_blocking_current_thread(allow_blocking_current_thread)
             .build(),
         table: Default::default(),
-    })?;
+    };
+    let (mut store, _td) = match dir {
+        Some(dir) => (Ctx::new_in_memory(&engine, name, dir, configure)?, None),
+        None => {
+            let (store, td) = Ctx::new(&engine, name, configure)?;
+            (store, Some(td))
+        }
+    };
     let component = Component::from_file(&engine, path)?;
     let command = Command::instantiate_async(&mut store, &component, &linker)
         .await
@@ -152,6 +175,21 @@ async fn p3_file_write_blocking() -> anyhow::Result<()> {
     run_allow_blocking_current_thread(P3_FILE_WRITE_COMPONENT, true).await
 }
 
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p3_filesystem_file_read_write_memory_dir() -> anyhow::Result<()> {
+    run_in_memory(P3_FILESYSTEM_FILE_READ_WRITE_COMPONENT).await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p3_readdir_memory_dir() -> anyhow::Result<()> {
+    run_in_memory(P3_READDIR_COMPONENT).await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn p3_file_write_memory_dir() -> anyhow::Result<()> {
+    run_in_memory(P3_FILE_WRITE_COMPONENT).await
+}
+
 #[expect(
     dead_code,
     reason = "tested in the wasi-cli crate, satisfying foreach_api! macro"
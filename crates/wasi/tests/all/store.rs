@@ -2,6 +2,7 @@ use anyhow::Result;
 use tempfile::TempDir;
 use wasmtime::component::ResourceTable;
 use wasmtime::{Engine, Store};
+use wasmtime_wasi::filesystem::MemoryDir;
 use wasmtime_wasi::{
     DirPerms, FilePerms, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView, p2::pipe::MemoryOutputPipe,
 };
@@ -24,10 +25,38 @@ impl<T> Ctx<T> {
         name: &str,
         configure: impl FnOnce(&mut WasiCtxBuilder) -> T,
     ) -> Result<(Store<Ctx<T>>, TempDir)> {
+        let workspace = prepare_workspace(name)?;
+        let store = Ctx::build(engine, name, configure, |builder| {
+            println!("preopen: {workspace:?}");
+            builder.preopened_dir(workspace.path(), ".", DirPerms::all(), FilePerms::all())?;
+            Ok(())
+        })?;
+        Ok((store, workspace))
+    }
+
+    /// Like [`Ctx::new`], but with `dir` preopened in place of a temporary
+    /// directory of the host.
+    pub fn new_in_memory(
+        engine: &Engine,
+        name: &str,
+        dir: MemoryDir,
+        configure: impl FnOnce(&mut WasiCtxBuilder) -> T,
+    ) -> Result<Store<Ctx<T>>> {
+        Ctx::build(engine, name, configure, |builder| {
+            builder.preopened_virtual_dir(dir, ".", DirPerms::all(), FilePerms::all());
+            Ok(())
+        })
+    }
+
+    fn build(
+        engine: &Engine,
+        name: &str,
+        configure: impl FnOnce(&mut WasiCtxBuilder) -> T,
+        preopen: impl FnOnce(&mut WasiCtxBuilder) -> Result<()>,
+    ) -> Result<Store<Ctx<T>>> {
         const MAX_OUTPUT_SIZE: usize = 10 << 20;
         let stdout = MemoryOutputPipe::new(MAX_OUTPUT_SIZE);
         let stderr = MemoryOutputPipe::new(MAX_OUTPUT_SIZE);
-        let workspace = prepare_workspace(name)?;
 
         // Create our wasi context.
         let mut builder = WasiCtxBuilder::new();
@@ -37,8 +66,7 @@ impl<T> Ctx<T> {
             .args(&[name, "."])
             .inherit_network()
             .allow_ip_name_lookup(true);
-        println!("preopen: {workspace:?}");
-        builder.preopened_dir(workspace.path(), ".", DirPerms::all(), FilePerms::all())?;
+        preopen(&mut builder)?;
         for (var, val) in test_programs_artifacts::wasi_tests_environment() {
             builder.env(var, val);
         }
@@ -54,7 +82,7 @@ impl<T> Ctx<T> {
             stdout,
         };
 
-        Ok((Store::new(&engine, ctx), workspace))
+        Ok(Store::new(&engine, ctx))
     }
 }
 
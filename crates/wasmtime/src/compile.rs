@@ -34,8 +34,9 @@ use wasmtime_environ::component::Translator;
 use wasmtime_environ::{
     Abi, CompiledFunctionBody, CompiledFunctionsTable, CompiledFunctionsTableBuilder,
     CompiledModuleInfo, Compiler, DefinedFuncIndex, FilePos, FinishedObject, FuncKey,
-    FunctionBodyData, InliningCompiler, IntraModuleInlining, ModuleEnvironment, ModuleTranslation,
-    ModuleTypes, ModuleTypesBuilder, ObjectKind, PrimaryMap, StaticModuleIndex, Tunables,
+    FunctionBodyData, FunctionLoc, InliningCompiler, IntraModuleInlining, ModuleEnvironment,
+    ModuleTranslation, ModuleTypes, ModuleTypesBuilder, ObjectKind, PrimaryMap, StaticModuleIndex,
+    Tunables,
 };
 
 mod call_graph;
@@ -130,6 +131,121 @@ pub(crate) fn build_module_artifacts<T: FinishedObject>(
     Ok((result, Some((info, index, types))))
 }
 
+/// The translation of a core wasm module which its hot functions are
+/// recompiled from by `build_tier_up_artifacts`.
+///
+/// This is created once per module so that the module isn't parsed and
+/// validated again for each function which is tiered up.
+#[cfg(all(feature = "runtime", feature = "winch"))]
+pub(crate) struct TierUpTranslation {
+    // NB: `translation` and `bodies` borrow from `_wasm`, so they're declared
+    // first to be dropped before it.
+    translation: ModuleTranslation<'static>,
+    /// The body of each defined function which hasn't been recompiled yet.
+    bodies: PrimaryMap<DefinedFuncIndex, Option<FunctionBodyData<'static>>>,
+    types: ModuleTypesBuilder,
+    _wasm: std::sync::Arc<[u8]>,
+}
+
+#[cfg(all(feature = "runtime", feature = "winch"))]
+impl TierUpTranslation {
+    /// Parses and validates the core wasm module `wasm`.
+    pub(crate) fn new(engine: &Engine, wasm: std::sync::Arc<[u8]>) -> Result<TierUpTranslation> {
+        // SAFETY: the bytes are immutable and kept alive by `_wasm` until
+        // after everything borrowing them is dropped, and borrows of them are
+        // never handed out for longer than a borrow of the returned value.
+        let data: &'static [u8] = unsafe { &*std::sync::Arc::as_ptr(&wasm) };
+
+        let mut parser = wasmparser::Parser::new(0);
+        let mut validator = wasmparser::Validator::new_with_features(engine.features());
+        parser.set_features(*validator.features());
+        let mut types = ModuleTypesBuilder::new(&validator);
+        let module = StaticModuleIndex::from_u32(0);
+        let mut translation =
+            ModuleEnvironment::new(engine.tunables(), &mut validator, &mut types, module)
+                .translate(parser, data)
+                .context("failed to parse WebAssembly module")?;
+        let bodies = mem::take(&mut translation.function_body_inputs)
+            .into_iter()
+            .map(|(_, body)| Some(body))
+            .collect();
+
+        Ok(TierUpTranslation {
+            translation,
+            bodies,
+            types,
+            _wasm: wasm,
+        })
+    }
+
+    /// Returns the index of the translated module.
+    pub(crate) fn module_index(&self) -> StaticModuleIndex {
+        self.translation.module_index()
+    }
+}
+
+/// Recompiles the defined function `index` of a core wasm module with the
+/// compiler that hot functions are tiered up to.
+///
+/// The function is compiled along with any builtin trampolines it needs. The
+/// returned artifact contains just their code, which the returned table
+/// locates. Calls from the function to others in the same module go through
+/// their `VMFuncRef`, as do calls from the function's array-to-Wasm
+/// trampoline, so nothing else from the module needs to be compiled.
+///
+/// Each function can only be recompiled once from a `TierUpTranslation`.
+#[cfg(all(feature = "runtime", feature = "winch"))]
+pub(crate) fn build_tier_up_artifacts<T: FinishedObject>(
+    engine: &Engine,
+    translation: &mut TierUpTranslation,
+    index: DefinedFuncIndex,
+    obj_state: &T::State,
+) -> Result<(T, CompiledFunctionsTable)> {
+    let compiler = engine.try_compiler()?;
+    let tier_up_compiler = compiler
+        .tier_up_compiler()
+        .ok_or_else(|| anyhow!("compiler does not support tiered compilation"))?;
+    let tunables = engine.tunables();
+
+    let function = translation
+        .bodies
+        .get_mut(index)
+        .and_then(Option::take)
+        .ok_or_else(|| anyhow!("no defined function {} to recompile", index.as_u32()))?;
+    let module = translation.translation.module_index();
+
+    let mut compile_inputs = CompileInputs::default();
+    compile_inputs.push_function_input(
+        &translation.types,
+        module,
+        &translation.translation,
+        index,
+        function,
+    );
+    let mut raw_outputs = compile_inputs
+        .inputs
+        .into_iter()
+        .map(|f| f(tier_up_compiler))
+        .collect::<Result<Vec<_>>>()?;
+    compile_required_builtins(engine, tier_up_compiler, &mut raw_outputs)?;
+    let outputs = raw_outputs.into_iter().map(|o| (o.key, o)).collect();
+    let PreLinkOutput {
+        compiled_funcs,
+        indices,
+        ..
+    } = UnlinkedCompileOutputs { outputs }.pre_link();
+
+    let mut object = compiler.object(ObjectKind::Module)?;
+    engine.append_bti(&mut object);
+    let symbol_ids_and_locs =
+        indices.append_code(&mut object, tier_up_compiler, &compiled_funcs)?;
+    let table = indices.functions_table(&symbol_ids_and_locs);
+    let object = wasmtime_environ::ObjectBuilder::new(object, tunables);
+    let result = T::finish_object(object, obj_state)?;
+
+    Ok((result, table))
+}
+
 /// Performs the compilation phase for a component, translating and
 /// validating the provided wasm binary to machine code.
 ///
@@ -413,6 +529,93 @@ impl<'a> CompileInputs<'a> {
         }
     }
 
+    /// Push the inputs for compiling the defined function `def_func_index` of
+    /// `translation`, along with its array-to-Wasm trampoline if it escapes.
+    fn push_function_inputs(
+        &mut self,
+        types: &'a ModuleTypesBuilder,
+        module: StaticModuleIndex,
+        translation: &'a ModuleTranslation<'a>,
+        def_func_index: DefinedFuncIndex,
+        func_body_data: FunctionBodyData<'a>,
+    ) {
+        self.push_function_input(types, module, translation, def_func_index, func_body_data);
+
+        let func_index = translation.module.func_index(def_func_index);
+        if translation.module.functions[func_index].is_escaping() {
+            self.push_input(move |compiler| {
+                let key = FuncKey::ArrayToWasmTrampoline(module, def_func_index);
+                let func_index = translation.module.func_index(def_func_index);
+                let symbol = format!(
+                    "wasm[{}]::array_to_wasm_trampoline[{}]",
+                    module.as_u32(),
+                    func_index.as_u32()
+                );
+                let function = compiler
+                    .compile_array_to_wasm_trampoline(translation, types, key, &symbol)
+                    .with_context(|| format!("failed to compile: {symbol}"))?;
+                Ok(CompileOutput {
+                    key,
+                    symbol,
+                    function,
+                    start_srcloc: FilePos::default(),
+                    translation: None,
+                    func_body: None,
+                })
+            });
+        }
+    }
+
+    /// Push the input for compiling just the defined function
+    /// `def_func_index` of `translation`.
+    fn push_function_input(
+        &mut self,
+        types: &'a ModuleTypesBuilder,
+        module: StaticModuleIndex,
+        translation: &'a ModuleTranslation<'a>,
+        def_func_index: DefinedFuncIndex,
+        func_body_data: FunctionBodyData<'a>,
+    ) {
+        self.push_input(move |compiler| {
+            let key = FuncKey::DefinedWasmFunction(module, def_func_index);
+            let func_index = translation.module.func_index(def_func_index);
+            let symbol = match translation
+                .debuginfo
+                .name_section
+                .func_names
+                .get(&func_index)
+            {
+                Some(name) => format!(
+                    "wasm[{}]::function[{}]::{}",
+                    module.as_u32(),
+                    func_index.as_u32(),
+                    Self::clean_symbol(&name)
+                ),
+                None => format!(
+                    "wasm[{}]::function[{}]",
+                    module.as_u32(),
+                    func_index.as_u32()
+                ),
+            };
+            let func_body = func_body_data.body.clone();
+            let data = func_body.get_binary_reader();
+            let offset = data.original_position();
+            let start_srcloc = FilePos::new(u32::try_from(offset).unwrap());
+            let function = compiler
+                .compile_function(translation, key, func_body_data, types, &symbol)
+                .with_context(|| format!("failed to compile: {symbol}"))?;
+
+            Ok(CompileOutput {
+                key,
+                symbol,
+                function,
+                start_srcloc,
+                translation: Some(translation),
+                func_body: Some(func_body),
+            })
+        });
+    }
+
     fn collect_inputs_in_translations(
         &mut self,
         types: &'a ModuleTypesBuilder,
@@ -426,68 +629,13 @@ impl<'a> CompileInputs<'a> {
     ) {
         for (module, translation, functions) in translations {
             for (def_func_index, func_body_data) in functions {
-                self.push_input(move |compiler| {
-                    let key = FuncKey::DefinedWasmFunction(module, def_func_index);
-                    let func_index = translation.module.func_index(def_func_index);
-                    let symbol = match translation
-                        .debuginfo
-                        .name_section
-                        .func_names
-                        .get(&func_index)
-                    {
-                        Some(name) => format!(
-                            "wasm[{}]::function[{}]::{}",
-                            module.as_u32(),
-                            func_index.as_u32(),
-                            Self::clean_symbol(&name)
-                        ),
-                        None => format!(
-                            "wasm[{}]::function[{}]",
-                            module.as_u32(),
-                            func_index.as_u32()
-                        ),
-                    };
-                    let func_body = func_body_data.body.clone();
-                    let data = func_body.get_binary_reader();
-                    let offset = data.original_position();
-                    let start_srcloc = FilePos::new(u32::try_from(offset).unwrap());
-                    let function = compiler
-                        .compile_function(translation, key, func_body_data, types, &symbol)
-                        .with_context(|| format!("failed to compile: {symbol}"))?;
-
-                    Ok(CompileOutput {
-                        key,
-                        symbol,
-                        function,
-                        start_srcloc,
-                        translation: Some(translation),
-                        func_body: Some(func_body),
-                    })
-                });
-
-                let func_index = translation.module.func_index(def_func_index);
-                if translation.module.functions[func_index].is_escaping() {
-                    self.push_input(move |compiler| {
-                        let key = FuncKey::ArrayToWasmTrampoline(module, def_func_index);
-                        let func_index = translation.module.func_index(def_func_index);
-                        let symbol = format!(
-                            "wasm[{}]::array_to_wasm_trampoline[{}]",
-                            module.as_u32(),
-                            func_index.as_u32()
-                        );
-                        let function = compiler
-                            .compile_array_to_wasm_trampoline(translation, types, key, &symbol)
-                            .with_context(|| format!("failed to compile: {symbol}"))?;
-                        Ok(CompileOutput {
-                            key,
-                            symbol,
-                            function,
-                            start_srcloc: FilePos::default(),
-                            translation: None,
-                            func_body: None,
-                        })
-                    });
-                }
+                self.push_function_inputs(
+                    types,
+                    module,
+                    translation,
+                    def_func_index,
+                    func_body_data,
+                );
             }
         }
 
@@ -580,7 +728,7 @@ the use case.
         // wasmtime-builtin functions are necessary. If so those need to be
         // collected and then those trampolines additionally need to be
         // compiled.
-        compile_required_builtins(engine, &mut raw_outputs)?;
+        compile_required_builtins(engine, compiler, &mut raw_outputs)?;
 
         // Bucket the outputs by kind.
         let mut outputs: BTreeMap<FuncKey, CompileOutput> = BTreeMap::new();
@@ -914,8 +1062,11 @@ the use case.
     }
 }
 
-fn compile_required_builtins(engine: &Engine, raw_outputs: &mut Vec<CompileOutput>) -> Result<()> {
-    let compiler = engine.try_compiler()?;
+fn compile_required_builtins(
+    engine: &Engine,
+    compiler: &dyn Compiler,
+    raw_outputs: &mut Vec<CompileOutput>,
+) -> Result<()> {
     let mut builtins = HashSet::new();
     let mut new_inputs: Vec<CompileInput<'_>> = Vec::new();
 
@@ -1056,15 +1207,7 @@ impl FunctionIndices {
         // `compiled_funcs[i]`.
         let compiler = engine.try_compiler()?;
         let tunables = engine.tunables();
-        let symbol_ids_and_locs = compiler.append_code(
-            &mut obj,
-            &compiled_funcs,
-            &|_caller_index: usize, callee: FuncKey| {
-                self.indices.get(&callee).copied().unwrap_or_else(|| {
-                    panic!("cannot resolve relocation! no index for callee {callee:?}")
-                })
-            },
-        )?;
+        let symbol_ids_and_locs = self.append_code(&mut obj, compiler, &compiled_funcs)?;
 
         // If requested, generate and add DWARF information.
         if tunables.debug_native {
@@ -1082,16 +1225,7 @@ impl FunctionIndices {
             )?;
         }
 
-        let mut table_builder = CompiledFunctionsTableBuilder::new();
-        for (key, compiled_func_index) in &self.indices {
-            let (_, func_loc) = symbol_ids_and_locs[*compiled_func_index];
-            let src_loc = self
-                .start_srclocs
-                .get(key)
-                .copied()
-                .unwrap_or_else(FilePos::none);
-            table_builder.push_func(*key, func_loc, src_loc);
-        }
+        let table = self.functions_table(&symbol_ids_and_locs);
 
         let mut obj = wasmtime_environ::ObjectBuilder::new(obj, tunables);
         let modules = translations
@@ -1118,13 +1252,52 @@ impl FunctionIndices {
             })
             .collect::<Result<PrimaryMap<_, _>>>()?;
 
-        let artifacts = Artifacts {
-            modules,
-            table: table_builder.finish(),
-        };
+        let artifacts = Artifacts { modules, table };
 
         Ok((obj, artifacts))
     }
+
+    /// Append the compiled functions to the given ELF file, resolving
+    /// relocations between them.
+    ///
+    /// The result is a vector parallel to `compiled_funcs` where
+    /// `symbol_ids_and_locs[i]` is the symbol ID and function location of
+    /// `compiled_funcs[i]`.
+    fn append_code(
+        &self,
+        obj: &mut object::write::Object<'static>,
+        compiler: &dyn Compiler,
+        compiled_funcs: &[(String, FuncKey, Box<dyn Any + Send + Sync>)],
+    ) -> Result<Vec<(object::write::SymbolId, FunctionLoc)>> {
+        compiler.append_code(
+            obj,
+            compiled_funcs,
+            &|_caller_index: usize, callee: FuncKey| {
+                self.indices.get(&callee).copied().unwrap_or_else(|| {
+                    panic!("cannot resolve relocation! no index for callee {callee:?}")
+                })
+            },
+        )
+    }
+
+    /// Build the table locating each compiled function given the result of
+    /// `append_code`.
+    fn functions_table(
+        &self,
+        symbol_ids_and_locs: &[(object::write::SymbolId, FunctionLoc)],
+    ) -> CompiledFunctionsTable {
+        let mut table_builder = CompiledFunctionsTableBuilder::new();
+        for (key, compiled_func_index) in &self.indices {
+            let (_, func_loc) = symbol_ids_and_locs[*compiled_func_index];
+            let src_loc = self
+                .start_srclocs
+                .get(key)
+                .copied()
+                .unwrap_or_else(FilePos::none);
+            table_builder.push_func(*key, func_loc, src_loc);
+        }
+        table_builder.finish()
+    }
 }
 
 /// The artifacts necessary for finding and calling Wasm functions at runtime,
@@ -773,7 +773,7 @@ impl<'a> CodeBuilder<'a> {
         let dwarf_package = self.get_dwarf_package();
         let (v, _) =
             super::build_module_artifacts(self.engine, &wasm, dwarf_package.as_deref(), &())?;
-        Ok(v)
+        self.engine.sign_serialized(v)
     }
 
     /// Same as [`CodeBuilder::compile_module_serialized`] except that it
@@ -789,7 +789,7 @@ impl<'a> CodeBuilder<'a> {
             self.get_unsafe_intrinsics_import(),
             &(),
         )?;
-        Ok(v)
+        self.engine.sign_serialized(v)
     }
 
     pub(super) fn get_unsafe_intrinsics_import(&self) -> Option<&str> {
@@ -820,6 +820,14 @@ impl std::hash::Hash for HashedEngineCompileEnv<'_> {
         self.0.features().hash(hasher);
         config.wmemcheck.hash(hasher);
 
+        // Artifacts to be signed have room for the signer's key reserved.
+        #[cfg(feature = "signing")]
+        config
+            .signing_key
+            .as_ref()
+            .map(|key| key.verifying_key().to_bytes())
+            .hash(hasher);
+
         // Catch accidental bugs of reusing across crate versions.
         config.module_version.hash(hasher);
     }
@@ -41,6 +41,38 @@ impl<'a> CodeBuilder<'a> {
                 // not impact the compilation result itself.
                 NotHashed(state),
             );
+            if let Some(store) = self.engine.code_store() {
+                let key = store.key(&state);
+                let kind = if wasmparser::Parser::is_component(&wasm) {
+                    wasmtime_environ::ObjectKind::Component
+                } else {
+                    wasmtime_environ::ObjectKind::Module
+                };
+                if let Some(code) = load_shared_code(self.engine, store, &key, kind) {
+                    return Ok((code, None));
+                }
+                let (mmap, info_and_types) = build_artifacts(
+                    self.engine,
+                    &wasm,
+                    dwarf_package.as_deref(),
+                    unsafe_intrinsics_import,
+                    state.5.0,
+                )?;
+                // Map the artifact back in from the store once it's published
+                // so that its memory is shared with other processes too.
+                let shared = match store.publish(&key, &mmap.0) {
+                    Ok(_) => load_shared_code(self.engine, store, &key, kind),
+                    Err(e) => {
+                        log::warn!("failed to publish compiled code: {e:?}");
+                        None
+                    }
+                };
+                let code = match shared {
+                    Some(code) => code,
+                    None => publish_mmap(self.engine, mmap.0)?,
+                };
+                return Ok((code, info_and_types));
+            }
             let (code, info_and_types) =
                 wasmtime_cache::ModuleCacheEntry::new("wasmtime", self.engine.cache())
                     .get_data_raw(
@@ -128,7 +160,16 @@ impl<'a> CodeBuilder<'a> {
             },
             &custom_alignment,
         )?;
-        Module::from_parts(self.engine, code, info_and_types)
+        let module = Module::from_parts(self.engine, code, info_and_types)?;
+
+        // Keep the original binary around for hot functions to be recompiled
+        // from later on.
+        #[cfg(feature = "winch")]
+        if let Some(tiering) = module.tiering() {
+            tiering.retain_wasm(self.engine, &self.get_wasm()?);
+        }
+
+        Ok(module)
     }
 
     /// Same as [`CodeBuilder::compile_module`] except that it compiles a
@@ -162,6 +203,27 @@ impl<'a> CodeBuilder<'a> {
     }
 }
 
+/// Maps the artifact published under `key` in `store`, if there is one and
+/// it's usable.
+#[cfg(feature = "cache")]
+fn load_shared_code(
+    engine: &Engine,
+    store: &wasmtime_cache::CodeStore,
+    key: &str,
+    kind: wasmtime_environ::ObjectKind,
+) -> Option<Arc<CodeMemory>> {
+    let path = store.lookup(key)?;
+    let result = crate::runtime::vm::open_file_for_mmap(&path)
+        .and_then(|file| engine.load_code_file(file, kind));
+    match result {
+        Ok(code) => Some(code),
+        Err(e) => {
+            log::warn!("failed to load shared code from {}: {e:?}", path.display());
+            None
+        }
+    }
+}
+
 fn publish_mmap(engine: &Engine, mmap: MmapVec) -> Result<Arc<CodeMemory>> {
     let mut code = CodeMemory::new(engine, mmap)?;
     code.publish()?;
@@ -28,7 +28,9 @@ use wasmtime_fiber::RuntimeFiberStackCreator;
 #[cfg(feature = "runtime")]
 pub use crate::runtime::code_memory::CustomCodeMemory;
 #[cfg(feature = "cache")]
-pub use wasmtime_cache::{Cache, CacheConfig};
+pub use wasmtime_cache::{
+    Cache, CacheConfig, CodeStore, CodeStoreGcStats, DirectoryCacheStore, ModuleCacheStore,
+};
 #[cfg(all(feature = "incremental-cache", feature = "cranelift"))]
 pub use wasmtime_environ::CacheStore;
 
@@ -130,6 +132,12 @@ pub struct Config {
 
     #[cfg(feature = "cache")]
     pub(crate) cache: Option<Cache>,
+    #[cfg(feature = "cache")]
+    pub(crate) code_store: Option<CodeStore>,
+    #[cfg(feature = "signing")]
+    pub(crate) signing_key: Option<crate::ArtifactSigningKey>,
+    #[cfg(feature = "signing")]
+    pub(crate) trusted_artifact_keys: Vec<crate::ArtifactVerifyingKey>,
     #[cfg(feature = "runtime")]
     pub(crate) mem_creator: Option<Arc<dyn RuntimeMemoryCreator>>,
     #[cfg(feature = "runtime")]
@@ -159,9 +167,12 @@ pub struct Config {
     pub(crate) parallel_compilation: bool,
     pub(crate) memory_guaranteed_dense_image_size: u64,
     pub(crate) force_memory_init_memfd: bool,
+    pub(crate) memory_init_userfaultfd: bool,
     pub(crate) wmemcheck: bool,
     #[cfg(feature = "coredump")]
     pub(crate) coredump_on_trap: bool,
+    #[cfg(feature = "rr")]
+    pub(crate) record_replay: bool,
     pub(crate) macos_use_mach_ports: bool,
     pub(crate) detect_host_feature: Option<fn(&str) -> Option<bool>>,
     pub(crate) x86_float_abi_ok: Option<bool>,
@@ -237,6 +248,12 @@ impl Config {
             collector: Collector::default(),
             #[cfg(feature = "cache")]
             cache: None,
+            #[cfg(feature = "cache")]
+            code_store: None,
+            #[cfg(feature = "signing")]
+            signing_key: None,
+            #[cfg(feature = "signing")]
+            trusted_artifact_keys: Vec::new(),
             profiling_strategy: ProfilingStrategy::None,
             #[cfg(feature = "runtime")]
             mem_creator: None,
@@ -268,9 +285,12 @@ impl Config {
             parallel_compilation: !cfg!(miri),
             memory_guaranteed_dense_image_size: 16 << 20,
             force_memory_init_memfd: false,
+            memory_init_userfaultfd: false,
             wmemcheck: false,
             #[cfg(feature = "coredump")]
             coredump_on_trap: false,
+            #[cfg(feature = "rr")]
+            record_replay: false,
             macos_use_mach_ports: !cfg!(miri),
             #[cfg(feature = "std")]
             detect_host_feature: Some(detect_host_feature),
@@ -645,6 +665,30 @@ impl Config {
         self
     }
 
+    /// Configures whether compiled code counts the WebAssembly instructions
+    /// that it executes.
+    ///
+    /// When enabled, generated code maintains a counter for each straight-line
+    /// run of instructions (a "basic block") in every function, which is
+    /// incremented by the number of instructions in the block each time it
+    /// runs. Unlike sampling profilers the resulting counts are exact and
+    /// deterministic, which makes them suitable for reproducible benchmarking,
+    /// for example in CI. Counters are kept per [`Store`] and can be read with
+    /// [`Store::instruction_counts`](crate::Store::instruction_counts).
+    ///
+    /// As with fuel, instructions in a block which traps partway through are
+    /// not counted.
+    ///
+    /// By default this option is `false`.
+    ///
+    /// **Note** Enabling this option is not compatible with the Winch compiler.
+    ///
+    /// [`Store`]: crate::Store
+    pub fn count_instructions(&mut self, enable: bool) -> &mut Self {
+        self.tunables.count_instructions = Some(enable);
+        self
+    }
+
     /// Enables epoch-based interruption.
     ///
     /// When executing code in async mode, we sometimes want to
@@ -1335,6 +1379,20 @@ impl Config {
         self
     }
 
+    /// Configures how many times a function must be called before it's
+    /// recompiled with Cranelift when using [`Strategy::Tiered`].
+    ///
+    /// Lower values tier up more functions sooner, at the cost of more time
+    /// spent compiling in the background. A threshold of zero tiers up every
+    /// function right away, whether or not it's ever called.
+    ///
+    /// The default value for this is 1000.
+    #[cfg(all(feature = "runtime", feature = "winch"))]
+    pub fn tier_up_threshold(&mut self, calls: u32) -> &mut Self {
+        self.tunables.tier_up_threshold = Some(calls);
+        self
+    }
+
     /// Configures which garbage collector will be used for Wasm modules.
     ///
     /// This method can be used to configure which garbage collector
@@ -1563,6 +1621,8 @@ impl Config {
     ///
     /// To load a cache configuration from a file, use [`Cache::from_file`]. Otherwise, you can
     /// create a new cache config using [`CacheConfig::new`] and passing that to [`Cache::new`].
+    /// To keep cached modules somewhere other than a local directory, implement
+    /// [`ModuleCacheStore`] and pass it to [`Cache::with_store`].
     ///
     /// If you want to disable the cache, you can call this method with `None`.
     ///
@@ -1580,6 +1640,92 @@ impl Config {
         self
     }
 
+    /// Set a [`CodeStore`] that compiled modules and components are shared
+    /// through.
+    ///
+    /// When a code store is configured, compiling a module or component first
+    /// looks for an artifact previously published to the store by any process
+    /// with a compatible configuration. If one is found it's mapped directly
+    /// from the store, as with [`Module::deserialize_file`], so that all
+    /// processes using it share its code and metadata in memory. Otherwise the
+    /// module is compiled and the artifact is published to the store and then
+    /// mapped from there.
+    ///
+    /// This is intended for servers running many worker processes with the
+    /// same configuration and modules. A code store takes precedence over a
+    /// [`Cache`] configured with [`Config::cache`]. Publishing failures aren't
+    /// reported, and fall back to keeping the compiled code in memory private
+    /// to this process.
+    ///
+    /// Artifacts in the store are trusted in the same way as artifacts passed
+    /// to [`Module::deserialize_file`], so the store's directory must only be
+    /// writable by trusted processes.
+    ///
+    /// By default no code store is used.
+    ///
+    /// This method is only available when the `cache` feature of this crate is
+    /// enabled.
+    ///
+    /// [`Module::deserialize_file`]: crate::Module::deserialize_file
+    #[cfg(feature = "cache")]
+    pub fn code_store(&mut self, store: Option<CodeStore>) -> &mut Self {
+        self.code_store = store;
+        self
+    }
+
+    /// Configures a key that precompiled artifacts produced by this
+    /// configuration are signed with.
+    ///
+    /// When a key is set, artifacts produced by
+    /// [`Engine::precompile_module`], [`Module::serialize`] and their component
+    /// counterparts carry an ed25519 signature made with `key`. Signed
+    /// artifacts can be loaded with the safe [`Module::deserialize_verified`]
+    /// by engines which trust the key, see [`Config::trust_artifact_key`]. They
+    /// can also still be loaded with [`Module::deserialize`] like any other
+    /// artifact.
+    ///
+    /// Signing doesn't otherwise change the code that's compiled, but
+    /// artifacts aren't shared through [`Config::cache`] or
+    /// [`Config::code_store`] with configurations using a different key.
+    ///
+    /// By default artifacts aren't signed.
+    ///
+    /// This method is only available when the `signing` feature of this crate
+    /// is enabled.
+    ///
+    /// [`Engine::precompile_module`]: crate::Engine::precompile_module
+    /// [`Module::serialize`]: crate::Module::serialize
+    /// [`Module::deserialize`]: crate::Module::deserialize
+    /// [`Module::deserialize_verified`]: crate::Module::deserialize_verified
+    #[cfg(feature = "signing")]
+    pub fn sign_artifacts(&mut self, key: Option<crate::ArtifactSigningKey>) -> &mut Self {
+        self.signing_key = key;
+        self
+    }
+
+    /// Adds `key` to the set of keys that precompiled artifacts loaded with
+    /// [`Module::deserialize_verified`] may be signed with.
+    ///
+    /// Artifacts signed by a trusted key are loaded with the same trust as
+    /// artifacts passed to [`Module::deserialize`], so only the keys of
+    /// trusted builders should be added here.
+    ///
+    /// By default no keys are trusted, and so all artifacts are rejected by
+    /// [`Module::deserialize_verified`].
+    ///
+    /// This method is only available when the `signing` feature of this crate
+    /// is enabled.
+    ///
+    /// [`Module::deserialize`]: crate::Module::deserialize
+    /// [`Module::deserialize_verified`]: crate::Module::deserialize_verified
+    #[cfg(feature = "signing")]
+    pub fn trust_artifact_key(&mut self, key: crate::ArtifactVerifyingKey) -> &mut Self {
+        if !self.trusted_artifact_keys.contains(&key) {
+            self.trusted_artifact_keys.push(key);
+        }
+        self
+    }
+
     /// Sets a custom memory creator.
     ///
     /// Custom memory creators are used when creating host `Memory` objects or when
@@ -2130,6 +2276,51 @@ impl Config {
         self
     }
 
+    /// Configures whether linear memories are initialized lazily, page by
+    /// page, on first access using Linux's `userfaultfd` mechanism.
+    ///
+    /// When enabled, modules whose memories would otherwise be initialized
+    /// with a copy-on-write image (see [`Config::memory_init_cow`]) instead
+    /// have the pages of their initial image registered with a
+    /// `userfaultfd` at instantiation. Nothing is copied while instantiating;
+    /// the first access to each page, whether from WebAssembly or from the
+    /// host, is paused while a handler thread owned by the [`Engine`] fills
+    /// that page in with its contents from the module's data segments. Pages
+    /// of the image that are never accessed never occupy any memory, and
+    /// unlike copy-on-write mappings every populated page is private to its
+    /// instance from the start, so there's no later copy or [IPI] when it's
+    /// first written.
+    ///
+    /// The cost is a round-trip to the handler thread for each page of the
+    /// initial image which is accessed, so this is best suited to modules
+    /// with large data segments of which each instance only touches a small
+    /// part. Statistics about lazily-initialized memories are available
+    /// through [`Engine::userfaultfd_stats`].
+    ///
+    /// Shared memories can't be initialized lazily, and keep using
+    /// copy-on-write images. Memories which can't use either, for example
+    /// because they don't meet the requirements of [`Config::memory_init_cow`],
+    /// fall back to being initialized by copying.
+    ///
+    /// This feature is only supported on Linux, and requires the
+    /// `userfaultfd` system call to be available to the process, which may
+    /// require the `vm.unprivileged_userfaultfd` sysctl to be set or the
+    /// `CAP_SYS_PTRACE` capability. [`Engine::new`] returns an error if this
+    /// option is enabled and `userfaultfd` isn't available. This option also
+    /// requires [`Config::memory_init_cow`] to be enabled, since the same
+    /// compile-time analysis of a module's data segments is used for both.
+    ///
+    /// This option is disabled by default.
+    ///
+    /// [`Engine`]: crate::Engine
+    /// [`Engine::new`]: crate::Engine::new
+    /// [`Engine::userfaultfd_stats`]: crate::Engine::userfaultfd_stats
+    /// [IPI]: https://en.wikipedia.org/wiki/Inter-processor_interrupt
+    pub fn memory_init_userfaultfd(&mut self, enable: bool) -> &mut Self {
+        self.memory_init_userfaultfd = enable;
+        self
+    }
+
     /// Configures whether or not a coredump should be generated and attached to
     /// the anyhow::Error when a trap is raised.
     ///
@@ -2140,6 +2331,28 @@ impl Config {
         self
     }
 
+    /// Configures whether stores may record and replay their nondeterministic
+    /// inputs with a [`RecordReplay`](crate::RecordReplay) trace.
+    ///
+    /// Enabling this makes compiled code fully deterministic so that a
+    /// recorded execution can be replayed bit-for-bit, possibly on a different
+    /// host: NaN canonicalization is enabled and relaxed SIMD instructions use
+    /// their deterministic lowerings. Explicitly disabling either of those with
+    /// [`Config::cranelift_nan_canonicalization`] or
+    /// [`Config::relaxed_simd_deterministic`] is an error.
+    ///
+    /// Traces are attached to a store with
+    /// [`Store::set_record_replay`](crate::Store::set_record_replay). Shared
+    /// memories and wasm threads are not supported by record/replay since the
+    /// interleaving of threads is not recorded.
+    ///
+    /// This option is disabled by default.
+    #[cfg(feature = "rr")]
+    pub fn record_replay(&mut self, enable: bool) -> &mut Self {
+        self.record_replay = enable;
+        self
+    }
+
     /// Enables memory error checking for wasm programs.
     ///
     /// This option is disabled by default.
@@ -2305,7 +2518,7 @@ impl Config {
                     }
                 }
             }
-            Some(Strategy::Winch) => {
+            Some(Strategy::Winch | Strategy::Tiered) => {
                 unsupported |= WasmFeatures::GC
                     | WasmFeatures::FUNCTION_REFERENCES
                     | WasmFeatures::RELAXED_SIMD
@@ -2479,13 +2692,38 @@ impl Config {
 
         self.tunables.configure(&mut tunables);
 
+        #[cfg(feature = "rr")]
+        if self.record_replay {
+            ensure!(
+                self.tunables.relaxed_simd_deterministic != Some(false),
+                "record/replay requires deterministic relaxed SIMD"
+            );
+            tunables.relaxed_simd_deterministic = true;
+        }
+
         // If we're going to compile with winch, we must use the winch calling convention.
         #[cfg(any(feature = "cranelift", feature = "winch"))]
         {
-            tunables.winch_callable = self
-                .compiler_config
-                .as_ref()
-                .is_some_and(|c| c.strategy == Some(Strategy::Winch));
+            let strategy = self.compiler_config.as_ref().and_then(|c| c.strategy);
+            if strategy == Some(Strategy::Tiered)
+                && !cfg!(all(feature = "cranelift", feature = "winch"))
+            {
+                bail!(
+                    "tiered compilation requires both cranelift and winch support to be compiled in"
+                );
+            }
+            tunables.winch_callable = matches!(strategy, Some(Strategy::Winch | Strategy::Tiered));
+            tunables.tiered_compilation = strategy == Some(Strategy::Tiered);
+        }
+
+        if self.memory_init_userfaultfd && !tunables.memory_init_cow {
+            bail!(
+                "`Config::memory_init_userfaultfd` requires `Config::memory_init_cow` to be enabled"
+            );
+        }
+
+        if tunables.count_instructions && tunables.winch_callable {
+            bail!("instruction counting is not supported with the Winch compiler");
         }
 
         tunables.collector = if features.gc_types() {
@@ -2495,6 +2733,7 @@ impl Config {
                 Some(match self.collector.try_not_auto()? {
                     Collector::DeferredReferenceCounting => EnvCollector::DeferredReferenceCounting,
                     Collector::Null => EnvCollector::Null,
+                    Collector::Copying => EnvCollector::Copying,
                     Collector::Auto => unreachable!(),
                 })
             }
@@ -2567,7 +2806,7 @@ impl Config {
 
         #[cfg(feature = "gc")]
         #[cfg_attr(
-            not(any(feature = "gc-null", feature = "gc-drc")),
+            not(any(feature = "gc-null", feature = "gc-drc", feature = "gc-copying")),
             expect(unreachable_code, reason = "definitions known to be dummy")
         )]
         {
@@ -2586,11 +2825,28 @@ impl Config {
                 #[cfg(not(feature = "gc-null"))]
                 Collector::Null => unreachable!(),
 
+                #[cfg(feature = "gc-copying")]
+                Collector::Copying => {
+                    Arc::new(crate::runtime::vm::CopyingCollector::default()) as Arc<dyn GcRuntime>
+                }
+                #[cfg(not(feature = "gc-copying"))]
+                Collector::Copying => unreachable!(),
+
                 Collector::Auto => unreachable!(),
             }))
         }
     }
 
+    #[cfg(all(feature = "runtime", has_virtual_memory))]
+    pub(crate) fn build_userfaultfd(&self) -> Result<Option<Arc<crate::runtime::vm::Userfaultfd>>> {
+        if !self.memory_init_userfaultfd {
+            return Ok(None);
+        }
+        let uffd = crate::runtime::vm::Userfaultfd::new()
+            .context("failed to create a userfaultfd for lazy memory initialization")?;
+        Ok(Some(Arc::new(uffd)))
+    }
+
     #[cfg(feature = "runtime")]
     pub(crate) fn build_profiler(&self) -> Result<Box<dyn ProfilingAgent>> {
         Ok(match self.profiling_strategy {
@@ -2632,6 +2888,12 @@ impl Config {
             Some(Strategy::Winch) => wasmtime_winch::builder(target_for_builder)?,
             #[cfg(not(feature = "winch"))]
             Some(Strategy::Winch) => bail!("winch support not compiled in"),
+            #[cfg(all(feature = "cranelift", feature = "winch"))]
+            Some(Strategy::Tiered) => wasmtime_winch::builder(target_for_builder)?,
+            #[cfg(not(all(feature = "cranelift", feature = "winch")))]
+            Some(Strategy::Tiered) => {
+                bail!("tiered compilation requires cranelift and winch support")
+            }
 
             None | Some(Strategy::Auto) => unreachable!(),
         };
@@ -2682,6 +2944,17 @@ impl Config {
             }
         }
 
+        // Replaying a recording must produce the same NaN bit patterns as the
+        // recorded execution, regardless of the host it's replayed on.
+        #[cfg(feature = "rr")]
+        if self.record_replay
+            && !self
+                .compiler_config_mut()
+                .ensure_setting_unset_or_given("enable_nan_canonicalization", "true")
+        {
+            bail!("record/replay requires NaN canonicalization");
+        }
+
         // We require frame pointers for correct stack walking, which is safety
         // critical in the presence of reference types, and otherwise it is just
         // really bad developer experience to get wrong.
@@ -3074,6 +3347,24 @@ pub enum Strategy {
     /// For more details regarding ISA support and Wasm proposals support
     /// see https://docs.wasmtime.dev/stability-tiers.html#current-tier-status
     Winch,
+
+    /// Compiles modules with Winch first, and then recompiles functions with
+    /// Cranelift in the background once they've been called often enough.
+    ///
+    /// This combines the fast compilation of Winch with the high quality code
+    /// of Cranelift for the functions where it matters. Functions are tiered
+    /// up once they've been called [`Config::tier_up_threshold`] times, after
+    /// which new calls to them execute the Cranelift-compiled code. The same
+    /// Wasm proposals as [`Strategy::Winch`] are supported.
+    ///
+    /// Only modules compiled in the current process with APIs such as
+    /// [`Module::new`](crate::Module::new) are tiered up. Deserialized modules
+    /// and modules within components keep running their Winch-compiled code.
+    /// [`Engine::tier_up_stats`](crate::Engine::tier_up_stats) reports how
+    /// many functions have been tiered up.
+    ///
+    /// This requires both the `cranelift` and `winch` features to be enabled.
+    Tiered,
 }
 
 #[cfg(any(feature = "winch", feature = "cranelift"))]
@@ -3103,8 +3394,9 @@ impl Strategy {
 ///
 /// | Collector                   | Collects Garbage[^1] | Latency[^2] | Throughput[^3] | Allocation Speed[^4] | Heap Utilization[^5] |
 /// |-----------------------------|----------------------|-------------|----------------|----------------------|----------------------|
-/// | `DeferredReferenceCounting` | Yes, but not cycles  | 🙂         | 🙁             | 😐                   | 😐                  |
+/// | `DeferredReferenceCounting` | Yes                  | 🙂         | 🙁             | 😐                   | 😐                  |
 /// | `Null`                      | No                   | 🙂         | 🙂             | 🙂                   | 🙂                  |
+/// | `Copying`                   | Yes                  | 🙁         | 🙂             | 🙂                   | 🙁                  |
 ///
 /// [^1]: Whether or not the collector is capable of collecting garbage and cyclic garbage.
 ///
@@ -3152,8 +3444,11 @@ pub enum Collector {
     /// refcount-increment and -decrement operations. The cost is the increased
     /// latency associated with tracing the stack.
     ///
-    /// This collector cannot currently collect cycles; they will leak until the
-    /// GC heap's store is dropped.
+    /// Reference counting alone cannot collect cycles, so this collector also
+    /// has a backup mark-sweep cycle collector. It runs as part of a collection
+    /// when most of the GC heap is still allocated after reference counts have
+    /// been updated, which means that cyclic garbage may linger until the heap
+    /// comes under pressure.
     DeferredReferenceCounting,
 
     /// The null collector.
@@ -3169,6 +3464,17 @@ pub enum Collector {
     /// collectors, as this collector imposes as close to zero throughput and
     /// latency overhead as possible.
     Null,
+
+    /// The semi-space copying collector.
+    ///
+    /// A tracing collector that bump-allocates objects and, when it runs out of
+    /// space, evacuates every reachable object into a fresh region of the GC
+    /// heap, reclaiming everything left behind (including cycles) all at once.
+    /// It requires no GC barriers, so compiled Wasm code runs fast and
+    /// allocation is cheap, but every collection pauses the Wasm program for
+    /// time proportional to the amount of live data, and at most half of the
+    /// GC heap is ever available for allocation.
+    Copying,
 }
 
 impl Default for Collector {
@@ -3184,6 +3490,8 @@ impl Collector {
             Collector::Auto => {
                 if cfg!(feature = "gc-drc") {
                     Some(Collector::DeferredReferenceCounting)
+                } else if cfg!(feature = "gc-copying") {
+                    Some(Collector::Copying)
                 } else if cfg!(feature = "gc-null") {
                     Some(Collector::Null)
                 } else {
@@ -3213,12 +3521,20 @@ impl Collector {
                  the `gc-null` feature was not enabled at compile time",
             ),
 
+            #[cfg(feature = "gc-copying")]
+            Some(c @ Collector::Copying) => Ok(c),
+            #[cfg(not(feature = "gc-copying"))]
+            Some(Collector::Copying) => bail!(
+                "cannot create an engine using the copying collector because \
+                 the `gc-copying` feature was not enabled at compile time",
+            ),
+
             Some(Collector::Auto) => unreachable!(),
 
             None => bail!(
                 "cannot create an engine with GC support when none of the \
                  collectors are available; enable one of the following \
-                 features: `gc-drc`, `gc-null`",
+                 features: `gc-drc`, `gc-null`, `gc-copying`",
             ),
         }
     }
@@ -18,6 +18,11 @@ use wasmparser::WasmFeatures;
 use wasmtime_environ::{FlagValue, ObjectKind, TripleExt, Tunables};
 
 mod serialization;
+#[cfg(feature = "signing")]
+mod signing;
+
+#[cfg(feature = "signing")]
+pub use self::signing::{ArtifactSigningKey, ArtifactVerifyingKey};
 
 /// An `Engine` which is a global context for compilation and management of wasm
 /// modules.
@@ -62,6 +67,10 @@ struct EngineInner {
     signatures: TypeRegistry,
     #[cfg(all(feature = "runtime", target_has_atomic = "64"))]
     epoch: AtomicU64,
+    #[cfg(all(feature = "runtime", has_virtual_memory))]
+    userfaultfd: Option<Arc<crate::runtime::vm::Userfaultfd>>,
+    #[cfg(all(feature = "runtime", feature = "winch"))]
+    tier_up_worker: crate::runtime::tiering::TierUpWorker,
 
     /// One-time check of whether the compiler's settings, if present, are
     /// compatible with the native host.
@@ -152,6 +161,10 @@ impl Engine {
                 signatures: TypeRegistry::new(),
                 #[cfg(all(feature = "runtime", target_has_atomic = "64"))]
                 epoch: AtomicU64::new(0),
+                #[cfg(all(feature = "runtime", has_virtual_memory))]
+                userfaultfd: config.build_userfaultfd()?,
+                #[cfg(all(feature = "runtime", feature = "winch"))]
+                tier_up_worker: Default::default(),
                 compatible_with_native_host: Default::default(),
                 config,
                 tunables,
@@ -284,6 +297,38 @@ impl Engine {
         serialization::detect_precompiled_bytes(bytes)
     }
 
+    /// Combines artifacts precompiled for different targets or settings into a
+    /// single bundle.
+    ///
+    /// Each of `artifacts` must have been produced by
+    /// [`Engine::precompile_module`], [`Module::serialize`], or their
+    /// component equivalents, typically using engines configured for different
+    /// [targets](crate::Config::target) or CPU features. They must all be
+    /// modules, or all be components.
+    ///
+    /// The returned bundle can be loaded anywhere a single precompiled
+    /// artifact can, such as with [`Module::deserialize`]. Loading it picks the
+    /// artifact compatible with the loading engine, and if several are, the one
+    /// which makes use of the most CPU features. For example a bundle could
+    /// hold x86_64 artifacts with and without AVX2, an aarch64 artifact, and a
+    /// Pulley artifact for engines configured to use Pulley on other hosts.
+    ///
+    /// Note that the selected artifact is always copied out of the bundle
+    /// into memory, even when loading with [`Module::deserialize_file`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `artifacts` is empty, contains something other
+    /// than a precompiled artifact, or mixes modules and components.
+    ///
+    /// [`Module::serialize`]: crate::Module::serialize
+    /// [`Module::deserialize`]: crate::Module::deserialize
+    /// [`Module::deserialize_file`]: crate::Module::deserialize_file
+    pub fn bundle_precompiled<A: AsRef<[u8]>>(artifacts: &[A]) -> Result<Vec<u8>> {
+        let artifacts = artifacts.iter().map(|a| a.as_ref()).collect::<Vec<_>>();
+        serialization::bundle_precompiled(&artifacts)
+    }
+
     /// Like [`Engine::detect_precompiled`], but performs the detection on a file.
     #[cfg(feature = "std")]
     pub fn detect_precompiled_file(path: impl AsRef<Path>) -> Result<Option<Precompiled>> {
@@ -633,6 +678,17 @@ information about this check\
     pub fn is_pulley(&self) -> bool {
         self.target().is_pulley()
     }
+
+    /// Signs the serialized artifact `bytes`, produced by this engine, if this
+    /// engine is configured to sign artifacts with `Config::sign_artifacts`.
+    pub(crate) fn sign_serialized(&self, bytes: Vec<u8>) -> Result<Vec<u8>> {
+        #[cfg(feature = "signing")]
+        let bytes = match &self.config().signing_key {
+            Some(key) => signing::sign(key, bytes)?,
+            None => bytes,
+        };
+        Ok(bytes)
+    }
 }
 
 #[cfg(any(feature = "cranelift", feature = "winch"))]
@@ -753,6 +809,33 @@ impl Engine {
         crate::runtime::vm::PoolingAllocatorMetrics::new(self)
     }
 
+    /// Returns statistics about lazily-initialized linear memories if this
+    /// engine was configured with
+    /// [`Config::memory_init_userfaultfd`](crate::Config::memory_init_userfaultfd).
+    pub fn userfaultfd_stats(&self) -> Option<UserfaultfdStats> {
+        #[cfg(has_virtual_memory)]
+        return self.userfaultfd().map(|uffd| uffd.stats());
+        #[cfg(not(has_virtual_memory))]
+        return None;
+    }
+
+    /// Returns statistics about the functions recompiled with Cranelift if
+    /// this engine was configured with
+    /// [`Strategy::Tiered`](crate::Strategy::Tiered).
+    #[cfg(feature = "winch")]
+    pub fn tier_up_stats(&self) -> Option<TierUpStats> {
+        if self.tunables().tiered_compilation {
+            Some(self.tier_up_worker().stats())
+        } else {
+            None
+        }
+    }
+
+    #[cfg(has_virtual_memory)]
+    pub(crate) fn userfaultfd(&self) -> Option<&Arc<crate::runtime::vm::Userfaultfd>> {
+        self.inner.userfaultfd.as_ref()
+    }
+
     pub(crate) fn allocator(&self) -> &dyn crate::runtime::vm::InstanceAllocator {
         self.inner.allocator.as_ref()
     }
@@ -770,10 +853,20 @@ impl Engine {
         self.config().cache.as_ref()
     }
 
+    #[cfg(all(feature = "cache", any(feature = "cranelift", feature = "winch")))]
+    pub(crate) fn code_store(&self) -> Option<&wasmtime_cache::CodeStore> {
+        self.config().code_store.as_ref()
+    }
+
     pub(crate) fn signatures(&self) -> &TypeRegistry {
         &self.inner.signatures
     }
 
+    #[cfg(feature = "winch")]
+    pub(crate) fn tier_up_worker(&self) -> &crate::runtime::tiering::TierUpWorker {
+        &self.inner.tier_up_worker
+    }
+
     #[cfg(feature = "runtime")]
     pub(crate) fn custom_code_memory(&self) -> Option<&Arc<dyn CustomCodeMemory>> {
         self.config().custom_code_memory.as_ref()
@@ -833,7 +926,7 @@ impl Engine {
     /// Returns the required alignment for a code image, if we
     /// allocate in a way that is not a system `mmap()` that naturally
     /// aligns it.
-    fn required_code_alignment(&self) -> usize {
+    pub(crate) fn required_code_alignment(&self) -> usize {
         self.custom_code_memory()
             .map(|c| c.required_alignment())
             .unwrap_or(1)
@@ -858,6 +951,27 @@ impl Engine {
         )
     }
 
+    /// Like `load_code_bytes`, but first verifies that the artifact was signed
+    /// by a key trusted by this engine.
+    #[cfg(feature = "signing")]
+    pub(crate) fn load_code_bytes_verified(
+        &self,
+        bytes: &[u8],
+        expected: ObjectKind,
+    ) -> Result<Arc<crate::CodeMemory>> {
+        let artifact = serialization::select_from_bundle(self, bytes, expected)?.unwrap_or(bytes);
+        let mut mmap = crate::runtime::vm::MmapVec::from_slice_with_alignment(
+            artifact,
+            self.required_code_alignment(),
+        )?;
+        // Verify the copy which is about to be loaded, rather than `bytes`, so
+        // that what's verified is exactly what gets loaded.
+        //
+        // SAFETY: `mmap` was just created and hasn't been made read-only.
+        signing::verify(self, unsafe { mmap.as_mut_slice() })?;
+        self.load_code(mmap, expected)
+    }
+
     /// Loads a `CodeMemory` from the specified memory region without copying
     ///
     /// The `expected` marker here is whether the bytes are expected to be
@@ -905,6 +1019,15 @@ impl Engine {
         self.check_compatible_with_native_host()
             .context("compilation settings are not compatible with the native host")?;
 
+        let selected = match serialization::select_from_bundle(self, &mmap, expected)? {
+            Some(artifact) => Some(crate::runtime::vm::MmapVec::from_slice_with_alignment(
+                artifact,
+                self.required_code_alignment(),
+            )?),
+            None => None,
+        };
+        let mmap = selected.unwrap_or(mmap);
+
         serialization::check_compatible(self, &mmap, expected)?;
         let mut code = crate::CodeMemory::new(self, mmap)?;
         code.publish()?;
@@ -984,3 +1107,43 @@ impl EngineWeak {
         alloc::sync::Weak::upgrade(&self.inner).map(|inner| Engine { inner })
     }
 }
+
+/// Statistics about the linear memories initialized lazily by an [`Engine`],
+/// see [`Config::memory_init_userfaultfd`](crate::Config::memory_init_userfaultfd).
+///
+/// Returned by [`Engine::userfaultfd_stats`]. All counts are totals since the
+/// engine was created.
+#[cfg(feature = "runtime")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct UserfaultfdStats {
+    /// The number of linear memories whose initial image was registered to
+    /// be populated lazily.
+    pub memories_registered: u64,
+
+    /// The number of host pages populated on first access, including those
+    /// counted in `zero_pages_faulted`.
+    pub pages_faulted: u64,
+
+    /// The number of host pages populated on first access whose initial
+    /// contents were entirely zero.
+    pub zero_pages_faulted: u64,
+}
+
+/// Statistics about the functions tiered up by an [`Engine`], see
+/// [`Strategy::Tiered`](crate::Strategy::Tiered).
+///
+/// Returned by [`Engine::tier_up_stats`]. All counts are totals since the
+/// engine was created.
+#[cfg(all(feature = "runtime", feature = "winch"))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TierUpStats {
+    /// The number of functions which have been recompiled with Cranelift and
+    /// are now called instead of their Winch-compiled code.
+    pub functions_tiered_up: u64,
+
+    /// The number of functions which couldn't be recompiled, and keep running
+    /// their Winch-compiled code.
+    pub functions_failed: u64,
+}
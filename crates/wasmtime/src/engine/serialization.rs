@@ -20,6 +20,16 @@
 //! This is hoped to help distinguish easily Wasmtime-based ELF files from
 //! other random ELF files, as well as provide better error messages for
 //! using wasmtime artifacts across versions.
+//!
+//! Artifacts compiled for several targets or sets of CPU features can also be
+//! combined into a single bundle, see `bundle_precompiled`. A bundle is:
+//!
+//! 1. The bytes of `BUNDLE_MAGIC`.
+//! 2. A version byte, currently `BUNDLE_VERSION`.
+//! 3. A `postcard`-encoded list of the ELF files of each artifact.
+//!
+//! When a bundle is loaded the best-matching compatible artifact is picked out
+//! of it with `select_from_bundle` and then loaded as usual.
 
 use crate::prelude::*;
 use crate::{Engine, ModuleVersionStrategy, Precompiled};
@@ -35,6 +45,9 @@ use wasmtime_environ::{FlagValue, ObjectKind, Tunables};
 
 const VERSION: u8 = 0;
 
+const BUNDLE_MAGIC: &[u8; 8] = b"WTBUNDLE";
+const BUNDLE_VERSION: u8 = 0;
+
 /// Verifies that the serialized engine in `mmap` is compatible with the
 /// `engine` provided.
 ///
@@ -44,6 +57,16 @@ const VERSION: u8 = 0;
 /// compiler options, etc. If a mismatch is found and the compilation metadata
 /// specified is incompatible then an error is returned.
 pub fn check_compatible(engine: &Engine, mmap: &[u8], expected: ObjectKind) -> Result<()> {
+    read_metadata(engine, mmap, expected)?.check_compatible(engine)
+}
+
+/// Reads the serialized engine in `mmap`, checking that it's a precompiled
+/// artifact of the `expected` kind for this version of Wasmtime.
+fn read_metadata<'a>(
+    engine: &Engine,
+    mmap: &'a [u8],
+    expected: ObjectKind,
+) -> Result<Metadata<'a>> {
     // Parse the input `mmap` as an ELF file and see if the header matches the
     // Wasmtime-generated header. This includes a Wasmtime-specific `os_abi` and
     // the `e_flags` field should indicate whether `expected` matches or not.
@@ -109,7 +132,89 @@ pub fn check_compatible(engine: &Engine, mmap: &[u8], expected: ObjectKind) -> R
         }
         ModuleVersionStrategy::None => { /* ignore the version info, accept all */ }
     }
-    postcard::from_bytes::<Metadata<'_>>(data)?.check_compatible(engine)
+    Ok(postcard::from_bytes::<Metadata<'_>>(data)?)
+}
+
+/// Combines precompiled `artifacts` into a bundle, see
+/// `Engine::bundle_precompiled`.
+pub fn bundle_precompiled(artifacts: &[&[u8]]) -> Result<Vec<u8>> {
+    let mut kind = None;
+    for (i, artifact) in artifacts.iter().enumerate() {
+        let artifact_kind = detect_precompiled_bytes(artifact)
+            .filter(|_| !is_bundle(artifact))
+            .ok_or_else(|| anyhow!("artifact {i} is not a precompiled module or component"))?;
+        if *kind.get_or_insert(artifact_kind) != artifact_kind {
+            bail!("cannot bundle precompiled modules together with components");
+        }
+    }
+    ensure!(kind.is_some(), "cannot create an empty bundle");
+
+    let mut bundle = Vec::new();
+    bundle.extend_from_slice(BUNDLE_MAGIC);
+    bundle.push(BUNDLE_VERSION);
+    // Note that `postcard` encodes a slice of `u8` the same as a byte string,
+    // which is what allows `read_bundle` to borrow the artifacts.
+    bundle.extend(postcard::to_allocvec(artifacts)?);
+    Ok(bundle)
+}
+
+fn is_bundle(bytes: &[u8]) -> bool {
+    bytes.starts_with(BUNDLE_MAGIC)
+}
+
+fn read_bundle(bytes: &[u8]) -> Result<Vec<&[u8]>> {
+    let data = &bytes[BUNDLE_MAGIC.len()..];
+    let (version, data) = data
+        .split_first()
+        .ok_or_else(|| anyhow!("invalid precompiled bundle"))?;
+    if *version != BUNDLE_VERSION {
+        bail!("mismatched version in precompiled bundle");
+    }
+    Ok(postcard::from_bytes(data)?)
+}
+
+/// Picks the artifact in the bundle `bytes` which best matches `engine`.
+///
+/// Returns `Ok(None)` if `bytes` isn't a bundle, and an error if none of the
+/// bundle's artifacts are compatible with `engine`. Among compatible artifacts
+/// the one which makes use of the most CPU features is picked, and otherwise
+/// the earliest one in the bundle.
+pub fn select_from_bundle<'a>(
+    engine: &Engine,
+    bytes: &'a [u8],
+    expected: ObjectKind,
+) -> Result<Option<&'a [u8]>> {
+    if !is_bundle(bytes) {
+        return Ok(None);
+    }
+    let mut best = None;
+    let mut errors = Vec::new();
+    for (i, artifact) in read_bundle(bytes)?.into_iter().enumerate() {
+        let result = read_metadata(engine, artifact, expected).and_then(|metadata| {
+            let features = metadata
+                .isa_flags
+                .iter()
+                .filter(|(_, value)| *value == FlagValue::Bool(true))
+                .count();
+            metadata.check_compatible(engine)?;
+            Ok(features)
+        });
+        match result {
+            Ok(features) => {
+                if best.is_none_or(|(best_features, _)| features > best_features) {
+                    best = Some((features, artifact));
+                }
+            }
+            Err(e) => errors.push(format!("artifact {i}: {e:#}")),
+        }
+    }
+    match best {
+        Some((_, artifact)) => Ok(Some(artifact)),
+        None => bail!(
+            "no artifact in precompiled bundle is compatible with this engine:\n  {}",
+            errors.join("\n  ")
+        ),
+    }
 }
 
 #[cfg(any(feature = "cranelift", feature = "winch"))]
@@ -135,6 +240,11 @@ pub fn append_compiler_info(engine: &Engine, obj: &mut Object<'_>, metadata: &Me
     data.extend_from_slice(version.as_bytes());
     data.extend(postcard::to_allocvec(metadata).unwrap());
     obj.set_section_data(section, data, 1);
+
+    #[cfg(feature = "signing")]
+    if let Some(key) = &engine.config().signing_key {
+        super::signing::append_signature_section(obj, key);
+    }
 }
 
 fn detect_precompiled<'data, R: object::ReadRef<'data>>(
@@ -156,12 +266,24 @@ fn detect_precompiled<'data, R: object::ReadRef<'data>>(
 }
 
 pub fn detect_precompiled_bytes(bytes: &[u8]) -> Option<Precompiled> {
+    if is_bundle(bytes) {
+        return detect_precompiled_bytes(read_bundle(bytes).ok()?.first()?);
+    }
     detect_precompiled(ElfFile64::parse(bytes).ok()?)
 }
 
 #[cfg(feature = "std")]
 pub fn detect_precompiled_file(path: impl AsRef<std::path::Path>) -> Result<Option<Precompiled>> {
-    let read_cache = object::ReadCache::new(std::fs::File::open(path)?);
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut magic = [0; BUNDLE_MAGIC.len()];
+    if file.read_exact(&mut magic).is_ok() && is_bundle(&magic) {
+        let mut bytes = magic.to_vec();
+        file.read_to_end(&mut bytes)?;
+        return Ok(detect_precompiled_bytes(&bytes));
+    }
+    let read_cache = object::ReadCache::new(file);
     let obj = ElfFile64::parse(&read_cache)?;
     Ok(detect_precompiled(obj))
 }
@@ -273,12 +395,14 @@ impl Metadata<'_> {
             debug_guest,
             parse_wasm_debuginfo,
             consume_fuel,
+            count_instructions,
             epoch_interruption,
             memory_may_move,
             guard_before_linear_memory,
             table_lazy_init,
             relaxed_simd_deterministic,
             winch_callable,
+            tiered_compilation,
             signals_based_traps,
             memory_init_cow,
             inlining,
@@ -297,6 +421,10 @@ impl Metadata<'_> {
 
             // Just a debugging aid, doesn't affect functionality at all.
             debug_adapter_modules: _,
+
+            // Deserialized modules never tier up, so it doesn't matter how
+            // many calls their functions ask to be tiered up after.
+            tier_up_threshold: _,
         } = self.tunables;
 
         Self::check_collector(collector, other.collector)?;
@@ -322,6 +450,11 @@ impl Metadata<'_> {
             "WebAssembly backtrace support",
         )?;
         Self::check_bool(consume_fuel, other.consume_fuel, "fuel support")?;
+        Self::check_bool(
+            count_instructions,
+            other.count_instructions,
+            "instruction counting",
+        )?;
         Self::check_bool(
             epoch_interruption,
             other.epoch_interruption,
@@ -344,6 +477,11 @@ impl Metadata<'_> {
             other.winch_callable,
             "Winch calling convention",
         )?;
+        Self::check_bool(
+            tiered_compilation,
+            other.tiered_compilation,
+            "tiered compilation",
+        )?;
         Self::check_bool(
             signals_based_traps,
             other.signals_based_traps,
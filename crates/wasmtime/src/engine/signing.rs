@@ -0,0 +1,173 @@
+//! Signing and verification of precompiled artifacts.
+//!
+//! An engine configured with `Config::sign_artifacts` adds a section named
+//! `ELF_WASMTIME_SIGNATURE` to the artifacts it compiles, next to the engine
+//! metadata added by `append_compiler_info`. The structure of this section is:
+//!
+//! 1. A version byte, currently `VERSION`.
+//! 2. The ed25519 public key of the signer.
+//! 3. The ed25519 signature of the artifact.
+//!
+//! The signature is left as zeros while the artifact is being compiled and is
+//! filled in by `sign` once the whole artifact has been produced. It covers
+//! every byte of the artifact, with the signature itself taken to be zero.
+
+#[cfg(feature = "runtime")]
+use crate::Engine;
+use crate::prelude::*;
+use core::fmt;
+use core::ops::Range;
+#[cfg(feature = "runtime")]
+use ed25519_dalek::Signature;
+use ed25519_dalek::{
+    PUBLIC_KEY_LENGTH, SECRET_KEY_LENGTH, SIGNATURE_LENGTH, Signer, SigningKey, VerifyingKey,
+};
+use object::endian::Endianness;
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+use object::write::{Object, StandardSegment};
+use object::{Object as _, ObjectSection, read::elf::ElfFile64};
+use wasmtime_environ::obj;
+
+const VERSION: u8 = 0;
+const SECTION_LEN: usize = 1 + PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH;
+
+/// A private key that precompiled artifacts are signed with.
+///
+/// See [`Config::sign_artifacts`](crate::Config::sign_artifacts).
+#[derive(Clone)]
+pub struct ArtifactSigningKey(SigningKey);
+
+impl ArtifactSigningKey {
+    /// Creates a signing key from the 32 bytes of an ed25519 secret key.
+    pub fn from_bytes(secret: &[u8; SECRET_KEY_LENGTH]) -> ArtifactSigningKey {
+        ArtifactSigningKey(SigningKey::from_bytes(secret))
+    }
+
+    /// Returns the public key which verifies signatures made with this key.
+    pub fn verifying_key(&self) -> ArtifactVerifyingKey {
+        ArtifactVerifyingKey(self.0.verifying_key())
+    }
+}
+
+impl fmt::Debug for ArtifactSigningKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ArtifactSigningKey")
+            .field("verifying_key", &self.verifying_key())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A public key that signatures of precompiled artifacts are verified with.
+///
+/// See [`Config::trust_artifact_key`](crate::Config::trust_artifact_key).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ArtifactVerifyingKey(VerifyingKey);
+
+impl ArtifactVerifyingKey {
+    /// Creates a verifying key from the 32 bytes of an ed25519 public key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` isn't a valid ed25519 public key.
+    pub fn from_bytes(bytes: &[u8; PUBLIC_KEY_LENGTH]) -> Result<ArtifactVerifyingKey> {
+        VerifyingKey::from_bytes(bytes)
+            .map(ArtifactVerifyingKey)
+            .map_err(|_| anyhow!("invalid ed25519 public key"))
+    }
+
+    /// Returns the 32 bytes of this ed25519 public key.
+    pub fn to_bytes(&self) -> [u8; PUBLIC_KEY_LENGTH] {
+        self.0.to_bytes()
+    }
+}
+
+/// Adds the signature section for `key` to `obj`, with the signature itself
+/// left to be filled in by `sign`.
+#[cfg(any(feature = "cranelift", feature = "winch"))]
+pub fn append_signature_section(obj: &mut Object<'_>, key: &ArtifactSigningKey) {
+    let section = obj.add_section(
+        obj.segment_name(StandardSegment::Data).to_vec(),
+        obj::ELF_WASMTIME_SIGNATURE.as_bytes().to_vec(),
+        object::SectionKind::ReadOnlyData,
+    );
+    let mut data = Vec::with_capacity(SECTION_LEN);
+    data.push(VERSION);
+    data.extend_from_slice(&key.verifying_key().to_bytes());
+    data.resize(SECTION_LEN, 0);
+    obj.set_section_data(section, data, 1);
+}
+
+/// Returns the range of `artifact` which holds its signature section, if it
+/// has one.
+fn signature_section(artifact: &[u8]) -> Result<Option<Range<usize>>> {
+    let obj = ElfFile64::<Endianness>::parse(artifact)
+        .map_err(obj::ObjectCrateErrorWrapper)
+        .context("failed to parse precompiled artifact as an ELF")?;
+    let Some(section) = obj.section_by_name(obj::ELF_WASMTIME_SIGNATURE) else {
+        return Ok(None);
+    };
+    let (offset, len) = section
+        .file_range()
+        .ok_or_else(|| anyhow!("invalid signature section"))?;
+    ensure!(
+        len == u64::try_from(SECTION_LEN).unwrap(),
+        "invalid signature section"
+    );
+    let start = usize::try_from(offset)?;
+    ensure!(
+        start
+            .checked_add(SECTION_LEN)
+            .is_some_and(|end| end <= artifact.len()),
+        "invalid signature section"
+    );
+    Ok(Some(start..start + SECTION_LEN))
+}
+
+/// Signs `artifact`, which must have been compiled with a signature section
+/// for `key`.
+pub fn sign(key: &ArtifactSigningKey, mut artifact: Vec<u8>) -> Result<Vec<u8>> {
+    let section = signature_section(&artifact)?
+        .ok_or_else(|| anyhow!("artifact was not compiled with signing enabled"))?;
+    ensure!(
+        artifact[section.start + 1..][..PUBLIC_KEY_LENGTH] == key.verifying_key().to_bytes(),
+        "artifact was compiled for signing with a different key"
+    );
+    let signature = section.end - SIGNATURE_LENGTH..section.end;
+
+    artifact[signature.clone()].fill(0);
+    let bytes = key.0.sign(&artifact).to_bytes();
+    artifact[signature].copy_from_slice(&bytes);
+    Ok(artifact)
+}
+
+/// Verifies that `artifact` was signed by one of the keys trusted by
+/// `engine`.
+///
+/// The signature within `artifact` is set to zero in the process.
+#[cfg(feature = "runtime")]
+pub fn verify(engine: &Engine, artifact: &mut [u8]) -> Result<()> {
+    let trusted = &engine.config().trusted_artifact_keys;
+    ensure!(
+        !trusted.is_empty(),
+        "no keys are trusted to sign precompiled artifacts"
+    );
+    let section = signature_section(artifact)?
+        .ok_or_else(|| anyhow!("precompiled artifact is not signed"))?;
+    let (version, rest) = artifact[section.clone()].split_first().unwrap();
+    ensure!(
+        *version == VERSION,
+        "mismatched version in signature section"
+    );
+    let (public_key, signature) = rest.split_at(PUBLIC_KEY_LENGTH);
+    let key = ArtifactVerifyingKey::from_bytes(public_key.try_into().unwrap())?;
+    ensure!(
+        trusted.contains(&key),
+        "precompiled artifact was signed by an untrusted key"
+    );
+    let signature = Signature::from_bytes(signature.try_into().unwrap());
+
+    artifact[section.end - SIGNATURE_LENGTH..section.end].fill(0);
+    key.0
+        .verify_strict(artifact, &signature)
+        .map_err(|_| anyhow!("invalid signature on precompiled artifact"))
+}
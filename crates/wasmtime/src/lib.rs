@@ -253,6 +253,16 @@
 //! * `threads` - Enabled by default, this enables compile-time support for the
 //!   WebAssembly `threads` proposal, notably shared memories.
 //!
+//! * `rr` - Disabled by default, this enables support for recording the
+//!   nondeterministic inputs of a [`Store`] to a trace and deterministically
+//!   replaying them later. This can be configured via
+//!   [`Config::record_replay`].
+//!
+//! * `signing` - Disabled by default, this enables signing precompiled
+//!   artifacts with [`Config::sign_artifacts`] and loading them without
+//!   `unsafe` through [`Module::deserialize_verified`] once their signature
+//!   has been verified.
+//!
 //! * `call-hook` - Disabled by default, this enables support for the
 //!   [`Store::call_hook`] API. This incurs a small overhead on all
 //!   entries/exits from WebAssembly and may want to be disabled by some
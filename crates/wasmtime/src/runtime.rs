@@ -49,6 +49,8 @@ pub(crate) mod module;
 pub(crate) mod native_debug;
 pub(crate) mod resources;
 pub(crate) mod store;
+#[cfg(feature = "winch")]
+pub(crate) mod tiering;
 pub(crate) mod trampoline;
 pub(crate) mod trap;
 pub(crate) mod type_registry;
@@ -92,7 +94,9 @@ pub use resources::*;
 #[cfg(all(feature = "async", feature = "call-hook"))]
 pub use store::CallHookHandler;
 pub use store::{
-    AsContext, AsContextMut, CallHook, Store, StoreContext, StoreContextMut, UpdateDeadline,
+    AsContext, AsContextMut, BlockInstructionCount, CallHook, FunctionInstructionCounts,
+    InstructionCounts, ModuleInstructionCounts, Store, StoreContext, StoreContextMut,
+    StoreSnapshot, StoreUsage, UpdateDeadline,
 };
 pub use trap::*;
 pub use types::*;
@@ -105,7 +109,7 @@ pub use vm::{PoolConcurrencyLimitError, PoolingAllocatorMetrics};
 #[cfg(feature = "profiling")]
 mod profiling;
 #[cfg(feature = "profiling")]
-pub use profiling::GuestProfiler;
+pub use profiling::{GuestProfiler, ProfileAggregator};
 
 #[cfg(feature = "async")]
 pub(crate) mod stack;
@@ -117,6 +121,11 @@ mod coredump;
 #[cfg(feature = "coredump")]
 pub use coredump::*;
 
+#[cfg(feature = "rr")]
+mod rr;
+#[cfg(feature = "rr")]
+pub use rr::*;
+
 #[cfg(feature = "wave")]
 mod wave;
 
@@ -215,6 +215,12 @@ impl EngineCode {
         self.original_code.frame_tables()
     }
 
+    /// Returns the encoded instruction-counts section to pass to
+    /// `wasmtime_environ::InstructionCountTable::parse`.
+    pub fn instruction_counts(&self) -> &[u8] {
+        self.original_code.instruction_counts()
+    }
+
     /// Returns the data in the `ELF_NAME_DATA` section.
     #[inline]
     pub fn func_name_data(&self) -> &[u8] {
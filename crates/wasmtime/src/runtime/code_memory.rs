@@ -8,7 +8,7 @@ use core::ops::Range;
 use object::SectionFlags;
 use object::endian::Endianness;
 use object::read::{Object, ObjectSection, elf::ElfFile64};
-use wasmtime_environ::{Trap, lookup_trap_code, obj};
+use wasmtime_environ::{InstructionCountTable, Trap, lookup_trap_code, obj};
 use wasmtime_unwinder::ExceptionTable;
 
 /// Management of executable memory within a `MmapVec`
@@ -38,6 +38,7 @@ pub struct CodeMemory {
     stack_map_data: Range<usize>,
     exception_data: Range<usize>,
     frame_tables_data: Range<usize>,
+    instruction_counts_data: Range<usize>,
     func_name_data: Range<usize>,
     info_data: Range<usize>,
     wasm_dwarf: Range<usize>,
@@ -129,6 +130,7 @@ impl CodeMemory {
         let mut trap_data = 0..0;
         let mut exception_data = 0..0;
         let mut frame_tables_data = 0..0;
+        let mut instruction_counts_data = 0..0;
         let mut wasm_data = 0..0;
         let mut address_map_data = 0..0;
         let mut stack_map_data = 0..0;
@@ -180,6 +182,7 @@ impl CodeMemory {
                 obj::ELF_WASMTIME_TRAPS => trap_data = range,
                 obj::ELF_WASMTIME_EXCEPTIONS => exception_data = range,
                 obj::ELF_WASMTIME_FRAMES => frame_tables_data = range,
+                obj::ELF_WASMTIME_INSTRUCTION_COUNTS => instruction_counts_data = range,
                 obj::ELF_NAME_DATA => func_name_data = range,
                 obj::ELF_WASMTIME_INFO => info_data = range,
                 obj::ELF_WASMTIME_DWARF => wasm_dwarf = range,
@@ -205,6 +208,14 @@ impl CodeMemory {
             let _ = ExceptionTable::parse(&mmap[exception_data.clone()])?;
         }
 
+        // Unlike the exception table the instruction-count metadata is
+        // validated unconditionally since it's only present when
+        // instruction counting is enabled and it's read eagerly when
+        // instantiating.
+        if !instruction_counts_data.is_empty() {
+            let _ = InstructionCountTable::parse(&mmap[instruction_counts_data.clone()])?;
+        }
+
         Ok(Self {
             mmap,
             #[cfg(has_host_compiler_backend)]
@@ -226,6 +237,7 @@ impl CodeMemory {
             stack_map_data,
             exception_data,
             frame_tables_data,
+            instruction_counts_data,
             func_name_data,
             wasm_dwarf,
             info_data,
@@ -293,6 +305,12 @@ impl CodeMemory {
         &self.mmap[self.frame_tables_data.clone()]
     }
 
+    /// Returns the encoded instruction-counts section to pass to
+    /// `wasmtime_environ::InstructionCountTable::parse`.
+    pub fn instruction_counts(&self) -> &[u8] {
+        &self.mmap[self.instruction_counts_data.clone()]
+    }
+
     /// Returns the contents of the `ELF_WASMTIME_INFO` section, or an empty
     /// slice if it wasn't found.
     #[inline]
@@ -214,6 +214,15 @@ impl Component {
         Component::from_parts(engine, code, None)
     }
 
+    /// Same as [`Module::deserialize_verified`], but for components.
+    ///
+    /// [`Module::deserialize_verified`]: crate::Module::deserialize_verified
+    #[cfg(feature = "signing")]
+    pub fn deserialize_verified(engine: &Engine, bytes: impl AsRef<[u8]>) -> Result<Component> {
+        let code = engine.load_code_bytes_verified(bytes.as_ref(), ObjectKind::Component)?;
+        Component::from_parts(engine, code, None)
+    }
+
     /// Same as [`Module::deserialize_raw`], but for components.
     ///
     /// See [`Component::deserialize`] for additional information; this method
@@ -562,7 +571,8 @@ impl Component {
     /// [`Module::serialize`]: crate::Module::serialize
     /// [`Module`]: crate::Module
     pub fn serialize(&self) -> Result<Vec<u8>> {
-        Ok(self.engine_code().image().to_vec())
+        self.engine()
+            .sign_serialized(self.engine_code().image().to_vec())
     }
 
     /// Creates a new `VMFuncRef` with all fields filled out for the destructor
@@ -20,6 +20,8 @@ use core::mem::{self, MaybeUninit};
 #[cfg(feature = "component-model-async")]
 use core::pin::Pin;
 use core::ptr::NonNull;
+#[cfg(feature = "rr")]
+use wasmtime_environ::component::ComponentTypes;
 use wasmtime_environ::component::{
     CanonicalAbiInfo, InterfaceType, MAX_FLAT_PARAMS, MAX_FLAT_RESULTS, OptionsIndex, TypeFuncIndex,
 };
@@ -295,6 +297,16 @@ where
             return Err(anyhow!(crate::Trap::CannotLeaveComponent));
         }
 
+        #[cfg(feature = "rr")]
+        if let Some(rr) = store.0.record_replay().cloned() {
+            ensure!(
+                !opts.async_ && !Self::ASYNC,
+                "record/replay does not support async-lowered imports or concurrent \
+                 host functions"
+            );
+            return self.call_recorded(rr, store, instance, ty, options, storage);
+        }
+
         if opts.async_ {
             #[cfg(feature = "component-model-async")]
             return self.call_async_lower(store, instance, ty, options, storage);
@@ -362,6 +374,61 @@ where
         Self::lower_result_and_exit_call(&mut lower, ty, ret, dst)
     }
 
+    /// Implementation of the "sync" ABI in a store which records or replays
+    /// its host calls, see [`RecordReplay`](crate::RecordReplay).
+    ///
+    /// When replaying, this host function isn't called at all and the effects
+    /// recorded for it are applied instead.
+    #[cfg(feature = "rr")]
+    fn call_recorded(
+        &self,
+        rr: crate::RecordReplay,
+        mut store: StoreContextMut<'_, T>,
+        instance: Instance,
+        ty: TypeFuncIndex,
+        options: OptionsIndex,
+        storage: &mut [MaybeUninit<ValRaw>],
+    ) -> Result<()> {
+        let types = instance.id().get(store.0).component().types().clone();
+        let fty = &types[ty];
+        ensure!(
+            ![fty.params, fty.results]
+                .iter()
+                .flat_map(|tuple| types[*tuple].types.iter())
+                .any(|ty| contains_handles(&types, ty)),
+            "record/replay does not support host functions with resources, futures, \
+             streams or error contexts in their signature"
+        );
+        let flat_results = types[fty.results]
+            .abi
+            .flat_count(MAX_FLAT_RESULTS)
+            .unwrap_or(0);
+
+        match rr.enter_component_host_call(store.0)? {
+            crate::rr::HostCall::Replay => {
+                rr.replay_component_host_call(store.0, &mut storage[..flat_results])
+            }
+            crate::rr::HostCall::Nested => {
+                self.call_sync_lower(store, instance, ty, options, storage)
+            }
+            crate::rr::HostCall::Record(recorder) => {
+                let result =
+                    self.call_sync_lower(store.as_context_mut(), instance, ty, options, storage);
+                let results = result.as_ref().map(|()| {
+                    // SAFETY: the flat results were initialized by lowering
+                    // them above.
+                    unsafe {
+                        mem::transmute::<&[MaybeUninit<ValRaw>], &[ValRaw]>(
+                            &storage[..flat_results],
+                        )
+                    }
+                });
+                recorder.exit_component(store.0, results)?;
+                result
+            }
+        }
+    }
+
     /// Implementation of the "async" ABI of the component model.
     ///
     /// This is invoked when a component has the `async` options specified on
@@ -705,3 +772,51 @@ pub(crate) fn validate_inbounds_dynamic(
     }
     Ok(ptr)
 }
+
+/// Returns whether values of type `ty` contain handles to state which lives
+/// in the host, such as resources, which record/replay can't capture.
+#[cfg(feature = "rr")]
+fn contains_handles(types: &ComponentTypes, ty: &InterfaceType) -> bool {
+    match ty {
+        InterfaceType::Own(_)
+        | InterfaceType::Borrow(_)
+        | InterfaceType::Future(_)
+        | InterfaceType::Stream(_)
+        | InterfaceType::ErrorContext(_) => true,
+        InterfaceType::Record(i) => types[*i]
+            .fields
+            .iter()
+            .any(|field| contains_handles(types, &field.ty)),
+        InterfaceType::Variant(i) => types[*i]
+            .cases
+            .values()
+            .flatten()
+            .any(|ty| contains_handles(types, ty)),
+        InterfaceType::List(i) => contains_handles(types, &types[*i].element),
+        InterfaceType::Tuple(i) => types[*i].types.iter().any(|ty| contains_handles(types, ty)),
+        InterfaceType::Option(i) => contains_handles(types, &types[*i].ty),
+        InterfaceType::Result(i) => {
+            let result = &types[*i];
+            result
+                .ok
+                .iter()
+                .chain(&result.err)
+                .any(|ty| contains_handles(types, ty))
+        }
+        InterfaceType::Bool
+        | InterfaceType::S8
+        | InterfaceType::U8
+        | InterfaceType::S16
+        | InterfaceType::U16
+        | InterfaceType::S32
+        | InterfaceType::U32
+        | InterfaceType::S64
+        | InterfaceType::U64
+        | InterfaceType::Float32
+        | InterfaceType::Float64
+        | InterfaceType::Char
+        | InterfaceType::String
+        | InterfaceType::Flags(_)
+        | InterfaceType::Enum(_) => false,
+    }
+}
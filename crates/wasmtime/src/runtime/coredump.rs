@@ -6,6 +6,9 @@ use crate::{
 };
 use std::fmt;
 
+mod image;
+pub use image::*;
+
 /// Representation of a core dump of a WebAssembly module
 ///
 /// When the Config::coredump_on_trap option is enabled this structure is
@@ -126,7 +129,9 @@ impl WasmCoreDump {
                     maximum: ty.maximum(),
                     memory64: ty.is_64(),
                     shared: ty.is_shared(),
-                    page_size_log2: None,
+                    page_size_log2: Some(ty.page_size_log2())
+                        .filter(|p| *p != wasmtime_environ::Memory::DEFAULT_PAGE_SIZE_LOG2)
+                        .map(u32::from),
                 });
 
                 // Attach the memory data, balancing number of data segments and
@@ -0,0 +1,471 @@
+use crate::prelude::*;
+use crate::{FrameInfo, MemoryType, MemoryTypeBuilder, Module, Mutability, Val, WasmBacktrace};
+use wasmparser::{
+    AbstractHeapType, BinaryReader, ConstExpr, CoreDumpInstancesSection, CoreDumpModulesSection,
+    CoreDumpSection, CoreDumpStackSection, CoreDumpValue, DataKind, HeapType, Operator, Parser,
+    Payload,
+};
+use wasmtime_environ::{EntityRef, FilePos, FuncIndex};
+
+/// The largest memory a core dump may contain, which is the size of the
+/// largest 32-bit memory.
+///
+/// A memory's size comes from the core dump itself rather than from the data
+/// that it contains, so without a limit a small, malformed core dump could
+/// claim a memory so large that allocating it aborts the process.
+const MAX_MEMORY_SIZE: u64 = 1 << 32;
+
+/// A core dump which was loaded back from [the standard core dump binary
+/// format][spec], for example one produced by [`WasmCoreDump::serialize`].
+///
+/// Unlike a [`WasmCoreDump`], which refers to the live items of the store it
+/// was captured from, this is a self-contained copy of the state recorded in a
+/// core dump. It can be used to inspect the memories, globals and stack frames
+/// of a crashed program without re-running it. Together with the modules that
+/// were running, see [`CoreDumpImage::backtrace`], the recorded program
+/// counters can also be mapped back to function names and, when the modules
+/// have DWARF debug information, to source locations.
+///
+/// [spec]: https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+/// [`WasmCoreDump`]: crate::WasmCoreDump
+/// [`WasmCoreDump::serialize`]: crate::WasmCoreDump::serialize
+#[derive(Debug)]
+pub struct CoreDumpImage {
+    name: String,
+    modules: Vec<String>,
+    instances: Vec<CoreDumpInstance>,
+    memories: Vec<CoreDumpMemory>,
+    globals: Vec<CoreDumpGlobal>,
+    threads: Vec<CoreDumpThread>,
+}
+
+/// An instance recorded in a [`CoreDumpImage`].
+#[derive(Debug)]
+pub struct CoreDumpInstance {
+    module: u32,
+    memories: Vec<u32>,
+    globals: Vec<u32>,
+}
+
+/// A memory recorded in a [`CoreDumpImage`].
+#[derive(Debug)]
+pub struct CoreDumpMemory {
+    ty: MemoryType,
+    data: Vec<u8>,
+}
+
+/// A global recorded in a [`CoreDumpImage`].
+#[derive(Debug)]
+pub struct CoreDumpGlobal {
+    mutability: Mutability,
+    value: Val,
+}
+
+/// The stack of a thread recorded in a [`CoreDumpImage`].
+#[derive(Debug)]
+pub struct CoreDumpThread {
+    name: String,
+    frames: Vec<CoreDumpFrame>,
+}
+
+/// A stack frame recorded in a [`CoreDumpImage`].
+#[derive(Debug)]
+pub struct CoreDumpFrame {
+    instance: u32,
+    func: u32,
+    offset: u32,
+    locals: Vec<Option<Val>>,
+    stack: Vec<Option<Val>>,
+}
+
+impl CoreDumpImage {
+    /// Parses a core dump from its binary encoding.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is not a well-formed core dump, if one of
+    /// its memories is larger than 4 GiB, or if its memories can't be
+    /// allocated.
+    pub fn parse(bytes: &[u8]) -> Result<CoreDumpImage> {
+        let mut name = None;
+        let mut dump = CoreDumpImage {
+            name: String::new(),
+            modules: Vec::new(),
+            instances: Vec::new(),
+            memories: Vec::new(),
+            globals: Vec::new(),
+            threads: Vec::new(),
+        };
+
+        for payload in Parser::new(0).parse_all(bytes) {
+            match payload? {
+                Payload::MemorySection(reader) => {
+                    for ty in reader {
+                        let ty = ty?;
+                        let mut builder = MemoryTypeBuilder::new();
+                        builder
+                            .min(ty.initial)
+                            .max(ty.maximum)
+                            .memory64(ty.memory64)
+                            .shared(ty.shared);
+                        if let Some(log2) = ty.page_size_log2 {
+                            builder.page_size_log2(u8::try_from(log2)?);
+                        }
+                        let ty = builder.build()?;
+                        let len = ty
+                            .minimum()
+                            .checked_mul(ty.page_size())
+                            .filter(|len| *len <= MAX_MEMORY_SIZE)
+                            .and_then(|len| usize::try_from(len).ok())
+                            .context("core dump memory is too large")?;
+                        let mut data = Vec::new();
+                        data.try_reserve_exact(len)
+                            .context("failed to allocate core dump memory")?;
+                        data.resize(len, 0);
+                        dump.memories.push(CoreDumpMemory { ty, data });
+                    }
+                }
+                Payload::GlobalSection(reader) => {
+                    for global in reader {
+                        let global = global?;
+                        dump.globals.push(CoreDumpGlobal {
+                            mutability: if global.ty.mutable {
+                                Mutability::Var
+                            } else {
+                                Mutability::Const
+                            },
+                            value: const_expr_value(&global.init_expr)?,
+                        });
+                    }
+                }
+                Payload::DataSection(reader) => {
+                    for data in reader {
+                        let data = data?;
+                        let DataKind::Active {
+                            memory_index,
+                            offset_expr,
+                        } = data.kind
+                        else {
+                            bail!("core dumps may only contain active data segments");
+                        };
+                        let offset = match const_expr_value(&offset_expr)? {
+                            Val::I32(offset) => u64::from(offset as u32),
+                            Val::I64(offset) => offset as u64,
+                            _ => bail!("invalid data segment offset in core dump"),
+                        };
+                        let memory = usize::try_from(memory_index)
+                            .ok()
+                            .and_then(|i| dump.memories.get_mut(i))
+                            .context("data segment for an unknown memory in core dump")?;
+                        let range = usize::try_from(offset)
+                            .ok()
+                            .and_then(|start| Some(start..start.checked_add(data.data.len())?))
+                            .filter(|range| range.end <= memory.data.len())
+                            .context("out-of-bounds data segment in core dump")?;
+                        memory.data[range].copy_from_slice(data.data);
+                    }
+                }
+                Payload::CustomSection(reader) => {
+                    let section = BinaryReader::new(reader.data(), reader.data_offset());
+                    match reader.name() {
+                        "core" => {
+                            name = Some(CoreDumpSection::new(section)?.name.to_string());
+                        }
+                        "coremodules" => {
+                            let modules = CoreDumpModulesSection::new(section)?;
+                            dump.modules
+                                .extend(modules.modules.iter().map(|m| m.to_string()));
+                        }
+                        "coreinstances" => {
+                            let instances = CoreDumpInstancesSection::new(section)?;
+                            dump.instances
+                                .extend(instances.instances.into_iter().map(|i| {
+                                    CoreDumpInstance {
+                                        module: i.module_index,
+                                        memories: i.memories,
+                                        globals: i.globals,
+                                    }
+                                }));
+                        }
+                        "corestack" => {
+                            let stack = CoreDumpStackSection::new(section)?;
+                            dump.threads.push(CoreDumpThread {
+                                name: stack.name.to_string(),
+                                frames: stack
+                                    .frames
+                                    .into_iter()
+                                    .map(|frame| CoreDumpFrame {
+                                        instance: frame.instanceidx,
+                                        func: frame.funcidx,
+                                        offset: frame.codeoffset,
+                                        locals: frame.locals.into_iter().map(dump_value).collect(),
+                                        stack: frame.stack.into_iter().map(dump_value).collect(),
+                                    })
+                                    .collect(),
+                            });
+                        }
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        dump.name = name.context("not a core dump: missing the `core` custom section")?;
+
+        for instance in &dump.instances {
+            ensure!(
+                usize::try_from(instance.module).unwrap() < dump.modules.len(),
+                "core dump instance refers to unknown module {}",
+                instance.module
+            );
+        }
+        for frame in dump.threads.iter().flat_map(|t| &t.frames) {
+            ensure!(
+                usize::try_from(frame.instance).unwrap() < dump.instances.len(),
+                "core dump stack frame refers to unknown instance {}",
+                frame.instance
+            );
+        }
+
+        Ok(dump)
+    }
+
+    /// The name of the program that was running when the core dump was
+    /// created, as passed to [`WasmCoreDump::serialize`].
+    ///
+    /// [`WasmCoreDump::serialize`]: crate::WasmCoreDump::serialize
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The names of all modules instantiated when the core dump was created.
+    pub fn modules(&self) -> &[String] {
+        &self.modules
+    }
+
+    /// All instances within the store when the core dump was created.
+    pub fn instances(&self) -> &[CoreDumpInstance] {
+        &self.instances
+    }
+
+    /// All memories recorded in the core dump.
+    ///
+    /// Note that shared memories are not recorded in core dumps.
+    pub fn memories(&self) -> &[CoreDumpMemory] {
+        &self.memories
+    }
+
+    /// All globals recorded in the core dump.
+    ///
+    /// Note that reference-typed globals are always recorded as null.
+    pub fn globals(&self) -> &[CoreDumpGlobal] {
+        &self.globals
+    }
+
+    /// The stacks of all threads recorded in the core dump.
+    pub fn threads(&self) -> &[CoreDumpThread] {
+        &self.threads
+    }
+
+    /// Returns the information about `frame` that a live [`FrameInfo`] would
+    /// contain, including debug symbols when available.
+    ///
+    /// The `modules` must be the modules which were running when the core dump
+    /// was created, in the same order as [`CoreDumpImage::modules`]. DWARF
+    /// debug information is only available when they were compiled with
+    /// [`Config::wasm_backtrace_details`] enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `modules` doesn't match this core dump's modules,
+    /// or if `frame` refers to an instance or function which doesn't exist,
+    /// for example because it is from another core dump.
+    ///
+    /// [`Config::wasm_backtrace_details`]: crate::Config::wasm_backtrace_details
+    pub fn frame_info(&self, frame: &CoreDumpFrame, modules: &[Module]) -> Result<FrameInfo> {
+        ensure!(
+            modules.len() == self.modules.len(),
+            "core dump contains {} modules but {} were provided",
+            self.modules.len(),
+            modules.len()
+        );
+        let instance = usize::try_from(frame.instance)
+            .ok()
+            .and_then(|i| self.instances.get(i))
+            .with_context(|| format!("instance {} is not in the core dump", frame.instance))?;
+        let index = usize::try_from(instance.module).unwrap();
+        let (name, module) = (&self.modules[index], &modules[index]);
+        if let Some(actual) = module.name() {
+            ensure!(
+                actual == name,
+                "core dump module {index} is named `{name}` but `{actual}` was provided"
+            );
+        }
+
+        let env_module = module.env_module();
+        let func = FuncIndex::from_u32(frame.func);
+        let index = env_module
+            .defined_func_index(func)
+            .filter(|index| index.index() < env_module.num_defined_funcs())
+            .with_context(|| {
+                format!("function {} is not defined by module `{name}`", frame.func)
+            })?;
+
+        let func_start = module.compiled_module().func_start_srcloc(index);
+        let instr = func_start
+            .file_offset()
+            .and_then(|start| start.checked_add(frame.offset))
+            .map(FilePos::new);
+        Ok(FrameInfo::from_instr(module.clone(), index, instr))
+    }
+
+    /// Returns the backtrace of `thread`, symbolicated with the given modules.
+    ///
+    /// See [`CoreDumpImage::frame_info`] for the requirements on `modules`.
+    pub fn backtrace(&self, thread: &CoreDumpThread, modules: &[Module]) -> Result<WasmBacktrace> {
+        let frames = thread
+            .frames
+            .iter()
+            .map(|frame| self.frame_info(frame, modules))
+            .collect::<Result<_>>()?;
+        Ok(WasmBacktrace::from_frames(frames))
+    }
+}
+
+impl CoreDumpInstance {
+    /// The index of this instance's module within
+    /// [`CoreDumpImage::modules`].
+    pub fn module(&self) -> u32 {
+        self.module
+    }
+
+    /// The indices of this instance's memories within
+    /// [`CoreDumpImage::memories`].
+    ///
+    /// Memories which were not recorded in the core dump, such as shared
+    /// memories, have an index of `u32::MAX`.
+    pub fn memories(&self) -> &[u32] {
+        &self.memories
+    }
+
+    /// The indices of this instance's globals within
+    /// [`CoreDumpImage::globals`].
+    pub fn globals(&self) -> &[u32] {
+        &self.globals
+    }
+}
+
+impl CoreDumpMemory {
+    /// The type of this memory, whose minimum size is the memory's size when
+    /// the core dump was created.
+    pub fn ty(&self) -> &MemoryType {
+        &self.ty
+    }
+
+    /// The contents of this memory.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl CoreDumpGlobal {
+    /// Whether this global is mutable.
+    pub fn mutability(&self) -> Mutability {
+        self.mutability
+    }
+
+    /// The value of this global when the core dump was created.
+    pub fn value(&self) -> &Val {
+        &self.value
+    }
+}
+
+impl CoreDumpThread {
+    /// The name of this thread.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The stack frames of this thread.
+    ///
+    /// Frames appear in callee to caller order, that is youngest to oldest
+    /// frames.
+    pub fn frames(&self) -> &[CoreDumpFrame] {
+        &self.frames
+    }
+}
+
+impl CoreDumpFrame {
+    /// The index of the instance this frame is executing within
+    /// [`CoreDumpImage::instances`].
+    pub fn instance(&self) -> u32 {
+        self.instance
+    }
+
+    /// The index of the function this frame is executing in its module's
+    /// function index space.
+    pub fn func_index(&self) -> u32 {
+        self.func
+    }
+
+    /// The offset of this frame's program counter from the start of its
+    /// function in the original wasm module.
+    pub fn func_offset(&self) -> u32 {
+        self.offset
+    }
+
+    /// The values of this frame's locals.
+    ///
+    /// Locals whose values were not recovered when the core dump was created
+    /// are `None`.
+    pub fn locals(&self) -> &[Option<Val>] {
+        &self.locals
+    }
+
+    /// The values on this frame's operand stack, from bottom to top.
+    ///
+    /// Values which were not recovered when the core dump was created are
+    /// `None`.
+    pub fn stack(&self) -> &[Option<Val>] {
+        &self.stack
+    }
+}
+
+fn dump_value(value: CoreDumpValue) -> Option<Val> {
+    match value {
+        CoreDumpValue::Missing => None,
+        CoreDumpValue::I32(x) => Some(Val::I32(x)),
+        CoreDumpValue::I64(x) => Some(Val::I64(x)),
+        CoreDumpValue::F32(x) => Some(Val::F32(x.bits())),
+        CoreDumpValue::F64(x) => Some(Val::F64(x.bits())),
+    }
+}
+
+/// Evaluates one of the constant expressions that core dumps use to record
+/// values, which consist of a single constant instruction.
+fn const_expr_value(expr: &ConstExpr<'_>) -> Result<Val> {
+    let mut ops = expr.get_operators_reader();
+    let value = match ops.read()? {
+        Operator::I32Const { value } => Val::I32(value),
+        Operator::I64Const { value } => Val::I64(value),
+        Operator::F32Const { value } => Val::F32(value.bits()),
+        Operator::F64Const { value } => Val::F64(value.bits()),
+        Operator::V128Const { value } => Val::V128((value.i128() as u128).into()),
+        Operator::RefNull {
+            hty: HeapType::Abstract { ty, .. },
+        } => match ty {
+            AbstractHeapType::Func | AbstractHeapType::NoFunc => Val::FuncRef(None),
+            AbstractHeapType::Extern | AbstractHeapType::NoExtern => Val::ExternRef(None),
+            AbstractHeapType::Exn | AbstractHeapType::NoExn => Val::ExnRef(None),
+            AbstractHeapType::Cont | AbstractHeapType::NoCont => Val::ContRef(None),
+            _ => Val::AnyRef(None),
+        },
+        _ => bail!("unsupported constant expression in core dump"),
+    };
+    ensure!(
+        matches!(ops.read()?, Operator::End),
+        "unsupported constant expression in core dump"
+    );
+    Ok(value)
+}
@@ -1,23 +1,28 @@
 //! Debugging API.
 
 use crate::{
-    AnyRef, AsContext, AsContextMut, CodeMemory, ExnRef, ExternRef, Func, Instance, Module,
+    AnyRef, AsContext, AsContextMut, CodeMemory, ExnRef, ExternRef, Func, Instance, Memory, Module,
     OwnedRooted, StoreContext, StoreContextMut, Val,
     code::StoreCodePC,
     module::ModuleRegistry,
-    store::{AutoAssertNoGc, StoreOpaque},
-    vm::{CompiledModuleId, FrameOrHostCode, StoreBacktrace, VMContext},
+    store::{AutoAssertNoGc, StoreId, StoreOpaque},
+    vm::{Backtrace, CompiledModuleId, FrameOrHostCode, StoreBacktrace, VMContext, VMStore},
 };
 use alloc::collections::BTreeSet;
 use alloc::vec;
 use alloc::vec::Vec;
-use anyhow::Result;
-use core::{ffi::c_void, ptr::NonNull};
+use anyhow::{Result, bail};
+use core::{
+    ffi::c_void,
+    ops::{ControlFlow, Range},
+    ptr::NonNull,
+};
 #[cfg(feature = "gc")]
 use wasmtime_environ::FrameTable;
 use wasmtime_environ::{
-    DefinedFuncIndex, FrameInstPos, FrameStackShape, FrameStateSlot, FrameStateSlotOffset,
-    FrameTableBreakpointData, FrameTableDescriptorIndex, FrameValType, FuncKey, Trap,
+    DefinedFuncIndex, FrameControl, FrameInstPos, FrameMemoryAccess, FrameMemoryAccessKind,
+    FrameStackShape, FrameStateSlot, FrameStateSlotOffset, FrameTableBreakpointData,
+    FrameTableDescriptorIndex, FrameValType, FuncKey, Trap,
 };
 use wasmtime_unwinder::Frame;
 
@@ -60,8 +65,9 @@ impl<'a, T> StoreContextMut<'a, T> {
             return None;
         }
 
+        let store_id = self.0.id();
         let (breakpoints, registry) = self.0.breakpoints_and_registry_mut();
-        Some(breakpoints.edit(registry))
+        Some(breakpoints.edit(registry, store_id))
     }
 }
 
@@ -146,6 +152,7 @@ impl<'a, T: 'static> DebugFrameCursor<'a, T> {
         // this ever changes, we can remove the assert and convert
         // this to a loop that polls until it finds virtual frames.
         let mut result = FrameParentResult::SameActivation;
+        let mut innermost = false;
         self.current = None;
         while self.frames.is_empty() {
             let Some(next_frame) = self.iter.next() else {
@@ -164,10 +171,20 @@ impl<'a, T: 'static> DebugFrameCursor<'a, T> {
             };
             debug_assert!(!self.frames.is_empty());
             self.is_trapping_frame = false;
+            innermost = true;
         }
 
-        // Take a frame and focus it as the current one.
-        self.current = self.frames.pop().map(|vf| FrameData::compute(vf));
+        // Take a frame and focus it as the current one. Only the
+        // innermost virtual frame in the physical frame that hit a
+        // breakpoint reloads its state when resumed.
+        let paused_fp = self.iter.store().0.breakpoints_and_registry().0.paused_fp;
+        self.current = self.frames.pop().map(|vf| {
+            let paused = innermost && paused_fp == Some(vf.fp.addr());
+            FrameData {
+                paused,
+                ..FrameData::compute(vf)
+            }
+        });
         result
     }
 
@@ -181,18 +198,7 @@ impl<'a, T: 'static> DebugFrameCursor<'a, T> {
     }
 
     fn raw_instance(&self) -> &crate::vm::Instance {
-        // Read out the vmctx slot.
-
-        // SAFETY: vmctx is always at offset 0 in the slot.
-        // (See crates/cranelift/src/func_environ.rs in `update_stack_slot_vmctx()`.)
-        let vmctx: *mut VMContext = unsafe { *(self.frame_data().slot_addr as *mut _) };
-        let vmctx = NonNull::new(vmctx).expect("null vmctx in debug state slot");
-        // SAFETY: the stored vmctx value is a valid instance in this
-        // store; we only visit frames from this store in the
-        // backtrace.
-        let instance = unsafe { crate::vm::Instance::from_vmctx(vmctx) };
-        // SAFETY: the instance pointer read above is valid.
-        unsafe { instance.as_ref() }
+        self.frame_data().raw_instance()
     }
 
     /// Get the instance associated with the current frame.
@@ -268,6 +274,103 @@ impl<'a, T: 'static> DebugFrameCursor<'a, T> {
         // into it.
         unsafe { read_value(&mut self.iter.store_mut().0, slot_addr, offset, ty) }
     }
+
+    /// Can this frame's state be modified with `set_local`,
+    /// `set_stack` and `force_return`?
+    ///
+    /// This is the case only for the innermost frame while it is
+    /// paused at a breakpoint (including a watchpoint): generated code
+    /// picks up changes right after a breakpoint, but not when
+    /// returning from a call or from any other debug event.
+    pub fn is_modifiable(&self) -> bool {
+        self.frame_data().paused
+    }
+
+    /// Set the value of the given local in this frame.
+    ///
+    /// The new value takes effect when execution resumes. Returns an
+    /// error if the frame is not modifiable (see
+    /// [`DebugFrameCursor::is_modifiable`]), if `val` doesn't have the
+    /// local's type, or if the local has a reference type, as
+    /// modifying references is not supported.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out-of-range (greater than
+    /// `num_locals()`).
+    pub fn set_local(&mut self, index: u32, val: Val) -> Result<()> {
+        let data = self.frame_data();
+        let (offset, ty) = data.locals[usize::try_from(index).unwrap()];
+        self.set_value(offset, ty, val)
+    }
+
+    /// Set the value of the given operand-stack value in this frame.
+    ///
+    /// Indices are as for [`DebugFrameCursor::stack`], and errors are
+    /// as for [`DebugFrameCursor::set_local`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is out-of-range (greater than
+    /// `num_stacks()`).
+    pub fn set_stack(&mut self, index: u32, val: Val) -> Result<()> {
+        let data = self.frame_data();
+        let (offset, ty) = data.stack[usize::try_from(index).unwrap()];
+        self.set_value(offset, ty, val)
+    }
+
+    /// Force this frame to return to its caller with the given
+    /// results as soon as execution resumes, without executing any
+    /// more of its code.
+    ///
+    /// Returns an error if the frame is not modifiable (see
+    /// [`DebugFrameCursor::is_modifiable`]), or if `results` doesn't
+    /// match the function's result types. Functions with
+    /// reference-typed results can't be forced to return.
+    pub fn force_return(&mut self, results: &[Val]) -> Result<()> {
+        let data = self.frame_data();
+        if !data.paused {
+            bail!("only a frame paused at a breakpoint can be forced to return");
+        }
+        if results.len() != data.results.len() {
+            bail!(
+                "expected {} results to return, got {}",
+                data.results.len(),
+                results.len()
+            );
+        }
+        for (&(_, ty), val) in data.results.iter().zip(results) {
+            check_writable_value(ty, val)?;
+        }
+        for (&(offset, ty), val) in data.results.iter().zip(results) {
+            // SAFETY: the frame table describes this result slot and
+            // the value was type-checked above.
+            unsafe { write_value(data.slot_addr, offset, ty, val) };
+        }
+        data.set_control(FrameControl::Return);
+        Ok(())
+    }
+
+    fn set_value(
+        &mut self,
+        offset: FrameStateSlotOffset,
+        ty: FrameValType,
+        val: Val,
+    ) -> Result<()> {
+        let data = self.frame_data();
+        if !data.paused {
+            bail!("only a frame paused at a breakpoint can be modified");
+        }
+        check_writable_value(ty, &val)?;
+        // SAFETY: the frame table describes this slot and the value
+        // was type-checked above.
+        unsafe { write_value(data.slot_addr, offset, ty, &val) };
+        // A pending forced return takes precedence over reloading.
+        if data.control() != FrameControl::Return {
+            data.set_control(FrameControl::Reload);
+        }
+        Ok(())
+    }
 }
 
 /// Internal data pre-computed for one stack frame.
@@ -343,6 +446,12 @@ struct FrameData {
     /// stack by depth, and the frame slot descriptor stores info in a
     /// linked-list (actually DAG, with dedup'ing) way.
     stack: Vec<(FrameStateSlotOffset, FrameValType)>,
+    /// Storage for the function's results, written when forcing an
+    /// early return.
+    results: Vec<(FrameStateSlotOffset, FrameValType)>,
+    /// Whether this frame is paused at a breakpoint, so that changes
+    /// to its state take effect when it resumes.
+    paused: bool,
 }
 
 impl FrameData {
@@ -368,6 +477,7 @@ impl FrameData {
         // Materialize the local offsets/types so we don't need to
         // keep the borrow to the module alive.
         let locals = frame_state_slot.locals().collect::<Vec<_>>();
+        let results = frame_state_slot.results().collect::<Vec<_>>();
 
         FrameData {
             slot_addr,
@@ -375,8 +485,53 @@ impl FrameData {
             wasm_pc: frame.wasm_pc,
             stack,
             locals,
+            results,
+            paused: false,
         }
     }
+
+    /// Get the raw instance whose code is executing in this frame.
+    fn raw_instance(&self) -> &crate::vm::Instance {
+        // Read out the vmctx slot.
+
+        // SAFETY: vmctx is always at offset 0 in the slot.
+        // (See crates/cranelift/src/func_environ.rs in `update_stack_slot_vmctx()`.)
+        let vmctx: *mut VMContext = unsafe { *(self.slot_addr as *mut _) };
+        let vmctx = NonNull::new(vmctx).expect("null vmctx in debug state slot");
+        // SAFETY: the stored vmctx value is a valid instance in this
+        // store; we only visit frames from this store in the
+        // backtrace.
+        let instance = unsafe { crate::vm::Instance::from_vmctx(vmctx) };
+        // SAFETY: the instance pointer read above is valid.
+        unsafe { instance.as_ref() }
+    }
+
+    fn control_word(&self) -> *mut u32 {
+        let pointer_size = u32::try_from(core::mem::size_of::<usize>()).unwrap();
+        let offset = FrameStateSlot::control_offset(pointer_size).offset();
+        self.slot_addr
+            .wrapping_offset(isize::try_from(offset).unwrap())
+            .cast::<u32>()
+            .cast_mut()
+    }
+
+    /// Read the control word of this frame's state slot.
+    fn control(&self) -> FrameControl {
+        // SAFETY: the control word is always present in the slot
+        // following the vmctx pointer.
+        match unsafe { self.control_word().read_unaligned() } {
+            1 => FrameControl::Reload,
+            2 => FrameControl::Return,
+            _ => FrameControl::None,
+        }
+    }
+
+    /// Request that this frame act on `control` when it resumes.
+    fn set_control(&self, control: FrameControl) {
+        // SAFETY: as above; generated code reads the control word
+        // right after the breakpoint this frame is paused at.
+        unsafe { self.control_word().write_unaligned(control as u32) }
+    }
 }
 
 /// Read the value at the given offset.
@@ -449,6 +604,56 @@ unsafe fn read_value(
     }
 }
 
+/// Check that `val` can be written to a frame slot of type `ty`.
+fn check_writable_value(ty: FrameValType, val: &Val) -> Result<()> {
+    match (ty, val) {
+        (FrameValType::I32, Val::I32(_))
+        | (FrameValType::I64, Val::I64(_))
+        | (FrameValType::F32, Val::F32(_))
+        | (FrameValType::F64, Val::F64(_))
+        | (FrameValType::V128, Val::V128(_)) => Ok(()),
+        (
+            FrameValType::AnyRef
+            | FrameValType::FuncRef
+            | FrameValType::ExternRef
+            | FrameValType::ExnRef
+            | FrameValType::ContRef,
+            _,
+        ) => bail!("modifying reference-typed values in a frame is not supported"),
+        _ => bail!("type mismatch: expected a value of type {ty:?}, got {val:?}"),
+    }
+}
+
+/// Write the value at the given offset.
+///
+/// # Safety
+///
+/// The `offset` and `ty` must correspond to a valid slot in the frame
+/// as described by the frame tables, and `val` must have passed
+/// `check_writable_value` for `ty`.
+unsafe fn write_value(
+    slot_base: *const u8,
+    offset: FrameStateSlotOffset,
+    ty: FrameValType,
+    val: &Val,
+) {
+    let address = unsafe { slot_base.offset(isize::try_from(offset.offset()).unwrap()) };
+    let address = address.cast_mut();
+
+    // SAFETY: each case writes a value of the slot's type, per our
+    // safety condition.
+    unsafe {
+        match *val {
+            Val::I32(x) => address.cast::<i32>().write_unaligned(x),
+            Val::I64(x) => address.cast::<i64>().write_unaligned(x),
+            Val::F32(x) => address.cast::<u32>().write_unaligned(x),
+            Val::F64(x) => address.cast::<u64>().write_unaligned(x),
+            Val::V128(x) => address.cast::<u128>().write_unaligned(x.as_u128()),
+            _ => unreachable!("cannot write value of type {ty:?} to a frame"),
+        }
+    }
+}
+
 /// Compute raw pointers to all GC refs in the given frame.
 // Note: ideally this would be an impl Iterator, but this is quite
 // awkward because of the locally computed data (FrameStateSlot::parse
@@ -512,10 +717,60 @@ pub enum DebugEvent<'a> {
     Trap(Trap),
     /// A breakpoint was reached.
     Breakpoint,
+    /// A load or store is about to access a watched range of linear
+    /// memory. The current state is just before the access executes.
+    Watchpoint(WatchpointEvent),
     /// An epoch yield occurred.
     EpochYield,
 }
 
+/// Details of a memory access that triggered a watchpoint.
+#[derive(Debug)]
+pub struct WatchpointEvent {
+    /// The module containing the accessing instruction.
+    pub module: Module,
+    /// Wasm PC offset of the accessing instruction within the module.
+    pub pc: u32,
+    /// The memory being accessed.
+    pub memory: Memory,
+    /// The effective address of the access, including its static
+    /// offset.
+    pub address: u64,
+    /// Whether the access is a read or a write; never
+    /// `WatchpointKind::ReadWrite`.
+    pub kind: WatchpointKind,
+    /// The bytes in memory at the accessed range before the access.
+    ///
+    /// This is empty if the access is out of bounds and is about to
+    /// trap.
+    pub old_value: Vec<u8>,
+    /// For writes, the bytes about to be stored; `None` for reads.
+    pub new_value: Option<Vec<u8>>,
+}
+
+/// Which accesses to a watched range raise a
+/// [`DebugEvent::Watchpoint`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchpointKind {
+    /// Loads from the range.
+    Read,
+    /// Stores to the range.
+    Write,
+    /// Both loads from and stores to the range.
+    ReadWrite,
+}
+
+impl WatchpointKind {
+    fn matches(self, access: FrameMemoryAccessKind) -> bool {
+        match (self, access) {
+            (WatchpointKind::ReadWrite, _)
+            | (WatchpointKind::Read, FrameMemoryAccessKind::Load)
+            | (WatchpointKind::Write, FrameMemoryAccessKind::Store) => true,
+            _ => false,
+        }
+    }
+}
+
 /// A handler for debug events.
 ///
 /// This is an async callback that is invoked directly within the
@@ -580,6 +835,23 @@ pub(crate) struct BreakpointState {
     single_step: bool,
     /// Breakpoints added individually.
     breakpoints: BTreeSet<BreakpointKey>,
+    /// Watched ranges of linear memory.
+    ///
+    /// While this is non-empty, the breakpoint sites of every load
+    /// and store in every registered module are enabled, and the
+    /// breakpoint libcall filters hits down to those that touch one
+    /// of these ranges.
+    watchpoints: Vec<Watchpoint>,
+    /// The frame pointer of the physical frame paused at a
+    /// breakpoint while the debug handler runs, if any.
+    paused_fp: Option<usize>,
+}
+
+#[derive(Clone, Debug)]
+struct Watchpoint {
+    memory: Memory,
+    range: Range<u64>,
+    kind: WatchpointKind,
 }
 
 /// A breakpoint.
@@ -618,6 +890,8 @@ impl BreakpointKey {
 pub struct BreakpointEdit<'a> {
     state: &'a mut BreakpointState,
     registry: &'a mut ModuleRegistry,
+    /// The store whose breakpoints are being edited.
+    store_id: StoreId,
     /// Modules that have been edited.
     ///
     /// Invariant: each of these modules' CodeMemory objects is
@@ -626,10 +900,15 @@ pub struct BreakpointEdit<'a> {
 }
 
 impl BreakpointState {
-    pub(crate) fn edit<'a>(&'a mut self, registry: &'a mut ModuleRegistry) -> BreakpointEdit<'a> {
+    pub(crate) fn edit<'a>(
+        &'a mut self,
+        registry: &'a mut ModuleRegistry,
+        store_id: StoreId,
+    ) -> BreakpointEdit<'a> {
         BreakpointEdit {
             state: self,
             registry,
+            store_id,
             dirty_modules: BTreeSet::new(),
         }
     }
@@ -644,6 +923,26 @@ impl BreakpointState {
     pub(crate) fn is_single_step(&self) -> bool {
         self.single_step
     }
+
+    /// Whether a hit of the breakpoint site at `pc` in `module` is a
+    /// breakpoint event in its own right, rather than only a
+    /// potential watchpoint hit.
+    fn site_hit(&self, module: &Module, pc: u32) -> bool {
+        self.single_step
+            || self
+                .breakpoints
+                .contains(&BreakpointKey::from_raw(module, pc))
+    }
+
+    /// Whether the breakpoint site at `pc` in `module` must be
+    /// enabled to satisfy the current state.
+    fn site_enabled(&self, module: &Module, pc: u32) -> bool {
+        self.site_hit(module, pc)
+            || (!self.watchpoints.is_empty()
+                && module
+                    .frame_table()
+                    .is_some_and(|table| table.lookup_memory_access(pc).is_some()))
+    }
 }
 
 impl<'a> BreakpointEdit<'a> {
@@ -705,7 +1004,7 @@ impl<'a> BreakpointEdit<'a> {
     pub fn remove_breakpoint(&mut self, module: &Module, pc: u32) -> Result<()> {
         let key = BreakpointKey::from_raw(module, pc);
         self.state.breakpoints.remove(&key);
-        if !self.state.single_step {
+        if !self.state.site_enabled(module, pc) {
             let mem = Self::get_code_memory(self.registry, &mut self.dirty_modules, module)?;
             let frame_table = module
                 .frame_table()
@@ -733,7 +1032,7 @@ impl<'a> BreakpointEdit<'a> {
                 .expect("Frame table must be present when guest-debug is enabled");
             for (wasm_pc, patch) in table.breakpoint_patches() {
                 let key = BreakpointKey::from_raw(&module, wasm_pc);
-                let this_enabled = enabled || self.state.breakpoints.contains(&key);
+                let this_enabled = enabled || self.state.site_enabled(&module, wasm_pc);
                 log::trace!(
                     "single_step: enabled {enabled} key {key:?} -> this_enabled {this_enabled}"
                 );
@@ -745,6 +1044,92 @@ impl<'a> BreakpointEdit<'a> {
 
         Ok(())
     }
+
+    /// Add a watchpoint on the byte range `range` of `memory`.
+    ///
+    /// A [`DebugEvent::Watchpoint`] is raised just before any load
+    /// (for [`WatchpointKind::Read`]) or store (for
+    /// [`WatchpointKind::Write`]) that accesses at least one byte of
+    /// the range. Bulk-memory operations such as `memory.copy` and
+    /// `memory.fill`, and atomic read-modify-write operations, do not
+    /// raise watchpoint events.
+    ///
+    /// Watching memory enables instrumentation on every load and
+    /// store in all modules instantiated in this store, so execution
+    /// is substantially slower until the last watchpoint is removed.
+    ///
+    /// Returns an error if `range` is empty, or if `memory` does not
+    /// belong to this store.
+    pub fn add_watchpoint(
+        &mut self,
+        memory: &Memory,
+        range: Range<u64>,
+        kind: WatchpointKind,
+    ) -> Result<()> {
+        if range.is_empty() {
+            bail!("cannot watch an empty range of memory");
+        }
+        if !memory.comes_from_store(self.store_id) {
+            bail!("memory does not belong to this store");
+        }
+        log::trace!("adding watchpoint on {range:?} ({kind:?})");
+        let was_empty = self.state.watchpoints.is_empty();
+        self.state.watchpoints.push(Watchpoint {
+            memory: *memory,
+            range,
+            kind,
+        });
+        if was_empty {
+            self.patch_memory_accesses(true)?;
+        }
+        Ok(())
+    }
+
+    /// Remove a watchpoint previously added with the same `memory`,
+    /// `range`, and `kind`.
+    ///
+    /// No effect if no such watchpoint was set.
+    pub fn remove_watchpoint(
+        &mut self,
+        memory: &Memory,
+        range: Range<u64>,
+        kind: WatchpointKind,
+    ) -> Result<()> {
+        let Some(i) = self
+            .state
+            .watchpoints
+            .iter()
+            .position(|w| w.memory.same(memory) && w.range == range && w.kind == kind)
+        else {
+            return Ok(());
+        };
+        self.state.watchpoints.remove(i);
+        if self.state.watchpoints.is_empty() {
+            self.patch_memory_accesses(false)?;
+        }
+        Ok(())
+    }
+
+    /// Enable, or disable where nothing else needs them, the
+    /// breakpoint sites at every memory access in every module.
+    fn patch_memory_accesses(&mut self, enable: bool) -> Result<()> {
+        let modules = self.registry.all_modules().cloned().collect::<Vec<_>>();
+        for module in modules {
+            let table = module
+                .frame_table()
+                .expect("Frame table must be present when guest-debug is enabled");
+            if table.memory_accesses().next().is_none() {
+                continue;
+            }
+            let mem = Self::get_code_memory(self.registry, &mut self.dirty_modules, &module)?;
+            for (wasm_pc, _) in table.memory_accesses() {
+                let this_enabled = enable || self.state.site_enabled(&module, wasm_pc);
+                let patches = table.lookup_breakpoint_patches_by_pc(wasm_pc);
+                Self::patch(patches, mem, this_enabled);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<'a> Drop for BreakpointEdit<'a> {
@@ -762,6 +1147,146 @@ impl<'a> Drop for BreakpointEdit<'a> {
     }
 }
 
+/// Handle a hit of an enabled breakpoint site in the innermost Wasm
+/// frame on the stack, invoking the debug handler if the hit
+/// corresponds to a debug event.
+pub(crate) fn handle_breakpoint(store: &mut dyn VMStore) -> Result<()> {
+    let mut innermost = None;
+    Backtrace::trace(store.store_opaque(), |frame| {
+        innermost = Some(frame);
+        ControlFlow::Break(())
+    });
+    let frame = innermost.expect("breakpoint must be hit from Wasm code");
+    let fp = frame.fp();
+    let Some(event) = breakpoint_event(store.store_opaque_mut(), frame) else {
+        return Ok(());
+    };
+
+    // The frame that hit the breakpoint picks up any modifications to
+    // its state once the handler returns, so allow them while the
+    // handler runs.
+    let state = &mut store.store_opaque_mut().breakpoints_and_registry_mut().0;
+    let outer_paused_fp = core::mem::replace(&mut state.paused_fp, Some(fp));
+    let result = store.block_on_debug_handler(event);
+    store
+        .store_opaque_mut()
+        .breakpoints_and_registry_mut()
+        .0
+        .paused_fp = outer_paused_fp;
+    result
+}
+
+/// Compute the debug event, if any, for a hit of an enabled
+/// breakpoint site in the innermost Wasm frame on the stack.
+///
+/// Breakpoint sites are enabled for user breakpoints, for
+/// single-stepping, and at every memory access while watchpoints are
+/// set, so a hit doesn't always correspond to an event.
+fn breakpoint_event(store: &mut StoreOpaque, frame: Frame) -> Option<DebugEvent<'static>> {
+    if store.breakpoints_and_registry().0.watchpoints.is_empty() {
+        return Some(DebugEvent::Breakpoint);
+    }
+
+    let frame = VirtualFrame::decode(store, frame, false)
+        .pop()
+        .expect("each physical frame decodes to at least one virtual frame");
+    let module = frame.module.clone();
+    let pc = frame.wasm_pc;
+    let access = module.frame_table().unwrap().lookup_memory_access(pc);
+    let data = FrameData::compute(frame);
+
+    if let Some(access) = access
+        && let Some(event) = watchpoint_event(store, &data, &module, pc, access)
+    {
+        return Some(DebugEvent::Watchpoint(event));
+    }
+    if store.breakpoints_and_registry().0.site_hit(&module, pc) {
+        return Some(DebugEvent::Breakpoint);
+    }
+    None
+}
+
+/// Check whether the memory access about to be performed in the
+/// frame described by `data` touches a watched range.
+fn watchpoint_event(
+    store: &mut StoreOpaque,
+    data: &FrameData,
+    module: &Module,
+    pc: u32,
+    access: FrameMemoryAccess,
+) -> Option<WatchpointEvent> {
+    // Loads have their address on top of the operand stack; stores
+    // have the value to store on top, with the address below it.
+    let depth = match access.kind {
+        FrameMemoryAccessKind::Load => 1,
+        FrameMemoryAccessKind::Store => 2,
+    };
+    let read_stack = |store: &mut StoreOpaque, depth: usize| {
+        let (offset, ty) = data.stack[data.stack.len() - depth];
+        // SAFETY: the frame table describes this operand-stack slot
+        // and generated code stored a value of this type into it.
+        unsafe { read_value(store, data.slot_addr, offset, ty) }
+    };
+    let address = match read_stack(store, depth) {
+        Val::I32(a) => u64::from(a as u32),
+        Val::I64(a) => a as u64,
+        v => unreachable!("memory address operand of unexpected type: {v:?}"),
+    };
+    // An overflowing address is going to trap before touching memory.
+    let address = address.checked_add(access.offset)?;
+    let end = address.checked_add(u64::from(access.size))?;
+
+    let memory = data
+        .raw_instance()
+        .get_exported_memory(store.id(), access.memory)
+        .unshared()?;
+    let (state, _) = store.breakpoints_and_registry();
+    let kind = match access.kind {
+        FrameMemoryAccessKind::Load => WatchpointKind::Read,
+        FrameMemoryAccessKind::Store => WatchpointKind::Write,
+    };
+    let hit = state.watchpoints.iter().any(|w| {
+        w.memory.same(&memory)
+            && w.kind.matches(access.kind)
+            && w.range.start < end
+            && address < w.range.end
+    });
+    if !hit {
+        return None;
+    }
+
+    let old_value = usize::try_from(address)
+        .ok()
+        .zip(usize::try_from(end).ok())
+        .and_then(|(start, end)| memory.internal_data(store).get(start..end))
+        .map(|bytes| bytes.to_vec())
+        .unwrap_or_default();
+    let new_value = match access.kind {
+        FrameMemoryAccessKind::Load => None,
+        FrameMemoryAccessKind::Store => {
+            let bytes = match read_stack(store, 1) {
+                Val::I32(x) => u128::from(x as u32),
+                Val::I64(x) => u128::from(x as u64),
+                Val::F32(x) => u128::from(x),
+                Val::F64(x) => u128::from(x),
+                Val::V128(x) => x.as_u128(),
+                v => unreachable!("stored value of unexpected type: {v:?}"),
+            };
+            Some(bytes.to_le_bytes()[..usize::from(access.size)].to_vec())
+        }
+    };
+
+    Some(WatchpointEvent {
+        module: module.clone(),
+        pc,
+        memory,
+        address,
+        kind,
+        old_value,
+        new_value,
+    })
+}
+
 /// Abort when we cannot re-publish executable code.
 ///
 /// Note that this puts us in quite a conundrum. Typically we will
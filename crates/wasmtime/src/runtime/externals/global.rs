@@ -396,7 +396,7 @@ impl Global {
         self.definition(store).as_ptr().addr()
     }
 
-    fn definition(&self, store: &StoreOpaque) -> NonNull<VMGlobalDefinition> {
+    pub(crate) fn definition(&self, store: &StoreOpaque) -> NonNull<VMGlobalDefinition> {
         self.store.assert_belongs_to(store.id());
         match self.kind {
             VMGlobalKind::Instance(index) => {
@@ -1495,6 +1495,10 @@ pub(crate) fn invoke_wasm_and_catch_traps<T>(
     store: &mut StoreContextMut<'_, T>,
     closure: impl FnMut(NonNull<VMContext>, Option<InterpreterRef<'_>>) -> bool,
 ) -> Result<()> {
+    // Parked memories and tables are moved back into place before any
+    // WebAssembly can observe them.
+    store.0.unpark()?;
+
     // The `enter_wasm` call below will reset the store context's
     // `stack_chain` to a new `InitialStack`, pointing to the
     // stack-allocated `initial_stack_csi`.
@@ -2349,11 +2353,35 @@ impl HostContext {
             };
             let func = &state.func;
 
+            #[cfg(feature = "rr")]
+            let mut recorder = None;
             let ret = 'ret: {
                 if let Err(trap) = caller.store.0.call_hook(CallHook::CallingHost) {
                     break 'ret R::fallible_from_error(trap);
                 }
 
+                #[cfg(feature = "rr")]
+                if let Some(rr) = caller.store.0.record_replay().cloned() {
+                    let ty = FuncType::from_registered_type(state._ty.clone());
+                    match rr.enter_host_call(caller.store.0, &ty) {
+                        Ok(crate::rr::HostCall::Record(r)) => recorder = Some((r, ty)),
+                        Ok(crate::rr::HostCall::Nested) => {}
+                        Ok(crate::rr::HostCall::Replay) => {
+                            // SAFETY: `args` is large enough to hold the
+                            // results, which are entirely overwritten here.
+                            let results = unsafe {
+                                core::slice::from_raw_parts_mut(
+                                    args.cast::<ValRaw>().as_ptr(),
+                                    ty.results().len(),
+                                )
+                            };
+                            rr.replay_host_call(caller.store.0, &ty, results)?;
+                            return caller.store.0.call_hook(CallHook::ReturningFromHost);
+                        }
+                        Err(e) => break 'ret R::fallible_from_error(e),
+                    }
+                }
+
                 let mut store = if P::may_gc() {
                     AutoAssertNoGc::new(caller.store.0)
                 } else {
@@ -2374,8 +2402,10 @@ impl HostContext {
                 r.into_fallible()
             };
 
-            if !ret.compatible_with_store(caller.store.0) {
-                bail!("host function attempted to return cross-`Store` value to Wasm")
+            let result = if !ret.compatible_with_store(caller.store.0) {
+                Err(anyhow!(
+                    "host function attempted to return cross-`Store` value to Wasm"
+                ))
             } else {
                 let mut store = if R::may_gc() {
                     AutoAssertNoGc::new(caller.store.0)
@@ -2385,9 +2415,23 @@ impl HostContext {
                 // SAFETY: this function requires that `args` is safe for this
                 // type signature, and the guarantees of `WasmRet` means that
                 // everything should be typed appropriately.
-                let ret = unsafe { ret.store(&mut store, args.as_mut())? };
-                Ok(ret)
+                unsafe { ret.store(&mut store, args.as_mut()) }
+            };
+            #[cfg(feature = "rr")]
+            if let Some((recorder, ty)) = recorder {
+                let results = result.as_ref().map(|()| {
+                    // SAFETY: the results were all initialized by `store`
+                    // above, and `args` is large enough to hold them.
+                    unsafe {
+                        core::slice::from_raw_parts(
+                            args.cast::<ValRaw>().as_ptr(),
+                            ty.results().len(),
+                        )
+                    }
+                });
+                recorder.exit(caller.store.0, &ty, results)?;
             }
+            result
         };
 
         // With nothing else on the stack move `run` into this
@@ -2474,6 +2518,8 @@ impl HostFunc {
         T: 'static,
     {
         assert!(ty.comes_from_same_engine(engine));
+        #[cfg(feature = "rr")]
+        let rr_ty = ty.clone();
         let ctx = crate::trampoline::create_array_call_function(
             &ty,
             move |store, instance, values: &mut [ValRaw]| {
@@ -2484,9 +2530,27 @@ impl HostFunc {
                 let store = unsafe { store.unchecked_context_mut::<T>() };
                 Caller::with(store, instance, |mut caller| {
                     caller.store.0.call_hook(CallHook::CallingHost)?;
-                    let result = func(caller.sub_caller(), values)?;
+                    #[cfg(feature = "rr")]
+                    let recorder = match caller.store.0.record_replay().cloned() {
+                        Some(rr) => match rr.enter_host_call(caller.store.0, &rr_ty)? {
+                            crate::rr::HostCall::Record(recorder) => Some(recorder),
+                            crate::rr::HostCall::Nested => None,
+                            crate::rr::HostCall::Replay => {
+                                rr.replay_host_call(caller.store.0, &rr_ty, values)?;
+                                return caller.store.0.call_hook(CallHook::ReturningFromHost);
+                            }
+                        },
+                        None => None,
+                    };
+                    let result = func(caller.sub_caller(), values);
+                    #[cfg(feature = "rr")]
+                    if let Some(recorder) = recorder {
+                        let results = result.as_ref().map(|()| &*values);
+                        recorder.exit(caller.store.0, &rr_ty, results)?;
+                    }
+                    result?;
                     caller.store.0.call_hook(CallHook::ReturningFromHost)?;
-                    Ok(result)
+                    Ok(())
                 })
             },
         )
@@ -123,3 +123,20 @@ impl<T> GcHeapOutOfMemory<T> {
         (self.inner, GcHeapOutOfMemory::new((), self.bytes_needed))
     }
 }
+
+/// Statistics about the garbage collections performed within a
+/// [`Store`][crate::Store].
+///
+/// Returned by [`Store::gc_stats`][crate::Store::gc_stats].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct GcStats {
+    /// The number of garbage collections performed so far.
+    pub collections: u64,
+
+    /// The total number of bytes reclaimed by all collections so far.
+    pub bytes_reclaimed: u64,
+
+    /// The number of bytes reclaimed by the most recent collection.
+    pub last_bytes_reclaimed: u64,
+}
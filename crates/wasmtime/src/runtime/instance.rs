@@ -351,6 +351,14 @@ impl Instance {
         // items from this instance into other instances should be ok when
         // those items are loaded and run we'll have all the metadata to
         // look at them.
+        //
+        // Instances recreated by `StoreSnapshot::fork` skip all of this, as
+        // well as their start function, since the snapshot is about to be
+        // restored over them.
+        if store.forking() {
+            return Ok((instance, None));
+        }
+
         let bulk_memory = store
             .engine()
             .features()
@@ -595,6 +603,53 @@ impl Instance {
         self.get_export(store, name)?.into_tag()
     }
 
+    /// Looks up the memory at `index` in this instance's memory index space,
+    /// whether or not it is exported.
+    ///
+    /// This is intended for debuggers, which need to see all of a guest's
+    /// state. Returns `None` if guest debugging is not enabled for the engine
+    /// containing `store`, if `index` is out of bounds, or if the memory is
+    /// shared.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `store` does not own this instance.
+    #[cfg(feature = "debug")]
+    pub fn debug_memory(&self, mut store: impl AsContextMut, index: u32) -> Option<Memory> {
+        let store = store.as_context_mut().0;
+        if !store.engine().tunables().debug_guest {
+            return None;
+        }
+        let store_id = store.id();
+        let instance = &store[self.id];
+        let index = MemoryIndex::from_u32(index);
+        instance.env_module().memories.get(index)?;
+        instance.get_exported_memory(store_id, index).unshared()
+    }
+
+    /// Looks up the global at `index` in this instance's global index space,
+    /// whether or not it is exported.
+    ///
+    /// This is intended for debuggers, which need to see all of a guest's
+    /// state. Returns `None` if guest debugging is not enabled for the engine
+    /// containing `store`, or if `index` is out of bounds.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `store` does not own this instance.
+    #[cfg(feature = "debug")]
+    pub fn debug_global(&self, mut store: impl AsContextMut, index: u32) -> Option<Global> {
+        let store = store.as_context_mut().0;
+        if !store.engine().tunables().debug_guest {
+            return None;
+        }
+        let store_id = store.id();
+        let instance = &store[self.id];
+        let index = GlobalIndex::from_u32(index);
+        instance.env_module().globals.get(index)?;
+        Some(instance.get_exported_global(store_id, index))
+    }
+
     #[allow(
         dead_code,
         reason = "c-api crate does not yet support exnrefs and causes this method to be dead."
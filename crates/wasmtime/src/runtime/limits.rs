@@ -1,5 +1,9 @@
 use crate::prelude::*;
 
+mod budget;
+pub(crate) use self::budget::MemoryCharge;
+pub use self::budget::{MemoryBudget, MemoryBudgetBuilder, MemoryPressure};
+
 /// Value returned by [`ResourceLimiter::instances`] default method
 pub const DEFAULT_INSTANCE_LIMIT: usize = 10000;
 /// Value returned by [`ResourceLimiter::tables`] default method
@@ -90,6 +94,22 @@ pub trait ResourceLimiter: Send {
         Ok(())
     }
 
+    /// The budget, if any, that linear memories created in the store are
+    /// charged against.
+    ///
+    /// This is queried whenever a memory is created. The memory's size is then
+    /// charged against the returned budget, and so is each growth of the
+    /// memory after `memory_growing` has permitted it. Creating a memory fails
+    /// if the budget doesn't have room for it, and growing a memory fails,
+    /// invoking `memory_grow_failed`, if the budget doesn't have room for the
+    /// growth. A memory gives its size back to the budget when it's dropped.
+    ///
+    /// See [`MemoryBudget`] for more information. By default memories aren't
+    /// charged against any budget.
+    fn memory_budget(&self) -> Option<&MemoryBudget> {
+        None
+    }
+
     /// Notifies the resource limiter that an instance's table has been
     /// requested to grow.
     ///
@@ -187,6 +207,11 @@ pub trait ResourceLimiterAsync: Send {
         Ok(())
     }
 
+    /// Identical to [`ResourceLimiter::memory_budget`]
+    fn memory_budget(&self) -> Option<&MemoryBudget> {
+        None
+    }
+
     /// Asynchronous version of [`ResourceLimiter::table_growing`]
     async fn table_growing(
         &mut self,
@@ -242,6 +267,34 @@ impl StoreLimitsBuilder {
         self
     }
 
+    /// A soft limit on the total number of bytes of this store's linear
+    /// memories.
+    ///
+    /// Growing memories beyond this limit succeeds, subject to the other
+    /// limits configured here, but afterwards
+    /// [`StoreLimits::memory_pressure`] returns `true` to let the embedder
+    /// know that the store is using more memory than it should.
+    ///
+    /// By default there is no soft limit.
+    pub fn memory_soft_limit(mut self, limit: usize) -> Self {
+        self.0.memory_soft_limit = Some(limit);
+        self
+    }
+
+    /// Charges the memories of this store against `budget`, which may be
+    /// shared with other stores to limit their total memory usage.
+    ///
+    /// Creating or growing a memory fails if it would exceed the budget's
+    /// limit. Each memory's size is given back to the budget when the memory
+    /// is dropped. See [`MemoryBudget`] for more information.
+    ///
+    /// By default memories aren't charged against any budget.
+    pub fn memory_budget(mut self, budget: &MemoryBudget) -> Self {
+        self.0.usage = MemoryBudget::account(Some(budget));
+        self.0.budget = Some(budget.clone());
+        self
+    }
+
     /// The maximum number of elements in a table.
     ///
     /// Growing a table beyond this limit will fail. This limit is applied to
@@ -312,29 +365,81 @@ impl StoreLimitsBuilder {
 /// This is a convenience type included to avoid needing to implement the
 /// [`ResourceLimiter`] trait if your use case fits in the static configuration
 /// that this [`StoreLimits`] provides.
-#[derive(Clone, Debug)]
+#[derive(Debug)]
 pub struct StoreLimits {
     memory_size: Option<usize>,
+    memory_soft_limit: Option<usize>,
+    budget: Option<MemoryBudget>,
     table_elements: Option<usize>,
     instances: usize,
     tables: usize,
     memories: usize,
     trap_on_grow_failure: bool,
+
+    /// The memory charged by this store's memories, which is also charged
+    /// against `budget` if there is one.
+    usage: MemoryBudget,
 }
 
 impl Default for StoreLimits {
     fn default() -> Self {
         Self {
             memory_size: None,
+            memory_soft_limit: None,
+            budget: None,
             table_elements: None,
             instances: DEFAULT_INSTANCE_LIMIT,
             tables: DEFAULT_TABLE_LIMIT,
             memories: DEFAULT_MEMORY_LIMIT,
             trap_on_grow_failure: false,
+            usage: MemoryBudget::account(None),
+        }
+    }
+}
+
+/// Clones the configured limits, but not the memory usage that has been
+/// accounted for so far, so the clone starts out as if no memories had been
+/// created yet.
+impl Clone for StoreLimits {
+    fn clone(&self) -> Self {
+        Self {
+            memory_size: self.memory_size,
+            memory_soft_limit: self.memory_soft_limit,
+            budget: self.budget.clone(),
+            table_elements: self.table_elements,
+            instances: self.instances,
+            tables: self.tables,
+            memories: self.memories,
+            trap_on_grow_failure: self.trap_on_grow_failure,
+            usage: MemoryBudget::account(self.budget.as_ref()),
         }
     }
 }
 
+impl StoreLimits {
+    /// Returns the total number of bytes of linear memory that the store's
+    /// memories currently use.
+    pub fn memory_used(&self) -> usize {
+        self.usage.used()
+    }
+
+    /// Returns whether the store's memory usage is above its soft limit, see
+    /// [`StoreLimitsBuilder::memory_soft_limit`], or its memory budget is above
+    /// one of its watermarks, see [`MemoryBudgetBuilder::watermark`].
+    ///
+    /// WebAssembly can't shrink its linear memories, so the embedder can't
+    /// reclaim memory from a store under pressure short of dropping it.
+    /// Instead it can, for example, stop giving the store more work.
+    pub fn memory_pressure(&self) -> bool {
+        self.memory_soft_limit
+            .is_some_and(|limit| self.memory_used() > limit)
+            || self
+                .budget
+                .as_ref()
+                .is_some_and(|budget| budget.watermark_exceeded().is_some())
+    }
+}
+
 impl ResourceLimiter for StoreLimits {
     fn memory_growing(
         &mut self,
@@ -365,6 +470,10 @@ impl ResourceLimiter for StoreLimits {
         }
     }
 
+    fn memory_budget(&self) -> Option<&MemoryBudget> {
+        Some(&self.usage)
+    }
+
     fn table_growing(
         &mut self,
         _current: usize,
@@ -0,0 +1,255 @@
+//! Memory budgets shared between stores, see [`MemoryBudget`].
+
+use crate::prelude::*;
+use alloc::sync::Arc;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// A limit on the total size of linear memories across a group of stores.
+///
+/// A budget is attached to each store in the group through its resource
+/// limiter, either with
+/// [`StoreLimitsBuilder::memory_budget`](crate::StoreLimitsBuilder::memory_budget)
+/// or by returning it from
+/// [`ResourceLimiter::memory_budget`](crate::ResourceLimiter::memory_budget).
+/// Every memory that those stores create or grow is then charged against the
+/// budget, and creation or growth fails once the group as a whole would exceed
+/// the budget's limit. A memory's size is given back to the budget when the
+/// memory is dropped, which normally happens when its store is dropped.
+///
+/// Budgets can additionally have watermarks below their limit at which a
+/// callback is invoked, see [`MemoryBudgetBuilder::watermark`]. This can be
+/// used to react to memory pressure before growth starts failing, for example
+/// by no longer scheduling new work onto the group, see
+/// [`StoreLimits::memory_pressure`](crate::StoreLimits::memory_pressure).
+///
+/// Budgets are cheap to clone, and clones all refer to the same budget.
+#[derive(Clone)]
+pub struct MemoryBudget(Arc<BudgetInner>);
+
+struct BudgetInner {
+    limit: usize,
+    used: AtomicUsize,
+    /// Sorted in increasing order.
+    watermarks: Vec<usize>,
+    on_watermark: Option<Box<dyn Fn(&MemoryPressure) + Send + Sync>>,
+    /// A budget that everything charged against this one is also charged
+    /// against.
+    parent: Option<MemoryBudget>,
+}
+
+/// The memory charged against a [`MemoryBudget`] for one linear memory, which
+/// is given back to the budget when dropped.
+pub(crate) struct MemoryCharge {
+    budget: MemoryBudget,
+    bytes: usize,
+}
+
+/// A notification that the memory charged against a [`MemoryBudget`] has
+/// crossed one of its watermarks.
+///
+/// Passed to the callback configured with
+/// [`MemoryBudgetBuilder::on_watermark`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MemoryPressure {
+    /// The watermark that was crossed, in bytes.
+    pub watermark: usize,
+
+    /// The total number of bytes charged against the budget just after the
+    /// watermark was crossed.
+    pub used: usize,
+
+    /// Whether usage rose above the watermark, or fell back to or below it.
+    pub rising: bool,
+}
+
+/// Used to build [`MemoryBudget`]s.
+pub struct MemoryBudgetBuilder(BudgetInner);
+
+impl MemoryBudgetBuilder {
+    /// Creates a new builder for a budget which allows the stores sharing it
+    /// to have at most `limit` bytes of linear memory in total.
+    pub fn new(limit: usize) -> Self {
+        Self(BudgetInner {
+            limit,
+            used: AtomicUsize::new(0),
+            watermarks: Vec::new(),
+            on_watermark: None,
+            parent: None,
+        })
+    }
+
+    /// Adds a watermark, in bytes, at which the callback configured with
+    /// [`MemoryBudgetBuilder::on_watermark`] is invoked.
+    ///
+    /// The callback is invoked whenever the total amount of memory charged
+    /// against the budget rises above the watermark, and again whenever it
+    /// falls back to or below it. Any number of watermarks may be added.
+    pub fn watermark(mut self, bytes: usize) -> Self {
+        let index = self.0.watermarks.partition_point(|w| *w < bytes);
+        self.0.watermarks.insert(index, bytes);
+        self
+    }
+
+    /// Configures the callback to invoke when a watermark is crossed.
+    ///
+    /// The callback is invoked synchronously from whichever thread is growing
+    /// or releasing memory, which for growth is in the middle of executing
+    /// WebAssembly or of a call to a host API such as
+    /// [`Memory::grow`](crate::Memory::grow). It therefore can't access the
+    /// store that caused the watermark to be crossed, and should generally
+    /// just record that the budget is under pressure for the embedding to act
+    /// on later.
+    pub fn on_watermark(
+        mut self,
+        callback: impl Fn(&MemoryPressure) + Send + Sync + 'static,
+    ) -> Self {
+        self.0.on_watermark = Some(Box::new(callback));
+        self
+    }
+
+    /// Consumes this builder and returns the [`MemoryBudget`].
+    pub fn build(self) -> MemoryBudget {
+        MemoryBudget(Arc::new(self.0))
+    }
+}
+
+impl MemoryBudget {
+    /// Creates a new budget which allows the stores sharing it to have at most
+    /// `limit` bytes of linear memory in total, without any watermarks.
+    pub fn new(limit: usize) -> MemoryBudget {
+        MemoryBudgetBuilder::new(limit).build()
+    }
+
+    /// Creates an unlimited budget which tracks the memory of a single store,
+    /// and which charges everything charged against it against `parent` too.
+    pub(crate) fn account(parent: Option<&MemoryBudget>) -> MemoryBudget {
+        let mut builder = MemoryBudgetBuilder::new(usize::MAX);
+        builder.0.parent = parent.cloned();
+        builder.build()
+    }
+
+    /// Returns the maximum number of bytes of memory that may be charged
+    /// against this budget.
+    pub fn limit(&self) -> usize {
+        self.0.limit
+    }
+
+    /// Returns the number of bytes of memory currently charged against this
+    /// budget.
+    pub fn used(&self) -> usize {
+        self.0.used.load(Ordering::Relaxed)
+    }
+
+    /// Returns the highest watermark that the memory charged against this
+    /// budget is currently above, if any.
+    pub fn watermark_exceeded(&self) -> Option<usize> {
+        let used = self.used();
+        self.0.watermarks.iter().rev().copied().find(|w| used > *w)
+    }
+
+    /// Attempts to charge `bytes` against this budget and its parent, if any,
+    /// returning whether it fit within both of their limits.
+    fn charge(&self, bytes: usize) -> bool {
+        if let Some(parent) = &self.0.parent
+            && !parent.charge(bytes)
+        {
+            return false;
+        }
+        let limit = self.0.limit;
+        let result = self
+            .0
+            .used
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |used| {
+                used.checked_add(bytes).filter(|new| *new <= limit)
+            });
+        match result {
+            Ok(used) => {
+                self.notify(used, used + bytes);
+                true
+            }
+            Err(_) => {
+                if let Some(parent) = &self.0.parent {
+                    parent.release(bytes);
+                }
+                false
+            }
+        }
+    }
+
+    /// Gives `bytes` previously charged with `charge` back to this budget and
+    /// its parent, if any.
+    fn release(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        let used = self.0.used.fetch_sub(bytes, Ordering::Relaxed);
+        debug_assert!(used >= bytes);
+        self.notify(used, used - bytes);
+        if let Some(parent) = &self.0.parent {
+            parent.release(bytes);
+        }
+    }
+
+    /// Invokes the watermark callback for every watermark between `old` and
+    /// `new`.
+    fn notify(&self, old: usize, new: usize) {
+        let Some(callback) = &self.0.on_watermark else {
+            return;
+        };
+        let rising = new > old;
+        let (low, high) = if rising { (old, new) } else { (new, old) };
+        for watermark in self.0.watermarks.iter().copied() {
+            if low <= watermark && watermark < high {
+                callback(&MemoryPressure {
+                    watermark,
+                    used: new,
+                    rising,
+                });
+            }
+        }
+    }
+}
+
+impl fmt::Debug for MemoryBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryBudget")
+            .field("limit", &self.0.limit)
+            .field("used", &self.used())
+            .field("watermarks", &self.0.watermarks)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MemoryCharge {
+    /// Charges a new memory of `bytes` bytes against `budget`, returning
+    /// `None` if it doesn't fit.
+    pub(crate) fn new(budget: &MemoryBudget, bytes: usize) -> Option<MemoryCharge> {
+        budget.charge(bytes).then(|| MemoryCharge {
+            budget: budget.clone(),
+            bytes,
+        })
+    }
+
+    /// Charges growing the memory by `bytes`, returning whether it fit.
+    pub(crate) fn grow(&mut self, bytes: usize) -> bool {
+        let fits = self.budget.charge(bytes);
+        if fits {
+            self.bytes += bytes;
+        }
+        fits
+    }
+
+    /// Gives back `bytes` charged by `grow` when growth then fails.
+    pub(crate) fn shrink(&mut self, bytes: usize) {
+        self.bytes -= bytes;
+        self.budget.release(bytes);
+    }
+}
+
+impl Drop for MemoryCharge {
+    fn drop(&mut self) {
+        self.budget.release(self.bytes);
+    }
+}
@@ -678,6 +678,30 @@ impl Memory {
     pub(crate) fn hash_key(&self, store: &StoreOpaque) -> impl core::hash::Hash + Eq + use<> {
         store[self.instance].memory_ptr(self.index).as_ptr().addr()
     }
+
+    #[cfg(feature = "debug")]
+    pub(crate) fn comes_from_store(&self, store: crate::store::StoreId) -> bool {
+        store == self.instance.store_id()
+    }
+
+    /// Returns whether `self` and `other` refer to the same memory.
+    ///
+    /// Both handles must have been produced by canonicalizing through the
+    /// defining instance, as `vm::Instance::get_exported_memory` does.
+    #[cfg(feature = "debug")]
+    pub(crate) fn same(&self, other: &Memory) -> bool {
+        self.instance == other.instance && self.index == other.index
+    }
+
+    #[cfg(feature = "debug")]
+    pub(crate) fn internal_data<'a>(&self, store: &'a StoreOpaque) -> &'a [u8] {
+        // SAFETY: the definition describes a valid region of memory owned
+        // by `store`, and shared memories are never wrapped in a `Memory`.
+        unsafe {
+            let definition = store[self.instance].memory(self.index);
+            slice::from_raw_parts(definition.base.as_ptr(), definition.current_length())
+        }
+    }
 }
 
 /// A linear memory. This trait provides an interface for raw memory buffers
@@ -21,8 +21,8 @@ use wasmparser::{Parser, ValidPayload, Validator};
 #[cfg(feature = "debug")]
 use wasmtime_environ::FrameTable;
 use wasmtime_environ::{
-    CompiledFunctionsTable, CompiledModuleInfo, EntityIndex, HostPtr, ModuleTypes, ObjectKind,
-    TypeTrace, VMOffsets, VMSharedTypeIndex,
+    CompiledFunctionsTable, CompiledModuleInfo, EntityIndex, HostPtr, InstructionCountTable,
+    ModuleTypes, ObjectKind, TypeTrace, VMOffsets, VMSharedTypeIndex,
 };
 #[cfg(feature = "gc")]
 use wasmtime_unwinder::ExceptionTable;
@@ -46,11 +46,11 @@ pub use registry::*;
 /// call to [`Module::deserialize`] will quickly load the module to execute and
 /// does not need to compile any code, representing a more AOT-style use case.
 ///
-/// Currently a `Module` does not implement any form of tiering or dynamic
-/// optimization of compiled code. Creation of a `Module` via [`Module::new`] or
-/// related APIs will perform the entire compilation step synchronously. When
-/// finished no further compilation will happen at runtime or later during
-/// execution of WebAssembly instances for example.
+/// Creation of a `Module` via [`Module::new`] or related APIs will perform the
+/// entire compilation step synchronously. When finished no further compilation
+/// will happen at runtime or later during execution of WebAssembly instances,
+/// unless [`Strategy::Tiered`](crate::Strategy::Tiered) is used, in which case
+/// frequently called functions are recompiled in the background.
 ///
 /// Compilation of WebAssembly by default goes through Cranelift and is
 /// recommended to be done once-per-module. The same WebAssembly binary need not
@@ -161,6 +161,11 @@ struct ModuleInner {
 
     /// Runtime offset information for `VMContext`.
     offsets: VMOffsets<HostPtr>,
+
+    /// Tiered compilation state, present if this module was compiled with
+    /// `Strategy::Tiered`.
+    #[cfg(feature = "winch")]
+    tiering: Option<Arc<crate::runtime::tiering::ModuleTiering>>,
 }
 
 impl fmt::Debug for Module {
@@ -349,7 +354,7 @@ impl Module {
     pub unsafe fn from_trusted_file(engine: &Engine, file: impl AsRef<Path>) -> Result<Module> {
         let open_file = open_file_for_mmap(file.as_ref())?;
         let mmap = crate::runtime::vm::MmapVec::from_file(open_file)?;
-        if &mmap[0..4] == b"\x7fELF" {
+        if &mmap[0..4] == b"\x7fELF" || Engine::detect_precompiled(&mmap).is_some() {
             let code = engine.load_code(mmap, ObjectKind::Module)?;
             return Module::from_parts(engine, code, None);
         }
@@ -370,6 +375,11 @@ impl Module {
     /// this function, so if the serialized module is already present in a file
     /// it's recommended to use that method instead.
     ///
+    /// Modules compiled with [`Strategy::Tiered`](crate::Strategy::Tiered)
+    /// are never tiered up once deserialized, since their original wasm
+    /// binary isn't available to recompile their functions from. They keep
+    /// running their Winch-compiled code.
+    ///
     /// # Unsafety
     ///
     /// This function is marked as `unsafe` because if fed invalid input or used
@@ -406,6 +416,29 @@ impl Module {
         Module::from_parts(engine, code, None)
     }
 
+    /// Deserializes an in-memory compiled module previously created with
+    /// [`Module::serialize`] or [`Engine::precompile_module`] by an engine
+    /// configured with [`Config::sign_artifacts`].
+    ///
+    /// Unlike [`Module::deserialize`] this function is safe to call with
+    /// untrusted input. Before the artifact is loaded its signature is checked
+    /// to have been made by one of the keys trusted with
+    /// [`Config::trust_artifact_key`]. Artifacts which are unsigned, were
+    /// signed by any other key, or were modified after signing are rejected
+    /// with an error.
+    ///
+    /// Trusting a key means trusting whoever holds its secret half in the
+    /// same way that calling [`Module::deserialize`] trusts its input, so keys
+    /// need to be protected accordingly.
+    ///
+    /// [`Config::sign_artifacts`]: crate::Config::sign_artifacts
+    /// [`Config::trust_artifact_key`]: crate::Config::trust_artifact_key
+    #[cfg(feature = "signing")]
+    pub fn deserialize_verified(engine: &Engine, bytes: impl AsRef<[u8]>) -> Result<Module> {
+        let code = engine.load_code_bytes_verified(bytes.as_ref(), ObjectKind::Module)?;
+        Module::from_parts(engine, code, None)
+    }
+
     /// In-place deserialization of an in-memory compiled module previously
     /// created with [`Module::serialize`] or [`Engine::precompile_module`].
     ///
@@ -542,6 +575,16 @@ impl Module {
 
         let _ = serializable;
 
+        #[cfg(feature = "winch")]
+        let tiering = if engine.tunables().tiered_compilation {
+            Some(Arc::new(crate::runtime::tiering::ModuleTiering::new(
+                module.module(),
+                &offsets,
+            )))
+        } else {
+            None
+        };
+
         Ok(Self {
             inner: Arc::new(ModuleInner {
                 engine: engine.clone(),
@@ -551,6 +594,8 @@ impl Module {
                 #[cfg(any(feature = "cranelift", feature = "winch"))]
                 serializable,
                 offsets,
+                #[cfg(feature = "winch")]
+                tiering,
             }),
         })
     }
@@ -631,7 +676,8 @@ impl Module {
         if !self.inner.serializable {
             bail!("cannot serialize a module exported from a component");
         }
-        Ok(self.engine_code().image().to_vec())
+        self.engine()
+            .sign_serialized(self.engine_code().image().to_vec())
     }
 
     pub(crate) fn compiled_module(&self) -> &CompiledModule {
@@ -650,7 +696,11 @@ impl Module {
         self.inner.code.module_types()
     }
 
-    #[cfg(any(feature = "component-model", feature = "gc-drc"))]
+    #[cfg(any(
+        feature = "component-model",
+        feature = "gc-drc",
+        feature = "gc-copying"
+    ))]
     pub(crate) fn signatures(&self) -> &crate::type_registry::TypeCollection {
         self.inner.code.signatures()
     }
@@ -1078,6 +1128,13 @@ impl Module {
         &self.inner.offsets
     }
 
+    /// Returns the tiered compilation state of this module, if it was compiled
+    /// with `Strategy::Tiered`.
+    #[cfg(feature = "winch")]
+    pub(crate) fn tiering(&self) -> Option<&Arc<crate::runtime::tiering::ModuleTiering>> {
+        self.inner.tiering.as_ref()
+    }
+
     /// Return the address, in memory, of the trampoline that allows Wasm to
     /// call a array function of the given signature.
     ///
@@ -1154,6 +1211,20 @@ impl Module {
         }
     }
 
+    /// Obtain a parser for this module's instruction-count metadata, if it
+    /// was compiled with instruction counting enabled.
+    pub(crate) fn instruction_count_table<'a>(&'a self) -> Option<InstructionCountTable<'a>> {
+        let data = self.inner.code.instruction_counts();
+        if data.is_empty() {
+            None
+        } else {
+            Some(
+                InstructionCountTable::parse(data)
+                    .expect("Instruction counts were validated on module load"),
+            )
+        }
+    }
+
     /// Is this `Module` the same as another?
     ///
     /// Ordinarily, module identity does not matter: a Wasmtime user
@@ -252,6 +252,20 @@ impl ModuleRegistry {
         Some((info, module_with_code))
     }
 
+    /// Fetches frame information about a program counter within the
+    /// recompiled code of a function which has been tiered up, see
+    /// [`Strategy::Tiered`](crate::Strategy::Tiered).
+    ///
+    /// Such code isn't part of any module's code, so `lookup_frame_info`
+    /// doesn't know about it.
+    #[cfg(feature = "winch")]
+    pub(crate) fn lookup_tiered_frame_info(&self, pc: usize) -> Option<FrameInfo> {
+        self.modules.values().find_map(|module| {
+            let (index, instr) = module.tiering()?.lookup_pc(pc)?;
+            Some(FrameInfo::from_instr(module.clone(), index, instr))
+        })
+    }
+
     pub fn wasm_to_array_trampoline(
         &self,
         sig: VMSharedTypeIndex,
@@ -6,14 +6,17 @@ use crate::{Engine, prelude::*};
 use core::cmp::Ordering;
 use fxprof_processed_profile::debugid::DebugId;
 use fxprof_processed_profile::{
-    CategoryHandle, Frame, FrameFlags, FrameInfo, LibraryInfo, MarkerLocations, MarkerTiming,
-    Profile, ReferenceTimestamp, StaticSchemaMarker, StaticSchemaMarkerField, StringHandle, Symbol,
-    SymbolTable, Timestamp,
+    CategoryHandle, Frame, FrameFlags, FrameInfo, LibraryHandle, LibraryInfo, MarkerLocations,
+    MarkerTiming, Profile, ReferenceTimestamp, StaticSchemaMarker, StaticSchemaMarkerField,
+    StringHandle, Symbol, SymbolTable, Timestamp,
 };
+use stacks::{PprofInfo, StackCounts, StackFrame};
 use std::ops::Range;
-use std::sync::Arc;
-use std::time::{Duration, Instant};
-use wasmtime_environ::demangle_function_name_or_index;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+mod pprof;
+mod stacks;
 
 // TODO: collect more data
 // - On non-Windows, measure thread-local CPU usage between events with
@@ -22,6 +25,14 @@ use wasmtime_environ::demangle_function_name_or_index;
 
 /// Collects basic profiling data for a single WebAssembly guest.
 ///
+/// The collected profile can be written in the Firefox Profiler format with
+/// [`GuestProfiler::finish`], as a [pprof] protobuf with
+/// [`GuestProfiler::finish_pprof`], or as collapsed stacks for flame graph
+/// tools with [`GuestProfiler::finish_collapsed`]. To combine samples from
+/// many stores or threads into a single profile, see [`ProfileAggregator`].
+///
+/// [pprof]: https://github.com/google/pprof
+///
 /// This profiler can't provide measurements that are as accurate or detailed
 /// as a platform-specific profiler, such as `perf` on Linux. On the other
 /// hand, this profiler works on every platform that Wasmtime supports. Also,
@@ -76,19 +87,25 @@ use wasmtime_environ::demangle_function_name_or_index;
 pub struct GuestProfiler {
     profile: Profile,
     modules: Modules,
+    /// The Firefox profile's library for each entry in `modules`.
+    libs: Vec<LibraryHandle>,
     process: fxprof_processed_profile::ProcessHandle,
     thread: fxprof_processed_profile::ThreadHandle,
     start: Instant,
+    start_time: SystemTime,
+    interval: Duration,
     marker: CallMarker,
+    stacks: StackCounts,
 }
 
 #[derive(Debug)]
 struct ProfiledModule {
+    name: String,
     module: Module,
-    fxprof_libhandle: fxprof_processed_profile::LibraryHandle,
     text_range: Range<usize>,
 }
 
+/// Profiled modules, sorted by `text_range`.
 type Modules = Vec<ProfiledModule>;
 
 impl GuestProfiler {
@@ -113,50 +130,15 @@ impl GuestProfiler {
         interval: Duration,
         modules: impl IntoIterator<Item = (String, Module)>,
     ) -> Result<Self> {
-        // Check that guest debugging is not enabled. The
-        // instrumentation would make profiling results unreliable,
-        // but more fundamentally, it means that code is cloned per
-        // instantiation (for breakpoint patching) so the logic below
-        // is incorrect.
-        if engine.tunables().debug_guest {
-            anyhow::bail!("Profiling cannot be performed when guest-debugging is enabled.");
-        }
+        let modules = profiled_modules(engine, modules)?;
 
         let zero = ReferenceTimestamp::from_millis_since_unix_epoch(0.0);
         let mut profile = Profile::new(module_name, zero, interval.into());
-
-        // Past this point, we just need to think about modules as we pull out
-        // the disparate module information from components.
-        let mut modules: Vec<_> = modules
-            .into_iter()
-            .filter_map(|(name, module)| {
-                assert!(Engine::same(module.engine(), engine));
-                let compiled = module.compiled_module();
-                let text_range = {
-                    // Assumption: within text, the code for a given module is packed linearly and
-                    // is non-overlapping; if this is violated, it should be safe but might result
-                    // in incorrect profiling results.
-                    let start = compiled.finished_function_ranges().next()?.1.start;
-                    let end = compiled.finished_function_ranges().last()?.1.end;
-
-                    let start = (module.engine_code().text_range().start + start).raw();
-                    let end = (module.engine_code().text_range().start + end).raw();
-                    start..end
-                };
-
-                module_symbols(name, &module).map(|lib| {
-                    let libhandle = profile.add_lib(lib);
-                    ProfiledModule {
-                        module,
-                        fxprof_libhandle: libhandle,
-                        text_range,
-                    }
-                })
-            })
+        let libs = modules
+            .iter()
+            .map(|m| profile.add_lib(module_symbols(m)))
             .collect();
 
-        modules.sort_unstable_by_key(|m| m.text_range.start);
-
         profile.set_reference_timestamp(std::time::SystemTime::now().into());
         let process = profile.add_process(module_name, 0, Timestamp::from_nanos_since_reference(0));
         let thread = profile.add_thread(process, 0, Timestamp::from_nanos_since_reference(0), true);
@@ -165,10 +147,14 @@ impl GuestProfiler {
         Ok(Self {
             profile,
             modules,
+            libs,
             process,
             thread,
             start,
+            start_time: SystemTime::now(),
+            interval,
             marker,
+            stacks: StackCounts::default(),
         })
     }
 
@@ -205,12 +191,14 @@ impl GuestProfiler {
             self.start.elapsed().as_nanos().try_into().unwrap(),
         );
         let backtrace = Backtrace::new(store.as_context().0);
-        let frames = lookup_frames(&self.modules, &backtrace);
+        let frames = lookup_frames(&self.modules, &self.libs, &backtrace);
         let stack = self
             .profile
             .intern_stack_frames(self.thread, frames.into_iter());
         self.profile
             .add_sample(self.thread, now, stack, delta.into(), 1);
+        self.stacks
+            .add(lookup_stack(&self.modules, &backtrace), delta);
     }
 
     /// Add a marker for transitions between guest and host to the profile.
@@ -225,7 +213,7 @@ impl GuestProfiler {
             CallHook::CallingWasm | CallHook::ReturningFromWasm => {}
             CallHook::CallingHost => {
                 let backtrace = Backtrace::new(store.as_context().0);
-                let frames = lookup_frames(&self.modules, &backtrace);
+                let frames = lookup_frames(&self.modules, &self.libs, &backtrace);
                 let marker = self.profile.add_marker(
                     self.thread,
                     MarkerTiming::IntervalStart(now),
@@ -259,37 +247,222 @@ impl GuestProfiler {
         serde_json::to_writer(output, &self.profile)?;
         Ok(())
     }
+
+    /// When the guest finishes running, call this function to write the
+    /// samples collected so far to `output` as an uncompressed [pprof]
+    /// protobuf, as consumed by `go tool pprof` and similar tools.
+    ///
+    /// Host-call markers are not included, and neither are samples taken
+    /// while none of the profiled modules were on the stack.
+    ///
+    /// [pprof]: https://github.com/google/pprof/blob/main/proto/profile.proto
+    pub fn finish_pprof(self, output: impl std::io::Write) -> Result<()> {
+        let info = PprofInfo {
+            start: self.start_time,
+            duration: self.start.elapsed(),
+            interval: self.interval,
+        };
+        self.stacks.write_pprof(&self.modules, &info, output)
+    }
+
+    /// When the guest finishes running, call this function to write the
+    /// samples collected so far to `output` as collapsed stacks: one line
+    /// per distinct stack, with frames separated by `;`, followed by the
+    /// number of samples. This is the input format of flame graph tools such
+    /// as `flamegraph.pl` and `inferno-flamegraph`.
+    ///
+    /// Like [`GuestProfiler::finish_pprof`], host-call markers and samples
+    /// without any profiled frames are not included.
+    pub fn finish_collapsed(self, output: impl std::io::Write) -> Result<()> {
+        self.stacks.write_collapsed(&self.modules, output)
+    }
+}
+
+/// Aggregates profiling samples from any number of stores, on any number of
+/// threads, into a single profile.
+///
+/// Unlike [`GuestProfiler`], which records a timeline for one guest, this
+/// only counts how often each stack was observed. This makes it suitable for
+/// long-running embeddings, such as an HTTP server creating a store per
+/// request, which want one profile of where all guests spend their time.
+/// Cloning a `ProfileAggregator` is cheap and yields a handle to the same
+/// profile, so each store can keep its own handle to sample into.
+///
+/// Samples are collected with [`ProfileAggregator::sample`], typically from a
+/// callback registered with
+/// [`Store::epoch_deadline_callback()`](crate::Store::epoch_deadline_callback),
+/// and the profile can be written at any point with
+/// [`ProfileAggregator::write_pprof`] or
+/// [`ProfileAggregator::write_collapsed`].
+///
+/// The accuracy and security considerations documented on [`GuestProfiler`]
+/// apply here as well.
+#[derive(Debug, Clone)]
+pub struct ProfileAggregator {
+    inner: Arc<AggregatorInner>,
 }
 
-fn module_symbols(name: String, module: &Module) -> Option<LibraryInfo> {
-    let compiled = module.compiled_module();
-    let symbols = Vec::from_iter(
-        module
-            .env_module()
-            .defined_func_indices()
-            .map(|defined_idx| {
-                let loc = compiled.func_loc(defined_idx);
-                let func_idx = compiled.module().func_index(defined_idx);
-                let mut name = String::new();
-                demangle_function_name_or_index(
-                    &mut name,
-                    compiled.func_name(func_idx),
-                    defined_idx.as_u32() as usize,
-                )
-                .unwrap();
-                Symbol {
-                    address: loc.start,
-                    size: Some(loc.length),
-                    name,
-                }
+#[derive(Debug)]
+struct AggregatorInner {
+    modules: Modules,
+    interval: Duration,
+    state: Mutex<AggregatorState>,
+}
+
+#[derive(Debug)]
+struct AggregatorState {
+    stacks: StackCounts,
+    start: Instant,
+    start_time: SystemTime,
+}
+
+impl ProfileAggregator {
+    /// Creates a new, empty aggregated profile.
+    ///
+    /// The `interval` parameter and `modules` list have the same meaning as
+    /// for [`GuestProfiler::new`]; only frames of the listed modules appear in
+    /// the profile.
+    pub fn new(
+        engine: &Engine,
+        interval: Duration,
+        modules: impl IntoIterator<Item = (String, Module)>,
+    ) -> Result<Self> {
+        Ok(Self {
+            inner: Arc::new(AggregatorInner {
+                modules: profiled_modules(engine, modules)?,
+                interval,
+                state: Mutex::new(AggregatorState {
+                    stacks: StackCounts::default(),
+                    start: Instant::now(),
+                    start_time: SystemTime::now(),
+                }),
             }),
-    );
-    if symbols.is_empty() {
-        return None;
+        })
     }
 
-    Some(LibraryInfo {
-        name,
+    /// Creates a new aggregated profile for the provided component, in the
+    /// same way as [`GuestProfiler::new_component`].
+    #[cfg(feature = "component-model")]
+    pub fn new_component(
+        engine: &Engine,
+        interval: Duration,
+        component: Component,
+        extra_modules: impl IntoIterator<Item = (String, Module)>,
+    ) -> Result<Self> {
+        let modules = component
+            .static_modules()
+            .map(|m| (m.name().unwrap_or("<unknown>").to_string(), m.clone()))
+            .chain(extra_modules);
+        Self::new(engine, interval, modules)
+    }
+
+    /// Adds a sample of the current stack of `store` to the profile.
+    ///
+    /// The `store` must belong to the engine this profile was created with.
+    /// The `delta` parameter has the same meaning as for
+    /// [`GuestProfiler::sample`].
+    pub fn sample(&self, store: impl AsContext, delta: Duration) {
+        let backtrace = Backtrace::new(store.as_context().0);
+        let stack: Vec<_> = lookup_stack(&self.inner.modules, &backtrace).collect();
+        self.inner
+            .state
+            .lock()
+            .unwrap()
+            .stacks
+            .add(stack.into_iter(), delta);
+    }
+
+    /// Writes all samples collected so far to `output` as an uncompressed
+    /// pprof protobuf. See [`GuestProfiler::finish_pprof`] for details.
+    pub fn write_pprof(&self, output: impl std::io::Write) -> Result<()> {
+        let (stacks, info) = {
+            let state = self.inner.state.lock().unwrap();
+            let info = PprofInfo {
+                start: state.start_time,
+                duration: state.start.elapsed(),
+                interval: self.inner.interval,
+            };
+            (state.stacks.clone(), info)
+        };
+        stacks.write_pprof(&self.inner.modules, &info, output)
+    }
+
+    /// Writes all samples collected so far to `output` as collapsed stacks.
+    /// See [`GuestProfiler::finish_collapsed`] for details.
+    pub fn write_collapsed(&self, output: impl std::io::Write) -> Result<()> {
+        let stacks = self.inner.state.lock().unwrap().stacks.clone();
+        stacks.write_collapsed(&self.inner.modules, output)
+    }
+
+    /// Discards all samples collected so far and restarts the profile's
+    /// clock, for example after periodically writing out the profile.
+    pub fn reset(&self) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.stacks.clear();
+        state.start = Instant::now();
+        state.start_time = SystemTime::now();
+    }
+}
+
+/// Collects the modules which should appear in a profile, sorted by their
+/// location in memory.
+fn profiled_modules(
+    engine: &Engine,
+    modules: impl IntoIterator<Item = (String, Module)>,
+) -> Result<Modules> {
+    // Check that guest debugging is not enabled. The
+    // instrumentation would make profiling results unreliable,
+    // but more fundamentally, it means that code is cloned per
+    // instantiation (for breakpoint patching) so the logic below
+    // is incorrect.
+    if engine.tunables().debug_guest {
+        anyhow::bail!("Profiling cannot be performed when guest-debugging is enabled.");
+    }
+
+    let mut modules: Vec<_> = modules
+        .into_iter()
+        .filter_map(|(name, module)| {
+            assert!(Engine::same(module.engine(), engine));
+            let compiled = module.compiled_module();
+            let text_range = {
+                // Assumption: within text, the code for a given module is packed linearly and
+                // is non-overlapping; if this is violated, it should be safe but might result
+                // in incorrect profiling results.
+                let start = compiled.finished_function_ranges().next()?.1.start;
+                let end = compiled.finished_function_ranges().last()?.1.end;
+
+                let start = (module.engine_code().text_range().start + start).raw();
+                let end = (module.engine_code().text_range().start + end).raw();
+                start..end
+            };
+
+            Some(ProfiledModule {
+                name,
+                module,
+                text_range,
+            })
+        })
+        .collect();
+
+    modules.sort_unstable_by_key(|m| m.text_range.start);
+    Ok(modules)
+}
+
+fn module_symbols(module: &ProfiledModule) -> LibraryInfo {
+    let compiled = module.module.compiled_module();
+    let symbols = Vec::from_iter(module.module.env_module().defined_func_indices().map(
+        |defined_idx| {
+            let loc = compiled.func_loc(defined_idx);
+            Symbol {
+                address: loc.start,
+                size: Some(loc.length),
+                name: stacks::func_name(module, defined_idx),
+            }
+        },
+    ));
+
+    LibraryInfo {
+        name: module.name.clone(),
         debug_name: String::new(),
         path: String::new(),
         debug_path: String::new(),
@@ -297,11 +470,30 @@ fn module_symbols(name: String, module: &Module) -> Option<LibraryInfo> {
         code_id: None,
         arch: None,
         symbol_table: Some(Arc::new(SymbolTable::new(symbols))),
-    })
+    }
+}
+
+/// Finds the profiled module containing `pc`, returning its index in
+/// `modules` along with `pc` as an offset into the module's full text (not
+/// just its functions; these can be different for component model modules).
+fn find_module(modules: &Modules, pc: usize) -> Option<(usize, &ProfiledModule, usize)> {
+    let idx = modules
+        .binary_search_by(|probe| {
+            if probe.text_range.contains(&pc) {
+                Ordering::Equal
+            } else {
+                probe.text_range.start.cmp(&pc)
+            }
+        })
+        .ok()?;
+    let module = modules.get(idx)?;
+    let module_text_start = module.module.text().as_ptr_range().start as usize;
+    Some((idx, module, pc - module_text_start))
 }
 
 fn lookup_frames<'a>(
     modules: &'a Modules,
+    libs: &'a [LibraryHandle],
     backtrace: &'a Backtrace,
 ) -> impl Iterator<Item = FrameInfo> + 'a {
     backtrace
@@ -310,25 +502,11 @@ fn lookup_frames<'a>(
         // first, so iterate in reverse.
         .rev()
         .filter_map(|frame| {
-            let idx = modules
-                .binary_search_by(|probe| {
-                    if probe.text_range.contains(&frame.pc()) {
-                        Ordering::Equal
-                    } else {
-                        probe.text_range.start.cmp(&frame.pc())
-                    }
-                })
-                .ok()?;
-            let module = modules.get(idx)?;
-
-            // We need to point to the modules full text (not just its functions) as
-            // the offset for the final phase; these can be different for component
-            // model modules.
-            let module_text_start = module.module.text().as_ptr_range().start as usize;
+            let (idx, _module, offset) = find_module(modules, frame.pc())?;
             return Some(FrameInfo {
                 frame: Frame::RelativeAddressFromReturnAddress(
-                    module.fxprof_libhandle,
-                    u32::try_from(frame.pc() - module_text_start).unwrap(),
+                    libs[idx],
+                    u32::try_from(offset).unwrap(),
                 ),
                 category_pair: CategoryHandle::OTHER.into(),
                 flags: FrameFlags::empty(),
@@ -336,6 +514,24 @@ fn lookup_frames<'a>(
         })
 }
 
+/// Like `lookup_frames`, but resolves each frame to the function containing
+/// it for aggregation. Frames are again produced oldest first.
+fn lookup_stack<'a>(
+    modules: &'a Modules,
+    backtrace: &'a Backtrace,
+) -> impl Iterator<Item = StackFrame> + 'a {
+    backtrace.frames().rev().filter_map(|frame| {
+        let (idx, module, offset) = find_module(modules, frame.pc())?;
+        // The pc is a return address, so look up the call instruction just
+        // before it, which is within the calling function.
+        let func = module
+            .module
+            .compiled_module()
+            .func_by_text_offset(offset.saturating_sub(1))?;
+        Some(StackFrame { module: idx, func })
+    })
+}
+
 #[derive(Debug, Clone, Copy)]
 struct CallMarker {
     name: StringHandle,
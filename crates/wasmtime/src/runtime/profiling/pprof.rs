@@ -0,0 +1,113 @@
+//! A minimal encoder for the [pprof] protobuf format.
+//!
+//! Only the subset of the format that guest profiles need is implemented
+//! here, which avoids pulling in a full protobuf implementation for a handful
+//! of messages.
+//!
+//! [pprof]: https://github.com/google/pprof/blob/main/proto/profile.proto
+
+use crate::hash_map::HashMap;
+use crate::prelude::*;
+
+/// A protobuf message which is being built up field-by-field.
+#[derive(Default)]
+pub(super) struct Message {
+    buf: Vec<u8>,
+}
+
+const WIRE_VARINT: u32 = 0;
+const WIRE_LEN: u32 = 2;
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.buf.push((value as u8) | 0x80);
+            value >>= 7;
+        }
+        self.buf.push(value as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(u64::from(field << 3 | wire_type));
+    }
+
+    /// Appends a `uint64` field, or a `bool` field when `value` is 0 or 1.
+    pub fn uint64(&mut self, field: u32, value: u64) {
+        if value != 0 {
+            self.key(field, WIRE_VARINT);
+            self.varint(value);
+        }
+    }
+
+    /// Appends an `int64` field.
+    pub fn int64(&mut self, field: u32, value: i64) {
+        self.uint64(field, value as u64);
+    }
+
+    /// Appends a `bytes` or `string` field.
+    pub fn bytes(&mut self, field: u32, bytes: &[u8]) {
+        self.key(field, WIRE_LEN);
+        self.varint(bytes.len() as u64);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Appends an embedded message field.
+    pub fn message(&mut self, field: u32, message: &Message) {
+        self.bytes(field, &message.buf);
+    }
+
+    /// Appends a packed `repeated uint64` or `repeated int64` field.
+    pub fn packed(&mut self, field: u32, values: impl IntoIterator<Item = u64>) {
+        let mut packed = Message::default();
+        for value in values {
+            packed.varint(value);
+        }
+        if !packed.buf.is_empty() {
+            self.message(field, &packed);
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+/// The `string_table` of a pprof profile, where index 0 is always the empty
+/// string.
+pub(super) struct StringTable {
+    strings: Vec<String>,
+    indices: HashMap<String, i64>,
+}
+
+impl StringTable {
+    pub fn new() -> Self {
+        let mut table = StringTable {
+            strings: Vec::new(),
+            indices: HashMap::new(),
+        };
+        table.intern("");
+        table
+    }
+
+    pub fn intern(&mut self, s: &str) -> i64 {
+        if let Some(index) = self.indices.get(s) {
+            return *index;
+        }
+        let index = i64::try_from(self.strings.len()).unwrap();
+        self.strings.push(s.to_string());
+        self.indices.insert(s.to_string(), index);
+        index
+    }
+
+    pub fn strings(&self) -> impl Iterator<Item = &str> {
+        self.strings.iter().map(|s| s.as_str())
+    }
+}
+
+/// Encodes a `ValueType` message.
+pub(super) fn value_type(strings: &mut StringTable, ty: &str, unit: &str) -> Message {
+    let mut message = Message::default();
+    message.int64(1, strings.intern(ty));
+    message.int64(2, strings.intern(unit));
+    message
+}
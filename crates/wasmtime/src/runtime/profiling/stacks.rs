@@ -0,0 +1,205 @@
+//! Aggregated, symbolized guest stacks and the pprof and collapsed-stack
+//! exporters built on top of them.
+
+use super::ProfiledModule;
+use super::pprof::{Message, StringTable, value_type};
+use crate::hash_map::HashMap;
+use crate::prelude::*;
+use std::time::{Duration, SystemTime};
+use wasmtime_environ::{DefinedFuncIndex, demangle_function_name_or_index};
+
+/// A single frame of an aggregated stack: a defined function within one of
+/// the profiled modules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(super) struct StackFrame {
+    /// Index into the sorted list of profiled modules.
+    pub module: usize,
+    pub func: DefinedFuncIndex,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct SampleCounts {
+    samples: u64,
+    cpu_nanos: u64,
+}
+
+/// The number of samples, and the CPU time, recorded for each distinct
+/// stack. Stacks are stored with the oldest frame first.
+#[derive(Debug, Default, Clone)]
+pub(super) struct StackCounts {
+    counts: HashMap<Box<[StackFrame]>, SampleCounts>,
+}
+
+/// Profile-wide metadata written into pprof profiles.
+pub(super) struct PprofInfo {
+    pub start: SystemTime,
+    pub duration: Duration,
+    pub interval: Duration,
+}
+
+impl StackCounts {
+    /// Records one sample of `stack`. Samples where none of the profiled
+    /// modules were on the stack are not recorded.
+    pub fn add(&mut self, stack: impl Iterator<Item = StackFrame>, delta: Duration) {
+        let stack: Box<[StackFrame]> = stack.collect();
+        if stack.is_empty() {
+            return;
+        }
+        let counts = self.counts.entry(stack).or_default();
+        counts.samples += 1;
+        counts.cpu_nanos += u64::try_from(delta.as_nanos()).unwrap_or(u64::MAX);
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.clear();
+    }
+
+    /// Returns all stacks in a deterministic order.
+    fn sorted(&self) -> Vec<(&[StackFrame], SampleCounts)> {
+        let mut stacks: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(stack, counts)| (&stack[..], *counts))
+            .collect();
+        stacks.sort_unstable_by_key(|(stack, _)| *stack);
+        stacks
+    }
+
+    /// Writes these stacks in the "collapsed" (or "folded") format consumed
+    /// by flame graph tools such as `flamegraph.pl` and `inferno`: one line
+    /// per stack, with `module!function` frames separated by `;`, oldest
+    /// first, followed by a space and the number of samples.
+    pub fn write_collapsed(
+        &self,
+        modules: &[ProfiledModule],
+        mut output: impl std::io::Write,
+    ) -> Result<()> {
+        let mut names = HashMap::new();
+        for (stack, counts) in self.sorted() {
+            let mut line = String::new();
+            for (i, frame) in stack.iter().enumerate() {
+                if i > 0 {
+                    line.push(';');
+                }
+                let name = names.entry(*frame).or_insert_with(|| {
+                    let module = &modules[frame.module];
+                    let name = format!("{}!{}", module.name, func_name(module, frame.func));
+                    // `;` is the frame separator, so it can't appear in names.
+                    name.replace(';', ":")
+                });
+                line.push_str(name);
+            }
+            writeln!(output, "{line} {}", counts.samples)?;
+        }
+        Ok(())
+    }
+
+    /// Writes these stacks as an uncompressed [pprof] protobuf `Profile`.
+    ///
+    /// Each sample has two values: the number of samples taken and the CPU
+    /// time reported for them, in nanoseconds. Every profiled module is
+    /// described by a mapping whose addresses are offsets within the module's
+    /// text section, so no host addresses are revealed.
+    ///
+    /// [pprof]: https://github.com/google/pprof/blob/main/proto/profile.proto
+    pub fn write_pprof(
+        &self,
+        modules: &[ProfiledModule],
+        info: &PprofInfo,
+        mut output: impl std::io::Write,
+    ) -> Result<()> {
+        let mut strings = StringTable::new();
+        let mut profile = Message::default();
+
+        profile.message(1, &value_type(&mut strings, "samples", "count"));
+        profile.message(1, &value_type(&mut strings, "cpu", "nanoseconds"));
+
+        // Each function which appears in a stack gets a location, and a
+        // function, with the same 1-based id.
+        let mut locations = HashMap::new();
+        let mut used_modules = Vec::new();
+        for (stack, counts) in self.sorted() {
+            let mut sample = Message::default();
+            // pprof lists the newest frame first.
+            sample.packed(
+                1,
+                stack.iter().rev().map(|frame| {
+                    let next = locations.len() as u64 + 1;
+                    *locations.entry(*frame).or_insert_with(|| {
+                        used_modules.push(frame.module);
+                        next
+                    })
+                }),
+            );
+            sample.packed(2, [counts.samples, counts.cpu_nanos]);
+            profile.message(2, &sample);
+        }
+
+        used_modules.sort_unstable();
+        used_modules.dedup();
+        for &index in &used_modules {
+            let module = &modules[index];
+            let mut mapping = Message::default();
+            mapping.uint64(1, index as u64 + 1);
+            mapping.uint64(3, module.module.text().len() as u64);
+            mapping.int64(5, strings.intern(&module.name));
+            mapping.uint64(7, 1);
+            profile.message(3, &mapping);
+        }
+
+        let mut locations: Vec<_> = locations.into_iter().collect();
+        locations.sort_unstable_by_key(|(_, id)| *id);
+        for (frame, id) in locations {
+            let module = &modules[frame.module];
+            let compiled = module.module.compiled_module();
+
+            let mut line = Message::default();
+            line.uint64(1, id);
+            let mut location = Message::default();
+            location.uint64(1, id);
+            location.uint64(2, frame.module as u64 + 1);
+            location.uint64(3, u64::from(compiled.func_loc(frame.func).start));
+            location.message(4, &line);
+            profile.message(4, &location);
+
+            let name = strings.intern(&func_name(module, frame.func));
+            let mut function = Message::default();
+            function.uint64(1, id);
+            function.int64(2, name);
+            function.int64(3, name);
+            function.int64(4, strings.intern(&module.name));
+            profile.message(5, &function);
+        }
+
+        let period_type = value_type(&mut strings, "wall", "nanoseconds");
+        for s in strings.strings() {
+            profile.bytes(6, s.as_bytes());
+        }
+        let nanos = |d: Duration| i64::try_from(d.as_nanos()).unwrap_or(i64::MAX);
+        let start = info
+            .start
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        profile.int64(9, nanos(start));
+        profile.int64(10, nanos(info.duration));
+        profile.message(11, &period_type);
+        profile.int64(12, nanos(info.interval));
+
+        output.write_all(&profile.into_bytes())?;
+        Ok(())
+    }
+}
+
+/// Returns the demangled name of `func`, falling back to its index.
+pub(super) fn func_name(module: &ProfiledModule, func: DefinedFuncIndex) -> String {
+    let compiled = module.module.compiled_module();
+    let func_idx = compiled.module().func_index(func);
+    let mut name = String::new();
+    demangle_function_name_or_index(
+        &mut name,
+        compiled.func_name(func_idx),
+        func.as_u32() as usize,
+    )
+    .unwrap();
+    name
+}
@@ -0,0 +1,813 @@
+//! Deterministic record and replay of the nondeterministic inputs observed by
+//! a [`Store`](crate::Store).
+//!
+//! WebAssembly execution itself is deterministic (modulo a few well-known
+//! exceptions such as NaN bit patterns and relaxed SIMD, which
+//! [`Config::record_replay`](crate::Config::record_replay) pins down), so the
+//! only sources of nondeterminism in a guest's execution are the values that
+//! flow in from the outside world. A [`RecordReplay`] handle sits at each of
+//! those boundaries:
+//!
+//! * calls to host functions, both core wasm ones and component model ones,
+//! * results of `memory.grow`, which depend on the store's resource limiter,
+//! * epoch-deadline decisions and out-of-fuel refuels,
+//! * arbitrary embedder-provided inputs through [`RecordReplay::u64`] and
+//!   [`RecordReplay::bytes`].
+//!
+//! When recording, each input is appended to a trace. When replaying, each
+//! input is instead read back from the trace and handed to the guest in place
+//! of the live value, which reproduces the recorded execution bit-for-bit
+//! provided the same module or component is run with the same host functions
+//! defined.
+//!
+//! Host functions aren't called at all when replaying. Instead, everything
+//! that a call changed in the store's guest state is recorded: its results,
+//! the bytes it wrote to linear memories (including arguments, file contents
+//! and anything else read into guest buffers), memories it grew, globals it
+//! set and the store's remaining fuel. Replaying a call applies those effects
+//! again. This includes whatever wasm ran while the host function was on the
+//! stack, for example a component's `cabi_realloc`, which isn't run again
+//! when replaying either. A host call which fails is replayed as an error with
+//! the recorded message.
+//!
+//! Host calls whose effects can't be captured this way are rejected with an
+//! error, both when recording and when replaying:
+//!
+//! * host functions which return references,
+//! * component model host functions with resources, futures, streams or error
+//!   contexts in their signature, since their effects live in the host, and
+//!   async-lowered imports or concurrent host functions,
+//! * host calls which create instances, memories, tables or globals, or which
+//!   modify tables, reference-typed globals or the GC heap,
+//! * stores with shared memories.
+//!
+//! Finding out what a call changed takes a copy of every memory in the store
+//! before the call, so recording is considerably slower than a regular
+//! execution for guests with large memories.
+//!
+//! A trace is a short header followed by a sequence of length-prefixed,
+//! `postcard`-encoded [`TraceEvent`]s. Traces can be inspected with
+//! [`RecordReplay::read_events`].
+
+use crate::prelude::*;
+use crate::store::{GuestState, StoreOpaque};
+use crate::{FuncType, ValRaw, ValType};
+use core::fmt;
+use serde_derive::{Deserialize, Serialize};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Magic bytes at the start of every trace.
+const MAGIC: [u8; 8] = *b"\0wasmrr\0";
+
+/// Version of the trace format, bumped whenever `TraceEvent` changes.
+const FORMAT_VERSION: u32 = 2;
+
+/// A handle to a record/replay trace.
+///
+/// A handle is created with either [`RecordReplay::record`] or
+/// [`RecordReplay::replay`] and is then attached to a store with
+/// [`Store::set_record_replay`](crate::Store::set_record_replay). Handles are
+/// cheaply cloneable and all clones refer to the same trace, which allows
+/// embedders (and `wasmtime-wasi`) to route their own nondeterministic inputs
+/// through the same trace as the store.
+///
+/// When a replayed execution diverges from its trace, for example because a
+/// different module is being run, the divergence is reported as an error from
+/// the next host call or libcall which observes it, and also from
+/// [`RecordReplay::finish`].
+#[derive(Clone)]
+pub struct RecordReplay {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    mode: Mode,
+    /// The first divergence or I/O error encountered, if any. Once set no
+    /// further events are read or written.
+    error: Option<String>,
+    /// The number of host calls which are currently being recorded. Nothing
+    /// that happens during a recorded host call is recorded separately, since
+    /// it's part of the call's effects.
+    host_calls: u32,
+}
+
+enum Mode {
+    Record(BufWriter<Box<dyn Write + Send>>),
+    Replay(BufReader<Box<dyn Read + Send>>),
+}
+
+/// A single nondeterministic input recorded in a trace.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum TraceEvent {
+    /// A call to a host function.
+    HostCall(RecordedHostCall),
+    /// The result of a `memory.grow`: the previous size in pages, or `None` if
+    /// the growth failed.
+    MemoryGrow(Option<u64>),
+    /// The decision made when an epoch deadline was reached.
+    EpochDeadline(RecordedDeadline),
+    /// Whether the store could be refueled when wasm ran out of fuel.
+    OutOfFuel {
+        /// Whether fuel was injected from the store's reserve.
+        refueled: bool,
+    },
+    /// An integer provided by the embedder through [`RecordReplay::u64`].
+    U64(u64),
+    /// Bytes provided by the embedder through [`RecordReplay::bytes`].
+    Bytes(Vec<u8>),
+}
+
+/// A recorded call to a host function.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct RecordedHostCall {
+    /// The results that the call returned to wasm, or the message of the
+    /// error that it failed with.
+    ///
+    /// The results of component model host functions are their flat lowered
+    /// values, which are empty when the results were instead written to linear
+    /// memory.
+    pub results: Result<Vec<RecordedValue>, String>,
+    /// The memories which the call grew or wrote to.
+    pub memories: Vec<RecordedMemory>,
+    /// The globals which the call set.
+    pub globals: Vec<RecordedGlobal>,
+    /// The fuel remaining after the call, if fuel is enabled.
+    pub fuel: Option<u64>,
+}
+
+/// A memory modified by a recorded host call.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct RecordedMemory {
+    /// The position of the memory among all memories in the store, which are
+    /// ordered by the instance that defines them, with host-created memories
+    /// at the position at which they were created.
+    pub index: u32,
+    /// The size of the memory in bytes after the call.
+    pub size: u64,
+    /// The bytes that the call wrote, by offset. Bytes that the call wrote
+    /// with the value that they already had may be missing, and unmodified
+    /// bytes next to modified ones may be included.
+    pub writes: Vec<(u64, Vec<u8>)>,
+}
+
+/// A global set by a recorded host call.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub struct RecordedGlobal {
+    /// The position of the global among all globals in the store: first
+    /// host-created ones, then those defined by each instance in turn.
+    pub index: u32,
+    /// The raw bits of the global's new value.
+    pub bits: u128,
+}
+
+/// A recorded host function result.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum RecordedValue {
+    /// An `i32` result.
+    I32(u32),
+    /// An `i64` result.
+    I64(u64),
+    /// An `f32` result, as its bit pattern.
+    F32(u32),
+    /// An `f64` result, as its bit pattern.
+    F64(u64),
+    /// A `v128` result.
+    V128(u128),
+    /// A flat result of a component model host function, as the bits of the
+    /// core wasm value that it was lowered to.
+    Flat(u64),
+}
+
+/// A recorded epoch-deadline decision.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[non_exhaustive]
+pub enum RecordedDeadline {
+    /// Execution was interrupted with a trap.
+    Interrupt,
+    /// The epoch-deadline callback returned an error with this message.
+    Error(String),
+    /// Execution continued with the deadline extended by this many ticks.
+    Continue(u64),
+    /// Execution yielded and then continued with the deadline extended by
+    /// this many ticks.
+    Yield(u64),
+}
+
+impl RecordReplay {
+    /// Creates a handle which records a new trace into `sink`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trace header could not be written.
+    pub fn record(sink: impl Write + Send + 'static) -> Result<RecordReplay> {
+        let mut sink = BufWriter::new(Box::new(sink) as Box<dyn Write + Send>);
+        sink.write_all(&MAGIC)?;
+        sink.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        Ok(RecordReplay::new(Mode::Record(sink)))
+    }
+
+    /// Creates a handle which replays the trace read from `source`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `source` does not start with a valid trace header.
+    pub fn replay(source: impl Read + Send + 'static) -> Result<RecordReplay> {
+        let mut source = BufReader::new(Box::new(source) as Box<dyn Read + Send>);
+        read_header(&mut source)?;
+        Ok(RecordReplay::new(Mode::Replay(source)))
+    }
+
+    fn new(mode: Mode) -> RecordReplay {
+        RecordReplay {
+            inner: Arc::new(Mutex::new(Inner {
+                mode,
+                error: None,
+                host_calls: 0,
+            })),
+        }
+    }
+
+    /// Reads all events from the trace in `source`.
+    ///
+    /// This is intended for inspecting and debugging traces.
+    pub fn read_events(source: impl Read) -> Result<Vec<TraceEvent>> {
+        let mut source = BufReader::new(source);
+        read_header(&mut source)?;
+        let mut events = Vec::new();
+        while let Some(event) = read_event(&mut source)? {
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    /// Returns whether this handle is recording a trace.
+    pub fn is_recording(&self) -> bool {
+        matches!(self.lock().mode, Mode::Record(_))
+    }
+
+    /// Returns whether this handle is replaying a trace.
+    pub fn is_replaying(&self) -> bool {
+        matches!(self.lock().mode, Mode::Replay(_))
+    }
+
+    /// Routes a nondeterministic integer through the trace.
+    ///
+    /// When recording, `live` is called and its result is recorded and
+    /// returned. When replaying the recorded value is returned instead and
+    /// `live` is not called.
+    ///
+    /// If the trace cannot be written or the replayed execution has diverged
+    /// then the live value is returned and the error is reported later, see
+    /// [`RecordReplay::check`].
+    pub fn u64(&self, live: impl FnOnce() -> u64) -> u64 {
+        let mut inner = self.lock();
+        if inner.is_replaying() {
+            match inner.replay_event("an integer input") {
+                Some(TraceEvent::U64(value)) => return value,
+                Some(event) => inner.diverged(format_args!("expected an integer, found {event:?}")),
+                None => {}
+            }
+            return live();
+        }
+        let value = live();
+        inner.record_event(&TraceEvent::U64(value));
+        value
+    }
+
+    /// Routes nondeterministic bytes through the trace.
+    ///
+    /// When recording, `live` is called to fill `buf` and its contents are
+    /// recorded. When replaying `buf` is filled from the trace instead and
+    /// `live` is not called.
+    ///
+    /// Errors are handled in the same way as [`RecordReplay::u64`].
+    pub fn bytes(&self, buf: &mut [u8], live: impl FnOnce(&mut [u8])) {
+        let mut inner = self.lock();
+        if inner.is_replaying() {
+            match inner.replay_event("a byte input") {
+                Some(TraceEvent::Bytes(bytes)) if bytes.len() == buf.len() => {
+                    buf.copy_from_slice(&bytes);
+                    return;
+                }
+                Some(event) => inner.diverged(format_args!(
+                    "expected {} bytes, found {event:?}",
+                    buf.len()
+                )),
+                None => {}
+            }
+            return live(buf);
+        }
+        live(buf);
+        inner.record_event(&TraceEvent::Bytes(buf.to_vec()));
+    }
+
+    /// Returns an error if recording failed or the replayed execution has
+    /// diverged from its trace.
+    pub fn check(&self) -> Result<()> {
+        self.lock().check()
+    }
+
+    /// Finishes this trace.
+    ///
+    /// When recording this flushes the trace to its sink. When replaying this
+    /// checks that the whole trace was consumed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if recording failed, if the replayed execution
+    /// diverged from its trace, or if the replayed execution ended before the
+    /// end of its trace.
+    pub fn finish(&self) -> Result<()> {
+        let mut inner = self.lock();
+        inner.check()?;
+        match &mut inner.mode {
+            Mode::Record(sink) => sink.flush()?,
+            Mode::Replay(source) => {
+                if let Some(event) = read_event(source)? {
+                    bail!("replay ended before the end of the trace, next event: {event:?}");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Called when wasm calls a core wasm host function of type `ty`, after
+    /// the [`CallHook::CallingHost`](crate::CallHook::CallingHost) hook.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the trace already failed, or if the host function
+    /// returns references, which can't be replayed.
+    pub(crate) fn enter_host_call(
+        &self,
+        store: &mut StoreOpaque,
+        ty: &FuncType,
+    ) -> Result<HostCall> {
+        ensure!(
+            !ty.results().any(|ty| ty.is_ref()),
+            "record/replay does not support host functions which return references"
+        );
+        self.enter(store)
+    }
+
+    /// Same as [`RecordReplay::enter_host_call`], but for component model host
+    /// functions, whose signature is checked by the caller.
+    #[cfg(feature = "component-model")]
+    pub(crate) fn enter_component_host_call(&self, store: &mut StoreOpaque) -> Result<HostCall> {
+        self.enter(store)
+    }
+
+    fn enter(&self, store: &mut StoreOpaque) -> Result<HostCall> {
+        {
+            let inner = self.lock();
+            inner.check()?;
+            if inner.is_replaying() {
+                return Ok(HostCall::Replay);
+            }
+            if inner.host_calls > 0 {
+                return Ok(HostCall::Nested);
+            }
+        }
+        let before = match store.capture_guest_state() {
+            Ok(before) => before,
+            Err(e) => return Err(self.lock().fail(e)),
+        };
+        self.lock().host_calls += 1;
+        Ok(HostCall::Record(HostCallRecorder {
+            rr: self.clone(),
+            before,
+        }))
+    }
+
+    /// Replays a call to a core wasm host function of type `ty`: applies the
+    /// call's recorded effects to `store` and writes its recorded results to
+    /// `results`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the replayed execution diverged from its trace,
+    /// or the recorded error if the call failed.
+    pub(crate) fn replay_host_call(
+        &self,
+        store: &mut StoreOpaque,
+        ty: &FuncType,
+        results: &mut [ValRaw],
+    ) -> Result<()> {
+        let recorded = self.replay_call(store)?;
+        let mut inner = self.lock();
+        if recorded.len() != results.len() {
+            inner.diverged(format_args!(
+                "host function returned {} results but {} were recorded",
+                results.len(),
+                recorded.len()
+            ));
+            return inner.check();
+        }
+        for ((result_ty, raw), recorded) in ty.results().zip(results.iter_mut()).zip(recorded) {
+            *raw = match (result_ty, recorded) {
+                (ValType::I32, RecordedValue::I32(i)) => ValRaw::u32(i),
+                (ValType::I64, RecordedValue::I64(i)) => ValRaw::u64(i),
+                (ValType::F32, RecordedValue::F32(i)) => ValRaw::f32(i),
+                (ValType::F64, RecordedValue::F64(i)) => ValRaw::f64(i),
+                (ValType::V128, RecordedValue::V128(i)) => ValRaw::v128(i),
+                (result_ty, recorded) => {
+                    inner.diverged(format_args!(
+                        "host function returned a `{result_ty}` but {recorded:?} was recorded"
+                    ));
+                    return inner.check();
+                }
+            };
+        }
+        Ok(())
+    }
+
+    /// Same as [`RecordReplay::replay_host_call`], but for component model
+    /// host functions, whose flat results are written to `results`.
+    #[cfg(feature = "component-model")]
+    pub(crate) fn replay_component_host_call(
+        &self,
+        store: &mut StoreOpaque,
+        results: &mut [core::mem::MaybeUninit<ValRaw>],
+    ) -> Result<()> {
+        let recorded = self.replay_call(store)?;
+        let mut inner = self.lock();
+        if recorded.len() != results.len() {
+            inner.diverged(format_args!(
+                "host function returned {} flat results but {} were recorded",
+                results.len(),
+                recorded.len()
+            ));
+            return inner.check();
+        }
+        for (raw, recorded) in results.iter_mut().zip(recorded) {
+            match recorded {
+                RecordedValue::Flat(bits) => {
+                    raw.write(ValRaw::u64(bits));
+                }
+                recorded => {
+                    inner.diverged(format_args!("expected a flat result, found {recorded:?}"));
+                    return inner.check();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the next host call from the trace and applies its effects to
+    /// `store`, returning its results.
+    fn replay_call(&self, store: &mut StoreOpaque) -> Result<Vec<RecordedValue>> {
+        let call = {
+            let mut inner = self.lock();
+            match inner.replay_event("a host call") {
+                Some(TraceEvent::HostCall(call)) => call,
+                Some(event) => {
+                    inner.diverged(format_args!("expected a host call, found {event:?}"));
+                    return Err(inner.error());
+                }
+                None => return Err(inner.error()),
+            }
+        };
+        if let Err(e) = store.apply_host_call(&call) {
+            let mut inner = self.lock();
+            inner.diverged(format_args!("{e:#}"));
+            return Err(inner.error());
+        }
+        call.results.map_err(|msg| anyhow!(msg))
+    }
+
+    /// Returns the recorded result of the next `memory.grow` when replaying,
+    /// or `None` when recording.
+    pub(crate) fn replay_memory_grow(&self) -> Result<Option<Option<u64>>> {
+        let mut inner = self.lock();
+        match inner.replay_event("a `memory.grow` result") {
+            Some(TraceEvent::MemoryGrow(result)) => return Ok(Some(result)),
+            Some(event) => inner.diverged(format_args!(
+                "expected a `memory.grow` result, found {event:?}"
+            )),
+            None => {}
+        }
+        inner.check()?;
+        Ok(None)
+    }
+
+    /// Records the result of a `memory.grow`. Does nothing when replaying.
+    pub(crate) fn record_memory_grow(&self, result: Option<u64>) -> Result<()> {
+        let mut inner = self.lock();
+        inner.record_event(&TraceEvent::MemoryGrow(result));
+        inner.check()
+    }
+
+    /// Records the epoch-deadline decision made by `live`, or returns the
+    /// recorded decision without calling `live` when replaying.
+    #[cfg(target_has_atomic = "64")]
+    pub(crate) fn epoch_deadline(
+        &self,
+        live: impl FnOnce() -> Result<crate::UpdateDeadline>,
+    ) -> Result<crate::UpdateDeadline> {
+        use crate::UpdateDeadline;
+
+        if self.is_replaying() {
+            let mut inner = self.lock();
+            let deadline = match inner.replay_event("an epoch deadline") {
+                Some(TraceEvent::EpochDeadline(deadline)) => deadline,
+                Some(event) => {
+                    inner.diverged(format_args!("expected an epoch deadline, found {event:?}"));
+                    return Err(inner.error());
+                }
+                None => return Err(inner.error()),
+            };
+            return match deadline {
+                RecordedDeadline::Interrupt => Ok(UpdateDeadline::Interrupt),
+                RecordedDeadline::Error(msg) => Err(anyhow!(msg)),
+                RecordedDeadline::Continue(delta) => Ok(UpdateDeadline::Continue(delta)),
+                #[cfg(feature = "async")]
+                RecordedDeadline::Yield(delta) => Ok(UpdateDeadline::Yield(delta)),
+                #[cfg(not(feature = "async"))]
+                RecordedDeadline::Yield(delta) => Ok(UpdateDeadline::Continue(delta)),
+            };
+        }
+
+        // Note that the lock isn't held while `live` runs since it may call
+        // back into the store, and from there into this handle.
+        let update = live();
+        let deadline = match &update {
+            Ok(UpdateDeadline::Interrupt) => RecordedDeadline::Interrupt,
+            Ok(UpdateDeadline::Continue(delta)) => RecordedDeadline::Continue(*delta),
+            #[cfg(feature = "async")]
+            Ok(UpdateDeadline::Yield(delta) | UpdateDeadline::YieldCustom(delta, _)) => {
+                RecordedDeadline::Yield(*delta)
+            }
+            Err(e) => RecordedDeadline::Error(format!("{e:#}")),
+        };
+        let mut inner = self.lock();
+        inner.record_event(&TraceEvent::EpochDeadline(deadline));
+        inner.check()?;
+        update
+    }
+
+    /// Records whether the store could be refueled, or checks that it matches
+    /// the recorded trace when replaying.
+    pub(crate) fn out_of_fuel(&self, refueled: bool) -> Result<()> {
+        let mut inner = self.lock();
+        if !inner.is_replaying() {
+            inner.record_event(&TraceEvent::OutOfFuel { refueled });
+            return inner.check();
+        }
+        match inner.replay_event("an out-of-fuel event") {
+            Some(TraceEvent::OutOfFuel { refueled: r }) if r == refueled => {}
+            Some(event) => inner.diverged(format_args!(
+                "expected an out-of-fuel event with `refueled: {refueled}`, found {event:?}"
+            )),
+            None => {}
+        }
+        inner.check()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // A panic while holding the lock can't leave the trace in an
+        // inconsistent state beyond a partially written event, which is
+        // reported when the trace is read, so ignore poisoning.
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// How a host call is handled in a store with a [`RecordReplay`] trace, see
+/// [`RecordReplay::enter_host_call`].
+pub(crate) enum HostCall {
+    /// The host function is called and its effects are then recorded with
+    /// [`HostCallRecorder::exit`].
+    Record(HostCallRecorder),
+    /// The host function isn't called. Instead its effects are replayed with
+    /// [`RecordReplay::replay_host_call`].
+    Replay,
+    /// The host function is called without recording anything, since it's
+    /// called during another recorded host call whose effects include its
+    /// own.
+    Nested,
+}
+
+/// A host call which is being recorded.
+pub(crate) struct HostCallRecorder {
+    rr: RecordReplay,
+    before: GuestState,
+}
+
+impl HostCallRecorder {
+    /// Records the effects of a call to a core wasm host function of type
+    /// `ty`, which returned `results`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the call's effects can't be recorded or recording
+    /// failed.
+    pub(crate) fn exit(
+        self,
+        store: &mut StoreOpaque,
+        ty: &FuncType,
+        results: Result<&[ValRaw], &Error>,
+    ) -> Result<()> {
+        let results = results
+            .map(|results| {
+                ty.results()
+                    .zip(results)
+                    .map(|(ty, raw)| match ty {
+                        ValType::I32 => RecordedValue::I32(raw.get_u32()),
+                        ValType::I64 => RecordedValue::I64(raw.get_u64()),
+                        ValType::F32 => RecordedValue::F32(raw.get_f32()),
+                        ValType::F64 => RecordedValue::F64(raw.get_f64()),
+                        ValType::V128 => RecordedValue::V128(raw.get_v128()),
+                        ValType::Ref(_) => unreachable!("checked in `enter_host_call`"),
+                    })
+                    .collect()
+            })
+            .map_err(|e| format!("{e:#}"));
+        self.finish(store, results)
+    }
+
+    /// Same as [`HostCallRecorder::exit`], but for component model host
+    /// functions, which returned the flat `results`.
+    #[cfg(feature = "component-model")]
+    pub(crate) fn exit_component(
+        self,
+        store: &mut StoreOpaque,
+        results: Result<&[ValRaw], &Error>,
+    ) -> Result<()> {
+        let results = results
+            .map(|results| {
+                results
+                    .iter()
+                    .map(|raw| RecordedValue::Flat(raw.get_u64()))
+                    .collect()
+            })
+            .map_err(|e| format!("{e:#}"));
+        self.finish(store, results)
+    }
+
+    fn finish(
+        self,
+        store: &mut StoreOpaque,
+        results: Result<Vec<RecordedValue>, String>,
+    ) -> Result<()> {
+        let effects = store.host_call_effects(&self.before, results);
+        let rr = self.rr.clone();
+        // Stop suppressing events before recording this one.
+        drop(self);
+        let mut inner = rr.lock();
+        match effects {
+            Ok(call) => inner.record_event(&TraceEvent::HostCall(call)),
+            Err(e) => return Err(inner.fail(e)),
+        }
+        inner.check()
+    }
+}
+
+impl Drop for HostCallRecorder {
+    fn drop(&mut self) {
+        self.rr.lock().host_calls -= 1;
+    }
+}
+
+impl fmt::Debug for RecordReplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let inner = self.lock();
+        let mode = match inner.mode {
+            Mode::Record(_) => "record",
+            Mode::Replay(_) => "replay",
+        };
+        f.debug_struct("RecordReplay")
+            .field("mode", &mode)
+            .field("error", &inner.error)
+            .finish()
+    }
+}
+
+impl Inner {
+    fn is_replaying(&self) -> bool {
+        matches!(self.mode, Mode::Replay(_))
+    }
+
+    fn check(&self) -> Result<()> {
+        match &self.error {
+            Some(_) => Err(self.error()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the error which stopped this trace.
+    ///
+    /// Only called once an error has happened: when replaying that's always
+    /// the case after `replay_event` returned `None`.
+    fn error(&self) -> Error {
+        debug_assert!(self.error.is_some());
+        anyhow!(
+            "{}",
+            self.error.as_deref().unwrap_or("record/replay failed")
+        )
+    }
+
+    fn diverged(&mut self, msg: fmt::Arguments<'_>) {
+        if self.error.is_none() {
+            self.error = Some(format!("replay diverged from the recorded trace: {msg}"));
+        }
+    }
+
+    /// Stops this trace because a host call couldn't be recorded or replayed,
+    /// returning the error which stopped it.
+    fn fail(&mut self, e: Error) -> Error {
+        if self.error.is_none() {
+            self.error = Some(format!("{e:#}"));
+        }
+        self.error()
+    }
+
+    /// Appends `event` to the trace if recording, no error has happened yet,
+    /// and no host call is being recorded.
+    fn record_event(&mut self, event: &TraceEvent) {
+        if self.error.is_some() || self.host_calls > 0 {
+            return;
+        }
+        if let Mode::Record(sink) = &mut self.mode {
+            if let Err(e) = write_event(sink, event) {
+                self.error = Some(format!("failed to record trace event: {e:#}"));
+            }
+        }
+    }
+
+    /// Reads the next event from the trace if replaying and no error has
+    /// happened yet.
+    ///
+    /// Returns `None` when recording, after an error, or when the trace ended,
+    /// in which case an error describing `expected` is stored.
+    fn replay_event(&mut self, expected: &str) -> Option<TraceEvent> {
+        if self.error.is_some() {
+            return None;
+        }
+        let Mode::Replay(source) = &mut self.mode else {
+            return None;
+        };
+        match read_event(source) {
+            Ok(Some(event)) => Some(event),
+            Ok(None) => {
+                self.diverged(format_args!(
+                    "expected {expected}, found the end of the trace"
+                ));
+                None
+            }
+            Err(e) => {
+                self.error = Some(format!("failed to read trace event: {e:#}"));
+                None
+            }
+        }
+    }
+}
+
+fn read_header(source: &mut impl Read) -> Result<()> {
+    let mut magic = [0; MAGIC.len()];
+    source
+        .read_exact(&mut magic)
+        .context("failed to read trace header")?;
+    ensure!(magic == MAGIC, "not a record/replay trace");
+    let mut version = [0; 4];
+    source
+        .read_exact(&mut version)
+        .context("failed to read trace header")?;
+    let version = u32::from_le_bytes(version);
+    ensure!(
+        version == FORMAT_VERSION,
+        "unsupported trace format version {version}, expected {FORMAT_VERSION}"
+    );
+    Ok(())
+}
+
+fn write_event(sink: &mut impl Write, event: &TraceEvent) -> Result<()> {
+    let bytes = postcard::to_allocvec(event)?;
+    let len = u32::try_from(bytes.len())?;
+    sink.write_all(&len.to_le_bytes())?;
+    sink.write_all(&bytes)?;
+    Ok(())
+}
+
+fn read_event(source: &mut impl Read) -> Result<Option<TraceEvent>> {
+    let mut len = [0; 4];
+    match source.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    // Don't trust the length to allocate the event's bytes up front: a
+    // truncated or corrupted trace could claim up to 4GiB.
+    let len = u64::from(u32::from_le_bytes(len));
+    let mut bytes = Vec::new();
+    source.take(len).read_to_end(&mut bytes)?;
+    ensure!(
+        bytes.len() as u64 == len,
+        "trace ended in the middle of an event"
+    );
+    Ok(Some(postcard::from_bytes(&bytes)?))
+}
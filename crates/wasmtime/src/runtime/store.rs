@@ -125,6 +125,21 @@ mod data;
 pub use self::data::*;
 mod func_refs;
 use func_refs::FuncRefs;
+mod snapshot;
+pub use self::snapshot::StoreSnapshot;
+#[cfg(feature = "rr")]
+mod rr;
+#[cfg(feature = "rr")]
+pub(crate) use self::rr::GuestState;
+mod instruction_counts;
+use self::instruction_counts::InstructionCounters;
+pub use self::instruction_counts::{
+    BlockInstructionCount, FunctionInstructionCounts, InstructionCounts, ModuleInstructionCounts,
+};
+mod usage;
+pub use self::usage::StoreUsage;
+use self::usage::UsageAccounting;
+mod park;
 #[cfg(feature = "component-model-async")]
 mod token;
 #[cfg(feature = "component-model-async")]
@@ -353,6 +368,14 @@ impl StoreResourceLimiter<'_> {
         }
     }
 
+    pub(crate) fn memory_budget(&self) -> Option<&crate::MemoryBudget> {
+        match self {
+            Self::Sync(s) => s.memory_budget(),
+            #[cfg(feature = "async")]
+            Self::Async(s) => s.memory_budget(),
+        }
+    }
+
     pub(crate) async fn table_growing(
         &mut self,
         current: usize,
@@ -483,6 +506,8 @@ pub struct StoreOpaque {
     gc_roots: RootSet,
     #[cfg(feature = "gc")]
     gc_roots_list: GcRootsList,
+    #[cfg(feature = "gc")]
+    gc_stats: crate::GcStats,
     // Types for which the embedder has created an allocator for.
     #[cfg(feature = "gc")]
     gc_host_alloc_types: crate::hash_set::HashSet<crate::type_registry::RegisteredType>,
@@ -564,6 +589,27 @@ pub struct StoreOpaque {
     /// logic before returning to allow execution to resume.
     #[cfg(feature = "debug")]
     breakpoints: BreakpointState,
+
+    /// Per-function and per-basic-block instruction counters, populated as
+    /// modules are instantiated when instruction counting is enabled.
+    instruction_counters: InstructionCounters,
+
+    /// Host-call and timing counters for [`Store::usage`], present only while
+    /// usage accounting is enabled.
+    usage_accounting: Option<Box<UsageAccounting>>,
+
+    /// Whether any instance's memories and tables are parked, see
+    /// [`Store::park`].
+    parked: bool,
+
+    /// Whether instances are being recreated by [`StoreSnapshot::fork`], in
+    /// which case they're allocated without being initialized or started.
+    forking: bool,
+
+    /// The record/replay trace that this store's nondeterministic inputs are
+    /// routed through, if any.
+    #[cfg(feature = "rr")]
+    record_replay: Option<crate::RecordReplay>,
 }
 
 /// Self-pointer to `StoreInner<T>` from within a `StoreOpaque` which is chiefly
@@ -741,6 +787,8 @@ impl<T> Store<T> {
             #[cfg(feature = "gc")]
             gc_roots_list: GcRootsList::default(),
             #[cfg(feature = "gc")]
+            gc_stats: crate::GcStats::default(),
+            #[cfg(feature = "gc")]
             gc_host_alloc_types: Default::default(),
             #[cfg(feature = "gc")]
             pending_exception: None,
@@ -774,6 +822,12 @@ impl<T> Store<T> {
             concurrent_state: Default::default(),
             #[cfg(feature = "debug")]
             breakpoints: Default::default(),
+            instruction_counters: Default::default(),
+            usage_accounting: None,
+            parked: false,
+            forking: false,
+            #[cfg(feature = "rr")]
+            record_replay: None,
         };
         let mut inner = Box::new(StoreInner {
             inner,
@@ -1011,6 +1065,18 @@ impl<T> Store<T> {
         StoreContextMut(&mut self.inner).gc(why)
     }
 
+    /// Get statistics about the garbage collections performed within this
+    /// store, such as how many bytes they have reclaimed.
+    ///
+    /// This includes both explicit collections, via [`Store::gc`] and
+    /// similar, and collections triggered automatically by allocation.
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_stats(&self) -> crate::GcStats {
+        self.inner.gc_stats()
+    }
+
     /// Returns the amount fuel in this [`Store`]. When fuel is enabled, it must
     /// be configured via [`Store::set_fuel`].
     ///
@@ -1077,6 +1143,43 @@ impl<T> Store<T> {
         self.inner.fuel_async_yield_interval(interval)
     }
 
+    /// Routes this store's nondeterministic inputs through the record/replay
+    /// trace `rr`.
+    ///
+    /// When `rr` is recording, the effects of host calls, the results of
+    /// `memory.grow`, epoch deadlines and fuel refuels observed by this store
+    /// are recorded to the trace. When `rr` is replaying, those inputs are
+    /// instead taken from the trace and host functions aren't called at all.
+    /// See [`RecordReplay`](crate::RecordReplay) for more information,
+    /// including which host calls can't be recorded.
+    ///
+    /// # Errors
+    ///
+    /// This method will error if record/replay was not enabled with
+    /// [`Config::record_replay`](crate::Config::record_replay), or if anything
+    /// has already been instantiated within this store, since its inputs would
+    /// be missing from the trace.
+    #[cfg(feature = "rr")]
+    pub fn set_record_replay(&mut self, rr: crate::RecordReplay) -> Result<()> {
+        ensure!(
+            self.engine().config().record_replay,
+            "record/replay is not enabled in this store's config"
+        );
+        ensure!(
+            self.inner.instance_count == 0,
+            "record/replay must be configured before anything is instantiated"
+        );
+        self.inner.record_replay = Some(rr);
+        Ok(())
+    }
+
+    /// Returns the record/replay trace configured with
+    /// [`Store::set_record_replay`], if any.
+    #[cfg(feature = "rr")]
+    pub fn record_replay(&self) -> Option<&crate::RecordReplay> {
+        self.inner.record_replay()
+    }
+
     /// Sets the epoch deadline to a certain number of ticks in the future.
     ///
     /// When the Wasm guest code is compiled with epoch-interruption
@@ -1336,6 +1439,17 @@ impl<'a, T> StoreContext<'a, T> {
         self.0.engine()
     }
 
+    /// Get statistics about the garbage collections performed within this
+    /// store.
+    ///
+    /// Same as [`Store::gc_stats`].
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_stats(&self) -> crate::GcStats {
+        self.0.gc_stats()
+    }
+
     /// Access the underlying data owned by this `Store`.
     ///
     /// Same as [`Store::data`].
@@ -1383,6 +1497,17 @@ impl<'a, T> StoreContextMut<'a, T> {
         vm::assert_ready(store.gc(limiter.as_mut(), None, why.map(|e| e.bytes_needed())));
     }
 
+    /// Get statistics about the garbage collections performed within this
+    /// store.
+    ///
+    /// Same as [`Store::gc_stats`].
+    ///
+    /// This method is only available when the `gc` Cargo feature is enabled.
+    #[cfg(feature = "gc")]
+    pub fn gc_stats(&self) -> crate::GcStats {
+        self.0.gc_stats()
+    }
+
     /// Returns remaining fuel in this store.
     ///
     /// For more information see [`Store::get_fuel`]
@@ -1498,7 +1623,10 @@ impl<T> StoreInner<T> {
 
     #[inline]
     pub fn call_hook(&mut self, s: CallHook) -> Result<()> {
-        if self.inner.pkey.is_none() && self.call_hook.is_none() {
+        if self.inner.pkey.is_none()
+            && self.call_hook.is_none()
+            && self.inner.usage_accounting.is_none()
+        {
             Ok(())
         } else {
             self.call_hook_slow_path(s)
@@ -1518,13 +1646,24 @@ impl<T> StoreInner<T> {
 
         // Temporarily take the configured behavior to avoid mutably borrowing
         // multiple times.
-        if let Some(mut call_hook) = self.call_hook.take() {
-            let result = self.invoke_call_hook(&mut call_hook, s);
-            self.call_hook = Some(call_hook);
-            return result;
+        let result = match self.call_hook.take() {
+            Some(mut call_hook) => {
+                let result = self.invoke_call_hook(&mut call_hook, s);
+                self.call_hook = Some(call_hook);
+                result
+            }
+            None => Ok(()),
+        };
+
+        // A failed hook when calling into wasm or the host means that the call
+        // doesn't happen, so only record transitions that actually happen.
+        if let Some(accounting) = &mut self.inner.usage_accounting {
+            if result.is_ok() || !matches!(s, CallHook::CallingWasm | CallHook::CallingHost) {
+                accounting.transition(s);
+            }
         }
 
-        Ok(())
+        result
     }
 
     fn invoke_call_hook(&mut self, call_hook: &mut CallHookInner<T>, s: CallHook) -> Result<()> {
@@ -2033,6 +2172,18 @@ impl StoreOpaque {
         &self.gc_roots
     }
 
+    #[inline]
+    #[cfg(feature = "gc")]
+    pub(crate) fn gc_stats(&self) -> crate::GcStats {
+        self.gc_stats
+    }
+
+    #[inline]
+    #[cfg(feature = "rr")]
+    pub(crate) fn record_replay(&self) -> Option<&crate::RecordReplay> {
+        self.record_replay.as_ref()
+    }
+
     #[inline]
     #[cfg(feature = "gc")]
     pub(crate) fn gc_roots_mut(&mut self) -> &mut RootSet {
@@ -2059,9 +2210,14 @@ impl StoreOpaque {
 
         self.trace_roots(&mut roots).await;
         let async_yield = self.async_support();
-        self.unwrap_gc_store_mut()
+        let bytes_reclaimed = self
+            .unwrap_gc_store_mut()
             .gc(async_yield, unsafe { roots.iter() })
             .await;
+        let bytes_reclaimed = u64::try_from(bytes_reclaimed).unwrap();
+        self.gc_stats.collections += 1;
+        self.gc_stats.bytes_reclaimed += bytes_reclaimed;
+        self.gc_stats.last_bytes_reclaimed = bytes_reclaimed;
 
         // Restore the GC roots for the next GC.
         roots.clear();
@@ -2652,6 +2808,14 @@ at https://bytecodealliance.org/security.
     ) -> Result<InstanceId> {
         let id = self.instances.next_key();
 
+        // Counters must exist before the instance is allocated since its
+        // `VMContext` is initialized with a pointer to them.
+        if self.engine().tunables().count_instructions
+            && let ModuleRuntimeInfo::Module(module) = runtime_info
+        {
+            self.instruction_counters.register(module);
+        }
+
         let allocator = match kind {
             AllocateInstanceKind::Module(_) => self.engine().allocator(),
             AllocateInstanceKind::Dummy { allocator } => allocator,
@@ -2697,6 +2861,29 @@ at https://bytecodealliance.org/security.
         // prediction of what the id would be is indeed the id it should be.
         assert_eq!(id, actual);
 
+        // Wasm calls between functions of a module compiled for tiering go
+        // through the callee's `VMFuncRef`, so all of them are initialized
+        // up-front and the instance is registered for them to be updated when
+        // functions are tiered up.
+        #[cfg(feature = "winch")]
+        if let ModuleRuntimeInfo::Module(module) = runtime_info
+            && let Some(tiering) = module.tiering()
+        {
+            let env_module = module.env_module();
+            let (mut instance, registry) = self.instance_and_module_registry_mut(id);
+            for i in env_module.num_imported_funcs..env_module.functions.len() {
+                instance
+                    .as_mut()
+                    .get_func_ref(registry, wasmtime_environ::FuncIndex::new(i));
+            }
+            // SAFETY: all the `VMFuncRef`s of defined functions were just
+            // initialized, and the instance is unregistered when it's
+            // deallocated.
+            unsafe {
+                tiering.register_instance(instance.vmctx());
+            }
+        }
+
         Ok(id)
     }
 
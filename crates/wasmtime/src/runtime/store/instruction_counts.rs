@@ -0,0 +1,328 @@
+//! Per-store instruction counters, see [`Store::instruction_counts`].
+
+use super::*;
+use crate::hash_map::HashMap;
+use crate::runtime::vm::{CompiledModuleId, VmPtr};
+use core::cell::UnsafeCell;
+use object::LittleEndian;
+use wasmtime_environ::{DefinedFuncIndex, demangle_function_name_or_index};
+
+/// The instruction counters of all modules instantiated within a store.
+///
+/// Compiled code reaches a function's counters through the table of
+/// per-function counter pointers which is stored in the `VMContext` of each
+/// instance, see `InstructionCounters::table`.
+#[derive(Default)]
+pub(super) struct InstructionCounters {
+    modules: Vec<ModuleCounters>,
+    by_id: HashMap<CompiledModuleId, usize>,
+}
+
+struct ModuleCounters {
+    module: Module,
+    funcs: PrimaryMap<DefinedFuncIndex, FuncCounters>,
+    /// A pointer to each of `funcs`' counters, indexed by
+    /// `DefinedFuncIndex`. This is what compiled code loads from.
+    table: Box<[VmPtr<u64>]>,
+}
+
+#[derive(Default)]
+struct FuncCounters {
+    /// The Wasm PC at which each counter's straight-line run of code starts.
+    pcs: Vec<u32>,
+    /// The counters themselves, which are written by compiled code.
+    counts: Box<[UnsafeCell<u64>]>,
+}
+
+// The counters are only ever written by compiled code running within the
+// owning store, and only read through `&Store` or reset through
+// `&mut Store`, so like `VMStoreContext` these are safe to send and share
+// across threads.
+unsafe impl Send for FuncCounters {}
+unsafe impl Sync for FuncCounters {}
+
+impl InstructionCounters {
+    /// Allocates counters for `module` unless they already exist.
+    pub(super) fn register(&mut self, module: &Module) {
+        if self.by_id.contains_key(&module.id()) {
+            return;
+        }
+        let Some(table) = module.instruction_count_table() else {
+            return;
+        };
+
+        // Component artifacts contain the functions of all their core
+        // modules, so only pick out those belonging to this one.
+        let env_module = module.env_module();
+        let mut funcs = PrimaryMap::with_capacity(env_module.num_defined_funcs());
+        for _ in 0..env_module.num_defined_funcs() {
+            funcs.push(FuncCounters::default());
+        }
+        for (key, pcs) in table.functions() {
+            let (module_index, func) = key.unwrap_defined_wasm_function();
+            if module_index != env_module.module_index {
+                continue;
+            }
+            funcs[func] = FuncCounters {
+                pcs: pcs.iter().map(|pc| pc.get(LittleEndian)).collect(),
+                counts: pcs.iter().map(|_| UnsafeCell::new(0)).collect(),
+            };
+        }
+        let table = funcs
+            .values()
+            .map(|f| VmPtr::from(NonNull::from(&f.counts[..]).cast::<u64>()))
+            .collect();
+
+        self.by_id.insert(module.id(), self.modules.len());
+        self.modules.push(ModuleCounters {
+            module: module.clone(),
+            funcs,
+            table,
+        });
+    }
+
+    /// Returns the counter-pointer table for the module `id`, if it was
+    /// registered.
+    pub(super) fn table(&self, id: CompiledModuleId) -> Option<NonNull<VmPtr<u64>>> {
+        let module = &self.modules[*self.by_id.get(&id)?];
+        Some(NonNull::from(&module.table[..]).cast())
+    }
+
+    fn read(&self) -> InstructionCounts {
+        let modules = self
+            .modules
+            .iter()
+            .map(|m| {
+                let compiled = m.module.compiled_module();
+                let functions = m
+                    .funcs
+                    .iter()
+                    .map(|(index, f)| {
+                        let func_index = compiled.module().func_index(index);
+                        let mut name = String::new();
+                        demangle_function_name_or_index(
+                            &mut name,
+                            compiled.func_name(func_index),
+                            func_index.as_u32() as usize,
+                        )
+                        .unwrap();
+                        let blocks = f
+                            .pcs
+                            .iter()
+                            .zip(f.counts.iter())
+                            .map(|(&pc, count)| BlockInstructionCount {
+                                module_offset: pc,
+                                // SAFETY: wasm isn't running while the store is
+                                // borrowed here, so nothing is concurrently
+                                // writing this counter.
+                                count: unsafe { *count.get() },
+                            })
+                            .collect();
+                        FunctionInstructionCounts {
+                            index: func_index.as_u32(),
+                            name,
+                            blocks,
+                        }
+                    })
+                    .collect();
+                ModuleInstructionCounts {
+                    module: m.module.clone(),
+                    functions,
+                }
+            })
+            .collect();
+        InstructionCounts { modules }
+    }
+
+    fn reset(&mut self) {
+        for module in self.modules.iter_mut() {
+            for func in module.funcs.values_mut() {
+                for count in func.counts.iter_mut() {
+                    *count.get_mut() = 0;
+                }
+            }
+        }
+    }
+}
+
+/// A snapshot of the instruction counters of a [`Store`], see
+/// [`Store::instruction_counts`].
+///
+/// The [`Display`](fmt::Display) implementation of this type renders a
+/// plain-text report with the hottest functions first, each followed by its
+/// hottest basic blocks.
+#[derive(Clone)]
+pub struct InstructionCounts {
+    modules: Vec<ModuleInstructionCounts>,
+}
+
+/// The instruction counts of one module within [`InstructionCounts`].
+#[derive(Clone)]
+pub struct ModuleInstructionCounts {
+    module: Module,
+    functions: Vec<FunctionInstructionCounts>,
+}
+
+/// The instruction counts of one function within [`ModuleInstructionCounts`].
+#[derive(Clone)]
+pub struct FunctionInstructionCounts {
+    index: u32,
+    name: String,
+    blocks: Vec<BlockInstructionCount>,
+}
+
+/// The number of instructions executed in one basic block of a function.
+#[derive(Clone, Copy, Debug)]
+pub struct BlockInstructionCount {
+    module_offset: u32,
+    count: u64,
+}
+
+impl InstructionCounts {
+    /// Returns the counts of each module that has been instantiated within the
+    /// store, in instantiation order.
+    pub fn modules(&self) -> &[ModuleInstructionCounts] {
+        &self.modules
+    }
+
+    /// Returns the total number of Wasm instructions executed across all
+    /// modules.
+    pub fn total(&self) -> u64 {
+        self.modules.iter().map(|m| m.total()).sum()
+    }
+}
+
+impl fmt::Display for InstructionCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} total", self.total())?;
+        let mut funcs = self
+            .modules
+            .iter()
+            .flat_map(|m| m.functions.iter().map(move |func| (m, func)))
+            .filter(|(_, func)| func.total() > 0)
+            .collect::<Vec<_>>();
+        funcs.sort_by_key(|(_, func)| core::cmp::Reverse(func.total()));
+        for (module, func) in funcs {
+            let module = module.module.name().unwrap_or("<unknown>");
+            writeln!(f, "{} {module}!{}", func.total(), func.name)?;
+            let mut blocks = func
+                .blocks
+                .iter()
+                .filter(|b| b.count > 0)
+                .collect::<Vec<_>>();
+            blocks.sort_by_key(|b| core::cmp::Reverse(b.count));
+            for block in blocks {
+                writeln!(f, "    {} @{:#x}", block.count, block.module_offset)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for InstructionCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InstructionCounts")
+            .field("total", &self.total())
+            .finish_non_exhaustive()
+    }
+}
+
+impl ModuleInstructionCounts {
+    /// Returns the module these counts are for.
+    pub fn module(&self) -> &Module {
+        &self.module
+    }
+
+    /// Returns the counts of each function defined in this module, in
+    /// definition order.
+    pub fn functions(&self) -> &[FunctionInstructionCounts] {
+        &self.functions
+    }
+
+    /// Returns the total number of instructions executed in this module.
+    pub fn total(&self) -> u64 {
+        self.functions.iter().map(|f| f.total()).sum()
+    }
+}
+
+impl FunctionInstructionCounts {
+    /// Returns the index of this function within its module's function index
+    /// space.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// Returns the demangled name of this function, or its index if it has no
+    /// name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the total number of instructions executed in this function.
+    pub fn total(&self) -> u64 {
+        self.blocks.iter().map(|b| b.count).sum()
+    }
+
+    /// Returns the counts of each of this function's basic blocks, in order of
+    /// their offset within the module.
+    pub fn blocks(&self) -> &[BlockInstructionCount] {
+        &self.blocks
+    }
+}
+
+impl BlockInstructionCount {
+    /// Returns the offset, within the original Wasm module, of the first
+    /// instruction in this basic block.
+    pub fn module_offset(&self) -> usize {
+        self.module_offset as usize
+    }
+
+    /// Returns the number of instructions executed in this basic block.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+}
+
+impl<T> Store<T> {
+    /// Returns the number of Wasm instructions executed so far by each
+    /// function and basic block of the modules instantiated within this
+    /// store.
+    ///
+    /// Counts accumulate across all calls into the store until they're cleared
+    /// with [`Store::reset_instruction_counts`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if instruction counting is not
+    /// enabled via
+    /// [`Config::count_instructions`](crate::Config::count_instructions).
+    pub fn instruction_counts(&self) -> Result<InstructionCounts> {
+        self.inner.instruction_counts()
+    }
+
+    /// Resets all of this store's instruction counters to zero.
+    ///
+    /// This does nothing if instruction counting is not enabled.
+    pub fn reset_instruction_counts(&mut self) {
+        self.inner.instruction_counters.reset();
+    }
+}
+
+impl StoreOpaque {
+    fn instruction_counts(&self) -> Result<InstructionCounts> {
+        anyhow::ensure!(
+            self.engine().tunables().count_instructions,
+            "instruction counting is not configured in this store"
+        );
+        Ok(self.instruction_counters.read())
+    }
+
+    /// Returns the table of per-function counter pointers for the module
+    /// `id`, to be stored in the `VMContext` of its instances.
+    pub(crate) fn instruction_counters_table(
+        &self,
+        id: CompiledModuleId,
+    ) -> Option<NonNull<VmPtr<u64>>> {
+        self.instruction_counters.table(id)
+    }
+}
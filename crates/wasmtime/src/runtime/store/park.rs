@@ -0,0 +1,141 @@
+//! Parking of idle stores, see [`Store::park`].
+
+use super::*;
+
+impl<T> Store<T> {
+    /// Parks this store, giving the linear memories and tables of its
+    /// instances back to the engine's instance allocator until the store is
+    /// next used.
+    ///
+    /// This is intended for embeddings using the [pooling allocator] which
+    /// keep many idle stores alive: every instance in the store otherwise
+    /// holds on to its memory and table slots in the pool, even while no
+    /// WebAssembly is running. Parking copies the contents of each memory and
+    /// table into an allocation of its own and releases the slots for use by
+    /// other stores. Pages of linear memory that are entirely zero aren't
+    /// copied and don't occupy any resident memory while parked.
+    ///
+    /// The store is unparked, with its memories and tables moved back into
+    /// newly acquired slots, the next time WebAssembly is entered within it.
+    /// Use [`Store::unpark`] to do this eagerly and to handle failure, such as
+    /// the pool's slots being exhausted, separately from a call. While parked
+    /// the host can continue to access and grow the store's memories and
+    /// tables as usual.
+    ///
+    /// Instances that define shared memories aren't parked, nor are memories
+    /// and tables created by the host or the store's GC heap. Resource
+    /// limiters aren't consulted when memories and tables are parked or
+    /// unparked. Instances created after a store is parked aren't parked until
+    /// this method is called again.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if allocating the copy of a memory or table fails.
+    /// Instances parked before the failure stay parked.
+    ///
+    /// [pooling allocator]: crate::PoolingAllocationConfig
+    pub fn park(&mut self) -> Result<()> {
+        self.inner.park()
+    }
+
+    /// Unparks this store if it's parked, see [`Store::park`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if new memories and tables can't be allocated for
+    /// the store's parked instances, for example because the pooling
+    /// allocator has no slots available. The store remains parked in that
+    /// case, and unparking can be retried later.
+    pub fn unpark(&mut self) -> Result<()> {
+        self.inner.unpark()
+    }
+
+    /// Returns whether any of this store's instances are parked, see
+    /// [`Store::park`].
+    pub fn is_parked(&self) -> bool {
+        self.inner.parked
+    }
+}
+
+impl StoreOpaque {
+    fn park(&mut self) -> Result<()> {
+        let engine = self.engine().clone();
+        for instance in self.instances.values_mut() {
+            if !matches!(instance.kind, StoreInstanceKind::Real { .. })
+                || !instance.handle.get().can_park()
+            {
+                continue;
+            }
+            // SAFETY: real instances are allocated with the engine's
+            // allocator, and no WebAssembly can be running while the store is
+            // mutably borrowed through `Store::park`.
+            unsafe {
+                instance
+                    .handle
+                    .get_mut()
+                    .park(engine.allocator(), engine.tunables())?;
+            }
+            self.parked = true;
+        }
+        Ok(())
+    }
+
+    /// Unparks this store, see [`Store::unpark`].
+    ///
+    /// This is called on every entry into WebAssembly.
+    #[inline]
+    pub(crate) fn unpark(&mut self) -> Result<()> {
+        if self.parked {
+            self.unpark_slow()?;
+        }
+        Ok(())
+    }
+
+    #[cold]
+    fn unpark_slow(&mut self) -> Result<()> {
+        let engine = self.engine().clone();
+        let allocator = engine.allocator();
+        let parked = self
+            .instances
+            .iter()
+            .filter(|(_, instance)| instance.handle.get().is_parked())
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+
+        for id in parked {
+            let runtime_info = self.instance(id).runtime_info().clone();
+            let mut memories = PrimaryMap::new();
+            let mut tables = PrimaryMap::new();
+            let mut request = InstanceAllocationRequest {
+                id,
+                runtime_info: &runtime_info,
+                imports: Imports::default(),
+                store: self,
+                limiter: None,
+            };
+            let result = vm::assert_ready(async {
+                allocator
+                    .allocate_memories(&mut request, &mut memories)
+                    .await?;
+                allocator.allocate_tables(&mut request, &mut tables).await
+            });
+            if let Err(e) = result {
+                // SAFETY: these were just allocated by `allocator`.
+                unsafe {
+                    allocator.deallocate_memories(&mut memories);
+                    allocator.deallocate_tables(&mut tables);
+                }
+                return Err(e);
+            }
+
+            // SAFETY: `memories` and `tables` were just allocated for this
+            // instance, and WebAssembly is only entered after unparking.
+            unsafe {
+                self.instance_mut(id).unpark(allocator, memories, tables)?;
+            }
+        }
+
+        self.parked = false;
+        Ok(())
+    }
+}
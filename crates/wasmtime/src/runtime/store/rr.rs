@@ -0,0 +1,291 @@
+//! Finding out what a recorded host call changed in a store's guest state, and
+//! applying those changes when the call is replayed, see
+//! [`RecordReplay`](crate::RecordReplay).
+
+use super::*;
+use crate::rr::{RecordedGlobal, RecordedHostCall, RecordedMemory, RecordedValue};
+use crate::runtime::vm::{TableElementType, VMGlobalDefinition};
+use core::slice;
+use wasmtime_environ::{DefinedMemoryIndex, WasmValType};
+
+/// The granularity at which memories are compared to find the bytes written
+/// by a host call.
+const CHUNK: usize = 64;
+
+/// A copy of a store's guest state from just before a recorded host call.
+pub(crate) struct GuestState {
+    instances: usize,
+    memories: Vec<Vec<u8>>,
+    tables: Vec<Vec<usize>>,
+    globals: Vec<u128>,
+    gc_heap: Option<Vec<u8>>,
+}
+
+impl StoreOpaque {
+    /// Captures this store's guest state before a host call is recorded.
+    pub(crate) fn capture_guest_state(&mut self) -> Result<GuestState> {
+        let memories = self
+            .rr_memories()?
+            .into_iter()
+            .map(|(id, index)| self.rr_memory(id, index).to_vec())
+            .collect();
+        let globals = self
+            .rr_globals()
+            .into_iter()
+            // SAFETY: global definitions live as long as the store.
+            .map(|(definition, _)| unsafe { definition.as_ref().get_u128() })
+            .collect();
+        Ok(GuestState {
+            instances: self.instances.len(),
+            memories,
+            tables: self.rr_tables(),
+            globals,
+            gc_heap: self.rr_gc_heap(),
+        })
+    }
+
+    /// Returns the recorded form of a host call which returned `results`,
+    /// given the store's guest state from `before` the call.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the call changed the store in a way which can't be
+    /// recorded.
+    pub(crate) fn host_call_effects(
+        &mut self,
+        before: &GuestState,
+        results: Result<Vec<RecordedValue>, String>,
+    ) -> Result<RecordedHostCall> {
+        let memories = self.rr_memories()?;
+        let globals = self.rr_globals();
+        ensure!(
+            self.instances.len() == before.instances
+                && memories.len() == before.memories.len()
+                && globals.len() == before.globals.len(),
+            "record/replay does not support host functions which create instances, \
+             memories, tables or globals"
+        );
+        ensure!(
+            self.rr_tables() == before.tables,
+            "record/replay does not support host functions which modify tables"
+        );
+        ensure!(
+            self.rr_gc_heap() == before.gc_heap,
+            "record/replay does not support host functions which modify the GC heap"
+        );
+
+        let mut recorded_memories = Vec::new();
+        for (i, ((id, index), before)) in memories.into_iter().zip(&before.memories).enumerate() {
+            let after = self.rr_memory(id, index);
+            let writes = diff(before, after);
+            if writes.is_empty() && after.len() == before.len() {
+                continue;
+            }
+            recorded_memories.push(RecordedMemory {
+                index: u32::try_from(i)?,
+                size: u64::try_from(after.len())?,
+                writes,
+            });
+        }
+
+        let mut recorded_globals = Vec::new();
+        for (i, ((definition, is_ref), before)) in
+            globals.into_iter().zip(&before.globals).enumerate()
+        {
+            // SAFETY: global definitions live as long as the store.
+            let bits = unsafe { definition.as_ref().get_u128() };
+            if bits == *before {
+                continue;
+            }
+            ensure!(
+                !is_ref,
+                "record/replay does not support host functions which modify \
+                 reference-typed globals"
+            );
+            recorded_globals.push(RecordedGlobal {
+                index: u32::try_from(i)?,
+                bits,
+            });
+        }
+
+        Ok(RecordedHostCall {
+            results,
+            memories: recorded_memories,
+            globals: recorded_globals,
+            fuel: self.get_fuel().ok(),
+        })
+    }
+
+    /// Applies the effects of a recorded host call to this store.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the effects don't fit this store's guest state, in
+    /// which case the replayed execution has diverged from its trace.
+    pub(crate) fn apply_host_call(&mut self, call: &RecordedHostCall) -> Result<()> {
+        let memories = self.rr_memories()?;
+        for memory in &call.memories {
+            let (id, index) = *usize::try_from(memory.index)
+                .ok()
+                .and_then(|i| memories.get(i))
+                .with_context(|| format!("memory {} does not exist", memory.index))?;
+            let size = usize::try_from(memory.size)?;
+            let current = self.instance(id).memory(index).current_length();
+            if current < size {
+                let module = self.instance(id).env_module();
+                let page_size_log2 = module.memories[module.memory_index(index)].page_size_log2;
+                let delta = u64::try_from(size - current)? >> page_size_log2;
+                // The limiter already allowed this growth when it was
+                // recorded, so it isn't consulted again, which also means that
+                // growing can't block.
+                let grown = vm::one_poll(self.instance_mut(id).memory_grow(None, index, delta));
+                ensure!(
+                    matches!(grown, Some(Ok(Some(_)))),
+                    "failed to grow memory {} to {size} bytes",
+                    memory.index
+                );
+            }
+            let definition = self.instance(id).memory(index);
+            ensure!(
+                definition.current_length() == size,
+                "memory {} is {} bytes rather than {size}",
+                memory.index,
+                definition.current_length()
+            );
+            // SAFETY: the memory isn't shared, as checked by `rr_memories`,
+            // and the wasm which called the host function isn't running, so
+            // nothing else can be accessing it.
+            let data = unsafe { slice::from_raw_parts_mut(definition.base.as_ptr(), size) };
+            for (offset, bytes) in &memory.writes {
+                let start = usize::try_from(*offset)?;
+                let dst = start
+                    .checked_add(bytes.len())
+                    .and_then(|end| data.get_mut(start..end))
+                    .with_context(|| format!("write to memory {} out of bounds", memory.index))?;
+                dst.copy_from_slice(bytes);
+            }
+        }
+
+        let globals = self.rr_globals();
+        for global in &call.globals {
+            let (mut definition, is_ref) = *usize::try_from(global.index)
+                .ok()
+                .and_then(|i| globals.get(i))
+                .with_context(|| format!("global {} does not exist", global.index))?;
+            ensure!(!is_ref, "global {} is reference-typed", global.index);
+            // SAFETY: global definitions live as long as the store, and this
+            // one holds a numeric or vector value.
+            unsafe { definition.as_mut().set_u128(global.bits) };
+        }
+
+        if let Some(fuel) = call.fuel {
+            self.set_fuel(fuel)?;
+        }
+        Ok(())
+    }
+
+    /// All memories in this store, in the order used by [`RecordedMemory`].
+    fn rr_memories(&self) -> Result<Vec<(InstanceId, DefinedMemoryIndex)>> {
+        let mut memories = Vec::new();
+        for id in self.instances.keys() {
+            let instance = self.instance(id);
+            for index in 0..instance.env_module().num_defined_memories() {
+                let index = DefinedMemoryIndex::new(index);
+                ensure!(
+                    !instance.get_defined_memory(index).is_shared_memory(),
+                    "record/replay does not support shared memories"
+                );
+                memories.push((id, index));
+            }
+        }
+        Ok(memories)
+    }
+
+    fn rr_memory(&self, id: InstanceId, index: DefinedMemoryIndex) -> &[u8] {
+        let definition = self.instance(id).memory(index);
+        // SAFETY: the memory isn't shared, as checked by `rr_memories`, and
+        // the wasm which called the host function isn't running, so nothing
+        // else can be modifying it.
+        unsafe { slice::from_raw_parts(definition.base.as_ptr(), definition.current_length()) }
+    }
+
+    /// The definitions of all globals in this store, in the order used by
+    /// [`RecordedGlobal`], and whether each is reference-typed.
+    fn rr_globals(&mut self) -> Vec<(NonNull<VMGlobalDefinition>, bool)> {
+        let mut globals = Vec::new();
+        self.for_each_global(|store, global| {
+            let is_ref = matches!(global.wasmtime_ty(store).wasm_ty, WasmValType::Ref(_));
+            globals.push((global.definition(store), is_ref));
+        });
+        globals
+    }
+
+    /// The raw elements of all tables in this store.
+    fn rr_tables(&mut self) -> Vec<Vec<usize>> {
+        let mut tables = Vec::new();
+        for id in self.instances.keys() {
+            for index in 0..self.instance(id).env_module().num_defined_tables() {
+                let index = DefinedTableIndex::new(index);
+                let (mut instance, registry) = self.instance_and_module_registry_mut(id);
+                let size = instance.as_mut().get_defined_table(index).size();
+                let size = u64::try_from(size).unwrap();
+                // Initialize all elements up front so that a host call which
+                // only initializes lazily-initialized elements doesn't appear
+                // to have modified the table.
+                let table = instance.get_defined_table_with_lazy_init(registry, index, 0..size);
+                tables.push(match table.element_type() {
+                    TableElementType::Func => (0..size)
+                        .map(|i| {
+                            table
+                                .get_func(i)
+                                .unwrap()
+                                .map_or(0, |f| f.as_ptr() as usize)
+                        })
+                        .collect(),
+                    TableElementType::GcRef => table
+                        .gc_refs_mut()
+                        .iter()
+                        .map(|r| r.as_ref().map_or(0, |r| r.as_raw_u32() as usize))
+                        .collect(),
+                    TableElementType::Cont => (0..size)
+                        .flat_map(|i| match table.get_cont(i).unwrap() {
+                            Some(cont) => [cont.contref.as_ptr() as usize, cont.revision],
+                            None => [0, 0],
+                        })
+                        .collect(),
+                });
+            }
+        }
+        tables
+    }
+
+    fn rr_gc_heap(&self) -> Option<Vec<u8>> {
+        let gc_store = self.gc_store.as_ref()?;
+        Some(gc_store.gc_heap.heap_slice().to_vec())
+    }
+}
+
+/// Returns the writes which turn `before` into `after`, where `after` may be
+/// larger than `before` because the memory grew, with zeroed new pages.
+fn diff(before: &[u8], after: &[u8]) -> Vec<(u64, Vec<u8>)> {
+    static ZEROS: [u8; CHUNK] = [0; CHUNK];
+
+    let mut writes: Vec<(u64, Vec<u8>)> = Vec::new();
+    for (i, chunk) in after.chunks(CHUNK).enumerate() {
+        let start = i * CHUNK;
+        let old = before.get(start..).unwrap_or(&[]);
+        let old = &old[..old.len().min(chunk.len())];
+        let (existing, grown) = chunk.split_at(old.len());
+        if existing == old && *grown == ZEROS[..grown.len()] {
+            continue;
+        }
+        let start = start as u64;
+        match writes.last_mut() {
+            Some((offset, bytes)) if *offset + bytes.len() as u64 == start => {
+                bytes.extend_from_slice(chunk);
+            }
+            _ => writes.push((start, chunk.to_vec())),
+        }
+    }
+    writes
+}
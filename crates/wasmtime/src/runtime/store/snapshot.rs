@@ -0,0 +1,984 @@
+//! Snapshots of a paused store's guest state, see [`Store::snapshot`].
+
+use super::*;
+use crate::hash_map::HashMap;
+#[cfg(has_virtual_memory)]
+use crate::runtime::vm::{HostAlignedByteCount, MemoryImage, host_page_size};
+use crate::runtime::vm::{TableElementType, VMGlobalDefinition};
+#[cfg(has_virtual_memory)]
+use crate::sync::OnceLock;
+use core::{ptr, slice};
+use serde_derive::{Deserialize, Serialize};
+use wasmtime_environ::{DefinedMemoryIndex, FuncIndex, WasmHeapTopType, WasmValType};
+
+/// The magic bytes at the start of a serialized [`StoreSnapshot`].
+const MAGIC: [u8; 8] = *b"\0wasmss\0";
+
+/// The version of the serialized snapshot format, bumped whenever it changes.
+const FORMAT_VERSION: u32 = 1;
+
+/// A snapshot of the guest state of a paused [`Store`].
+///
+/// Snapshots are taken with [`Store::snapshot`] and restored into another
+/// store with [`Store::restore`]. They can be converted to and from bytes with
+/// [`StoreSnapshot::to_bytes`] and [`StoreSnapshot::from_bytes`], for example
+/// to checkpoint a long-running guest to disk or to migrate it to another
+/// process.
+#[derive(Clone)]
+pub struct StoreSnapshot {
+    instances: Vec<InstanceSnapshot>,
+    host_globals: Vec<GlobalSnapshot>,
+    fuel: Option<u64>,
+    gc_heap: Option<GcHeapSnapshot>,
+    host: Vec<u8>,
+}
+
+/// The state of one of a store's instances, including the dummy instances
+/// which hold host-created memories and tables.
+#[derive(Clone, Serialize, Deserialize)]
+struct InstanceSnapshot {
+    /// The module that this instance is of, or `None` for dummy instances.
+    module: Option<ModuleSnapshot>,
+    memories: Vec<MemorySnapshot>,
+    tables: Vec<TableSnapshot>,
+    globals: Vec<GlobalSnapshot>,
+}
+
+/// Enough information about a module to check that a restored instance is of
+/// the same module as the snapshotted one.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct ModuleSnapshot {
+    name: Option<String>,
+    functions: u32,
+    /// The engine-wide indices of this module's types, which objects in the GC
+    /// heap refer to.
+    types: Vec<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct MemorySnapshot {
+    page_size_log2: u8,
+    #[serde(with = "memory_data")]
+    data: Arc<MemoryData>,
+}
+
+/// The contents of a snapshotted memory, shared between clones of a snapshot.
+struct MemoryData {
+    bytes: Vec<u8>,
+    /// A copy-on-write image of `bytes`, created the first time that the
+    /// snapshot is restored and then mapped into every memory it's restored
+    /// into.
+    #[cfg(has_virtual_memory)]
+    image: OnceLock<Option<Arc<MemoryImage>>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum TableSnapshot {
+    Func(Vec<Option<FuncLocation>>),
+    /// Raw GC references into the snapshotted GC heap.
+    GcRef(Vec<u32>),
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+enum GlobalSnapshot {
+    /// The raw bits of a numeric or vector value, or of a GC reference into
+    /// the snapshotted GC heap.
+    Raw(u128),
+    Func(Option<FuncLocation>),
+}
+
+/// A function, identified by an instance which defines or imports it.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct FuncLocation {
+    instance: u32,
+    func: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct GcHeapSnapshot {
+    collector: wasmtime_environ::Collector,
+    memory: Vec<u8>,
+    /// The collector's bookkeeping, see `GcHeap::snapshot_state`.
+    state: Vec<u8>,
+    /// The functions referenced from the GC heap, in `FuncRefTableId` order.
+    func_refs: Vec<Option<FuncLocation>>,
+}
+
+impl StoreSnapshot {
+    /// Creates a new store which starts out in the state captured by this
+    /// snapshot.
+    ///
+    /// This creates a store with `data` as its host state and calls
+    /// `instantiate` to recreate the snapshotted store's instance graph within
+    /// it, with the same requirements as [`Store::restore`]. Instances created
+    /// by `instantiate` are only allocated and linked to their imports: their
+    /// data and element segments aren't applied, their globals aren't
+    /// initialized and their start functions don't run. Instead their state is
+    /// then taken from this snapshot, and the host state captured by the
+    /// snapshot isn't used. Whatever `instantiate` returns, for example the
+    /// newly created instances, is returned alongside the new store.
+    ///
+    /// When [`Config::memory_init_cow`](crate::Config::memory_init_cow) is
+    /// enabled, memories are mapped copy-on-write from an image of the
+    /// snapshotted pages rather than copied into each store, so all of a
+    /// snapshot's forks share those pages until they write to them. This image
+    /// is created the first time the snapshot is restored, so that afterwards
+    /// forking a store costs a few page-table operations per memory on top of
+    /// allocating its instances. Like the images of modules, this is currently
+    /// only supported on Linux.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `instantiate` fails or if restoring the snapshot
+    /// fails, for the same reasons as [`Store::restore`].
+    ///
+    /// # Safety
+    ///
+    /// Until `instantiate` returns, the instances it creates have zeroed
+    /// globals, tables and memories, which may not be valid for their types:
+    /// for example a global of type `(ref func)` is null. So `instantiate`
+    /// must not run any wasm in the new store, including through host
+    /// functions that it calls, nor read or write the globals, tables or
+    /// memories of the instances it creates. Looking up their exports and
+    /// returning them is fine.
+    pub unsafe fn fork<T: 'static, R>(
+        &self,
+        engine: &Engine,
+        data: T,
+        instantiate: impl FnOnce(&mut Store<T>) -> Result<R>,
+    ) -> Result<(Store<T>, R)> {
+        let mut store = Store::new(engine, data);
+        store.inner.forking = true;
+        let ret = instantiate(&mut store)?;
+        store.inner.forking = false;
+        store.restore(self, |_, _| Ok(()))?;
+        Ok((store, ret))
+    }
+
+    /// Serializes this snapshot into bytes which can be turned back into a
+    /// snapshot with [`StoreSnapshot::from_bytes`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+        bytes.extend(postcard::to_allocvec(&(
+            &self.instances,
+            &self.host_globals,
+            self.fuel,
+            &self.gc_heap,
+            &self.host,
+        ))?);
+        Ok(bytes)
+    }
+
+    /// Deserializes a snapshot previously serialized with
+    /// [`StoreSnapshot::to_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` isn't a serialized snapshot of this
+    /// version of Wasmtime's format.
+    ///
+    /// # Unsafety
+    ///
+    /// This function is marked as `unsafe` because the snapshot is trusted
+    /// when it is restored. [`Store::restore`] checks that the snapshot's
+    /// instance graph matches the store's, but it doesn't validate the
+    /// contents of the GC heap, the collector's bookkeeping or the GC
+    /// references held in tables and globals. Restoring a corrupted or
+    /// malicious snapshot can therefore lead to memory unsafety.
+    ///
+    /// Callers must ensure that `bytes` was produced by
+    /// [`StoreSnapshot::to_bytes`], with the same version of Wasmtime, and
+    /// hasn't been tampered with since, for example by only loading
+    /// snapshots from trusted storage or by authenticating them.
+    pub unsafe fn from_bytes(bytes: &[u8]) -> Result<StoreSnapshot> {
+        let (version, bytes) = bytes
+            .strip_prefix(&MAGIC[..])
+            .and_then(|bytes| bytes.split_first_chunk())
+            .context("not a store snapshot")?;
+        let version = u32::from_le_bytes(*version);
+        ensure!(
+            version == FORMAT_VERSION,
+            "unsupported store snapshot format version {version}, expected {FORMAT_VERSION}"
+        );
+        let (instances, host_globals, fuel, gc_heap, host) = postcard::from_bytes(bytes)?;
+        Ok(StoreSnapshot {
+            instances,
+            host_globals,
+            fuel,
+            gc_heap,
+            host,
+        })
+    }
+
+    /// Returns the host state that was returned by the callback passed to
+    /// [`Store::snapshot`].
+    pub fn host_state(&self) -> &[u8] {
+        &self.host
+    }
+}
+
+impl fmt::Debug for StoreSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StoreSnapshot")
+            .field("instances", &self.instances.len())
+            .field("gc_heap", &self.gc_heap.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Store<T> {
+    /// Captures a snapshot of this store's guest state.
+    ///
+    /// The snapshot contains the contents of every memory, table and global in
+    /// this store, whether created by the host or defined by an instance, the
+    /// GC heap and the store's remaining fuel. The host's own state, such as
+    /// the store's `T` and the closures of host functions, isn't part of the
+    /// snapshot. Instead, `host` is called to serialize whatever host state
+    /// needs to survive alongside the guest, and its result is kept in the
+    /// snapshot for [`Store::restore`] to hand back.
+    ///
+    /// Taking a snapshot doesn't modify this store, which can keep running
+    /// afterwards.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `host` fails, or if this store contains state that
+    /// can't be snapshotted:
+    ///
+    /// * Shared memories, which other threads may be concurrently modifying.
+    /// * Component instances.
+    /// * `externref`s, whose host data is host state. Note that unreachable
+    ///   `externref`s count until they are collected, so it may help to call
+    ///   [`Store::gc`] before taking a snapshot.
+    /// * References to host functions that are not imported by any instance in
+    ///   this store, since they can't be recreated in the restored store.
+    /// * Continuation tables and non-null continuation references.
+    pub fn snapshot(
+        &mut self,
+        host: impl FnOnce(&mut T) -> Result<Vec<u8>>,
+    ) -> Result<StoreSnapshot> {
+        let host = host(self.data_mut())?;
+        self.inner.snapshot(host)
+    }
+
+    /// Restores a snapshot taken with [`Store::snapshot`] into this store.
+    ///
+    /// Snapshots don't contain compiled code or instances, so the instance
+    /// graph must be recreated before a snapshot can be restored: this store
+    /// must belong to an [`Engine`] with the same configuration as the
+    /// snapshotted store's, and must have had the same memories, tables and
+    /// globals created by the host and the same modules instantiated, in the
+    /// same order, as the snapshotted store. Restoring then overwrites the
+    /// contents of every memory, table and global in this store, growing them
+    /// as necessary, along with its GC heap and remaining fuel. Finally, `host`
+    /// is called with the host state captured by the snapshot.
+    ///
+    /// Where possible the contents of memories are mapped copy-on-write rather
+    /// than copied, see [`StoreSnapshot::fork`].
+    ///
+    /// Objects in the GC heap refer to their types by engine-wide indices, so
+    /// a snapshot with a GC heap can only be restored when the snapshotted
+    /// modules' types were registered with the same indices in this store's
+    /// engine, for example in the same engine or in an engine which loaded
+    /// the same modules in the same order.
+    ///
+    /// Any GC references held by the host in this store from before it was
+    /// restored, such as [`Rooted`](crate::Rooted)s, are invalid afterwards.
+    ///
+    /// The snapshot's GC heap and GC references are trusted rather than
+    /// validated, which is why snapshots can only be created by
+    /// [`Store::snapshot`] or by the `unsafe` [`StoreSnapshot::from_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot's instance graph doesn't match this
+    /// store's, if a memory, table or the GC heap cannot be grown to the
+    /// snapshotted size, if this store has a
+    /// [`ResourceLimiterAsync`](crate::ResourceLimiterAsync), or if `host`
+    /// fails. This store's guest state is left unmodified if the instance
+    /// graph doesn't match or there's an async limiter, but is otherwise
+    /// unspecified after an error.
+    pub fn restore(
+        &mut self,
+        snapshot: &StoreSnapshot,
+        host: impl FnOnce(&mut T, &[u8]) -> Result<()>,
+    ) -> Result<()> {
+        let (mut limiter, store) = self.inner.resource_limiter_and_store_opaque();
+        #[cfg(feature = "async")]
+        ensure!(
+            !matches!(limiter, Some(StoreResourceLimiter::Async(_))),
+            "cannot restore a snapshot into a store with an async resource limiter"
+        );
+        vm::one_poll(store.restore(limiter.as_mut(), snapshot))
+            .expect("restoring with a synchronous resource limiter doesn't block")?;
+        host(self.data_mut(), &snapshot.host)
+    }
+}
+
+impl StoreOpaque {
+    /// Whether instances are being recreated by [`StoreSnapshot::fork`], and
+    /// so shouldn't be initialized or started.
+    pub(crate) fn forking(&self) -> bool {
+        self.forking
+    }
+
+    fn snapshot(&mut self, host: Vec<u8>) -> Result<StoreSnapshot> {
+        #[cfg(feature = "component-model")]
+        ensure!(
+            self.num_component_instances == 0,
+            "cannot snapshot a store containing component instances"
+        );
+
+        let funcs = self.func_locations();
+        let store_id = self.id();
+
+        let ids = self.instances.keys().collect::<Vec<_>>();
+        let mut instances = Vec::with_capacity(ids.len());
+        for id in ids {
+            let module = self
+                .module_for_instance(StoreInstanceId::new(store_id, id))
+                .map(ModuleSnapshot::new);
+
+            let env_module = self.instance(id).env_module().clone();
+            let mut memories = Vec::with_capacity(env_module.num_defined_memories());
+            for index in 0..env_module.num_defined_memories() {
+                let index = DefinedMemoryIndex::new(index);
+                let instance = self.instance(id);
+                ensure!(
+                    !instance.get_defined_memory(index).is_shared_memory(),
+                    "cannot snapshot a store containing shared memories"
+                );
+                let definition = instance.memory(index);
+                // SAFETY: the memory isn't shared and no Wasm is running in
+                // this store, so nothing else can be modifying it.
+                let data = unsafe {
+                    slice::from_raw_parts(definition.base.as_ptr(), definition.current_length())
+                };
+                let ty = &env_module.memories[env_module.memory_index(index)];
+                memories.push(MemorySnapshot {
+                    page_size_log2: ty.page_size_log2,
+                    data: Arc::new(MemoryData::new(data.to_vec())),
+                });
+            }
+
+            let mut tables = Vec::with_capacity(env_module.num_defined_tables());
+            for index in 0..env_module.num_defined_tables() {
+                let index = DefinedTableIndex::new(index);
+                let (mut instance, registry) = self.instance_and_module_registry_mut(id);
+                let size = instance.as_mut().get_defined_table(index).size();
+                let size = u64::try_from(size).unwrap();
+                let table = instance.get_defined_table_with_lazy_init(registry, index, 0..size);
+                tables.push(match table.element_type() {
+                    TableElementType::Func => TableSnapshot::Func(
+                        (0..size)
+                            .map(|i| funcs.locate(table.get_func(i)?))
+                            .collect::<Result<_>>()?,
+                    ),
+                    TableElementType::GcRef => TableSnapshot::GcRef(
+                        table
+                            .gc_refs_mut()
+                            .iter()
+                            .map(|r| r.as_ref().map_or(0, |r| r.as_raw_u32()))
+                            .collect(),
+                    ),
+                    TableElementType::Cont => {
+                        bail!("cannot snapshot a store containing continuation tables")
+                    }
+                });
+            }
+
+            let mut globals = Vec::with_capacity(env_module.num_defined_globals());
+            for index in 0..env_module.num_defined_globals() {
+                let index = DefinedGlobalIndex::new(index);
+                let ty = &env_module.globals[env_module.global_index(index)];
+                let definition = self.instance(id).global_ptr(index);
+                // SAFETY: the definition is valid and of type `ty`.
+                globals.push(unsafe { snapshot_global(&funcs, ty, definition.as_ref())? });
+            }
+
+            instances.push(InstanceSnapshot {
+                module,
+                memories,
+                tables,
+                globals,
+            });
+        }
+
+        let host_globals = self
+            .host_globals
+            .values()
+            .map(|global| {
+                // SAFETY: host globals live as long as the store and their
+                // definitions are of their type.
+                unsafe {
+                    let global = global.get().as_ref();
+                    snapshot_global(&funcs, &global.ty, &global.global)
+                }
+            })
+            .collect::<Result<_>>()?;
+
+        let gc_heap = match &self.gc_store {
+            None => None,
+            Some(gc_store) => {
+                ensure!(
+                    gc_store.host_data_table.is_empty(),
+                    "cannot snapshot a store containing `externref`s"
+                );
+                let mut func_refs = gc_store.func_ref_table.iter().collect::<Vec<_>>();
+                func_refs.sort_by_key(|(id, _)| id.into_raw());
+                Some(GcHeapSnapshot {
+                    collector: self.engine().tunables().collector.unwrap(),
+                    memory: gc_store.gc_heap.heap_slice().to_vec(),
+                    state: gc_store.gc_heap.snapshot_state()?,
+                    func_refs: func_refs
+                        .into_iter()
+                        .map(|(_, f)| funcs.locate(f.map(|f| f.as_non_null())))
+                        .collect::<Result<_>>()?,
+                })
+            }
+        };
+
+        Ok(StoreSnapshot {
+            instances,
+            host_globals,
+            fuel: self.get_fuel().ok(),
+            gc_heap,
+            host,
+        })
+    }
+
+    /// Build a map from every function that instances in this store define or
+    /// import to its location.
+    fn func_locations(&mut self) -> FuncLocations {
+        let mut funcs = HashMap::new();
+        for id in self.instances.keys().collect::<Vec<_>>() {
+            let (mut instance, registry) = self.instance_and_module_registry_mut(id);
+            for func in 0..instance.env_module().functions.len() {
+                let func = FuncIndex::new(func);
+                if let Some(func_ref) = instance.as_mut().get_func_ref(registry, func) {
+                    funcs.entry(func_ref).or_insert(FuncLocation {
+                        instance: id.as_u32(),
+                        func: func.as_u32(),
+                    });
+                }
+            }
+        }
+        FuncLocations(funcs)
+    }
+
+    async fn restore(
+        &mut self,
+        mut limiter: Option<&mut StoreResourceLimiter<'_>>,
+        snapshot: &StoreSnapshot,
+    ) -> Result<()> {
+        self.check_instance_graph(snapshot)?;
+
+        // Restore the GC heap first, so that GC references in tables and
+        // globals don't dangle while it is being restored.
+        if let Some(gc_heap) = &snapshot.gc_heap {
+            self.restore_gc_heap(limiter.as_deref_mut(), gc_heap)
+                .await?;
+        }
+
+        let ids = self.instances.keys().collect::<Vec<_>>();
+        for (id, instance) in ids.into_iter().zip(&snapshot.instances) {
+            for (index, memory) in instance.memories.iter().enumerate() {
+                let index = DefinedMemoryIndex::new(index);
+                let current = self.instance(id).memory(index).current_length();
+                let len = memory.data.bytes.len();
+                ensure!(
+                    current <= len,
+                    "memory {} of instance {} is larger than in the snapshot",
+                    index.as_u32(),
+                    id.as_u32(),
+                );
+                if current < len {
+                    let delta = u64::try_from(len - current)? >> memory.page_size_log2;
+                    self.instance_mut(id)
+                        .memory_grow(limiter.as_deref_mut(), index, delta)
+                        .await?
+                        .context("failed to grow memory to its snapshotted size")?;
+                }
+                let definition = self.instance(id).memory(index);
+                ensure!(
+                    definition.current_length() == len,
+                    "failed to grow memory to its snapshotted size"
+                );
+
+                #[cfg(has_virtual_memory)]
+                if self.engine().tunables().memory_init_cow
+                    && let Some(image) = memory.data.image(self.engine())?
+                    && self
+                        .instance_mut(id)
+                        .get_defined_memory_mut(index)
+                        .map_image(image)?
+                {
+                    continue;
+                }
+
+                // SAFETY: the memory isn't shared, as checked above, and no
+                // Wasm is running in this store, so nothing else can be
+                // accessing it.
+                let data = unsafe { slice::from_raw_parts_mut(definition.base.as_ptr(), len) };
+                data.copy_from_slice(&memory.data.bytes);
+            }
+
+            for (index, table) in instance.tables.iter().enumerate() {
+                let index = DefinedTableIndex::new(index);
+                self.grow_table_for_restore(limiter.as_deref_mut(), id, index, table.len())
+                    .await?;
+                match table {
+                    TableSnapshot::Func(elements) => {
+                        for (i, func) in elements.iter().enumerate() {
+                            let func_ref = self.func_ref(*func)?;
+                            self.instance_mut(id)
+                                .get_defined_table(index)
+                                .set_func(u64::try_from(i).unwrap(), func_ref)?;
+                        }
+                    }
+                    TableSnapshot::GcRef(elements) => {
+                        // NB: these are written without GC barriers, since the
+                        // restored GC heap already accounts for them.
+                        let table = self.instance_mut(id).get_defined_table(index);
+                        for (slot, raw) in table.gc_refs_mut().iter_mut().zip(elements) {
+                            *slot = VMGcRef::from_raw_u32(*raw);
+                        }
+                    }
+                }
+            }
+
+            for (index, global) in instance.globals.iter().enumerate() {
+                let index = DefinedGlobalIndex::new(index);
+                let mut definition = self.instance(id).global_ptr(index);
+                // SAFETY: the global's type was checked against the snapshot
+                // above.
+                unsafe { self.restore_global(definition.as_mut(), global)? };
+            }
+        }
+
+        for (index, global) in snapshot.host_globals.iter().enumerate() {
+            let index = DefinedGlobalIndex::new(index);
+            let mut definition = self.host_globals[index].get();
+            // SAFETY: host globals live as long as the store and their types
+            // were checked against the snapshot above.
+            unsafe { self.restore_global(&mut definition.as_mut().global, global)? };
+        }
+
+        if let Some(fuel) = snapshot.fuel {
+            self.set_fuel(fuel)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check that `snapshot` was taken of a store with the same instance graph
+    /// as this store, before any of this store's state is overwritten.
+    fn check_instance_graph(&self, snapshot: &StoreSnapshot) -> Result<()> {
+        #[cfg(feature = "component-model")]
+        ensure!(
+            self.num_component_instances == 0,
+            "cannot restore a snapshot into a store containing component instances"
+        );
+        ensure!(
+            self.instances.len() == snapshot.instances.len(),
+            "the snapshot has {} instances but this store has {}",
+            snapshot.instances.len(),
+            self.instances.len(),
+        );
+
+        let store_id = self.id();
+        for (id, instance) in self.instances.keys().zip(&snapshot.instances) {
+            let module = self
+                .module_for_instance(StoreInstanceId::new(store_id, id))
+                .map(ModuleSnapshot::new);
+            match (&module, &instance.module) {
+                (None, None) => {}
+                (Some(module), Some(expected))
+                    if module.name == expected.name && module.functions == expected.functions =>
+                {
+                    ensure!(
+                        snapshot.gc_heap.is_none() || module.types == expected.types,
+                        "the types of instance {}'s module are registered with different \
+                         indices than in the snapshot, so its GC heap cannot be restored",
+                        id.as_u32(),
+                    );
+                }
+                _ => bail!(
+                    "instance {} is not of the same module as in the snapshot",
+                    id.as_u32()
+                ),
+            }
+
+            let env_module = self.instance(id).env_module();
+            ensure!(
+                env_module.num_defined_memories() == instance.memories.len()
+                    && env_module.num_defined_tables() == instance.tables.len()
+                    && env_module.num_defined_globals() == instance.globals.len(),
+                "instance {} defines different memories, tables or globals than in the snapshot",
+                id.as_u32(),
+            );
+
+            for (index, memory) in instance.memories.iter().enumerate() {
+                let index = DefinedMemoryIndex::new(index);
+                let ty = &env_module.memories[env_module.memory_index(index)];
+                ensure!(
+                    !ty.shared && ty.page_size_log2 == memory.page_size_log2,
+                    "memory {} of instance {} has a different type than in the snapshot",
+                    index.as_u32(),
+                    id.as_u32(),
+                );
+            }
+
+            for (index, table) in instance.tables.iter().enumerate() {
+                let index = DefinedTableIndex::new(index);
+                let ty = &env_module.tables[env_module.table_index(index)];
+                let matches = match (table, ty.ref_type.heap_type.top()) {
+                    (TableSnapshot::Func(_), WasmHeapTopType::Func) => true,
+                    (TableSnapshot::Func(_), _) | (_, WasmHeapTopType::Func) => false,
+                    (_, WasmHeapTopType::Cont) => false,
+                    (TableSnapshot::GcRef(_), _) => true,
+                };
+                ensure!(
+                    matches,
+                    "table {} of instance {} has a different type than in the snapshot",
+                    index.as_u32(),
+                    id.as_u32(),
+                );
+            }
+
+            for (index, global) in instance.globals.iter().enumerate() {
+                let index = DefinedGlobalIndex::new(index);
+                let ty = &env_module.globals[env_module.global_index(index)];
+                ensure!(
+                    global.matches(ty),
+                    "global {} of instance {} has a different type than in the snapshot",
+                    index.as_u32(),
+                    id.as_u32(),
+                );
+            }
+        }
+
+        ensure!(
+            self.host_globals.len() == snapshot.host_globals.len(),
+            "the snapshot has {} host-created globals but this store has {}",
+            snapshot.host_globals.len(),
+            self.host_globals.len(),
+        );
+        for (index, (global, expected)) in self
+            .host_globals
+            .values()
+            .zip(&snapshot.host_globals)
+            .enumerate()
+        {
+            // SAFETY: host globals live as long as the store.
+            let ty = unsafe { &global.get().as_ref().ty };
+            ensure!(
+                expected.matches(ty),
+                "host-created global {index} has a different type than in the snapshot"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Grow the given table to `len` elements, filling the new elements with
+    /// null references regardless of the table's type since they are about to
+    /// be overwritten.
+    async fn grow_table_for_restore(
+        &mut self,
+        limiter: Option<&mut StoreResourceLimiter<'_>>,
+        id: InstanceId,
+        index: DefinedTableIndex,
+        len: usize,
+    ) -> Result<()> {
+        let current = self.instance_mut(id).get_defined_table(index).size();
+        ensure!(
+            current <= len,
+            "table {} of instance {} is larger than in the snapshot",
+            index.as_u32(),
+            id.as_u32(),
+        );
+        if current == len {
+            return Ok(());
+        }
+
+        let delta = u64::try_from(len - current).unwrap();
+        let (gc_store, instance) = self.optional_gc_store_and_instance_mut(id);
+        instance
+            .defined_table_grow(index, async |table| {
+                // SAFETY: in the context of `defined_table_grow` this is safe
+                // to call as it'll update the internal table pointer in the
+                // instance.
+                unsafe {
+                    match table.element_type() {
+                        TableElementType::Func => table.grow_func(limiter, delta, None).await,
+                        TableElementType::GcRef => {
+                            table.grow_gc_ref(limiter, gc_store, delta, None).await
+                        }
+                        TableElementType::Cont => table.grow_cont(limiter, delta, None).await,
+                    }
+                }
+            })
+            .await?
+            .context("failed to grow table to its snapshotted size")?;
+        Ok(())
+    }
+
+    /// Write a snapshotted global value into `definition`.
+    ///
+    /// # Safety
+    ///
+    /// `definition` must be a global of a type that `global` matches.
+    unsafe fn restore_global(
+        &mut self,
+        definition: &mut VMGlobalDefinition,
+        global: &GlobalSnapshot,
+    ) -> Result<()> {
+        match global {
+            // NB: this may write a GC reference without GC barriers, since the
+            // restored GC heap already accounts for it.
+            GlobalSnapshot::Raw(bits) => unsafe { definition.set_u128(*bits) },
+            GlobalSnapshot::Func(func) => {
+                let func_ref = self.func_ref(*func)?;
+                unsafe {
+                    *definition.as_func_ref_mut() =
+                        func_ref.map_or(ptr::null_mut(), |f| f.as_ptr());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "gc")]
+    async fn restore_gc_heap(
+        &mut self,
+        mut limiter: Option<&mut StoreResourceLimiter<'_>>,
+        snapshot: &GcHeapSnapshot,
+    ) -> Result<()> {
+        let collector = self.engine().tunables().collector;
+        ensure!(
+            collector == Some(snapshot.collector),
+            "the snapshot's GC heap uses the {} collector, but this store does not",
+            snapshot.collector,
+        );
+
+        let len = snapshot.memory.len();
+        let current = self
+            .ensure_gc_store(limiter.as_deref_mut())
+            .await?
+            .gc_heap
+            .heap_slice()
+            .len();
+        if current < len {
+            self.grow_gc_heap(limiter, u64::try_from(len - current)?)
+                .await?;
+        }
+
+        let gc_store = self.unwrap_gc_store_mut();
+        let heap = gc_store.gc_heap.heap_slice_mut();
+        heap[..len].copy_from_slice(&snapshot.memory);
+        heap[len..].fill(0);
+        gc_store.gc_heap.restore_state(len, &snapshot.state)?;
+
+        // Any objects previously in this heap are gone, along with their host
+        // data and function references.
+        gc_store.host_data_table = Default::default();
+        gc_store.func_ref_table = Default::default();
+        for (i, func) in snapshot.func_refs.iter().enumerate() {
+            let func_ref = self.func_ref(*func)?.map(SendSyncPtr::new);
+            // SAFETY: the function reference belongs to an instance in this
+            // store, so it remains valid as long as the GC heap does.
+            let id = unsafe { self.unwrap_gc_store_mut().func_ref_table.intern(func_ref) };
+            ensure!(
+                usize::try_from(id.into_raw()).unwrap() == i,
+                "duplicate function reference in the snapshot's GC heap"
+            );
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "gc"))]
+    async fn restore_gc_heap(
+        &mut self,
+        _: Option<&mut StoreResourceLimiter<'_>>,
+        _: &GcHeapSnapshot,
+    ) -> Result<()> {
+        bail!("cannot restore a GC heap: the `gc` feature was disabled at compile time")
+    }
+
+    /// Get the function reference in this store at the given snapshotted
+    /// location.
+    fn func_ref(&mut self, func: Option<FuncLocation>) -> Result<Option<NonNull<VMFuncRef>>> {
+        let Some(FuncLocation { instance, func }) = func else {
+            return Ok(None);
+        };
+        let instance = InstanceId::from_u32(instance);
+        let func = FuncIndex::from_u32(func);
+        ensure!(
+            self.instances.get(instance).is_some()
+                && self
+                    .instance(instance)
+                    .env_module()
+                    .functions
+                    .get(func)
+                    .is_some(),
+            "invalid function reference in snapshot"
+        );
+        let (instance, registry) = self.instance_and_module_registry_mut(instance);
+        Ok(instance.get_func_ref(registry, func))
+    }
+}
+
+/// A map from the functions of every instance in a store to their locations.
+struct FuncLocations(HashMap<NonNull<VMFuncRef>, FuncLocation>);
+
+impl FuncLocations {
+    fn locate(&self, func_ref: Option<NonNull<VMFuncRef>>) -> Result<Option<FuncLocation>> {
+        let Some(func_ref) = func_ref else {
+            return Ok(None);
+        };
+        let location = self.0.get(&func_ref).copied().context(
+            "cannot snapshot a reference to a host function which is not imported by any \
+             instance",
+        )?;
+        Ok(Some(location))
+    }
+}
+
+impl ModuleSnapshot {
+    fn new(module: &Module) -> ModuleSnapshot {
+        ModuleSnapshot {
+            name: module.name().map(String::from),
+            functions: u32::try_from(module.env_module().functions.len()).unwrap(),
+            types: module
+                .signatures()
+                .as_module_map()
+                .values()
+                .map(|ty| ty.bits())
+                .collect(),
+        }
+    }
+}
+
+impl MemoryData {
+    fn new(bytes: Vec<u8>) -> MemoryData {
+        MemoryData {
+            bytes,
+            #[cfg(has_virtual_memory)]
+            image: OnceLock::new(),
+        }
+    }
+
+    /// Get the copy-on-write image of this memory's contents, creating it if
+    /// this is the first time it's needed.
+    ///
+    /// Returns `None` if the contents can't be mapped copy-on-write, in which
+    /// case they need to be copied instead.
+    #[cfg(has_virtual_memory)]
+    fn image(self: &Arc<Self>, engine: &Engine) -> Result<Option<&Arc<MemoryImage>>> {
+        let image = self.image.get_or_try_init(|| -> Result<_> {
+            let page_size = host_page_size();
+            if self.bytes.len() % page_size != 0 {
+                return Ok(None);
+            }
+            // Restored memories are zeroed before the image is mapped in, so
+            // trailing zero pages don't need to be part of the image.
+            let len = self
+                .bytes
+                .chunks(page_size)
+                .rposition(|page| page.iter().any(|b| *b != 0))
+                .map_or(0, |i| (i + 1) * page_size);
+            let len = HostAlignedByteCount::new(len).unwrap();
+            Ok(MemoryImage::from_source(engine, self, len)?.map(Arc::new))
+        })?;
+        Ok(image.as_ref())
+    }
+}
+
+impl crate::vm::ModuleMemoryImageSource for MemoryData {
+    fn wasm_data(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn mmap(&self) -> Option<&crate::vm::MmapVec> {
+        None
+    }
+}
+
+/// (De)serialization of `MemoryData` as just its bytes.
+mod memory_data {
+    use super::MemoryData;
+    use crate::prelude::*;
+    use alloc::sync::Arc;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        data: &Arc<MemoryData>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        data.bytes.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Arc<MemoryData>, D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        Ok(Arc::new(MemoryData::new(bytes)))
+    }
+}
+
+impl TableSnapshot {
+    fn len(&self) -> usize {
+        match self {
+            TableSnapshot::Func(elements) => elements.len(),
+            TableSnapshot::GcRef(elements) => elements.len(),
+        }
+    }
+}
+
+impl GlobalSnapshot {
+    /// Can this snapshotted value be restored into a global of type `ty`?
+    fn matches(&self, ty: &wasmtime_environ::Global) -> bool {
+        let top = match ty.wasm_ty {
+            WasmValType::Ref(r) => Some(r.heap_type.top()),
+            _ => None,
+        };
+        match self {
+            GlobalSnapshot::Func(_) => top == Some(WasmHeapTopType::Func),
+            GlobalSnapshot::Raw(_) => top != Some(WasmHeapTopType::Func),
+        }
+    }
+}
+
+/// Capture the value of a global of type `ty`.
+///
+/// # Safety
+///
+/// `definition` must be a global of type `ty`.
+unsafe fn snapshot_global(
+    funcs: &FuncLocations,
+    ty: &wasmtime_environ::Global,
+    definition: &VMGlobalDefinition,
+) -> Result<GlobalSnapshot> {
+    let top = match ty.wasm_ty {
+        WasmValType::Ref(r) => Some(r.heap_type.top()),
+        _ => None,
+    };
+    match top {
+        Some(WasmHeapTopType::Func) => {
+            let func_ref = unsafe { NonNull::new(definition.as_func_ref()) };
+            Ok(GlobalSnapshot::Func(funcs.locate(func_ref)?))
+        }
+        Some(WasmHeapTopType::Cont) => {
+            let bits = unsafe { definition.get_u128() };
+            ensure!(
+                bits == 0,
+                "cannot snapshot a store containing continuation references"
+            );
+            Ok(GlobalSnapshot::Raw(bits))
+        }
+        _ => Ok(GlobalSnapshot::Raw(unsafe { definition.get_u128() })),
+    }
+}
@@ -0,0 +1,272 @@
+//! Per-store resource usage accounting, see [`Store::usage`].
+
+use super::*;
+use crate::runtime::vm::ExportMemory;
+use core::time::Duration;
+
+/// Resource usage of a [`Store`].
+///
+/// Returned by [`Store::usage`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct StoreUsage {
+    /// The accessible size, in bytes, of all linear memories defined within
+    /// the store, including host-created memories.
+    ///
+    /// This is how large the memories are, not how much physical memory they
+    /// use, see `memory_resident_bytes` for that.
+    pub memory_bytes: u64,
+
+    /// The number of bytes of all linear memories defined within the store
+    /// which are resident in physical memory, including host-created
+    /// memories.
+    ///
+    /// Pages which have never been touched, or which were decommitted, aren't
+    /// counted. This is measured by asking the OS which pages are resident,
+    /// which takes time proportional to the size of the memories. On
+    /// platforms which can't measure residency, currently all but Unix-like
+    /// ones, this is the same as `memory_bytes`.
+    pub memory_resident_bytes: u64,
+
+    /// The number of elements in all tables defined within the store,
+    /// including host-created tables.
+    pub table_elements: u64,
+
+    /// The size, in bytes, of the store's GC heap, or zero if it hasn't been
+    /// allocated.
+    pub gc_heap_bytes: u64,
+
+    /// The number of calls that WebAssembly has made to host functions.
+    ///
+    /// Only counted while usage accounting is enabled, see
+    /// [`Store::usage_accounting`].
+    pub host_calls: u64,
+
+    /// Wall-clock time spent executing WebAssembly.
+    ///
+    /// Only measured while usage accounting is enabled, see
+    /// [`Store::usage_accounting`].
+    pub wasm_time: Duration,
+
+    /// Wall-clock time spent in host functions called from WebAssembly.
+    ///
+    /// Only measured while usage accounting is enabled, see
+    /// [`Store::usage_accounting`].
+    pub host_time: Duration,
+
+    /// CPU time spent executing WebAssembly, or zero on platforms which can't
+    /// measure per-thread CPU time.
+    ///
+    /// Only measured while usage accounting is enabled, see
+    /// [`Store::usage_accounting`].
+    pub wasm_cpu_time: Duration,
+
+    /// CPU time spent in host functions called from WebAssembly, or zero on
+    /// platforms which can't measure per-thread CPU time.
+    ///
+    /// Only measured while usage accounting is enabled, see
+    /// [`Store::usage_accounting`].
+    pub host_cpu_time: Duration,
+}
+
+/// The counters behind the call- and time-related fields of [`StoreUsage`],
+/// updated on every transition between WebAssembly and the host.
+#[derive(Default)]
+pub(super) struct UsageAccounting {
+    host_calls: u64,
+    wasm_time: Duration,
+    host_time: Duration,
+    wasm_cpu_time: Duration,
+    host_cpu_time: Duration,
+    /// The number of host-to-wasm calls currently on the stack.
+    wasm_depth: usize,
+    /// When the last transition happened, along with the thread's CPU time at
+    /// that point.
+    #[cfg(feature = "std")]
+    last_transition: Option<(std::time::Instant, Option<Duration>)>,
+}
+
+impl UsageAccounting {
+    /// Records a transition between WebAssembly and the host.
+    ///
+    /// The time since the previous transition is attributed to whichever side
+    /// was running in the meantime. Time spent in the host outside of any
+    /// call into WebAssembly isn't attributed to either side.
+    pub(super) fn transition(&mut self, s: CallHook) {
+        // Which side, if any, was running since the previous transition.
+        let in_host_call = match s {
+            // Entering wasm from the top-level host doesn't end a period of
+            // either wasm or host-call execution.
+            CallHook::CallingWasm => {
+                self.wasm_depth += 1;
+                if self.wasm_depth == 1 {
+                    None
+                } else {
+                    Some(true)
+                }
+            }
+            CallHook::ReturningFromWasm => {
+                self.wasm_depth = self.wasm_depth.saturating_sub(1);
+                Some(false)
+            }
+            CallHook::CallingHost => {
+                self.host_calls += 1;
+                Some(false)
+            }
+            CallHook::ReturningFromHost => Some(true),
+        };
+
+        #[cfg(feature = "std")]
+        {
+            let now = std::time::Instant::now();
+            let cpu = crate::runtime::vm::thread_cpu_time();
+            if let (Some((last, last_cpu)), Some(in_host_call)) =
+                (self.last_transition, in_host_call)
+            {
+                let wall = now.saturating_duration_since(last);
+                let cpu = match (cpu, last_cpu) {
+                    (Some(cpu), Some(last_cpu)) => cpu.saturating_sub(last_cpu),
+                    _ => Duration::ZERO,
+                };
+                if in_host_call {
+                    self.host_time += wall;
+                    self.host_cpu_time += cpu;
+                } else {
+                    self.wasm_time += wall;
+                    self.wasm_cpu_time += cpu;
+                }
+            }
+            self.last_transition = Some((now, cpu));
+        }
+        #[cfg(not(feature = "std"))]
+        let _ = in_host_call;
+    }
+
+    fn reset(&mut self) {
+        *self = UsageAccounting {
+            wasm_depth: self.wasm_depth,
+            #[cfg(feature = "std")]
+            last_transition: self.last_transition,
+            ..UsageAccounting::default()
+        };
+    }
+}
+
+impl<T> Store<T> {
+    /// Returns the current resource usage of this store.
+    ///
+    /// Memory, table, and GC heap sizes are always available and are computed
+    /// when this method is called. Host-call counts and time spent in
+    /// WebAssembly and host functions are only gathered while
+    /// [`Store::usage_accounting`] is enabled, and are zero otherwise.
+    pub fn usage(&self) -> StoreUsage {
+        self.inner.usage()
+    }
+
+    /// Configures whether this store counts host calls and measures the time
+    /// spent in WebAssembly and in host functions, as reported by
+    /// [`Store::usage`].
+    ///
+    /// Accounting is performed on every transition between WebAssembly and
+    /// the host, and costs a clock read or two per transition. Disabling
+    /// accounting discards the counters gathered so far.
+    ///
+    /// Time spent while an async call is suspended is attributed to whichever
+    /// side was running when it was suspended.
+    ///
+    /// By default accounting is disabled.
+    pub fn usage_accounting(&mut self, enable: bool) {
+        let accounting = &mut self.inner.inner.usage_accounting;
+        match (enable, accounting.is_some()) {
+            (true, false) => *accounting = Some(Box::default()),
+            (false, true) => *accounting = None,
+            _ => {}
+        }
+    }
+
+    /// Resets the host-call count and time counters reported by
+    /// [`Store::usage`] to zero, for example to measure a single request.
+    pub fn reset_usage(&mut self) {
+        if let Some(accounting) = &mut self.inner.inner.usage_accounting {
+            accounting.reset();
+        }
+    }
+}
+
+impl<'a, T> StoreContext<'a, T> {
+    /// Returns the current resource usage of this store.
+    ///
+    /// Same as [`Store::usage`].
+    pub fn usage(&self) -> StoreUsage {
+        self.0.usage()
+    }
+}
+
+impl<'a, T> StoreContextMut<'a, T> {
+    /// Returns the current resource usage of this store.
+    ///
+    /// Same as [`Store::usage`].
+    pub fn usage(&self) -> StoreUsage {
+        self.0.usage()
+    }
+}
+
+impl StoreOpaque {
+    fn usage(&self) -> StoreUsage {
+        let mut memory_bytes = 0;
+        let mut memory_resident_bytes = 0;
+        for memory in self.all_memories() {
+            let (base, len) = match memory {
+                ExportMemory::Unshared(m) => {
+                    let data = m.internal_data(self);
+                    (data.as_ptr(), data.len())
+                }
+                ExportMemory::Shared(m, _) => {
+                    // SAFETY: a shared memory's definition lives as long as
+                    // the memory itself.
+                    let def = unsafe { m.vmmemory_ptr().as_ref() };
+                    (def.base.as_ptr().cast_const(), def.current_length())
+                }
+            };
+            #[cfg(has_virtual_memory)]
+            let resident = crate::runtime::vm::resident_bytes(base, len).unwrap_or(len);
+            #[cfg(not(has_virtual_memory))]
+            let resident = {
+                let _ = base;
+                len
+            };
+            memory_bytes += u64::try_from(len).unwrap();
+            memory_resident_bytes += u64::try_from(resident).unwrap();
+        }
+
+        let mut table_elements = 0;
+        for id in self.instances.keys() {
+            let instance = StoreInstanceId::new(self.id(), id);
+            for table in 0..self.instance(id).env_module().num_defined_tables() {
+                let table = Table::from_raw(instance, DefinedTableIndex::new(table));
+                table_elements += table._size(self);
+            }
+        }
+
+        let gc_heap_bytes = self
+            .gc_store
+            .as_ref()
+            .map_or(0, |gc| gc.vmmemory_definition().current_length());
+
+        let mut usage = StoreUsage {
+            memory_bytes,
+            memory_resident_bytes,
+            table_elements,
+            gc_heap_bytes: u64::try_from(gc_heap_bytes).unwrap(),
+            ..StoreUsage::default()
+        };
+        if let Some(accounting) = &self.usage_accounting {
+            usage.host_calls = accounting.host_calls;
+            usage.wasm_time = accounting.wasm_time;
+            usage.host_time = accounting.host_time;
+            usage.wasm_cpu_time = accounting.wasm_cpu_time;
+            usage.host_cpu_time = accounting.host_cpu_time;
+        }
+        usage
+    }
+}
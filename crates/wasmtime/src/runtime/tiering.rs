@@ -0,0 +1,450 @@
+//! Tiered compilation, see [`Strategy::Tiered`](crate::Strategy::Tiered).
+//!
+//! Modules are first compiled with Winch, and every Winch-compiled function
+//! increments its entry in its module's array of call counts when it's called,
+//! see `PtrSize::vmctx_call_counts`. Calls between functions of the same
+//! module load the callee from its `VMFuncRef` in the caller's `VMContext`
+//! rather than calling it directly, and so do array-to-Wasm trampolines.
+//!
+//! When a function's count reaches `Config::tier_up_threshold` it calls the
+//! `tier_up` builtin, which queues the function for a background thread per
+//! engine, see `TierUpWorker`. The function is recompiled on its own with
+//! Cranelift into new code memory, after which the `wasm_call` pointer of the
+//! function's `VMFuncRef` in every live instance of the module is atomically
+//! swapped to point at the new code. That's the only field of a `VMFuncRef`
+//! which ever changes, so nothing can observe a partially updated
+//! `VMFuncRef`. Calls which are already executing the old code finish there,
+//! which is fine since the old code lives as long as its module.
+//!
+//! Modules which are deserialized rather than compiled in this process don't
+//! have their original wasm binary to recompile functions from, so they keep
+//! running their Winch-compiled code.
+
+use crate::compile::TierUpTranslation;
+use crate::hash_map::HashMap;
+use crate::prelude::*;
+use crate::runtime::vm::{MmapVec, SendSyncPtr, VMContext, VMFuncRef, VMWasmCallFunction, VmPtr};
+use crate::{CodeMemory, Engine, EngineWeak, TierUpStats};
+use alloc::collections::VecDeque;
+use alloc::sync::{Arc, Weak};
+use core::ops::Range;
+use core::ptr::NonNull;
+use core::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, Once, OnceLock};
+use std::thread;
+use wasmtime_environ::{
+    DefinedFuncIndex, EntityRef, FilePos, FuncKey, FunctionLoc, HostPtr, PrimaryMap, VMOffsets,
+};
+
+/// The tiered compilation state of a module.
+pub(crate) struct ModuleTiering {
+    /// The number of calls made to each defined function, which is written by
+    /// compiled code and shared by all instances of the module.
+    counts: Box<[AtomicU32]>,
+    /// The offset of each defined function's `VMFuncRef` within a
+    /// `VMContext`.
+    func_refs: PrimaryMap<DefinedFuncIndex, u32>,
+    /// The module's original wasm binary, which hot functions are recompiled
+    /// from. Modules which weren't compiled in this process, e.g. because
+    /// they were deserialized, don't have this and never tier up.
+    wasm: OnceLock<Arc<[u8]>>,
+    /// The translation of `wasm`, which is created when the first function
+    /// is tiered up and reused for all others. This is only used by the
+    /// worker thread.
+    translation: Mutex<Option<TierUpTranslation>>,
+    state: Mutex<TieringState>,
+}
+
+#[derive(Default)]
+struct TieringState {
+    /// The `VMContext` of each live instance of the module.
+    instances: Vec<SendSyncPtr<VMContext>>,
+    /// The recompiled code of each function which has been tiered up.
+    tiered: HashMap<DefinedFuncIndex, TieredFunction>,
+    /// The address range of the recompiled code of each function in
+    /// `tiered`, sorted by start address, for `lookup_pc`.
+    ranges: Vec<(Range<usize>, DefinedFuncIndex)>,
+    /// Whether tiering up each defined function has been requested, whether
+    /// or not it has happened yet or succeeded.
+    requested: Vec<bool>,
+}
+
+/// The code of a function which has been tiered up, which is registered for
+/// trap handling as long as it's alive.
+struct TieredFunction {
+    code: Arc<CodeMemory>,
+    wasm_call: FunctionLoc,
+}
+
+impl ModuleTiering {
+    /// Creates the tiered compilation state of a module with the given
+    /// metadata.
+    pub(crate) fn new(
+        module: &wasmtime_environ::Module,
+        offsets: &VMOffsets<HostPtr>,
+    ) -> ModuleTiering {
+        let func_refs = (0..module.num_defined_funcs())
+            .map(|i| {
+                let index = module.func_index(DefinedFuncIndex::new(i));
+                offsets.vmctx_func_ref(module.functions[index].func_ref)
+            })
+            .collect();
+        ModuleTiering {
+            counts: (0..module.num_defined_funcs())
+                .map(|_| AtomicU32::new(0))
+                .collect(),
+            func_refs,
+            wasm: OnceLock::new(),
+            translation: Mutex::new(None),
+            state: Mutex::new(TieringState {
+                requested: vec![false; module.num_defined_funcs()],
+                ..TieringState::default()
+            }),
+        }
+    }
+
+    /// Keeps a copy of the module's original wasm binary so that its hot
+    /// functions can be recompiled.
+    ///
+    /// With a threshold of zero all of the module's functions are queued to
+    /// be tiered up right away.
+    pub(crate) fn retain_wasm(self: &Arc<Self>, engine: &Engine, wasm: &[u8]) {
+        if self.wasm.set(wasm.into()).is_ok() && engine.tunables().tier_up_threshold == 0 {
+            for i in 0..self.counts.len() {
+                self.request_tier_up(engine, DefinedFuncIndex::new(i));
+            }
+        }
+    }
+
+    /// Queues the defined function `index` to be tiered up by `engine`'s
+    /// worker thread, unless that has already been requested.
+    ///
+    /// This is called through the `tier_up` builtin by compiled code once the
+    /// function has been called often enough.
+    pub(crate) fn request_tier_up(self: &Arc<Self>, engine: &Engine, index: DefinedFuncIndex) {
+        if self.wasm.get().is_none() {
+            return;
+        }
+        {
+            let mut state = self.state.lock().unwrap();
+            if core::mem::replace(&mut state.requested[index.index()], true) {
+                return;
+            }
+        }
+        engine
+            .tier_up_worker()
+            .push(engine, Arc::downgrade(self), index);
+    }
+
+    /// Returns the array of call counts which instances' `VMContext`s point
+    /// to.
+    pub(crate) fn call_counts(&self) -> VmPtr<u32> {
+        NonNull::from(&self.counts[..]).cast::<u32>().into()
+    }
+
+    /// Calls `f` with the `wasm_call` pointer of the recompiled code of the
+    /// defined function `index`, if it has been tiered up.
+    ///
+    /// No function is tiered up while `f` runs, so `f` can write a
+    /// `VMFuncRef` for the function without racing with it being updated.
+    pub(crate) fn with_tiered_code<R>(
+        &self,
+        index: DefinedFuncIndex,
+        f: impl FnOnce(Option<VmPtr<VMWasmCallFunction>>) -> R,
+    ) -> R {
+        let state = self.state.lock().unwrap();
+        f(state.tiered.get(&index).map(|f| f.wasm_call_code()))
+    }
+
+    /// Returns the defined function whose recompiled code contains `pc`, and
+    /// the original wasm binary offset of the instruction at `pc`, if any.
+    ///
+    /// This is used to symbolicate frames of tiered-up functions, whose code
+    /// isn't part of their module's.
+    pub(crate) fn lookup_pc(&self, pc: usize) -> Option<(DefinedFuncIndex, Option<FilePos>)> {
+        let state = self.state.lock().unwrap();
+        let i = state.ranges.partition_point(|(range, _)| range.start <= pc);
+        let (range, index) = state.ranges.get(i.checked_sub(1)?)?;
+        if !range.contains(&pc) {
+            return None;
+        }
+        let function = &state.tiered[index];
+        let text_offset = pc - function.code.text().as_ptr().addr();
+        let instr =
+            wasmtime_environ::lookup_file_pos(function.code.address_map_data(), text_offset);
+        Some((*index, instr))
+    }
+
+    /// Registers the `VMContext` of a new instance of the module, pointing
+    /// the `VMFuncRef`s of all functions which have been tiered up at their
+    /// recompiled code.
+    ///
+    /// # Safety
+    ///
+    /// `vmctx` must be a valid `VMContext` of an instance of this module, whose
+    /// `VMFuncRef`s for all defined functions have been initialized, and it
+    /// must be unregistered with `unregister_instance` before it's
+    /// deallocated.
+    pub(crate) unsafe fn register_instance(&self, vmctx: NonNull<VMContext>) {
+        let mut state = self.state.lock().unwrap();
+        for (index, function) in state.tiered.iter() {
+            // SAFETY: the contract of this function is forwarded from ours.
+            unsafe {
+                self.update_func_ref(vmctx, *index, function);
+            }
+        }
+        state.instances.push(SendSyncPtr::new(vmctx));
+    }
+
+    /// Unregisters the `VMContext` of an instance of the module which is about
+    /// to be deallocated, if it was registered.
+    pub(crate) fn unregister_instance(&self, vmctx: NonNull<VMContext>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(i) = state
+            .instances
+            .iter()
+            .position(|p| p.as_non_null() == vmctx)
+        {
+            state.instances.swap_remove(i);
+        }
+    }
+
+    /// Recompiles the defined function `index` and points all instances at
+    /// the new code.
+    fn tier_up(&self, engine: &Engine, index: DefinedFuncIndex) {
+        let Some(wasm) = self.wasm.get() else {
+            return;
+        };
+        let mut translation = self.translation.lock().unwrap();
+
+        // The module is only parsed and validated once, when its first
+        // function is tiered up.
+        if translation.is_none() {
+            match TierUpTranslation::new(engine, wasm.clone()) {
+                Ok(t) => *translation = Some(t),
+                Err(e) => {
+                    log::warn!("failed to translate module for tier-up: {e:?}");
+                    engine
+                        .tier_up_worker()
+                        .stats
+                        .functions_failed
+                        .fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+
+        // Compile without holding the state lock so that instantiation isn't
+        // blocked in the meantime.
+        match TieredFunction::compile(engine, translation.as_mut().unwrap(), index) {
+            Ok(function) => {
+                self.publish(index, function);
+                engine
+                    .tier_up_worker()
+                    .stats
+                    .functions_tiered_up
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            Err(e) => {
+                log::warn!("failed to tier up function {}: {e:?}", index.as_u32());
+                engine
+                    .tier_up_worker()
+                    .stats
+                    .functions_failed
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Points the `VMFuncRef` of the defined function `index` in all live
+    /// instances at its recompiled code.
+    fn publish(&self, index: DefinedFuncIndex, function: TieredFunction) {
+        log::debug!("tiered up function {}", index.as_u32());
+        let mut state = self.state.lock().unwrap();
+        for vmctx in state.instances.iter() {
+            // SAFETY: registered instances are valid until they're
+            // unregistered, which can't happen while the lock is held.
+            unsafe {
+                self.update_func_ref(vmctx.as_non_null(), index, &function);
+            }
+        }
+        let range = function.code_range();
+        let i = state.ranges.partition_point(|(r, _)| r.start < range.start);
+        state.ranges.insert(i, (range, index));
+        state.tiered.insert(index, function);
+    }
+
+    /// Atomically swaps the `wasm_call` pointer of the `VMFuncRef` of the
+    /// defined function `index` within `vmctx` for the recompiled code.
+    ///
+    /// # Safety
+    ///
+    /// `vmctx` must be a valid `VMContext` of an instance of this module, and
+    /// `self.state` must be locked.
+    unsafe fn update_func_ref(
+        &self,
+        vmctx: NonNull<VMContext>,
+        index: DefinedFuncIndex,
+        function: &TieredFunction,
+    ) {
+        let wasm_call = function.wasm_call_code();
+        // SAFETY: `func_refs` holds the offset of a `VMFuncRef` within the
+        // `VMContext` of each instance of this module. The code pointer is
+        // written atomically since compiled code may be reading it on another
+        // thread, which is fine since the old code and the new code are
+        // interchangeable. The `array_call` pointer doesn't need updating
+        // since array-to-Wasm trampolines of modules compiled for tiered
+        // compilation call through `wasm_call`.
+        unsafe {
+            let func_ref = vmctx
+                .byte_add(usize::try_from(self.func_refs[index]).unwrap())
+                .cast::<VMFuncRef>()
+                .as_ptr();
+            AtomicUsize::from_ptr((&raw mut (*func_ref).wasm_call).cast())
+                .store(wasm_call.as_ptr().expose_provenance(), Ordering::Release);
+        }
+    }
+}
+
+impl TieredFunction {
+    /// Recompiles the defined function `index` of the module `translation`.
+    fn compile(
+        engine: &Engine,
+        translation: &mut TierUpTranslation,
+        index: DefinedFuncIndex,
+    ) -> Result<TieredFunction> {
+        let (artifact, table) =
+            crate::compile::build_tier_up_artifacts::<Vec<u8>>(engine, translation, index, &())?;
+        let mmap = MmapVec::from_slice_with_alignment(&artifact, engine.required_code_alignment())?;
+        let mut code = CodeMemory::new(engine, mmap)?;
+        code.publish()?;
+        let code = Arc::new(code);
+
+        let key = FuncKey::DefinedWasmFunction(translation.module_index(), index);
+        let function = TieredFunction {
+            wasm_call: table
+                .func_loc(key)
+                .copied()
+                .ok_or_else(|| anyhow!("missing {key:?} in tier-up artifact"))?,
+            code,
+        };
+        // The corresponding unregister for this is below in `Drop for
+        // TieredFunction`.
+        crate::module::register_code(&function.code, function.code.raw_addr_range());
+        Ok(function)
+    }
+
+    /// Returns the address range of this function's code.
+    fn code_range(&self) -> Range<usize> {
+        let start =
+            self.code.text().as_ptr().addr() + usize::try_from(self.wasm_call.start).unwrap();
+        start..start + usize::try_from(self.wasm_call.length).unwrap()
+    }
+
+    /// Returns the `wasm_call` pointer for this function's `VMFuncRef`s.
+    fn wasm_call_code(&self) -> VmPtr<VMWasmCallFunction> {
+        let text = NonNull::from(self.code.text()).cast::<u8>();
+        // SAFETY: the function's location is within the text section.
+        let code = unsafe { text.add(usize::try_from(self.wasm_call.start).unwrap()) };
+        code.cast().into()
+    }
+}
+
+impl Drop for TieredFunction {
+    fn drop(&mut self) {
+        crate::module::unregister_code(self.code.raw_addr_range());
+    }
+}
+
+/// The background thread of an engine which recompiles hot functions.
+///
+/// The thread is started when the first function is queued to be tiered up,
+/// and sleeps until there's more work to do. It exits once the engine is
+/// dropped.
+#[derive(Default)]
+pub(crate) struct TierUpWorker {
+    queue: Arc<WorkQueue>,
+    started: Once,
+    stats: Stats,
+}
+
+#[derive(Default)]
+struct WorkQueue {
+    state: Mutex<WorkQueueState>,
+    /// Signalled when a function is pushed or the engine is dropped.
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct WorkQueueState {
+    pending: VecDeque<(Weak<ModuleTiering>, DefinedFuncIndex)>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Stats {
+    functions_tiered_up: AtomicU64,
+    functions_failed: AtomicU64,
+}
+
+impl TierUpWorker {
+    /// Queues the defined function `index` of `module` to be tiered up,
+    /// starting the thread if it isn't running yet.
+    fn push(&self, engine: &Engine, module: Weak<ModuleTiering>, index: DefinedFuncIndex) {
+        self.started.call_once(|| {
+            let engine = engine.weak();
+            let queue = self.queue.clone();
+            let spawned = thread::Builder::new()
+                .name("wasmtime-tier-up".to_string())
+                .spawn(move || run(engine, &queue));
+            if let Err(e) = spawned {
+                log::warn!("failed to spawn tier-up thread, functions won't tier up: {e}");
+            }
+        });
+        self.queue
+            .state
+            .lock()
+            .unwrap()
+            .pending
+            .push_back((module, index));
+        self.queue.ready.notify_one();
+    }
+
+    pub(crate) fn stats(&self) -> TierUpStats {
+        TierUpStats {
+            functions_tiered_up: self.stats.functions_tiered_up.load(Ordering::Relaxed),
+            functions_failed: self.stats.functions_failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for TierUpWorker {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().shutdown = true;
+        self.queue.ready.notify_one();
+    }
+}
+
+fn run(engine: EngineWeak, queue: &WorkQueue) {
+    loop {
+        let (module, index) = {
+            let mut state = queue.state.lock().unwrap();
+            loop {
+                if state.shutdown {
+                    return;
+                }
+                if let Some(work) = state.pending.pop_front() {
+                    break work;
+                }
+                state = queue.ready.wait(state).unwrap();
+            }
+        };
+        let Some(module) = module.upgrade() else {
+            continue;
+        };
+        let Some(engine) = engine.upgrade() else {
+            return;
+        };
+        module.tier_up(&engine, index);
+    }
+}
@@ -6,7 +6,9 @@ use crate::prelude::*;
 use crate::store::StoreOpaque;
 use crate::{AsContext, Module};
 use core::fmt;
-use wasmtime_environ::{FilePos, demangle_function_name, demangle_function_name_or_index};
+use wasmtime_environ::{
+    DefinedFuncIndex, FilePos, demangle_function_name, demangle_function_name_or_index,
+};
 
 /// Representation of a WebAssembly trap and what caused it to occur.
 ///
@@ -333,6 +335,11 @@ impl WasmBacktrace {
                 {
                     hint_wasm_backtrace_details_env = true;
                 }
+            } else {
+                #[cfg(feature = "winch")]
+                if let Some(info) = store.modules().lookup_tiered_frame_info(pc_to_lookup) {
+                    wasm_trace.push(info);
+                }
             }
         }
 
@@ -343,6 +350,17 @@ impl WasmBacktrace {
         }
     }
 
+    /// Creates a backtrace out of frames which were not captured from a live
+    /// stack, such as those loaded from a core dump.
+    #[cfg(feature = "coredump")]
+    pub(crate) fn from_frames(wasm_trace: Vec<FrameInfo>) -> WasmBacktrace {
+        WasmBacktrace {
+            wasm_trace,
+            hint_wasm_backtrace_details_env: false,
+            _runtime_trace: crate::runtime::vm::Backtrace::empty(),
+        }
+    }
+
     /// Returns a list of function frames in WebAssembly this backtrace
     /// represents.
     pub fn frames(&self) -> &[FrameInfo] {
@@ -439,12 +457,8 @@ impl FrameInfo {
     pub(crate) fn new(module: Module, text_offset: usize) -> Option<FrameInfo> {
         let compiled_module = module.compiled_module();
         let index = compiled_module.func_by_text_offset(text_offset)?;
-        let func_start = compiled_module.func_start_srcloc(index);
         let instr =
             wasmtime_environ::lookup_file_pos(module.engine_code().address_map_data(), text_offset);
-        let index = compiled_module.module().func_index(index);
-        let func_index = index.as_u32();
-        let func_name = compiled_module.func_name(index).map(|s| s.to_string());
 
         // In debug mode for now assert that we found a mapping for `pc` within
         // the function, because otherwise something is buggy along the way and
@@ -458,6 +472,22 @@ impl FrameInfo {
             "failed to find instruction for {text_offset:#x}"
         );
 
+        Some(FrameInfo::from_instr(module, index, instr))
+    }
+
+    /// Creates frame information for the instruction at the original wasm
+    /// binary offset `instr` within the given defined function.
+    pub(crate) fn from_instr(
+        module: Module,
+        index: DefinedFuncIndex,
+        instr: Option<FilePos>,
+    ) -> FrameInfo {
+        let compiled_module = module.compiled_module();
+        let func_start = compiled_module.func_start_srcloc(index);
+        let index = compiled_module.module().func_index(index);
+        let func_index = index.as_u32();
+        let func_name = compiled_module.func_name(index).map(|s| s.to_string());
+
         // Use our wasm-relative pc to symbolize this frame. If there's a
         // symbolication context (dwarf debug info) available then we can try to
         // look this up there.
@@ -494,14 +524,14 @@ impl FrameInfo {
             }
         }
 
-        Some(FrameInfo {
+        FrameInfo {
             module,
             func_index,
             func_name,
             instr,
             func_start,
             symbols,
-        })
+        }
     }
 
     /// Returns the WebAssembly function index for this frame.
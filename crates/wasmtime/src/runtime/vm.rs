@@ -70,6 +70,8 @@ mod table;
 #[cfg(feature = "gc")]
 mod throw;
 mod traphandlers;
+#[cfg(has_virtual_memory)]
+mod uffd_disabled;
 mod vmcontext;
 
 #[cfg(feature = "threads")]
@@ -116,8 +118,11 @@ pub use crate::runtime::vm::stack_switching::*;
 pub use crate::runtime::vm::store_box::*;
 #[cfg(feature = "std")]
 pub use crate::runtime::vm::sys::mmap::open_file_for_mmap;
+pub use crate::runtime::vm::sys::thread_cpu_time;
 #[cfg(has_host_compiler_backend)]
 pub use crate::runtime::vm::sys::unwind::UnwindRegistration;
+#[cfg(has_virtual_memory)]
+pub use crate::runtime::vm::sys::vm::{Userfaultfd, UserfaultfdRegistration, resident_bytes};
 pub use crate::runtime::vm::table::{Table, TableElementType};
 #[cfg(feature = "gc")]
 pub use crate::runtime::vm::throw::*;
@@ -370,7 +375,6 @@ impl ModuleRuntimeInfo {
     /// A unique ID for this particular module. This can be used to
     /// allow for fastpaths to optimize a "re-instantiate the same
     /// module again" case.
-    #[cfg(feature = "pooling-allocator")]
     fn unique_id(&self) -> Option<CompiledModuleId> {
         match self {
             ModuleRuntimeInfo::Module(m) => Some(m.id()),
@@ -144,6 +144,31 @@ impl MemoryImage {
         Ok(None)
     }
 
+    /// Creates an image of the first `len` bytes of `source`'s data, placed
+    /// at the start of linear memory.
+    ///
+    /// This is used for images that aren't part of a module, such as the
+    /// contents of a memory in a store snapshot. Returns `None` if this
+    /// platform has no way to map the data copy-on-write.
+    pub fn from_source(
+        engine: &Engine,
+        source: &Arc<impl ModuleMemoryImageSource>,
+        len: HostAlignedByteCount,
+    ) -> Result<Option<MemoryImage>> {
+        MemoryImage::new(
+            engine,
+            u32::try_from(host_page_size()).unwrap(),
+            HostAlignedByteCount::ZERO,
+            source,
+            0..len.byte_count(),
+        )
+    }
+
+    /// Returns the offset in linear memory just past the end of this image.
+    pub(crate) fn end(&self) -> usize {
+        self.linear_memory_offset.byte_count() + self.len.byte_count()
+    }
+
     unsafe fn map_at(&self, mmap_base: &MmapOffset) -> Result<()> {
         unsafe {
             mmap_base.map_image_at(
@@ -201,6 +226,15 @@ impl ModuleMemoryImages {
                 }
             };
 
+            // Likewise memories which are lazily initialized by the engine's
+            // userfaultfd are populated directly from the module's data, so
+            // only shared memories, which can't be registered with it, need
+            // an image.
+            if engine.config().memory_init_userfaultfd && !module.memories[memory_index].shared {
+                memories.push(None);
+                continue;
+            }
+
             let data_range = init.data.start as usize..init.data.end as usize;
             if module.memories[memory_index]
                 .minimum_byte_size()
@@ -499,14 +533,42 @@ impl MemoryImageSlot {
 
     pub(crate) fn remove_image(&mut self) -> Result<()> {
         if let Some(image) = &self.image {
-            unsafe {
-                image.remap_as_zeros_at(self.base.as_mut_ptr())?;
+            if !image.len.is_zero() {
+                unsafe {
+                    image.remap_as_zeros_at(self.base.as_mut_ptr())?;
+                }
             }
             self.image = None;
         }
         Ok(())
     }
 
+    /// Replaces the entire contents of this slot's accessible memory with
+    /// `image`, mapped copy-on-write.
+    ///
+    /// Unlike `instantiate` this may be used on a slot that's in use, for
+    /// example to restore a snapshot into a memory that has already been
+    /// initialized. Whatever was previously written to the slot is discarded.
+    pub(crate) fn replace_image(&mut self, image: &Arc<MemoryImage>) -> Result<()> {
+        assert!(image.end() <= self.accessible.byte_count());
+
+        // Remove the previous image, if any, and then zero everything else
+        // that's been written to the slot before mapping in the new image.
+        self.remove_image()?;
+        unsafe {
+            vm::decommit_pages(self.base.as_mut_ptr(), self.accessible.byte_count())?;
+            if !image.len.is_zero() {
+                image.map_at(&self.base)?;
+            }
+        }
+        self.image = Some(image.clone());
+
+        // The slot now needs to be reset before it's reused, just like after
+        // `instantiate`.
+        self.dirty = true;
+        Ok(())
+    }
+
     /// Resets this linear memory slot back to a "pristine state".
     ///
     /// This will reset the memory back to its original contents on Linux or
@@ -68,9 +68,13 @@ impl GcStore {
     }
 
     /// Asynchronously perform garbage collection within this heap.
-    pub async fn gc(&mut self, async_yield: bool, roots: GcRootsIter<'_>) {
+    ///
+    /// Returns the number of bytes that the collection reclaimed.
+    pub async fn gc(&mut self, async_yield: bool, roots: GcRootsIter<'_>) -> usize {
+        let allocated_before = self.gc_heap.allocated_bytes();
         let collection = self.gc_heap.gc(roots, &mut self.host_data_table);
         collect_async(collection, async_yield).await;
+        allocated_before.saturating_sub(self.gc_heap.allocated_bytes())
     }
 
     /// Get the kind of the given GC reference.
@@ -0,0 +1,857 @@
+//! The semi-space copying collector.
+//!
+//! This is a non-generational, stop-the-world, Cheney-style copying
+//! collector. The GC heap is logically split into an *active* region, where new
+//! objects are bump allocated, and free space that is large enough to hold
+//! every object in the active region. When the active region fills up, the
+//! collector evacuates every object that is reachable from the roots into the
+//! free space (the "to-space"), updates every reference to a moved object, and
+//! then makes the to-space the new active region. Anything left behind in the
+//! old active region (the "from-space") is garbage, and its storage is
+//! reclaimed wholesale.
+//!
+//! Because the GC heap is a single, growable linear memory rather than two
+//! fixed halves, the active region is described by the range
+//! `active_start..limit`, and we maintain the invariant that at least one of
+//! `HEAP_START..active_start` or `limit..heap_end` is as large as the active
+//! region. That region is used as the to-space during the next collection.
+//! Growing the GC heap raises `limit` as far as this invariant allows.
+//!
+//! Objects move during collection, so every GC reference the collector cannot
+//! see through the roots is a bug. In exchange, there are no read or write
+//! barriers, cycles are reclaimed like any other garbage, and allocation is a
+//! simple pointer bump.
+//!
+//! Each object's header records the object's size, so that the from-space can
+//! be walked linearly after evacuation to find unreachable `externref`s whose
+//! host data must be dropped, and a forwarding reference, which is set once the
+//! object has been copied into to-space.
+
+use super::*;
+use crate::hash_map::HashMap;
+use crate::runtime::vm::{
+    ExternRefHostDataId, ExternRefHostDataTable, GarbageCollection, GcHeap, GcHeapObject,
+    GcProgress, GcRootsIter, GcRuntime, TypedGcRef, VMGcHeader, VMGcRef, VMMemoryDefinition,
+};
+use crate::{Engine, EngineWeak, prelude::*};
+use core::sync::atomic::AtomicUsize;
+use core::{alloc::Layout, any::Any, mem, num::NonZeroU32, ops::Range, ptr::NonNull};
+use serde_derive::{Deserialize, Serialize};
+use wasmtime_environ::copying::{ALLOC_GRANULARITY, ARRAY_LENGTH_OFFSET, CopyingTypeLayouts};
+use wasmtime_environ::{
+    GcArrayLayout, GcLayout, GcStructLayout, GcTypeLayouts, VMGcKind, VMSharedTypeIndex,
+};
+
+#[expect(clippy::cast_possible_truncation, reason = "known to not overflow")]
+const GC_REF_ARRAY_ELEMS_OFFSET: u32 = ARRAY_LENGTH_OFFSET + (mem::size_of::<u32>() as u32);
+
+/// The index of the first object in the GC heap.
+///
+/// Index zero is the null reference, and every object must begin on an
+/// `ALLOC_GRANULARITY` boundary.
+const HEAP_START: u32 = ALLOC_GRANULARITY;
+
+/// The semi-space copying collector.
+///
+/// This is a moving collector: live objects are evacuated to a fresh region of
+/// the GC heap during every collection.
+#[derive(Default)]
+pub struct CopyingCollector {
+    layouts: CopyingTypeLayouts,
+}
+
+unsafe impl GcRuntime for CopyingCollector {
+    fn layouts(&self) -> &dyn GcTypeLayouts {
+        &self.layouts
+    }
+
+    fn new_gc_heap(&self, engine: &Engine) -> Result<Box<dyn GcHeap>> {
+        let heap = CopyingHeap::new(engine)?;
+        Ok(Box::new(heap) as _)
+    }
+}
+
+/// How to trace a GC object.
+enum TraceInfo {
+    /// How to trace an array.
+    Array {
+        /// Whether this array type's elements are GC references, and need
+        /// tracing.
+        gc_ref_elems: bool,
+    },
+
+    /// How to trace a struct.
+    Struct {
+        /// The offsets of each GC reference field that needs tracing in
+        /// instances of this struct type.
+        gc_ref_offsets: Box<[u32]>,
+    },
+}
+
+/// A GC heap for the copying collector.
+struct CopyingHeap {
+    engine: EngineWeak,
+
+    /// For every type that we have allocated in this heap, how do we trace it?
+    trace_infos: HashMap<VMSharedTypeIndex, TraceInfo>,
+
+    /// Count of how many no-gc scopes we are currently within.
+    no_gc_count: u64,
+
+    /// The start of the active region, where new objects are allocated.
+    active_start: u32,
+
+    /// Bump-allocation finger indexing within `active_start..limit`.
+    next: u32,
+
+    /// The end of the active region.
+    ///
+    /// Invariant: `limit - active_start` is less than or equal to either
+    /// `active_start - HEAP_START` or `heap_end - limit`.
+    limit: u32,
+
+    /// The storage for the GC heap itself.
+    memory: Option<crate::vm::Memory>,
+
+    /// The cached `VMMemoryDefinition` for `self.memory` so that we don't have
+    /// to make indirect calls through a `dyn RuntimeLinearMemory` object.
+    ///
+    /// Must be updated and kept in sync with `self.memory`, cleared when the
+    /// memory is taken and updated when the memory is replaced.
+    vmmemory: Option<VMMemoryDefinition>,
+
+    /// Scratch space for the offsets of an object's outgoing GC edges during
+    /// collection.
+    ///
+    /// We store this here to reuse the storage and avoid repeated allocations.
+    edges: Option<Vec<u32>>,
+}
+
+/// The bookkeeping of a `CopyingHeap`, as captured by
+/// `GcHeap::snapshot_state`.
+#[derive(Serialize, Deserialize)]
+struct CopyingHeapState {
+    active_start: u32,
+    next: u32,
+    limit: u32,
+}
+
+/// Convert the given GC reference as a typed GC reference pointing to a
+/// `VMCopyingHeader`.
+fn copying_ref(gc_ref: &VMGcRef) -> &TypedGcRef<VMCopyingHeader> {
+    debug_assert!(!gc_ref.is_i31());
+    gc_ref.as_typed_unchecked()
+}
+
+/// Convert a generic `externref` to a typed reference to our concrete
+/// `externref` type.
+fn externref_to_copying(externref: &VMExternRef) -> &TypedGcRef<VMCopyingExternRef> {
+    let gc_ref = externref.as_gc_ref();
+    debug_assert!(!gc_ref.is_i31());
+    gc_ref.as_typed_unchecked()
+}
+
+/// Create a GC reference to the object at the given heap index.
+fn gc_ref_at(index: u32) -> VMGcRef {
+    VMGcRef::from_heap_index(NonZeroU32::new(index).unwrap()).unwrap()
+}
+
+/// The common header for all objects in the copying collector.
+#[repr(C)]
+struct VMCopyingHeader {
+    header: VMGcHeader,
+
+    /// The size of this object, rounded up to `ALLOC_GRANULARITY`.
+    object_size: u32,
+
+    /// This object's new location in to-space, if it has been evacuated during
+    /// the current collection.
+    forwarding: Option<VMGcRef>,
+}
+
+unsafe impl GcHeapObject for VMCopyingHeader {
+    #[inline]
+    fn is(_header: &VMGcHeader) -> bool {
+        // All copying-collector objects have a copying header.
+        true
+    }
+}
+
+/// The common header for all arrays in the copying collector.
+#[repr(C)]
+struct VMCopyingArrayHeader {
+    header: VMCopyingHeader,
+    length: u32,
+}
+
+unsafe impl GcHeapObject for VMCopyingArrayHeader {
+    #[inline]
+    fn is(header: &VMGcHeader) -> bool {
+        header.kind() == VMGcKind::ArrayRef
+    }
+}
+
+/// The representation of an `externref` in the copying collector.
+#[repr(C)]
+struct VMCopyingExternRef {
+    header: VMCopyingHeader,
+    host_data: ExternRefHostDataId,
+}
+
+unsafe impl GcHeapObject for VMCopyingExternRef {
+    #[inline]
+    fn is(header: &VMGcHeader) -> bool {
+        header.kind() == VMGcKind::ExternRef
+    }
+}
+
+impl CopyingHeap {
+    /// Construct a new, default copying heap.
+    fn new(engine: &Engine) -> Result<Self> {
+        log::trace!("allocating new copying heap");
+        Ok(Self {
+            engine: engine.weak(),
+            trace_infos: HashMap::with_capacity(1),
+            no_gc_count: 0,
+            active_start: HEAP_START,
+            next: HEAP_START,
+            limit: HEAP_START,
+            memory: None,
+            vmmemory: None,
+            edges: Some(Vec::with_capacity(1)),
+        })
+    }
+
+    fn engine(&self) -> Engine {
+        self.engine.upgrade().unwrap()
+    }
+
+    /// The end of the usable portion of the GC heap.
+    ///
+    /// This is the heap's length, clamped to the range of indices that a
+    /// `VMGcRef` can represent and rounded down to `ALLOC_GRANULARITY`.
+    fn heap_end(&self) -> u32 {
+        let len = self.vmmemory.as_ref().unwrap().current_length();
+        let len = u32::try_from(len).unwrap_or(u32::MAX);
+        let len = len & !(ALLOC_GRANULARITY - 1);
+        len.max(HEAP_START)
+    }
+
+    /// Raise `limit` as far as possible while maintaining the invariant that
+    /// there is always a to-space at least as large as the active region.
+    fn update_limit(&mut self) {
+        let heap_end = u64::from(self.heap_end());
+        let active_start = u64::from(self.active_start);
+        debug_assert!(active_start <= heap_end);
+
+        // Use the space below the active region as the to-space.
+        let below = heap_end.min(active_start + (active_start - u64::from(HEAP_START)));
+
+        // Use the space above the active region as the to-space, splitting it
+        // in half.
+        let above = ((active_start + heap_end) / 2) & !u64::from(ALLOC_GRANULARITY - 1);
+
+        let limit = below.max(above);
+        let limit = u32::try_from(limit).unwrap();
+        self.limit = self.limit.max(self.next).max(limit);
+
+        debug_assert!(self.active_start <= self.next);
+        debug_assert!(self.next <= self.limit);
+        debug_assert!(u64::from(self.limit) <= heap_end);
+        debug_assert!(
+            self.limit - self.active_start
+                <= (self.active_start - HEAP_START).max(self.heap_end() - self.limit)
+        );
+    }
+
+    /// How many more bytes the GC heap must grow by before an allocation of
+    /// `size` bytes is guaranteed to succeed without a collection.
+    fn bytes_needed(&self, size: u32) -> u64 {
+        // Growing the heap by `delta` bytes raises `limit` to at least
+        // `(active_start + heap_end + delta) / 2`, rounded down.
+        let end_of_object = u64::from(self.next) + u64::from(size);
+        let needed = (2 * end_of_object + u64::from(ALLOC_GRANULARITY))
+            .saturating_sub(u64::from(self.active_start) + u64::from(self.heap_end()));
+        needed.max(u64::from(size))
+    }
+
+    /// Attempt to bump-allocate an object with the given layout and header.
+    fn alloc(&mut self, header: VMGcHeader, layout: Layout) -> Result<Result<VMGcRef, u64>> {
+        debug_assert!(layout.size() >= mem::size_of::<VMCopyingHeader>());
+        debug_assert!(layout.align() >= mem::align_of::<VMCopyingHeader>());
+
+        // Every object starts on an `ALLOC_GRANULARITY` boundary, so we cannot
+        // satisfy any larger alignment.
+        if layout.align() > usize::try_from(ALLOC_GRANULARITY).unwrap() {
+            return Err(crate::Trap::AllocationTooLarge.into());
+        }
+
+        let size = match u32::try_from(layout.size())
+            .ok()
+            .and_then(|size| size.checked_next_multiple_of(ALLOC_GRANULARITY))
+        {
+            Some(size) => size,
+            None => return Err(crate::Trap::AllocationTooLarge.into()),
+        };
+
+        // We must have trace info for every GC type that we allocate in this
+        // heap. The only kinds of GC objects we allocate that do not have an
+        // associated `VMSharedTypeIndex` are `externref`s, and they don't have
+        // any GC edges.
+        if let Some(ty) = header.ty() {
+            self.ensure_trace_info(ty);
+        } else {
+            debug_assert_eq!(header.kind(), VMGcKind::ExternRef);
+        }
+
+        let end_of_object = u64::from(self.next) + u64::from(size);
+        if end_of_object > u64::from(self.limit) {
+            return Ok(Err(self.bytes_needed(size)));
+        }
+
+        let gc_ref = gc_ref_at(self.next);
+        self.next = u32::try_from(end_of_object).unwrap();
+        *self.index_mut(copying_ref(&gc_ref)) = VMCopyingHeader {
+            header,
+            object_size: size,
+            forwarding: None,
+        };
+        Ok(Ok(gc_ref))
+    }
+
+    /// Deallocate the given object if it was the most recent allocation;
+    /// otherwise it is simply left behind as garbage.
+    fn dealloc(&mut self, gc_ref: VMGcRef) {
+        let index = gc_ref.as_heap_index().unwrap().get();
+        let size = self.index(copying_ref(&gc_ref)).object_size;
+        if index + size == self.next {
+            self.next = index;
+        }
+    }
+
+    /// Ensure that we have tracing information for the given type.
+    fn ensure_trace_info(&mut self, ty: VMSharedTypeIndex) {
+        if self.trace_infos.contains_key(&ty) {
+            return;
+        }
+
+        self.insert_new_trace_info(ty);
+    }
+
+    fn insert_new_trace_info(&mut self, ty: VMSharedTypeIndex) {
+        debug_assert!(!self.trace_infos.contains_key(&ty));
+
+        let engine = self.engine();
+        let gc_layout = engine
+            .signatures()
+            .layout(ty)
+            .unwrap_or_else(|| panic!("should have a GC layout for {ty:?}"));
+
+        let info = match gc_layout {
+            GcLayout::Array(l) => {
+                if l.elems_are_gc_refs {
+                    debug_assert_eq!(l.elem_offset(0), GC_REF_ARRAY_ELEMS_OFFSET);
+                }
+                TraceInfo::Array {
+                    gc_ref_elems: l.elems_are_gc_refs,
+                }
+            }
+            GcLayout::Struct(l) => TraceInfo::Struct {
+                gc_ref_offsets: l
+                    .fields
+                    .iter()
+                    .filter_map(|f| if f.is_gc_ref { Some(f.offset) } else { None })
+                    .collect(),
+            },
+        };
+
+        let old_entry = self.trace_infos.insert(ty, info);
+        debug_assert!(old_entry.is_none());
+    }
+
+    /// Enumerate the offsets, within the given object, of each of its outgoing
+    /// GC edges.
+    fn trace_gc_ref(&self, gc_ref: &VMGcRef, edges: &mut Vec<u32>) {
+        debug_assert!(!gc_ref.is_i31());
+
+        let header = self.header(gc_ref);
+        let Some(ty) = header.ty() else {
+            debug_assert!(header.kind().matches(VMGcKind::ExternRef));
+            return;
+        };
+        match self
+            .trace_infos
+            .get(&ty)
+            .expect("should have inserted trace info for every GC type allocated in this heap")
+        {
+            TraceInfo::Struct { gc_ref_offsets } => {
+                edges.extend_from_slice(gc_ref_offsets);
+            }
+            TraceInfo::Array { gc_ref_elems } => {
+                if !*gc_ref_elems {
+                    return;
+                }
+
+                let len = self.array_len(gc_ref.as_arrayref_unchecked());
+                edges.reserve(usize::try_from(len).unwrap());
+                for i in 0..len {
+                    let elem_offset = GC_REF_ARRAY_ELEMS_OFFSET
+                        + i * u32::try_from(mem::size_of::<u32>()).unwrap();
+                    edges.push(elem_offset);
+                }
+            }
+        }
+    }
+
+    /// Copy the given object into to-space, unless it was already copied, and
+    /// return its new location.
+    fn forward(&mut self, gc_ref: &VMGcRef, to_space: &mut Range<u32>) -> VMGcRef {
+        debug_assert!(!gc_ref.is_i31());
+
+        let header = self.index(copying_ref(gc_ref));
+        if let Some(forwarded) = &header.forwarding {
+            return forwarded.unchecked_copy();
+        }
+
+        let size = header.object_size;
+        let new_index = to_space.start;
+        to_space.start += size;
+        assert!(
+            to_space.start <= to_space.end,
+            "to-space should always be large enough to hold every live object"
+        );
+
+        let src = usize::try_from(gc_ref.as_heap_index().unwrap().get()).unwrap();
+        let len = usize::try_from(size).unwrap();
+        let dst = usize::try_from(new_index).unwrap();
+        self.heap_slice_mut().copy_within(src..src + len, dst);
+
+        let new_ref = gc_ref_at(new_index);
+        log::trace!("evacuated {gc_ref:#p} to {new_ref:#p}");
+        self.index_mut(copying_ref(gc_ref)).forwarding = Some(new_ref.unchecked_copy());
+        new_ref
+    }
+
+    /// Evacuate every object reachable from the given roots into to-space,
+    /// update the roots, and make to-space the new active region.
+    ///
+    /// Returns the old active region, which now only contains garbage and
+    /// forwarding references.
+    fn evacuate(&mut self, roots: &mut GcRootsIter<'_>) -> Range<u32> {
+        let from_space = self.active_start..self.next;
+
+        // Pick whichever free region is larger; the `limit` invariant ensures
+        // that it is large enough to hold the whole active region.
+        let heap_end = self.heap_end();
+        let to_space = if self.active_start - HEAP_START >= heap_end - self.limit {
+            HEAP_START..self.active_start
+        } else {
+            self.limit..heap_end
+        };
+        debug_assert!(to_space.len() >= from_space.len());
+        log::trace!("evacuating {from_space:#x?} into {to_space:#x?}");
+
+        // `free` is the unused portion of to-space; its start is the bump
+        // pointer for evacuated objects.
+        let mut free = to_space.clone();
+
+        for mut root in roots {
+            let gc_ref = root.get();
+
+            if gc_ref.is_i31() {
+                continue;
+            }
+
+            let new_ref = self.forward(&gc_ref, &mut free);
+            root.set(new_ref);
+        }
+
+        // Scan the evacuated objects in to-space, evacuating the objects they
+        // reference in turn, until we have caught up with the bump pointer.
+        let mut edges = self.edges.take().unwrap();
+        let mut scan = to_space.start;
+        while scan < free.start {
+            let gc_ref = gc_ref_at(scan);
+
+            debug_assert!(edges.is_empty());
+            self.trace_gc_ref(&gc_ref, &mut edges);
+            for offset in edges.drain(..) {
+                let raw = self.gc_object_data(&gc_ref).read_u32(offset);
+                let Some(edge) = VMGcRef::from_raw_u32(raw) else {
+                    continue;
+                };
+                if edge.is_i31() {
+                    continue;
+                }
+                let new_edge = self.forward(&edge, &mut free);
+                self.gc_object_data_mut(&gc_ref)
+                    .write_u32(offset, new_edge.as_raw_u32());
+            }
+
+            scan += self.index(copying_ref(&gc_ref)).object_size;
+        }
+        debug_assert_eq!(scan, free.start);
+        self.edges = Some(edges);
+
+        // Flip: to-space becomes the new active region.
+        self.active_start = to_space.start;
+        self.next = free.start;
+        self.limit = free.start;
+        self.update_limit();
+        log::trace!(
+            "evacuated {} bytes; active region is now {:#x}..{:#x}",
+            self.next - self.active_start,
+            self.active_start,
+            self.limit,
+        );
+
+        from_space
+    }
+
+    /// Walk the from-space after evacuation and drop the host data of every
+    /// `externref` that was not evacuated.
+    fn sweep(&mut self, host_data_table: &mut ExternRefHostDataTable, from_space: Range<u32>) {
+        let mut index = from_space.start;
+        while index < from_space.end {
+            let gc_ref = gc_ref_at(index);
+            let header = self.index(copying_ref(&gc_ref));
+            index += header.object_size;
+
+            if header.forwarding.is_some() || header.header.kind() != VMGcKind::ExternRef {
+                continue;
+            }
+
+            log::trace!("sweeping unreachable externref {gc_ref:#p}");
+            let host_data = self
+                .index::<VMCopyingExternRef>(gc_ref.as_typed_unchecked())
+                .host_data;
+            host_data_table.dealloc(host_data);
+        }
+        debug_assert_eq!(index, from_space.end);
+    }
+}
+
+unsafe impl GcHeap for CopyingHeap {
+    fn is_attached(&self) -> bool {
+        debug_assert_eq!(self.memory.is_some(), self.vmmemory.is_some());
+        self.memory.is_some()
+    }
+
+    fn attach(&mut self, memory: crate::vm::Memory) {
+        assert!(!self.is_attached());
+        assert!(!memory.is_shared_memory());
+        self.vmmemory = Some(memory.vmmemory());
+        self.memory = Some(memory);
+        self.active_start = HEAP_START;
+        self.next = HEAP_START;
+        self.limit = HEAP_START;
+        self.update_limit();
+    }
+
+    fn detach(&mut self) -> crate::vm::Memory {
+        assert!(self.is_attached());
+
+        let CopyingHeap {
+            engine: _,
+            no_gc_count,
+            active_start,
+            next,
+            limit,
+            memory,
+            vmmemory,
+            edges,
+
+            // NB: we will only ever be reused with the same engine, so no need
+            // to clear out our tracing info just to fill it back in with the
+            // same exact stuff.
+            trace_infos: _,
+        } = self;
+
+        *no_gc_count = 0;
+        *active_start = HEAP_START;
+        *next = HEAP_START;
+        *limit = HEAP_START;
+        *vmmemory = None;
+        debug_assert!(edges.as_ref().is_some_and(|e| e.is_empty()));
+
+        memory.take().unwrap()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self as _
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self as _
+    }
+
+    fn enter_no_gc_scope(&mut self) {
+        self.no_gc_count += 1;
+    }
+
+    fn exit_no_gc_scope(&mut self) {
+        self.no_gc_count -= 1;
+    }
+
+    fn clone_gc_ref(&mut self, gc_ref: &VMGcRef) -> VMGcRef {
+        gc_ref.unchecked_copy()
+    }
+
+    fn write_gc_ref(
+        &mut self,
+        _host_data_table: &mut ExternRefHostDataTable,
+        destination: &mut Option<VMGcRef>,
+        source: Option<&VMGcRef>,
+    ) {
+        *destination = source.map(|s| s.unchecked_copy());
+    }
+
+    fn expose_gc_ref_to_wasm(&mut self, _gc_ref: VMGcRef) {
+        // Don't need to do anything special here.
+    }
+
+    fn alloc_externref(
+        &mut self,
+        host_data: ExternRefHostDataId,
+    ) -> Result<Result<VMExternRef, u64>> {
+        let gc_ref =
+            match self.alloc(VMGcHeader::externref(), Layout::new::<VMCopyingExternRef>())? {
+                Err(n) => return Ok(Err(n)),
+                Ok(gc_ref) => gc_ref,
+            };
+        self.index_mut::<VMCopyingExternRef>(gc_ref.as_typed_unchecked())
+            .host_data = host_data;
+        Ok(Ok(gc_ref.into_externref_unchecked()))
+    }
+
+    fn externref_host_data(&self, externref: &VMExternRef) -> ExternRefHostDataId {
+        let typed_ref = externref_to_copying(externref);
+        self.index(typed_ref).host_data
+    }
+
+    fn header(&self, gc_ref: &VMGcRef) -> &VMGcHeader {
+        self.index(gc_ref.as_typed_unchecked())
+    }
+
+    fn header_mut(&mut self, gc_ref: &VMGcRef) -> &mut VMGcHeader {
+        self.index_mut(gc_ref.as_typed_unchecked())
+    }
+
+    fn object_size(&self, gc_ref: &VMGcRef) -> usize {
+        usize::try_from(self.index(copying_ref(gc_ref)).object_size).unwrap()
+    }
+
+    fn alloc_raw(&mut self, header: VMGcHeader, layout: Layout) -> Result<Result<VMGcRef, u64>> {
+        self.alloc(header, layout)
+    }
+
+    fn alloc_uninit_struct_or_exn(
+        &mut self,
+        ty: VMSharedTypeIndex,
+        layout: &GcStructLayout,
+    ) -> Result<Result<VMGcRef, u64>> {
+        let kind = if layout.is_exception {
+            VMGcKind::ExnRef
+        } else {
+            VMGcKind::StructRef
+        };
+        self.alloc(VMGcHeader::from_kind_and_index(kind, ty), layout.layout())
+    }
+
+    fn dealloc_uninit_struct_or_exn(&mut self, gc_ref: VMGcRef) {
+        self.dealloc(gc_ref);
+    }
+
+    fn alloc_uninit_array(
+        &mut self,
+        ty: VMSharedTypeIndex,
+        length: u32,
+        layout: &GcArrayLayout,
+    ) -> Result<Result<VMArrayRef, u64>> {
+        let gc_ref = match self.alloc(
+            VMGcHeader::from_kind_and_index(VMGcKind::ArrayRef, ty),
+            layout.layout(length),
+        )? {
+            Err(n) => return Ok(Err(n)),
+            Ok(gc_ref) => gc_ref,
+        };
+
+        self.index_mut(gc_ref.as_typed_unchecked::<VMCopyingArrayHeader>())
+            .length = length;
+
+        Ok(Ok(gc_ref.into_arrayref_unchecked()))
+    }
+
+    fn dealloc_uninit_array(&mut self, arrayref: VMArrayRef) {
+        self.dealloc(arrayref.into())
+    }
+
+    fn array_len(&self, arrayref: &VMArrayRef) -> u32 {
+        debug_assert!(arrayref.as_gc_ref().is_typed::<VMCopyingArrayHeader>(self));
+        self.index::<VMCopyingArrayHeader>(arrayref.as_gc_ref().as_typed_unchecked())
+            .length
+    }
+
+    fn allocated_bytes(&self) -> usize {
+        usize::try_from(self.next - self.active_start).unwrap()
+    }
+
+    fn gc<'a>(
+        &'a mut self,
+        roots: GcRootsIter<'a>,
+        host_data_table: &'a mut ExternRefHostDataTable,
+    ) -> Box<dyn GarbageCollection<'a> + 'a> {
+        assert_eq!(self.no_gc_count, 0, "Cannot GC inside a no-GC scope!");
+        Box::new(CopyingCollection {
+            roots,
+            host_data_table,
+            heap: self,
+            phase: CopyingCollectionPhase::Evacuate,
+        })
+    }
+
+    fn snapshot_state(&self) -> Result<Vec<u8>> {
+        Ok(postcard::to_allocvec(&CopyingHeapState {
+            active_start: self.active_start,
+            next: self.next,
+            limit: self.limit,
+        })?)
+    }
+
+    fn restore_state(&mut self, snapshot_len: usize, state: &[u8]) -> Result<()> {
+        assert_eq!(self.no_gc_count, 0, "Cannot restore inside a no-GC scope!");
+        let CopyingHeapState {
+            active_start,
+            next,
+            limit,
+        } = postcard::from_bytes(state)?;
+
+        let snapshot_end = u32::try_from(snapshot_len).unwrap_or(u32::MAX);
+        ensure!(
+            HEAP_START <= active_start
+                && active_start <= next
+                && next <= limit
+                && limit <= snapshot_end.min(self.heap_end())
+                && limit - active_start <= (active_start - HEAP_START).max(self.heap_end() - limit),
+            "invalid active region in copying GC heap snapshot"
+        );
+        self.active_start = active_start;
+        self.next = next;
+        self.limit = limit;
+        self.update_limit();
+
+        // Walk the active region to recreate the tracing info for every type
+        // of object in it.
+        let mut index = self.active_start;
+        while index < self.next {
+            let gc_ref = gc_ref_at(index);
+            let header = self.index(copying_ref(&gc_ref));
+            let size = header.object_size;
+            ensure!(
+                size > 0 && size % ALLOC_GRANULARITY == 0,
+                "invalid object size in copying GC heap snapshot"
+            );
+            if let Some(ty) = header.header.ty() {
+                self.ensure_trace_info(ty);
+            }
+            index = index.saturating_add(size);
+        }
+        ensure!(
+            index == self.next,
+            "invalid object size in copying GC heap snapshot"
+        );
+
+        Ok(())
+    }
+
+    unsafe fn vmctx_gc_heap_data(&self) -> NonNull<u8> {
+        // Compiled code doesn't access any collector-specific data for this
+        // collector, so just hand out a valid pointer into `self`.
+        NonNull::from(&self.next).cast()
+    }
+
+    fn take_memory(&mut self) -> crate::vm::Memory {
+        debug_assert!(self.is_attached());
+        self.vmmemory.take();
+        self.memory.take().unwrap()
+    }
+
+    unsafe fn replace_memory(&mut self, memory: crate::vm::Memory, _delta_bytes_grown: u64) {
+        debug_assert!(self.memory.is_none());
+        debug_assert!(!memory.is_shared_memory());
+        self.vmmemory = Some(memory.vmmemory());
+        self.memory = Some(memory);
+        self.update_limit();
+    }
+
+    #[inline]
+    fn vmmemory(&self) -> VMMemoryDefinition {
+        debug_assert!(self.is_attached());
+        debug_assert!(!self.memory.as_ref().unwrap().is_shared_memory());
+        let vmmemory = self.vmmemory.as_ref().unwrap();
+        VMMemoryDefinition {
+            base: vmmemory.base,
+            current_length: AtomicUsize::new(vmmemory.current_length()),
+        }
+    }
+}
+
+struct CopyingCollection<'a> {
+    roots: GcRootsIter<'a>,
+    host_data_table: &'a mut ExternRefHostDataTable,
+    heap: &'a mut CopyingHeap,
+    phase: CopyingCollectionPhase,
+}
+
+enum CopyingCollectionPhase {
+    Evacuate,
+    Sweep { from_space: Range<u32> },
+    Done,
+}
+
+impl<'a> GarbageCollection<'a> for CopyingCollection<'a> {
+    fn collect_increment(&mut self) -> GcProgress {
+        match &self.phase {
+            CopyingCollectionPhase::Evacuate => {
+                log::trace!("Begin copying evacuation");
+                let from_space = self.heap.evacuate(&mut self.roots);
+                log::trace!("End copying evacuation");
+                self.phase = CopyingCollectionPhase::Sweep { from_space };
+                GcProgress::Continue
+            }
+            CopyingCollectionPhase::Sweep { from_space } => {
+                log::trace!("Begin copying sweep");
+                self.heap.sweep(self.host_data_table, from_space.clone());
+                log::trace!("End copying sweep");
+                self.phase = CopyingCollectionPhase::Done;
+                GcProgress::Complete
+            }
+            CopyingCollectionPhase::Done => GcProgress::Complete,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vm_copying_header_size_align() {
+        assert_eq!(
+            (wasmtime_environ::copying::HEADER_SIZE as usize),
+            core::mem::size_of::<VMCopyingHeader>()
+        );
+        assert_eq!(
+            (wasmtime_environ::copying::HEADER_ALIGN as usize),
+            core::mem::align_of::<VMCopyingHeader>()
+        );
+    }
+
+    #[test]
+    fn vm_copying_array_header_length_offset() {
+        assert_eq!(
+            wasmtime_environ::copying::ARRAY_LENGTH_OFFSET,
+            u32::try_from(core::mem::offset_of!(VMCopyingArrayHeader, length)).unwrap(),
+        );
+    }
+}
@@ -1,8 +1,5 @@
 //! The deferred reference-counting (DRC) collector.
 //!
-//! Warning: this ref-counting collector does not have a tracing cycle
-//! collector, and therefore cannot collect cycles between GC objects!
-//!
 //! For host VM code, we use plain reference counting, where cloning increments
 //! the reference count, and dropping decrements it. We can avoid many of the
 //! on-stack increment/decrement operations that typically plague the
@@ -40,6 +37,12 @@
 //! The precise set of stack roots is implemented with a mark bit in the object
 //! header. See the `trace` and `sweep` methods for more details.
 //!
+//! Reference counting alone cannot reclaim cycles of GC objects. Therefore,
+//! when the heap is under pressure after a collection's sweep, we run a backup
+//! mark-sweep cycle collector: we mark everything transitively reachable from
+//! the full set of GC roots, walk the heap's allocated blocks, and free every
+//! unmarked object. See the `collect_cycles` method for more details.
+//!
 //! For more general information on deferred reference counting, see *An
 //! Examination of Deferred Reference Counting and Cycle Detection* by Quinane:
 //! <https://openresearch-repository.anu.edu.au/bitstream/1885/42030/2/hon-thesis.pdf>
@@ -62,6 +65,7 @@ use core::{
     ops::{Deref, DerefMut},
     ptr::NonNull,
 };
+use serde_derive::{Deserialize, Serialize};
 use wasmtime_environ::drc::{ARRAY_LENGTH_OFFSET, DrcTypeLayouts};
 use wasmtime_environ::{
     GcArrayLayout, GcLayout, GcStructLayout, GcTypeLayouts, VMGcKind, VMSharedTypeIndex,
@@ -72,8 +76,9 @@ const GC_REF_ARRAY_ELEMS_OFFSET: u32 = ARRAY_LENGTH_OFFSET + (mem::size_of::<u32
 
 /// The deferred reference-counting (DRC) collector.
 ///
-/// This reference-counting collector does not have a cycle collector, and so it
-/// will not be able to reclaim garbage cycles.
+/// Garbage cycles are not reclaimed by reference counting, but are reclaimed by
+/// a backup mark-sweep cycle collector that runs when the heap is under
+/// pressure.
 ///
 /// This is not a moving collector; it doesn't have a nursery or do any
 /// compaction.
@@ -110,6 +115,15 @@ enum TraceInfo {
     },
 }
 
+/// The bookkeeping of a `DrcHeap`, as captured by `GcHeap::snapshot_state`.
+#[derive(Serialize, Deserialize)]
+struct DrcHeapState {
+    capacity: u64,
+    free_blocks: Vec<(u32, u32)>,
+    allocated_bytes: u64,
+    over_approximated_stack_roots: Option<u32>,
+}
+
 /// A deferred reference-counting (DRC) heap.
 struct DrcHeap {
     engine: EngineWeak,
@@ -139,6 +153,9 @@ struct DrcHeap {
     /// A free list describing which ranges of the heap are available for use.
     free_list: Option<FreeList>,
 
+    /// The sum of the sizes of every object currently allocated in this heap.
+    allocated_bytes: usize,
+
     /// An explicit stack to avoid recursion when deallocating one object needs
     /// to dec-ref another object, which can then be deallocated and dec-refs
     /// yet another object, etc...
@@ -165,6 +182,7 @@ impl DrcHeap {
             memory: None,
             vmmemory: None,
             free_list: None,
+            allocated_bytes: 0,
             dec_ref_stack: Some(Vec::with_capacity(1)),
         })
     }
@@ -181,6 +199,7 @@ impl DrcHeap {
             .as_mut()
             .unwrap()
             .dealloc(gc_ref.as_heap_index().unwrap(), layout);
+        self.allocated_bytes -= size;
     }
 
     /// Increment the ref count for the associated object.
@@ -359,7 +378,7 @@ impl DrcHeap {
         })
     }
 
-    fn trace(&mut self, roots: &mut GcRootsIter<'_>) {
+    fn trace(&mut self, roots: &mut GcRootsIter<'_>, cycle_roots: &mut Vec<VMGcRef>) {
         // The `over_approx_set` is used for `debug_assert!`s checking that
         // every reference we read out from the stack via stack maps is actually
         // in the table. If that weren't true, than either we forgot to insert a
@@ -371,6 +390,16 @@ impl DrcHeap {
         }
 
         for root in roots {
+            let gc_ref = root.get();
+
+            if gc_ref.is_i31() {
+                continue;
+            }
+
+            // Every root, whether or not it is on the Wasm stack, is a root
+            // for the backup cycle collector, should we need to run it.
+            cycle_roots.push(gc_ref.unchecked_copy());
+
             if !root.is_on_wasm_stack() {
                 // We only trace on-Wasm-stack GC roots. These are the
                 // GC references that we do deferred ref counting for
@@ -380,12 +409,6 @@ impl DrcHeap {
                 continue;
             }
 
-            let gc_ref = root.get();
-
-            if gc_ref.is_i31() {
-                continue;
-            }
-
             log::trace!("Found GC reference on the stack: {gc_ref:#p}");
 
             debug_assert!(
@@ -526,6 +549,111 @@ impl DrcHeap {
             );
         }
     }
+
+    /// Is this heap under enough pressure that we should run the backup cycle
+    /// collector?
+    ///
+    /// Cycle collection must walk the whole heap, so we only do it when the
+    /// sweep of our over-approximated stack roots left more than half of the
+    /// heap allocated.
+    fn should_collect_cycles(&self) -> bool {
+        let capacity = self.vmmemory.as_ref().unwrap().current_length();
+        self.allocated_bytes > capacity / 2
+    }
+
+    /// Get the object allocated immediately after `prev` in the heap, or the
+    /// first object in the heap if `prev` is `None`.
+    ///
+    /// The free list must not be mutated while walking the heap with this
+    /// method.
+    fn next_object(&self, prev: Option<&VMGcRef>) -> Option<VMGcRef> {
+        let prev = prev.map(|gc_ref| {
+            let size = self.index(drc_ref(gc_ref)).object_size();
+            (gc_ref.as_heap_index().unwrap(), FreeList::layout(size))
+        });
+        let index = self
+            .free_list
+            .as_ref()
+            .unwrap()
+            .next_allocated_block(prev)?;
+        VMGcRef::from_heap_index(index)
+    }
+
+    /// Reclaim every object that is not reachable from the given roots,
+    /// including garbage cycles that reference counting cannot reclaim.
+    ///
+    /// This must be called after `sweep`, at which point the given roots are a
+    /// complete and precise root set: every on-stack root is in the
+    /// over-approximated-stack-roots list, which holds a reference count for
+    /// it, and every other root holds its own reference count.
+    fn collect_cycles(&mut self, roots: &[VMGcRef], host_data_table: &mut ExternRefHostDataTable) {
+        // First, mark every object that is transitively reachable from the
+        // roots.
+        let mut stack = self.dec_ref_stack.take().unwrap();
+        debug_assert!(stack.is_empty());
+        stack.extend(roots.iter().map(|r| r.unchecked_copy()));
+        while let Some(gc_ref) = stack.pop() {
+            if gc_ref.is_i31() {
+                continue;
+            }
+            let header = self.index_mut(drc_ref(&gc_ref));
+            if header.is_marked() {
+                continue;
+            }
+            header.set_marked();
+            self.trace_gc_ref(&gc_ref, &mut stack);
+        }
+
+        // Second, walk the heap to find every unmarked object. These are
+        // unreachable garbage.
+        let mut garbage = vec![];
+        let mut next = self.next_object(None);
+        while let Some(gc_ref) = next {
+            next = self.next_object(Some(&gc_ref));
+            if !self.index(drc_ref(&gc_ref)).is_marked() {
+                garbage.push(gc_ref);
+            }
+        }
+        log::trace!("Found {} unreachable objects", garbage.len());
+
+        // Third, garbage may reference live objects (although live objects
+        // never reference garbage) so remove the reference counts that
+        // garbage holds on live objects. These counts can never reach zero,
+        // since every live object is kept alive by some other reference.
+        for gc_ref in &garbage {
+            self.trace_gc_ref(gc_ref, &mut stack);
+            while let Some(edge) = stack.pop() {
+                if edge.is_i31() || !self.index(drc_ref(&edge)).is_marked() {
+                    continue;
+                }
+                let should_dealloc = self.dec_ref(&edge);
+                debug_assert!(
+                    !should_dealloc,
+                    "{edge:#p} is reachable from the roots; should have nonzero ref count",
+                );
+            }
+        }
+        debug_assert!(stack.is_empty());
+        self.dec_ref_stack = Some(stack);
+
+        // Fourth, deallocate the garbage, removing the host data for any
+        // `externref`s.
+        for gc_ref in garbage {
+            if let Some(externref) = gc_ref.as_typed::<VMDrcExternRef>(self) {
+                let host_data_id = self.index(externref).host_data;
+                host_data_table.dealloc(host_data_id);
+            }
+            self.dealloc(gc_ref);
+        }
+
+        // Finally, clear the mark bits of every surviving object.
+        let mut next = self.next_object(None);
+        while let Some(gc_ref) = next {
+            next = self.next_object(Some(&gc_ref));
+            let was_marked = self.index_mut(drc_ref(&gc_ref)).clear_marked();
+            debug_assert!(was_marked);
+        }
+    }
 }
 
 /// Convert the given GC reference as a typed GC reference pointing to a
@@ -695,6 +823,7 @@ unsafe impl GcHeap for DrcHeap {
             no_gc_count,
             over_approximated_stack_roots,
             free_list,
+            allocated_bytes,
             dec_ref_stack,
             memory,
             vmmemory,
@@ -708,6 +837,7 @@ unsafe impl GcHeap for DrcHeap {
         *no_gc_count = 0;
         **over_approximated_stack_roots = None;
         *free_list = None;
+        *allocated_bytes = 0;
         *vmmemory = None;
         debug_assert!(dec_ref_stack.as_ref().is_some_and(|s| s.is_empty()));
 
@@ -827,6 +957,7 @@ unsafe impl GcHeap for DrcHeap {
             None => return Ok(Err(u64::try_from(layout.size()).unwrap())),
             Some(index) => VMGcRef::from_heap_index(index).unwrap(),
         };
+        self.allocated_bytes += layout.size();
 
         *self.index_mut(drc_ref(&gc_ref)) = VMDrcHeader {
             header,
@@ -891,6 +1022,10 @@ unsafe impl GcHeap for DrcHeap {
             .length
     }
 
+    fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes
+    }
+
     fn gc<'a>(
         &'a mut self,
         roots: GcRootsIter<'a>,
@@ -899,12 +1034,62 @@ unsafe impl GcHeap for DrcHeap {
         assert_eq!(self.no_gc_count, 0, "Cannot GC inside a no-GC scope!");
         Box::new(DrcCollection {
             roots,
+            cycle_roots: Vec::new(),
             host_data_table,
             heap: self,
             phase: DrcCollectionPhase::Trace,
         })
     }
 
+    fn snapshot_state(&self) -> Result<Vec<u8>> {
+        let free_list = self.free_list.as_ref().unwrap();
+        Ok(postcard::to_allocvec(&DrcHeapState {
+            capacity: u64::try_from(free_list.capacity()).unwrap(),
+            free_blocks: free_list.free_blocks().collect(),
+            allocated_bytes: u64::try_from(self.allocated_bytes).unwrap(),
+            over_approximated_stack_roots: self
+                .over_approximated_stack_roots
+                .as_ref()
+                .map(|r| r.as_raw_u32()),
+        })?)
+    }
+
+    fn restore_state(&mut self, snapshot_len: usize, state: &[u8]) -> Result<()> {
+        assert_eq!(self.no_gc_count, 0, "Cannot restore inside a no-GC scope!");
+        let DrcHeapState {
+            capacity,
+            free_blocks,
+            allocated_bytes,
+            over_approximated_stack_roots,
+        } = postcard::from_bytes(state)?;
+
+        let capacity = usize::try_from(capacity)?;
+        let len = self.vmmemory.as_ref().unwrap().current_length();
+        ensure!(
+            capacity == snapshot_len && capacity <= len,
+            "invalid capacity in DRC GC heap snapshot"
+        );
+        let mut free_list = FreeList::from_free_blocks(capacity, free_blocks)?;
+        free_list.add_capacity(len - capacity);
+        self.free_list = Some(free_list);
+        self.allocated_bytes = usize::try_from(allocated_bytes)?;
+        *self.over_approximated_stack_roots = over_approximated_stack_roots
+            .map(|raw| VMGcRef::from_raw_u32(raw).context("invalid DRC GC heap snapshot"))
+            .transpose()?;
+
+        // Walk the heap to recreate the tracing info for every type of object
+        // in it.
+        let mut next = self.next_object(None);
+        while let Some(gc_ref) = next {
+            if let Some(ty) = self.index(drc_ref(&gc_ref)).header.ty() {
+                self.ensure_trace_info(ty);
+            }
+            next = self.next_object(Some(&gc_ref));
+        }
+
+        Ok(())
+    }
+
     unsafe fn vmctx_gc_heap_data(&self) -> NonNull<u8> {
         let ptr: NonNull<Option<VMGcRef>> = NonNull::from(&*self.over_approximated_stack_roots);
         ptr.cast()
@@ -942,6 +1127,7 @@ unsafe impl GcHeap for DrcHeap {
 
 struct DrcCollection<'a> {
     roots: GcRootsIter<'a>,
+    cycle_roots: Vec<VMGcRef>,
     host_data_table: &'a mut ExternRefHostDataTable,
     heap: &'a mut DrcHeap,
     phase: DrcCollectionPhase,
@@ -950,6 +1136,7 @@ struct DrcCollection<'a> {
 enum DrcCollectionPhase {
     Trace,
     Sweep,
+    CollectCycles,
     Done,
 }
 
@@ -958,7 +1145,7 @@ impl<'a> GarbageCollection<'a> for DrcCollection<'a> {
         match self.phase {
             DrcCollectionPhase::Trace => {
                 log::trace!("Begin DRC trace");
-                self.heap.trace(&mut self.roots);
+                self.heap.trace(&mut self.roots, &mut self.cycle_roots);
                 log::trace!("End DRC trace");
                 self.phase = DrcCollectionPhase::Sweep;
                 GcProgress::Continue
@@ -967,6 +1154,19 @@ impl<'a> GarbageCollection<'a> for DrcCollection<'a> {
                 log::trace!("Begin DRC sweep");
                 self.heap.sweep(self.host_data_table);
                 log::trace!("End DRC sweep");
+                if self.heap.should_collect_cycles() {
+                    self.phase = DrcCollectionPhase::CollectCycles;
+                    GcProgress::Continue
+                } else {
+                    self.phase = DrcCollectionPhase::Done;
+                    GcProgress::Complete
+                }
+            }
+            DrcCollectionPhase::CollectCycles => {
+                log::trace!("Begin DRC cycle collection");
+                self.heap
+                    .collect_cycles(&self.cycle_roots, self.host_data_table);
+                log::trace!("End DRC cycle collection");
                 self.phase = DrcCollectionPhase::Done;
                 GcProgress::Complete
             }
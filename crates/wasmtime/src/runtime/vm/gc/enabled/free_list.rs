@@ -135,6 +135,52 @@ impl FreeList {
         self.dealloc(index, layout);
     }
 
+    /// Recreate a free list from another free list's `capacity` and
+    /// `free_blocks`.
+    ///
+    /// Returns an error if the blocks are not a valid free list for the given
+    /// capacity.
+    pub fn from_free_blocks(
+        capacity: usize,
+        blocks: impl IntoIterator<Item = (u32, u32)>,
+    ) -> Result<Self> {
+        let mut free_list = FreeList {
+            capacity,
+            free_block_index_to_len: BTreeMap::new(),
+        };
+
+        let mut prev_end = None;
+        for (index, len) in blocks {
+            let end = index.checked_add(len);
+            ensure!(
+                index >= ALIGN_U32
+                    && index % ALIGN_U32 == 0
+                    && len > 0
+                    && len % ALIGN_U32 == 0
+                    && end.is_some_and(|end| usize::try_from(end).unwrap() <= capacity)
+                    && prev_end.is_none_or(|prev_end| prev_end < index),
+                "invalid free list block of length {len:#x} at index {index:#x}"
+            );
+            free_list.free_block_index_to_len.insert(index, len);
+            prev_end = end;
+        }
+
+        Ok(free_list)
+    }
+
+    /// The total capacity of the region of memory this free list manages.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Iterate over this free list's free blocks as `(index, len)` pairs, in
+    /// address order.
+    pub fn free_blocks(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        self.free_block_index_to_len
+            .iter()
+            .map(|(&index, &len)| (index, len))
+    }
+
     #[cfg(test)]
     fn max_size(&self) -> usize {
         let cap = core::cmp::min(self.capacity, usize::try_from(u32::MAX).unwrap());
@@ -340,6 +386,35 @@ impl FreeList {
         self.check_integrity();
     }
 
+    /// Get the index of the allocated block immediately following `prev`, or
+    /// of the first allocated block if `prev` is `None`.
+    ///
+    /// `prev` must be the index and layout of a currently-allocated block.
+    ///
+    /// Every aligned index within the managed region is either inside a free
+    /// block or inside an allocated block, so this can be used to walk every
+    /// allocated block in address order, as long as the free list is not
+    /// mutated during the walk.
+    pub fn next_allocated_block(&self, prev: Option<(NonZeroU32, Layout)>) -> Option<NonZeroU32> {
+        let mut index = match prev {
+            None => ALIGN_U32,
+            Some((index, layout)) => index.get() + self.check_layout(layout).unwrap(),
+        };
+
+        let end = u32::try_from(self.capacity).unwrap_or(u32::MAX);
+        let end = round_u32_down_to_pow2(end, ALIGN_U32);
+
+        while index < end {
+            match self.free_block_index_to_len.get(&index) {
+                // Skip over free blocks.
+                Some(len) => index += len,
+                None => return NonZeroU32::new(index),
+            }
+        }
+
+        None
+    }
+
     /// Assert that the free list is valid:
     ///
     /// 1. All blocks are within `ALIGN..self.capacity`
@@ -925,6 +1000,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn walk_allocated_blocks() {
+        let small = Layout::from_size_align(ALIGN_USIZE, ALIGN_USIZE).unwrap();
+        let large = Layout::from_size_align(3 * ALIGN_USIZE - 1, ALIGN_USIZE).unwrap();
+
+        let mut free_list = FreeList::new(0x100);
+        assert!(free_list.next_allocated_block(None).is_none());
+
+        let a = free_list.alloc(small).unwrap().unwrap();
+        let b = free_list.alloc(large).unwrap().unwrap();
+        let c = free_list.alloc(small).unwrap().unwrap();
+        let d = free_list.alloc(large).unwrap().unwrap();
+        free_list.dealloc(b, large);
+        free_list.dealloc(c, small);
+
+        let mut walked = vec![];
+        let mut layouts = HashMap::new();
+        layouts.insert(a, small);
+        layouts.insert(d, large);
+        let mut prev = None;
+        while let Some(index) = free_list.next_allocated_block(prev) {
+            walked.push(index);
+            prev = Some((index, layouts[&index]));
+        }
+        assert_eq!(walked, [a, d]);
+
+        free_list.dealloc(a, small);
+        assert_eq!(free_list.next_allocated_block(None), Some(d));
+        free_list.dealloc(d, large);
+        assert!(free_list.next_allocated_block(None).is_none());
+    }
+
+    #[test]
+    fn from_free_blocks() {
+        let layout = Layout::from_size_align(ALIGN_USIZE, ALIGN_USIZE).unwrap();
+
+        let mut free_list = FreeList::new(0x105);
+        let a = free_list.alloc(layout).unwrap().unwrap();
+        let b = free_list.alloc(layout).unwrap().unwrap();
+        free_list.dealloc(a, layout);
+
+        let mut copy =
+            FreeList::from_free_blocks(free_list.capacity(), free_list.free_blocks()).unwrap();
+        assert_eq!(copy.capacity(), 0x105);
+        assert!(copy.free_blocks().eq(free_list.free_blocks()));
+        assert_eq!(copy.next_allocated_block(None), Some(b));
+        assert_eq!(copy.alloc(layout).unwrap(), Some(a));
+
+        // Overlapping, misaligned, and out-of-bounds blocks are rejected.
+        assert!(FreeList::from_free_blocks(0x100, [(0x10, 0x20), (0x20, 0x10)]).is_err());
+        assert!(FreeList::from_free_blocks(0x100, [(0x18, 0x10)]).is_err());
+        assert!(FreeList::from_free_blocks(0x100, [(0xf0, 0x20)]).is_err());
+        assert!(FreeList::from_free_blocks(0x100, [(0, 0x10)]).is_err());
+    }
+
     #[test]
     fn add_capacity_not_enough_for_first_alloc() {
         let layout = Layout::from_size_align(ALIGN_USIZE, ALIGN_USIZE).unwrap();
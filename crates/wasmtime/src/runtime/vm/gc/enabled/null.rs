@@ -327,6 +327,15 @@ unsafe impl GcHeap for NullHeap {
         self.index(arrayref).length
     }
 
+    fn allocated_bytes(&self) -> usize {
+        if !self.is_attached() {
+            return 0;
+        }
+        // The bump pointer starts at `1`, since index `0` is reserved for null.
+        let next = unsafe { *self.next.get() };
+        usize::try_from(next.get() - 1).unwrap()
+    }
+
     fn gc<'a>(
         &'a mut self,
         _roots: GcRootsIter<'a>,
@@ -336,6 +345,21 @@ unsafe impl GcHeap for NullHeap {
         Box::new(NullCollection {})
     }
 
+    fn snapshot_state(&self) -> Result<Vec<u8>> {
+        let next = unsafe { *self.next.get() };
+        Ok(postcard::to_allocvec(&next.get())?)
+    }
+
+    fn restore_state(&mut self, snapshot_len: usize, state: &[u8]) -> Result<()> {
+        assert_eq!(self.no_gc_count, 0, "Cannot restore inside a no-GC scope!");
+        let next = postcard::from_bytes::<u32>(state)?;
+        let next = NonZeroU32::new(next)
+            .filter(|next| usize::try_from(next.get()).unwrap() <= snapshot_len)
+            .context("invalid bump pointer in null GC heap snapshot")?;
+        *self.next.get_mut() = next;
+        Ok(())
+    }
+
     unsafe fn vmctx_gc_heap_data(&self) -> NonNull<u8> {
         let ptr_to_next: *mut NonZeroU32 = unsafe { self.next.get() };
         NonNull::new(ptr_to_next).unwrap().cast()
@@ -93,4 +93,13 @@ impl FuncRefTable {
     pub fn get_untyped(&self, id: FuncRefTableId) -> Option<SendSyncPtr<VMFuncRef>> {
         self.slab.get(id.0).copied().expect("bad FuncRefTableId")
     }
+
+    /// Iterate over every entry in this table.
+    ///
+    /// Entries are never removed from this table, so its IDs are always
+    /// `0..n` and interning every entry into a new table, in ID order,
+    /// recreates this table.
+    pub fn iter(&self) -> impl Iterator<Item = (FuncRefTableId, Option<SendSyncPtr<VMFuncRef>>)> {
+        self.slab.iter().map(|(id, f)| (FuncRefTableId(id), *f))
+    }
 }
@@ -341,6 +341,11 @@ pub unsafe trait GcHeap: 'static + Send + Sync {
     ////////////////////////////////////////////////////////////////////////////
     // Garbage Collection Methods
 
+    /// Get the number of bytes currently allocated in this heap.
+    ///
+    /// This is used to report how many bytes each collection reclaimed.
+    fn allocated_bytes(&self) -> usize;
+
     /// Start a new garbage collection process.
     ///
     /// The given `roots` are GC roots and should not be collected (nor anything
@@ -361,6 +366,31 @@ pub unsafe trait GcHeap: 'static + Send + Sync {
         host_data_table: &'a mut ExternRefHostDataTable,
     ) -> Box<dyn GarbageCollection<'a> + 'a>;
 
+    ////////////////////////////////////////////////////////////////////////////
+    // Snapshot Methods
+
+    /// Get this heap's collector-specific bookkeeping (free lists, bump
+    /// pointers, etc...) as opaque bytes.
+    ///
+    /// Together with a copy of `heap_slice()`, this is everything needed to
+    /// recreate this heap's state in another heap of the same collector with
+    /// `restore_state`.
+    fn snapshot_state(&self) -> Result<Vec<u8>>;
+
+    /// Restore the bookkeeping captured by `snapshot_state`.
+    ///
+    /// The caller must have already copied the snapshotted heap's bytes into
+    /// the start of this heap's memory, which must be at least as large as the
+    /// snapshotted heap, and zeroed the rest of the memory. Any objects that
+    /// were previously allocated in this heap are forgotten.
+    ///
+    /// Callers should pass state produced by the same kind of collector.
+    /// Failure to do so is memory safe, but may result in general failures
+    /// such as panics or incorrect results.
+    ///
+    /// This method should panic if we are in a no-GC scope.
+    fn restore_state(&mut self, snapshot_len: usize, state: &[u8]) -> Result<()>;
+
     ////////////////////////////////////////////////////////////////////////////
     // JIT-Code Interaction Methods
 
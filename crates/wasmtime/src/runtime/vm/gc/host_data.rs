@@ -62,6 +62,11 @@ impl ExternRefHostDataTable {
         let data: &mut Box<dyn Any + Send + Sync> = self.slab.get_mut(id.0).unwrap();
         deref_box_mut(data)
     }
+
+    /// Are there no host data values in this table?
+    pub fn is_empty(&self) -> bool {
+        self.slab.is_empty()
+    }
 }
 
 #[cfg(test)]
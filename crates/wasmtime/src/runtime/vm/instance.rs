@@ -44,6 +44,8 @@ use wasmtime_wmemcheck::Wmemcheck;
 mod allocator;
 pub use allocator::*;
 
+mod park;
+
 /// A type that roughly corresponds to a WebAssembly instance, but is also used
 /// for host-defined objects.
 ///
@@ -127,6 +129,10 @@ pub struct Instance {
     /// If the index is present in the set, the segment has been dropped.
     dropped_data: EntitySet<DataIndex>,
 
+    /// Whether `memories` and `tables` currently hold parked copies which
+    /// don't belong to any `InstanceAllocator`, see `Instance::park`.
+    parked: bool,
+
     // TODO: add support for multiple memories; `wmemcheck_state` corresponds to
     // memory 0.
     #[cfg(feature = "wmemcheck")]
@@ -175,6 +181,7 @@ impl Instance {
             tables,
             dropped_elements,
             dropped_data,
+            parked: false,
             #[cfg(feature = "wmemcheck")]
             wmemcheck_state: {
                 if req.store.engine().config().wmemcheck {
@@ -349,6 +356,10 @@ impl Instance {
         self.runtime_info.env_module()
     }
 
+    pub(crate) fn runtime_info(&self) -> &ModuleRuntimeInfo {
+        &self.runtime_info
+    }
+
     pub(crate) fn runtime_module(&self) -> Option<&crate::Module> {
         match &self.runtime_info {
             ModuleRuntimeInfo::Module(m) => Some(m),
@@ -356,6 +367,13 @@ impl Instance {
         }
     }
 
+    /// Returns the tiered compilation state of this instance's module, if it
+    /// has any.
+    #[cfg(feature = "winch")]
+    pub(crate) fn tiering(&self) -> Option<&Arc<crate::runtime::tiering::ModuleTiering>> {
+        self.runtime_module().and_then(|m| m.tiering())
+    }
+
     /// Translate a module-level interned type index into an engine-level
     /// interned type index.
     #[cfg(feature = "gc")]
@@ -768,6 +786,14 @@ impl Instance {
         unsafe { self.vmctx_plus_offset_raw(self.offsets().ptr.vmctx_type_ids_array()) }
     }
 
+    fn instruction_counters(&self) -> NonNull<Option<VmPtr<VmPtr<u64>>>> {
+        unsafe { self.vmctx_plus_offset_raw(self.offsets().ptr.vmctx_instruction_counters()) }
+    }
+
+    fn call_counts(&self) -> NonNull<Option<VmPtr<u32>>> {
+        unsafe { self.vmctx_plus_offset_raw(self.offsets().ptr.vmctx_call_counts()) }
+    }
+
     /// Construct a new VMFuncRef for the given function
     /// (imported or defined in this module) and store into the given
     /// location. Used during lazy initialization.
@@ -828,6 +854,26 @@ impl Instance {
             }
         };
 
+        // Functions which have been tiered up use their recompiled code
+        // instead, and the tiering lock must be held while writing so that
+        // this doesn't race with the function being tiered up.
+        #[cfg(feature = "winch")]
+        if let Some(tiering) = self.tiering() {
+            if let Some(def_index) = self.env_module().defined_func_index(index) {
+                return tiering.with_tiered_code(def_index, |wasm_call| {
+                    let mut func_ref = func_ref;
+                    if let Some(wasm_call) = wasm_call {
+                        func_ref.wasm_call = Some(wasm_call);
+                    }
+                    // SAFETY: the unsafe contract here is forwarded to callers
+                    // of this function.
+                    unsafe {
+                        ptr::write(into, func_ref);
+                    }
+                });
+            }
+        }
+
         // SAFETY: the unsafe contract here is forwarded to callers of this
         // function.
         unsafe {
@@ -1343,6 +1389,34 @@ impl Instance {
             self.type_ids_array().write(types.cast().into());
         }
 
+        // Initialize the instruction-counters table, which is null unless
+        // instruction counting is enabled.
+        //
+        // SAFETY: validity of the vmctx means it should be safe to write to it
+        // here, and the store keeps the table alive for as long as this
+        // instance.
+        unsafe {
+            let table = self
+                .runtime_info
+                .unique_id()
+                .and_then(|id| store.instruction_counters_table(id));
+            self.instruction_counters().write(table.map(VmPtr::from));
+        }
+
+        // Initialize the call counts used by tiered compilation, which are
+        // null unless the module was compiled with it.
+        //
+        // SAFETY: validity of the vmctx means it should be safe to write to it
+        // here, and the module keeps the counts alive for as long as this
+        // instance.
+        unsafe {
+            #[cfg(feature = "winch")]
+            let counts = self.tiering().map(|t| t.call_counts());
+            #[cfg(not(feature = "winch"))]
+            let counts = None;
+            self.call_counts().write(counts);
+        }
+
         // Initialize the built-in functions
         //
         // SAFETY: the type of the builtin functions field is indeed a pointer
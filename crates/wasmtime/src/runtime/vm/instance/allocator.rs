@@ -379,6 +379,17 @@ impl dyn InstanceAllocator + '_ {
     ///
     /// The instance must have previously been allocated by `Self::allocate`.
     pub(crate) unsafe fn deallocate_module(&self, handle: &mut InstanceHandle) {
+        #[cfg(feature = "winch")]
+        if let Some(tiering) = handle.get().tiering() {
+            tiering.unregister_instance(handle.get().vmctx());
+        }
+
+        // Parked memories and tables were already given back to this
+        // allocator when they were parked, so only the copies are dropped.
+        if handle.get().is_parked() {
+            handle.get_mut().drop_parked();
+        }
+
         // SAFETY: the contract of `deallocate_*` is itself a contract of this
         // function, that the memories/tables were previously allocated from
         // here.
@@ -392,7 +403,7 @@ impl dyn InstanceAllocator + '_ {
 
     /// Allocate the memories for the given instance allocation request, pushing
     /// them into `memories`.
-    async fn allocate_memories(
+    pub(crate) async fn allocate_memories(
         &self,
         request: &mut InstanceAllocationRequest<'_, '_>,
         memories: &mut PrimaryMap<DefinedMemoryIndex, (MemoryAllocationIndex, Memory)>,
@@ -424,7 +435,7 @@ impl dyn InstanceAllocator + '_ {
     ///
     /// The memories must have previously been allocated by
     /// `Self::allocate_memories`.
-    unsafe fn deallocate_memories(
+    pub(crate) unsafe fn deallocate_memories(
         &self,
         memories: &mut PrimaryMap<DefinedMemoryIndex, (MemoryAllocationIndex, Memory)>,
     ) {
@@ -445,7 +456,7 @@ impl dyn InstanceAllocator + '_ {
 
     /// Allocate tables for the given instance allocation request, pushing them
     /// into `tables`.
-    async fn allocate_tables(
+    pub(crate) async fn allocate_tables(
         &self,
         request: &mut InstanceAllocationRequest<'_, '_>,
         tables: &mut PrimaryMap<DefinedTableIndex, (TableAllocationIndex, Table)>,
@@ -475,7 +486,7 @@ impl dyn InstanceAllocator + '_ {
     ///
     /// The tables must have previously been allocated by
     /// `Self::allocate_tables`.
-    unsafe fn deallocate_tables(
+    pub(crate) unsafe fn deallocate_tables(
         &self,
         tables: &mut PrimaryMap<DefinedTableIndex, (TableAllocationIndex, Table)>,
     ) {
@@ -651,6 +662,41 @@ fn initialize_memories(
         const_evaluator: &'a mut ConstExprEvaluator,
     }
 
+    impl InitMemoryAtInstantiation<'_> {
+        /// Registers `init` to be populated lazily by the engine's
+        /// userfaultfd, if it has one, returning whether it was.
+        #[cfg(has_virtual_memory)]
+        fn init_lazily(
+            &mut self,
+            memory_index: wasmtime_environ::MemoryIndex,
+            init: &wasmtime_environ::StaticMemoryInitializer,
+        ) -> bool {
+            let Some(uffd) = self.store.engine().userfaultfd().cloned() else {
+                return false;
+            };
+            let MemoryInitialization::Static { .. } = self.module.memory_initialization else {
+                return false;
+            };
+            let Some(memory_index) = self.module.defined_memory_index(memory_index) else {
+                return false;
+            };
+            let mut instance = self.store.instance_mut(self.context.instance);
+            if !instance.memories[memory_index].1.needs_init() {
+                return false;
+            }
+            let Some(module) = instance.runtime_module() else {
+                return false;
+            };
+            let source = module.engine_code().module_memory_image_source().clone();
+            let offset = usize::try_from(init.offset).unwrap();
+            let data =
+                usize::try_from(init.data.start).unwrap()..usize::try_from(init.data.end).unwrap();
+            instance.as_mut().memories_mut()[memory_index]
+                .1
+                .init_lazily(&uffd, offset, source, data)
+        }
+    }
+
     impl InitMemory for InitMemoryAtInstantiation<'_> {
         fn memory_size_in_bytes(
             &mut self,
@@ -686,6 +732,13 @@ fn initialize_memories(
             memory_index: wasmtime_environ::MemoryIndex,
             init: &wasmtime_environ::StaticMemoryInitializer,
         ) -> bool {
+            // Static initializers may instead be populated lazily, page by
+            // page, if the engine is configured to do so.
+            #[cfg(has_virtual_memory)]
+            if self.init_lazily(memory_index, init) {
+                return true;
+            }
+
             // If this initializer applies to a defined memory but that memory
             // doesn't need initialization, due to something like copy-on-write
             // pre-initializing it via mmap magic, then this initializer can be
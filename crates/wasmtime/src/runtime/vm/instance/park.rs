@@ -0,0 +1,174 @@
+//! Parking of an instance's memories and tables, see `Store::park`.
+//!
+//! Parking moves each of an instance's defined memories and tables into a copy
+//! that is allocated independently of the instance allocator, and then gives
+//! the originals back to the allocator. For the pooling allocator this frees
+//! up their slots. Unparking does the reverse with freshly allocated memories
+//! and tables. The instance itself, and with it its `VMContext`, stays where it
+//! is throughout; only the memory and table definitions within the
+//! `VMContext` are updated.
+
+use super::*;
+use crate::runtime::vm::assert_ready;
+use wasmtime_environ::Tunables;
+
+impl Instance {
+    /// Returns whether this instance's memories and tables are currently
+    /// parked.
+    pub(crate) fn is_parked(&self) -> bool {
+        self.parked
+    }
+
+    /// Returns whether this instance can be parked, which is the case unless
+    /// it's already parked or defines a shared memory.
+    pub(crate) fn can_park(&self) -> bool {
+        !self.parked && self.memories.values().all(|(_, m)| !m.is_shared_memory())
+    }
+
+    /// Moves this instance's defined memories and tables into copies which
+    /// don't belong to `allocator`, and then deallocates the originals.
+    ///
+    /// On error this instance is left as it was.
+    ///
+    /// # Safety
+    ///
+    /// This instance's memories and tables must have been allocated by
+    /// `allocator`, and no WebAssembly may be executing within this instance's
+    /// store.
+    pub(crate) unsafe fn park(
+        mut self: Pin<&mut Self>,
+        allocator: &dyn InstanceAllocator,
+        tunables: &Tunables,
+    ) -> Result<()> {
+        assert!(self.can_park());
+
+        // Create all of the copies up front so that failing to allocate one of
+        // them doesn't leave this instance half-parked.
+        let mut memories = PrimaryMap::with_capacity(self.memories.len());
+        for (_, memory) in self.memories.values() {
+            memories.push(memory.new_parked(tunables)?);
+        }
+        let mut tables = PrimaryMap::with_capacity(self.tables.len());
+        for index in self.tables.keys() {
+            let module = self.env_module();
+            let ty = &module.tables[module.table_index(index)];
+            tables.push(assert_ready(Table::new_dynamic(ty, tunables, None))?);
+        }
+
+        for (index, mut parked) in memories {
+            parked.take_charge(&mut self.as_mut().memories_mut()[index].1);
+            let (allocation_index, memory) = mem::replace(
+                &mut self.as_mut().memories_mut()[index],
+                (MemoryAllocationIndex::default(), parked),
+            );
+            self.set_memory(index, self.memories[index].1.vmmemory());
+            // SAFETY: `memory` was allocated by `allocator` per this
+            // function's contract, and it's no longer reachable from the
+            // `VMContext`.
+            unsafe {
+                allocator.deallocate_memory(Some(index), allocation_index, memory);
+            }
+        }
+        for (index, mut parked) in tables {
+            let slot = &mut self.as_mut().tables_mut()[index];
+            parked.move_elements_from(&mut slot.1);
+            let (allocation_index, table) =
+                mem::replace(slot, (TableAllocationIndex::default(), parked));
+            let vmtable = self.as_mut().tables_mut()[index].1.vmtable();
+            self.as_mut().set_table(index, vmtable);
+            // SAFETY: same as for memories above.
+            unsafe {
+                allocator.deallocate_table(index, allocation_index, table);
+            }
+        }
+
+        *self.parked_mut() = true;
+        Ok(())
+    }
+
+    /// Moves the contents of this instance's parked memories and tables into
+    /// `memories` and `tables`, which replace them, and drops the parked
+    /// copies.
+    ///
+    /// The parked copies may have been grown while parked, so `memories` and
+    /// `tables` are first grown to match. If that fails then `memories` and
+    /// `tables` are deallocated and this instance stays parked.
+    ///
+    /// # Safety
+    ///
+    /// `memories` and `tables` must have been allocated by `allocator` for
+    /// this instance's defined memories and tables, and no WebAssembly may be
+    /// executing within this instance's store.
+    pub(crate) unsafe fn unpark(
+        mut self: Pin<&mut Self>,
+        allocator: &dyn InstanceAllocator,
+        mut memories: PrimaryMap<DefinedMemoryIndex, (MemoryAllocationIndex, Memory)>,
+        mut tables: PrimaryMap<DefinedTableIndex, (TableAllocationIndex, Table)>,
+    ) -> Result<()> {
+        assert!(self.parked);
+        assert_eq!(memories.len(), self.memories.len());
+        assert_eq!(tables.len(), self.tables.len());
+
+        let result = (|| -> Result<()> {
+            for (index, (_, memory)) in memories.iter_mut() {
+                let parked = &self.memories[index].1;
+                let delta = parked.byte_size().saturating_sub(memory.byte_size());
+                let delta = u64::try_from(delta).unwrap() / parked.page_size();
+                // SAFETY: `memory` isn't reachable from the store yet.
+                if assert_ready(unsafe { memory.grow(delta, None) })?.is_none() {
+                    bail!("failed to grow unparked memory to the size of its parked copy");
+                }
+            }
+            for (index, (_, table)) in tables.iter() {
+                let size = self.tables[index].1.size();
+                ensure!(
+                    table.maximum().is_none_or(|max| size <= max),
+                    "parked table of {size} elements exceeds the maximum size of its \
+                     unparked allocation",
+                );
+            }
+            Ok(())
+        })();
+        if let Err(e) = result {
+            // SAFETY: these were allocated by `allocator` per this function's
+            // contract, and haven't been made reachable from the store.
+            unsafe {
+                allocator.deallocate_memories(&mut memories);
+                allocator.deallocate_tables(&mut tables);
+            }
+            return Err(e);
+        }
+
+        for (index, (allocation_index, mut memory)) in memories {
+            let slot = &mut self.as_mut().memories_mut()[index];
+            memory.restore_parked(&slot.1);
+            memory.take_charge(&mut slot.1);
+            *slot = (allocation_index, memory);
+            self.set_memory(index, self.memories[index].1.vmmemory());
+        }
+        for (index, (allocation_index, mut table)) in tables {
+            let slot = &mut self.as_mut().tables_mut()[index];
+            table.move_elements_from(&mut slot.1);
+            *slot = (allocation_index, table);
+            let vmtable = slot.1.vmtable();
+            self.as_mut().set_table(index, vmtable);
+        }
+
+        *self.parked_mut() = false;
+        Ok(())
+    }
+
+    /// Drops this instance's parked memories and tables, which don't belong to
+    /// any allocator, in preparation for deallocating this instance.
+    pub(crate) fn drop_parked(mut self: Pin<&mut Self>) {
+        assert!(self.parked);
+        self.as_mut().memories_mut().clear();
+        self.as_mut().tables_mut().clear();
+        *self.parked_mut() = false;
+    }
+
+    fn parked_mut(self: Pin<&mut Self>) -> &mut bool {
+        // SAFETY: see `store_mut` in the parent module.
+        unsafe { &mut self.get_unchecked_mut().parked }
+    }
+}
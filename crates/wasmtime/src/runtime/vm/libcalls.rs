@@ -220,7 +220,22 @@ fn memory_grow(
     let memory_index = DefinedMemoryIndex::from_u32(memory_index);
     let (mut limiter, store) = store.resource_limiter_and_store_opaque();
     let limiter = limiter.as_mut();
-    block_on!(store, async |store| {
+
+    // When replaying, a growth which failed in the recording fails again
+    // without consulting the resource limiter.
+    #[cfg(feature = "rr")]
+    let rr = store.record_replay().cloned();
+    #[cfg(feature = "rr")]
+    let replayed = match &rr {
+        Some(rr) => rr.replay_memory_grow()?,
+        None => None,
+    };
+    #[cfg(feature = "rr")]
+    if replayed == Some(None) {
+        return Ok(None);
+    }
+
+    let result = block_on!(store, async |store| {
         let instance = store.instance_mut(instance);
         let module = instance.env_module();
         let page_size_log2 = module.memories[module.memory_index(memory_index)].page_size_log2;
@@ -230,8 +245,23 @@ fn memory_grow(
             .await?
             .map(|size_in_bytes| AllocationSize(size_in_bytes >> page_size_log2));
 
-        Ok(result)
-    })?
+        anyhow::Ok(result)
+    })??;
+
+    #[cfg(feature = "rr")]
+    if let Some(rr) = &rr {
+        let pages = result.as_ref().map(|size| size.0 as u64);
+        if let Some(recorded) = replayed {
+            ensure!(
+                pages == recorded,
+                "replay diverged from the recorded trace: `memory.grow` returned \
+                 {pages:?} but {recorded:?} was recorded"
+            );
+        }
+        rr.record_memory_grow(pages)?;
+    }
+
+    Ok(result)
 }
 
 /// A helper structure to represent the return value of a memory or table growth
@@ -647,7 +677,7 @@ fn grow_gc_heap(store: &mut dyn VMStore, _instance: InstanceId, bytes_needed: u6
 /// Allocate a raw, unininitialized GC object for Wasm code.
 ///
 /// The Wasm code is responsible for initializing the object.
-#[cfg(feature = "gc-drc")]
+#[cfg(any(feature = "gc-drc", feature = "gc-copying"))]
 fn gc_alloc_raw(
     store: &mut dyn VMStore,
     instance: InstanceId,
@@ -1240,7 +1270,12 @@ fn memory_atomic_wait64(
 // Hook for when an instance runs out of fuel.
 fn out_of_gas(store: &mut dyn VMStore, _instance: InstanceId) -> Result<()> {
     block_on!(store, async |store| {
-        if !store.refuel() {
+        let refueled = store.refuel();
+        #[cfg(feature = "rr")]
+        if let Some(rr) = store.record_replay() {
+            rr.out_of_fuel(refueled)?;
+        }
+        if !refueled {
             return Err(Trap::OutOfFuel.into());
         }
         #[cfg(feature = "async")]
@@ -1261,6 +1296,14 @@ fn new_epoch(store: &mut dyn VMStore, _instance: InstanceId) -> Result<NextEpoch
         store.block_on_debug_handler(crate::DebugEvent::EpochYield)?;
     }
 
+    // When replaying, the recorded decision is used instead of consulting
+    // the store's epoch deadline behavior.
+    #[cfg(feature = "rr")]
+    let update_deadline = match store.record_replay().cloned() {
+        Some(rr) => rr.epoch_deadline(|| store.new_epoch_updated_deadline())?,
+        None => store.new_epoch_updated_deadline()?,
+    };
+    #[cfg(not(feature = "rr"))]
     let update_deadline = store.new_epoch_updated_deadline()?;
     block_on!(store, async move |store| {
         let delta = match update_deadline {
@@ -1311,6 +1354,18 @@ unsafe impl HostResultHasUnwindSentinel for NextEpoch {
     }
 }
 
+// Hook for when a function compiled for tiered compilation has been called
+// often enough to be recompiled.
+fn tier_up(store: &mut dyn VMStore, instance: InstanceId, func_index: u32) {
+    #[cfg(feature = "winch")]
+    if let Some(tiering) = store.instance(instance).tiering() {
+        let index = wasmtime_environ::DefinedFuncIndex::from_u32(func_index);
+        tiering.request_tier_up(store.engine(), index);
+    }
+    #[cfg(not(feature = "winch"))]
+    let _ = (store, instance, func_index);
+}
+
 // Hook for validating malloc using wmemcheck_state.
 #[cfg(feature = "wmemcheck")]
 fn check_malloc(store: &mut dyn VMStore, instance: InstanceId, addr: u32, len: u32) -> Result<()> {
@@ -1715,7 +1770,7 @@ fn breakpoint(store: &mut dyn VMStore, _instance: InstanceId) -> Result<()> {
     #[cfg(feature = "debug")]
     {
         log::trace!("hit breakpoint");
-        store.block_on_debug_handler(crate::DebugEvent::Breakpoint)?;
+        crate::runtime::debug::handle_breakpoint(store)?;
     }
     // Avoid unused-argument warning in no-debugger builds.
     let _ = store;
@@ -76,10 +76,14 @@
 
 use crate::Engine;
 use crate::prelude::*;
+use crate::runtime::limits::MemoryCharge;
 use crate::runtime::store::StoreResourceLimiter;
 use crate::runtime::vm::vmcontext::VMMemoryDefinition;
 #[cfg(has_virtual_memory)]
-use crate::runtime::vm::{HostAlignedByteCount, MmapOffset};
+use crate::runtime::vm::{
+    HostAlignedByteCount, MmapOffset, ModuleMemoryImageSource, Userfaultfd,
+    UserfaultfdRegistration, host_page_size,
+};
 use crate::runtime::vm::{MemoryImage, MemoryImageSlot, SendSyncPtr};
 use alloc::sync::Arc;
 use core::{ops::Range, ptr::NonNull};
@@ -96,6 +100,9 @@ pub use self::mmap::MmapMemory;
 mod malloc;
 pub use self::malloc::MallocMemory;
 
+mod parked;
+use self::parked::{ParkedMemory, copy_changed_pages};
+
 #[cfg(feature = "pooling-allocator")]
 mod static_;
 #[cfg(feature = "pooling-allocator")]
@@ -237,13 +244,15 @@ impl Memory {
         engine: &Engine,
         creator: &dyn RuntimeMemoryCreator,
         memory_image: Option<&Arc<MemoryImage>>,
-        limiter: Option<&mut StoreResourceLimiter<'_>>,
+        mut limiter: Option<&mut StoreResourceLimiter<'_>>,
     ) -> Result<Self> {
-        let (minimum, maximum) = Self::limit_new(ty, limiter).await?;
+        let (minimum, maximum) = Self::limit_new(ty, limiter.as_deref_mut()).await?;
+        let charge = Self::charge_new(minimum, limiter.as_deref())?;
         let tunables = engine.tunables();
         let allocation = creator.new_memory(ty, tunables, minimum, maximum)?;
 
-        let memory = LocalMemory::new(ty, tunables, allocation, memory_image)?;
+        let mut memory = LocalMemory::new(ty, tunables, allocation, memory_image)?;
+        memory.charge = charge;
         Ok(if ty.shared {
             Memory::Shared(SharedMemory::wrap(engine, ty, memory)?)
         } else {
@@ -259,9 +268,10 @@ impl Memory {
         base: MemoryBase,
         base_capacity: usize,
         memory_image: MemoryImageSlot,
-        limiter: Option<&mut StoreResourceLimiter<'_>>,
+        mut limiter: Option<&mut StoreResourceLimiter<'_>>,
     ) -> Result<Self> {
-        let (minimum, maximum) = Self::limit_new(ty, limiter).await?;
+        let (minimum, maximum) = Self::limit_new(ty, limiter.as_deref_mut()).await?;
+        let charge = Self::charge_new(minimum, limiter.as_deref())?;
         let pooled_memory = StaticMemory::new(base, base_capacity, minimum, maximum)?;
         let allocation = Box::new(pooled_memory);
 
@@ -273,6 +283,7 @@ impl Memory {
         assert!(memory.memory_image.is_none());
         memory.memory_image = Some(memory_image);
         memory.memory_may_move = false;
+        memory.charge = charge;
 
         Ok(if ty.shared {
             // FIXME(#4244): not supported with the pooling allocator (which
@@ -357,6 +368,21 @@ impl Memory {
         Ok((minimum, maximum))
     }
 
+    /// Charges a new memory of `bytes` bytes against the budget of the
+    /// store's limiter, if it has one.
+    fn charge_new(
+        bytes: usize,
+        limiter: Option<&StoreResourceLimiter<'_>>,
+    ) -> Result<Option<MemoryCharge>> {
+        let Some(budget) = limiter.and_then(|l| l.memory_budget()) else {
+            return Ok(None);
+        };
+        match MemoryCharge::new(budget, bytes) {
+            Some(charge) => Ok(Some(charge)),
+            None => bail!("memory of {bytes} bytes exceeds the store's memory budget"),
+        }
+    }
+
     /// Returns this memory's page size, in bytes.
     pub fn page_size(&self) -> u64 {
         match self {
@@ -438,6 +464,81 @@ impl Memory {
         }
     }
 
+    /// Creates a copy of this memory which lives outside of any slot or
+    /// reservation, for use while this memory's store is parked.
+    ///
+    /// The copy has the same type and contents as this memory, and may be
+    /// grown, moving its base pointer, up to the type's maximum size.
+    pub(crate) fn new_parked(&self, tunables: &Tunables) -> Result<Memory> {
+        let Memory::Local(mem) = self else {
+            bail!("shared memories cannot be parked");
+        };
+        let parked = ParkedMemory::new(mem.byte_size())?;
+        let mut memory = LocalMemory::new(&mem.ty, tunables, Box::new(parked), None)?;
+        memory.memory_may_move = true;
+        copy_changed_pages(memory.as_mut_slice(), mem.as_slice());
+        Ok(Memory::Local(memory))
+    }
+
+    /// Moves the budget charge of `other`, if any, to this memory, which is
+    /// replacing it.
+    pub(crate) fn take_charge(&mut self, other: &mut Memory) {
+        if let (Memory::Local(this), Memory::Local(other)) = (self, other) {
+            this.charge = other.charge.take();
+        }
+    }
+
+    /// Copies the contents of `parked`, created with `Memory::new_parked`, back
+    /// into this memory.
+    ///
+    /// # Panics
+    ///
+    /// Panics if either memory is shared or if this memory is smaller than
+    /// `parked`.
+    pub(crate) fn restore_parked(&mut self, parked: &Memory) {
+        match (self, parked) {
+            (Memory::Local(dst), Memory::Local(src)) => {
+                copy_changed_pages(dst.as_mut_slice(), src.as_slice());
+            }
+            _ => panic!("expected local memories"),
+        }
+    }
+
+    /// Registers this memory's initial contents, `source.wasm_data()[data]`
+    /// at `offset`, to be populated by `uffd` page by page on first access
+    /// rather than copied in now.
+    ///
+    /// Returns whether the memory was registered. If it wasn't, because for
+    /// example this memory isn't backed by an mmap or `offset` isn't
+    /// page-aligned, then it needs to be initialized by copying as usual.
+    #[cfg(has_virtual_memory)]
+    pub(crate) fn init_lazily(
+        &mut self,
+        uffd: &Userfaultfd,
+        offset: usize,
+        source: Arc<dyn ModuleMemoryImageSource>,
+        data: Range<usize>,
+    ) -> bool {
+        match self {
+            Memory::Local(mem) => mem.init_lazily(uffd, offset, source, data),
+            Memory::Shared(_) => false,
+        }
+    }
+
+    /// Replaces the contents of this memory with `image`, mapped
+    /// copy-on-write, rather than copying them in.
+    ///
+    /// Returns whether the image was mapped. If it wasn't, because for
+    /// example this memory isn't backed by an mmap or is smaller than
+    /// `image`, then the memory is left as it was.
+    #[cfg(has_virtual_memory)]
+    pub(crate) fn map_image(&mut self, image: &Arc<MemoryImage>) -> Result<bool> {
+        match self {
+            Memory::Local(mem) => mem.map_image(image),
+            Memory::Shared(_) => Ok(false),
+        }
+    }
+
     /// Is this a shared memory?
     pub fn is_shared_memory(&self) -> bool {
         matches!(self, Memory::Shared(_))
@@ -515,6 +616,13 @@ impl Memory {
 /// the implementation basis for a `SharedMemory` behind an `RwLock` for
 /// example.
 pub struct LocalMemory {
+    /// The registration of this memory's initial contents for lazy
+    /// initialization, see `Memory::init_lazily`.
+    ///
+    /// This is declared before `alloc` so that it's dropped, unregistering
+    /// this memory, before the memory itself is deallocated.
+    #[cfg(has_virtual_memory)]
+    lazy_init: Option<UserfaultfdRegistration>,
     alloc: Box<dyn RuntimeLinearMemory>,
     ty: wasmtime_environ::Memory,
     memory_may_move: bool,
@@ -524,6 +632,10 @@ pub struct LocalMemory {
     /// An optional CoW mapping that provides the initial content of this
     /// memory.
     memory_image: Option<MemoryImageSlot>,
+
+    /// This memory's size as charged against the store's memory budget, if
+    /// there is one.
+    charge: Option<MemoryCharge>,
 }
 
 impl LocalMemory {
@@ -564,10 +676,13 @@ impl LocalMemory {
             None => None,
         };
         Ok(LocalMemory {
+            #[cfg(has_virtual_memory)]
+            lazy_init: None,
             ty: *ty,
             alloc,
             memory_may_move: ty.memory_may_move(tunables),
             memory_image,
+            charge: None,
             memory_guard_size: tunables.memory_guard_size.try_into().unwrap(),
             memory_reservation: tunables.memory_reservation.try_into().unwrap(),
         })
@@ -626,12 +741,21 @@ impl LocalMemory {
             }
         }
 
+        // Then the memory budget, if any, which is given back the growth below
+        // if it fails.
+        let delta_bytes = new_byte_size - old_byte_size;
+        let charged = self.charge.as_mut().map(|c| c.grow(delta_bytes));
+
         // Save the original base pointer to assert the invariant that growth up
         // to the byte capacity never relocates the base pointer.
         let base_ptr_before = self.alloc.base().as_mut_ptr();
         let required_to_not_move_memory = new_byte_size <= self.alloc.byte_capacity();
 
         let result = (|| -> Result<()> {
+            if charged == Some(false) {
+                bail!("memory budget exceeded");
+            }
+
             // Never exceed maximum, even if limiter permitted it.
             if let Some(max) = maximum {
                 if new_byte_size > max {
@@ -667,6 +791,19 @@ impl LocalMemory {
                 self.memory_image = None;
             }
 
+            // Likewise a lazily-initialized memory which is about to move
+            // needs the rest of its initial contents populated, so that they're
+            // copied along with it, and to be unregistered before its current
+            // mapping goes away. If they can't be populated the memory can't
+            // move, and so can't grow.
+            #[cfg(has_virtual_memory)]
+            if new_byte_size > self.alloc.byte_capacity()
+                && let Some(lazy_init) = &self.lazy_init
+            {
+                lazy_init.populate()?;
+                self.lazy_init = None;
+            }
+
             // And failing all that fall back to the underlying allocation to
             // grow it.
             self.alloc.grow_to(new_byte_size)
@@ -683,6 +820,10 @@ impl LocalMemory {
                 Ok(Some((old_byte_size, new_byte_size)))
             }
             Err(e) => {
+                if charged == Some(true) {
+                    self.charge.as_mut().unwrap().shrink(delta_bytes);
+                }
+
                 // FIXME: shared memories may not have an associated store to
                 // report the growth failure to but the error should not be
                 // dropped
@@ -703,6 +844,84 @@ impl LocalMemory {
         self.alloc.byte_size()
     }
 
+    fn as_slice(&self) -> &[u8] {
+        let vmmemory = self.alloc.vmmemory();
+        // SAFETY: the first `current_length` bytes of the allocation are
+        // accessible and initialized.
+        unsafe { core::slice::from_raw_parts(vmmemory.base.as_ptr(), vmmemory.current_length()) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        let vmmemory = self.alloc.vmmemory();
+        // SAFETY: see `as_slice` above, and `&mut self` guarantees exclusive
+        // access.
+        unsafe {
+            core::slice::from_raw_parts_mut(vmmemory.base.as_ptr(), vmmemory.current_length())
+        }
+    }
+
+    #[cfg(has_virtual_memory)]
+    fn init_lazily(
+        &mut self,
+        uffd: &Userfaultfd,
+        offset: usize,
+        source: Arc<dyn ModuleMemoryImageSource>,
+        data: Range<usize>,
+    ) -> bool {
+        // Only memories in an anonymous mapping of Wasmtime's own can be
+        // registered, which excludes those created by a `MemoryCreator`.
+        let MemoryBase::Mmap(_) = self.alloc.base() else {
+            return false;
+        };
+        let page_size = host_page_size();
+        let base = self.alloc.base().as_mut_ptr();
+        let len = data.len().next_multiple_of(page_size);
+        if self.lazy_init.is_some()
+            || (base as usize + offset) % page_size != 0
+            || offset + len > self.alloc.byte_size()
+        {
+            return false;
+        }
+
+        // SAFETY: the range is within the accessible part of this memory's
+        // mapping, which lives as long as `self.alloc` and therefore outlives
+        // the registration, and this memory hasn't been initialized yet.
+        match unsafe { uffd.register(base.add(offset), len, source, data) } {
+            Ok(registration) => {
+                self.lazy_init = Some(registration);
+                true
+            }
+            Err(e) => {
+                log::debug!("falling back to eager memory initialization: {e:?}");
+                false
+            }
+        }
+    }
+
+    #[cfg(has_virtual_memory)]
+    fn map_image(&mut self, image: &Arc<MemoryImage>) -> Result<bool> {
+        let MemoryBase::Mmap(base) = self.alloc.base() else {
+            return Ok(false);
+        };
+        let Ok(byte_size) = HostAlignedByteCount::new(self.alloc.byte_size()) else {
+            return Ok(false);
+        };
+        if image.end() > byte_size.byte_count() {
+            return Ok(false);
+        }
+
+        // Any pending lazy initialization is superseded by the image.
+        self.lazy_init = None;
+
+        // Memories without an image of their own don't have a slot yet, so
+        // create one over their mapping just like `LocalMemory::new` does.
+        let slot = self.memory_image.get_or_insert_with(|| {
+            MemoryImageSlot::create(base, byte_size, self.alloc.byte_capacity())
+        });
+        slot.replace_image(image)?;
+        Ok(true)
+    }
+
     pub fn needs_init(&self) -> bool {
         match &self.memory_image {
             Some(image) => !image.has_image(),
@@ -93,7 +93,7 @@ impl RuntimeLinearMemory for MallocMemory {
     }
 }
 
-fn byte_size_to_element_len(byte_size: usize) -> usize {
+pub(super) fn byte_size_to_element_len(byte_size: usize) -> usize {
     let align = mem::align_of::<Align16>();
 
     // Round up the requested byte size to the size of each vector element.
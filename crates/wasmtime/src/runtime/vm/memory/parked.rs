@@ -0,0 +1,121 @@
+//! Support for holding the contents of a linear memory outside of the slot or
+//! reservation it was allocated in, used while its store is parked.
+//!
+//! Storage is allocated with `alloc_zeroed`, which for anything but small
+//! sizes is served by fresh zero pages from the OS, and only the host pages
+//! whose contents differ from zero are written. Unused regions of a parked
+//! memory therefore don't take up any resident memory.
+
+use crate::prelude::*;
+use crate::runtime::vm::SendSyncPtr;
+use crate::runtime::vm::memory::malloc::{Align16, byte_size_to_element_len};
+use crate::runtime::vm::memory::{MemoryBase, RuntimeLinearMemory};
+use core::alloc::Layout;
+use core::ptr::NonNull;
+
+/// A linear memory holding the contents of a parked memory.
+pub struct ParkedMemory {
+    storage: Vec<Align16>,
+    base_ptr: SendSyncPtr<u8>,
+    byte_len: usize,
+}
+
+impl ParkedMemory {
+    /// Creates a new zero-filled memory of `byte_len` bytes.
+    pub fn new(byte_len: usize) -> Result<Self> {
+        let mut storage = alloc_zeroed_storage(byte_len)?;
+        Ok(ParkedMemory {
+            base_ptr: SendSyncPtr::new(NonNull::new(storage.as_mut_ptr()).unwrap()).cast(),
+            storage,
+            byte_len,
+        })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: `storage` owns at least `byte_len` initialized bytes.
+        unsafe { core::slice::from_raw_parts(self.base_ptr.as_ptr(), self.byte_len) }
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: `storage` owns at least `byte_len` initialized bytes.
+        unsafe { core::slice::from_raw_parts_mut(self.base_ptr.as_ptr(), self.byte_len) }
+    }
+}
+
+impl RuntimeLinearMemory for ParkedMemory {
+    fn byte_size(&self) -> usize {
+        self.byte_len
+    }
+
+    fn byte_capacity(&self) -> usize {
+        self.storage.len() * core::mem::size_of::<Align16>()
+    }
+
+    fn grow_to(&mut self, new_size: usize) -> Result<()> {
+        if new_size > self.byte_capacity() {
+            let mut grown = ParkedMemory::new(new_size)?;
+            copy_changed_pages(grown.as_mut_slice(), self.as_slice());
+            *self = grown;
+        }
+        self.byte_len = new_size;
+        Ok(())
+    }
+
+    fn base(&self) -> MemoryBase {
+        MemoryBase::Raw(self.base_ptr)
+    }
+
+    fn vmmemory(&self) -> crate::vm::VMMemoryDefinition {
+        let base = self.base_ptr.as_non_null();
+        crate::vm::VMMemoryDefinition {
+            base: base.into(),
+            current_length: self.byte_len.into(),
+        }
+    }
+}
+
+/// Allocates zeroed storage for `byte_len` bytes without touching it, so that
+/// large allocations are left to the OS to lazily provide.
+fn alloc_zeroed_storage(byte_len: usize) -> Result<Vec<Align16>> {
+    let len = byte_size_to_element_len(byte_len);
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    let layout = Layout::array::<Align16>(len)?;
+
+    // SAFETY: `layout` has a non-zero size.
+    let ptr = unsafe { alloc::alloc::alloc_zeroed(layout) };
+    ensure!(
+        !ptr.is_null(),
+        "failed to allocate memory for parked memory"
+    );
+
+    // SAFETY: `ptr` was allocated by the global allocator with the layout of
+    // `[Align16; len]`, and zero is a valid `Align16`.
+    Ok(unsafe { Vec::from_raw_parts(ptr.cast(), len, len) })
+}
+
+/// Copies `src` into the start of `dst`, one host page at a time, skipping
+/// pages whose contents are already equal.
+///
+/// Skipping equal pages avoids touching the zero pages of a fresh allocation
+/// when parking and avoids copying-on-write pages of a memory's initial image
+/// when unparking.
+pub fn copy_changed_pages(dst: &mut [u8], src: &[u8]) {
+    let page_size = page_size();
+    for (dst, src) in dst[..src.len()]
+        .chunks_mut(page_size)
+        .zip(src.chunks(page_size))
+    {
+        if dst != src {
+            dst.copy_from_slice(src);
+        }
+    }
+}
+
+fn page_size() -> usize {
+    #[cfg(has_virtual_memory)]
+    return crate::runtime::vm::host_page_size();
+    #[cfg(not(has_virtual_memory))]
+    return 4096;
+}
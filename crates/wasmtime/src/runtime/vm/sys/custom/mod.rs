@@ -39,3 +39,7 @@ pub fn tls_get() -> *mut u8 {
 pub fn tls_set(ptr: *mut u8) {
     unsafe { capi::wasmtime_tls_set(ptr) }
 }
+
+pub fn thread_cpu_time() -> Option<core::time::Duration> {
+    None
+}
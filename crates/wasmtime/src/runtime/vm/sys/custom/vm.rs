@@ -8,6 +8,7 @@ use core::ptr::{self, NonNull};
 use std::{fs::File, sync::Arc};
 
 pub use crate::runtime::vm::pagemap_disabled::{PageMap, reset_with_pagemap};
+pub use crate::runtime::vm::uffd_disabled::{Userfaultfd, UserfaultfdRegistration};
 
 pub unsafe fn expose_existing_mapping(ptr: *mut u8, len: usize) -> Result<()> {
     unsafe {
@@ -53,6 +54,10 @@ pub fn get_page_size() -> usize {
     unsafe { capi::wasmtime_page_size() }
 }
 
+pub fn resident_bytes(_base: *const u8, _len: usize) -> Option<usize> {
+    None
+}
+
 pub fn decommit_behavior() -> DecommitBehavior {
     DecommitBehavior::Zero
 }
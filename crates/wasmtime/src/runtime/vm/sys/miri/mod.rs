@@ -22,3 +22,7 @@ pub fn tls_get() -> *mut u8 {
 pub fn tls_set(ptr: *mut u8) {
     TLS.with(|p| p.set(ptr));
 }
+
+pub fn thread_cpu_time() -> Option<std::time::Duration> {
+    None
+}
@@ -4,6 +4,7 @@ use std::io;
 use std::sync::Arc;
 
 pub use crate::runtime::vm::pagemap_disabled::{PageMap, reset_with_pagemap};
+pub use crate::runtime::vm::uffd_disabled::{Userfaultfd, UserfaultfdRegistration};
 
 pub unsafe fn expose_existing_mapping(ptr: *mut u8, len: usize) -> io::Result<()> {
     unsafe {
@@ -45,6 +46,10 @@ pub fn get_page_size() -> usize {
     4096
 }
 
+pub fn resident_bytes(_base: *const u8, _len: usize) -> Option<usize> {
+    None
+}
+
 pub fn decommit_behavior() -> DecommitBehavior {
     DecommitBehavior::Zero
 }
@@ -23,6 +23,11 @@ mod pagemap;
 #[cfg(not(all(target_os = "linux", target_pointer_width = "64", feature = "std")))]
 use crate::vm::pagemap_disabled as pagemap;
 
+#[cfg(all(target_os = "linux", feature = "std"))]
+mod uffd;
+#[cfg(not(all(target_os = "linux", feature = "std")))]
+use crate::vm::uffd_disabled as uffd;
+
 std::thread_local!(static TLS: Cell<*mut u8> = const { Cell::new(std::ptr::null_mut()) });
 
 #[inline]
@@ -34,3 +39,21 @@ pub fn tls_get() -> *mut u8 {
 pub fn tls_set(ptr: *mut u8) {
     TLS.with(|p| p.set(ptr));
 }
+
+/// Returns the CPU time consumed so far by the calling thread, if the
+/// platform can measure it.
+pub fn thread_cpu_time() -> Option<core::time::Duration> {
+    let mut ts = core::mem::MaybeUninit::<libc::timespec>::uninit();
+    // SAFETY: `ts` is a valid place for `clock_gettime` to write to and it's
+    // only read if the call succeeds.
+    unsafe {
+        if libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, ts.as_mut_ptr()) != 0 {
+            return None;
+        }
+        let ts = ts.assume_init();
+        Some(core::time::Duration::new(
+            ts.tv_sec as u64,
+            ts.tv_nsec as u32,
+        ))
+    }
+}
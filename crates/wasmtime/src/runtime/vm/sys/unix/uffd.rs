@@ -0,0 +1,557 @@
+//! Lazy initialization of linear memories with Linux's `userfaultfd`, see
+//! `Config::memory_init_userfaultfd`.
+//!
+//! Each engine configured for lazy initialization owns one `userfaultfd` and
+//! one thread which handles its events. When a memory is instantiated the
+//! range of it covered by its module's initial image is registered with the
+//! `userfaultfd` in "missing" mode, which makes the kernel pause any thread
+//! touching a page of that range which isn't populated yet and report the
+//! fault to the handler thread. The handler thread then looks up which image
+//! the page belongs to and atomically populates it with `UFFDIO_COPY`, or
+//! with `UFFDIO_ZEROPAGE` if its contents are all zero, which also wakes up
+//! the faulting thread.
+//!
+//! If the handler thread can't read events from the `userfaultfd` anymore it
+//! makes every registered region inaccessible and wakes up the threads
+//! waiting on it, so that they trap rather than waiting forever, and then
+//! exits. Memories instantiated afterwards are initialized by copying.
+
+use self::ioctl::*;
+use crate::UserfaultfdStats;
+use crate::prelude::*;
+use crate::runtime::vm::{ModuleMemoryImageSource, host_page_size};
+use rustix::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use rustix::mm::{Advice, MprotectFlags, madvise, mprotect};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::mem::MaybeUninit;
+use std::ops::Range;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+/// A `userfaultfd` along with the thread that populates the pages of the
+/// memories registered with it.
+pub struct Userfaultfd {
+    shared: Arc<Shared>,
+    /// The write end of a pipe whose read end the handler thread polls
+    /// alongside the `userfaultfd`. Closing this tells the thread to exit.
+    shutdown: Option<OwnedFd>,
+    thread: Option<JoinHandle<()>>,
+}
+
+/// State shared between a `Userfaultfd`, its handler thread, and all of its
+/// registrations.
+struct Shared {
+    uffd: OwnedFd,
+    /// All currently registered regions, keyed by their start address.
+    regions: Mutex<BTreeMap<usize, Region>>,
+    /// Whether the handler thread failed and exited, see `Shared::fail`. This
+    /// is only set with `regions` locked.
+    failed: AtomicBool,
+    memories_registered: AtomicU64,
+    pages_faulted: AtomicU64,
+    zero_pages_faulted: AtomicU64,
+}
+
+struct Region {
+    /// The length of this region, a multiple of the host page size.
+    len: usize,
+    /// The module whose data provides this region's contents.
+    source: Arc<dyn ModuleMemoryImageSource>,
+    /// The range of `source.wasm_data()` to populate this region with. This
+    /// may be shorter than `len`, in which case the rest is zero.
+    data: Range<usize>,
+}
+
+impl Userfaultfd {
+    /// Opens a new `userfaultfd` and spawns its handler thread.
+    pub fn new() -> Result<Userfaultfd> {
+        // SAFETY: this system call doesn't have any memory-safety
+        // requirements, and the returned descriptor, if any, is owned by
+        // nothing else.
+        let uffd = unsafe {
+            let fd = libc::syscall(libc::SYS_userfaultfd, libc::O_CLOEXEC | libc::O_NONBLOCK);
+            if fd < 0 {
+                return Err(io::Error::last_os_error()).context("failed to open a userfaultfd");
+            }
+            OwnedFd::from_raw_fd(fd as RawFd)
+        };
+
+        let mut api = uffdio_api {
+            api: UFFD_API,
+            features: 0,
+            ioctls: 0,
+        };
+        // SAFETY: `UFFDIO_API` reads and writes a `uffdio_api`.
+        unsafe {
+            rustix::ioctl::ioctl(&uffd, UffdioApi::new(&mut api))
+                .context("failed to negotiate the userfaultfd API")?;
+        }
+        ensure!(
+            api.ioctls & (1 << UFFDIO_REGISTER_NR) != 0,
+            "userfaultfd does not support registering memory"
+        );
+
+        let mut pipe = [0; 2];
+        // SAFETY: `pipe` has room for the two descriptors `pipe2` returns.
+        let (shutdown_rx, shutdown_tx) = unsafe {
+            if libc::pipe2(pipe.as_mut_ptr(), libc::O_CLOEXEC) != 0 {
+                return Err(io::Error::last_os_error()).context("failed to create a pipe");
+            }
+            (OwnedFd::from_raw_fd(pipe[0]), OwnedFd::from_raw_fd(pipe[1]))
+        };
+
+        let shared = Arc::new(Shared {
+            uffd,
+            regions: Mutex::new(BTreeMap::new()),
+            failed: AtomicBool::new(false),
+            memories_registered: AtomicU64::new(0),
+            pages_faulted: AtomicU64::new(0),
+            zero_pages_faulted: AtomicU64::new(0),
+        });
+        let thread = std::thread::Builder::new()
+            .name("wasmtime-userfaultfd".to_string())
+            .spawn({
+                let shared = shared.clone();
+                move || shared.handle_faults(shutdown_rx)
+            })
+            .context("failed to spawn the userfaultfd handler thread")?;
+
+        Ok(Userfaultfd {
+            shared,
+            shutdown: Some(shutdown_tx),
+            thread: Some(thread),
+        })
+    }
+
+    /// Registers the `len` bytes starting at `base` to be populated from
+    /// `source.wasm_data()[data]` on first access, followed by zeros.
+    ///
+    /// Any pages of the range which are already populated are discarded
+    /// first. The registration lasts until the returned value is dropped,
+    /// after which the range behaves like ordinary anonymous memory again.
+    ///
+    /// Returns an error if the handler thread has failed.
+    ///
+    /// # Safety
+    ///
+    /// `base` must be host-page-aligned, and the range must be part of a
+    /// private anonymous mapping which stays mapped for as long as the
+    /// registration is alive. Nothing may depend on the current contents of
+    /// the range.
+    pub unsafe fn register(
+        &self,
+        base: *mut u8,
+        len: usize,
+        source: Arc<dyn ModuleMemoryImageSource>,
+        data: Range<usize>,
+    ) -> Result<UserfaultfdRegistration> {
+        let page_size = host_page_size();
+        assert_eq!(base as usize % page_size, 0);
+        let len = len.next_multiple_of(page_size);
+        assert!(data.len() <= len);
+        assert!(data.end <= source.wasm_data().len());
+
+        // Discard whatever's already in this range, such as the contents of a
+        // previous instance in a pooling allocator slot, so that every page
+        // of it faults on its next access.
+        //
+        // SAFETY: the range is anonymous memory whose contents aren't needed,
+        // per this function's contract.
+        unsafe {
+            madvise(base.cast(), len, Advice::LinuxDontNeed)?;
+        }
+
+        // Add the region before registering it so that it's known to the
+        // handler thread by the time the first fault within it arrives. If the
+        // handler thread fails from here on then the region is among those it
+        // makes inaccessible.
+        let start = base as usize;
+        let mut regions = self.shared.regions.lock().unwrap();
+        ensure!(
+            !self.shared.failed.load(Ordering::Acquire),
+            "the userfaultfd handler thread has failed"
+        );
+        regions.insert(start, Region { len, source, data });
+        drop(regions);
+        let mut registration = UserfaultfdRegistration {
+            shared: self.shared.clone(),
+            start,
+            len,
+            registered: false,
+        };
+
+        let mut register = uffdio_register {
+            range: uffdio_range {
+                start: start as u64,
+                len: len as u64,
+            },
+            mode: UFFDIO_REGISTER_MODE_MISSING,
+            ioctls: 0,
+        };
+        // SAFETY: `UFFDIO_REGISTER` reads and writes a `uffdio_register`, and
+        // the range is valid to register per this function's contract.
+        unsafe {
+            rustix::ioctl::ioctl(&self.shared.uffd, UffdioRegister::new(&mut register))
+                .context("failed to register memory with userfaultfd")?;
+        }
+        registration.registered = true;
+        ensure!(
+            register.ioctls & (1 << UFFDIO_COPY_NR) != 0
+                && register.ioctls & (1 << UFFDIO_ZEROPAGE_NR) != 0,
+            "userfaultfd cannot populate the registered memory",
+        );
+
+        self.shared
+            .memories_registered
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(registration)
+    }
+
+    /// Returns statistics about the memories registered with this
+    /// `userfaultfd` so far.
+    pub fn stats(&self) -> UserfaultfdStats {
+        UserfaultfdStats {
+            memories_registered: self.shared.memories_registered.load(Ordering::Relaxed),
+            pages_faulted: self.shared.pages_faulted.load(Ordering::Relaxed),
+            zero_pages_faulted: self.shared.zero_pages_faulted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl Drop for Userfaultfd {
+    fn drop(&mut self) {
+        // Closing the write end of the pipe wakes up the handler thread, which
+        // then exits.
+        drop(self.shutdown.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    /// The body of the handler thread, which runs until `shutdown` becomes
+    /// readable or the `userfaultfd` can't be read anymore.
+    fn handle_faults(&self, shutdown: OwnedFd) {
+        let page_size = host_page_size();
+        let mut page = vec![0; page_size];
+        loop {
+            let mut fds = [
+                libc::pollfd {
+                    fd: self.uffd.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: shutdown.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            // SAFETY: `fds` is a valid array of two `pollfd`s.
+            let rc = unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) };
+            if rc < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return self.fail(format_args!("failed to poll userfaultfd: {err}"));
+            }
+            if fds[1].revents != 0 {
+                return;
+            }
+
+            let mut msg = MaybeUninit::<uffd_msg>::uninit();
+            // SAFETY: `msg` is valid to write `size_of::<uffd_msg>()` bytes
+            // to.
+            let n = unsafe {
+                libc::read(
+                    self.uffd.as_raw_fd(),
+                    msg.as_mut_ptr().cast(),
+                    size_of::<uffd_msg>(),
+                )
+            };
+            if n < 0 {
+                // Another fault may have been handled in the meantime, or
+                // the read was interrupted.
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    io::ErrorKind::WouldBlock | io::ErrorKind::Interrupted => continue,
+                    _ => return self.fail(format_args!("failed to read userfaultfd: {err}")),
+                }
+            }
+            if n as usize != size_of::<uffd_msg>() {
+                return self.fail(format_args!("short read of {n} bytes from userfaultfd"));
+            }
+            // SAFETY: the kernel wrote a whole `uffd_msg`.
+            let msg = unsafe { msg.assume_init() };
+            if msg.event != UFFD_EVENT_PAGEFAULT {
+                continue;
+            }
+            let addr = msg.pagefault_address as usize & !(page_size - 1);
+            self.populate(addr, &mut page);
+        }
+    }
+
+    /// Populates the page at `addr` with its contents, waking up any threads
+    /// waiting on it.
+    fn populate(&self, addr: usize, page: &mut [u8]) {
+        let page_size = page.len();
+        // Hold the lock while populating the page so that the region isn't
+        // unregistered, and its memory possibly reused, in the meantime.
+        let regions = self.regions.lock().unwrap();
+        let data = regions
+            .range(..=addr)
+            .next_back()
+            .filter(|(start, region)| addr < **start + region.len)
+            .map(|(start, region)| {
+                let data = &region.source.wasm_data()[region.data.clone()];
+                let offset = (addr - start).min(data.len());
+                &data[offset..][..(data.len() - offset).min(page_size)]
+            })
+            .unwrap_or(&[]);
+
+        let result = if data.iter().all(|b| *b == 0) {
+            self.zero_pages_faulted.fetch_add(1, Ordering::Relaxed);
+            let mut zeropage = uffdio_zeropage {
+                range: uffdio_range {
+                    start: addr as u64,
+                    len: page_size as u64,
+                },
+                mode: 0,
+                zeropage: 0,
+            };
+            // SAFETY: `UFFDIO_ZEROPAGE` reads and writes a `uffdio_zeropage`.
+            unsafe { rustix::ioctl::ioctl(&self.uffd, UffdioZeropage::new(&mut zeropage)) }
+        } else {
+            page[..data.len()].copy_from_slice(data);
+            page[data.len()..].fill(0);
+            let mut copy = uffdio_copy {
+                dst: addr as u64,
+                src: page.as_ptr() as u64,
+                len: page_size as u64,
+                mode: 0,
+                copy: 0,
+            };
+            // SAFETY: `UFFDIO_COPY` reads and writes a `uffdio_copy`, and
+            // `page` is valid to read `page_size` bytes from.
+            unsafe { rustix::ioctl::ioctl(&self.uffd, UffdioCopy::new(&mut copy)) }
+        };
+        self.pages_faulted.fetch_add(1, Ordering::Relaxed);
+
+        // `EEXIST` means the page was populated by a racing fault. For any
+        // other failure wake up the faulting threads anyway so that they
+        // retry, rather than leaving them blocked forever; if the page is
+        // still missing their retry produces a new event.
+        if let Err(e) = result {
+            if e != rustix::io::Errno::EXIST {
+                self.wake(addr, page_size);
+            }
+        }
+    }
+
+    /// Wakes up the threads waiting on a fault in the `len` bytes at `start`,
+    /// which then retry their access.
+    fn wake(&self, start: usize, len: usize) {
+        let mut range = uffdio_range {
+            start: start as u64,
+            len: len as u64,
+        };
+        // SAFETY: `UFFDIO_WAKE` reads a `uffdio_range`.
+        if let Err(e) = unsafe { rustix::ioctl::ioctl(&self.uffd, UffdioWake::new(&mut range)) } {
+            log::warn!("failed to wake threads waiting on userfaultfd: {e}");
+        }
+    }
+
+    /// Called when the handler thread can't handle faults anymore, just
+    /// before it exits.
+    ///
+    /// No more regions can be registered afterwards, and every currently
+    /// registered region is made inaccessible before the threads waiting on
+    /// it are woken up. Their retried access then faults without producing
+    /// an event, which traps if it came from wasm, rather than them waiting
+    /// forever for a page which will never be populated.
+    fn fail(&self, error: fmt::Arguments<'_>) {
+        log::error!("userfaultfd handler thread exiting: {error}");
+        let regions = self.regions.lock().unwrap();
+        self.failed.store(true, Ordering::Release);
+        for (start, region) in regions.iter() {
+            // SAFETY: the region is part of a mapping which stays mapped
+            // while it's registered, per the contract of
+            // `Userfaultfd::register`, and nothing may access its pages
+            // without a fault anyway since they aren't populated.
+            let base = *start as *mut _;
+            if let Err(e) = unsafe { mprotect(base, region.len, MprotectFlags::empty()) } {
+                log::error!("failed to protect memory registered with userfaultfd: {e}");
+            }
+            self.wake(*start, region.len);
+        }
+    }
+}
+
+/// A range of memory registered with a `Userfaultfd`, which is unregistered
+/// when this is dropped.
+pub struct UserfaultfdRegistration {
+    shared: Arc<Shared>,
+    start: usize,
+    len: usize,
+    /// Whether `UFFDIO_REGISTER` succeeded, and so whether `UFFDIO_UNREGISTER`
+    /// is needed.
+    registered: bool,
+}
+
+impl UserfaultfdRegistration {
+    /// Populates every page of the registered range which isn't populated
+    /// yet, after which this can be dropped without losing its contents.
+    ///
+    /// This is used before the range is unmapped while its contents are
+    /// still needed, such as when a memory is moved as part of growing it.
+    ///
+    /// Returns an error if the handler thread has failed, since the
+    /// unpopulated parts of the range are then lost.
+    pub fn populate(&self) -> Result<()> {
+        ensure!(
+            !self.shared.failed.load(Ordering::Acquire),
+            "the userfaultfd handler thread has failed"
+        );
+        for addr in (self.start..self.start + self.len).step_by(host_page_size()) {
+            // SAFETY: the range stays mapped while it's registered, per the
+            // contract of `Userfaultfd::register`, and reading a page of it
+            // blocks until the handler thread has populated the page.
+            unsafe {
+                ptr::read_volatile(addr as *const u8);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for UserfaultfdRegistration {
+    fn drop(&mut self) {
+        let mut regions = self.shared.regions.lock().unwrap();
+        if self.registered {
+            let mut range = uffdio_range {
+                start: self.start as u64,
+                len: self.len as u64,
+            };
+            // SAFETY: `UFFDIO_UNREGISTER` reads a `uffdio_range`. Pages that
+            // were never populated become ordinary zero-filled anonymous
+            // memory again.
+            let result = unsafe {
+                rustix::ioctl::ioctl(&self.shared.uffd, UffdioUnregister::new(&mut range))
+            };
+            // Nothing can be done about a failure here, and at worst pages
+            // of this range which are accessed after it's reused fault with
+            // the handler thread finding no region for them, and so
+            // populating them with zeros.
+            if let Err(e) = result {
+                log::warn!("failed to unregister memory from userfaultfd: {e}");
+            }
+        }
+        regions.remove(&self.start);
+
+        // If the handler thread failed then it made this range inaccessible,
+        // so make it accessible again for its memory to be reused.
+        if self.shared.failed.load(Ordering::Acquire) {
+            // SAFETY: the range is still mapped, per the contract of
+            // `Userfaultfd::register`, and this only restores the protection
+            // that it was registered with.
+            let base = self.start as *mut _;
+            let flags = MprotectFlags::READ | MprotectFlags::WRITE;
+            if let Err(e) = unsafe { mprotect(base, self.len, flags) } {
+                log::error!("failed to unprotect memory registered with userfaultfd: {e}");
+            }
+        }
+    }
+}
+
+/// Bindings for the `userfaultfd` ioctls, from `linux/userfaultfd.h`.
+#[expect(non_camel_case_types, reason = "matching Linux")]
+mod ioctl {
+    use rustix::ioctl::{Updater, opcode};
+
+    const UFFDIO: u8 = 0xaa;
+    pub const UFFD_API: u64 = 0xaa;
+    pub const UFFD_EVENT_PAGEFAULT: u8 = 0x12;
+    pub const UFFDIO_REGISTER_MODE_MISSING: u64 = 1 << 0;
+
+    pub const UFFDIO_REGISTER_NR: u8 = 0x00;
+    const UFFDIO_UNREGISTER_NR: u8 = 0x01;
+    const UFFDIO_WAKE_NR: u8 = 0x02;
+    pub const UFFDIO_COPY_NR: u8 = 0x03;
+    pub const UFFDIO_ZEROPAGE_NR: u8 = 0x04;
+    const UFFDIO_API_NR: u8 = 0x3f;
+
+    #[repr(C)]
+    pub struct uffdio_api {
+        pub api: u64,
+        pub features: u64,
+        pub ioctls: u64,
+    }
+
+    #[repr(C)]
+    pub struct uffdio_range {
+        pub start: u64,
+        pub len: u64,
+    }
+
+    #[repr(C)]
+    pub struct uffdio_register {
+        pub range: uffdio_range,
+        pub mode: u64,
+        pub ioctls: u64,
+    }
+
+    #[repr(C)]
+    pub struct uffdio_copy {
+        pub dst: u64,
+        pub src: u64,
+        pub len: u64,
+        pub mode: u64,
+        pub copy: i64,
+    }
+
+    #[repr(C)]
+    pub struct uffdio_zeropage {
+        pub range: uffdio_range,
+        pub mode: u64,
+        pub zeropage: i64,
+    }
+
+    /// A `uffd_msg` with its union of event arguments flattened to the
+    /// `pagefault` variant, the only one which is enabled.
+    #[repr(C)]
+    #[expect(dead_code, reason = "only ever written by the kernel")]
+    pub struct uffd_msg {
+        pub event: u8,
+        reserved: [u8; 7],
+        pub pagefault_flags: u64,
+        pub pagefault_address: u64,
+        pagefault_feat: [u8; 8],
+    }
+
+    const _: () = assert!(size_of::<uffd_msg>() == 32);
+
+    pub type UffdioApi<'a> =
+        Updater<'a, { opcode::read_write::<uffdio_api>(UFFDIO, UFFDIO_API_NR) }, uffdio_api>;
+    pub type UffdioRegister<'a> = Updater<
+        'a,
+        { opcode::read_write::<uffdio_register>(UFFDIO, UFFDIO_REGISTER_NR) },
+        uffdio_register,
+    >;
+    pub type UffdioUnregister<'a> =
+        Updater<'a, { opcode::read::<uffdio_range>(UFFDIO, UFFDIO_UNREGISTER_NR) }, uffdio_range>;
+    pub type UffdioWake<'a> =
+        Updater<'a, { opcode::read::<uffdio_range>(UFFDIO, UFFDIO_WAKE_NR) }, uffdio_range>;
+    pub type UffdioCopy<'a> =
+        Updater<'a, { opcode::read_write::<uffdio_copy>(UFFDIO, UFFDIO_COPY_NR) }, uffdio_copy>;
+    pub type UffdioZeropage<'a> = Updater<
+        'a,
+        { opcode::read_write::<uffdio_zeropage>(UFFDIO, UFFDIO_ZEROPAGE_NR) },
+        uffdio_zeropage,
+    >;
+}
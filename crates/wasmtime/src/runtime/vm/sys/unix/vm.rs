@@ -7,6 +7,7 @@ use std::io;
 use std::sync::Arc;
 
 pub use super::pagemap::{PageMap, reset_with_pagemap};
+pub use super::uffd::{Userfaultfd, UserfaultfdRegistration};
 
 pub unsafe fn expose_existing_mapping(ptr: *mut u8, len: usize) -> io::Result<()> {
     unsafe {
@@ -80,6 +81,46 @@ pub fn get_page_size() -> usize {
     unsafe { libc::sysconf(libc::_SC_PAGESIZE).try_into().unwrap() }
 }
 
+/// Returns how many of the `len` bytes at `base` are resident in physical
+/// memory, or `None` if that can't be determined.
+pub fn resident_bytes(base: *const u8, len: usize) -> Option<usize> {
+    if len == 0 {
+        return Some(0);
+    }
+    let page_size = crate::runtime::vm::host_page_size();
+    let start = base.addr() & !(page_size - 1);
+    let end = base.addr().checked_add(len)?.next_multiple_of(page_size);
+    let start_ptr = base.wrapping_sub(base.addr() - start);
+
+    // `mincore` writes one byte per page, so large memories are queried a
+    // chunk at a time.
+    let mut pages = [0u8; 1024];
+    let mut resident = 0;
+    let mut offset = 0;
+    while start + offset < end {
+        let chunk = (end - start - offset).min(pages.len() * page_size);
+        // SAFETY: the range is page-aligned and `pages` has room for the one
+        // byte per page which `mincore` writes.
+        let rc = unsafe {
+            libc::mincore(
+                start_ptr.wrapping_add(offset).cast_mut().cast(),
+                chunk,
+                pages.as_mut_ptr().cast(),
+            )
+        };
+        if rc != 0 {
+            return None;
+        }
+        let count = pages[..chunk / page_size]
+            .iter()
+            .filter(|p| **p & 1 != 0)
+            .count();
+        resident += count * page_size;
+        offset += chunk;
+    }
+    Some(resident.min(len))
+}
+
 pub fn decommit_behavior() -> DecommitBehavior {
     if cfg!(target_os = "linux") {
         DecommitBehavior::RestoreOriginalMapping
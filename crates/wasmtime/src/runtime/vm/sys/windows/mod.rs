@@ -28,3 +28,34 @@ pub fn tls_get() -> *mut u8 {
 pub fn tls_set(ptr: *mut u8) {
     TLS.with(|p| p.set(ptr));
 }
+
+/// Returns the CPU time consumed so far by the calling thread, if the
+/// platform can measure it.
+pub fn thread_cpu_time() -> Option<std::time::Duration> {
+    use windows_sys::Win32::Foundation::FILETIME;
+    use windows_sys::Win32::System::Threading::{GetCurrentThread, GetThreadTimes};
+
+    let zero = FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    };
+    let (mut creation, mut exit, mut kernel, mut user) = (zero, zero, zero, zero);
+    // SAFETY: all out-pointers are valid for writes.
+    let ok = unsafe {
+        GetThreadTimes(
+            GetCurrentThread(),
+            &mut creation,
+            &mut exit,
+            &mut kernel,
+            &mut user,
+        )
+    };
+    if ok == 0 {
+        return None;
+    }
+    // `FILETIME`s count 100-nanosecond intervals.
+    let ticks = |t: FILETIME| (u64::from(t.dwHighDateTime) << 32) | u64::from(t.dwLowDateTime);
+    Some(std::time::Duration::from_nanos(
+        (ticks(kernel) + ticks(user)) * 100,
+    ))
+}
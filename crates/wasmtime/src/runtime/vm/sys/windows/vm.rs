@@ -7,6 +7,7 @@ use windows_sys::Win32::System::Memory::*;
 use windows_sys::Win32::System::SystemInformation::*;
 
 pub use crate::runtime::vm::pagemap_disabled::{PageMap, reset_with_pagemap};
+pub use crate::runtime::vm::uffd_disabled::{Userfaultfd, UserfaultfdRegistration};
 
 pub unsafe fn expose_existing_mapping(ptr: *mut u8, len: usize) -> io::Result<()> {
     if len == 0 {
@@ -52,6 +53,10 @@ pub fn get_page_size() -> usize {
     }
 }
 
+pub fn resident_bytes(_base: *const u8, _len: usize) -> Option<usize> {
+    None
+}
+
 pub fn decommit_behavior() -> DecommitBehavior {
     DecommitBehavior::Zero
 }
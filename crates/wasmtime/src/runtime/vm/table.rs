@@ -900,6 +900,68 @@ impl Table {
         Ok((src_range, dst_range))
     }
 
+    /// Moves all of `src`'s elements into this table, resizing this table to
+    /// `src`'s size and leaving `src` empty.
+    ///
+    /// Elements are moved rather than cloned so no GC barriers are involved.
+    /// This is used to move tables in and out of the pooling allocator's slots
+    /// when their store is parked and unparked.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tables' element types differ or if `src`'s elements
+    /// don't fit within this table's maximum size.
+    pub(crate) fn move_elements_from(&mut self, src: &mut Table) {
+        assert_eq!(self.element_type(), src.element_type());
+        let len = src.size();
+        assert!(self.maximum().is_none_or(|max| len <= max));
+
+        self.set_size_with_nulls(len);
+        match self.element_type() {
+            TableElementType::Func => {
+                let (dst, _lazy_init) = self.funcrefs_mut();
+                let (src, _lazy_init) = src.funcrefs_mut();
+                dst.copy_from_slice(src);
+                src.fill(MaybeTaggedFuncRef(None));
+            }
+            TableElementType::GcRef => {
+                for (dst, src) in self.gc_refs_mut().iter_mut().zip(src.gc_refs_mut()) {
+                    *dst = src.take();
+                }
+            }
+            TableElementType::Cont => {
+                let src = src.contrefs_mut();
+                self.contrefs_mut().copy_from_slice(src);
+                src.fill(None);
+            }
+        }
+        src.set_size_with_nulls(0);
+    }
+
+    /// Sets the size of this table without checking limits, filling any new
+    /// elements with null.
+    ///
+    /// Any elements beyond the new size must already be null.
+    fn set_size_with_nulls(&mut self, new_size: usize) {
+        match self {
+            Table::Static(StaticTable::Func(StaticFuncTable { size, .. }))
+            | Table::Static(StaticTable::GcRef(StaticGcRefTable { size, .. }))
+            | Table::Static(StaticTable::Cont(StaticContTable { size, .. })) => {
+                *size = new_size;
+            }
+            Table::Dynamic(DynamicTable::Func(DynamicFuncTable { elements, .. })) => {
+                elements.resize(new_size, None);
+            }
+            Table::Dynamic(DynamicTable::GcRef(DynamicGcRefTable { elements, .. })) => {
+                debug_assert!(elements.iter().skip(new_size).all(|e| e.is_none()));
+                elements.resize_with(new_size, || None);
+            }
+            Table::Dynamic(DynamicTable::Cont(DynamicContTable { elements, .. })) => {
+                elements.resize(new_size, None);
+            }
+        }
+    }
+
     /// Return a `VMTableDefinition` for exposing the table to compiled wasm code.
     pub fn vmtable(&mut self) -> VMTableDefinition {
         match self {
@@ -0,0 +1,40 @@
+//! Stand-in for the `userfaultfd`-based lazy memory initialization on
+//! platforms other than Linux, see `Config::memory_init_userfaultfd`.
+
+#![allow(dead_code, reason = "not used on linux")]
+
+use crate::UserfaultfdStats;
+use crate::prelude::*;
+use crate::runtime::vm::ModuleMemoryImageSource;
+use alloc::sync::Arc;
+use core::ops::Range;
+
+pub enum Userfaultfd {}
+
+impl Userfaultfd {
+    pub fn new() -> Result<Userfaultfd> {
+        bail!("userfaultfd is only supported on Linux")
+    }
+
+    pub unsafe fn register(
+        &self,
+        _base: *mut u8,
+        _len: usize,
+        _source: Arc<dyn ModuleMemoryImageSource>,
+        _data: Range<usize>,
+    ) -> Result<UserfaultfdRegistration> {
+        match *self {}
+    }
+
+    pub fn stats(&self) -> UserfaultfdStats {
+        match *self {}
+    }
+}
+
+pub enum UserfaultfdRegistration {}
+
+impl UserfaultfdRegistration {
+    pub fn populate(&self) -> Result<()> {
+        match *self {}
+    }
+}
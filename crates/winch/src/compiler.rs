@@ -111,6 +111,16 @@ impl wasmtime_environ::Compiler for Compiler {
         None
     }
 
+    fn tier_up_compiler(&self) -> Option<&dyn wasmtime_environ::Compiler> {
+        // The Cranelift compiler used for trampolines targets the Winch calling
+        // convention, so functions it compiles can replace Winch's.
+        if self.tunables.tiered_compilation {
+            Some(&self.trampolines)
+        } else {
+            None
+        }
+    }
+
     fn compile_function(
         &self,
         translation: &ModuleTranslation<'_>,
@@ -137,6 +147,7 @@ impl wasmtime_environ::Compiler for Compiler {
         let func = self
             .isa
             .compile_function(
+                def_func_index,
                 ty,
                 &body,
                 translation,
@@ -90,6 +90,10 @@ enum Subcommand {
     #[cfg(feature = "objdump")]
     Objdump(wasmtime_cli::commands::ObjdumpCommand),
 
+    /// Inspect a core dump of a WebAssembly module
+    #[cfg(feature = "debug-core")]
+    DebugCore(wasmtime_cli::commands::DebugCoreCommand),
+
     #[cfg(feature = "wizer")]
     Wizer(wasmtime_cli::commands::WizerCommand),
 }
@@ -130,6 +134,9 @@ impl Wasmtime {
             #[cfg(feature = "objdump")]
             Subcommand::Objdump(c) => c.execute(),
 
+            #[cfg(feature = "debug-core")]
+            Subcommand::DebugCore(c) => c.execute(),
+
             #[cfg(feature = "wizer")]
             Subcommand::Wizer(c) => c.execute(),
         }
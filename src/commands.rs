@@ -35,6 +35,11 @@ mod settings;
 #[cfg(feature = "cranelift")]
 pub use self::settings::*;
 
+#[cfg(feature = "debug-core")]
+mod debug_core;
+#[cfg(feature = "debug-core")]
+pub use self::debug_core::*;
+
 #[cfg(feature = "objdump")]
 mod objdump;
 #[cfg(feature = "objdump")]
@@ -43,6 +43,14 @@ pub struct CompileCommand {
     #[arg(long = "emit-clif", value_name = "PATH")]
     pub emit_clif: Option<PathBuf>,
 
+    /// The path of a raw 32-byte ed25519 secret key to sign the output with.
+    ///
+    /// Signed modules can be loaded with `Module::deserialize_verified` by
+    /// embedders which trust the corresponding public key.
+    #[cfg(feature = "signing")]
+    #[arg(long = "sign-key", value_name = "PATH")]
+    pub sign_key: Option<PathBuf>,
+
     /// The path of the WebAssembly to compile
     #[arg(index = 1, value_name = "MODULE")]
     pub module: PathBuf,
@@ -70,6 +78,19 @@ impl CompileCommand {
             config.emit_clif(&path);
         }
 
+        #[cfg(feature = "signing")]
+        if let Some(path) = &self.sign_key {
+            let key = fs::read(path)
+                .with_context(|| format!("failed to read signing key: {}", path.display()))?;
+            let Ok(key) = <[u8; 32]>::try_from(key) else {
+                bail!(
+                    "the signing key in '{}' must be exactly 32 bytes",
+                    path.display()
+                );
+            };
+            config.sign_artifacts(Some(wasmtime::ArtifactSigningKey::from_bytes(&key)));
+        }
+
         let engine = Engine::new(&config)?;
 
         if self.module.file_name().is_none() {
@@ -142,6 +163,57 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_signed_compile() -> Result<()> {
+        let (mut input, input_path) = NamedTempFile::new()?.into_parts();
+        input.write_all("(module (func (export \"f\")))".as_bytes())?;
+        drop(input);
+
+        let (mut key, key_path) = NamedTempFile::new()?.into_parts();
+        key.write_all(&[7; 32])?;
+        drop(key);
+
+        let output_path = NamedTempFile::new()?.into_temp_path();
+
+        let command = CompileCommand::try_parse_from(vec![
+            "compile",
+            "-Dlogging=n",
+            "--sign-key",
+            key_path.to_str().unwrap(),
+            "-o",
+            output_path.to_str().unwrap(),
+            input_path.to_str().unwrap(),
+        ])?;
+
+        command.execute()?;
+
+        let contents = std::fs::read(output_path)?;
+        let mut config = wasmtime::Config::new();
+        config.trust_artifact_key(ArtifactSigningKey::from_bytes(&[7; 32]).verifying_key());
+        let engine = Engine::new(&config)?;
+        let module = Module::deserialize_verified(&engine, &contents)?;
+        let mut store = Store::new(&engine, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        instance
+            .get_typed_func::<(), ()>(&mut store, "f")?
+            .call(&mut store, ())?;
+
+        // A key of the wrong length is rejected.
+        let (mut key, key_path) = NamedTempFile::new()?.into_parts();
+        key.write_all(&[7; 31])?;
+        drop(key);
+        let command = CompileCommand::try_parse_from(vec![
+            "compile",
+            "-Dlogging=n",
+            "--sign-key",
+            key_path.to_str().unwrap(),
+            input_path.to_str().unwrap(),
+        ])?;
+        assert!(command.execute().is_err());
+
+        Ok(())
+    }
+
     #[cfg(target_arch = "x86_64")]
     #[test]
     fn test_x64_flags_compile() -> Result<()> {
@@ -0,0 +1,238 @@
+//! The module that implements the `wasmtime debug-core` command.
+
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::str::FromStr;
+use wasmtime::{
+    CoreDumpFrame, CoreDumpImage, Engine, Module, Mutability, Val, WasmBacktraceDetails,
+};
+use wasmtime_cli_flags::CommonOptions;
+
+/// Inspect a core dump of a WebAssembly module without re-running it.
+#[derive(Parser)]
+pub struct DebugCoreCommand {
+    #[command(flatten)]
+    common: CommonOptions,
+
+    /// The path of the WebAssembly module that was running when the core dump
+    /// was created
+    #[arg(required = true, value_name = "MODULE")]
+    module: PathBuf,
+
+    /// The path of the core dump, as written by `wasmtime run -D coredump=...`
+    #[arg(required = true, value_name = "COREDUMP")]
+    coredump: PathBuf,
+
+    /// Print a hex dump of a range of memory, given as
+    /// `MEMORY:ADDRESS:LENGTH`, where `MEMORY` is the index of a memory in the
+    /// core dump
+    #[arg(long = "memory", value_name = "MEMORY:ADDRESS:LENGTH")]
+    memory_ranges: Vec<MemoryRange>,
+}
+
+#[derive(Clone)]
+struct MemoryRange {
+    memory: usize,
+    address: usize,
+    len: usize,
+}
+
+impl FromStr for MemoryRange {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parse = |s: &str| -> Result<usize> {
+            Ok(match s.strip_prefix("0x") {
+                Some(hex) => usize::from_str_radix(hex, 16)?,
+                None => s.parse()?,
+            })
+        };
+        let mut parts = s.split(':');
+        match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(memory), Some(address), Some(len), None) => Ok(MemoryRange {
+                memory: parse(memory)?,
+                address: parse(address)?,
+                len: parse(len)?,
+            }),
+            _ => bail!("expected `MEMORY:ADDRESS:LENGTH`"),
+        }
+    }
+}
+
+impl DebugCoreCommand {
+    /// Executes the command.
+    pub fn execute(mut self) -> Result<()> {
+        self.common.init_logging()?;
+
+        let mut config = self.common.config(None)?;
+        config.wasm_backtrace_details(WasmBacktraceDetails::Enable);
+        let engine = Engine::new(&config)?;
+        let module = Module::from_file(&engine, &self.module)?;
+
+        let bytes = std::fs::read(&self.coredump)
+            .with_context(|| format!("failed to read core dump: {}", self.coredump.display()))?;
+        let dump = CoreDumpImage::parse(&bytes).context("failed to parse core dump")?;
+        if dump.modules().len() != 1 {
+            bail!(
+                "core dump contains {} modules, but only core dumps of a single module are \
+                 supported",
+                dump.modules().len()
+            );
+        }
+        let modules = [module];
+
+        println!("core dump of `{}`", dump.name());
+        for thread in dump.threads() {
+            println!();
+            println!("thread `{}`:", thread.name());
+            for (i, frame) in thread.frames().iter().enumerate() {
+                print_frame(&dump, &modules, i, frame)?;
+            }
+        }
+
+        println!();
+        println!("globals:");
+        for (i, global) in dump.globals().iter().enumerate() {
+            let mutability = match global.mutability() {
+                Mutability::Const => "const",
+                Mutability::Var => "mut",
+            };
+            println!("  {i:>3}: {mutability} {}", fmt_val(global.value()));
+        }
+
+        println!();
+        println!("memories:");
+        for (i, memory) in dump.memories().iter().enumerate() {
+            println!(
+                "  {i:>3}: {} pages ({:#x} bytes)",
+                memory.ty().minimum(),
+                memory.data().len()
+            );
+        }
+
+        for range in &self.memory_ranges {
+            let memory = dump
+                .memories()
+                .get(range.memory)
+                .with_context(|| format!("core dump has no memory {}", range.memory))?;
+            let data = range
+                .address
+                .checked_add(range.len)
+                .and_then(|end| memory.data().get(range.address..end))
+                .with_context(|| {
+                    format!(
+                        "range {:#x}..{:#x} is out of bounds of memory {}",
+                        range.address,
+                        range.address.saturating_add(range.len),
+                        range.memory
+                    )
+                })?;
+            println!();
+            println!("memory {} at {:#x}:", range.memory, range.address);
+            print_hex_dump(range.address, data);
+        }
+
+        Ok(())
+    }
+}
+
+fn print_frame(
+    dump: &CoreDumpImage,
+    modules: &[Module],
+    index: usize,
+    frame: &CoreDumpFrame,
+) -> Result<()> {
+    let info = dump.frame_info(frame, modules)?;
+    let mut line = format!("  {index:>3}: ");
+    if let Some(offset) = info.module_offset() {
+        write!(line, "{offset:#8x} - ")?;
+    }
+    let name = info.module().name().unwrap_or("<unknown>");
+    write!(line, "{name}!")?;
+    wasmtime_environ::demangle_function_name_or_index(
+        &mut line,
+        info.func_name(),
+        info.func_index() as usize,
+    )?;
+    println!("{line}");
+
+    for symbol in info.symbols() {
+        let mut line = String::from("                ");
+        match symbol.name() {
+            Some(name) => wasmtime_environ::demangle_function_name(&mut line, name)?,
+            None => line.push_str("<inlined function>"),
+        }
+        if let Some(file) = symbol.file() {
+            write!(line, " at {file}")?;
+            if let Some(l) = symbol.line() {
+                write!(line, ":{l}")?;
+                if let Some(col) = symbol.column() {
+                    write!(line, ":{col}")?;
+                }
+            }
+        }
+        println!("{line}");
+    }
+
+    let fmt_vals = |vals: &[Option<Val>]| {
+        vals.iter()
+            .map(|v| v.as_ref().map_or_else(|| "<missing>".to_string(), fmt_val))
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    if !frame.locals().is_empty() {
+        println!("                locals: {}", fmt_vals(frame.locals()));
+    }
+    if !frame.stack().is_empty() {
+        println!("                stack: {}", fmt_vals(frame.stack()));
+    }
+    Ok(())
+}
+
+fn fmt_val(val: &Val) -> String {
+    match val {
+        Val::I32(x) => format!("i32 {x}"),
+        Val::I64(x) => format!("i64 {x}"),
+        Val::F32(x) => format!("f32 {}", f32::from_bits(*x)),
+        Val::F64(x) => format!("f64 {}", f64::from_bits(*x)),
+        Val::V128(x) => format!("v128 {:#034x}", x.as_u128()),
+        Val::FuncRef(r) => fmt_ref("funcref", r.is_none()),
+        Val::ExternRef(r) => fmt_ref("externref", r.is_none()),
+        Val::AnyRef(r) => fmt_ref("anyref", r.is_none()),
+        Val::ExnRef(r) => fmt_ref("exnref", r.is_none()),
+        Val::ContRef(r) => fmt_ref("contref", r.is_none()),
+    }
+}
+
+/// References can't be inspected outside of the store they belong to, so only
+/// whether they're null is printed.
+fn fmt_ref(ty: &str, is_null: bool) -> String {
+    if is_null {
+        format!("{ty} null")
+    } else {
+        format!("{ty} <opaque>")
+    }
+}
+
+fn print_hex_dump(address: usize, data: &[u8]) {
+    for (i, chunk) in data.chunks(16).enumerate() {
+        let mut line = format!("  {:08x}: ", address + i * 16);
+        for byte in chunk {
+            line.push_str(&format!("{byte:02x} "));
+        }
+        for _ in chunk.len()..16 {
+            line.push_str("   ");
+        }
+        line.push(' ');
+        line.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        println!("{line}");
+    }
+}
@@ -16,6 +16,8 @@ use wasi_common::sync::{Dir, TcpListener, WasiCtxBuilder, ambient_authority};
 use wasmtime::{Engine, Func, Module, Store, StoreLimits, Val, ValType};
 use wasmtime_wasi::{WasiCtxView, WasiView};
 
+#[cfg(feature = "profiling")]
+use crate::common::GuestProfileFormat;
 #[cfg(feature = "wasi-config")]
 use wasmtime_wasi_config::{WasiConfig, WasiConfigVariables};
 #[cfg(feature = "wasi-http")]
@@ -63,6 +65,55 @@ pub struct RunCommand {
     #[arg(long)]
     pub argv0: Option<String>,
 
+    /// Record the nondeterministic inputs of this execution to a trace at
+    /// this path, including everything that each host call, such as a WASI
+    /// call, returned or wrote to the guest's memory.
+    ///
+    /// The trace can later be passed to `--replay` to deterministically
+    /// re-execute the same run.
+    #[cfg(feature = "rr")]
+    #[arg(long, value_name = "PATH", conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Replay an execution from a trace previously recorded with `--record`.
+    ///
+    /// The same WebAssembly module should be passed as when the trace was
+    /// recorded. Host calls aren't made when replaying, so the guest observes
+    /// the recorded arguments, environment, files and so on, and doesn't
+    /// print anything or otherwise affect the outside world.
+    #[cfg(feature = "rr")]
+    #[arg(long, value_name = "PATH")]
+    pub replay: Option<PathBuf>,
+
+    /// Run the WebAssembly module deterministically, so that running it
+    /// again with the same seed and inputs produces the same results.
+    ///
+    /// Clocks start at 2000-01-01 and only advance when they're read or slept
+    /// on, random numbers are derived from the seed (0 if not given), the
+    /// entries of preopened directories are listed in order of their names
+    /// with fixed timestamps and inode numbers, and networking is disabled.
+    /// Relaxed SIMD instructions and NaNs are made deterministic as well, so
+    /// this can't be combined with `-Wrelaxed-simd-deterministic=n` or
+    /// `-Wnan-canonicalization=n`.
+    #[arg(
+        long,
+        value_name = "SEED",
+        num_args = 0..=1,
+        default_missing_value = "0",
+        require_equals = true
+    )]
+    pub deterministic: Option<u64>,
+
+    /// Wait for a debugger such as LLDB to connect on this address, using
+    /// the GDB remote serial protocol, before running.
+    ///
+    /// The module runs under the debugger's control and can be inspected
+    /// at source level if it contains DWARF debug info. Only core modules
+    /// are supported.
+    #[cfg(feature = "debug")]
+    #[arg(long, value_name = "ADDR")]
+    pub debug_server: Option<String>,
+
     /// The WebAssembly module to run and arguments to pass to it.
     ///
     /// Arguments passed to the wasm module will be configured as WASI CLI
@@ -119,14 +170,74 @@ impl RunCommand {
                 .load_module(&engine, self.module_and_args[0].as_ref())?;
             let (mut store, mut linker) = self.new_store_and_linker(&engine, &main)?;
 
+            #[cfg(feature = "debug")]
+            if let Some(addr) = self.debug_server.clone() {
+                return self
+                    .run_with_debug_server(&addr, engine, linker, main, store)
+                    .await;
+            }
+
             self.instantiate_and_run(&engine, &mut linker, &main, &mut store)
                 .await?;
             Ok(())
         })
     }
 
+    /// Runs the module under a `wasmtime_debugger::Debugger`, controlled
+    /// by a client connecting to `addr`.
+    #[cfg(all(feature = "run", feature = "debug"))]
+    async fn run_with_debug_server(
+        self,
+        addr: &str,
+        engine: Engine,
+        mut linker: CliLinker,
+        main: RunTarget,
+        store: Store<Host>,
+    ) -> Result<()> {
+        let module = match &main {
+            RunTarget::Core(m) => m.clone(),
+            #[cfg(feature = "component-model")]
+            RunTarget::Component(_) => bail!("`--debug-server` does not support components"),
+        };
+
+        // The client reads debug info out of the original wasm binary.
+        let path = PathBuf::from(&self.module_and_args[0]);
+        let bytes =
+            std::fs::read(&path).with_context(|| format!("failed to read wasm module {path:?}"))?;
+        if Engine::detect_precompiled(&bytes).is_some() {
+            bail!("`--debug-server` does not support precompiled modules");
+        }
+        #[cfg(feature = "wat")]
+        let bytes = wat::parse_bytes(&bytes)?.into_owned();
+
+        let debugger = wasmtime_debugger::Debugger::new(store, move |mut store| async move {
+            self.instantiate_and_run(&engine, &mut linker, &main, &mut store)
+                .await?;
+            Ok(store)
+        });
+        let mut server = wasmtime_debugger::GdbServer::new(debugger);
+        server.add_module(path.display().to_string(), &module, bytes.into());
+
+        eprintln!("waiting for a debugger to connect on {addr}");
+        let mut debugger = server.listen(addr).await?;
+        debugger.finish().await?;
+        debugger.take_store().await?;
+        Ok(())
+    }
+
     /// Creates a new `Engine` with the configuration for this command.
     pub fn new_engine(&mut self) -> Result<Engine> {
+        if self.deterministic.is_some() {
+            let wasm = &mut self.run.common.wasm;
+            if wasm.relaxed_simd_deterministic == Some(false) {
+                bail!("`--deterministic` conflicts with `-Wrelaxed-simd-deterministic=n`");
+            }
+            if wasm.nan_canonicalization == Some(false) {
+                bail!("`--deterministic` conflicts with `-Wnan-canonicalization=n`");
+            }
+            wasm.relaxed_simd_deterministic = Some(true);
+            wasm.nan_canonicalization = Some(true);
+        }
         let mut config = self.run.common.config(None)?;
         config.async_support(true);
 
@@ -141,9 +252,25 @@ impl RunCommand {
                 // Further configured down below as well.
                 config.epoch_interruption(true);
             }
+            Some(Profile::Counts { .. }) => {
+                config.count_instructions(true);
+            }
             None => {}
         }
 
+        #[cfg(feature = "debug")]
+        if self.debug_server.is_some() {
+            config.guest_debug(true);
+        }
+
+        #[cfg(feature = "rr")]
+        if self.record.is_some() || self.replay.is_some() {
+            if self.run.common.wasm.timeout.is_some() || self.run.profile.is_some() {
+                bail!("`--record` and `--replay` cannot be combined with timeouts or profiling");
+            }
+            config.record_replay(true);
+        }
+
         Engine::new(&config)
     }
 
@@ -196,6 +323,8 @@ impl RunCommand {
         };
 
         let mut store = Store::new(&engine, host);
+        #[cfg(feature = "rr")]
+        self.set_record_replay(&mut store)?;
         self.populate_with_wasi(&mut linker, &mut store, &main)?;
 
         store.data_mut().limits = self.run.store_limits();
@@ -282,11 +411,25 @@ impl RunCommand {
         })
         .await;
 
-        // Load the main wasm module.
-        let instance = match result.unwrap_or_else(|elapsed| {
+        let result = result.unwrap_or_else(|elapsed| {
             Err(anyhow::Error::from(wasmtime::Trap::Interrupt))
                 .with_context(|| format!("timed out after {elapsed}"))
-        }) {
+        });
+
+        // Finish the record/replay trace, if any, before the process may exit
+        // below. A replay which diverged from its trace takes precedence over
+        // the result of the run since it's likely the cause of it.
+        #[cfg(feature = "rr")]
+        let result = match store.record_replay() {
+            Some(rr) => rr
+                .finish()
+                .context("failed to finish the record/replay trace")
+                .and(result),
+            None => result,
+        };
+
+        // Load the main wasm module.
+        let instance = match result {
             Ok(instance) => instance,
             Err(e) => {
                 // Exit the process if Wasmtime understands the error;
@@ -347,7 +490,12 @@ impl RunCommand {
         main_target: &RunTarget,
         profiled_modules: Vec<(String, Module)>,
     ) -> Result<Box<dyn FnOnce(&mut Store<Host>)>> {
-        if let Some(Profile::Guest { path, interval }) = &self.run.profile {
+        if let Some(Profile::Guest {
+            path,
+            interval,
+            format,
+        }) = &self.run.profile
+        {
             #[cfg(feature = "profiling")]
             return Ok(self.setup_guest_profiler(
                 store,
@@ -355,10 +503,11 @@ impl RunCommand {
                 profiled_modules,
                 path,
                 *interval,
+                *format,
             )?);
             #[cfg(not(feature = "profiling"))]
             {
-                let _ = (profiled_modules, path, interval, main_target);
+                let _ = (profiled_modules, path, interval, format, main_target);
                 bail!("support for profiling disabled at compile time");
             }
         }
@@ -372,6 +521,20 @@ impl RunCommand {
             });
         }
 
+        if let Some(Profile::Counts { path }) = &self.run.profile {
+            let path = path.clone();
+            return Ok(Box::new(move |store| {
+                if let Err(e) = store.instruction_counts().and_then(|counts| {
+                    std::fs::write(&path, counts.to_string()).map_err(anyhow::Error::new)
+                }) {
+                    eprintln!("failed writing instruction counts at {path}: {e:#}");
+                } else {
+                    eprintln!();
+                    eprintln!("Instruction counts written to: {path}");
+                }
+            }));
+        }
+
         Ok(Box::new(|_store| {}))
     }
 
@@ -383,6 +546,7 @@ impl RunCommand {
         profiled_modules: Vec<(String, Module)>,
         path: &str,
         interval: std::time::Duration,
+        format: GuestProfileFormat,
     ) -> Result<Box<dyn FnOnce(&mut Store<Host>)>> {
         use wasmtime::{AsContext, GuestProfiler, StoreContext, StoreContextMut, UpdateDeadline};
 
@@ -457,13 +621,22 @@ impl RunCommand {
                 .expect("profiling doesn't support threads yet");
             if let Err(e) = std::fs::File::create(&path)
                 .map_err(anyhow::Error::new)
-                .and_then(|output| profiler.finish(std::io::BufWriter::new(output)))
+                .and_then(|output| {
+                    let output = std::io::BufWriter::new(output);
+                    match format {
+                        GuestProfileFormat::Firefox => profiler.finish(output),
+                        GuestProfileFormat::Pprof => profiler.finish_pprof(output),
+                        GuestProfileFormat::Collapsed => profiler.finish_collapsed(output),
+                    }
+                })
             {
                 eprintln!("failed writing profile at {path}: {e:#}");
             } else {
                 eprintln!();
                 eprintln!("Profile written to: {path}");
-                eprintln!("View this profile at https://profiler.firefox.com/.");
+                if format == GuestProfileFormat::Firefox {
+                    eprintln!("View this profile at https://profiler.firefox.com/.");
+                }
             }
         }))
     }
@@ -1021,6 +1194,9 @@ impl RunCommand {
         }
 
         if self.run.common.wasi.http == Some(true) {
+            if self.deterministic.is_some() {
+                bail!("`--deterministic` cannot be combined with `-Shttp`");
+            }
             #[cfg(not(all(feature = "wasi-http", feature = "component-model")))]
             {
                 bail!("Cannot enable wasi-http when the binary is not compiled with this feature.");
@@ -1078,6 +1254,12 @@ impl RunCommand {
     }
 
     fn set_legacy_p1_ctx(&self, store: &mut Store<Host>) -> Result<()> {
+        if self.deterministic.is_some() {
+            bail!(
+                "`--deterministic` is not supported with the legacy WASIp1 \
+                 implementation used by `-Spreview2=n` and wasi-threads"
+            );
+        }
         let mut builder = WasiCtxBuilder::new();
         builder.inherit_stdio().args(&self.compute_argv()?)?;
 
@@ -1135,11 +1317,33 @@ impl RunCommand {
         let mut builder = wasmtime_wasi::WasiCtxBuilder::new();
         builder.inherit_stdio().args(&self.compute_argv()?);
         self.run.configure_wasip2(&mut builder)?;
+        if let Some(seed) = self.deterministic {
+            builder.deterministic(seed);
+        }
         let ctx = builder.build_p1();
         store.data_mut().wasip1_ctx = Some(Arc::new(Mutex::new(ctx)));
         Ok(())
     }
 
+    /// Attaches the trace given with `--record` or `--replay`, if any, to
+    /// `store`.
+    #[cfg(feature = "rr")]
+    fn set_record_replay(&self, store: &mut Store<Host>) -> Result<()> {
+        let rr = if let Some(path) = &self.record {
+            let trace = std::fs::File::create(path)
+                .with_context(|| format!("failed to create trace `{}`", path.display()))?;
+            wasmtime::RecordReplay::record(trace)?
+        } else if let Some(path) = &self.replay {
+            let trace = std::fs::File::open(path)
+                .with_context(|| format!("failed to open trace `{}`", path.display()))?;
+            wasmtime::RecordReplay::replay(trace)
+                .with_context(|| format!("failed to read trace `{}`", path.display()))?
+        } else {
+            return Ok(());
+        };
+        store.set_record_replay(rr)
+    }
+
     #[cfg(feature = "wasi-nn")]
     fn collect_preloaded_nn_graphs(
         &self,
@@ -1,3 +1,5 @@
+#[cfg(feature = "profiling")]
+use crate::common::GuestProfileFormat;
 use crate::common::{Profile, RunCommon, RunTarget};
 use anyhow::{Context as _, Result, bail};
 use bytes::Bytes;
@@ -415,6 +417,9 @@ impl ServeCommand {
             Some(Profile::Guest { .. }) => {
                 config.epoch_interruption(true);
             }
+            Some(Profile::Counts { .. }) => {
+                bail!("`--profile=counts` is not supported with `wasmtime serve`");
+            }
             None => {}
         }
 
@@ -505,6 +510,27 @@ impl ServeCommand {
             1
         };
 
+        // The pprof and collapsed-stack formats aggregate samples from every
+        // request into a single profile which is written on shutdown.
+        #[cfg(feature = "profiling")]
+        let aggregated_profile = match &self.run.profile {
+            Some(Profile::Guest {
+                path,
+                interval,
+                format,
+            }) if *format != GuestProfileFormat::Firefox => Some((
+                wasmtime::ProfileAggregator::new_component(
+                    &engine,
+                    *interval,
+                    component.clone(),
+                    std::iter::empty(),
+                )?,
+                path.clone(),
+                *format,
+            )),
+            _ => None,
+        };
+
         let handler = ProxyHandler::new(
             HostHandlerState {
                 cmd: self,
@@ -512,6 +538,8 @@ impl ServeCommand {
                 component,
                 max_instance_reuse_count,
                 max_instance_concurrent_reuse_count,
+                #[cfg(feature = "profiling")]
+                profile_aggregator: aggregated_profile.as_ref().map(|(a, ..)| a.clone()),
             },
             instance,
         );
@@ -589,13 +617,17 @@ impl ServeCommand {
         // processing in child tasks. If there are wait for those to complete
         // before shutting down completely. Also enable short-circuiting this
         // wait with a second ctrl-c signal.
-        if shutdown.close() {
-            return Ok(());
+        if !shutdown.close() {
+            eprintln!("Waiting for child tasks to exit, ctrl-c again to quit sooner...");
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = shutdown.complete.notified() => {}
+            }
         }
-        eprintln!("Waiting for child tasks to exit, ctrl-c again to quit sooner...");
-        tokio::select! {
-            _ = tokio::signal::ctrl_c() => {}
-            _ = shutdown.complete.notified() => {}
+
+        #[cfg(feature = "profiling")]
+        if let Some((aggregator, path, format)) = aggregated_profile {
+            write_aggregated_profile(&aggregator, &path, format);
         }
 
         Ok(())
@@ -608,6 +640,8 @@ struct HostHandlerState {
     component: Component,
     max_instance_reuse_count: usize,
     max_instance_concurrent_reuse_count: usize,
+    #[cfg(feature = "profiling")]
+    profile_aggregator: Option<wasmtime::ProfileAggregator>,
 }
 
 impl HandlerState for HostHandlerState {
@@ -615,7 +649,7 @@ impl HandlerState for HostHandlerState {
 
     fn new_store(&self, req_id: Option<u64>) -> Result<StoreBundle<Host>> {
         let mut store = self.cmd.new_store(&self.engine, req_id)?;
-        let write_profile = setup_epoch_handler(&self.cmd, &mut store, self.component.clone())?;
+        let write_profile = setup_epoch_handler(self, &mut store)?;
 
         Ok(StoreBundle {
             store,
@@ -733,15 +767,16 @@ impl Drop for EpochThread {
 
 type WriteProfile = Box<dyn FnOnce(StoreContextMut<Host>) + Send>;
 
-fn setup_epoch_handler(
-    cmd: &ServeCommand,
-    store: &mut Store<Host>,
-    component: Component,
-) -> Result<WriteProfile> {
+fn setup_epoch_handler(state: &HostHandlerState, store: &mut Store<Host>) -> Result<WriteProfile> {
+    let cmd = &state.cmd;
+
     // Profiling Enabled
-    if let Some(Profile::Guest { interval, path }) = &cmd.run.profile {
+    if let Some(Profile::Guest { interval, path, .. }) = &cmd.run.profile {
         #[cfg(feature = "profiling")]
-        return setup_guest_profiler(store, path.clone(), *interval, component.clone());
+        return match &state.profile_aggregator {
+            Some(aggregator) => setup_aggregated_profiler(store, aggregator.clone()),
+            None => setup_guest_profiler(store, path.clone(), *interval, state.component.clone()),
+        };
         #[cfg(not(feature = "profiling"))]
         {
             let _ = (path, interval);
@@ -822,6 +857,44 @@ fn setup_guest_profiler(
     Ok(write_profile)
 }
 
+#[cfg(feature = "profiling")]
+fn setup_aggregated_profiler(
+    store: &mut Store<Host>,
+    aggregator: wasmtime::ProfileAggregator,
+) -> Result<WriteProfile> {
+    store.epoch_deadline_callback(move |store| {
+        aggregator.sample(store, std::time::Duration::ZERO);
+        Ok(UpdateDeadline::Continue(1))
+    });
+
+    store.set_epoch_deadline(1);
+
+    // The aggregated profile is written once the server shuts down.
+    Ok(Box::new(|_store| {}))
+}
+
+#[cfg(feature = "profiling")]
+fn write_aggregated_profile(
+    aggregator: &wasmtime::ProfileAggregator,
+    path: &str,
+    format: GuestProfileFormat,
+) {
+    if let Err(e) = std::fs::File::create(path)
+        .map_err(anyhow::Error::new)
+        .and_then(|output| {
+            let output = std::io::BufWriter::new(output);
+            match format {
+                GuestProfileFormat::Pprof => aggregator.write_pprof(output),
+                _ => aggregator.write_collapsed(output),
+            }
+        })
+    {
+        eprintln!("failed writing profile at {path}: {e:#}");
+    } else {
+        eprintln!("Profile written to: {path}");
+    }
+}
+
 type Request = hyper::Request<hyper::body::Incoming>;
 
 async fn handle_request(
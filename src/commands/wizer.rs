@@ -85,6 +85,13 @@ impl WizerCommand {
             }),
             module_and_args: vec![self.input.clone().into()],
             preloads: self.preloads.clone(),
+            #[cfg(feature = "rr")]
+            record: None,
+            #[cfg(feature = "rr")]
+            replay: None,
+            deterministic: None,
+            #[cfg(feature = "debug")]
+            debug_server: None,
         };
         let engine = run.new_engine()?;
 
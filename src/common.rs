@@ -57,7 +57,8 @@ pub struct RunCommon {
     #[arg(long = "allow-precompiled")]
     pub allow_precompiled: bool,
 
-    /// Profiling strategy (valid options are: perfmap, jitdump, vtune, guest)
+    /// Profiling strategy (valid options are: perfmap, jitdump, vtune, guest,
+    /// counts)
     ///
     /// The perfmap, jitdump, and vtune profiling strategies integrate Wasmtime
     /// with external profilers such as `perf`. The guest profiling strategy
@@ -72,6 +73,23 @@ pub struct RunCommon {
     /// where `path` is where to write the profile and `interval` is the
     /// duration between samples. When used with `--wasm-timeout` the timeout
     /// will be rounded up to the nearest multiple of this interval.
+    ///
+    /// The profile is written in the Firefox Profiler's format unless `path`
+    /// ends in `.pb` or `.pprof`, which writes a pprof protobuf, or in
+    /// `.folded` or `.collapsed`, which writes collapsed stacks for flame
+    /// graph tools. With `wasmtime serve` the pprof and collapsed formats
+    /// aggregate samples from all requests into one profile, which is written
+    /// when the server shuts down.
+    ///
+    /// The counts strategy instruments compiled code to count exactly how
+    /// many WebAssembly instructions each function and basic block executes,
+    /// without any sampling or hardware performance counters. It is
+    /// configured as:
+    ///
+    ///     --profile=counts[,path]
+    ///
+    /// and writes a plain-text report, hottest functions first, to `path` or
+    /// `wasmtime-instruction-counts.txt` by default.
     #[arg(
         long,
         value_name = "STRATEGY",
@@ -401,7 +419,40 @@ impl RunCommon {
 #[derive(Clone, PartialEq)]
 pub enum Profile {
     Native(wasmtime::ProfilingStrategy),
-    Guest { path: String, interval: Duration },
+    Guest {
+        path: String,
+        interval: Duration,
+        format: GuestProfileFormat,
+    },
+    Counts {
+        path: String,
+    },
+}
+
+/// The file format a guest profile is written in, chosen based on the
+/// extension of the profile's path.
+#[derive(Clone, Copy, PartialEq)]
+pub enum GuestProfileFormat {
+    /// The Firefox Profiler's JSON format, the default.
+    Firefox,
+    /// A pprof protobuf, for `.pb` and `.pprof` paths.
+    Pprof,
+    /// Collapsed stacks for flame graph tools, for `.folded` and `.collapsed`
+    /// paths.
+    Collapsed,
+}
+
+impl GuestProfileFormat {
+    fn from_path(path: &str) -> GuestProfileFormat {
+        match std::path::Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+        {
+            Some("pb" | "pprof") => GuestProfileFormat::Pprof,
+            Some("folded" | "collapsed") => GuestProfileFormat::Collapsed,
+            _ => GuestProfileFormat::Firefox,
+        }
+    }
 }
 
 impl Profile {
@@ -416,14 +467,23 @@ impl Profile {
             ["guest"] => Ok(Profile::Guest {
                 path: "wasmtime-guest-profile.json".to_string(),
                 interval: Duration::from_millis(10),
+                format: GuestProfileFormat::Firefox,
             }),
             ["guest", path] => Ok(Profile::Guest {
                 path: path.to_string(),
                 interval: Duration::from_millis(10),
+                format: GuestProfileFormat::from_path(path),
             }),
             ["guest", path, dur] => Ok(Profile::Guest {
                 path: path.to_string(),
                 interval: WasmtimeOptionValue::parse(Some(dur))?,
+                format: GuestProfileFormat::from_path(path),
+            }),
+            ["counts"] => Ok(Profile::Counts {
+                path: "wasmtime-instruction-counts.txt".to_string(),
+            }),
+            ["counts", path] => Ok(Profile::Counts {
+                path: path.to_string(),
             }),
             _ => bail!("unknown profiling strategy: {s}"),
         }
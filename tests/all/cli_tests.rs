@@ -109,6 +109,102 @@ fn run_coredump_smoketest() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg(feature = "debug-core")]
+fn debug_core() -> Result<()> {
+    use wasm_encoder::{
+        ConstExpr, CoreDumpInstancesSection, CoreDumpModulesSection, CoreDumpSection,
+        CoreDumpStackSection, CoreDumpValue, DataSection, GlobalSection, GlobalType, MemorySection,
+        MemoryType, Module, ValType,
+    };
+
+    let wasm = build_wasm("tests/all/cli_tests/debug-core.wat")?;
+
+    // A core dump of `$divide` trapping when called from `$main`, with the
+    // locals and operand stack recorded, which `wasmtime run` doesn't do.
+    let mut dump = Module::new();
+    dump.section(&CoreDumpSection::new("crashy.wasm"));
+    let mut memories = MemorySection::new();
+    memories.memory(MemoryType {
+        minimum: 1,
+        maximum: None,
+        memory64: false,
+        shared: false,
+        page_size_log2: None,
+    });
+    dump.section(&memories);
+    let mut globals = GlobalSection::new();
+    globals.global(
+        GlobalType {
+            val_type: ValType::I32,
+            mutable: true,
+            shared: false,
+        },
+        &ConstExpr::i32_const(42),
+    );
+    dump.section(&globals);
+    let mut data = DataSection::new();
+    data.active(0, &ConstExpr::i32_const(0x10), b"hello".iter().copied());
+    dump.section(&data);
+    let mut modules = CoreDumpModulesSection::new();
+    modules.module("crashy");
+    dump.section(&modules);
+    let mut instances = CoreDumpInstancesSection::new();
+    instances.instance(0, [0], [0]);
+    dump.section(&instances);
+    let mut stack = CoreDumpStackSection::new("main");
+    stack.frame(
+        0,
+        1,
+        4,
+        [CoreDumpValue::I32(1), CoreDumpValue::I32(0)],
+        [CoreDumpValue::I32(1), CoreDumpValue::I32(0)],
+    );
+    stack.frame(
+        0,
+        0,
+        6,
+        [CoreDumpValue::I32(0), CoreDumpValue::I64(-5)],
+        [CoreDumpValue::Missing],
+    );
+    dump.section(&stack);
+    let mut coredump = NamedTempFile::new()?;
+    coredump.write_all(&dump.finish())?;
+
+    let output = run_wasmtime(&[
+        "debug-core",
+        "-Ccache=n",
+        "--memory",
+        "0:0x10:5",
+        wasm.path().to_str().unwrap(),
+        coredump.path().to_str().unwrap(),
+    ])?;
+    let lines = output.lines().collect::<Vec<_>>();
+
+    assert_eq!(lines[0], "core dump of `crashy.wasm`");
+    assert_eq!(lines[2], "thread `main`:");
+    assert!(lines[3].starts_with("    0: "), "{output}");
+    assert!(lines[3].ends_with(" - crashy!divide"), "{output}");
+    assert_eq!(lines[4].trim(), "locals: i32 1, i32 0");
+    assert_eq!(lines[5].trim(), "stack: i32 1, i32 0");
+    assert!(lines[6].starts_with("    1: "), "{output}");
+    assert!(lines[6].ends_with(" - crashy!main"), "{output}");
+    assert_eq!(lines[7].trim(), "locals: i32 0, i64 -5");
+    assert_eq!(lines[8].trim(), "stack: <missing>");
+    assert_eq!(lines[10..12], ["globals:", "    0: mut i32 42"]);
+    assert_eq!(
+        lines[13..15],
+        ["memories:", "    0: 1 pages (0x10000 bytes)"]
+    );
+    assert_eq!(lines[16], "memory 0 at 0x10:");
+    assert!(
+        lines[17].starts_with("  00000010: 68 65 6c 6c 6f "),
+        "{output}"
+    );
+    assert!(lines[17].ends_with(" hello"), "{output}");
+    Ok(())
+}
+
 // Running simple wat
 #[test]
 fn run_wasmtime_simple_wat() -> Result<()> {
@@ -1102,6 +1198,43 @@ fn increase_stack_size() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg_attr(miri, ignore)]
+fn record_and_replay() -> Result<()> {
+    let wasm = build_wasm("tests/all/cli_tests/record-replay.wat")?;
+    let wasm = wasm.path().to_str().unwrap();
+    let td = TempDir::new()?;
+    let trace = td.path().join("trace");
+    let trace = trace.to_str().unwrap();
+
+    // The module traps unless it's given two arguments.
+    let stdout = run_wasmtime(&["run", "-Ccache=n", "--record", trace, wasm, "a", "b"])?;
+    assert_eq!(stdout, "two arguments\n");
+    assert!(run_wasmtime(&["run", "-Ccache=n", wasm]).is_err());
+
+    // When replaying, the module sees the recorded arguments rather than the
+    // real ones, and its output isn't written again.
+    let stdout = run_wasmtime(&["run", "-Ccache=n", "--replay", trace, wasm])?;
+    assert_eq!(stdout, "");
+
+    // A module which makes different host calls diverges from the trace.
+    let other = build_wasm("tests/all/cli_tests/print-arguments.wat")?;
+    let output = run_wasmtime_for_output(
+        &[
+            "run",
+            "-Ccache=n",
+            "--replay",
+            trace,
+            other.path().to_str().unwrap(),
+        ],
+        None,
+    )?;
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("replay diverged"), "{stderr}");
+    Ok(())
+}
+
 mod test_programs {
     use super::{get_wasmtime_command, run_wasmtime};
     use anyhow::{Context, Result, bail};
@@ -1476,6 +1609,35 @@ mod test_programs {
         Ok(())
     }
 
+    #[test]
+    fn p2_cli_deterministic() -> Result<()> {
+        // Networking is turned off, even when inherited, and sleeps return
+        // immediately.
+        for component in [
+            P2_CLI_NO_TCP_COMPONENT,
+            P2_CLI_NO_UDP_COMPONENT,
+            P2_CLI_NO_IP_NAME_LOOKUP_COMPONENT,
+        ] {
+            run_wasmtime(&[
+                "run",
+                "--deterministic",
+                "-Sinherit-network,allow-ip-name-lookup",
+                component,
+            ])?;
+        }
+        run_wasmtime(&["run", "--deterministic=42", P2_CLI_SLEEP_COMPONENT])?;
+        run_wasmtime(&["run", "--deterministic", P2_SLEEP_COMPONENT])?;
+
+        // Asking for nondeterministic execution at the same time is an error.
+        for flag in ["-Wrelaxed-simd-deterministic=n", "-Wnan-canonicalization=n"] {
+            let err = run_wasmtime(&["run", "--deterministic", flag, P2_SLEEP_COMPONENT])
+                .unwrap_err()
+                .to_string();
+            assert!(err.contains("conflicts with"), "{err}");
+        }
+        Ok(())
+    }
+
     #[test]
     fn p2_cli_sleep() -> Result<()> {
         run_wasmtime(&["run", P2_CLI_SLEEP])?;
@@ -0,0 +1,13 @@
+(module $crashy
+    (func $main (export "main") (param i32) (result i32)
+        (local i64)
+        i32.const 1
+        local.get 0
+        call $divide
+    )
+    (func $divide (param i32 i32) (result i32)
+        local.get 0
+        local.get 1
+        i32.div_u
+    )
+)
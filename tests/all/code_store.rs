@@ -0,0 +1,75 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use wasmtime::*;
+
+const WAT: &str = r#"(module (func (export "answer") (result i32) i32.const 42))"#;
+
+/// Returns the paths of every file in `dir`, recursively.
+fn files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut ret = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            ret.extend(files(&path)?);
+        } else {
+            ret.push(path);
+        }
+    }
+    Ok(ret)
+}
+
+fn answer(engine: &Engine, module: &Module) -> Result<i32> {
+    let mut store = Store::new(engine, ());
+    let instance = Instance::new(&mut store, module, &[])?;
+    let answer = instance.get_typed_func::<(), i32>(&mut store, "answer")?;
+    answer.call(&mut store, ())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn compile_and_load_through_code_store() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let new_engine = || -> Result<Engine> {
+        let mut config = Config::new();
+        config.code_store(Some(CodeStore::new(dir.path())?));
+        Engine::new(&config)
+    };
+
+    // Compiling a module publishes its artifact to the store, and the module
+    // runs from the published artifact.
+    let engine = new_engine()?;
+    let module = Module::new(&engine, WAT)?;
+    assert_eq!(answer(&engine, &module)?, 42);
+    let artifacts = files(dir.path())?;
+    assert_eq!(artifacts.len(), 1);
+    let artifact = &artifacts[0];
+
+    // Another engine, standing in for another process, loads the artifact
+    // from the store rather than compiling the module again, even when the
+    // artifact is read-only. Loading it marks it as used.
+    let long_ago = SystemTime::UNIX_EPOCH + Duration::from_secs(24 * 60 * 60);
+    fs::File::options()
+        .write(true)
+        .open(artifact)?
+        .set_modified(long_ago)?;
+    let mut permissions = fs::metadata(artifact)?.permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(artifact, permissions)?;
+
+    let engine = new_engine()?;
+    let module = Module::new(&engine, WAT)?;
+    assert_eq!(answer(&engine, &module)?, 42);
+    assert_eq!(files(dir.path())?, artifacts);
+    assert!(fs::metadata(artifact)?.modified()? > long_ago);
+
+    // Engines with a different configuration don't share artifacts.
+    let mut config = Config::new();
+    config.code_store(Some(CodeStore::new(dir.path())?));
+    config.cranelift_opt_level(OptLevel::None);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, WAT)?;
+    assert_eq!(answer(&engine, &module)?, 42);
+    assert_eq!(files(dir.path())?.len(), 2);
+    Ok(())
+}
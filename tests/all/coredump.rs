@@ -293,3 +293,91 @@ fn core_dump_with_shared_memory() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn core_dump_image_round_trip() -> Result<()> {
+    let mut config = Config::new();
+    config.coredump_on_trap(true);
+    let engine = Engine::new(&config)?;
+    let mut store = Store::new(&engine, ());
+    let wat = r#"(module $m
+        (memory 2)
+        (global (mut i64) (i64.const 7))
+        (global f32 (f32.const 1.5))
+        (func $a (export "a")
+            (i32.store (i32.const 0x10000) (i32.const 0x01020304))
+            call $b)
+        (func $b unreachable)
+        (data (i32.const 4) "abc")
+    )"#;
+    let module = Module::new(&engine, wat)?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let a = instance.get_typed_func::<(), ()>(&mut store, "a")?;
+    let err = a.call(&mut store, ()).unwrap_err();
+    let coredump = err.downcast_ref::<WasmCoreDump>().unwrap();
+    let bytes = coredump.serialize(&mut store, "round-trip");
+
+    let image = CoreDumpImage::parse(&bytes)?;
+    assert_eq!(image.name(), "round-trip");
+    assert_eq!(image.modules(), ["m"]);
+    assert_eq!(image.instances().len(), 1);
+    assert_eq!(image.instances()[0].module(), 0);
+
+    assert_eq!(image.memories().len(), 1);
+    let memory = &image.memories()[0];
+    assert_eq!(memory.ty().minimum(), 2);
+    assert_eq!(memory.data().len(), 2 << 16);
+    assert_eq!(&memory.data()[4..7], b"abc");
+    assert_eq!(&memory.data()[0x10000..0x10004], &[4, 3, 2, 1]);
+
+    assert_eq!(image.globals().len(), 2);
+    assert_eq!(image.globals()[0].mutability(), Mutability::Var);
+    assert_eq!(image.globals()[0].value().unwrap_i64(), 7);
+    assert_eq!(image.globals()[1].value().unwrap_f32(), 1.5);
+
+    assert_eq!(image.threads().len(), 1);
+    let thread = &image.threads()[0];
+    assert_eq!(thread.frames().len(), 2);
+    assert_eq!(thread.frames()[0].func_index(), 1);
+    assert_eq!(thread.frames()[1].func_index(), 0);
+
+    // Program counters are mapped back through the module that was running.
+    let backtrace = image.backtrace(thread, &[module.clone()])?;
+    let names = backtrace
+        .frames()
+        .iter()
+        .map(|f| f.func_name())
+        .collect::<Vec<_>>();
+    assert_eq!(names, [Some("b"), Some("a")]);
+    for (frame, original) in backtrace.frames().iter().zip(coredump.frames()) {
+        assert_eq!(frame.module_offset(), original.module_offset());
+    }
+
+    let other = Module::new(&engine, "(module $other)")?;
+    assert!(image.backtrace(thread, &[other]).is_err());
+    assert!(image.backtrace(thread, &[]).is_err());
+
+    assert!(CoreDumpImage::parse(&wat::parse_str("(module)")?).is_err());
+
+    // Frames from another core dump are rejected rather than indexing out of
+    // bounds.
+    let empty = CoreDumpImage::parse(&wat::parse_str(r#"(module (@custom "core" "\00\00"))"#)?)?;
+    assert!(empty.instances().is_empty());
+    assert!(empty.frame_info(&thread.frames()[0], &[]).is_err());
+    Ok(())
+}
+
+#[test]
+fn core_dump_image_rejects_huge_memories() -> Result<()> {
+    // A 1 TiB memory, described by a few bytes.
+    let bytes = wat::parse_str(
+        r#"(module
+            (memory i64 0x1000000)
+            (@custom "core" "\00\00")
+        )"#,
+    )?;
+    let err = CoreDumpImage::parse(&bytes).unwrap_err();
+    assert!(format!("{err:?}").contains("too large"), "{err:?}");
+    Ok(())
+}
@@ -4,7 +4,7 @@ use std::sync::Arc;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use wasmtime::{
     AsContextMut, Caller, Config, DebugEvent, DebugHandler, Engine, Extern, FrameParentResult,
-    Func, Instance, Module, Store, StoreContextMut, Val,
+    Func, Instance, Module, Store, StoreContextMut, Val, WatchpointKind,
 };
 
 #[test]
@@ -798,3 +798,203 @@ async fn epoch_events() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn watchpoint_events() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+
+    let (module, mut store) = get_module_and_store(
+        |config| {
+            config.async_support(true);
+        },
+        r#"
+    (module
+      (memory (export "memory") 1)
+      (func (export "main") (param i32)
+        (i32.store offset=4 (i32.const 12) (local.get 0))
+        (i32.store (i32.const 64) (local.get 0))
+        (drop (i32.load8_u (i32.const 17)))))
+    "#,
+    )?;
+
+    let instance = Instance::new_async(&mut store, &module, &[]).await?;
+    let func = instance.get_func(&mut store, "main").unwrap();
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+
+    debug_event_checker!(
+        D, store,
+        { 0 ;
+          wasmtime::DebugEvent::Watchpoint(event) => {
+              assert!(Module::same(&event.module, &module));
+              assert_eq!(event.address, 16);
+              assert_eq!(event.kind, WatchpointKind::Write);
+              assert_eq!(event.old_value, [0, 0, 0, 0]);
+              assert_eq!(event.new_value.as_deref(), Some(&[0x44, 0x33, 0x22, 0x11][..]));
+              let stack = store.debug_frames().unwrap();
+              let (_, pc) = stack.wasm_function_index_and_pc().unwrap();
+              assert_eq!(pc, event.pc);
+          }
+        }
+    );
+
+    let (handler, counter) = D::new_and_counter();
+    store.set_debug_handler(handler);
+    store
+        .edit_breakpoints()
+        .unwrap()
+        .add_watchpoint(&memory, 16..20, WatchpointKind::Write)?;
+    func.call_async(&mut store, &[Val::I32(0x11223344)], &mut [])
+        .await?;
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+
+    // Swap the write watchpoint for a read watchpoint overlapping
+    // only the byte loaded by `i32.load8_u`.
+    debug_event_checker!(
+        D2, store,
+        { 0 ;
+          wasmtime::DebugEvent::Watchpoint(event) => {
+              assert_eq!(event.address, 17);
+              assert_eq!(event.kind, WatchpointKind::Read);
+              assert_eq!(event.old_value, [0x33]);
+              assert_eq!(event.new_value, None);
+          }
+        }
+    );
+
+    let (handler, counter) = D2::new_and_counter();
+    store.set_debug_handler(handler);
+    {
+        let mut edit = store.edit_breakpoints().unwrap();
+        edit.remove_watchpoint(&memory, 16..20, WatchpointKind::Write)?;
+        edit.add_watchpoint(&memory, 17..18, WatchpointKind::Read)?;
+    }
+    func.call_async(&mut store, &[Val::I32(0x11223344)], &mut [])
+        .await?;
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+
+    // With no watchpoints left, no more events are raised.
+    store
+        .edit_breakpoints()
+        .unwrap()
+        .remove_watchpoint(&memory, 17..18, WatchpointKind::Read)?;
+    func.call_async(&mut store, &[Val::I32(0x11223344)], &mut [])
+        .await?;
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+
+    assert!(
+        store
+            .edit_breakpoints()
+            .unwrap()
+            .add_watchpoint(&memory, 4..4, WatchpointKind::Read)
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+#[cfg_attr(miri, ignore)]
+async fn modify_frame_state() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+
+    let (module, mut store) = get_module_and_store(
+        |config| {
+            config.async_support(true);
+        },
+        r#"
+    (module
+      (func (export "main") (param i32) (result i32)
+        (local i32)
+        local.get 0
+        i32.const 10
+        i32.add
+        local.set 1
+        local.get 1))
+    "#,
+    )?;
+
+    debug_event_checker!(
+        D, store,
+        { 0 ;
+          wasmtime::DebugEvent::Breakpoint => {
+              let mut stack = store.debug_frames().unwrap();
+              assert!(stack.is_modifiable());
+              assert!(stack.set_local(0, Val::I64(5)).is_err());
+              stack.set_local(0, Val::I32(5)).unwrap();
+              assert_eq!(stack.local(0).unwrap_i32(), 5);
+          }
+        },
+        { 1 ; wasmtime::DebugEvent::Breakpoint => {} },
+        { 2 ;
+          wasmtime::DebugEvent::Breakpoint => {
+              // Before `i32.add`.
+              let mut stack = store.debug_frames().unwrap();
+              assert_eq!(stack.num_stacks(), 2);
+              assert_eq!(stack.stack(0).unwrap_i32(), 5);
+              assert_eq!(stack.stack(1).unwrap_i32(), 10);
+              stack.set_stack(1, Val::I32(100)).unwrap();
+          }
+        },
+        { 3 ; wasmtime::DebugEvent::Breakpoint => {} },
+        { 4 ; wasmtime::DebugEvent::Breakpoint => {} },
+        { 5 ; wasmtime::DebugEvent::Breakpoint => {} }
+    );
+
+    let (handler, counter) = D::new_and_counter();
+    store.set_debug_handler(handler);
+    let instance = Instance::new_async(&mut store, &module, &[]).await?;
+    let func = instance.get_func(&mut store, "main").unwrap();
+    store.edit_breakpoints().unwrap().single_step(true)?;
+
+    let mut results = [Val::I32(0)];
+    func.call_async(&mut store, &[Val::I32(1)], &mut results)
+        .await?;
+    assert_eq!(counter.load(Ordering::Relaxed), 6);
+    assert_eq!(results[0].unwrap_i32(), 105);
+
+    debug_event_checker!(
+        D2, store,
+        { 0 ;
+          wasmtime::DebugEvent::Breakpoint => {
+              let mut stack = store.debug_frames().unwrap();
+              assert!(stack.force_return(&[]).is_err());
+              assert!(stack.force_return(&[Val::F32(0)]).is_err());
+              stack.force_return(&[Val::I32(42)]).unwrap();
+          }
+        }
+    );
+
+    let (handler, counter) = D2::new_and_counter();
+    store.set_debug_handler(handler);
+    func.call_async(&mut store, &[Val::I32(1)], &mut results)
+        .await?;
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+    assert_eq!(results[0].unwrap_i32(), 42);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn frames_outside_breakpoints_are_not_modifiable() -> anyhow::Result<()> {
+    let _ = env_logger::try_init();
+
+    test_stack_values(
+        r#"
+    (module
+      (import "" "host" (func))
+      (func (export "main")
+        (local i32)
+        call 0))
+    "#,
+        |_config| {},
+        |mut caller: Caller<'_, ()>| {
+            let mut stack = caller.debug_frames().unwrap();
+            assert!(!stack.done());
+            assert!(!stack.is_modifiable());
+            assert!(stack.set_local(0, Val::I32(1)).is_err());
+            assert!(stack.force_return(&[]).is_err());
+        },
+    )
+}
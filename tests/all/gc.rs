@@ -1496,6 +1496,84 @@ fn drc_gc_inbetween_host_calls() -> Result<()> {
     Ok(())
 }
 
+#[test]
+#[cfg_attr(miri, ignore)]
+fn drc_collects_cycles_under_pressure() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::DeferredReferenceCounting);
+    config.memory_may_move(false);
+    config.memory_reservation(64 << 10);
+    config.memory_reservation_for_growth(0);
+
+    let engine = Engine::new(&config)?;
+
+    // Allocate many more two-node cycles than fit in the GC heap at once. Ref
+    // counting alone can't reclaim any of them, so this only succeeds if the
+    // backup cycle collector runs.
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (type $node (struct (field externref) (field (mut (ref null $node)))))
+                (global (export "g") (ref null $node) (ref.null $node))
+
+                (func (export "run") (param $n i32)
+                    (local $a (ref null $node))
+                    (local $b (ref null $node))
+                    (block $done
+                        (loop $loop
+                            (br_if $done (i32.eqz (local.get $n)))
+                            (local.set $a (struct.new $node (ref.null extern) (ref.null $node)))
+                            (local.set $b (struct.new $node (ref.null extern) (local.get $a)))
+                            (struct.set $node 1 (local.get $a) (local.get $b))
+                            (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                            (br $loop)))
+                )
+            )
+        "#,
+    )?;
+
+    let export = module.exports().nth(0).unwrap().ty();
+    let global = export.unwrap_global();
+    let ref_ty = global.content().unwrap_ref();
+    let struct_ty = ref_ty.heap_type().unwrap_concrete_struct();
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let run = instance.get_typed_func::<i32, ()>(&mut store, "run")?;
+
+    // Create a host cycle whose `externref`s' host data we can observe being
+    // dropped.
+    let pre = StructRefPre::new(&mut store, struct_ty.clone());
+    let num_refs_dropped = Arc::new(AtomicUsize::new(0));
+    {
+        let mut store = RootScope::new(&mut store);
+        let a_data = ExternRef::new(&mut store, CountDrops(num_refs_dropped.clone()))?;
+        let a = StructRef::new(&mut store, &pre, &[a_data.into(), Val::null_any_ref()])?;
+        let b_data = ExternRef::new(&mut store, CountDrops(num_refs_dropped.clone()))?;
+        let b = StructRef::new(&mut store, &pre, &[b_data.into(), a.into()])?;
+        a.set_field(&mut store, 1, b.into())?;
+    }
+
+    // The heap is not under pressure, so an explicit GC does not reclaim the
+    // cycle, even though it is unreachable.
+    store.gc(None);
+    assert_eq!(num_refs_dropped.load(SeqCst), 0);
+
+    run.call(&mut store, 10_000)?;
+    assert_eq!(num_refs_dropped.load(SeqCst), 2);
+
+    let stats = store.gc_stats();
+    assert!(stats.collections > 1);
+    assert!(stats.bytes_reclaimed > 64 << 10);
+
+    Ok(())
+}
+
 #[test]
 fn owned_rooted() -> Result<()> {
     let _ = env_logger::try_init();
@@ -1802,3 +1880,127 @@ fn array_init_elem_oom() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn copying_collects_cycles() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::Copying);
+
+    let engine = Engine::new(&config)?;
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (type $node (struct (field externref) (field (mut (ref null $node)))))
+                (global (export "g") (ref null $node) (ref.null $node))
+            )
+        "#,
+    )?;
+
+    let export = module.exports().nth(0).unwrap().ty();
+    let global = export.unwrap_global();
+    let ref_ty = global.content().unwrap_ref();
+    let struct_ty = ref_ty.heap_type().unwrap_concrete_struct();
+
+    let mut store = Store::new(&engine, ());
+
+    let pre = StructRefPre::new(&mut store, struct_ty.clone());
+    let num_refs_dropped = Arc::new(AtomicUsize::new(0));
+
+    {
+        let mut store = RootScope::new(&mut store);
+
+        let a_data = ExternRef::new(&mut store, CountDrops(num_refs_dropped.clone()))?;
+        let a = StructRef::new(&mut store, &pre, &[a_data.into(), Val::null_any_ref()])?;
+        let b_data = ExternRef::new(&mut store, CountDrops(num_refs_dropped.clone()))?;
+        let b = StructRef::new(&mut store, &pre, &[b_data.into(), a.into()])?;
+        a.set_field(&mut store, 1, b.into())?;
+
+        // Objects move during collection, but the cycle is still reachable and
+        // must survive intact.
+        store.as_context_mut().gc(None);
+        assert_eq!(num_refs_dropped.load(SeqCst), 0);
+        let a_next = a
+            .field(&mut store, 1)?
+            .unwrap_anyref()
+            .unwrap()
+            .unwrap_struct(&store)?;
+        let b_next = b
+            .field(&mut store, 1)?
+            .unwrap_anyref()
+            .unwrap()
+            .unwrap_struct(&store)?;
+        assert!(Rooted::ref_eq(&store, &a_next, &b)?);
+        assert!(Rooted::ref_eq(&store, &b_next, &a)?);
+    }
+
+    // Not holding the cycle alive anymore; it should be reclaimed.
+    store.gc(None);
+    assert_eq!(num_refs_dropped.load(SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn copying_preserves_live_objects_across_collections() -> Result<()> {
+    let _ = env_logger::try_init();
+
+    let mut config = Config::new();
+    config.wasm_function_references(true);
+    config.wasm_gc(true);
+    config.collector(Collector::Copying);
+    config.memory_may_move(false);
+    config.memory_reservation(64 << 10);
+    config.memory_reservation_for_growth(0);
+
+    let engine = Engine::new(&config)?;
+
+    // Build a linked list while allocating plenty of garbage in between, so
+    // that the list is evacuated many times before we walk it.
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (type $cons (struct (field i32) (field (ref null $cons))))
+                (type $bytes (array i8))
+
+                (func (export "run") (param $n i32) (result i32)
+                    (local $list (ref null $cons))
+                    (local $sum i32)
+
+                    (block $done
+                        (loop $build
+                            (br_if $done (i32.eqz (local.get $n)))
+                            (local.set $list (struct.new $cons (local.get $n) (local.get $list)))
+                            (drop (array.new_default $bytes (i32.const 1000)))
+                            (local.set $n (i32.sub (local.get $n) (i32.const 1)))
+                            (br $build)))
+
+                    (block $done
+                        (loop $walk
+                            (br_if $done (ref.is_null (local.get $list)))
+                            (local.set $sum
+                                (i32.add (local.get $sum)
+                                         (struct.get $cons 0 (local.get $list))))
+                            (local.set $list (struct.get $cons 1 (local.get $list)))
+                            (br $walk)))
+
+                    (local.get $sum)
+                )
+            )
+        "#,
+    )?;
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let run = instance.get_typed_func::<i32, i32>(&mut store, "run")?;
+    assert_eq!(run.call(&mut store, 200)?, 200 * 201 / 2);
+
+    Ok(())
+}
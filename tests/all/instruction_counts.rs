@@ -0,0 +1,106 @@
+use wasmtime::*;
+use wasmtime_test_macros::wasmtime_test;
+
+const SUM: &str = r#"
+    (module $m
+        (func $sum (export "sum") (param i32) (result i32)
+            (local i32)
+            (block $done
+                (loop $loop
+                    (br_if $done (i32.eqz (local.get 0)))
+                    (local.set 1 (i32.add (local.get 1) (local.get 0)))
+                    (local.set 0 (i32.sub (local.get 0) (i32.const 1)))
+                    (br $loop)))
+            (local.get 1))
+        (func $unused (export "unused")
+            nop)
+    )
+"#;
+
+#[wasmtime_test(strategies(not(Winch)))]
+#[cfg_attr(miri, ignore)]
+fn counts_blocks(config: &mut Config) -> Result<()> {
+    config.count_instructions(true);
+    let engine = Engine::new(config)?;
+    let module = Module::new(&engine, SUM)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let sum = instance.get_typed_func::<i32, i32>(&mut store, "sum")?;
+    assert_eq!(sum.call(&mut store, 3)?, 6);
+
+    let counts = store.instruction_counts()?;
+    assert_eq!(counts.modules().len(), 1);
+    let funcs = counts.modules()[0].functions();
+    assert_eq!(funcs.len(), 2);
+    assert_eq!(funcs[0].name(), "sum");
+    assert_eq!(funcs[1].total(), 0);
+
+    // `block` and `loop`, then the loop header four times, the loop body
+    // three times, and finally `local.get` and `end`.
+    let blocks = funcs[0]
+        .blocks()
+        .iter()
+        .map(|b| b.count())
+        .collect::<Vec<_>>();
+    assert_eq!(blocks, [2, 4 * 3, 3 * 8, 2]);
+    assert!(
+        funcs[0]
+            .blocks()
+            .windows(2)
+            .all(|w| w[0].module_offset() < w[1].module_offset())
+    );
+    assert_eq!(counts.total(), 40);
+
+    let report = counts.to_string();
+    assert!(
+        report.starts_with("40 total\n40 m!sum\n    24 @"),
+        "{report}"
+    );
+    assert!(!report.contains("unused"), "{report}");
+
+    // Counts accumulate across calls until reset.
+    sum.call(&mut store, 3)?;
+    assert_eq!(store.instruction_counts()?.total(), 80);
+    store.reset_instruction_counts();
+    assert_eq!(store.instruction_counts()?.total(), 0);
+    sum.call(&mut store, 0)?;
+    assert_eq!(store.instruction_counts()?.total(), 2 + 3 + 2);
+    Ok(())
+}
+
+#[wasmtime_test(strategies(not(Winch)))]
+#[cfg_attr(miri, ignore)]
+fn counts_are_per_store(config: &mut Config) -> Result<()> {
+    config.count_instructions(true);
+    let engine = Engine::new(config)?;
+    let module = Module::new(&engine, SUM)?;
+
+    let mut store1 = Store::new(&engine, ());
+    let instance1 = Instance::new(&mut store1, &module, &[])?;
+    let mut store2 = Store::new(&engine, ());
+    let instance2 = Instance::new(&mut store2, &module, &[])?;
+    // A second instance of the same module shares its counters.
+    Instance::new(&mut store2, &module, &[])?;
+
+    let sum = instance1.get_typed_func::<i32, i32>(&mut store1, "sum")?;
+    sum.call(&mut store1, 3)?;
+    let sum = instance2.get_typed_func::<i32, i32>(&mut store2, "sum")?;
+    sum.call(&mut store2, 0)?;
+
+    assert_eq!(store1.instruction_counts()?.total(), 40);
+    let counts = store2.instruction_counts()?;
+    assert_eq!(counts.modules().len(), 1);
+    assert_eq!(counts.total(), 7);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn counting_disabled() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, SUM)?;
+    let mut store = Store::new(&engine, ());
+    Instance::new(&mut store, &module, &[])?;
+    assert!(store.instruction_counts().is_err());
+    Ok(())
+}
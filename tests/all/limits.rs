@@ -1175,3 +1175,141 @@ fn growth_trap() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_memory_budget() -> Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"(module
+            (memory (export "m") 1)
+            (func (export "grow") (param i32) (result i32)
+              (memory.grow (local.get 0)))
+           )"#,
+    )?;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let budget = MemoryBudgetBuilder::new(6 * WASM_PAGE_SIZE)
+        .watermark(3 * WASM_PAGE_SIZE)
+        .on_watermark({
+            let events = events.clone();
+            move |pressure| events.lock().unwrap().push(*pressure)
+        })
+        .build();
+    let new_store = || {
+        let mut store = Store::new(
+            &engine,
+            StoreLimitsBuilder::new()
+                .memory_budget(&budget)
+                .memory_soft_limit(2 * WASM_PAGE_SIZE)
+                .build(),
+        );
+        store.limiter(|s| s as &mut dyn ResourceLimiter);
+        store
+    };
+
+    let mut a = new_store();
+    let a_instance = Instance::new(&mut a, &module, &[])?;
+    let a_grow = a_instance.get_typed_func::<i32, i32>(&mut a, "grow")?;
+    let mut b = new_store();
+    let b_instance = Instance::new(&mut b, &module, &[])?;
+    let b_grow = b_instance.get_typed_func::<i32, i32>(&mut b, "grow")?;
+    assert_eq!(budget.used(), 2 * WASM_PAGE_SIZE);
+    assert!(!a.data().memory_pressure());
+    assert!(events.lock().unwrap().is_empty());
+
+    // Crossing the watermark notifies the callback and puts every store
+    // sharing the budget under pressure.
+    assert_eq!(a_grow.call(&mut a, 2)?, 1);
+    assert_eq!(a.data().memory_used(), 3 * WASM_PAGE_SIZE);
+    assert_eq!(budget.watermark_exceeded(), Some(3 * WASM_PAGE_SIZE));
+    assert!(a.data().memory_pressure());
+    assert!(b.data().memory_pressure());
+    assert_eq!(events.lock().unwrap().len(), 1);
+    assert!(events.lock().unwrap()[0].rising);
+
+    // Growth is limited by the budget shared between the stores.
+    assert_eq!(b_grow.call(&mut b, 2)?, 1);
+    assert_eq!(a_grow.call(&mut a, 1)?, -1);
+    assert_eq!(budget.used(), 6 * WASM_PAGE_SIZE);
+
+    // Dropping a store gives its memory back to the budget.
+    drop(a);
+    assert_eq!(budget.used(), 3 * WASM_PAGE_SIZE);
+    assert_eq!(budget.watermark_exceeded(), None);
+    let events = events.lock().unwrap().clone();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[1].watermark, 3 * WASM_PAGE_SIZE);
+    assert!(!events[1].rising);
+
+    // The remaining store is still over its own soft limit.
+    assert!(b.data().memory_pressure());
+    assert_eq!(b_grow.call(&mut b, 3)?, 3);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_memory_budget_resource_limiter() -> Result<()> {
+    struct Limiter(MemoryBudget);
+
+    impl ResourceLimiter for Limiter {
+        fn memory_growing(&mut self, _: usize, _: usize, _: Option<usize>) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn table_growing(&mut self, _: usize, _: usize, _: Option<usize>) -> Result<bool> {
+            Ok(true)
+        }
+
+        fn memory_budget(&self) -> Option<&MemoryBudget> {
+            Some(&self.0)
+        }
+    }
+
+    let engine = Engine::default();
+    let budget = MemoryBudget::new(3 * WASM_PAGE_SIZE);
+    let mut store = Store::new(&engine, Limiter(budget.clone()));
+    store.limiter(|s| s as &mut dyn ResourceLimiter);
+
+    let one = Module::new(&engine, r#"(module (memory (export "m") 1))"#)?;
+    let instance = Instance::new(&mut store, &one, &[])?;
+    let memory = instance.get_memory(&mut store, "m").unwrap();
+    assert_eq!(budget.used(), WASM_PAGE_SIZE);
+    memory.grow(&mut store, 1)?;
+    assert_eq!(budget.used(), 2 * WASM_PAGE_SIZE);
+    assert!(memory.grow(&mut store, 2).is_err());
+    assert_eq!(budget.used(), 2 * WASM_PAGE_SIZE);
+
+    // The first memory of this module fits in the budget but the second
+    // doesn't, so instantiation fails and the first memory is dropped again
+    // while the store is still alive.
+    let two = Module::new(&engine, r#"(module (memory 1) (memory 1))"#)?;
+    assert!(Instance::new(&mut store, &two, &[]).is_err());
+    assert_eq!(budget.used(), 2 * WASM_PAGE_SIZE);
+
+    drop(store);
+    assert_eq!(budget.used(), 0);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn test_memory_used_after_failed_instantiation() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(
+        &engine,
+        StoreLimitsBuilder::new()
+            .memory_budget(&MemoryBudget::new(WASM_PAGE_SIZE))
+            .build(),
+    );
+    store.limiter(|s| s as &mut dyn ResourceLimiter);
+
+    let module = Module::new(&engine, r#"(module (memory 1) (memory 1))"#)?;
+    assert!(Instance::new(&mut store, &module, &[]).is_err());
+    assert_eq!(store.data().memory_used(), 0);
+    Ok(())
+}
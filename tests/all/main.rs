@@ -6,6 +6,7 @@ mod arrays;
 mod async_functions;
 mod call_hook;
 mod cli_tests;
+mod code_store;
 mod code_too_large;
 mod compile_time_builtins;
 mod component_model;
@@ -30,6 +31,7 @@ mod iloop;
 mod import_calling_export;
 mod import_indexes;
 mod instance;
+mod instruction_counts;
 mod intrinsics;
 mod invoke_func_via_table;
 mod limits;
@@ -45,7 +47,10 @@ mod piped_tests;
 mod pooling_allocator;
 mod profiling;
 mod pulley;
+#[cfg(feature = "rr")]
+mod record_replay;
 mod relocs;
+mod snapshot;
 mod stack_creator;
 mod stack_overflow;
 mod store;
@@ -54,6 +59,7 @@ mod table;
 #[cfg(all(feature = "stack-switching", unix, target_arch = "x86_64"))]
 mod tags;
 mod threads;
+mod tiered;
 mod traps;
 mod types;
 mod wait_notify;
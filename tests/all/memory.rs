@@ -777,3 +777,79 @@ fn configure_zero(config: &mut Config) -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg(target_os = "linux")]
+fn memory_init_userfaultfd() -> Result<()> {
+    let mut config = Config::new();
+    config.memory_init_userfaultfd(true);
+    config.memory_init_cow(false);
+    assert!(Engine::new(&config).is_err());
+    config.memory_init_cow(true);
+
+    // The `userfaultfd` system call may not be permitted in this environment,
+    // in which case there's nothing to test.
+    let Ok(engine) = Engine::new(&config) else {
+        return Ok(());
+    };
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "memory") 4)
+                (data (i32.const 0) "hello")
+                (data (i32.const 0x20000) "world")
+                (func (export "load") (param i32) (result i32)
+                    (i32.load8_u (local.get 0)))
+            )
+        "#,
+    )?;
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let stats = engine.userfaultfd_stats().unwrap();
+    assert_eq!(stats.memories_registered, 1);
+    assert_eq!(stats.pages_faulted, 0);
+
+    // Pages are populated on first access from both wasm and the host.
+    let load = instance.get_typed_func::<u32, u32>(&mut store, "load")?;
+    assert_eq!(load.call(&mut store, 1)?, u32::from(b'e'));
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&memory.data(&store)[0x20000..][..5], b"world");
+    assert_eq!(memory.data(&store)[0x10000], 0);
+    let stats = engine.userfaultfd_stats().unwrap();
+    assert!(stats.pages_faulted >= 3);
+    assert!(stats.zero_pages_faulted >= 1);
+
+    // Writes land in populated pages, and contents survive growth.
+    memory.data_mut(&mut store)[0x20005] = b'!';
+    memory.grow(&mut store, 1)?;
+    assert_eq!(&memory.data(&store)[..5], b"hello");
+    assert_eq!(&memory.data(&store)[0x20000..][..6], b"world!");
+
+    // A second instance starts out with the original contents again.
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    assert_eq!(&memory.data(&store)[0x20000..][..6], b"world\0");
+    assert_eq!(engine.userfaultfd_stats().unwrap().memories_registered, 2);
+
+    // Shared memories aren't registered, but are still initialized.
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory 1 1 shared)
+                (data (i32.const 0x1000) "shared")
+                (func (export "load") (param i32) (result i32)
+                    (i32.load8_u (local.get 0)))
+            )
+        "#,
+    )?;
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let load = instance.get_typed_func::<u32, u32>(&mut store, "load")?;
+    assert_eq!(load.call(&mut store, 0x1000)?, u32::from(b's'));
+    assert_eq!(engine.userfaultfd_stats().unwrap().memories_registered, 2);
+
+    Ok(())
+}
@@ -154,3 +154,129 @@ fn detect_precompiled() -> Result<()> {
     );
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn deserialize_bundle() -> Result<()> {
+    let wat = "(module (func (export \"run\") (result i32) i32.const 42))";
+    let default_engine = Engine::default();
+    let small_engine = Engine::new(Config::new().memory_reservation(0))?;
+    let bundle = Engine::bundle_precompiled(&[
+        small_engine.precompile_module(wat.as_bytes())?,
+        default_engine.precompile_module(wat.as_bytes())?,
+    ])?;
+    assert_eq!(
+        Engine::detect_precompiled(&bundle),
+        Some(Precompiled::Module)
+    );
+
+    // Each engine picks the artifact compiled for it out of the bundle.
+    for engine in [&default_engine, &small_engine] {
+        let mut store = Store::new(engine, ());
+        let instance = unsafe { deserialize_and_instantiate(&mut store, &bundle)? };
+        let run = instance.get_typed_func::<(), i32>(&mut store, "run")?;
+        assert_eq!(run.call(&mut store, ())?, 42);
+    }
+
+    let td = tempfile::TempDir::new()?;
+    let path = td.path().join("bundle.cwasm");
+    fs::write(&path, &bundle)?;
+    assert_eq!(
+        Engine::detect_precompiled_file(&path)?,
+        Some(Precompiled::Module)
+    );
+    unsafe {
+        Module::deserialize_file(&default_engine, &path)?;
+    }
+
+    // Engines matching none of the artifacts are rejected.
+    let other_engine = Engine::new(Config::new().wasm_relaxed_simd(false))?;
+    let err = unsafe { Module::deserialize(&other_engine, &bundle) }.unwrap_err();
+    assert!(
+        format!("{err:?}").contains("no artifact in precompiled bundle is compatible"),
+        "{err:?}"
+    );
+
+    // Bundles can't mix kinds of artifacts, or contain other bundles.
+    assert!(Engine::bundle_precompiled::<&[u8]>(&[]).is_err());
+    assert!(Engine::bundle_precompiled(&[b"\0asm\x01\0\0\0"]).is_err());
+    assert!(Engine::bundle_precompiled(&[&bundle]).is_err());
+    assert!(
+        Engine::bundle_precompiled(&[
+            default_engine.precompile_module(wat.as_bytes())?,
+            default_engine.precompile_component(b"(component)")?,
+        ])
+        .is_err()
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn deserialize_verified() -> Result<()> {
+    let wat = "(module (func (export \"run\") (result i32) i32.const 42))";
+    let key = ArtifactSigningKey::from_bytes(&[1; 32]);
+    let other_key = ArtifactSigningKey::from_bytes(&[2; 32]);
+
+    let mut config = Config::new();
+    config.sign_artifacts(Some(key.clone()));
+    let signer = Engine::new(&config)?;
+    let signed = signer.precompile_module(wat.as_bytes())?;
+    let reserialized = Module::new(&signer, wat)?.serialize()?;
+
+    let mut config = Config::new();
+    config.trust_artifact_key(other_key.verifying_key());
+    config.trust_artifact_key(key.verifying_key());
+    let verifier = Engine::new(&config)?;
+    for artifact in [&signed, &reserialized] {
+        let module = Module::deserialize_verified(&verifier, artifact)?;
+        let mut store = Store::new(&verifier, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let run = instance.get_typed_func::<(), i32>(&mut store, "run")?;
+        assert_eq!(run.call(&mut store, ())?, 42);
+    }
+    let bundle = Engine::bundle_precompiled(&[&signed])?;
+    Module::deserialize_verified(&verifier, &bundle)?;
+
+    // Signed artifacts can still be loaded without verifying them.
+    unsafe {
+        Module::deserialize(&verifier, &signed)?;
+    }
+
+    let assert_rejected = |engine: &Engine, artifact: &[u8], msg: &str| {
+        let err = Module::deserialize_verified(engine, artifact).unwrap_err();
+        assert!(format!("{err:?}").contains(msg), "{err:?}");
+    };
+
+    // Unsigned artifacts are rejected.
+    let unsigned = Engine::new(&Config::new())?.precompile_module(wat.as_bytes())?;
+    assert_rejected(&verifier, &unsigned, "precompiled artifact is not signed");
+
+    // Artifacts signed with keys that aren't trusted are rejected.
+    assert_rejected(
+        &Engine::default(),
+        &signed,
+        "no keys are trusted to sign precompiled artifacts",
+    );
+    let mut config = Config::new();
+    config.trust_artifact_key(other_key.verifying_key());
+    assert_rejected(
+        &Engine::new(&config)?,
+        &signed,
+        "precompiled artifact was signed by an untrusted key",
+    );
+
+    // Artifacts modified after signing are rejected.
+    let mut tampered = signed.clone();
+    let mid = tampered.len() / 2;
+    tampered[mid] ^= 1;
+    assert!(Module::deserialize_verified(&verifier, &tampered).is_err());
+
+    // Components are signed as well.
+    let mut config = Config::new();
+    config.sign_artifacts(Some(key.clone()));
+    let signed = Engine::new(&config)?.precompile_component(b"(component)")?;
+    component::Component::deserialize_verified(&verifier, &signed)?;
+    assert!(Module::deserialize_verified(&verifier, &signed).is_err());
+    Ok(())
+}
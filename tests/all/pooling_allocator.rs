@@ -1411,3 +1411,69 @@ fn memory_reset_if_instantiation_fails() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn park_and_unpark_store() -> Result<()> {
+    let mut pool = crate::small_pool_config();
+    pool.max_memory_size(2 << 16);
+    let mut config = Config::new();
+    config.allocation_strategy(pool);
+    let engine = Engine::new(&config)?;
+
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (memory (export "m") 1)
+                (table 1 funcref)
+                (func $load (result i32)
+                    (i32.load (i32.const 100)))
+                (func (export "call") (result i32)
+                    (call_indirect (result i32) (i32.const 0)))
+                (elem (i32.const 0) $load)
+                (data (i32.const 0) "\01\02\03")
+            )
+        "#,
+    )?;
+
+    let mut store1 = Store::new(&engine, ());
+    let instance1 = Instance::new(&mut store1, &module, &[])?;
+    let memory = instance1.get_memory(&mut store1, "m").unwrap();
+    let call = instance1.get_typed_func::<(), i32>(&mut store1, "call")?;
+    memory.data_mut(&mut store1)[100] = 42;
+    assert_eq!(call.call(&mut store1, ())?, 42);
+
+    // The first store holds the pool's only memory and table slots.
+    let mut store2 = Store::new(&engine, ());
+    assert!(Instance::new(&mut store2, &module, &[]).is_err());
+
+    store1.park()?;
+    assert!(store1.is_parked());
+
+    // The host can keep using the parked memory.
+    assert_eq!(memory.data(&store1)[..3], [1, 2, 3]);
+    memory.data_mut(&mut store1)[101] = 1;
+    memory.grow(&mut store1, 1)?;
+
+    // Parking released the slots, so while the second store holds them the
+    // first can't be unparked.
+    let instance2 = Instance::new(&mut store2, &module, &[])?;
+    assert!(call.call(&mut store1, ()).is_err());
+    assert!(store1.is_parked());
+    drop((instance2, store2));
+
+    // Entering wasm transparently unparks the store.
+    assert_eq!(call.call(&mut store1, ())?, 42 + (1 << 8));
+    assert!(!store1.is_parked());
+    assert_eq!(memory.size(&store1), 2);
+    assert_eq!(memory.data(&store1)[..3], [1, 2, 3]);
+
+    // Dropping a parked store doesn't return the parked copies to the pool.
+    store1.park()?;
+    drop(store1);
+    let mut store3 = Store::new(&engine, ());
+    Instance::new(&mut store3, &module, &[])?;
+
+    Ok(())
+}
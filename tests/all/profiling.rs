@@ -1,5 +1,9 @@
+use std::collections::HashMap;
+use std::time::Duration;
 use wasmtime::component::Component;
-use wasmtime::{Config, Engine, Module, Result};
+use wasmtime::{
+    Caller, Config, Engine, Func, GuestProfiler, Instance, Module, ProfileAggregator, Result, Store,
+};
 
 #[test]
 #[cfg_attr(miri, ignore)]
@@ -22,3 +26,243 @@ fn perfmap() -> Result<()> {
 
     Ok(())
 }
+
+const SAMPLED: &str = r#"
+    (module
+        (import "" "sample" (func $sample))
+        (func $inner (call $sample))
+        (func $outer (export "run")
+            (call $inner)
+            (call $inner))
+    )
+"#;
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn guest_profiler_exporters() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, SAMPLED)?;
+    let modules = || [("m".to_string(), module.clone())];
+
+    let mut collapsed = Vec::new();
+    let mut pprof = Vec::new();
+    for (i, output) in [&mut collapsed, &mut pprof].into_iter().enumerate() {
+        let profiler = GuestProfiler::new(&engine, "m", Duration::from_millis(10), modules())?;
+        let mut store = Store::new(&engine, Some(profiler));
+        let sample = Func::wrap(
+            &mut store,
+            |mut caller: Caller<'_, Option<GuestProfiler>>| {
+                let mut profiler = caller.data_mut().take().unwrap();
+                profiler.sample(&caller, Duration::from_micros(1));
+                *caller.data_mut() = Some(profiler);
+            },
+        );
+        let instance = Instance::new(&mut store, &module, &[sample.into()])?;
+        let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+        run.call(&mut store, ())?;
+
+        let profiler = store.data_mut().take().unwrap();
+        if i == 0 {
+            profiler.finish_collapsed(output)?;
+        } else {
+            profiler.finish_pprof(output)?;
+        }
+    }
+
+    assert_eq!(String::from_utf8(collapsed)?, "m!outer;m!inner 2\n");
+
+    // Field numbers below are from pprof's `profile.proto`.
+    let profile = proto::decode(&pprof)?;
+    let strings = profile
+        .iter()
+        .filter(|(field, _)| *field == 6)
+        .map(|(_, value)| Ok(std::str::from_utf8(value.bytes()?)?))
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(strings[0], "");
+    let string = |index: u64| strings[usize::try_from(index).unwrap()];
+    let value_type = |bytes: &[u8]| -> Result<(&str, &str)> {
+        let value_type = proto::decode(bytes)?;
+        Ok((
+            string(proto::varint(&value_type, 1)?),
+            string(proto::varint(&value_type, 2)?),
+        ))
+    };
+
+    let sample_types = proto::messages(&profile, 1)?
+        .into_iter()
+        .map(&value_type)
+        .collect::<Result<Vec<_>>>()?;
+    assert_eq!(sample_types, [("samples", "count"), ("cpu", "nanoseconds")]);
+    assert_eq!(
+        value_type(proto::message(&profile, 11)?)?,
+        ("wall", "nanoseconds")
+    );
+    assert_eq!(proto::varint(&profile, 12)?, 10_000_000);
+
+    // One stack was sampled twice, newest frame first.
+    let samples = proto::messages(&profile, 2)?;
+    assert_eq!(samples.len(), 1);
+    let sample = proto::decode(samples[0])?;
+    let location_ids = proto::packed(proto::message(&sample, 1)?)?;
+    assert_eq!(proto::packed(proto::message(&sample, 2)?)?, [2, 2000]);
+
+    let mappings = proto::messages(&profile, 3)?;
+    assert_eq!(mappings.len(), 1);
+    let mapping = proto::decode(mappings[0])?;
+    assert_eq!(string(proto::varint(&mapping, 5)?), "m");
+
+    let mut names = HashMap::new();
+    for function in proto::messages(&profile, 5)? {
+        let function = proto::decode(function)?;
+        let name = string(proto::varint(&function, 2)?);
+        assert_eq!(string(proto::varint(&function, 4)?), "m");
+        assert!(names.insert(proto::varint(&function, 1)?, name).is_none());
+    }
+    let mut frames = HashMap::new();
+    for location in proto::messages(&profile, 4)? {
+        let location = proto::decode(location)?;
+        assert_eq!(proto::varint(&location, 2)?, proto::varint(&mapping, 1)?);
+        let line = proto::decode(proto::message(&location, 4)?)?;
+        let name = names[&proto::varint(&line, 1)?];
+        assert!(frames.insert(proto::varint(&location, 1)?, name).is_none());
+    }
+    let stack = location_ids.iter().map(|id| frames[id]).collect::<Vec<_>>();
+    assert_eq!(stack, ["inner", "outer"]);
+    Ok(())
+}
+
+/// Just enough of a protobuf decoder to check the structure of pprof output.
+mod proto {
+    use anyhow::{Result, bail};
+
+    pub enum Value<'a> {
+        Varint(u64),
+        Bytes(&'a [u8]),
+    }
+
+    impl<'a> Value<'a> {
+        pub fn bytes(&self) -> Result<&'a [u8]> {
+            match self {
+                Value::Bytes(bytes) => Ok(bytes),
+                Value::Varint(_) => bail!("expected a length-delimited field"),
+            }
+        }
+    }
+
+    fn read_varint(bytes: &mut &[u8]) -> Result<u64> {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let Some((&byte, rest)) = bytes.split_first() else {
+                bail!("truncated varint");
+            };
+            *bytes = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("overlong varint")
+    }
+
+    /// Decodes a message into its fields, in order.
+    pub fn decode(mut bytes: &[u8]) -> Result<Vec<(u64, Value<'_>)>> {
+        let mut fields = Vec::new();
+        while !bytes.is_empty() {
+            let key = read_varint(&mut bytes)?;
+            let value = match key & 7 {
+                0 => Value::Varint(read_varint(&mut bytes)?),
+                2 => {
+                    let len = usize::try_from(read_varint(&mut bytes)?)?;
+                    if len > bytes.len() {
+                        bail!("truncated field");
+                    }
+                    let (value, rest) = bytes.split_at(len);
+                    bytes = rest;
+                    Value::Bytes(value)
+                }
+                wire_type => bail!("unexpected wire type {wire_type}"),
+            };
+            fields.push((key >> 3, value));
+        }
+        Ok(fields)
+    }
+
+    /// Returns the value of an integer field, which is 0 when it's absent.
+    pub fn varint(fields: &[(u64, Value<'_>)], field: u64) -> Result<u64> {
+        let mut result = 0;
+        for (f, value) in fields {
+            if *f == field {
+                match value {
+                    Value::Varint(v) => result = *v,
+                    Value::Bytes(_) => bail!("expected an integer in field {field}"),
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Returns every occurrence of an embedded message field.
+    pub fn messages<'a>(fields: &[(u64, Value<'a>)], field: u64) -> Result<Vec<&'a [u8]>> {
+        fields
+            .iter()
+            .filter(|(f, _)| *f == field)
+            .map(|(_, value)| value.bytes())
+            .collect()
+    }
+
+    /// Returns the single occurrence of an embedded message field.
+    pub fn message<'a>(fields: &[(u64, Value<'a>)], field: u64) -> Result<&'a [u8]> {
+        match messages(fields, field)?[..] {
+            [message] => Ok(message),
+            _ => bail!("expected exactly one field {field}"),
+        }
+    }
+
+    /// Decodes a packed repeated integer field.
+    pub fn packed(mut bytes: &[u8]) -> Result<Vec<u64>> {
+        let mut values = Vec::new();
+        while !bytes.is_empty() {
+            values.push(read_varint(&mut bytes)?);
+        }
+        Ok(values)
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn aggregate_across_threads() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, SAMPLED)?;
+    let aggregator = ProfileAggregator::new(
+        &engine,
+        Duration::from_millis(10),
+        [("m".to_string(), module.clone())],
+    )?;
+
+    std::thread::scope(|s| {
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                s.spawn(|| -> Result<()> {
+                    let mut store = Store::new(&engine, aggregator.clone());
+                    let sample = Func::wrap(&mut store, |caller: Caller<'_, ProfileAggregator>| {
+                        caller.data().sample(&caller, Duration::ZERO);
+                    });
+                    let instance = Instance::new(&mut store, &module, &[sample.into()])?;
+                    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+                    run.call(&mut store, ())
+                })
+            })
+            .collect();
+        threads.into_iter().try_for_each(|t| t.join().unwrap())
+    })?;
+
+    let mut collapsed = Vec::new();
+    aggregator.write_collapsed(&mut collapsed)?;
+    assert_eq!(String::from_utf8(collapsed)?, "m!outer;m!inner 8\n");
+
+    aggregator.reset();
+    let mut collapsed = Vec::new();
+    aggregator.write_collapsed(&mut collapsed)?;
+    assert!(collapsed.is_empty());
+    Ok(())
+}
@@ -518,3 +518,19 @@ fn decode_unaligned() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn pulley_fallback_in_bundle() -> Result<()> {
+    let native = Engine::default();
+    let pulley = Engine::new(&pulley_config())?;
+    let bundle = Engine::bundle_precompiled(&[
+        native.precompile_module(b"(module)")?,
+        pulley.precompile_module(b"(module)")?,
+    ])?;
+    unsafe {
+        Module::deserialize(&native, &bundle)?;
+        Module::deserialize(&pulley, &bundle)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,582 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering::SeqCst};
+use std::sync::{Arc, Mutex};
+use wasmtime::*;
+
+/// An in-memory trace sink which can be read back after recording.
+#[derive(Clone, Default)]
+struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuf {
+    fn contents(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl Write for SharedBuf {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn engine() -> Result<Engine> {
+    let mut config = Config::new();
+    config.record_replay(true);
+    Engine::new(&config)
+}
+
+const HOST_CALLS: &str = r#"
+    (module
+        (import "" "next" (func $next (result i64)))
+        (import "" "now" (func $now (result f64)))
+        (func (export "run") (result i64 f64)
+            (i64.add (call $next) (i64.add (call $next) (call $next)))
+            (f64.add (call $now) (call $now)))
+    )
+"#;
+
+/// Runs `HOST_CALLS`, returning its results and how many times the host
+/// functions were called.
+fn run_host_calls(engine: &Engine, rr: RecordReplay, base: u64) -> Result<((i64, f64), u64)> {
+    let module = Module::new(engine, HOST_CALLS)?;
+    let mut store = Store::new(engine, ());
+    store.set_record_replay(rr.clone())?;
+
+    let counter = Arc::new(AtomicU64::new(base));
+    let calls = Arc::new(AtomicU64::new(0));
+    let next = Func::wrap(&mut store, {
+        let calls = calls.clone();
+        move || {
+            calls.fetch_add(1, SeqCst);
+            counter.fetch_add(1, SeqCst) as i64
+        }
+    });
+    let now_ty = FuncType::new(engine, [], [ValType::F64]);
+    let now = Func::new(&mut store, now_ty, {
+        let calls = calls.clone();
+        move |_, _, results| {
+            calls.fetch_add(1, SeqCst);
+            results[0] = Val::F64((base as f64 * 1.5).to_bits());
+            Ok(())
+        }
+    });
+
+    let instance = Instance::new(&mut store, &module, &[next.into(), now.into()])?;
+    let run = instance.get_typed_func::<(), (i64, f64)>(&mut store, "run")?;
+    let result = run.call(&mut store, ())?;
+    rr.finish()?;
+    Ok((result, calls.load(SeqCst)))
+}
+
+/// Returns the host calls recorded in `trace`.
+fn host_calls(trace: &[u8]) -> Result<Vec<RecordedHostCall>> {
+    Ok(RecordReplay::read_events(trace)?
+        .into_iter()
+        .filter_map(|event| match event {
+            TraceEvent::HostCall(call) => Some(call),
+            _ => None,
+        })
+        .collect())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn replay_host_function_results() -> Result<()> {
+    let engine = engine()?;
+    let trace = SharedBuf::default();
+
+    let (recorded, calls) = run_host_calls(&engine, RecordReplay::record(trace.clone())?, 10)?;
+    assert_eq!(recorded, (33, 30.0));
+    assert_eq!(calls, 5);
+
+    // The host functions would return different values when replaying, but
+    // they aren't called and the guest observes the recorded values.
+    let (replayed, calls) = run_host_calls(
+        &engine,
+        RecordReplay::replay(io::Cursor::new(trace.contents()))?,
+        100,
+    )?;
+    assert_eq!(replayed, recorded);
+    assert_eq!(calls, 0);
+
+    let results = host_calls(&trace.contents())?
+        .into_iter()
+        .map(|call| call.results)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        results,
+        [
+            Ok(vec![RecordedValue::I64(10)]),
+            Ok(vec![RecordedValue::I64(11)]),
+            Ok(vec![RecordedValue::I64(12)]),
+            Ok(vec![RecordedValue::F64(15.0f64.to_bits())]),
+            Ok(vec![RecordedValue::F64(15.0f64.to_bits())]),
+        ]
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn replay_host_function_effects() -> Result<()> {
+    const FILL: &str = r#"
+        (module
+            (import "" "fill" (func $fill (param i32 i32)))
+            (memory (export "memory") 1)
+            (global $g (export "g") (mut i32) (i32.const 0))
+            (func (export "run") (result i64 i32 i32 i32)
+                (call $fill (i32.const 100) (i32.const 16))
+                (i64.load (i32.const 100))
+                (i32.load (i32.const 65536))
+                (memory.size)
+                (global.get $g))
+        )
+    "#;
+
+    /// Runs `FILL` with a host function which writes bytes counting up from
+    /// `base` to memory, grows it, and sets a global, or which panics if
+    /// `base` is `None`.
+    fn run(engine: &Engine, rr: RecordReplay, base: Option<u8>) -> Result<(Vec<u8>, [i64; 4])> {
+        let module = Module::new(engine, FILL)?;
+        let mut store = Store::new(engine, ());
+        store.set_record_replay(rr.clone())?;
+        let fill = Func::wrap(
+            &mut store,
+            move |mut caller: Caller<'_, ()>, ptr: u32, len: u32| -> Result<()> {
+                let base = base.expect("host functions aren't called when replaying");
+                let memory = caller.get_export("memory").unwrap().into_memory().unwrap();
+                let dst = &mut memory.data_mut(&mut caller)[ptr as usize..][..len as usize];
+                for (i, byte) in dst.iter_mut().enumerate() {
+                    *byte = base + i as u8;
+                }
+                let pages = memory.grow(&mut caller, 1)?;
+                memory.data_mut(&mut caller)[(pages as usize) << 16] = base;
+                let g = caller.get_export("g").unwrap().into_global().unwrap();
+                g.set(&mut caller, Val::I32(base.into()))
+            },
+        );
+        let instance = Instance::new(&mut store, &module, &[fill.into()])?;
+        let run = instance.get_typed_func::<(), (i64, i32, i32, i32)>(&mut store, "run")?;
+        let (a, b, c, d) = run.call(&mut store, ())?;
+        rr.finish()?;
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        Ok((
+            memory.data(&store).to_vec(),
+            [a, b.into(), c.into(), d.into()],
+        ))
+    }
+
+    let engine = engine()?;
+    let trace = SharedBuf::default();
+    let (recorded_memory, recorded) = run(&engine, RecordReplay::record(trace.clone())?, Some(7))?;
+    assert_eq!(recorded, [0x0e0d0c0b0a090807, 7, 2, 7]);
+
+    let (replayed_memory, replayed) = run(
+        &engine,
+        RecordReplay::replay(io::Cursor::new(trace.contents()))?,
+        None,
+    )?;
+    assert_eq!(replayed, recorded);
+    assert!(replayed_memory == recorded_memory);
+
+    let calls = host_calls(&trace.contents())?;
+    assert_eq!(calls.len(), 1);
+    let [memory] = &calls[0].memories[..] else {
+        panic!("expected one modified memory: {:?}", calls[0].memories);
+    };
+    assert_eq!(memory.size, 2 << 16);
+    assert_eq!(
+        memory
+            .writes
+            .iter()
+            .map(|(offset, _)| *offset)
+            .collect::<Vec<_>>(),
+        [64, 1 << 16]
+    );
+    let [global] = &calls[0].globals[..] else {
+        panic!("expected one modified global: {:?}", calls[0].globals);
+    };
+    assert_eq!((global.index, global.bits), (0, 7));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn unsupported_host_calls_are_rejected() -> Result<()> {
+    let engine = engine()?;
+
+    // Host functions which return references.
+    let mut store = Store::new(&engine, ());
+    let rr = RecordReplay::record(SharedBuf::default())?;
+    store.set_record_replay(rr.clone())?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "f" (func $f (result funcref)))
+                (func (export "run") (drop (call $f)))
+            )
+        "#,
+    )?;
+    let f = Func::wrap(&mut store, || -> Option<Func> { None });
+    let instance = Instance::new(&mut store, &module, &[f.into()])?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+    let err = run.call(&mut store, ()).unwrap_err();
+    assert!(
+        format!("{err:?}").contains("host functions which return references"),
+        "{err:?}"
+    );
+
+    // Host functions which modify tables, which also stops the trace.
+    let mut store = Store::new(&engine, ());
+    let rr = RecordReplay::record(SharedBuf::default())?;
+    store.set_record_replay(rr.clone())?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "f" (func $f))
+                (table (export "t") 1 funcref)
+                (func (export "run") (call $f))
+            )
+        "#,
+    )?;
+    let f = Func::wrap(&mut store, |mut caller: Caller<'_, ()>| -> Result<()> {
+        let t = caller.get_export("t").unwrap().into_table().unwrap();
+        let f = Func::wrap(&mut caller, || {});
+        t.set(&mut caller, 0, f.into())
+    });
+    let instance = Instance::new(&mut store, &module, &[f.into()])?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+    let err = run.call(&mut store, ()).unwrap_err();
+    assert!(
+        format!("{err:?}").contains("host functions which modify tables"),
+        "{err:?}"
+    );
+    let err = rr.finish().unwrap_err();
+    assert!(format!("{err}").contains("host functions which modify tables"));
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn replay_component_host_function() -> Result<()> {
+    use wasmtime::component::{Component, Linker};
+    use wasmtime_component_util::REALLOC_AND_FREE;
+
+    let engine = engine()?;
+    let component = Component::new(
+        &engine,
+        format!(
+            r#"
+                (component
+                    (import "random" (func $random (param "n" u32) (result (list u8))))
+                    (core module $libc
+                        (memory (export "memory") 1)
+                        {REALLOC_AND_FREE}
+                    )
+                    (core instance $libc (instantiate $libc))
+                    (core func $random (canon lower (func $random)
+                        (memory $libc "memory") (realloc (func $libc "realloc"))))
+                    (core module $m
+                        (import "" "random" (func $random (param i32 i32)))
+                        (func (export "run") (result i32)
+                            (call $random (i32.const 16) (i32.const 0))
+                            (i32.const 0)))
+                    (core instance $m (instantiate $m
+                        (with "" (instance (export "random" (func $random))))))
+                    (func (export "run") (result (list u8))
+                        (canon lift (core func $m "run") (memory $libc "memory")))
+                )
+            "#
+        ),
+    )?;
+
+    // Returns the list that the guest received from the host, which counts
+    // up from `base`, or panics if `base` is `None`.
+    let run = |rr: RecordReplay, base: Option<u8>| -> Result<Vec<u8>> {
+        let mut store = Store::new(&engine, ());
+        store.set_record_replay(rr.clone())?;
+        let mut linker = Linker::new(&engine);
+        linker
+            .root()
+            .func_wrap("random", move |_, (n,): (u32,)| -> Result<(Vec<u8>,)> {
+                let base = base.expect("host functions aren't called when replaying");
+                Ok(((0..n as u8).map(|i| base + i).collect(),))
+            })?;
+        let instance = linker.instantiate(&mut store, &component)?;
+        let run = instance.get_typed_func::<(), (Vec<u8>,)>(&mut store, "run")?;
+        let (list,) = run.call(&mut store, ())?;
+        rr.finish()?;
+        Ok(list)
+    };
+
+    let trace = SharedBuf::default();
+    let recorded = run(RecordReplay::record(trace.clone())?, Some(3))?;
+    assert_eq!(recorded, (3..19).collect::<Vec<u8>>());
+    let replayed = run(
+        RecordReplay::replay(io::Cursor::new(trace.contents()))?,
+        None,
+    )?;
+    assert_eq!(replayed, recorded);
+
+    // The list was lowered to memory allocated by `realloc`, so it's among
+    // the call's effects alongside the allocator's global.
+    let calls = host_calls(&trace.contents())?;
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0].results, Ok(vec![]));
+    assert_eq!(calls[0].memories.len(), 1);
+    assert_eq!(calls[0].globals.len(), 1);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn component_resources_are_rejected() -> Result<()> {
+    use wasmtime::component::{Component, Linker, Resource, ResourceType};
+
+    let engine = engine()?;
+    let component = Component::new(
+        &engine,
+        r#"
+            (component
+                (import "t" (type $t (sub resource)))
+                (import "make" (func $make (result (own $t))))
+                (core func $make (canon lower (func $make)))
+                (core module $m
+                    (import "" "make" (func $make (result i32)))
+                    (func (export "run") (drop (call $make))))
+                (core instance $m (instantiate $m
+                    (with "" (instance (export "make" (func $make))))))
+                (func (export "run") (canon lift (core func $m "run")))
+            )
+        "#,
+    )?;
+
+    let mut store = Store::new(&engine, ());
+    store.set_record_replay(RecordReplay::record(SharedBuf::default())?)?;
+    let mut linker = Linker::new(&engine);
+    linker
+        .root()
+        .resource("t", ResourceType::host::<u32>(), |_, _| Ok(()))?;
+    linker
+        .root()
+        .func_wrap("make", |_, ()| Ok((Resource::<u32>::new_own(1),)))?;
+    let instance = linker.instantiate(&mut store, &component)?;
+    let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+    let err = run.call(&mut store, ()).unwrap_err();
+    assert!(
+        format!("{err:?}").contains("does not support host functions with resources"),
+        "{err:?}"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn replay_wasi_p1() -> Result<()> {
+    use wasmtime_wasi::p2::pipe::MemoryInputPipe;
+    use wasmtime_wasi::{WasiCtxBuilder, p1::WasiP1Ctx};
+
+    const WASI: &str = r#"
+        (module
+            (import "wasi_snapshot_preview1" "args_sizes_get"
+                (func $args_sizes_get (param i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "args_get"
+                (func $args_get (param i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "environ_sizes_get"
+                (func $environ_sizes_get (param i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "environ_get"
+                (func $environ_get (param i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "random_get"
+                (func $random_get (param i32 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "clock_time_get"
+                (func $clock_time_get (param i32 i64 i32) (result i32)))
+            (import "wasi_snapshot_preview1" "fd_read"
+                (func $fd_read (param i32 i32 i32 i32) (result i32)))
+            (memory (export "memory") 1)
+            (func $check (param i32)
+                (if (local.get 0) (then unreachable)))
+            (func (export "_start")
+                (call $check (call $args_sizes_get (i32.const 0) (i32.const 4)))
+                (call $check (call $args_get (i32.const 1024) (i32.const 2048)))
+                (call $check (call $environ_sizes_get (i32.const 8) (i32.const 12)))
+                (call $check (call $environ_get (i32.const 3072) (i32.const 4096)))
+                (call $check (call $random_get (i32.const 16) (i32.const 32)))
+                (call $check (call $clock_time_get (i32.const 0) (i64.const 1) (i32.const 48)))
+                (i32.store (i32.const 56) (i32.const 5120))
+                (i32.store (i32.const 60) (i32.const 1024))
+                (call $check
+                    (call $fd_read (i32.const 0) (i32.const 56) (i32.const 1) (i32.const 64))))
+        )
+    "#;
+
+    fn run(engine: &Engine, rr: RecordReplay, ctx: WasiP1Ctx) -> Result<Vec<u8>> {
+        let mut linker = Linker::<WasiP1Ctx>::new(engine);
+        wasmtime_wasi::p1::add_to_linker_sync(&mut linker, |t| t)?;
+        let module = Module::new(engine, WASI)?;
+        let mut store = Store::new(engine, ctx);
+        store.set_record_replay(rr.clone())?;
+        let instance = linker.instantiate(&mut store, &module)?;
+        let start = instance.get_typed_func::<(), ()>(&mut store, "_start")?;
+        start.call(&mut store, ())?;
+        rr.finish()?;
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        Ok(memory.data(&store).to_vec())
+    }
+
+    let engine = engine()?;
+    let trace = SharedBuf::default();
+    let ctx = WasiCtxBuilder::new()
+        .args(&["main", "a", "bb"])
+        .env("FOO", "bar")
+        .stdin(MemoryInputPipe::new("hello"))
+        .build_p1();
+    let recorded = run(&engine, RecordReplay::record(trace.clone())?, ctx)?;
+    assert_eq!(&recorded[0..4], 3u32.to_le_bytes());
+    assert_eq!(&recorded[2048..2048 + 10], b"main\0a\0bb\0");
+    assert_eq!(&recorded[4096..4096 + 8], b"FOO=bar\0");
+    assert_eq!(&recorded[64..68], 5u32.to_le_bytes());
+    assert_eq!(&recorded[5120..5125], b"hello");
+
+    // When replaying, the guest observes the recorded arguments, environment,
+    // randomness, time and stdin rather than those of this context.
+    let ctx = WasiCtxBuilder::new().args(&["other"]).build_p1();
+    let replayed = run(
+        &engine,
+        RecordReplay::replay(io::Cursor::new(trace.contents()))?,
+        ctx,
+    )?;
+    assert!(replayed == recorded);
+    Ok(())
+}
+
+#[test]
+fn truncated_trace_is_rejected() -> Result<()> {
+    let trace = SharedBuf::default();
+    RecordReplay::record(trace.clone())?.finish()?;
+
+    // An event claiming to be 4GiB long is rejected once the trace runs out,
+    // rather than allocated up front.
+    let mut bytes = trace.contents();
+    bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+    bytes.extend_from_slice(b"short");
+    let err = RecordReplay::read_events(&bytes[..]).unwrap_err();
+    assert!(
+        format!("{err}").contains("trace ended in the middle of an event"),
+        "{err}"
+    );
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn replay_memory_grow_denied_by_limiter() -> Result<()> {
+    const GROW: &str = r#"
+        (module
+            (memory 1)
+            (func (export "grow") (result i32)
+                (memory.grow (i32.const 1)))
+        )
+    "#;
+
+    fn run(engine: &Engine, rr: RecordReplay, limits: StoreLimits) -> Result<Vec<i32>> {
+        let module = Module::new(engine, GROW)?;
+        let mut store = Store::new(engine, limits);
+        store.limiter(|s| s as &mut dyn ResourceLimiter);
+        store.set_record_replay(rr.clone())?;
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let grow = instance.get_typed_func::<(), i32>(&mut store, "grow")?;
+        let results = (0..3)
+            .map(|_| grow.call(&mut store, ()))
+            .collect::<Result<_>>()?;
+        rr.finish()?;
+        Ok(results)
+    }
+
+    let engine = engine()?;
+    let trace = SharedBuf::default();
+
+    let limits = StoreLimitsBuilder::new().memory_size(2 << 16).build();
+    let recorded = run(&engine, RecordReplay::record(trace.clone())?, limits)?;
+    assert_eq!(recorded, [1, -1, -1]);
+
+    // Without a limiter the growths would succeed, but they fail again when
+    // replayed.
+    let replayed = run(
+        &engine,
+        RecordReplay::replay(io::Cursor::new(trace.contents()))?,
+        StoreLimits::default(),
+    )?;
+    assert_eq!(replayed, recorded);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn replay_divergence_is_reported() -> Result<()> {
+    let engine = engine()?;
+    let trace = SharedBuf::default();
+    run_host_calls(&engine, RecordReplay::record(trace.clone())?, 0)?;
+
+    // Replaying a module which makes fewer host calls than were recorded
+    // leaves events behind in the trace.
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "next" (func $next (result i64)))
+                (func (export "run") (result i64) (call $next))
+            )
+        "#,
+    )?;
+    let rr = RecordReplay::replay(io::Cursor::new(trace.contents()))?;
+    let mut store = Store::new(&engine, ());
+    store.set_record_replay(rr.clone())?;
+    let next = Func::wrap(&mut store, || 0i64);
+    let instance = Instance::new(&mut store, &module, &[next.into()])?;
+    let run = instance.get_typed_func::<(), i64>(&mut store, "run")?;
+    run.call(&mut store, ())?;
+    let err = rr.finish().unwrap_err();
+    assert!(format!("{err}").contains("replay ended before the end of the trace"));
+
+    // Replaying a host call with a different type than was recorded fails the
+    // call itself.
+    let rr = RecordReplay::replay(io::Cursor::new(trace.contents()))?;
+    let mut store = Store::new(&engine, ());
+    store.set_record_replay(rr.clone())?;
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "now" (func $now (result f64)))
+                (func (export "run") (result f64) (call $now))
+            )
+        "#,
+    )?;
+    let now = Func::wrap(&mut store, || 0.0f64);
+    let instance = Instance::new(&mut store, &module, &[now.into()])?;
+    let run = instance.get_typed_func::<(), f64>(&mut store, "run")?;
+    let err = run.call(&mut store, ()).unwrap_err();
+    assert!(
+        format!("{err:?}").contains("replay diverged from the recorded trace"),
+        "{err:?}"
+    );
+    Ok(())
+}
+
+#[test]
+fn record_replay_requires_config() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, ());
+    let err = store
+        .set_record_replay(RecordReplay::record(SharedBuf::default())?)
+        .unwrap_err();
+    assert!(format!("{err}").contains("not enabled"));
+    Ok(())
+}
@@ -0,0 +1,423 @@
+use super::gc_store;
+use wasmtime::*;
+
+const COUNTER: &str = r#"
+    (module
+        (import "" "log" (func $log (param i32)))
+        (memory (export "memory") 1)
+        (global $count (export "count") (mut i32) (i32.const 0))
+        (table $funcs 2 funcref)
+        (elem declare func $log $double)
+
+        (func $double (param i32) (result i32)
+            (i32.mul (local.get 0) (i32.const 2)))
+
+        (func (export "bump") (result i32)
+            (global.set $count (i32.add (global.get $count) (i32.const 1)))
+            (i32.store (i32.const 100) (global.get $count))
+            (table.set $funcs (i32.const 1) (ref.func $double))
+            (table.set $funcs (i32.const 0) (ref.func $log))
+            (drop (memory.grow (i32.const 1)))
+            (global.get $count))
+
+        (func (export "double") (param i32) (result i32)
+            (call_indirect $funcs (param i32) (result i32)
+                (local.get 0) (i32.const 1)))
+    )
+"#;
+
+fn instantiate(engine: &Engine, module: &Module) -> Result<(Store<u32>, Instance)> {
+    let mut store = Store::new(engine, 0);
+    let log = Func::wrap(&mut store, |mut caller: Caller<'_, u32>, x: i32| {
+        *caller.data_mut() += x as u32;
+    });
+    let instance = Instance::new(&mut store, module, &[log.into()])?;
+    Ok((store, instance))
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn round_trip_memories_globals_and_tables() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, COUNTER)?;
+
+    let (mut store, instance) = instantiate(&engine, &module)?;
+    let bump = instance.get_typed_func::<(), i32>(&mut store, "bump")?;
+    bump.call(&mut store, ())?;
+    assert_eq!(bump.call(&mut store, ())?, 2);
+    *store.data_mut() = 42;
+
+    let snapshot = store.snapshot(|data| Ok(data.to_le_bytes().to_vec()))?;
+    // SAFETY: the bytes were just serialized from a snapshot.
+    let snapshot = unsafe { StoreSnapshot::from_bytes(&snapshot.to_bytes()?)? };
+
+    let (mut restored, instance) = instantiate(&engine, &module)?;
+    restored.restore(&snapshot, |data, host| {
+        *data = u32::from_le_bytes(host.try_into()?);
+        Ok(())
+    })?;
+    assert_eq!(*restored.data(), 42);
+
+    let memory = instance.get_memory(&mut restored, "memory").unwrap();
+    assert_eq!(memory.size(&restored), 3);
+    assert_eq!(memory.data(&restored)[100], 2);
+    let count = instance.get_global(&mut restored, "count").unwrap();
+    assert_eq!(count.get(&mut restored).unwrap_i32(), 2);
+
+    let double = instance.get_typed_func::<i32, i32>(&mut restored, "double")?;
+    assert_eq!(double.call(&mut restored, 21)?, 42);
+
+    // The restored store carries on from where the snapshotted one was.
+    let bump = instance.get_typed_func::<(), i32>(&mut restored, "bump")?;
+    assert_eq!(bump.call(&mut restored, ())?, 3);
+    assert_eq!(bump.call(&mut store, ())?, 3);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn restore_into_mismatched_store() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(&engine, COUNTER)?;
+    let (mut store, _) = instantiate(&engine, &module)?;
+    let snapshot = store.snapshot(|_| Ok(vec![]))?;
+
+    // No instances at all.
+    let mut empty = Store::new(&engine, 0);
+    let err = empty.restore(&snapshot, |_, _| Ok(())).unwrap_err();
+    assert!(format!("{err}").contains("instances"), "{err}");
+
+    // An instance of a different module.
+    let other = Module::new(&engine, r#"(module (memory 1))"#)?;
+    let mut store = Store::new(&engine, 0);
+    Instance::new(&mut store, &other, &[])?;
+    let err = store.restore(&snapshot, |_, _| Ok(())).unwrap_err();
+    assert!(format!("{err}").contains("not of the same module"), "{err}");
+
+    // Memories which are larger than in the snapshot can't be shrunk.
+    let (mut store, instance) = instantiate(&engine, &module)?;
+    let bump = instance.get_typed_func::<(), i32>(&mut store, "bump")?;
+    bump.call(&mut store, ())?;
+    let err = store.restore(&snapshot, |_, _| Ok(())).unwrap_err();
+    assert!(
+        format!("{err}").contains("larger than in the snapshot"),
+        "{err}"
+    );
+
+    // SAFETY: these bytes are rejected before anything is trusted.
+    assert!(unsafe { StoreSnapshot::from_bytes(b"not a snapshot") }.is_err());
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn host_created_items() -> Result<()> {
+    let engine = Engine::default();
+    let mut store = Store::new(&engine, ());
+    let global = Global::new(
+        &mut store,
+        GlobalType::new(ValType::I64, Mutability::Var),
+        Val::I64(1),
+    )?;
+    let memory = Memory::new(&mut store, MemoryType::new(1, None))?;
+    global.set(&mut store, Val::I64(7))?;
+    memory.grow(&mut store, 1)?;
+    memory.data_mut(&mut store)[1 << 16] = 9;
+
+    // Host functions can't be snapshotted unless an instance imports them.
+    let func = Func::wrap(&mut store, || {});
+    let table = Table::new(
+        &mut store,
+        TableType::new(RefType::FUNCREF, 1, None),
+        func.into(),
+    )?;
+    assert!(store.snapshot(|_| Ok(vec![])).is_err());
+    table.set(&mut store, 0, Ref::Func(None))?;
+    let snapshot = store.snapshot(|_| Ok(vec![]))?;
+
+    let mut restored = Store::new(&engine, ());
+    let global = Global::new(
+        &mut restored,
+        GlobalType::new(ValType::I64, Mutability::Var),
+        Val::I64(1),
+    )?;
+    let memory = Memory::new(&mut restored, MemoryType::new(1, None))?;
+    Table::new(
+        &mut restored,
+        TableType::new(RefType::FUNCREF, 1, None),
+        Ref::Func(None),
+    )?;
+    restored.restore(&snapshot, |_, _| Ok(()))?;
+    assert_eq!(global.get(&mut restored).unwrap_i64(), 7);
+    assert_eq!(memory.data(&restored)[1 << 16], 9);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn round_trip_gc_heap() -> Result<()> {
+    let mut store = gc_store()?;
+    let engine = store.engine().clone();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (type $pair (struct (field i32) (field (mut (ref null $pair)))))
+                (global $head (mut (ref null $pair)) (ref.null $pair))
+                (func (export "push") (param i32)
+                    (global.set $head (struct.new $pair (local.get 0) (global.get $head))))
+                (func (export "sum") (result i32)
+                    (local $sum i32)
+                    (local $p (ref null $pair))
+                    (local.set $p (global.get $head))
+                    (block $done
+                        (loop $next
+                            (br_if $done (ref.is_null (local.get $p)))
+                            (local.set $sum (i32.add (local.get $sum)
+                                (struct.get $pair 0 (local.get $p))))
+                            (local.set $p (struct.get $pair 1 (local.get $p)))
+                            (br $next)))
+                    (local.get $sum))
+            )
+        "#,
+    )?;
+
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let push = instance.get_typed_func::<i32, ()>(&mut store, "push")?;
+    for i in 1..=10 {
+        push.call(&mut store, i)?;
+    }
+    store.gc(None);
+    let snapshot = store.snapshot(|_| Ok(vec![]))?;
+
+    let mut restored = Store::new(&engine, ());
+    let instance = Instance::new(&mut restored, &module, &[])?;
+    restored.restore(&snapshot, |_, _| Ok(()))?;
+
+    let sum = instance.get_typed_func::<(), i32>(&mut restored, "sum")?;
+    assert_eq!(sum.call(&mut restored, ())?, 55);
+    let push = instance.get_typed_func::<i32, ()>(&mut restored, "push")?;
+    push.call(&mut restored, 45)?;
+    restored.gc(None);
+    assert_eq!(sum.call(&mut restored, ())?, 100);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn fork() -> Result<()> {
+    let mut pooling = Config::new();
+    pooling.allocation_strategy(PoolingAllocationConfig::default());
+
+    for config in [Config::new(), pooling] {
+        let engine = Engine::new(&config)?;
+        let module = Module::new(&engine, COUNTER)?;
+        let (mut store, instance) = instantiate(&engine, &module)?;
+        let bump = instance.get_typed_func::<(), i32>(&mut store, "bump")?;
+        bump.call(&mut store, ())?;
+        bump.call(&mut store, ())?;
+        let snapshot = store.snapshot(|_| Ok(vec![]))?;
+
+        // SAFETY: the closure only instantiates the module, which doesn't run
+        // its start function or touch its state when forking.
+        let fork = || unsafe {
+            snapshot.fork(&engine, 7, |store| {
+                let log = Func::wrap(&mut *store, |mut caller: Caller<'_, u32>, x: i32| {
+                    *caller.data_mut() += x as u32;
+                });
+                Instance::new(store, &module, &[log.into()])
+            })
+        };
+        let (mut a, a_instance) = fork()?;
+        let (mut b, b_instance) = fork()?;
+        assert_eq!(*a.data(), 7);
+
+        // Each fork starts out where the snapshotted store was, and carries on
+        // independently of the others.
+        let a_memory = a_instance.get_memory(&mut a, "memory").unwrap();
+        let b_memory = b_instance.get_memory(&mut b, "memory").unwrap();
+        assert_eq!(a_memory.size(&a), 3);
+        assert_eq!(a_memory.data(&a)[100], 2);
+        a_memory.data_mut(&mut a)[200] = 1;
+        let bump = a_instance.get_typed_func::<(), i32>(&mut a, "bump")?;
+        assert_eq!(bump.call(&mut a, ())?, 3);
+        assert_eq!(a_memory.data(&a)[100], 3);
+        assert_eq!(a_memory.size(&a), 4);
+
+        assert_eq!(b_memory.data(&b)[100], 2);
+        assert_eq!(b_memory.data(&b)[200], 0);
+        let double = b_instance.get_typed_func::<i32, i32>(&mut b, "double")?;
+        assert_eq!(double.call(&mut b, 21)?, 42);
+
+        let memory = instance.get_memory(&mut store, "memory").unwrap();
+        assert_eq!(memory.data(&store)[100], 2);
+        assert_eq!(memory.data(&store)[200], 0);
+
+        // Stores can be forked again after earlier forks are dropped, which
+        // for the pooling allocator reuses their slots.
+        drop((a, b));
+        let (mut c, c_instance) = fork()?;
+        let c_memory = c_instance.get_memory(&mut c, "memory").unwrap();
+        assert_eq!(c_memory.data(&c)[100], 2);
+        assert_eq!(c_memory.data(&c)[200], 0);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn fork_does_not_rerun_start() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "log" (func $log (param i32)))
+                (memory (export "memory") 1)
+                (global $started (export "started") (mut i32) (i32.const 0))
+                (func $start
+                    (global.set $started (i32.add (global.get $started) (i32.const 1)))
+                    (i32.store8 (i32.const 0) (global.get $started))
+                    (call $log (i32.const 1)))
+                (start $start)
+                (data (i32.const 1) "\2a")
+            )
+        "#,
+    )?;
+    let (mut store, _instance) = instantiate(&engine, &module)?;
+    assert_eq!(*store.data(), 1);
+    let snapshot = store.snapshot(|_| Ok(vec![]))?;
+
+    // SAFETY: the closure only instantiates the module, which doesn't run its
+    // start function or touch its state when forking.
+    let (mut fork, instance) = unsafe {
+        snapshot.fork(&engine, 0, |store| {
+            let log = Func::wrap(&mut *store, |mut caller: Caller<'_, u32>, x: i32| {
+                *caller.data_mut() += x as u32;
+            });
+            Instance::new(store, &module, &[log.into()])
+        })?
+    };
+
+    // The start function didn't run again in the fork, neither calling the
+    // host nor touching its globals or memory, which are as they were in the
+    // snapshotted store.
+    assert_eq!(*fork.data(), 0);
+    let started = instance.get_global(&mut fork, "started").unwrap();
+    assert_eq!(started.get(&mut fork).unwrap_i32(), 1);
+    let memory = instance.get_memory(&mut fork, "memory").unwrap();
+    assert_eq!(&memory.data(&fork)[..2], &[1, 0x2a]);
+    Ok(())
+}
+
+/// Find the mapping containing `addr` in `/proc/self/smaps`, returning its
+/// header line and how many kilobytes of it are anonymous, i.e. have been
+/// copied on write rather than shared with the file it maps.
+#[cfg(target_os = "linux")]
+fn smaps_mapping(addr: *const u8) -> Option<(String, u64)> {
+    let smaps = std::fs::read_to_string("/proc/self/smaps").unwrap();
+    let addr = addr as usize;
+    let mut lines = smaps.lines();
+    while let Some(header) = lines.next() {
+        let Some((range, _)) = header.split_once(' ') else {
+            continue;
+        };
+        let Some((start, end)) = range.split_once('-') else {
+            continue;
+        };
+        let (Ok(start), Ok(end)) = (
+            usize::from_str_radix(start, 16),
+            usize::from_str_radix(end, 16),
+        ) else {
+            continue;
+        };
+        if !(start..end).contains(&addr) {
+            continue;
+        }
+        let anonymous = lines
+            .find_map(|line| line.strip_prefix("Anonymous:"))?
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .unwrap();
+        return Some((header.to_string(), anonymous));
+    }
+    None
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+#[cfg(target_os = "linux")]
+fn fork_shares_pages_until_written() -> Result<()> {
+    let mut config = Config::new();
+    config.memory_init_cow(true);
+    let engine = Engine::new(&config)?;
+    let module = Module::new(&engine, COUNTER)?;
+    let (mut store, instance) = instantiate(&engine, &module)?;
+    let memory = instance.get_memory(&mut store, "memory").unwrap();
+    memory.data_mut(&mut store)[100] = 1;
+    let snapshot = store.snapshot(|_| Ok(vec![]))?;
+
+    // SAFETY: the closure only instantiates the module, which doesn't touch
+    // its state when forking.
+    let fork = || unsafe {
+        snapshot.fork(&engine, 0, |store| {
+            let log = Func::wrap(&mut *store, |_: Caller<'_, u32>, _: i32| {});
+            Instance::new(store, &module, &[log.into()])
+        })
+    };
+    let (mut a, a_instance) = fork()?;
+    let (mut b, b_instance) = fork()?;
+    let a_memory = a_instance.get_memory(&mut a, "memory").unwrap();
+    let b_memory = b_instance.get_memory(&mut b, "memory").unwrap();
+    assert_eq!(a_memory.data(&a)[100], 1);
+    assert_eq!(b_memory.data(&b)[100], 1);
+
+    // Both forks map the snapshotted pages from the same image, and neither
+    // has a private copy of them yet.
+    let (a_mapping, a_copied) = smaps_mapping(a_memory.data_ptr(&a)).unwrap();
+    let (b_mapping, b_copied) = smaps_mapping(b_memory.data_ptr(&b)).unwrap();
+    assert!(a_mapping.contains("memfd:wasm-memory-image"), "{a_mapping}");
+    assert!(b_mapping.contains("memfd:wasm-memory-image"), "{b_mapping}");
+    let inode = |mapping: &str| mapping.split_whitespace().nth(4).unwrap().to_string();
+    assert_eq!(inode(&a_mapping), inode(&b_mapping));
+    assert_eq!((a_copied, b_copied), (0, 0));
+
+    // Writing to a page copies it for the fork that wrote to it, and only for
+    // that fork.
+    a_memory.data_mut(&mut a)[100] = 2;
+    let (_, a_copied) = smaps_mapping(a_memory.data_ptr(&a)).unwrap();
+    let (_, b_copied) = smaps_mapping(b_memory.data_ptr(&b)).unwrap();
+    assert!(a_copied > 0);
+    assert_eq!(b_copied, 0);
+    assert_eq!(b_memory.data(&b)[100], 1);
+    assert_eq!(memory.data(&store)[100], 1);
+    Ok(())
+}
+
+#[test]
+fn restore_with_async_limiter_is_an_error() -> Result<()> {
+    struct Unlimited;
+
+    #[async_trait::async_trait]
+    impl ResourceLimiterAsync for Unlimited {
+        async fn memory_growing(&mut self, _: usize, _: usize, _: Option<usize>) -> Result<bool> {
+            Ok(true)
+        }
+        async fn table_growing(&mut self, _: usize, _: usize, _: Option<usize>) -> Result<bool> {
+            Ok(true)
+        }
+    }
+
+    let mut config = Config::new();
+    config.async_support(true);
+    let engine = Engine::new(&config)?;
+    let snapshot = Store::new(&engine, ()).snapshot(|_| Ok(vec![]))?;
+
+    let mut store = Store::new(&engine, Unlimited);
+    store.limiter_async(|limiter| limiter);
+    let err = store.restore(&snapshot, |_, _| Ok(())).unwrap_err();
+    assert!(err.to_string().contains("async resource limiter"), "{err}");
+    Ok(())
+}
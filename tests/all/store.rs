@@ -1,5 +1,8 @@
 use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
-use wasmtime::{Engine, Store};
+use std::time::Duration;
+use wasmtime::{
+    AsContext, Caller, Engine, Func, Instance, Memory, MemoryType, Module, Result, Store,
+};
 
 #[test]
 fn into_inner() {
@@ -20,3 +23,65 @@ fn into_inner() {
     Store::new(&engine, A).into_data();
     assert_eq!(HITS.load(SeqCst), 2);
 }
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn usage() -> Result<()> {
+    let engine = Engine::default();
+    let module = Module::new(
+        &engine,
+        r#"
+            (module
+                (import "" "host" (func $host))
+                (memory (export "mem") 2)
+                (table 10 funcref)
+                (func (export "run") (param i32)
+                    (loop $l
+                        (call $host)
+                        (br_if $l (local.tee 0 (i32.sub (local.get 0) (i32.const 1)))))))
+        "#,
+    )?;
+    let mut store = Store::new(&engine, ());
+    let host = Func::wrap(&mut store, |caller: Caller<'_, ()>| {
+        // Usage can be observed from within host calls, too.
+        assert!(caller.as_context().usage().host_calls > 0);
+        std::thread::sleep(Duration::from_millis(1));
+    });
+    let instance = Instance::new(&mut store, &module, &[host.into()])?;
+    Memory::new(&mut store, MemoryType::new(1, None))?;
+    let run = instance.get_typed_func::<i32, ()>(&mut store, "run")?;
+
+    let usage = store.usage();
+    assert_eq!(usage.memory_bytes, 3 << 16);
+    assert!(usage.memory_resident_bytes <= usage.memory_bytes);
+    assert_eq!(usage.table_elements, 10);
+    assert_eq!(usage.host_calls, 0);
+
+    // Nothing has touched the second page of `mem` yet, so writing to it
+    // makes it resident.
+    let memory = instance.get_memory(&mut store, "mem").unwrap();
+    memory.data_mut(&mut store)[1 << 16] = 1;
+    let resident = store.usage().memory_resident_bytes;
+    if cfg!(target_os = "linux") {
+        assert!(resident > usage.memory_resident_bytes, "{usage:?}");
+    }
+    assert!(resident <= usage.memory_bytes);
+
+    store.usage_accounting(true);
+    run.call(&mut store, 5)?;
+    let usage = store.usage();
+    assert_eq!(usage.host_calls, 5);
+    assert!(usage.host_time >= Duration::from_millis(5), "{usage:?}");
+    assert!(usage.wasm_time < usage.host_time, "{usage:?}");
+
+    store.reset_usage();
+    assert_eq!(store.usage().host_calls, 0);
+    assert_eq!(store.usage().host_time, Duration::ZERO);
+    run.call(&mut store, 2)?;
+    assert_eq!(store.usage().host_calls, 2);
+
+    store.usage_accounting(false);
+    run.call(&mut store, 2)?;
+    assert_eq!(store.usage().host_calls, 0);
+    Ok(())
+}
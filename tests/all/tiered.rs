@@ -0,0 +1,210 @@
+// Winch, which modules are first compiled with, only supports x86_64 well.
+#![cfg(target_arch = "x86_64")]
+
+use std::thread;
+use std::time::{Duration, Instant};
+use wasmtime::*;
+
+const WAT: &str = r#"
+    (module
+        (func $add (param i32 i32) (result i32)
+            local.get 0
+            local.get 1
+            i32.add)
+        (func $div (export "div") (param i32 i32) (result i32)
+            local.get 0
+            local.get 1
+            i32.div_u)
+        (func (export "sum") (param i32) (result i32)
+            (local i32)
+            (block $done
+                (loop $loop
+                    local.get 0
+                    i32.eqz
+                    br_if $done
+                    local.get 1
+                    local.get 0
+                    call $add
+                    local.set 1
+                    local.get 0
+                    i32.const 1
+                    i32.sub
+                    local.set 0
+                    br $loop))
+            local.get 1)
+        (func (export "quotient") (param i32 i32) (result i32)
+            local.get 0
+            local.get 1
+            call $div)
+    )
+"#;
+
+fn engine() -> Result<Engine> {
+    engine_with_threshold(10)
+}
+
+fn engine_with_threshold(calls: u32) -> Result<Engine> {
+    let mut config = Config::new();
+    config.strategy(Strategy::Tiered);
+    config.tier_up_threshold(calls);
+    Engine::new(&config)
+}
+
+/// Waits for the background thread to have tiered up `functions` functions.
+fn wait_for_tier_up(engine: &Engine, functions: u64) {
+    let start = Instant::now();
+    loop {
+        let stats = engine.tier_up_stats().unwrap();
+        assert_eq!(stats.functions_failed, 0);
+        if stats.functions_tiered_up >= functions {
+            assert_eq!(stats.functions_tiered_up, functions);
+            return;
+        }
+        assert!(
+            start.elapsed() < Duration::from_secs(60),
+            "timed out waiting for functions to tier up: {stats:?}"
+        );
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn results_are_unchanged_by_tiering_up() -> Result<()> {
+    let engine = engine()?;
+    let module = Module::new(&engine, WAT)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let sum = instance.get_typed_func::<u32, u32>(&mut store, "sum")?;
+    let quotient = instance.get_typed_func::<(u32, u32), u32>(&mut store, "quotient")?;
+
+    // Give the background thread a chance to tier up `$add` and `$div` in
+    // between rounds of calls.
+    for _ in 0..10 {
+        assert_eq!(sum.call(&mut store, 100)?, 5050);
+        assert_eq!(quotient.call(&mut store, (100, 7))?, 14);
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    // All four functions have been called at least 10 times by now.
+    wait_for_tier_up(&engine, 4);
+    assert_eq!(sum.call(&mut store, 100)?, 5050);
+    assert_eq!(quotient.call(&mut store, (100, 7))?, 14);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn threshold_of_zero_tiers_up_all_functions() -> Result<()> {
+    let engine = engine_with_threshold(0)?;
+    let module = Module::new(&engine, WAT)?;
+    wait_for_tier_up(&engine, 4);
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let sum = instance.get_typed_func::<u32, u32>(&mut store, "sum")?;
+    assert_eq!(sum.call(&mut store, 100)?, 5050);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn functions_below_threshold_are_not_tiered_up() -> Result<()> {
+    let engine = engine()?;
+    let module = Module::new(&engine, WAT)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let quotient = instance.get_typed_func::<(u32, u32), u32>(&mut store, "quotient")?;
+    for _ in 0..9 {
+        assert_eq!(quotient.call(&mut store, (100, 7))?, 14);
+    }
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(engine.tier_up_stats().unwrap().functions_tiered_up, 0);
+
+    // The tenth call of `quotient` and `$div` asks for them to be tiered up.
+    assert_eq!(quotient.call(&mut store, (100, 7))?, 14);
+    wait_for_tier_up(&engine, 2);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn traps_in_tiered_up_functions() -> Result<()> {
+    let engine = engine()?;
+    let module = Module::new(&engine, WAT)?;
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let quotient = instance.get_typed_func::<(u32, u32), u32>(&mut store, "quotient")?;
+    let div = instance.get_typed_func::<(u32, u32), u32>(&mut store, "div")?;
+
+    for _ in 0..10 {
+        for _ in 0..20 {
+            quotient.call(&mut store, (1, 1))?;
+        }
+        thread::sleep(Duration::from_millis(20));
+
+        // Frames of `$div` are part of the backtrace whether or not it has
+        // been tiered up yet.
+        let trap = quotient.call(&mut store, (1, 0)).unwrap_err();
+        let trace = trap.downcast_ref::<WasmBacktrace>().unwrap();
+        let frames = trace
+            .frames()
+            .iter()
+            .map(|f| (f.func_index(), f.func_name().map(str::to_string)))
+            .collect::<Vec<_>>();
+        assert_eq!(frames, [(1, Some("div".to_string())), (3, None)]);
+        assert!(trace.frames()[0].module_offset().is_some());
+        assert_eq!(trap.downcast::<Trap>()?, Trap::IntegerDivisionByZero);
+        let trap = div.call(&mut store, (1, 0)).unwrap_err();
+        assert_eq!(trap.downcast::<Trap>()?, Trap::IntegerDivisionByZero);
+    }
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn instances_created_after_tiering_up() -> Result<()> {
+    let engine = engine()?;
+    let module = Module::new(&engine, WAT)?;
+
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let sum = instance.get_typed_func::<u32, u32>(&mut store, "sum")?;
+    assert_eq!(sum.call(&mut store, 100)?, 5050);
+    wait_for_tier_up(&engine, 1);
+
+    // New instances, in the same store and in new ones, pick up the
+    // recompiled code of `$add`.
+    for _ in 0..2 {
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let sum = instance.get_typed_func::<u32, u32>(&mut store, "sum")?;
+        assert_eq!(sum.call(&mut store, 100)?, 5050);
+    }
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let sum = instance.get_typed_func::<u32, u32>(&mut store, "sum")?;
+    assert_eq!(sum.call(&mut store, 100)?, 5050);
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn deserialized_modules_run_without_tiering_up() -> Result<()> {
+    let engine = engine()?;
+    let bytes = Module::new(&engine, WAT)?.serialize()?;
+    let module = unsafe { Module::deserialize(&engine, &bytes)? };
+    let mut store = Store::new(&engine, ());
+    let instance = Instance::new(&mut store, &module, &[])?;
+    let sum = instance.get_typed_func::<u32, u32>(&mut store, "sum")?;
+    for _ in 0..20 {
+        assert_eq!(sum.call(&mut store, 100)?, 5050);
+    }
+
+    // Both `sum` and `$add` reached the threshold, but there's no wasm
+    // binary to recompile them from.
+    thread::sleep(Duration::from_millis(100));
+    let stats = engine.tier_up_stats().unwrap();
+    assert_eq!(stats.functions_tiered_up, 0);
+    assert_eq!(stats.functions_failed, 0);
+    Ok(())
+}
@@ -92,8 +92,8 @@ fn main() {
             },
         );
 
-        // If applicable, also run with the null collector in addition to the
-        // default collector.
+        // If applicable, also run with the null and copying collectors in
+        // addition to the default collector.
         if test.test_uses_gc_types() {
             add_trial(
                 &test,
@@ -103,6 +103,14 @@ fn main() {
                     collector: Collector::Null,
                 },
             );
+            add_trial(
+                &test,
+                WastConfig {
+                    compiler,
+                    pooling: false,
+                    collector: Collector::Copying,
+                },
+            );
         }
     }
 
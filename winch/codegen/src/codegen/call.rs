@@ -70,7 +70,7 @@ use crate::{
     stack::Val,
 };
 use anyhow::{Result, ensure};
-use wasmtime_environ::{DefinedFuncIndex, FuncIndex, PtrSize, VMOffsets};
+use wasmtime_environ::{DefinedFuncIndex, FuncIndex, FuncRefIndex, PtrSize, VMOffsets};
 
 /// All the information needed to emit a function call.
 #[derive(Copy, Clone)]
@@ -152,6 +152,11 @@ impl FnCall {
                 let f = env.translation.module.defined_func_index(*i).unwrap();
                 Ok(Self::lower_local(env, f))
             }
+            Callee::LocalFuncRef(i) => {
+                let func_ref = env.translation.module.functions[*i].func_ref;
+                let sig = env.callee_sig::<M::ABI>(callee)?;
+                Self::lower_local_funcref(func_ref, sig, context, masm, vmoffsets)
+            }
             Callee::Import(i) => {
                 let sig = env.callee_sig::<M::ABI>(callee)?;
                 Self::lower_import(*i, sig, context, masm, vmoffsets)
@@ -188,6 +193,33 @@ impl FnCall {
         )
     }
 
+    /// Lowers a local function which is called through its `VMFuncRef` by
+    /// loading the function's current address to the next available register.
+    ///
+    /// The callee shares the caller's `VMContext`, but the address may be
+    /// replaced at any time, e.g. by tiered compilation.
+    fn lower_local_funcref<M: MacroAssembler, P: PtrSize>(
+        func_ref: FuncRefIndex,
+        sig: &ABISig,
+        context: &mut CodeGenContext<Emission>,
+        masm: &mut M,
+        vmoffsets: &VMOffsets<P>,
+    ) -> Result<(CalleeKind, ContextArgs)> {
+        let callee =
+            context.without::<Result<Reg>, M, _>(&sig.regs, masm, |context, masm| {
+                context.any_gpr(masm)
+            })??;
+        let callee_body_offset =
+            vmoffsets.vmctx_func_ref(func_ref) + u32::from(vmoffsets.ptr.vm_func_ref_wasm_call());
+        let callee_addr = masm.address_at_vmctx(callee_body_offset)?;
+        masm.load_ptr(callee_addr, writable!(callee))?;
+
+        Ok((
+            CalleeKind::indirect(callee),
+            ContextArgs::pinned_callee_and_caller_vmctx(),
+        ))
+    }
+
     /// Lowers a function import by loading its address to the next available
     /// register.
     fn lower_import<M: MacroAssembler, P: PtrSize>(
@@ -14,7 +14,7 @@ use wasmparser::BlockType;
 use wasmtime_environ::{
     BuiltinFunctionIndex, DefinedFuncIndex, FuncIndex, FuncKey, GlobalIndex, IndexType, Memory,
     MemoryIndex, ModuleTranslation, ModuleTypesBuilder, PrimaryMap, PtrSize, Table, TableIndex,
-    TypeConvert, TypeIndex, VMOffsets, WasmHeapType, WasmValType,
+    Tunables, TypeConvert, TypeIndex, VMOffsets, WasmHeapType, WasmValType,
 };
 
 #[derive(Debug, Clone, Copy)]
@@ -78,6 +78,9 @@ impl HeapData {
 pub(crate) enum Callee {
     /// Locally defined function.
     Local(FuncIndex),
+    /// Locally defined function, called through its `VMFuncRef` in the
+    /// `VMContext` rather than directly.
+    LocalFuncRef(FuncIndex),
     /// Imported function.
     Import(FuncIndex),
     /// Function reference.
@@ -94,6 +97,8 @@ pub(crate) enum Callee {
 /// Contains all information about the module and runtime that is accessible to
 /// to a particular function during code generation.
 pub struct FuncEnv<'a, 'translation: 'a, 'data: 'translation, P: PtrSize> {
+    /// The index of the function being compiled.
+    pub func_index: DefinedFuncIndex,
     /// Offsets to the fields within the `VMContext` ptr.
     pub vmoffsets: &'a VMOffsets<P>,
     /// Metadata about the translation process of a WebAssembly module.
@@ -122,6 +127,9 @@ pub struct FuncEnv<'a, 'translation: 'a, 'data: 'translation, P: PtrSize> {
     table_access_spectre_mitigation: bool,
     /// Size of pages on the compilation target.
     pub page_size_log2: u8,
+    /// Whether or not calls to local functions are made through their
+    /// `VMFuncRef`, so that they can be replaced by tiered compilation.
+    tiered_compilation: bool,
     name_map: PrimaryMap<UserExternalNameRef, UserExternalName>,
     name_intern: HashMap<UserExternalName, UserExternalNameRef>,
 }
@@ -135,14 +143,17 @@ pub fn ptr_type_from_ptr_size(size: u8) -> WasmValType {
 impl<'a, 'translation, 'data, P: PtrSize> FuncEnv<'a, 'translation, 'data, P> {
     /// Create a new function environment.
     pub fn new(
+        func_index: DefinedFuncIndex,
         vmoffsets: &'a VMOffsets<P>,
         translation: &'translation ModuleTranslation<'data>,
         types: &'translation ModuleTypesBuilder,
         builtins: &'translation mut BuiltinFunctions,
         isa: &dyn TargetIsa,
+        tunables: &Tunables,
         ptr_type: WasmValType,
     ) -> Self {
         Self {
+            func_index,
             vmoffsets,
             translation,
             types,
@@ -155,6 +166,7 @@ impl<'a, 'translation, 'data, P: PtrSize> FuncEnv<'a, 'translation, 'data, P> {
             heap_access_spectre_mitigation: isa.flags().enable_heap_access_spectre_mitigation(),
             table_access_spectre_mitigation: isa.flags().enable_table_access_spectre_mitigation(),
             page_size_log2: isa.page_size_align_log2(),
+            tiered_compilation: tunables.tiered_compilation,
             builtins,
             name_map: Default::default(),
             name_intern: Default::default(),
@@ -176,6 +188,8 @@ impl<'a, 'translation, 'data, P: PtrSize> FuncEnv<'a, 'translation, 'data, P> {
         let import = self.translation.module.is_imported_function(idx);
         if import {
             Callee::Import(idx)
+        } else if self.tiered_compilation {
+            Callee::LocalFuncRef(idx)
         } else {
             Callee::Local(idx)
         }
@@ -323,7 +337,7 @@ impl<'a, 'translation, 'data, P: PtrSize> FuncEnv<'a, 'translation, 'data, P> {
         A: ABI,
     {
         match callee {
-            Callee::Local(idx) | Callee::Import(idx) => {
+            Callee::Local(idx) | Callee::LocalFuncRef(idx) | Callee::Import(idx) => {
                 if self.resolved_callees.contains_key(idx) {
                     Ok(self.resolved_callees.get(idx).unwrap())
                 } else {
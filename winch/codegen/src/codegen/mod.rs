@@ -315,6 +315,8 @@ where
         body: BinaryReader<'a>,
         validator: &mut FuncValidator<ValidatorResources>,
     ) -> Result<()> {
+        self.maybe_emit_call_count_increment()?;
+
         self.maybe_emit_fuel_check()?;
 
         self.maybe_emit_epoch_check()?;
@@ -1268,6 +1270,88 @@ where
         )
     }
 
+    /// Checks if tiered compilation is enabled and emits a series of
+    /// instructions that increment this function's entry in the call-counts
+    /// array, calling the `tier_up` builtin once the count reaches the tier-up
+    /// threshold.
+    ///
+    /// The increment isn't atomic: calls racing on different threads may be
+    /// lost, which is fine for a heuristic. Some call still observes the
+    /// count reaching the threshold, since every call adds exactly one.
+    fn maybe_emit_call_count_increment(&mut self) -> Result<()> {
+        if !self.tunables.tiered_compilation {
+            return Ok(());
+        }
+
+        let tier_up = self.env.builtins.tier_up::<M::ABI>()?;
+        let call_counts_offset = self.env.vmoffsets.ptr.vmctx_call_counts();
+        let count_offset = self.env.func_index.as_u32() * 4;
+        let (counts_reg, count_reg) = self.context.without::<Result<(Reg, Reg)>, M, _>(
+            &tier_up.sig().regs,
+            self.masm,
+            |cx, masm| Ok((cx.any_gpr(masm)?, cx.any_gpr(masm)?)),
+        )??;
+
+        // Load the call-counts array into the `counts_reg` reg.
+        self.masm.load_ptr(
+            self.masm.address_at_vmctx(u32::from(call_counts_offset))?,
+            writable!(counts_reg),
+        )?;
+
+        self.masm.load(
+            self.masm.address_at_reg(counts_reg, count_offset)?,
+            writable!(count_reg),
+            OperandSize::S32,
+        )?;
+        self.masm.add(
+            writable!(count_reg),
+            count_reg,
+            RegImm::i32(1),
+            OperandSize::S32,
+        )?;
+        self.masm.store(
+            count_reg.into(),
+            self.masm.address_at_reg(counts_reg, count_offset)?,
+            OperandSize::S32,
+        )?;
+        self.context.free_reg(counts_reg);
+
+        // With a threshold of zero all functions are tiered up as soon as
+        // their module is created, so there's nothing to ask for.
+        let threshold = self.tunables.tier_up_threshold;
+        if threshold != 0 {
+            // The continuation label if the count isn't at the threshold.
+            let continuation = self.masm.get_label()?;
+
+            // Spill locals and registers to avoid conflicts at the control
+            // flow merge below.
+            self.context.spill(self.masm)?;
+            self.masm.branch(
+                IntCmpKind::Ne,
+                count_reg,
+                RegImm::i32(threshold as i32),
+                continuation,
+                OperandSize::S32,
+            )?;
+            // Threshold reached branch.
+            self.context
+                .stack
+                .extend([self.env.func_index.as_u32().try_into()?]);
+            FnCall::emit::<M>(
+                &mut self.env,
+                self.masm,
+                &mut self.context,
+                Callee::Builtin(tier_up),
+            )?;
+
+            self.masm.bind(continuation)?;
+        }
+
+        self.context.free_reg(count_reg);
+
+        Ok(())
+    }
+
     /// Increments the fuel consumed in `VMStoreContext` by flushing
     /// `self.fuel_consumed` to memory.
     fn emit_fuel_increment(&mut self) -> Result<()> {